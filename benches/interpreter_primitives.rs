@@ -0,0 +1,87 @@
+//! Micro-benchmarks of the interpreter's primitives on synthetic bytecode
+//! built with the assembler, run with
+//! `cargo bench --bench interpreter_primitives`.
+//!
+//! Every program runs `ITERATIONS` times a loop whose body repeats the
+//! measured instructions `UNROLL` times, the loop itself is measured on its
+//! own by the `dispatch` benchmark. Method calls need a constant pool and
+//! are measured on a class file instead.
+use std::path::Path;
+
+use coldbrew::assembler::assemble;
+use coldbrew::jvm::{read_class_file, JVMParser};
+use coldbrew::program::Program;
+use coldbrew::runtime::{Runtime, Value};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+const ITERATIONS: usize = 1000;
+const UNROLL: usize = 16;
+
+/// Assemble a counted loop on local 0 around `body` repeated `UNROLL`
+/// times.
+fn counted_loop(body: &str) -> Vec<u8> {
+    let source = format!(
+        "
+            iconst_0
+            istore_0
+        loop:
+            {}
+            iinc 0 1
+            iload_0
+            sipush {ITERATIONS}
+            if_icmplt loop
+            return
+        ",
+        body.repeat(UNROLL)
+    );
+    assemble(&source).expect("benchmark programs assemble")
+}
+
+/// Benchmark running `body` in a counted loop, the runtime is created
+/// outside of the measurement.
+fn bench_loop(c: &mut Criterion, name: &str, body: &str, max_locals: u16) {
+    let code = counted_loop(body);
+    c.bench_function(name, |b| {
+        b.iter_batched(
+            || Runtime::new(Program::from_code(code.clone(), max_locals)),
+            |mut runtime| runtime.run(false).unwrap(),
+            BatchSize::SmallInput,
+        );
+    });
+}
+
+fn dispatch(c: &mut Criterion) {
+    bench_loop(c, "dispatch", "nop\n", 1);
+}
+
+/// The interpreter doesn't implement `pop`, constants are popped into a
+/// local which `locals` compares against loading from one.
+fn push_pop(c: &mut Criterion) {
+    bench_loop(c, "push_pop", "iconst_1\nistore_1\n", 2);
+}
+
+fn locals(c: &mut Criterion) {
+    bench_loop(c, "locals", "iload_1\nistore_2\n", 3);
+}
+
+/// Programs built from code have no constant pool for `invokestatic` to
+/// resolve, calls are measured on `Inline.total` which calls a small static
+/// method `ITERATIONS` times.
+fn calls(c: &mut Criterion) {
+    let path =
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("support/jit/Inline.class");
+    let bytes = read_class_file(&path).expect("failed to read Inline.class");
+    let class_file = JVMParser::parse(&bytes).expect("Inline.class parses");
+    let mut runtime = Runtime::new(Program::new(&class_file));
+    let args = [Value::Int(ITERATIONS as i32)];
+    c.bench_function("calls", |b| {
+        b.iter(|| {
+            runtime
+                .call_static("Inline", "total", "(I)I", &args)
+                .unwrap()
+        });
+    });
+}
+
+criterion_group!(primitives, dispatch, push_pop, locals, calls);
+criterion_main!(primitives);
@@ -0,0 +1,26 @@
+//! Compares the interpreter against the tracing JIT on the programs found
+//! in `support/jit`, run with `cargo bench`.
+use std::path::Path;
+
+use coldbrew::bench;
+
+const RUNS: usize = 10;
+
+fn main() {
+    let folder = Path::new(env!("CARGO_MANIFEST_DIR")).join("support/jit");
+    let mut paths: Vec<_> = folder
+        .read_dir()
+        .expect("failed to read support/jit")
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "class"))
+        .collect();
+    paths.sort();
+
+    for path in &paths {
+        match bench::run(path, RUNS) {
+            Ok(report) => println!("{report}"),
+            Err(err) => println!("Failed to benchmark {path:?} : {err}"),
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! Runs class files in the browser with the interpreter, see
+//! `examples/wasm/index.html` for the page driving it.
+//!
+//! ```sh
+//! cargo build --release --example wasm --target wasm32-unknown-unknown \
+//!     --no-default-features --features std
+//! cp target/wasm32-unknown-unknown/release/examples/wasm.wasm examples/wasm/
+//! ```
+//!
+//! The page copies the class file into a buffer returned by
+//! `wasm_alloc`, runs it with `wasm_run` and reads the program
+//! output back with `wasm_output` and `wasm_output_len`.
+use std::sync::Mutex;
+
+use coldbrew::options::RuntimeOptions;
+
+// Output of the last run, the program's stdout followed by the error that
+// ended it if any.
+static OUTPUT: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+/// Allocate a buffer of `len` bytes to copy a class file into.
+#[no_mangle]
+pub extern "C" fn wasm_alloc(len: usize) -> *mut u8 {
+    Box::into_raw(vec![0u8; len].into_boxed_slice()).cast()
+}
+
+/// Run the class file held in the buffer at `ptr` and return its exit code.
+/// # Safety
+/// `ptr` and `len` must come from a single call to `wasm_alloc`, the
+/// buffer is freed by this call.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_run(ptr: *mut u8, len: usize) -> i32 {
+    let bytes = Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len));
+    let (exit_code, output) =
+        match coldbrew::run_class_bytes(&bytes, &[], RuntimeOptions::default())
+        {
+            Ok(execution) => {
+                let mut output = execution.stdout;
+                if let Some(err) = &execution.error {
+                    output.push_str(&format!("Error : {err}\n"));
+                }
+                (execution.exit_code, output)
+            }
+            Err(err) => (1, format!("Error : {err}\n")),
+        };
+    if let Ok(mut buffer) = OUTPUT.lock() {
+        *buffer = output.into_bytes();
+    }
+    exit_code
+}
+
+/// Returns a pointer to the output of the last run.
+#[no_mangle]
+pub extern "C" fn wasm_output() -> *const u8 {
+    OUTPUT
+        .lock()
+        .map_or(std::ptr::null(), |buffer| buffer.as_ptr())
+}
+
+/// Returns the length in bytes of the output of the last run.
+#[no_mangle]
+pub extern "C" fn wasm_output_len() -> usize {
+    OUTPUT.lock().map_or(0, |buffer| buffer.len())
+}
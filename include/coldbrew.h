@@ -0,0 +1,65 @@
+/*
+ * C interface of the coldbrew JVM, see `src/capi.rs`.
+ *
+ * Build the shared library with :
+ *   cargo rustc --release --lib --features capi --crate-type cdylib
+ */
+#ifndef COLDBREW_H
+#define COLDBREW_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum {
+  COLDBREW_OK = 0,
+  COLDBREW_INVALID_ARGUMENT = 1,
+  COLDBREW_PARSE = 2,
+  COLDBREW_LINK = 3,
+  COLDBREW_RUNTIME = 4,
+  COLDBREW_IO = 5,
+  COLDBREW_JIT = 6,
+} ColdbrewStatus;
+
+typedef enum {
+  COLDBREW_VOID = 0,
+  COLDBREW_INT = 1,
+  COLDBREW_LONG = 2,
+  COLDBREW_FLOAT = 3,
+  COLDBREW_DOUBLE = 4,
+  COLDBREW_REFERENCE = 5,
+} ColdbrewType;
+
+typedef struct {
+  ColdbrewType kind;
+  union {
+    int32_t int_;
+    int64_t long_;
+    float float_;
+    double double_;
+    uint32_t reference;
+  } payload;
+} ColdbrewValue;
+
+typedef struct ColdbrewProgram ColdbrewProgram;
+
+ColdbrewStatus coldbrew_parse(const uint8_t *bytes, size_t len,
+                              ColdbrewProgram **program);
+void coldbrew_program_free(ColdbrewProgram *program);
+ColdbrewStatus coldbrew_run(const ColdbrewProgram *program, bool jit);
+ColdbrewStatus coldbrew_call_static(const ColdbrewProgram *program,
+                                    const char *method,
+                                    const char *descriptor,
+                                    const ColdbrewValue *args, size_t nargs,
+                                    ColdbrewValue *result);
+const char *coldbrew_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* COLDBREW_H */
@@ -0,0 +1,360 @@
+//! Minimal textual assembler for JVM bytecode.
+//!
+//! Programs are written one instruction per line as `mnemonic operands...`,
+//! comments start with `;` and labels are declared as `name:` on their own
+//! line. Branch operands are either relative offsets or label names.
+//!
+//! ```text
+//!     iconst_0
+//!     istore_1
+//! loop:
+//!     iinc 1 1
+//!     iload_1
+//!     bipush 10
+//!     if_icmplt loop
+//! ```
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::bytecode::{OPCode, OperandKind};
+
+/// `AssemblerErrorKind` represents the possible errors that can occur
+/// when assembling a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssemblerErrorKind {
+    UnknownMnemonic(String),
+    UnsupportedOpcode(OPCode),
+    OperandCount {
+        opcode: OPCode,
+        expected: usize,
+        got: usize,
+    },
+    InvalidOperand(String),
+    UnknownLabel(String),
+    DuplicateLabel(String),
+}
+
+/// `AssemblerError` reports an assembly failure and the line it occurred at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssemblerError {
+    pub kind: AssemblerErrorKind,
+    pub line: usize,
+}
+
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {} : ", self.line)?;
+        match &self.kind {
+            AssemblerErrorKind::UnknownMnemonic(mnemonic) => {
+                write!(f, "unknown mnemonic `{mnemonic}`")
+            }
+            AssemblerErrorKind::UnsupportedOpcode(opcode) => {
+                write!(f, "variable length instruction {opcode} is unsupported")
+            }
+            AssemblerErrorKind::OperandCount {
+                opcode,
+                expected,
+                got,
+            } => write!(
+                f,
+                "instruction {opcode} expects {expected} operands, got {got}"
+            ),
+            AssemblerErrorKind::InvalidOperand(operand) => {
+                write!(f, "invalid operand `{operand}`")
+            }
+            AssemblerErrorKind::UnknownLabel(label) => {
+                write!(f, "unknown label `{label}`")
+            }
+            AssemblerErrorKind::DuplicateLabel(label) => {
+                write!(f, "label `{label}` is already defined")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AssemblerError {}
+
+/// A parsed instruction waiting to be encoded.
+struct Statement<'a> {
+    line: usize,
+    offset: usize,
+    opcode: OPCode,
+    operands: Vec<&'a str>,
+}
+
+/// Assemble `source` into bytecode.
+/// # Errors
+/// Returns an error on unknown mnemonics, labels or invalid operands.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssemblerError> {
+    let mut labels: HashMap<&str, usize> = HashMap::new();
+    let mut statements = Vec::new();
+    let mut offset = 0;
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |kind| AssemblerError {
+            kind,
+            line: line_number,
+        };
+        let text = line.split(';').next().unwrap_or_default().trim();
+        if text.is_empty() {
+            continue;
+        }
+        if let Some(label) = text.strip_suffix(':') {
+            if labels.insert(label.trim(), offset).is_some() {
+                return Err(error(AssemblerErrorKind::DuplicateLabel(
+                    label.trim().to_string(),
+                )));
+            }
+            continue;
+        }
+        let mut tokens = text.split_whitespace();
+        let mnemonic = tokens.next().unwrap_or_default();
+        let opcode = OPCode::from_mnemonic(mnemonic).ok_or_else(|| {
+            error(AssemblerErrorKind::UnknownMnemonic(mnemonic.to_string()))
+        })?;
+        let kinds = opcode.operands().ok_or_else(|| {
+            error(AssemblerErrorKind::UnsupportedOpcode(opcode))
+        })?;
+        let operands: Vec<&str> = tokens.collect();
+        if operands.len() != kinds.len() {
+            return Err(error(AssemblerErrorKind::OperandCount {
+                opcode,
+                expected: kinds.len(),
+                got: operands.len(),
+            }));
+        }
+        statements.push(Statement {
+            line: line_number,
+            offset,
+            opcode,
+            operands,
+        });
+        offset += 1 + kinds.iter().map(OperandKind::size).sum::<usize>();
+    }
+
+    let mut code = Vec::with_capacity(offset);
+    for statement in &statements {
+        let error = |kind| AssemblerError {
+            kind,
+            line: statement.line,
+        };
+        code.push(statement.opcode.byte());
+        let kinds = statement.opcode.operands().unwrap_or_default();
+        for (operand, kind) in statement.operands.iter().zip(kinds) {
+            let value = match parse_integer(operand) {
+                Some(value) => value,
+                None if statement.opcode.is_branch() => {
+                    let target = labels.get(operand).ok_or_else(|| {
+                        error(AssemblerErrorKind::UnknownLabel(
+                            operand.to_string(),
+                        ))
+                    })?;
+                    *target as i64 - statement.offset as i64
+                }
+                None => {
+                    return Err(error(AssemblerErrorKind::InvalidOperand(
+                        operand.to_string(),
+                    )))
+                }
+            };
+            encode_operand(&mut code, value, *kind).ok_or_else(|| {
+                error(AssemblerErrorKind::InvalidOperand(operand.to_string()))
+            })?;
+        }
+    }
+    Ok(code)
+}
+
+/// Parse a decimal or `0x` prefixed hexadecimal integer.
+fn parse_integer(token: &str) -> Option<i64> {
+    let (negative, digits) = match token.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, token),
+    };
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None => digits.parse::<i64>().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Encode `value` as a big endian operand of the given kind, returns `None`
+/// if the value doesn't fit.
+fn encode_operand(
+    code: &mut Vec<u8>,
+    value: i64,
+    kind: OperandKind,
+) -> Option<()> {
+    match kind {
+        OperandKind::I8 => code.push(i8::try_from(value).ok()? as u8),
+        OperandKind::U8 => code.push(u8::try_from(value).ok()?),
+        OperandKind::I16 => {
+            code.extend(i16::try_from(value).ok()?.to_be_bytes());
+        }
+        OperandKind::U16 => {
+            code.extend(u16::try_from(value).ok()?.to_be_bytes());
+        }
+        OperandKind::I32 => {
+            code.extend(i32::try_from(value).ok()?.to_be_bytes());
+        }
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::disassemble;
+    use proptest::prelude::*;
+
+    /// Opcodes with fixed operands that the assembler knows by name.
+    fn fixed_length_opcodes() -> Vec<OPCode> {
+        (0..=OPCode::Breakpoint.byte())
+            .map(OPCode::from)
+            .filter(|opcode| opcode.operands().is_some())
+            .filter(|opcode| {
+                OPCode::from_mnemonic(&opcode.to_string()) == Some(*opcode)
+            })
+            .collect()
+    }
+
+    fn operand(kind: OperandKind) -> BoxedStrategy<i64> {
+        match kind {
+            OperandKind::I8 => {
+                (i64::from(i8::MIN)..=i64::from(i8::MAX)).boxed()
+            }
+            OperandKind::U8 => (0..=i64::from(u8::MAX)).boxed(),
+            OperandKind::I16 => {
+                (i64::from(i16::MIN)..=i64::from(i16::MAX)).boxed()
+            }
+            OperandKind::U16 => (0..=i64::from(u16::MAX)).boxed(),
+            OperandKind::I32 => {
+                (i64::from(i32::MIN)..=i64::from(i32::MAX)).boxed()
+            }
+        }
+    }
+
+    fn instruction() -> impl Strategy<Value = (OPCode, Vec<i64>)> {
+        proptest::sample::select(fixed_length_opcodes()).prop_flat_map(
+            |opcode| {
+                let kinds = opcode.operands().unwrap_or_default();
+                let operands: Vec<_> =
+                    kinds.iter().copied().map(operand).collect();
+                (Just(opcode), operands)
+            },
+        )
+    }
+
+    proptest! {
+        // Catches operand width and signedness mismatches between the
+        // assembler and the disassembler.
+        #[test]
+        fn disassembles_what_it_assembles(
+            program in proptest::collection::vec(instruction(), 1..32)
+        ) {
+            let source: Vec<String> = program
+                .iter()
+                .map(|(opcode, operands)| {
+                    operands.iter().fold(opcode.to_string(), |line, operand| {
+                        format!("{line} {operand}")
+                    })
+                })
+                .collect();
+            let code = assemble(&source.join("\n")).unwrap();
+            let decoded = disassemble(&code).unwrap();
+            prop_assert_eq!(decoded.len(), program.len());
+            let mut offset = 0;
+            for (inst, (opcode, operands)) in decoded.iter().zip(&program) {
+                prop_assert_eq!(inst.offset, offset);
+                prop_assert_eq!(inst.opcode, *opcode);
+                prop_assert_eq!(&inst.operands, operands);
+                let kinds = opcode.operands().unwrap_or_default();
+                offset += 1 + kinds.iter().map(OperandKind::size).sum::<usize>();
+            }
+            prop_assert_eq!(offset, code.len());
+        }
+    }
+
+    #[test]
+    fn can_assemble_instructions() {
+        let code = assemble(
+            "
+            bipush 12     ; push a constant
+            invokestatic 7
+            istore_1
+            iinc 2 -1
+            sipush 0x100
+            return
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            code,
+            vec![16, 12, 184, 0, 7, 60, 132, 2, 255, 17, 1, 0, 177]
+        );
+    }
+
+    #[test]
+    fn resolves_labels_to_relative_offsets() {
+        // Same loop as the `factorial` method in `support/tests/Factorial`.
+        let code = assemble(
+            "
+                iconst_1
+                istore_1
+                iconst_2
+                istore_2
+            loop:
+                iload_2
+                iload_0
+                if_icmpgt exit
+                iload_1
+                iload_2
+                imul
+                istore_1
+                iinc 2 1
+                goto loop
+            exit:
+                iload_1
+                ireturn
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            code,
+            vec![
+                4, 60, 5, 61, 28, 26, 163, 0, 13, 27, 28, 104, 60, 132, 2, 1,
+                167, 255, 244, 27, 172,
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_errors_with_line_numbers() {
+        let err = assemble("nop\nfoo 1").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert_eq!(
+            err.kind,
+            AssemblerErrorKind::UnknownMnemonic("foo".to_string())
+        );
+        let err = assemble("bipush 200").unwrap_err();
+        assert_eq!(
+            err.kind,
+            AssemblerErrorKind::InvalidOperand("200".to_string())
+        );
+        let err = assemble("goto nowhere").unwrap_err();
+        assert_eq!(
+            err.kind,
+            AssemblerErrorKind::UnknownLabel("nowhere".to_string())
+        );
+        let err = assemble("iinc 1").unwrap_err();
+        assert_eq!(
+            err.kind,
+            AssemblerErrorKind::OperandCount {
+                opcode: OPCode::IInc,
+                expected: 2,
+                got: 1
+            }
+        );
+    }
+}
@@ -0,0 +1,703 @@
+//! Baseline compiler, the second tier of the JIT.
+//!
+//! Warm methods and methods whose loops keep aborting trace recording, as
+//! decided by `coldbrew::tiering`, are compiled whole, one template per
+//! opcode and without any optimization. The JVM operand stack is kept in
+//! memory and its depth at every instruction is known at compile time, so
+//! the interpreter can resume a method wherever its native code bails out.
+//!
+//! The native code of a method has the following signature :
+//!
+//! ```text
+//! extern "sysv64" fn(locals: *mut i32, stack: *mut i32, fuel: *mut i32,
+//!                    out: *mut i32, back_edges: *mut i32) -> i32
+//! ```
+//!
+//! and returns `RETURN_VOID`, `RETURN_VALUE` with the returned value in
+//! `out` or one of `CALL`, `SAFEPOINT` and `DEOPT` with the program counter
+//! the interpreter resumes at in `out`. Back edges taken are counted in
+//! `back_edges` for the tiering policy. The native code is entered with the program counter to start at
+//! in `out`, either the start of the method, a loop header or the return
+//! site of a call. Calls are made by the interpreter, the native code
+//! bails out at every call, when a division would raise an exception and
+//! at loop back edges once the fuel runs out.
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use crate::bytecode::OPCode;
+use crate::disasm::{self, DecodedInstruction};
+use crate::error::{JitError, JitErrorKind};
+use crate::fault::{self, Fault};
+use crate::program::{Program, RuntimeConstant};
+use crate::runtime::{Locals, ProgramCounter, Value};
+
+use dynasmrt::x64::Assembler;
+use dynasmrt::{
+    dynasm, AssemblyOffset, DynamicLabel, DynasmApi, DynasmLabelApi,
+    ExecutableBuffer,
+};
+
+/// The method returned without a value.
+const RETURN_VOID: i32 = 0;
+/// The method returned the value stored in `out`.
+const RETURN_VALUE: i32 = 1;
+/// The interpreter makes the call at the program counter stored in `out`.
+const CALL: i32 = 2;
+/// The fuel ran out at the back edge to the loop header stored in `out`.
+const SAFEPOINT: i32 = 3;
+/// The instruction stored in `out` has to be interpreted.
+const DEOPT: i32 = 4;
+
+/// `ExitKind` is why the native code of a method returned to the
+/// interpreter before the method returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitKind {
+    /// The method calls another method.
+    Call,
+    /// The fuel ran out at a loop back edge.
+    Safepoint,
+    /// The instruction would raise an exception.
+    Deopt,
+}
+
+/// `BaselineExit` is how the native code of a method left.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BaselineExit {
+    /// The method returned, with a value unless it's `void`.
+    Return(Option<Value>),
+    /// The interpreter resumes the method at `pc` with `stack` as the
+    /// operand stack.
+    Interpret {
+        kind: ExitKind,
+        pc: usize,
+        stack: Vec<Value>,
+    },
+}
+
+/// Native code of a method.
+struct BaselineMethod {
+    entry: AssemblyOffset,
+    code: ExecutableBuffer,
+    // Operand stack depth before every reachable instruction.
+    depths: HashMap<usize, usize>,
+    // Program counters the native code can be entered at.
+    entries: HashSet<usize>,
+    max_locals: usize,
+    max_stack: usize,
+}
+
+/// `BaselineCache` compiles, caches and executes baseline methods.
+#[derive(Default)]
+pub struct BaselineCache {
+    methods: HashMap<usize, BaselineMethod>,
+}
+
+impl BaselineCache {
+    /// Create an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns true if the method at `method_index` has native code.
+    pub fn has_method(&self, method_index: usize) -> bool {
+        self.methods.contains_key(&method_index)
+    }
+
+    /// Returns true if the native code of the method at `pc` can be
+    /// entered at `pc`.
+    pub fn has_entry(&self, pc: ProgramCounter) -> bool {
+        self.methods
+            .get(&pc.get_method_index())
+            .is_some_and(|method| {
+                method.entries.contains(&pc.get_instruction_index())
+            })
+    }
+
+    /// Compile the method at `method_index` of `program`.
+    /// # Errors
+    /// Returns an error if the method uses an instruction or a feature the
+    /// baseline compiler doesn't support.
+    pub fn compile(
+        &mut self,
+        program: &Program,
+        method_index: usize,
+    ) -> Result<(), JitError> {
+        let method = assemble(program, method_index)?;
+        self.methods.insert(method_index, method);
+        Ok(())
+    }
+
+    /// Run the native code of the method at `pc` from `pc` with `stack` as
+    /// the operand stack, `locals` are updated and the loop back edges taken
+    /// are added to `back_edges`.
+    ///
+    /// Returns `None` if the native code can't be entered at `pc` or one of
+    /// the locals or stack values isn't an integer, and the fault raised by
+    /// the native code in which case `locals` are left as they were.
+    pub fn execute(
+        &self,
+        pc: ProgramCounter,
+        locals: &mut Locals,
+        stack: &[Value],
+        fuel: &mut i32,
+        back_edges: &mut usize,
+    ) -> Option<Result<BaselineExit, Fault>> {
+        let method = self.methods.get(&pc.get_method_index())?;
+        let start = pc.get_instruction_index();
+        if !method.entries.contains(&start)
+            || method.depths[&start] != stack.len()
+        {
+            return None;
+        }
+        let ints = |value: &Value| match value {
+            Value::Int(value) => Some(*value),
+            _ => None,
+        };
+        let mut native_locals = vec![0i32; method.max_locals.max(1)];
+        for (index, value) in locals.iter() {
+            *native_locals.get_mut(index)? = ints(&value)?;
+        }
+        let mut native_stack = vec![0i32; method.max_stack.max(1)];
+        for (slot, value) in native_stack.iter_mut().zip(stack) {
+            *slot = ints(value)?;
+        }
+        let mut out = i32::try_from(start).ok()?;
+        let mut taken = 0i32;
+        let args = [
+            native_locals.as_mut_ptr() as usize,
+            native_stack.as_mut_ptr() as usize,
+            fuel as *mut i32 as usize,
+            &mut out as *mut i32 as usize,
+            &mut taken as *mut i32 as usize,
+        ];
+        let status =
+            match unsafe { fault::call(&method.code, method.entry, &args) } {
+                Ok(status) => status as i32,
+                Err(fault) => return Some(Err(fault)),
+            };
+        *back_edges += usize::try_from(taken).unwrap_or(0);
+        // Methods without locals still get a slot, it isn't written back.
+        for (index, value) in native_locals.iter().enumerate() {
+            let _ = locals.insert(index, Value::Int(*value));
+        }
+        Some(Ok(match status {
+            RETURN_VOID => BaselineExit::Return(None),
+            RETURN_VALUE => BaselineExit::Return(Some(Value::Int(out))),
+            status => {
+                let pc = out as usize;
+                let depth = method.depths.get(&pc).copied().unwrap_or(0);
+                BaselineExit::Interpret {
+                    kind: match status {
+                        CALL => ExitKind::Call,
+                        SAFEPOINT => ExitKind::Safepoint,
+                        _ => ExitKind::Deopt,
+                    },
+                    pc,
+                    stack: native_stack[..depth]
+                        .iter()
+                        .copied()
+                        .map(Value::Int)
+                        .collect(),
+                }
+            }
+        }))
+    }
+}
+
+/// Returns the stack depth `instruction` needs and the depth it leaves, or
+/// `None` if the baseline compiler doesn't support it.
+fn stack_effect(instruction: &DecodedInstruction) -> Option<(usize, usize)> {
+    Some(match instruction.opcode {
+        OPCode::Nop | OPCode::IInc | OPCode::Goto | OPCode::Return => (0, 0),
+        OPCode::IconstM1
+        | OPCode::Iconst0
+        | OPCode::Iconst1
+        | OPCode::Iconst2
+        | OPCode::Iconst3
+        | OPCode::Iconst4
+        | OPCode::Iconst5
+        | OPCode::BiPush
+        | OPCode::SiPush
+        | OPCode::Ldc
+        | OPCode::LdcW
+        | OPCode::ILoad
+        | OPCode::ILoad0
+        | OPCode::ILoad1
+        | OPCode::ILoad2
+        | OPCode::ILoad3 => (0, 1),
+        OPCode::IStore
+        | OPCode::IStore0
+        | OPCode::IStore1
+        | OPCode::IStore2
+        | OPCode::IStore3
+        | OPCode::IfEq
+        | OPCode::IfNe
+        | OPCode::IfLt
+        | OPCode::IfGe
+        | OPCode::IfGt
+        | OPCode::IfLe
+        | OPCode::IReturn => (1, 0),
+        OPCode::IAdd
+        | OPCode::ISub
+        | OPCode::IMul
+        | OPCode::IDiv
+        | OPCode::IRem => (2, 1),
+        OPCode::IfICmpEq
+        | OPCode::IfICmpNe
+        | OPCode::IfICmpLt
+        | OPCode::IfICmpGe
+        | OPCode::IfICmpGt
+        | OPCode::IfICmpLe => (2, 0),
+        _ => return None,
+    })
+}
+
+/// Returns the stack depth before every instruction reachable from the
+/// start of the method.
+fn stack_depths(
+    instructions: &[DecodedInstruction],
+    calls: &HashMap<usize, (usize, usize)>,
+) -> Result<HashMap<usize, usize>, JitErrorKind> {
+    let index: HashMap<usize, usize> = instructions
+        .iter()
+        .enumerate()
+        .map(|(index, instruction)| (instruction.offset, index))
+        .collect();
+    let mut depths = HashMap::from([(0, 0)]);
+    let mut work = VecDeque::from([0]);
+    while let Some(offset) = work.pop_front() {
+        let instruction = &instructions[index[&offset]];
+        let depth = depths[&offset];
+        let (pops, pushes) = stack_effect(instruction)
+            .or_else(|| calls.get(&offset).copied())
+            .filter(|(pops, _)| *pops <= depth)
+            .ok_or(JitErrorKind::Unsupported(instruction.opcode))?;
+        let after = depth - pops + pushes;
+        let mut successors = instruction.targets.clone();
+        if !matches!(
+            instruction.opcode,
+            OPCode::Goto | OPCode::Return | OPCode::IReturn
+        ) {
+            let next = instructions
+                .get(index[&offset] + 1)
+                .ok_or(JitErrorKind::Unsupported(instruction.opcode))?;
+            successors.push(next.offset);
+        }
+        for successor in successors {
+            if !index.contains_key(&successor) {
+                return Err(JitErrorKind::Unsupported(instruction.opcode));
+            }
+            match depths.insert(successor, after) {
+                None => work.push_back(successor),
+                Some(previous) if previous != after => {
+                    return Err(JitErrorKind::Unsupported(instruction.opcode));
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    Ok(depths)
+}
+
+/// Returns the number of arguments and results of the program methods
+/// `invokestatic` calls at every call site, calls taking or returning
+/// anything but integers aren't supported.
+fn static_calls(
+    program: &Program,
+    method_index: usize,
+    instructions: &[DecodedInstruction],
+) -> HashMap<usize, (usize, usize)> {
+    let int = |c: char| matches!(c, 'I' | 'B' | 'C' | 'S' | 'Z');
+    instructions
+        .iter()
+        .filter(|instruction| instruction.opcode == OPCode::InvokeStatic)
+        .filter_map(|instruction| {
+            let id = program
+                .symbol_id(method_index, instruction.operands[0] as usize)?;
+            let symbol = program.symbol(id).filter(|s| s.target.is_some())?;
+            let (args, ret) =
+                symbol.descriptor.strip_prefix('(')?.split_once(')')?;
+            let pushes = match ret {
+                "V" => 0,
+                ret if ret.len() == 1 && ret.chars().all(int) => 1,
+                _ => return None,
+            };
+            args.chars()
+                .all(int)
+                .then_some((instruction.offset, (args.len(), pushes)))
+        })
+        .collect()
+}
+
+/// Assemble the method at `method_index`.
+fn assemble(
+    program: &Program,
+    method_index: usize,
+) -> Result<BaselineMethod, JitError> {
+    let error = |offset, kind| JitError {
+        pc: ProgramCounter::new(method_index, offset),
+        kind,
+    };
+    if !program.exception_table(method_index).is_empty() {
+        return Err(error(
+            0,
+            JitErrorKind::Method("exception handlers".to_string()),
+        ));
+    }
+    let instructions = disasm::disassemble(program.code(method_index))
+        .map_err(|err| {
+            error(err.offset, JitErrorKind::Method(err.to_string()))
+        })?;
+    let calls = static_calls(program, method_index, &instructions);
+    let depths =
+        stack_depths(&instructions, &calls).map_err(|kind| error(0, kind))?;
+    // The method is entered at its start, at loop headers once the
+    // interpreter took over and at the return sites of calls.
+    let mut entries = HashSet::from([0]);
+    for (position, instruction) in instructions.iter().enumerate() {
+        if !depths.contains_key(&instruction.offset) {
+            continue;
+        }
+        entries.extend(
+            instruction
+                .targets
+                .iter()
+                .filter(|target| **target <= instruction.offset),
+        );
+        if calls.contains_key(&instruction.offset) {
+            entries.extend(instructions.get(position + 1).map(|i| i.offset));
+        }
+    }
+    let mut ops = Assembler::new()
+        .map_err(|err| error(0, JitErrorKind::Assembler(err.to_string())))?;
+    let labels: HashMap<usize, DynamicLabel> = depths
+        .keys()
+        .map(|offset| (*offset, ops.new_dynamic_label()))
+        .collect();
+    // Exit stubs by the program counter they resume at and their status.
+    let mut exits: BTreeMap<(usize, i32), DynamicLabel> = BTreeMap::new();
+    let mut exit = |ops: &mut Assembler, pc: usize, status: i32| {
+        *exits
+            .entry((pc, status))
+            .or_insert_with(|| ops.new_dynamic_label())
+    };
+
+    let entry = ops.offset();
+    // `rdx` and `rcx` are needed by `idiv`, the fuel and `out` pointers
+    // are kept in `r10` and `r11`, `back_edges` stays in `r8`.
+    dynasm!(ops
+        ; mov r10, rdx
+        ; mov r11, rcx
+    );
+    for pc in &entries {
+        dynasm!(ops
+            ; cmp DWORD [r11], *pc as i32
+            ; je =>labels[pc]
+        );
+    }
+    dynasm!(ops
+        ; jmp =>labels[&0]
+    );
+    for (position, instruction) in instructions.iter().enumerate() {
+        let offset = instruction.offset;
+        let Some(&depth) = depths.get(&offset) else {
+            continue;
+        };
+        let slot = |index: usize| 4 * index as i32;
+        let operand = |index: usize| instruction.operands[index] as i32;
+        dynasm!(ops
+            ; =>labels[&offset]
+        );
+        match instruction.opcode {
+            OPCode::IconstM1
+            | OPCode::Iconst0
+            | OPCode::Iconst1
+            | OPCode::Iconst2
+            | OPCode::Iconst3
+            | OPCode::Iconst4
+            | OPCode::Iconst5
+            | OPCode::BiPush
+            | OPCode::SiPush
+            | OPCode::Ldc
+            | OPCode::LdcW => {
+                let value = match instruction.opcode {
+                    OPCode::BiPush | OPCode::SiPush => operand(0),
+                    OPCode::Ldc | OPCode::LdcW => {
+                        let index = instruction.operands[0] as usize;
+                        match program.constant(method_index, index) {
+                            Some(RuntimeConstant::Int(value)) => value,
+                            _ => {
+                                return Err(error(
+                                    offset,
+                                    JitErrorKind::Unsupported(
+                                        instruction.opcode,
+                                    ),
+                                ));
+                            }
+                        }
+                    }
+                    opcode => {
+                        opcode.byte() as i32 - OPCode::Iconst0.byte() as i32
+                    }
+                };
+                dynasm!(ops
+                    ; mov DWORD [rsi + slot(depth)], value
+                );
+            }
+            OPCode::ILoad
+            | OPCode::ILoad0
+            | OPCode::ILoad1
+            | OPCode::ILoad2
+            | OPCode::ILoad3 => {
+                let local = local_index(instruction);
+                dynasm!(ops
+                    ; mov eax, DWORD [rdi + slot(local)]
+                    ; mov DWORD [rsi + slot(depth)], eax
+                );
+            }
+            OPCode::IStore
+            | OPCode::IStore0
+            | OPCode::IStore1
+            | OPCode::IStore2
+            | OPCode::IStore3 => {
+                let local = local_index(instruction);
+                dynasm!(ops
+                    ; mov eax, DWORD [rsi + slot(depth - 1)]
+                    ; mov DWORD [rdi + slot(local)], eax
+                );
+            }
+            OPCode::IInc => {
+                let (local, constant) = (operand(0) as usize, operand(1));
+                dynasm!(ops
+                    ; add DWORD [rdi + slot(local)], constant
+                );
+            }
+            OPCode::IAdd | OPCode::ISub | OPCode::IMul => {
+                let (lhs, rhs) = (slot(depth - 2), slot(depth - 1));
+                dynasm!(ops
+                    ; mov eax, DWORD [rsi + lhs]
+                );
+                match instruction.opcode {
+                    OPCode::IAdd => dynasm!(ops
+                        ; add eax, DWORD [rsi + rhs]
+                    ),
+                    OPCode::ISub => dynasm!(ops
+                        ; sub eax, DWORD [rsi + rhs]
+                    ),
+                    _ => dynasm!(ops
+                        ; imul eax, DWORD [rsi + rhs]
+                    ),
+                }
+                dynasm!(ops
+                    ; mov DWORD [rsi + lhs], eax
+                );
+            }
+            // Division by zero and the overflowing `MIN / -1` are left to
+            // the interpreter.
+            OPCode::IDiv | OPCode::IRem => {
+                let (lhs, rhs) = (slot(depth - 2), slot(depth - 1));
+                let bail = exit(&mut ops, offset, DEOPT);
+                dynasm!(ops
+                    ; mov ecx, DWORD [rsi + rhs]
+                    ; test ecx, ecx
+                    ; je =>bail
+                    ; cmp ecx, -1
+                    ; je =>bail
+                    ; mov eax, DWORD [rsi + lhs]
+                    ; cdq
+                    ; idiv ecx
+                );
+                if instruction.opcode == OPCode::IDiv {
+                    dynasm!(ops
+                        ; mov DWORD [rsi + lhs], eax
+                    );
+                } else {
+                    dynasm!(ops
+                        ; mov DWORD [rsi + lhs], edx
+                    );
+                }
+            }
+            OPCode::Goto => {
+                let target = instruction.targets[0];
+                emit_jump(
+                    &mut ops,
+                    &instructions,
+                    position,
+                    target,
+                    &labels,
+                    &mut exit,
+                );
+            }
+            OPCode::IfEq
+            | OPCode::IfNe
+            | OPCode::IfLt
+            | OPCode::IfGe
+            | OPCode::IfGt
+            | OPCode::IfLe
+            | OPCode::IfICmpEq
+            | OPCode::IfICmpNe
+            | OPCode::IfICmpLt
+            | OPCode::IfICmpGe
+            | OPCode::IfICmpGt
+            | OPCode::IfICmpLe => {
+                let target = instruction.targets[0];
+                let (pops, _) = stack_effect(instruction).unwrap_or((1, 0));
+                if pops == 1 {
+                    dynasm!(ops
+                        ; cmp DWORD [rsi + slot(depth - 1)], 0
+                    );
+                } else {
+                    dynasm!(ops
+                        ; mov eax, DWORD [rsi + slot(depth - 2)]
+                        ; cmp eax, DWORD [rsi + slot(depth - 1)]
+                    );
+                }
+                // The branch is skipped when its condition doesn't hold.
+                let skip = ops.new_dynamic_label();
+                emit_jcc(&mut ops, negate(instruction.opcode), skip);
+                emit_jump(
+                    &mut ops,
+                    &instructions,
+                    position,
+                    target,
+                    &labels,
+                    &mut exit,
+                );
+                dynasm!(ops
+                    ; =>skip
+                );
+            }
+            // The interpreter makes the call.
+            OPCode::InvokeStatic => {
+                let call = exit(&mut ops, offset, CALL);
+                dynasm!(ops
+                    ; jmp =>call
+                );
+            }
+            OPCode::IReturn => {
+                dynasm!(ops
+                    ; mov eax, DWORD [rsi + slot(depth - 1)]
+                    ; mov DWORD [r11], eax
+                    ; mov eax, RETURN_VALUE
+                    ; ret
+                );
+            }
+            OPCode::Return => {
+                dynasm!(ops
+                    ; mov eax, RETURN_VOID
+                    ; ret
+                );
+            }
+            _ => {}
+        }
+    }
+    for ((pc, status), label) in exits {
+        dynasm!(ops
+            ; =>label
+            ; mov DWORD [r11], pc as i32
+            ; mov eax, status
+            ; ret
+        );
+    }
+    let code = ops.finalize().map_err(|_| {
+        error(
+            0,
+            JitErrorKind::Assembler(
+                "failed to finalize executable buffer".to_string(),
+            ),
+        )
+    })?;
+    Ok(BaselineMethod {
+        entry,
+        code,
+        depths,
+        entries,
+        max_locals: usize::from(program.max_locals(method_index)),
+        max_stack: usize::from(program.max_stack(method_index)),
+    })
+}
+
+/// Returns the local a load or a store accesses.
+fn local_index(instruction: &DecodedInstruction) -> usize {
+    match instruction.opcode {
+        OPCode::ILoad0 | OPCode::IStore0 => 0,
+        OPCode::ILoad1 | OPCode::IStore1 => 1,
+        OPCode::ILoad2 | OPCode::IStore2 => 2,
+        OPCode::ILoad3 | OPCode::IStore3 => 3,
+        _ => instruction.operands[0] as usize,
+    }
+}
+
+/// Emit a jump from the instruction at `position` to `target`, back edges
+/// are counted and burn a unit of fuel for every instruction of the loop,
+/// they exit to the loop header when it runs out.
+fn emit_jump(
+    ops: &mut Assembler,
+    instructions: &[DecodedInstruction],
+    position: usize,
+    target: usize,
+    labels: &HashMap<usize, DynamicLabel>,
+    exit: &mut impl FnMut(&mut Assembler, usize, i32) -> DynamicLabel,
+) {
+    let offset = instructions[position].offset;
+    if target <= offset {
+        let header = instructions
+            .iter()
+            .position(|instruction| instruction.offset == target)
+            .unwrap_or(position);
+        let burn = (position - header + 1) as i32;
+        let safepoint = exit(ops, target, SAFEPOINT);
+        dynasm!(ops
+            ; add DWORD [r8], 1
+            ; sub DWORD [r10], burn
+            ; jle =>safepoint
+        );
+    }
+    dynasm!(ops
+        ; jmp =>labels[&target]
+    );
+}
+
+/// Emit a jump to `label` taken when the condition of `cond` holds for
+/// the last comparison.
+fn emit_jcc(ops: &mut Assembler, cond: OPCode, label: DynamicLabel) {
+    match cond {
+        OPCode::IfEq | OPCode::IfICmpEq => dynasm!(ops
+            ; je =>label
+        ),
+        OPCode::IfNe | OPCode::IfICmpNe => dynasm!(ops
+            ; jne =>label
+        ),
+        OPCode::IfLt | OPCode::IfICmpLt => dynasm!(ops
+            ; jl =>label
+        ),
+        OPCode::IfGe | OPCode::IfICmpGe => dynasm!(ops
+            ; jge =>label
+        ),
+        OPCode::IfGt | OPCode::IfICmpGt => dynasm!(ops
+            ; jg =>label
+        ),
+        OPCode::IfLe | OPCode::IfICmpLe => dynasm!(ops
+            ; jle =>label
+        ),
+        _ => unreachable!("expected a conditional branch got {cond}"),
+    }
+}
+
+/// Returns the branch taken when `cond` doesn't hold.
+fn negate(cond: OPCode) -> OPCode {
+    match cond {
+        OPCode::IfEq => OPCode::IfNe,
+        OPCode::IfNe => OPCode::IfEq,
+        OPCode::IfLt => OPCode::IfGe,
+        OPCode::IfGe => OPCode::IfLt,
+        OPCode::IfGt => OPCode::IfLe,
+        OPCode::IfLe => OPCode::IfGt,
+        OPCode::IfICmpEq => OPCode::IfICmpNe,
+        OPCode::IfICmpNe => OPCode::IfICmpEq,
+        OPCode::IfICmpLt => OPCode::IfICmpGe,
+        OPCode::IfICmpGe => OPCode::IfICmpLt,
+        OPCode::IfICmpGt => OPCode::IfICmpLe,
+        OPCode::IfICmpLe => OPCode::IfICmpGt,
+        _ => cond,
+    }
+}
@@ -0,0 +1,210 @@
+//! Benchmark harness comparing interpreted execution against the tracing JIT.
+//!
+//! A class file is run once in interpret-only mode and once with the JIT
+//! enabled, each run reports the time spent in every phase of the pipeline
+//! and per loop timings which we use to compute the speedup of each loop.
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::jvm::{read_class_file, JVMParser};
+use crate::loader::ClassLoader;
+use crate::runtime::Runtime;
+use crate::stats::Stats;
+use crate::timing::Instant;
+
+/// Wall-clock time spent in each phase of the pipeline.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Phases {
+    /// Reading and parsing the class file.
+    pub parse: Duration,
+    /// Loading the classes the class file references and linking them into
+    /// the `Program`.
+    pub program: Duration,
+    /// Running the program.
+    pub run: Duration,
+}
+
+impl Phases {
+    /// Returns the total time spent in all phases.
+    pub fn total(&self) -> Duration {
+        self.parse + self.program + self.run
+    }
+}
+
+/// Result of running a class file once.
+#[derive(Debug, Clone)]
+pub struct Measurement {
+    pub phases: Phases,
+    pub stats: Stats,
+}
+
+/// Run the class file at `path` once and measure it.
+/// # Errors
+/// Returns an error if the class file can't be parsed or linked or the
+/// program fails during execution.
+pub fn measure(
+    path: &Path,
+    jit_mode: bool,
+) -> Result<Measurement, Box<dyn Error>> {
+    let start = Instant::now();
+    let class_file_bytes = read_class_file(path)?;
+    let class_file = JVMParser::parse(&class_file_bytes)?;
+    let parse = start.elapsed();
+
+    let start = Instant::now();
+    let program = ClassLoader::link_file(path, &class_file)?;
+    let program_time = start.elapsed();
+
+    let start = Instant::now();
+    let mut runtime = Runtime::new(program);
+    runtime.enable_stats();
+    runtime.run(jit_mode)?;
+    let run = start.elapsed();
+
+    Ok(Measurement {
+        phases: Phases {
+            parse,
+            program: program_time,
+            run,
+        },
+        stats: runtime.stats().cloned().unwrap_or_default(),
+    })
+}
+
+/// Comparison of the interpreter and the JIT on a single class file.
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub path: PathBuf,
+    pub runs: usize,
+    pub interpreter: Measurement,
+    pub jit: Measurement,
+}
+
+impl BenchReport {
+    /// Returns the speedup of the JIT over the interpreter for the whole run.
+    pub fn speedup(&self) -> f64 {
+        ratio(self.interpreter.phases.run, self.jit.phases.run)
+    }
+}
+
+/// Benchmark the class file at `path`, each mode is run `runs` times and
+/// the fastest run is kept.
+/// # Errors
+/// Returns an error if any of the runs fails.
+pub fn run(path: &Path, runs: usize) -> Result<BenchReport, Box<dyn Error>> {
+    let runs = runs.max(1);
+    let fastest = |jit_mode: bool| -> Result<Measurement, Box<dyn Error>> {
+        let mut best = measure(path, jit_mode)?;
+        for _ in 1..runs {
+            let measurement = measure(path, jit_mode)?;
+            if measurement.phases.run < best.phases.run {
+                best = measurement;
+            }
+        }
+        Ok(best)
+    };
+    Ok(BenchReport {
+        path: path.to_path_buf(),
+        runs,
+        interpreter: fastest(false)?,
+        jit: fastest(true)?,
+    })
+}
+
+/// Returns how many times faster `new` is compared to `old`.
+fn ratio(old: Duration, new: Duration) -> f64 {
+    if new.is_zero() {
+        return f64::INFINITY;
+    }
+    old.as_secs_f64() / new.as_secs_f64()
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Benchmark : {} (fastest of {} runs)",
+            self.path.display(),
+            self.runs
+        )?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<10} {:>14} {:>14} {:>10}",
+            "phase", "interpreter", "jit", "speedup"
+        )?;
+        let (interpreter, jit) = (&self.interpreter.phases, &self.jit.phases);
+        for (phase, old, new) in [
+            ("parse", interpreter.parse, jit.parse),
+            ("program", interpreter.program, jit.program),
+            ("run", interpreter.run, jit.run),
+            ("total", interpreter.total(), jit.total()),
+        ] {
+            writeln!(
+                f,
+                "{:<10} {:>14} {:>14} {:>9.2}x",
+                phase,
+                format!("{old:.2?}"),
+                format!("{new:.2?}"),
+                ratio(old, new)
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<24} {:>10} {:>14} {:>14} {:>10}",
+            "loop (method:offset)",
+            "iterations",
+            "interpreter",
+            "jit",
+            "speedup"
+        )?;
+        for (header, old) in self.interpreter.stats.loops() {
+            let new = self.jit.stats.loop_stats(header).copied();
+            let new_time = new.map(|stats| stats.time).unwrap_or_default();
+            writeln!(
+                f,
+                "{:<24} {:>10} {:>14} {:>14} {:>9.2}x",
+                format!(
+                    "{}:{}",
+                    header.get_method_index(),
+                    header.get_instruction_index()
+                ),
+                old.iterations,
+                format!("{:.2?}", old.time),
+                format!("{new_time:.2?}"),
+                ratio(old.time, new_time)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    #[cfg_attr(not(feature = "jit"), ignore = "requires the jit feature")]
+    fn can_bench_hot_loop() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/jit/Loop100.class");
+        let report = run(&path, 1).unwrap();
+        // The loop body runs a hundred times, the interpreter sees every
+        // backward branch while the JIT only sees the ones before the trace
+        // is compiled.
+        let (header, stats) = report.interpreter.stats.loops().next().unwrap();
+        assert_eq!(stats.iterations, 100);
+        let jit_stats = report.jit.stats.loop_stats(header).unwrap();
+        assert!(jit_stats.native_entries > 0);
+        assert!(jit_stats.iterations < stats.iterations);
+        assert_eq!(
+            jit_stats.iterations + jit_stats.native_iterations,
+            stats.iterations
+        );
+        assert!(report.to_string().contains("speedup"));
+    }
+}
@@ -0,0 +1,252 @@
+//! Builder collecting the knobs of a `Runtime`.
+//!
+//! `Runtime::new` only takes the program to run, everything else has a
+//! default and is set through the builder. Classes are loaded into the
+//! program before the runtime is built, see `coldbrew::loader`, so there is
+//! no class path knob. The heap size is bounded by `Limits::heap`.
+//!
+//!
+//! ```no_run
+//! # use coldbrew::builder::RuntimeBuilder;
+//! # use coldbrew::options::Limits;
+//! # use coldbrew::program::Program;
+//! # fn build(program: Program) -> coldbrew::Result<()> {
+//! let mut runtime = RuntimeBuilder::new(program)
+//!     .jit(true)
+//!     .hot_threshold(10)
+//!     .limits(Limits { instructions: Some(1_000_000), ..Limits::default() })
+//!     .build()?;
+//! runtime.start().unwrap();
+//! # Ok(())
+//! # }
+//! ```
+use std::io::{Read, Write};
+
+use crate::error::Result;
+use crate::options::{Limits, RuntimeOptions};
+use crate::program::Program;
+use crate::runtime::{MethodEnterHook, MethodExitHook, NativeMethod, Runtime};
+
+/// `RuntimeBuilder` configures a `Runtime` before it's created.
+pub struct RuntimeBuilder {
+    program: Program,
+    options: RuntimeOptions,
+    args: Vec<String>,
+    stdout: Option<Box<dyn Write + Send>>,
+    stdin: Option<Box<dyn Read + Send>>,
+    limits: Option<Limits>,
+    stats: bool,
+    coverage: bool,
+    natives: Vec<(String, String, NativeMethod)>,
+    enter_hooks: Vec<MethodEnterHook>,
+    exit_hooks: Vec<MethodExitHook>,
+}
+
+impl RuntimeBuilder {
+    pub fn new(program: Program) -> Self {
+        Self {
+            program,
+            options: RuntimeOptions::default(),
+            args: vec![],
+            stdout: None,
+            stdin: None,
+            limits: None,
+            stats: false,
+            coverage: false,
+            natives: vec![],
+            enter_hooks: vec![],
+            exit_hooks: vec![],
+        }
+    }
+
+    /// Start from `options`, setters called afterwards override them.
+    #[must_use]
+    pub fn options(mut self, options: RuntimeOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Pass `args` to `main`, see `Runtime::set_args`.
+    #[must_use]
+    pub fn args(mut self, args: &[String]) -> Self {
+        self.args = args.to_vec();
+        self
+    }
+
+    /// Compile hot loops with the tracing JIT when the runtime is started.
+    #[must_use]
+    pub fn jit(mut self, enabled: bool) -> Self {
+        self.options.jit = enabled;
+        self
+    }
+
+    /// Number of loop entries after which a loop is considered hot.
+    #[must_use]
+    pub fn hot_threshold(mut self, threshold: usize) -> Self {
+        self.options.hot_threshold = threshold;
+        self
+    }
+
+    /// Measure the time spent in each phase of the run.
+    #[must_use]
+    pub fn timings(mut self, enabled: bool) -> Self {
+        self.options.time = enabled;
+        self
+    }
+
+    /// Collect execution statistics.
+    #[must_use]
+    pub fn stats(mut self, enabled: bool) -> Self {
+        self.stats = enabled;
+        self
+    }
+
+    /// Collect bytecode coverage.
+    #[must_use]
+    pub fn coverage(mut self, enabled: bool) -> Self {
+        self.coverage = enabled;
+        self
+    }
+
+    /// Write the program's standard output to `sink` instead of stdout.
+    #[must_use]
+    pub fn stdout(mut self, sink: Box<dyn Write + Send>) -> Self {
+        self.stdout = Some(sink);
+        self
+    }
+
+    /// Read the program's standard input from `source` instead of stdin.
+    #[must_use]
+    pub fn stdin(mut self, source: Box<dyn Read + Send>) -> Self {
+        self.stdin = Some(source);
+        self
+    }
+
+    /// Enforce `limits` instead of the limits of the options.
+    #[must_use]
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Implement the static method `name` with `method`, see
+    /// `Runtime::register_native`.
+    #[must_use]
+    pub fn native(
+        mut self,
+        name: &str,
+        descriptor: &str,
+        method: NativeMethod,
+    ) -> Self {
+        self.natives
+            .push((name.to_string(), descriptor.to_string(), method));
+        self
+    }
+
+    /// Call `hook` on method entry, see `Runtime::on_method_enter`.
+    #[must_use]
+    pub fn on_method_enter(mut self, hook: MethodEnterHook) -> Self {
+        self.enter_hooks.push(hook);
+        self
+    }
+
+    /// Call `hook` on method exit, see `Runtime::on_method_exit`.
+    #[must_use]
+    pub fn on_method_exit(mut self, hook: MethodExitHook) -> Self {
+        self.exit_hooks.push(hook);
+        self
+    }
+
+    /// Build the runtime.
+    /// # Errors
+    /// Returns an error if the execution log can't be opened or a native
+    /// method has an unsupported descriptor.
+    pub fn build(self) -> Result<Runtime> {
+        let mut runtime = Runtime::new(self.program);
+        runtime.configure(&self.options)?;
+        runtime.set_args(&self.args);
+        if let Some(sink) = self.stdout {
+            runtime.set_stdout(sink);
+        }
+        if let Some(source) = self.stdin {
+            runtime.set_stdin(source);
+        }
+        if let Some(limits) = self.limits {
+            runtime.set_limits(limits);
+        }
+        if self.stats {
+            runtime.enable_stats();
+        }
+        if self.coverage {
+            runtime.enable_coverage();
+        }
+        for (name, descriptor, method) in self.natives {
+            runtime.register_native(&name, &descriptor, method)?;
+        }
+        for hook in self.enter_hooks {
+            runtime.on_method_enter(hook);
+        }
+        for hook in self.exit_hooks {
+            runtime.on_method_exit(hook);
+        }
+        Ok(runtime)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jvm::{read_class_file, JVMParser};
+    use crate::options::LimitKind;
+    use crate::runtime::{RuntimeErrorKind, Value};
+    use crate::sink::SharedBuffer;
+    use std::env;
+    use std::path::Path;
+
+    fn factorial() -> Program {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/tests/Factorial.class");
+        Program::new(
+            &JVMParser::parse(&read_class_file(&path).unwrap()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn can_build_runtime() {
+        let stdout = SharedBuffer::new();
+        let mut runtime = RuntimeBuilder::new(factorial())
+            .stdout(Box::new(stdout.clone()))
+            .native(
+                "factorial",
+                "(I)I",
+                Box::new(|args| {
+                    let n = i32::try_from(args[0]).ok()?;
+                    Some(Value::from(n * 2))
+                }),
+            )
+            .stats(true)
+            .build()
+            .unwrap();
+        runtime.start().unwrap();
+        assert_eq!(stdout.to_string_lossy(), "24\n");
+        assert!(runtime.stats().is_some());
+
+        let mut runtime = RuntimeBuilder::new(factorial())
+            .stdout(Box::new(SharedBuffer::new()))
+            .limits(Limits {
+                instructions: Some(20),
+                ..Limits::default()
+            })
+            .build()
+            .unwrap();
+        let err = runtime.start().unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            RuntimeErrorKind::LimitExceeded {
+                kind: LimitKind::Instructions(20),
+                ..
+            }
+        ));
+        assert_eq!(runtime.executed(), 21);
+    }
+}
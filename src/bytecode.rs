@@ -1,5 +1,6 @@
 //! JVM bytecode definitions.
-use std::fmt;
+use alloc::string::ToString;
+use core::fmt;
 
 /// OPCodes supported by the JVM as documented in the spec document.
 /// ref: https://docs.oracle.com/javase/specs/jvms/se7/html/jvms-7.html
@@ -624,6 +625,158 @@ impl fmt::Display for OPCode {
     }
 }
 
+/// Encoding of an immediate operand that follows an opcode in the bytecode.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OperandKind {
+    /// Signed 1 byte immediate (`bipush`, `iinc` constant).
+    I8,
+    /// Unsigned 1 byte index (local variable, constant pool or array type).
+    U8,
+    /// Signed 2 byte immediate or branch offset.
+    I16,
+    /// Unsigned 2 byte constant pool index.
+    U16,
+    /// Signed 4 byte branch offset.
+    I32,
+}
+
+impl OperandKind {
+    /// Returns the encoded size of the operand in bytes.
+    pub const fn size(&self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 => 4,
+        }
+    }
+}
+
+impl OPCode {
+    /// Returns the byte value of the opcode, variants are declared in the
+    /// order of their encoding.
+    pub const fn byte(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the opcode with the given mnemonic e.g `iload_0`.
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+        (0..=OPCode::Breakpoint.byte())
+            .map(OPCode::from)
+            .find(|opcode| opcode.to_string() == mnemonic)
+    }
+
+    /// Returns the immediate operands encoded after the opcode, variable
+    /// length instructions (`tableswitch`, `lookupswitch` and `wide`) return
+    /// `None`.
+    pub const fn operands(self) -> Option<&'static [OperandKind]> {
+        use OperandKind::{I16, I32, I8, U16, U8};
+        match self {
+            Self::BiPush => Some(&[I8]),
+            Self::SiPush => Some(&[I16]),
+            Self::Ldc
+            | Self::ILoad
+            | Self::LLoad
+            | Self::FLoad
+            | Self::DLoad
+            | Self::ALoad
+            | Self::IStore
+            | Self::LStore
+            | Self::FStore
+            | Self::DStore
+            | Self::AStore
+            | Self::Ret
+            | Self::NewArray => Some(&[U8]),
+            Self::IInc => Some(&[U8, I8]),
+            Self::IfEq
+            | Self::IfNe
+            | Self::IfLt
+            | Self::IfGe
+            | Self::IfGt
+            | Self::IfLe
+            | Self::IfICmpEq
+            | Self::IfICmpNe
+            | Self::IfICmpLt
+            | Self::IfICmpGe
+            | Self::IfICmpGt
+            | Self::IfICmpLe
+            | Self::IfACmpEq
+            | Self::IfACmpNe
+            | Self::Goto
+            | Self::Jsr
+            | Self::IfNull
+            | Self::IfNonNull => Some(&[I16]),
+            Self::GotoW | Self::JsrW => Some(&[I32]),
+            Self::LdcW
+            | Self::Ldc2W
+            | Self::GetStatic
+            | Self::PutStatic
+            | Self::GetField
+            | Self::PutField
+            | Self::InvokeVirtual
+            | Self::InvokeSpecial
+            | Self::InvokeStatic
+            | Self::New
+            | Self::ANewArray
+            | Self::CheckCast
+            | Self::InstanceOf => Some(&[U16]),
+            Self::InvokeInterface | Self::InvokeDynamic => Some(&[U16, U8, U8]),
+            Self::MultiANewArray => Some(&[U16, U8]),
+            Self::TableSwitch | Self::LookupSwitch | Self::Wide => None,
+            _ => Some(&[]),
+        }
+    }
+
+    /// Returns true if the opcode calls a method.
+    pub const fn is_invoke(self) -> bool {
+        matches!(
+            self,
+            Self::InvokeVirtual
+                | Self::InvokeSpecial
+                | Self::InvokeStatic
+                | Self::InvokeInterface
+                | Self::InvokeDynamic
+        )
+    }
+
+    /// Returns true if the opcode is a branch taken depending on the values
+    /// it pops.
+    pub const fn is_conditional_branch(self) -> bool {
+        self.is_branch()
+            && !matches!(
+                self,
+                Self::Goto | Self::GotoW | Self::Jsr | Self::JsrW
+            )
+    }
+
+    /// Returns true if the opcode is a branch whose operand is a relative
+    /// offset.
+    pub const fn is_branch(self) -> bool {
+        matches!(
+            self,
+            Self::IfEq
+                | Self::IfNe
+                | Self::IfLt
+                | Self::IfGe
+                | Self::IfGt
+                | Self::IfLe
+                | Self::IfICmpEq
+                | Self::IfICmpNe
+                | Self::IfICmpLt
+                | Self::IfICmpGe
+                | Self::IfICmpGt
+                | Self::IfICmpLe
+                | Self::IfACmpEq
+                | Self::IfACmpNe
+                | Self::Goto
+                | Self::Jsr
+                | Self::IfNull
+                | Self::IfNonNull
+                | Self::GotoW
+                | Self::JsrW
+        )
+    }
+}
+
 // Since bytecode is initially loaded as `Vec<u8>` we need a way to convert it
 // to `OPCode` enum, this might be done better with a macro but copy paste and
 // move on for now.
@@ -837,3 +990,22 @@ impl From<u8> for OPCode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opcode_bytes_round_trip() {
+        for byte in 0..=OPCode::Breakpoint.byte() {
+            let opcode = OPCode::from(byte);
+            assert_eq!(opcode.byte(), byte);
+            assert_eq!(
+                OPCode::from_mnemonic(&opcode.to_string()),
+                Some(opcode)
+            );
+        }
+        assert_eq!(OPCode::from(203), OPCode::Unspecified);
+        assert_eq!(OPCode::from_mnemonic("unspecified"), None);
+    }
+}
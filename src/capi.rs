@@ -0,0 +1,342 @@
+//! C interface for embedding the VM in hosts written in other languages.
+//!
+//! Build the shared library with
+//! `cargo rustc --release --lib --features capi --crate-type cdylib`, the
+//! matching declarations are in `include/coldbrew.h`.
+//!
+//! Every function returns a `ColdbrewStatus`, on failure a message
+//! describing the error is kept for the calling thread and can be read with
+//! `coldbrew_last_error`. Panics of the runtime are caught and reported as
+//! runtime errors instead of unwinding into the host.
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use crate::error::Error;
+use crate::jvm::JVMParser;
+use crate::program::Program;
+use crate::runtime::{Runtime, Value};
+
+/// Outcome of a call through the C interface.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColdbrewStatus {
+    Ok = 0,
+    /// A pointer was null or a string wasn't valid UTF-8.
+    InvalidArgument = 1,
+    /// The class file couldn't be parsed.
+    Parse = 2,
+    /// The method couldn't be resolved or the arguments don't match it.
+    Link = 3,
+    /// The program failed or panicked while running.
+    Runtime = 4,
+    /// An auxiliary file couldn't be read or written.
+    Io = 5,
+    /// A trace couldn't be compiled.
+    Jit = 6,
+}
+
+impl From<&Error> for ColdbrewStatus {
+    fn from(err: &Error) -> Self {
+        match err {
+            Error::Io(_) => Self::Io,
+            Error::Parse(_) => Self::Parse,
+            Error::Link(_) => Self::Link,
+            Error::Runtime { .. } => Self::Runtime,
+            Error::Jit(_) => Self::Jit,
+        }
+    }
+}
+
+/// Type tag of a `ColdbrewValue`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColdbrewType {
+    /// No value, returned by `void` methods.
+    Void = 0,
+    Int = 1,
+    Long = 2,
+    Float = 3,
+    Double = 4,
+    /// Handle of an object, 0 is `null`.
+    Reference = 5,
+}
+
+/// Payload of a `ColdbrewValue`, the field to read is given by its type.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union ColdbrewPayload {
+    pub int: i32,
+    pub long: i64,
+    pub float: f32,
+    pub double: f64,
+    pub reference: u32,
+}
+
+/// A JVM value passed to or returned from a method.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ColdbrewValue {
+    pub kind: ColdbrewType,
+    pub payload: ColdbrewPayload,
+}
+
+impl fmt::Debug for ColdbrewValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.value())
+    }
+}
+
+impl From<Option<Value>> for ColdbrewValue {
+    fn from(value: Option<Value>) -> Self {
+        let (kind, payload) = match value {
+            None => (ColdbrewType::Void, ColdbrewPayload { long: 0 }),
+            Some(Value::Int(int)) => {
+                (ColdbrewType::Int, ColdbrewPayload { int })
+            }
+            Some(Value::Long(long)) => {
+                (ColdbrewType::Long, ColdbrewPayload { long })
+            }
+            Some(Value::Float(float)) => {
+                (ColdbrewType::Float, ColdbrewPayload { float })
+            }
+            Some(Value::Double(double)) => {
+                (ColdbrewType::Double, ColdbrewPayload { double })
+            }
+            Some(Value::Reference(reference)) => {
+                (ColdbrewType::Reference, ColdbrewPayload { reference })
+            }
+        };
+        Self { kind, payload }
+    }
+}
+
+impl ColdbrewValue {
+    /// Returns the value held, `None` for `void`.
+    pub fn value(&self) -> Option<Value> {
+        // SAFETY: the payload field read is the one selected by `kind`.
+        unsafe {
+            match self.kind {
+                ColdbrewType::Void => None,
+                ColdbrewType::Int => Some(Value::Int(self.payload.int)),
+                ColdbrewType::Long => Some(Value::Long(self.payload.long)),
+                ColdbrewType::Float => Some(Value::Float(self.payload.float)),
+                ColdbrewType::Double => {
+                    Some(Value::Double(self.payload.double))
+                }
+                ColdbrewType::Reference => {
+                    Some(Value::Reference(self.payload.reference))
+                }
+            }
+        }
+    }
+}
+
+/// A parsed class file, opaque to the host.
+#[derive(Debug)]
+pub struct ColdbrewProgram {
+    program: Program,
+}
+
+thread_local! {
+    // Message of the last failure on this thread.
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Record `message` as the last error and return `status`.
+fn fail(status: ColdbrewStatus, message: impl fmt::Display) -> ColdbrewStatus {
+    // Interior NUL bytes would truncate the message, drop them.
+    let message = message.to_string().replace('\0', "");
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).ok());
+    status
+}
+
+/// Run `f`, reporting its errors and panics through the last error.
+fn guard(f: impl FnOnce() -> Result<(), Error>) -> ColdbrewStatus {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => ColdbrewStatus::Ok,
+        Ok(Err(err)) => fail(ColdbrewStatus::from(&err), err),
+        Err(_) => fail(ColdbrewStatus::Runtime, "the runtime panicked"),
+    }
+}
+
+/// Returns the string at `ptr` or `None` if it's null or not UTF-8.
+unsafe fn str_arg<'a>(ptr: *const c_char) -> Option<&'a str> {
+    if ptr.is_null() {
+        return None;
+    }
+    CStr::from_ptr(ptr).to_str().ok()
+}
+
+/// Parse the class file held in `bytes` and store the program in `program`,
+/// free it with `coldbrew_program_free`.
+/// # Safety
+/// `bytes` must point to `len` readable bytes and `program` to writable
+/// memory.
+#[no_mangle]
+pub unsafe extern "C" fn coldbrew_parse(
+    bytes: *const u8,
+    len: usize,
+    program: *mut *mut ColdbrewProgram,
+) -> ColdbrewStatus {
+    if bytes.is_null() || program.is_null() {
+        return fail(ColdbrewStatus::InvalidArgument, "null pointer");
+    }
+    let bytes = slice::from_raw_parts(bytes, len);
+    match JVMParser::parse(bytes) {
+        Ok(class_file) => {
+            let parsed = ColdbrewProgram {
+                program: Program::new(&class_file),
+            };
+            *program = Box::into_raw(Box::new(parsed));
+            ColdbrewStatus::Ok
+        }
+        Err(err) => {
+            *program = ptr::null_mut();
+            fail(ColdbrewStatus::Parse, err)
+        }
+    }
+}
+
+/// Free a program returned by `coldbrew_parse`, null is ignored.
+/// # Safety
+/// `program` must come from `coldbrew_parse` and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn coldbrew_program_free(program: *mut ColdbrewProgram) {
+    if !program.is_null() {
+        drop(Box::from_raw(program));
+    }
+}
+
+/// Run the program's `main` method, its output goes to the process stdout.
+/// # Safety
+/// `program` must come from `coldbrew_parse`.
+#[no_mangle]
+pub unsafe extern "C" fn coldbrew_run(
+    program: *const ColdbrewProgram,
+    jit: bool,
+) -> ColdbrewStatus {
+    let Some(program) = program.as_ref() else {
+        return fail(ColdbrewStatus::InvalidArgument, "null program");
+    };
+    guard(|| {
+        let mut runtime = Runtime::new(program.program.clone());
+        runtime.run(jit).map_err(|err| runtime.runtime_error(err))
+    })
+}
+
+/// Call the static method `method` with descriptor `descriptor`, such as
+/// `(I)I`, passing the `nargs` values of `args` and store what it returns
+/// in `result`, methods returning `void` store a `Void` value.
+/// # Safety
+/// `program` must come from `coldbrew_parse`, `method` and `descriptor`
+/// must be NUL terminated strings, `args` must point to `nargs` values
+/// (it may be null when `nargs` is 0) and `result` to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn coldbrew_call_static(
+    program: *const ColdbrewProgram,
+    method: *const c_char,
+    descriptor: *const c_char,
+    args: *const ColdbrewValue,
+    nargs: usize,
+    result: *mut ColdbrewValue,
+) -> ColdbrewStatus {
+    let (Some(program), Some(method), Some(descriptor)) =
+        (program.as_ref(), str_arg(method), str_arg(descriptor))
+    else {
+        return fail(ColdbrewStatus::InvalidArgument, "invalid argument");
+    };
+    if result.is_null() || (args.is_null() && nargs > 0) {
+        return fail(ColdbrewStatus::InvalidArgument, "null pointer");
+    }
+    let args = if nargs == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(args, nargs)
+    };
+    let Some(args) = args
+        .iter()
+        .map(ColdbrewValue::value)
+        .collect::<Option<Vec<_>>>()
+    else {
+        return fail(ColdbrewStatus::InvalidArgument, "void argument");
+    };
+    guard(|| {
+        let mut runtime = Runtime::new(program.program.clone());
+        let class = program.program.class_name().to_string();
+        let value = runtime.call_static(&class, method, descriptor, &args)?;
+        *result = ColdbrewValue::from(value);
+        Ok(())
+    })
+}
+
+/// Returns the message of the last failure on the calling thread, or null
+/// if nothing failed yet. The string is valid until the next failing call
+/// on the same thread.
+#[no_mangle]
+pub extern "C" fn coldbrew_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jvm::read_class_file;
+    use std::env;
+    use std::path::Path;
+
+    #[test]
+    fn can_embed_through_c_interface() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/tests/Factorial.class");
+        let bytes = read_class_file(&path).unwrap();
+        let mut program = ptr::null_mut();
+        unsafe {
+            let status =
+                coldbrew_parse(bytes.as_ptr(), bytes.len(), &mut program);
+            assert_eq!(status, ColdbrewStatus::Ok);
+
+            let args = [ColdbrewValue::from(Some(Value::Int(10)))];
+            let mut result = ColdbrewValue::from(None);
+            let status = coldbrew_call_static(
+                program,
+                c"factorial".as_ptr(),
+                c"(I)I".as_ptr(),
+                args.as_ptr(),
+                args.len(),
+                &mut result,
+            );
+            assert_eq!(status, ColdbrewStatus::Ok);
+            assert_eq!(result.value(), Some(Value::Int(3_628_800)));
+
+            let status = coldbrew_call_static(
+                program,
+                c"factorial".as_ptr(),
+                c"(J)I".as_ptr(),
+                ptr::null(),
+                0,
+                &mut result,
+            );
+            assert_eq!(status, ColdbrewStatus::Link);
+            let message = CStr::from_ptr(coldbrew_last_error());
+            assert!(message.to_str().unwrap().contains("declared as (I)I"));
+
+            let mut truncated = ptr::null_mut();
+            let status = coldbrew_parse(bytes.as_ptr(), 10, &mut truncated);
+            assert_eq!(status, ColdbrewStatus::Parse);
+            assert!(truncated.is_null());
+            assert_eq!(
+                coldbrew_run(ptr::null(), false),
+                ColdbrewStatus::InvalidArgument
+            );
+            coldbrew_program_free(program);
+        }
+    }
+}
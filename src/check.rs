@@ -0,0 +1,176 @@
+//! Capability reports.
+//!
+//! `check` scans the code of every method of a program for what the runtime
+//! doesn't implement yet, instructions the interpreter fails on and `new` on
+//! classes that are neither part of the program nor implemented natively.
+//! Programs can be vetted this way before they run instead of failing on
+//! the first unsupported instruction they reach.
+use std::fmt;
+
+use crate::bytecode::OPCode;
+use crate::collections;
+use crate::disasm::{disassemble, DecodedInstruction};
+use crate::program::{Program, RuntimeConstant};
+use crate::runtime;
+
+/// A feature a program needs that the runtime doesn't implement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Feature {
+    /// An instruction the interpreter doesn't implement.
+    Opcode(OPCode),
+    /// A class whose instances can't be created.
+    Class(String),
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Opcode(opcode) => write!(f, "{opcode}"),
+            Self::Class(class) => write!(f, "new {class}"),
+        }
+    }
+}
+
+/// A use of an unsupported feature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// Method using the feature, e.g `Foo.main([Ljava/lang/String;)V`.
+    pub method: String,
+    /// Offset of the instruction in the method's code.
+    pub offset: usize,
+    pub feature: Feature,
+}
+
+/// `Report` lists every unsupported feature a program uses in method and
+/// offset order.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub findings: Vec<Finding>,
+}
+
+impl Report {
+    /// Returns true if the runtime implements everything the program uses.
+    pub fn is_supported(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    /// Returns the unsupported features with the number of times they are
+    /// used, in order of first use.
+    pub fn features(&self) -> Vec<(&Feature, usize)> {
+        let mut features: Vec<(&Feature, usize)> = vec![];
+        for finding in &self.findings {
+            match features.iter_mut().find(|(f, _)| *f == &finding.feature) {
+                Some((_, uses)) => *uses += 1,
+                None => features.push((&finding.feature, 1)),
+            }
+        }
+        features
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_supported() {
+            return writeln!(f, "No unsupported instructions or classes");
+        }
+        for finding in &self.findings {
+            writeln!(
+                f,
+                "{} @ {} : {}",
+                finding.method, finding.offset, finding.feature
+            )?;
+        }
+        writeln!(f, "\nUnsupported features :")?;
+        for (feature, uses) in self.features() {
+            writeln!(f, "{uses:>6} {feature}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Scan the code of every method of `program` for unsupported features.
+/// Code that can't be disassembled is skipped, `Program::validate` reports
+/// it.
+pub fn check(program: &Program) -> Report {
+    let mut findings = vec![];
+    for (method_index, method) in program.methods.iter().enumerate() {
+        let Some(name) = program.declared_method(method_index) else {
+            continue;
+        };
+        let name = name.to_string();
+        for inst in disassemble(&method.code).unwrap_or_default() {
+            // `wide` is supported along with the instruction it modifies.
+            let opcode = match inst.opcode {
+                OPCode::Wide => OPCode::from(inst.operands[0] as u8),
+                opcode => opcode,
+            };
+            let feature = match opcode {
+                opcode if !runtime::supports(opcode) => Feature::Opcode(opcode),
+                OPCode::New => match new_class(program, method_index, &inst) {
+                    Some(class) => Feature::Class(class.to_string()),
+                    None => continue,
+                },
+                _ => continue,
+            };
+            findings.push(Finding {
+                method: name.clone(),
+                offset: inst.offset,
+                feature,
+            });
+        }
+    }
+    Report { findings }
+}
+
+/// Returns the class `new` allocates if its instances can't be created.
+fn new_class<'a>(
+    program: &'a Program,
+    method_index: usize,
+    inst: &DecodedInstruction,
+) -> Option<&'a str> {
+    let index = usize::try_from(*inst.operands.first()?).ok()?;
+    let Some(RuntimeConstant::Class { name, .. }) =
+        program.constant(method_index, index)
+    else {
+        return None;
+    };
+    let class = program.string(name)?;
+    (program.class_index(class).is_none()
+        && collections::new_object(class).is_none())
+    .then_some(class)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_unsupported_instructions() {
+        // iconst_1, iconst_2, monitorenter, swap, monitorexit, ireturn.
+        let program = Program::from_code(vec![4, 5, 194, 95, 195, 172], 0);
+        let report = check(&program);
+        let found: Vec<_> = report
+            .findings
+            .iter()
+            .map(|finding| (finding.offset, finding.feature.clone()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (2, Feature::Opcode(OPCode::MonitorEnter)),
+                (3, Feature::Opcode(OPCode::Swap)),
+                (4, Feature::Opcode(OPCode::MonitorExit)),
+            ]
+        );
+        assert_eq!(report.features().len(), 3);
+        assert!(!report.is_supported());
+
+        // iconst_1, ireturn.
+        let report = check(&Program::from_code(vec![4, 172], 0));
+        assert!(report.is_supported());
+        assert_eq!(
+            report.to_string(),
+            "No unsupported instructions or classes\n"
+        );
+    }
+}
@@ -0,0 +1,503 @@
+//! Native implementations of the library classes programs use the most.
+//!
+//! Boxed primitives, `java.lang.StringBuilder`, `java.util.ArrayList` and
+//! `java.util.HashMap` are implemented on top of the heap instead of
+//! running the library's bytecode. Only the common methods are supported:
+//! `append` and `length` on builders, `add`, `get`, `set`, `remove`, `size`
+//! and iteration on lists, `put`, `get`, `containsKey`, `remove`, `size`
+//! and the key and value views on maps. Views are copies
+//! taken when they're asked for, and interface calls are dispatched on the
+//! receiver so `List.add` and `ArrayList.add` land on the same code.
+use crate::heap::{self, Heap, Object};
+use crate::runtime::{RuntimeErrorKind, Value};
+
+/// Capacity a `HashMap` starts with.
+const DEFAULT_CAPACITY: usize = 16;
+
+/// Primitive wrappers and the descriptor of the primitive they box.
+const WRAPPERS: [(&str, char); 8] = [
+    ("java/lang/Boolean", 'Z'),
+    ("java/lang/Byte", 'B'),
+    ("java/lang/Character", 'C'),
+    ("java/lang/Short", 'S'),
+    ("java/lang/Integer", 'I'),
+    ("java/lang/Long", 'J'),
+    ("java/lang/Float", 'F'),
+    ("java/lang/Double", 'D'),
+];
+
+/// Returns true if calls to methods of `class` are implemented here.
+pub fn supports(class: &str) -> bool {
+    class.starts_with("java/util/")
+        || class == "java/lang/StringBuilder"
+        || wrapped(class).is_some()
+}
+
+/// Returns true if `class.name` is a static method, the arguments of
+/// instance methods are preceded by their receiver.
+pub fn is_static(class: &str, name: &str) -> bool {
+    name == "valueOf" && wrapped(class).is_some()
+}
+
+/// Returns the object `new class` allocates, `None` if `class` isn't
+/// implemented here. Wrappers are only created through `valueOf`.
+pub fn new_object(class: &str) -> Option<Object> {
+    match class {
+        "java/lang/StringBuilder" => Some(Object::StringBuilder(String::new())),
+        "java/util/ArrayList" => Some(Object::ArrayList(vec![])),
+        "java/util/HashMap" => Some(Object::HashMap {
+            entries: vec![],
+            capacity: DEFAULT_CAPACITY,
+        }),
+        _ => None,
+    }
+}
+
+/// Call the method `class.name:descriptor` with `args`, receiver first for
+/// instance methods. Returns the result, `None` for `void` methods.
+pub fn call(
+    heap: &mut Heap,
+    class: &str,
+    name: &str,
+    descriptor: &str,
+    args: &[Value],
+) -> Result<Option<Value>, RuntimeErrorKind> {
+    let unknown = || RuntimeErrorKind::UnknownMethod {
+        method: format!("{class}.{name}:{descriptor}"),
+    };
+    if is_static(class, name) {
+        let kind = wrapped(class).ok_or_else(unknown)?;
+        let value = args
+            .first()
+            .and_then(|value| value.convert(kind))
+            .ok_or_else(unknown)?;
+        let boxed = heap.alloc(Object::Boxed {
+            class: class.to_string(),
+            value,
+        });
+        return Ok(Some(boxed));
+    }
+    let (&receiver, args) = args.split_first().ok_or_else(unknown)?;
+    let value = match (name, descriptor) {
+        ("hashCode", "()I") => Some(Value::Int(heap.hash_code(receiver))),
+        ("equals", "(Ljava/lang/Object;)Z") => {
+            Some(Value::Int(i32::from(heap.equals(receiver, args[0]))))
+        }
+        ("toString", "()Ljava/lang/String;") => {
+            let string = heap.java_string(receiver);
+            Some(heap.intern(&string))
+        }
+        _ => match heap.get(receiver) {
+            Some(Object::Boxed { value, .. }) => {
+                let kind = heap::parameter_types(descriptor)
+                    .and_then(|(_, ret)| ret.chars().next())
+                    .ok_or_else(unknown)?;
+                Some(value.convert(kind).ok_or_else(unknown)?)
+            }
+            Some(Object::StringBuilder(_)) => {
+                builder(heap, receiver, name, descriptor, args)
+                    .ok_or_else(unknown)??
+            }
+            Some(Object::ArrayList(_)) => {
+                list(heap, receiver, name, descriptor, args)
+                    .ok_or_else(unknown)??
+            }
+            Some(Object::HashMap { .. }) => {
+                map(heap, receiver, name, descriptor, args)
+                    .ok_or_else(unknown)??
+            }
+            Some(Object::Iterator { .. }) => {
+                iterator(heap, receiver, name).ok_or_else(unknown)??
+            }
+            Some(_) => return Err(unknown()),
+            None => {
+                return Err(RuntimeErrorKind::NullPointer(
+                    crate::bytecode::OPCode::InvokeVirtual,
+                ))
+            }
+        },
+    };
+    Ok(value)
+}
+
+/// Result of a collection method, `None` when the method isn't supported.
+type MethodResult = Option<Result<Option<Value>, RuntimeErrorKind>>;
+
+/// Call the `StringBuilder` method `name` on `receiver`, arguments are
+/// appended the way `String.valueOf` formats them.
+fn builder(
+    heap: &mut Heap,
+    receiver: Value,
+    name: &str,
+    descriptor: &str,
+    args: &[Value],
+) -> MethodResult {
+    let Some(Object::StringBuilder(contents)) = heap.get(receiver) else {
+        return None;
+    };
+    let value = match (name, descriptor, args) {
+        ("<init>", "()V" | "(I)V", _) => None,
+        ("length", "()I", _) => {
+            Some(Value::Int(contents.encode_utf16().count() as i32))
+        }
+        ("<init>", "(Ljava/lang/String;)V", [value])
+        | ("append", _, [value]) => {
+            let kind = heap::parameter_types(descriptor)
+                .and_then(|(params, _)| params.first()?.chars().next())?;
+            let text = heap.value_string(*value, kind);
+            if let Some(Object::StringBuilder(contents)) =
+                heap.get_mut(receiver)
+            {
+                contents.push_str(&text);
+            }
+            (name == "append").then_some(receiver)
+        }
+        _ => return None,
+    };
+    Some(Ok(value))
+}
+
+/// Call the `ArrayList` method `name` on `receiver`.
+fn list(
+    heap: &mut Heap,
+    receiver: Value,
+    name: &str,
+    descriptor: &str,
+    args: &[Value],
+) -> MethodResult {
+    let Some(Object::ArrayList(elements)) = heap.get(receiver) else {
+        return None;
+    };
+    let length = elements.len();
+    let index = |value: &Value, length: usize| match *value {
+        Value::Int(index) if (0..length as i32).contains(&index) => {
+            Ok(index as usize)
+        }
+        Value::Int(index) => {
+            Err(RuntimeErrorKind::IndexOutOfBounds { index, length })
+        }
+        _ => Err(RuntimeErrorKind::InvalidValue),
+    };
+    let position = |value: Value| {
+        elements
+            .iter()
+            .position(|element| heap.equals(value, *element))
+    };
+    let value = match (name, descriptor, args) {
+        ("<init>", "()V" | "(I)V", _) => None,
+        ("size", "()I", _) => Some(Value::Int(length as i32)),
+        ("isEmpty", "()Z", _) => Some(Value::Int(i32::from(length == 0))),
+        ("contains", "(Ljava/lang/Object;)Z", [value]) => {
+            Some(Value::Int(i32::from(position(*value).is_some())))
+        }
+        ("indexOf", "(Ljava/lang/Object;)I", [value]) => {
+            Some(Value::Int(position(*value).map_or(-1, |at| at as i32)))
+        }
+        ("get", "(I)Ljava/lang/Object;", [at]) => match index(at, length) {
+            Ok(at) => Some(elements[at]),
+            Err(err) => return Some(Err(err)),
+        },
+        ("iterator", "()Ljava/util/Iterator;", _) => {
+            Some(heap.alloc(Object::Iterator {
+                list: receiver,
+                next: 0,
+            }))
+        }
+        ("remove", "(Ljava/lang/Object;)Z", [value]) => {
+            let removed = position(*value);
+            let elements = list_mut(heap, receiver);
+            if let Some(at) = removed {
+                elements.remove(at);
+            }
+            Some(Value::Int(i32::from(removed.is_some())))
+        }
+        ("add", "(Ljava/lang/Object;)Z", [value]) => {
+            list_mut(heap, receiver).push(*value);
+            Some(Value::Int(1))
+        }
+        ("add", "(ILjava/lang/Object;)V", [at, value]) => {
+            // Inserting at the end is allowed.
+            let at = match index(at, length + 1) {
+                Ok(at) => at,
+                Err(RuntimeErrorKind::IndexOutOfBounds { index, .. }) => {
+                    let kind =
+                        RuntimeErrorKind::IndexOutOfBounds { index, length };
+                    return Some(Err(kind));
+                }
+                Err(err) => return Some(Err(err)),
+            };
+            list_mut(heap, receiver).insert(at, *value);
+            None
+        }
+        ("set", "(ILjava/lang/Object;)Ljava/lang/Object;", [at, value]) => {
+            match index(at, length) {
+                Ok(at) => Some(std::mem::replace(
+                    &mut list_mut(heap, receiver)[at],
+                    *value,
+                )),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        ("remove", "(I)Ljava/lang/Object;", [at]) => match index(at, length) {
+            Ok(at) => Some(list_mut(heap, receiver).remove(at)),
+            Err(err) => return Some(Err(err)),
+        },
+        ("clear", "()V", _) => {
+            list_mut(heap, receiver).clear();
+            None
+        }
+        _ => return None,
+    };
+    Some(Ok(value))
+}
+
+/// Returns the elements of the list `receiver` for mutation.
+fn list_mut(heap: &mut Heap, receiver: Value) -> &mut Vec<Value> {
+    match heap.get_mut(receiver) {
+        Some(Object::ArrayList(elements)) => elements,
+        _ => unreachable!("receiver is a list"),
+    }
+}
+
+/// Call the `HashMap` method `name` on `receiver`.
+fn map(
+    heap: &mut Heap,
+    receiver: Value,
+    name: &str,
+    descriptor: &str,
+    args: &[Value],
+) -> MethodResult {
+    let Some(Object::HashMap { entries, .. }) = heap.get(receiver) else {
+        return None;
+    };
+    let length = entries.len();
+    let position = |key: Value| {
+        entries
+            .iter()
+            .position(|(other, _)| heap.equals(key, *other))
+    };
+    let value = match (name, descriptor, args) {
+        ("<init>", "()V", _) => None,
+        ("<init>", "(I)V", [Value::Int(capacity)]) => {
+            // Tables are sized to the next power of two like the library's.
+            let capacity = (*capacity).max(1) as usize;
+            let Some(Object::HashMap {
+                capacity: table, ..
+            }) = heap.get_mut(receiver)
+            else {
+                return None;
+            };
+            *table = capacity.next_power_of_two();
+            None
+        }
+        ("size", "()I", _) => Some(Value::Int(length as i32)),
+        ("isEmpty", "()Z", _) => Some(Value::Int(i32::from(length == 0))),
+        ("containsKey", "(Ljava/lang/Object;)Z", [key]) => {
+            Some(Value::Int(i32::from(position(*key).is_some())))
+        }
+        ("get", "(Ljava/lang/Object;)Ljava/lang/Object;", [key]) => {
+            Some(position(*key).map_or(Value::NULL, |at| entries[at].1))
+        }
+        (
+            "getOrDefault",
+            "(Ljava/lang/Object;Ljava/lang/Object;)Ljava/lang/Object;",
+            [key, default],
+        ) => Some(position(*key).map_or(*default, |at| entries[at].1)),
+        ("keySet", "()Ljava/util/Set;", _)
+        | ("values", "()Ljava/util/Collection;", _) => {
+            let keys = name == "keySet";
+            let view = heap
+                .map_entries(receiver)
+                .into_iter()
+                .map(|(key, value)| if keys { key } else { value })
+                .collect();
+            Some(heap.alloc(Object::ArrayList(view)))
+        }
+        (
+            "put",
+            "(Ljava/lang/Object;Ljava/lang/Object;)Ljava/lang/Object;",
+            [key, value],
+        ) => {
+            let at = position(*key);
+            let Some(Object::HashMap { entries, capacity }) =
+                heap.get_mut(receiver)
+            else {
+                return None;
+            };
+            match at {
+                Some(at) => Some(std::mem::replace(&mut entries[at].1, *value)),
+                None => {
+                    entries.push((*key, *value));
+                    // The table doubles when it gets three quarters full.
+                    if entries.len() > *capacity / 4 * 3 {
+                        *capacity *= 2;
+                    }
+                    Some(Value::NULL)
+                }
+            }
+        }
+        ("remove", "(Ljava/lang/Object;)Ljava/lang/Object;", [key]) => {
+            let at = position(*key);
+            let Some(Object::HashMap { entries, .. }) = heap.get_mut(receiver)
+            else {
+                return None;
+            };
+            Some(at.map_or(Value::NULL, |at| entries.remove(at).1))
+        }
+        ("clear", "()V", _) => {
+            if let Some(Object::HashMap { entries, .. }) =
+                heap.get_mut(receiver)
+            {
+                entries.clear();
+            }
+            None
+        }
+        _ => return None,
+    };
+    Some(Ok(value))
+}
+
+/// Call the `Iterator` method `name` on `receiver`.
+fn iterator(heap: &mut Heap, receiver: Value, name: &str) -> MethodResult {
+    let Some(&Object::Iterator { list, next }) = heap.get(receiver) else {
+        return None;
+    };
+    let elements = heap.get(list).and_then(|object| match object {
+        Object::ArrayList(elements) => Some(elements),
+        _ => None,
+    })?;
+    let value = match name {
+        "hasNext" => Value::Int(i32::from(next < elements.len())),
+        "next" => {
+            let Some(&element) = elements.get(next) else {
+                return Some(Err(RuntimeErrorKind::NoSuchElement));
+            };
+            if let Some(Object::Iterator { next, .. }) = heap.get_mut(receiver)
+            {
+                *next += 1;
+            }
+            element
+        }
+        _ => return None,
+    };
+    Some(Ok(Some(value)))
+}
+
+/// Returns the descriptor of the primitive the wrapper `class` boxes.
+fn wrapped(class: &str) -> Option<char> {
+    WRAPPERS
+        .iter()
+        .find(|(wrapper, _)| *wrapper == class)
+        .map(|(_, kind)| *kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call_ok(
+        heap: &mut Heap,
+        name: &str,
+        desc: &str,
+        args: &[Value],
+    ) -> Value {
+        call(heap, "java/util/Map", name, desc, args)
+            .unwrap()
+            .unwrap_or(Value::NULL)
+    }
+
+    #[test]
+    fn can_use_lists() {
+        let mut heap = Heap::new();
+        let list = heap.alloc(new_object("java/util/ArrayList").unwrap());
+        for value in [3, 1, 2] {
+            let boxed = call(
+                &mut heap,
+                "java/lang/Integer",
+                "valueOf",
+                "(I)Ljava/lang/Integer;",
+                &[Value::Int(value)],
+            )
+            .unwrap()
+            .unwrap();
+            call(
+                &mut heap,
+                "java/util/List",
+                "add",
+                "(Ljava/lang/Object;)Z",
+                &[list, boxed],
+            )
+            .unwrap();
+        }
+        assert_eq!(heap.java_string(list), "[3, 1, 2]");
+        let removed = call(
+            &mut heap,
+            "java/util/List",
+            "remove",
+            "(I)Ljava/lang/Object;",
+            &[list, Value::Int(0)],
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(heap.java_string(removed), "3");
+        assert_eq!(
+            call(
+                &mut heap,
+                "java/util/List",
+                "get",
+                "(I)Ljava/lang/Object;",
+                &[list, Value::Int(2)]
+            ),
+            Err(RuntimeErrorKind::IndexOutOfBounds {
+                index: 2,
+                length: 2
+            })
+        );
+    }
+
+    #[test]
+    fn iterates_maps_like_the_library() {
+        let mut heap = Heap::new();
+        let map = heap.alloc(new_object("java/util/HashMap").unwrap());
+        // "banana" and "p" share the first bucket of a 16 entry table and
+        // "a" lands in the second one.
+        for (key, value) in [("a", 2), ("banana", 1), ("p", 3), ("a", 4)] {
+            let key = heap.intern(key);
+            let value = Value::Int(value);
+            call_ok(
+                &mut heap,
+                "put",
+                "(Ljava/lang/Object;Ljava/lang/Object;)Ljava/lang/Object;",
+                &[map, key, value],
+            );
+        }
+        assert_eq!(heap.java_string(map), "{banana=1, p=3, a=4}");
+        let key = heap.intern("a");
+        let value = call_ok(
+            &mut heap,
+            "get",
+            "(Ljava/lang/Object;)Ljava/lang/Object;",
+            &[map, key],
+        );
+        assert_eq!(value, Value::Int(4));
+        assert_eq!(call_ok(&mut heap, "size", "()I", &[map]), Value::Int(3));
+
+        // Growing the table past 12 entries reorders them.
+        for key in 13..=40 {
+            let key = heap.alloc(Object::Boxed {
+                class: "java/lang/Integer".to_string(),
+                value: Value::Int(key * 3),
+            });
+            call_ok(
+                &mut heap,
+                "put",
+                "(Ljava/lang/Object;Ljava/lang/Object;)Ljava/lang/Object;",
+                &[map, key, Value::NULL],
+            );
+        }
+        let Some(Object::HashMap { capacity, .. }) = heap.get(map) else {
+            panic!("expected a map");
+        };
+        assert_eq!(*capacity, 64);
+    }
+}
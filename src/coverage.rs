@@ -0,0 +1,217 @@
+//! Bytecode coverage collected by the runtime.
+//!
+//! Every instruction dispatched by the interpreter is counted by method and
+//! bytecode offset. Loops compiled by the JIT were interpreted while their
+//! trace was recorded so their instructions are covered as well, only the
+//! hit counts stop growing once the native trace takes over.
+//!
+//! Reports come in two flavours, an annotated disassembly for humans and
+//! an lcov tracefile where every method is a record and bytecode offsets
+//! stand in for line numbers (offset `n` is line `n + 1`).
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::disasm::disassemble;
+use crate::instrument::Instrument;
+use crate::program::Program;
+use crate::runtime::{Frame, Instruction, ProgramCounter};
+
+/// `Coverage` counts how many times each instruction was executed.
+#[derive(Debug, Default, Clone)]
+pub struct Coverage {
+    // Hit counts keyed by method index then bytecode offset.
+    hits: BTreeMap<usize, BTreeMap<usize, usize>>,
+}
+
+/// Coverage of a single method.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodCoverage {
+    pub name: String,
+    /// Hit count of every instruction keyed by its offset, zero for the
+    /// instructions that were never executed.
+    pub instructions: BTreeMap<usize, usize>,
+}
+
+impl MethodCoverage {
+    /// Returns the number of instructions executed at least once.
+    pub fn covered(&self) -> usize {
+        self.instructions.values().filter(|hits| **hits > 0).count()
+    }
+
+    /// Returns the ratio of executed instructions in percent.
+    pub fn percent(&self) -> f64 {
+        if self.instructions.is_empty() {
+            return 100.0;
+        }
+        self.covered() as f64 * 100.0 / self.instructions.len() as f64
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count an execution of the instruction at `pc`.
+    pub fn record(&mut self, pc: ProgramCounter) {
+        *self
+            .hits
+            .entry(pc.get_method_index())
+            .or_default()
+            .entry(pc.get_instruction_index())
+            .or_default() += 1;
+    }
+
+    /// Returns how many times the instruction at `pc` was executed.
+    pub fn hits(&self, pc: &ProgramCounter) -> usize {
+        self.hits
+            .get(&pc.get_method_index())
+            .and_then(|offsets| offsets.get(&pc.get_instruction_index()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns the coverage of every method of `program` that has code,
+    /// ordered by method index.
+    pub fn methods(&self, program: &Program) -> Vec<MethodCoverage> {
+        program
+            .methods
+            .iter()
+            .enumerate()
+            .filter(|(_, method)| !method.code.is_empty())
+            .map(|(index, method)| {
+                let hits = self.hits.get(&index);
+                let instructions = disassemble(&method.code)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|inst| {
+                        let count = hits
+                            .and_then(|hits| hits.get(&inst.offset))
+                            .copied()
+                            .unwrap_or_default();
+                        (inst.offset, count)
+                    })
+                    .collect();
+                MethodCoverage {
+                    name: method_name(program, index),
+                    instructions,
+                }
+            })
+            .collect()
+    }
+
+    /// Render the disassembly of every method annotated with hit counts,
+    /// instructions that never ran are marked with `#####`.
+    pub fn annotate(&self, program: &Program) -> String {
+        let mut report = String::new();
+        for (method, coverage) in program
+            .methods
+            .iter()
+            .filter(|method| !method.code.is_empty())
+            .zip(self.methods(program))
+        {
+            let _ = writeln!(
+                report,
+                "{} : {}/{} instructions ({:.1}%)",
+                coverage.name,
+                coverage.covered(),
+                coverage.instructions.len(),
+                coverage.percent()
+            );
+            for inst in disassemble(&method.code).unwrap_or_default() {
+                let hits = coverage.instructions[&inst.offset];
+                let count = if hits == 0 {
+                    "#####".to_string()
+                } else {
+                    hits.to_string()
+                };
+                let _ = writeln!(report, "{count:>9} | {inst}");
+            }
+            report.push('\n');
+        }
+        report
+    }
+
+    /// Render an lcov tracefile, `source` names the class file the records
+    /// belong to.
+    pub fn lcov(&self, program: &Program, source: &str) -> String {
+        let mut report = String::from("TN:\n");
+        for coverage in self.methods(program) {
+            let _ = writeln!(report, "SF:{source}#{}", coverage.name);
+            let _ = writeln!(report, "FN:1,{}", coverage.name);
+            let calls = coverage.instructions.get(&0).copied();
+            let _ = writeln!(
+                report,
+                "FNDA:{},{}",
+                calls.unwrap_or_default(),
+                coverage.name
+            );
+            let _ = writeln!(report, "FNF:1");
+            let _ = writeln!(report, "FNH:{}", usize::from(calls > Some(0)));
+            for (offset, hits) in &coverage.instructions {
+                let _ = writeln!(report, "DA:{},{hits}", offset + 1);
+            }
+            let _ = writeln!(report, "LF:{}", coverage.instructions.len());
+            let _ = writeln!(report, "LH:{}", coverage.covered());
+            report.push_str("end_of_record\n");
+        }
+        report
+    }
+}
+
+/// Returns the name of the method at `index`.
+fn method_name(program: &Program, index: usize) -> String {
+    program
+        .method_name(index)
+        .map_or_else(|| format!("method#{index}"), str::to_string)
+}
+
+impl Instrument for Coverage {
+    fn before_instruction(&mut self, frame: &Frame, _inst: &Instruction) {
+        self.record(frame.pc);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jvm::{read_class_file, JVMParser};
+    use crate::runtime::Runtime;
+    use std::env;
+    use std::path::Path;
+
+    #[test]
+    fn can_report_coverage() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/tests/Factorial.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let program = Program::new(&class_file);
+        let mut runtime = Runtime::new(program.clone());
+        runtime.enable_coverage();
+        runtime.run(false).unwrap();
+        let coverage = runtime.coverage().unwrap();
+
+        let methods = coverage.methods(&program);
+        let factorial = methods
+            .iter()
+            .find(|method| method.name == "factorial")
+            .unwrap();
+        // Every instruction runs, the loop header runs once more than the
+        // body since factorial(12) exits on the 12th comparison.
+        assert_eq!(factorial.covered(), factorial.instructions.len());
+        assert_eq!(factorial.instructions[&4], 12);
+        assert_eq!(factorial.instructions[&9], 11);
+        // The constructor is never called.
+        let init = methods.iter().find(|method| method.name == "<init>");
+        assert_eq!(init.unwrap().covered(), 0);
+
+        let annotated = coverage.annotate(&program);
+        assert!(annotated.contains("factorial : 15/15 instructions (100.0%)"));
+        assert!(annotated.contains("##### |    0: aload_0"));
+        let lcov = coverage.lcov(&program, "Factorial.class");
+        assert!(lcov.contains("SF:Factorial.class#factorial\n"));
+        assert!(lcov.contains("DA:5,12\n"));
+        assert!(lcov.contains("LH:0\nend_of_record"));
+    }
+}
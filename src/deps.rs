@@ -0,0 +1,266 @@
+//! Class dependency graphs.
+//!
+//! Starting from a root class, every class named by a `ConstantClass` entry
+//! of the constant pool is looked up on a class path and resolved in turn
+//! until no new class is found. Classes that can't be found, which includes
+//! the JDK classes unless a directory holding them is on the class path, are
+//! kept in the graph and flagged as missing.
+//!
+//! Graphs can be exported to Graphviz DOT or to JSON.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::jvm::{read_class_file, CPInfo, JVMClassFile, JVMParser};
+
+/// `ClassPath` maps binary class names such as `java/lang/Object` to class
+/// files in a list of directories.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassPath {
+    entries: Vec<PathBuf>,
+}
+
+impl ClassPath {
+    pub fn new(entries: Vec<PathBuf>) -> Self {
+        Self { entries }
+    }
+
+    /// Parse a class path made of directories separated by `:`.
+    pub fn parse(classpath: &str) -> Self {
+        Self::new(
+            classpath
+                .split(':')
+                .filter(|entry| !entry.is_empty())
+                .map(PathBuf::from)
+                .collect(),
+        )
+    }
+
+    /// Returns the path of the class file for `class`, the first entry
+    /// holding it wins.
+    pub fn find(&self, class: &str) -> Option<PathBuf> {
+        self.entries
+            .iter()
+            .map(|entry| entry.join(format!("{class}.class")))
+            .find(|path| path.is_file())
+    }
+}
+
+/// A class of the dependency graph.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClassNode {
+    /// Class file the class was loaded from, `None` if it wasn't found.
+    pub path: Option<PathBuf>,
+    /// Error raised while reading or parsing the class file.
+    pub error: Option<String>,
+    /// Classes referenced by the constant pool, excluding the class itself.
+    pub dependencies: BTreeSet<String>,
+}
+
+impl ClassNode {
+    /// Returns true if the class file couldn't be found on the class path.
+    pub fn is_missing(&self) -> bool {
+        self.path.is_none()
+    }
+}
+
+/// `DependencyGraph` holds every class reachable from a root class.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyGraph {
+    pub root: String,
+    pub classes: BTreeMap<String, ClassNode>,
+}
+
+impl DependencyGraph {
+    /// Resolve the classes reachable from `root` on `classpath`.
+    pub fn resolve(root: &str, classpath: &ClassPath) -> Self {
+        let mut classes = BTreeMap::new();
+        let mut pending = vec![root.to_string()];
+        while let Some(class) = pending.pop() {
+            if classes.contains_key(&class) {
+                continue;
+            }
+            let mut node = ClassNode {
+                path: classpath.find(&class),
+                ..ClassNode::default()
+            };
+            if let Some(path) = &node.path {
+                match read_class_file(path)
+                    .and_then(|bytes| Ok(JVMParser::parse(&bytes)?))
+                {
+                    Ok(class_file) => {
+                        node.dependencies = referenced_classes(&class_file);
+                        node.dependencies.remove(&class);
+                    }
+                    Err(err) => node.error = Some(err.to_string()),
+                }
+            }
+            pending.extend(node.dependencies.iter().cloned());
+            classes.insert(class, node);
+        }
+        Self {
+            root: root.to_string(),
+            classes,
+        }
+    }
+
+    /// Returns the names of the classes missing from the class path.
+    pub fn missing(&self) -> Vec<&str> {
+        self.classes
+            .iter()
+            .filter(|(_, node)| node.is_missing())
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    /// Render the graph in Graphviz DOT, missing classes are drawn dashed
+    /// in red and classes that failed to parse in orange.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph dependencies {\n");
+        let _ = writeln!(dot, "  node [shape=box];");
+        for (name, node) in &self.classes {
+            let style = if node.is_missing() {
+                ", style=dashed, color=red"
+            } else if node.error.is_some() {
+                ", color=orange"
+            } else if *name == self.root {
+                ", style=bold"
+            } else {
+                ""
+            };
+            let _ = writeln!(dot, "  {name:?} [label={name:?}{style}];");
+        }
+        for (name, node) in &self.classes {
+            for dependency in &node.dependencies {
+                let _ = writeln!(dot, "  {name:?} -> {dependency:?};");
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Render the graph as a JSON object.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\n");
+        let _ = writeln!(json, "  \"root\": {},", json_string(&self.root));
+        json.push_str("  \"classes\": {");
+        for (index, (name, node)) in self.classes.iter().enumerate() {
+            let separator = if index == 0 { "" } else { "," };
+            let path = node.path.as_ref().map_or("null".to_string(), |path| {
+                json_string(&path.display().to_string())
+            });
+            let error = node
+                .error
+                .as_ref()
+                .map_or("null".to_string(), |error| json_string(error));
+            let dependencies: Vec<String> = node
+                .dependencies
+                .iter()
+                .map(|dep| json_string(dep))
+                .collect();
+            let _ = write!(
+                json,
+                "{separator}\n    {}: {{\"path\": {path}, \"missing\": {}, \
+                 \"error\": {error}, \"dependencies\": [{}]}}",
+                json_string(name),
+                node.is_missing(),
+                dependencies.join(", ")
+            );
+        }
+        json.push_str("\n  }\n}\n");
+        json
+    }
+}
+
+/// Returns the classes named by the `ConstantClass` entries of the constant
+/// pool, array classes are reduced to their element class.
+pub(crate) fn referenced_classes(
+    class_file: &JVMClassFile,
+) -> BTreeSet<String> {
+    class_file
+        .constant_pool()
+        .iter()
+        .filter_map(|entry| match entry {
+            CPInfo::ConstantClass { name_index } => {
+                class_file.utf8(*name_index).and_then(element_class)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the element class of `name`, `None` for arrays of primitives.
+fn element_class(name: &str) -> Option<String> {
+    let element = name.trim_start_matches('[');
+    if element.len() == name.len() {
+        return Some(name.to_string());
+    }
+    element
+        .strip_prefix('L')
+        .and_then(|element| element.strip_suffix(';'))
+        .map(ToString::to_string)
+}
+
+/// Quote `value` as a JSON string.
+pub(crate) fn json_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Returns the binary class name of the class file at `path` if it can be
+/// parsed.
+pub fn class_name(path: &Path) -> Option<String> {
+    let class_file = JVMParser::parse(&read_class_file(path).ok()?).ok()?;
+    class_file.class_name().map(ToString::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn resolves_dependencies() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let root = Path::new(&env_var).join("support/tests");
+        let classpath = ClassPath::new(vec![root.clone()]);
+        assert_eq!(
+            class_name(&root.join("Factorial.class")).as_deref(),
+            Some("Factorial")
+        );
+
+        let graph = DependencyGraph::resolve("Factorial", &classpath);
+        let factorial = &graph.classes["Factorial"];
+        assert!(!factorial.is_missing());
+        assert!(factorial.dependencies.contains("java/lang/Object"));
+        assert!(factorial.dependencies.contains("java/lang/System"));
+        assert!(graph.missing().contains(&"java/lang/Object"));
+        assert_eq!(graph.classes.len(), factorial.dependencies.len() + 1);
+
+        let dot = graph.to_dot();
+        assert!(dot.contains("\"Factorial\" -> \"java/lang/Object\";"));
+        assert!(dot.contains("\"java/lang/Object\" [label=\"java/lang/Object\", style=dashed, color=red];"));
+        let json = graph.to_json();
+        assert!(json.contains("\"root\": \"Factorial\""));
+        assert!(json.contains(
+            "\"java/lang/Object\": {\"path\": null, \"missing\": true"
+        ));
+
+        assert_eq!(
+            element_class("[[Ljava/lang/String;").as_deref(),
+            Some("java/lang/String")
+        );
+        assert_eq!(element_class("[I"), None);
+    }
+}
@@ -0,0 +1,238 @@
+//! Differential testing against a system JVM.
+//!
+//! The same class file is run under coldbrew (interpreter only and with the
+//! tracing JIT) and under an installed `java` binary, the captured standard
+//! output and exit codes are then compared.
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use crate::jvm::{read_class_file, JVMParser};
+use crate::loader::ClassLoader;
+use crate::runtime::Runtime;
+use crate::sink::SharedBuffer;
+
+/// Observable outcome of running a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub stdout: String,
+    pub exit_code: i32,
+}
+
+/// Run the class file at `path` with coldbrew, linked with the classes next
+/// to it, and capture its outcome, any failure is reported as a non zero exit code like an uncaught exception
+/// would be by the JVM.
+pub fn run_coldbrew(path: &Path, jit_mode: bool) -> Outcome {
+    let stdout = SharedBuffer::new();
+    let exit_code = match read_class_file(path)
+        .and_then(|bytes| Ok(JVMParser::parse(&bytes)?))
+    {
+        Ok(class_file) => match ClassLoader::link_file(path, &class_file) {
+            Ok(program) => {
+                let mut runtime = Runtime::new(program);
+                runtime.set_stdout(Box::new(stdout.clone()));
+                match runtime.run(jit_mode) {
+                    Ok(()) => 0,
+                    Err(_) => 1,
+                }
+            }
+            Err(_) => 1,
+        },
+        Err(_) => 1,
+    };
+    Outcome {
+        stdout: stdout.to_string_lossy(),
+        exit_code,
+    }
+}
+
+/// Run the class file at `path` with the `java` executable and capture its
+/// outcome.
+/// # Errors
+/// Returns an error if `java` can't be spawned.
+pub fn run_java(java: &str, path: &Path) -> io::Result<Outcome> {
+    let class_name = path.file_stem().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "missing class name")
+    })?;
+    let class_path = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let output = Command::new(java)
+        .arg("-cp")
+        .arg(class_path)
+        .arg(class_name)
+        .output()?;
+    Ok(Outcome {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        exit_code: output.status.code().unwrap_or(-1),
+    })
+}
+
+/// Comparison between a reference outcome produced by `java` and the
+/// outcome produced by coldbrew.
+#[derive(Debug, Clone)]
+pub struct Divergence<'a> {
+    mode: &'a str,
+    expected: &'a Outcome,
+    actual: &'a Outcome,
+}
+
+impl<'a> Divergence<'a> {
+    /// Compare `actual` with `expected`, `mode` labels every reported line.
+    pub fn new(
+        mode: &'a str,
+        expected: &'a Outcome,
+        actual: &'a Outcome,
+    ) -> Self {
+        Self {
+            mode,
+            expected,
+            actual,
+        }
+    }
+}
+
+impl fmt::Display for Divergence<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.expected.exit_code != self.actual.exit_code {
+            writeln!(
+                f,
+                "[{}] exit code : expected {} got {}",
+                self.mode, self.expected.exit_code, self.actual.exit_code
+            )?;
+        }
+        let expected: Vec<&str> = self.expected.stdout.lines().collect();
+        let actual: Vec<&str> = self.actual.stdout.lines().collect();
+        for line in 0..expected.len().max(actual.len()) {
+            let (lhs, rhs) = (expected.get(line), actual.get(line));
+            if lhs != rhs {
+                writeln!(
+                    f,
+                    "[{}] stdout line {} : expected {:?} got {:?}",
+                    self.mode,
+                    line + 1,
+                    lhs.unwrap_or(&"<eof>"),
+                    rhs.unwrap_or(&"<eof>")
+                )?;
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Result of running a class file under `java` and coldbrew.
+#[derive(Debug, Clone)]
+pub struct DiffReport {
+    pub java: Outcome,
+    pub interpreter: Outcome,
+    pub jit: Outcome,
+}
+
+impl DiffReport {
+    /// Returns true if coldbrew matched `java` in every mode.
+    pub fn is_match(&self) -> bool {
+        self.java == self.interpreter && self.java == self.jit
+    }
+
+    /// Returns the divergences from the reference outcome.
+    pub fn divergences(&self) -> Vec<Divergence<'_>> {
+        [("interpreter", &self.interpreter), ("jit", &self.jit)]
+            .into_iter()
+            .filter(|(_, outcome)| **outcome != self.java)
+            .map(|(mode, actual)| Divergence::new(mode, &self.java, actual))
+            .collect()
+    }
+}
+
+impl fmt::Display for DiffReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_match() {
+            return writeln!(f, "No divergence found.");
+        }
+        for divergence in self.divergences() {
+            write!(f, "{divergence}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Run the class file at `path` under `java` and coldbrew and compare them.
+/// # Errors
+/// Returns an error if `java` can't be spawned.
+pub fn diff(java: &str, path: &Path) -> io::Result<DiffReport> {
+    Ok(DiffReport {
+        java: run_java(java, path)?,
+        interpreter: run_coldbrew(path, false),
+        jit: run_coldbrew(path, true),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    /// Compile `source` with `javac` into a scratch directory, returns None
+    /// when no JDK is installed.
+    fn javac(source: &str, out: &str) -> Option<std::path::PathBuf> {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let out_dir = env::temp_dir().join(out);
+        let status = Command::new("javac")
+            .arg("-d")
+            .arg(&out_dir)
+            .arg(Path::new(&env_var).join(source))
+            .status()
+            .ok()?;
+        assert!(status.success());
+        let stem = Path::new(source).file_stem().unwrap();
+        Some(out_dir.join(stem).with_extension("class"))
+    }
+
+    #[test]
+    fn matches_java_output() {
+        let Some(path) =
+            javac("support/tests/SingleFuncCall.java", "coldbrew-diff")
+        else {
+            return;
+        };
+        let report = diff("java", &path).unwrap();
+        assert_eq!(report.java.stdout, "5\n");
+        assert!(report.is_match(), "{report}");
+    }
+
+    #[test]
+    fn runs_linked_classes() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/linking/Main.class");
+        for jit_mode in [false, true] {
+            let outcome = run_coldbrew(&path, jit_mode);
+            assert_eq!(outcome.stdout, "58\n");
+            assert_eq!(outcome.exit_code, 0);
+        }
+    }
+
+    #[test]
+    fn reports_divergence() {
+        let java = Outcome {
+            stdout: "1\n2\n".to_string(),
+            exit_code: 0,
+        };
+        let coldbrew = Outcome {
+            stdout: "1\n3\n".to_string(),
+            exit_code: 0,
+        };
+        let report = DiffReport {
+            java,
+            interpreter: coldbrew.clone(),
+            jit: coldbrew,
+        };
+        assert!(!report.is_match());
+        assert_eq!(report.divergences().len(), 2);
+        assert!(report.to_string().contains(
+            "[interpreter] stdout line 2 : expected \"2\" got \"3\""
+        ));
+    }
+}
@@ -0,0 +1,334 @@
+//! Bytecode disassembler.
+//!
+//! Decodes a method's code into instructions with their operands, branch
+//! operands are resolved to absolute offsets the same way `javap -c` prints
+//! them. `jump_arrows` renders a gutter that links branches to their
+//! targets for display next to the disassembly.
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::bytecode::{OPCode, OperandKind};
+
+/// A decoded instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedInstruction {
+    /// Offset of the opcode in the method's code.
+    pub offset: usize,
+    pub opcode: OPCode,
+    /// Immediate operands, branch offsets are kept relative.
+    pub operands: Vec<i64>,
+    /// Absolute offsets of the branch targets, switches list the default
+    /// target first.
+    pub targets: Vec<usize>,
+}
+
+impl fmt::Display for DecodedInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>4}: {}", self.offset, self.opcode)?;
+        match self.opcode {
+            OPCode::TableSwitch | OPCode::LookupSwitch => {
+                let keys = self.switch_keys();
+                write!(f, " default:{}", self.targets[0])?;
+                for (key, target) in keys.iter().zip(&self.targets[1..]) {
+                    write!(f, " {key}:{target}")?;
+                }
+                Ok(())
+            }
+            opcode if opcode.is_branch() => {
+                write!(f, " {}", self.targets[0])
+            }
+            _ => {
+                for operand in &self.operands {
+                    write!(f, " {operand}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl DecodedInstruction {
+    /// Returns the match keys of a switch instruction in target order.
+    fn switch_keys(&self) -> Vec<i64> {
+        match self.opcode {
+            // operands : default, low, high, offsets...
+            OPCode::TableSwitch => (self.operands[1]..=self.operands[2])
+                .take(self.targets.len() - 1)
+                .collect(),
+            // operands : default, npairs, (key, offset)...
+            OPCode::LookupSwitch => {
+                self.operands[2..].iter().step_by(2).copied().collect()
+            }
+            _ => vec![],
+        }
+    }
+}
+
+/// `DisassemblyError` is returned when the code ends in the middle of an
+/// instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisassemblyError {
+    pub offset: usize,
+}
+
+impl fmt::Display for DisassemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "truncated instruction at offset {}", self.offset)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DisassemblyError {}
+
+/// Cursor over the code of a method.
+struct Reader<'a> {
+    code: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn read(&mut self, kind: OperandKind) -> Option<i64> {
+        let bytes =
+            self.code.get(self.position..self.position + kind.size())?;
+        self.position += kind.size();
+        Some(match kind {
+            OperandKind::I8 => i64::from(bytes[0] as i8),
+            OperandKind::U8 => i64::from(bytes[0]),
+            OperandKind::I16 => {
+                i64::from(i16::from_be_bytes([bytes[0], bytes[1]]))
+            }
+            OperandKind::U16 => {
+                i64::from(u16::from_be_bytes([bytes[0], bytes[1]]))
+            }
+            OperandKind::I32 => i64::from(i32::from_be_bytes([
+                bytes[0], bytes[1], bytes[2], bytes[3],
+            ])),
+        })
+    }
+}
+
+/// Decode `code` into instructions.
+/// # Errors
+/// Returns an error if the code ends in the middle of an instruction.
+pub fn disassemble(
+    code: &[u8],
+) -> Result<Vec<DecodedInstruction>, DisassemblyError> {
+    let mut reader = Reader { code, position: 0 };
+    let mut instructions = Vec::new();
+    while reader.position < code.len() {
+        let offset = reader.position;
+        let error = DisassemblyError { offset };
+        let opcode = OPCode::from(code[offset]);
+        reader.position += 1;
+        let branch = |relative: i64| (offset as i64 + relative) as usize;
+        let (operands, targets) = match opcode {
+            OPCode::TableSwitch | OPCode::LookupSwitch => {
+                // Operands are aligned on a 4 byte boundary.
+                reader.position = (reader.position + 3) & !3;
+                let default = reader.read(OperandKind::I32).ok_or(error)?;
+                let mut operands = vec![default];
+                let mut targets = vec![branch(default)];
+                if opcode == OPCode::TableSwitch {
+                    let low = reader.read(OperandKind::I32).ok_or(error)?;
+                    let high = reader.read(OperandKind::I32).ok_or(error)?;
+                    operands.extend([low, high]);
+                    for _ in low..=high {
+                        let relative =
+                            reader.read(OperandKind::I32).ok_or(error)?;
+                        operands.push(relative);
+                        targets.push(branch(relative));
+                    }
+                } else {
+                    let npairs = reader.read(OperandKind::I32).ok_or(error)?;
+                    operands.push(npairs);
+                    for _ in 0..npairs {
+                        let key = reader.read(OperandKind::I32).ok_or(error)?;
+                        let relative =
+                            reader.read(OperandKind::I32).ok_or(error)?;
+                        operands.extend([key, relative]);
+                        targets.push(branch(relative));
+                    }
+                }
+                (operands, targets)
+            }
+            OPCode::Wide => {
+                let modified = reader.read(OperandKind::U8).ok_or(error)?;
+                let mut operands = vec![modified];
+                operands.push(reader.read(OperandKind::U16).ok_or(error)?);
+                if OPCode::from(modified as u8) == OPCode::IInc {
+                    operands.push(reader.read(OperandKind::I16).ok_or(error)?);
+                }
+                (operands, vec![])
+            }
+            _ => {
+                let kinds = opcode.operands().unwrap_or_default();
+                let mut operands = vec![];
+                for kind in kinds {
+                    operands.push(reader.read(*kind).ok_or(error)?);
+                }
+                let targets = if opcode.is_branch() {
+                    vec![branch(operands[0])]
+                } else {
+                    vec![]
+                };
+                (operands, targets)
+            }
+        };
+        instructions.push(DecodedInstruction {
+            offset,
+            opcode,
+            operands,
+            targets,
+        });
+    }
+    Ok(instructions)
+}
+
+/// Render a gutter of jump arrows, one string per instruction, linking
+/// every branch to its target. Branches are drawn with `+` at both ends,
+/// `|` in between and an arrow head `>` on the target line. Nested jumps
+/// get their own columns with the shortest jumps closest to the code.
+pub fn jump_arrows(instructions: &[DecodedInstruction]) -> Vec<String> {
+    let line_of = |offset: usize| {
+        instructions
+            .binary_search_by_key(&offset, |inst| inst.offset)
+            .ok()
+    };
+    // (source line, target line) of every jump.
+    let mut jumps: Vec<(usize, usize)> = instructions
+        .iter()
+        .enumerate()
+        .flat_map(|(line, inst)| {
+            inst.targets
+                .iter()
+                .filter_map(move |target| line_of(*target).map(|t| (line, t)))
+        })
+        .collect();
+    jumps.sort_by_key(|(src, dst)| src.abs_diff(*dst));
+
+    // Assign every jump the closest column not used by an overlapping jump.
+    let mut columns: Vec<Vec<(usize, usize)>> = vec![];
+    let mut placed = vec![];
+    for (src, dst) in jumps {
+        let span = (src.min(dst), src.max(dst));
+        let overlaps =
+            |(lo, hi): &(usize, usize)| lo <= &span.1 && &span.0 <= hi;
+        let column = match columns
+            .iter()
+            .position(|spans| !spans.iter().any(overlaps))
+        {
+            Some(column) => column,
+            None => {
+                columns.push(vec![]);
+                columns.len() - 1
+            }
+        };
+        columns[column].push(span);
+        placed.push((column, src, dst));
+    }
+
+    let width = columns.len() * 2 + 1;
+    let mut gutter = vec![vec![' '; width]; instructions.len()];
+    if columns.is_empty() {
+        return gutter.iter().map(|row| row.iter().collect()).collect();
+    }
+    for (column, src, dst) in placed {
+        let x = (columns.len() - 1 - column) * 2;
+        let (lo, hi) = (src.min(dst), src.max(dst));
+        for row in &mut gutter[lo + 1..hi] {
+            row[x] = '|';
+        }
+        for line in [src, dst] {
+            let row = &mut gutter[line];
+            row[x] = '+';
+            for cell in &mut row[x + 1..width - 1] {
+                if *cell == ' ' {
+                    *cell = '-';
+                }
+            }
+            if row[width - 1] != '>' {
+                row[width - 1] = if line == dst { '>' } else { '-' };
+            }
+        }
+    }
+    gutter.iter().map(|row| row.iter().collect()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    #[test]
+    fn can_disassemble_method() {
+        // `factorial` from `support/tests/Factorial`.
+        let code = vec![
+            4, 60, 5, 61, 28, 26, 163, 0, 13, 27, 28, 104, 60, 132, 2, 1, 167,
+            255, 244, 27, 172,
+        ];
+        let listing: Vec<String> = disassemble(&code)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            listing,
+            vec![
+                "   0: iconst_1",
+                "   1: istore_1",
+                "   2: iconst_2",
+                "   3: istore_2",
+                "   4: iload_2",
+                "   5: iload_0",
+                "   6: if_icmpgt 19",
+                "   9: iload_1",
+                "  10: iload_2",
+                "  11: imul",
+                "  12: istore_1",
+                "  13: iinc 2 1",
+                "  16: goto 4",
+                "  19: iload_1",
+                "  20: ireturn",
+            ]
+        );
+        assert_eq!(
+            disassemble(&code[..8]),
+            Err(DisassemblyError { offset: 6 })
+        );
+    }
+
+    #[test]
+    fn can_disassemble_switches() {
+        let code = vec![
+            0, 170, 0, 0, 0, 0, 0, 20, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 20, 0,
+            0, 0, 21, 177,
+        ];
+        let instructions = disassemble(&code).unwrap();
+        assert_eq!(instructions[1].targets, vec![21, 21, 22]);
+        assert_eq!(
+            instructions[1].to_string(),
+            "   1: tableswitch default:21 1:21 2:22"
+        );
+        assert_eq!(instructions[2].offset, 24);
+    }
+
+    #[test]
+    fn draws_nested_jump_arrows() {
+        let code = assemble(
+            "
+            outer:
+                iconst_0
+            inner:
+                iconst_1
+                ifeq inner
+                ifeq outer
+                return
+            ",
+        )
+        .unwrap();
+        let arrows = jump_arrows(&disassemble(&code).unwrap());
+        assert_eq!(arrows, vec!["+--->", "| +->", "| +--", "+----", "     "]);
+    }
+}
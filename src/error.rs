@@ -0,0 +1,285 @@
+//! Errors returned by the public entry points.
+//!
+//! Every stage of the pipeline has its own error type, `Error` wraps them
+//! along with the class, method and program counter they happened at so
+//! that embedders only have to handle a single type.
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::bytecode::OPCode;
+use crate::runtime::{ProgramCounter, RuntimeError, Value};
+
+/// `ParseError` reports a class file that couldn't be read or parsed.
+#[derive(Debug)]
+pub struct ParseError {
+    pub path: PathBuf,
+    pub source: io::Error,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Failed to parse class file {} : {}",
+            self.path.display(),
+            self.source
+        )
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// `LinkErrorKind` represents the ways resolving a method can fail.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkErrorKind {
+    /// The program holds another class.
+    UnknownClass,
+    /// The class has no method with this name.
+    UnknownMethod,
+    /// The class has no field with this name.
+    UnknownField,
+    /// Two linked classes have the same name.
+    DuplicateClass,
+    /// The class inherits several default methods for the member and
+    /// none of them is more specific than the others.
+    IncompatibleClassChange,
+    /// The entry point isn't static, or public for `main`.
+    InvalidEntryPoint,
+    /// The descriptor isn't a method descriptor made of primitive types.
+    InvalidDescriptor(String),
+    /// The method was declared with another descriptor.
+    DescriptorMismatch { expected: String, got: String },
+    /// The number of arguments doesn't match the descriptor.
+    ArgumentCount { expected: usize, got: usize },
+    /// An argument doesn't match its type in the descriptor.
+    ArgumentType {
+        index: usize,
+        expected: char,
+        got: Value,
+    },
+}
+
+/// `LinkError` reports a method that couldn't be resolved or called.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkError {
+    pub class: String,
+    pub method: String,
+    pub kind: LinkErrorKind,
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{} : ", self.class, self.method)?;
+        match &self.kind {
+            LinkErrorKind::UnknownClass => write!(f, "unknown class"),
+            LinkErrorKind::UnknownMethod => write!(f, "unknown method"),
+            LinkErrorKind::UnknownField => write!(f, "unknown field"),
+            LinkErrorKind::DuplicateClass => write!(f, "duplicate class"),
+            LinkErrorKind::IncompatibleClassChange => {
+                write!(f, "conflicting default methods")
+            }
+            LinkErrorKind::InvalidEntryPoint => {
+                write!(f, "entry point must be static, and public for main")
+            }
+            LinkErrorKind::InvalidDescriptor(descriptor) => {
+                write!(f, "unsupported method descriptor {descriptor}")
+            }
+            LinkErrorKind::DescriptorMismatch { expected, got } => {
+                write!(f, "method is declared as {expected}, got {got}")
+            }
+            LinkErrorKind::ArgumentCount { expected, got } => {
+                write!(f, "expected {expected} arguments, got {got}")
+            }
+            LinkErrorKind::ArgumentType {
+                index,
+                expected,
+                got,
+            } => write!(
+                f,
+                "argument {index} expects type {expected}, got {got:?}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+/// `Error` is the error type of the public entry points.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing auxiliary files such as the execution log.
+    Io(io::Error),
+    /// The class file couldn't be parsed.
+    Parse(ParseError),
+    /// A method couldn't be resolved.
+    Link(LinkError),
+    /// The program failed while running.
+    Runtime {
+        class: String,
+        method: String,
+        /// Program counter of the failing instruction when known.
+        pc: Option<ProgramCounter>,
+        source: RuntimeError,
+    },
+    /// A trace couldn't be compiled.
+    Jit(JitError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::Parse(err) => write!(f, "{err}"),
+            Self::Link(err) => write!(f, "{err}"),
+            Self::Runtime {
+                class,
+                method,
+                pc,
+                source,
+            } => {
+                write!(f, "{class}.{method}")?;
+                if let Some(pc) = pc {
+                    write!(f, " @ {}", pc.get_instruction_index())?;
+                }
+                write!(f, " : {source}")
+            }
+            Self::Jit(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Parse(err) => Some(err),
+            Self::Link(err) => Some(err),
+            Self::Runtime { source, .. } => Some(source),
+            Self::Jit(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        Self::Parse(err)
+    }
+}
+
+impl From<LinkError> for Error {
+    fn from(err: LinkError) -> Self {
+        Self::Link(err)
+    }
+}
+
+impl From<JitError> for Error {
+    fn from(err: JitError) -> Self {
+        Self::Jit(err)
+    }
+}
+
+/// `JitErrorKind` represents the reasons a trace can fail to compile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JitErrorKind {
+    /// The assembler failed to allocate or finalize executable memory.
+    Assembler(String),
+    /// Every allocatable register is in use.
+    OutOfRegisters,
+    /// The instruction expected an operand the trace didn't produce.
+    MissingOperand(OPCode),
+    /// The instruction can't be compiled outside of a vectorized loop.
+    Unsupported(OPCode),
+    /// The guard would exit the trace with values on the operand stack.
+    LiveOperands(OPCode),
+    /// The method uses a feature the baseline compiler doesn't support.
+    Method(String),
+}
+
+/// `JitError` reports a trace that couldn't be compiled, the runtime keeps
+/// interpreting the loop instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JitError {
+    /// Program counter of the instruction that failed to compile.
+    pub pc: ProgramCounter,
+    pub kind: JitErrorKind,
+}
+
+impl fmt::Display for JitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Failed to compile trace @ {} : ", self.pc)?;
+        match &self.kind {
+            JitErrorKind::Assembler(err) => write!(f, "{err}"),
+            JitErrorKind::OutOfRegisters => write!(f, "no available registers"),
+            JitErrorKind::MissingOperand(opcode) => {
+                write!(f, "{opcode} expected an operand found none")
+            }
+            JitErrorKind::Unsupported(opcode) => {
+                write!(f, "{opcode} isn't supported by the trace compiler")
+            }
+            JitErrorKind::LiveOperands(opcode) => {
+                write!(f, "{opcode} exits the trace with operands on the stack")
+            }
+            JitErrorKind::Method(reason) => {
+                write!(f, "{reason} aren't supported by the baseline compiler")
+            }
+        }
+    }
+}
+
+impl std::error::Error for JitError {}
+
+/// Result type of the public entry points.
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::RuntimeBuilder;
+    use crate::jvm::{read_class_file, JVMParser};
+    use crate::options::Limits;
+    use crate::program::Program;
+    use crate::sink::SharedBuffer;
+    use std::env;
+    use std::path::Path;
+
+    #[test]
+    fn runtime_errors_carry_context() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/tests/Factorial.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let mut runtime = RuntimeBuilder::new(Program::new(&class_file))
+            .stdout(Box::new(SharedBuffer::new()))
+            .limits(Limits {
+                instructions: Some(20),
+                ..Limits::default()
+            })
+            .build()
+            .unwrap();
+        let err = runtime.start().unwrap_err();
+        let err = runtime.runtime_error(err);
+        let Error::Runtime {
+            class, method, pc, ..
+        } = &err
+        else {
+            panic!("expected a runtime error, got {err:?}");
+        };
+        assert_eq!(
+            (class.as_str(), method.as_str()),
+            ("Factorial", "factorial")
+        );
+        assert!(pc.is_some());
+        assert!(err.to_string().starts_with("Factorial.factorial @ "));
+    }
+}
@@ -0,0 +1,244 @@
+//! Structured log of significant VM events.
+//!
+//! When enabled with `--events <path>` the runtime writes one JSON object
+//! per line for every trace recorded, compiled or aborted, every exit of a
+//! native trace, every baseline deopt, tier transition and blacklisted
+//! method or loop. Lines are meant for external analysis scripts, each has
+//! the `event` name, the microseconds `t` since the log was opened and the
+//! program counter as `method:instruction` :
+//!
+//! ```text
+//! {"event":"trace_compiled","t":412,"pc":"1:4","instructions":9,"bytes":187}
+//! {"event":"trace_exit","t":530,"pc":"1:4","exit":"1:22","iterations":99,"reason":"guard"}
+//! ```
+//!
+//! The heap is never collected so there are no GC events.
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use crate::deps::json_string;
+use crate::runtime::ProgramCounter;
+use crate::tiering::Transition;
+use crate::timing::Instant;
+
+/// Why a native trace returned to the interpreter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitReason {
+    /// The trace ran out of fuel at its loop back edge.
+    Safepoint,
+    /// A guard failed and the trace left through an exit stub.
+    Guard,
+    /// An instruction raised an exception.
+    Exception,
+}
+
+impl ExitReason {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Safepoint => "safepoint",
+            Self::Guard => "guard",
+            Self::Exception => "exception",
+        }
+    }
+}
+
+/// A significant VM event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A loop was recorded starting at `pc`.
+    TraceRecorded {
+        pc: ProgramCounter,
+        instructions: usize,
+    },
+    /// The trace at `pc` was compiled to `bytes` of machine code.
+    TraceCompiled {
+        pc: ProgramCounter,
+        instructions: usize,
+        bytes: usize,
+    },
+    /// The trace at `pc` couldn't be compiled.
+    CompileFailed { pc: ProgramCounter, reason: String },
+    /// Recording the loop at `pc` was abandoned.
+    RecordingAborted { pc: ProgramCounter },
+    /// The native trace at `pc` ran `iterations` and resumed the
+    /// interpreter at `exit`.
+    TraceExit {
+        pc: ProgramCounter,
+        exit: ProgramCounter,
+        iterations: usize,
+        reason: ExitReason,
+    },
+    /// The baseline code of a method resumed the interpreter at `pc` to
+    /// handle an instruction it can't run.
+    Deopt { pc: ProgramCounter },
+    /// Code moved between tiers.
+    Transition(Transition),
+    /// The method or loop at `pc` won't be compiled again.
+    Blacklist { pc: ProgramCounter, reason: String },
+    /// The native trace at `pc` was evicted from the code cache.
+    TraceEvicted { pc: ProgramCounter },
+}
+
+impl Event {
+    /// Returns the event name written to the `event` key.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::TraceRecorded { .. } => "trace_recorded",
+            Self::TraceCompiled { .. } => "trace_compiled",
+            Self::CompileFailed { .. } => "compile_failed",
+            Self::RecordingAborted { .. } => "recording_aborted",
+            Self::TraceExit { .. } => "trace_exit",
+            Self::Deopt { .. } => "deopt",
+            Self::Transition(_) => "tier_transition",
+            Self::Blacklist { .. } => "blacklist",
+            Self::TraceEvicted { .. } => "trace_evicted",
+        }
+    }
+
+    /// Render the event as a JSON object stamped with `micros`.
+    pub fn to_json(&self, micros: u128) -> String {
+        let pc = |pc: &ProgramCounter| {
+            json_string(&format!(
+                "{}:{}",
+                pc.get_method_index(),
+                pc.get_instruction_index()
+            ))
+        };
+        let mut json =
+            format!("{{\"event\":\"{}\",\"t\":{micros}", self.name());
+        let _ = match self {
+            Self::TraceRecorded {
+                pc: at,
+                instructions,
+            } => write!(
+                json,
+                ",\"pc\":{},\"instructions\":{instructions}",
+                pc(at)
+            ),
+            Self::TraceCompiled {
+                pc: at,
+                instructions,
+                bytes,
+            } => write!(
+                json,
+                ",\"pc\":{},\"instructions\":{instructions},\"bytes\":{bytes}",
+                pc(at)
+            ),
+            Self::CompileFailed { pc: at, reason }
+            | Self::Blacklist { pc: at, reason } => write!(
+                json,
+                ",\"pc\":{},\"reason\":{}",
+                pc(at),
+                json_string(reason)
+            ),
+            Self::RecordingAborted { pc: at }
+            | Self::Deopt { pc: at }
+            | Self::TraceEvicted { pc: at } => {
+                write!(json, ",\"pc\":{}", pc(at))
+            }
+            Self::TraceExit {
+                pc: at,
+                exit,
+                iterations,
+                reason,
+            } => write!(
+                json,
+                ",\"pc\":{},\"exit\":{},\"iterations\":{iterations},\
+                 \"reason\":\"{}\"",
+                pc(at),
+                pc(exit),
+                reason.name()
+            ),
+            Self::Transition(transition) => write!(
+                json,
+                ",\"pc\":{},\"from\":\"{}\",\"to\":\"{}\",\"reason\":\"{}\"",
+                pc(&transition.pc),
+                transition.from,
+                transition.to,
+                transition.reason
+            ),
+        };
+        json.push('}');
+        json
+    }
+}
+
+/// `EventLog` writes events to a sink as JSON lines.
+pub struct EventLog {
+    sink: Box<dyn Write + Send>,
+    start: Instant,
+}
+
+impl EventLog {
+    pub fn new(sink: Box<dyn Write + Send>) -> Self {
+        Self {
+            sink,
+            start: Instant::now(),
+        }
+    }
+
+    /// Write `event` on its own line, write errors are ignored so that a
+    /// full disk doesn't stop the program.
+    pub fn emit(&mut self, event: &Event) {
+        let micros = self.start.elapsed().as_micros();
+        let _ = writeln!(self.sink, "{}", event.to_json(micros));
+    }
+
+    /// Flush buffered events to the sink.
+    /// # Errors
+    /// Returns an error if the sink can't be flushed.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::SharedBuffer;
+    use crate::tiering::{Reason, Tier};
+
+    #[test]
+    fn writes_one_object_per_line() {
+        let buffer = SharedBuffer::new();
+        let mut log = EventLog::new(Box::new(buffer.clone()));
+        let pc = ProgramCounter::new(1, 4);
+        log.emit(&Event::TraceExit {
+            pc,
+            exit: ProgramCounter::new(1, 22),
+            iterations: 99,
+            reason: ExitReason::Guard,
+        });
+        log.emit(&Event::Transition(Transition {
+            pc: ProgramCounter::new(2, 0),
+            from: Tier::Interpreter,
+            to: Tier::Baseline,
+            reason: Reason::RecordingAborts,
+        }));
+        log.emit(&Event::CompileFailed {
+            pc,
+            reason: "invokestatic isn't \"supported\"".to_string(),
+        });
+        let output = buffer.to_string_lossy();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        // Strip the timestamps.
+        let strip = |line: &str| {
+            let (head, tail) = line.split_once(",\"t\":").unwrap();
+            let (_, tail) = tail.split_once(',').unwrap();
+            format!("{head},{tail}")
+        };
+        assert_eq!(
+            strip(lines[0]),
+            r#"{"event":"trace_exit","pc":"1:4","exit":"1:22","iterations":99,"reason":"guard"}"#
+        );
+        assert_eq!(
+            strip(lines[1]),
+            r#"{"event":"tier_transition","pc":"2:0","from":"interpreter","to":"baseline","reason":"recording aborts"}"#
+        );
+        assert_eq!(
+            strip(lines[2]),
+            r#"{"event":"compile_failed","pc":"1:4","reason":"invokestatic isn't \"supported\""}"#
+        );
+    }
+}
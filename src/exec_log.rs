@@ -0,0 +1,162 @@
+//! Per instruction execution log.
+//!
+//! When enabled the runtime writes one line per interpreted instruction
+//! with the program counter, the instruction and the value on top of the
+//! operand stack before it executes :
+//!
+//! ```text
+//! 2:6 factorial if_icmpgt 13 tos=12
+//! ```
+//!
+//! Filters restrict the log to some methods or opcodes so that diverging
+//! programs can be debugged without drowning in output.
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use crate::bytecode::OPCode;
+use crate::instrument::Instrument;
+use crate::program::Program;
+use crate::runtime::{Frame, Instruction, ProgramCounter, Value};
+
+/// `ExecLog` writes executed instructions to a sink.
+pub struct ExecLog {
+    sink: Box<dyn Write + Send>,
+    // Only log instructions of these methods, all methods when empty.
+    methods: Vec<String>,
+    // Only log these opcodes, all opcodes when empty.
+    opcodes: Vec<OPCode>,
+    // Method names keyed by method index, filled on attach.
+    names: BTreeMap<usize, String>,
+}
+
+impl ExecLog {
+    pub fn new(sink: Box<dyn Write + Send>) -> Self {
+        Self {
+            sink,
+            methods: vec![],
+            opcodes: vec![],
+            names: BTreeMap::new(),
+        }
+    }
+
+    /// Restrict the log to the method called `name`, can be called more than
+    /// once to log several methods.
+    #[must_use]
+    pub fn with_method(mut self, name: &str) -> Self {
+        self.methods.push(name.to_string());
+        self
+    }
+
+    /// Restrict the log to `opcode`, can be called more than once to log
+    /// several opcodes.
+    #[must_use]
+    pub fn with_opcode(mut self, opcode: OPCode) -> Self {
+        self.opcodes.push(opcode);
+        self
+    }
+
+    /// Returns true if instructions with `opcode` in method `method` pass
+    /// the filters.
+    pub fn accepts(&self, method: &str, opcode: OPCode) -> bool {
+        (self.methods.is_empty() || self.methods.iter().any(|m| m == method))
+            && (self.opcodes.is_empty() || self.opcodes.contains(&opcode))
+    }
+
+    /// Log the instruction about to execute at `pc`.
+    /// # Errors
+    /// Returns an error if writing to the sink fails.
+    pub fn log(
+        &mut self,
+        pc: ProgramCounter,
+        method: &str,
+        inst: &Instruction,
+        top: Option<Value>,
+    ) -> io::Result<()> {
+        if !self.accepts(method, inst.get_mnemonic()) {
+            return Ok(());
+        }
+        write!(
+            self.sink,
+            "{}:{} {method} {}",
+            pc.get_method_index(),
+            pc.get_instruction_index(),
+            inst.get_mnemonic()
+        )?;
+        for operand in inst.get_params().unwrap_or_default() {
+            write!(self.sink, " {operand}")?;
+        }
+        match top {
+            Some(value) => writeln!(self.sink, " tos={value}"),
+            None => writeln!(self.sink, " tos=<empty>"),
+        }
+    }
+
+    /// Flush the underlying sink.
+    /// # Errors
+    /// Returns an error if flushing the sink fails.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+}
+
+impl Instrument for ExecLog {
+    fn attach(&mut self, program: &Program) {
+        self.names = program
+            .methods
+            .iter()
+            .enumerate()
+            .filter(|(_, method)| !method.code.is_empty())
+            .filter_map(|(index, _)| {
+                Some((index, program.method_name(index)?.to_string()))
+            })
+            .collect();
+    }
+
+    fn before_instruction(&mut self, frame: &Frame, inst: &Instruction) {
+        let names = std::mem::take(&mut self.names);
+        let method = names
+            .get(&frame.pc.get_method_index())
+            .map_or("", String::as_str);
+        let top = frame.stack().last().copied();
+        // The log is a debugging aid, failing to write it shouldn't abort
+        // the program.
+        let _ = self.log(frame.pc, method, inst, top);
+        self.names = names;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jvm::{read_class_file, JVMParser};
+    use crate::program::Program;
+    use crate::runtime::Runtime;
+    use crate::sink::SharedBuffer;
+    use std::env;
+    use std::path::Path;
+
+    #[test]
+    fn logs_filtered_instructions() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/tests/Factorial.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let buffer = SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(SharedBuffer::new()));
+        runtime.set_exec_log(
+            ExecLog::new(Box::new(buffer.clone()))
+                .with_method("factorial")
+                .with_opcode(OPCode::IMul),
+        );
+        runtime.run(false).unwrap();
+
+        let log = buffer.to_string_lossy();
+        let lines: Vec<&str> = log.lines().collect();
+        // One multiplication per loop iteration, i = 2..=12.
+        assert_eq!(lines.len(), 11);
+        assert!(lines.iter().all(|line| line.contains(" factorial imul")));
+        assert!(lines[0].ends_with("tos=2"));
+        assert!(lines[10].ends_with("tos=12"));
+    }
+}
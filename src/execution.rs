@@ -0,0 +1,197 @@
+//! One call entry point for embedders.
+//!
+//! `run_class` performs the whole pipeline `main.rs` goes through, reading
+//! and parsing the class file, building the `Program` and running it, and
+//! returns everything observable about the run. Classes the program
+//! references are loaded from `RuntimeOptions::classpath`, which defaults
+//! to the root of the package directories of the class file.
+use std::path::Path;
+
+use crate::builder::RuntimeBuilder;
+use crate::error::{Error, ParseError, Result};
+use crate::jvm::{read_class_file, JVMParser};
+use crate::loader::ClassLoader;
+use crate::options::RuntimeOptions;
+use crate::sink::SharedBuffer;
+use crate::stats::Stats;
+use crate::timing::{Instant, Timings};
+
+/// Outcome of running a class file with `run_class`.
+#[derive(Debug)]
+pub struct Execution {
+    /// Exit status of the program, non zero if it failed at runtime.
+    pub exit_code: i32,
+    /// Everything the program printed to its standard output.
+    pub stdout: String,
+    /// Runtime failure that ended the program, if any.
+    pub error: Option<Error>,
+    /// Execution statistics of the run.
+    pub stats: Stats,
+    /// Phase timings, only collected when `RuntimeOptions::time` is set.
+    pub timings: Option<Timings>,
+}
+
+impl Execution {
+    /// Returns true if the program ran to completion.
+    pub fn success(&self) -> bool {
+        self.exit_code == 0
+    }
+}
+
+/// Run the class file at `path` with `opts` and capture its outcome.
+///
+/// `args` are passed to `main` as its `String[]` argument.
+/// # Errors
+/// Returns an error if the class file or the classes it references can't be
+/// read, parsed or linked or if the execution log can't be opened, failures
+/// of the program itself are reported in the returned `Execution`.
+pub fn run_class(
+    path: &Path,
+    args: &[String],
+    opts: RuntimeOptions,
+) -> Result<Execution> {
+    let start = Instant::now();
+    let bytes = read_class_file(path).map_err(|source| ParseError {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    run(&bytes, Some(path), start, args, opts)
+}
+
+/// Run the class file held in `bytes`, unlike `run_class` nothing is read
+/// from the file system which makes it usable on targets without one such
+/// as `wasm32-unknown-unknown`.
+/// # Errors
+/// Returns an error if the class file can't be parsed, the classes it
+/// references can't be loaded from `RuntimeOptions::classpath` or if the
+/// execution log can't be opened.
+pub fn run_class_bytes(
+    bytes: &[u8],
+    args: &[String],
+    opts: RuntimeOptions,
+) -> Result<Execution> {
+    run(bytes, None, Instant::now(), args, opts)
+}
+
+/// Run the class file held in `bytes` read from `path`, if any, which
+/// gives the default class path.
+fn run(
+    bytes: &[u8],
+    path: Option<&Path>,
+    start: Instant,
+    args: &[String],
+    mut opts: RuntimeOptions,
+) -> Result<Execution> {
+    let class_file = JVMParser::parse(bytes).map_err(|err| ParseError {
+        path: path.unwrap_or(Path::new("<bytes>")).to_path_buf(),
+        source: err.into(),
+    })?;
+    let parse = start.elapsed();
+    if let Some(path) = path.filter(|_| opts.classpath.is_empty()) {
+        let class = class_file.class_name().unwrap_or_default();
+        opts.classpath = ClassLoader::default_classpath(path, class);
+    }
+
+    let start = Instant::now();
+    let program = ClassLoader::new(&opts.classpath)?.link(&class_file)?;
+    let program_time = start.elapsed();
+
+    let stdout = SharedBuffer::new();
+    let mut runtime = RuntimeBuilder::new(program)
+        .options(opts)
+        .args(args)
+        .stdout(Box::new(stdout.clone()))
+        .stats(true)
+        .build()?;
+    let error = runtime.start().err().map(|err| runtime.runtime_error(err));
+
+    Ok(Execution {
+        exit_code: i32::from(error.is_some()),
+        stdout: stdout.to_string_lossy(),
+        error,
+        stats: runtime.stats().cloned().unwrap_or_default(),
+        timings: runtime.timings().map(|timings| Timings {
+            parse,
+            program: program_time,
+            ..timings.clone()
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jvm::{ClassFormatError, ClassFormatErrorKind};
+    use std::env;
+
+    #[test]
+    fn runs_class_in_one_call() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/tests/Factorial.class");
+        let opts = RuntimeOptions {
+            time: true,
+            ..RuntimeOptions::default()
+        };
+        let execution = run_class(&path, &[], opts).unwrap();
+        assert!(execution.success());
+        assert_eq!(execution.stdout, "479001600\n");
+        assert!(execution.stats.interpreted() > 0);
+        assert!(!execution.timings.unwrap().parse.is_zero());
+
+        let bytes = read_class_file(&path).unwrap();
+        let execution =
+            run_class_bytes(&bytes, &[], RuntimeOptions::default()).unwrap();
+        assert_eq!(execution.stdout, "479001600\n");
+        assert!(matches!(
+            run_class_bytes(&bytes[..10], &[], RuntimeOptions::default()),
+            Err(Error::Parse(_))
+        ));
+
+        let main = Path::new(&env_var).join("support/linking/Main.class");
+        let execution =
+            run_class(&main, &[], RuntimeOptions::default()).unwrap();
+        assert_eq!(execution.stdout, "58\n");
+        // Classes in packages are found from the root of the packages.
+        let packaged =
+            Path::new(&env_var).join("support/packages/com/example/Main.class");
+        let execution =
+            run_class(&packaged, &[], RuntimeOptions::default()).unwrap();
+        assert_eq!(execution.stdout, "42\n");
+
+        let echo = Path::new(&env_var).join("support/args/Echo.class");
+        let args = ["one".to_string(), "two".to_string()];
+        let execution =
+            run_class(&echo, &args, RuntimeOptions::default()).unwrap();
+        assert_eq!(execution.stdout, "2\none\ntwo\n");
+        let execution =
+            run_class(&echo, &[], RuntimeOptions::default()).unwrap();
+        assert_eq!(execution.stdout, "0\n");
+
+        let missing = Path::new(&env_var).join("support/tests/Missing.class");
+        assert!(matches!(
+            run_class(&missing, &[], RuntimeOptions::default()),
+            Err(Error::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn reports_invalid_class_files() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let kind = |path: &Path| {
+            let opts = RuntimeOptions::default();
+            let Err(Error::Parse(err)) = run_class(path, &[], opts) else {
+                return None;
+            };
+            let err = err.source.get_ref()?.downcast_ref::<ClassFormatError>();
+            err.map(|err| err.kind)
+        };
+        let truncated = env::temp_dir().join("coldbrew-truncated.class");
+        std::fs::write(&truncated, [0xCA, 0xFE]).unwrap();
+        assert_eq!(kind(&truncated), Some(ClassFormatErrorKind::UnexpectedEnd));
+        let source = Path::new(&env_var).join("support/tests/Factorial.java");
+        assert!(matches!(
+            kind(&source),
+            Some(ClassFormatErrorKind::InvalidMagic(_))
+        ));
+    }
+}
@@ -0,0 +1,406 @@
+//! Recovery from faults in generated code.
+//!
+//! Native traces and baseline methods are entered through a trampoline
+//! that registers the code being run. If it raises `SIGSEGV`, `SIGBUS`,
+//! `SIGILL`, `SIGFPE` or `SIGTRAP`, the signal handler checks that the
+//! faulting instruction is inside the registered code, restores the stack
+//! pointer saved by the trampoline and resumes it, which returns a `Fault`
+//! to the caller instead of taking down the process :
+//!
+//! ```text
+//! call(code, entry, args) -> trampoline -> entry ... mov eax, [rax]
+//!                                  ^                      | SIGSEGV
+//!                                  '--- handler: rsp, rip --'
+//! ```
+//!
+//! Generated code only works on copies of the interpreter state, so after
+//! a fault the runtime drops the code and resumes interpreting at the
+//! program counter it was entered at. Faults anywhere else, in the runtime
+//! or in code run by another thread, are handed to the handler installed
+//! before ours.
+//!
+//! Recovery is implemented on Linux x86-64, elsewhere generated code is
+//! called directly and a fault still terminates the process.
+use std::fmt;
+
+use dynasmrt::{AssemblyOffset, ExecutableBuffer};
+
+/// Bytes dumped on each line of `Fault::dump`.
+const DUMP_WIDTH: usize = 16;
+
+/// Signals raised by faulting instructions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// Invalid memory access.
+    Segv,
+    /// Misaligned or unmapped physical memory access.
+    Bus,
+    /// Illegal instruction, such as `ud2`.
+    Ill,
+    /// Arithmetic exception, such as `idiv` by zero.
+    Fpe,
+    /// Breakpoint, such as `int3`.
+    Trap,
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Segv => write!(f, "SIGSEGV"),
+            Self::Bus => write!(f, "SIGBUS"),
+            Self::Ill => write!(f, "SIGILL"),
+            Self::Fpe => write!(f, "SIGFPE"),
+            Self::Trap => write!(f, "SIGTRAP"),
+        }
+    }
+}
+
+/// `Fault` describes a signal raised by generated code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fault {
+    /// Signal raised.
+    pub signal: Signal,
+    /// Address reported by the kernel, the memory accessed for `SIGSEGV`
+    /// and `SIGBUS` and the faulting instruction otherwise.
+    pub address: usize,
+    /// Offset of the faulting instruction in `code`, past the instruction
+    /// for `SIGTRAP`.
+    pub offset: usize,
+    /// Machine code that was running.
+    pub code: Vec<u8>,
+}
+
+impl Fault {
+    /// Hex dump of the machine code with the byte at the faulting offset
+    /// in brackets.
+    pub fn dump(&self) -> String {
+        let mut dump = String::new();
+        for (line, bytes) in self.code.chunks(DUMP_WIDTH).enumerate() {
+            let start = line * DUMP_WIDTH;
+            dump.push_str(&format!("{start:04x} "));
+            for (index, byte) in bytes.iter().enumerate() {
+                if start + index == self.offset {
+                    dump.push_str(&format!("[{byte:02x}]"));
+                } else if start + index == self.offset + 1 {
+                    dump.push_str(&format!("{byte:02x}"));
+                } else {
+                    dump.push_str(&format!(" {byte:02x}"));
+                }
+            }
+            dump.push('\n');
+        }
+        dump
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {:#x}", self.signal, self.offset)?;
+        if matches!(self.signal, Signal::Segv | Signal::Bus) {
+            write!(f, " accessing {:#x}", self.address)?;
+        }
+        Ok(())
+    }
+}
+
+/// Call the function at `entry` in `code` with up to five integer or
+/// pointer arguments following the System V AMD64 calling convention,
+/// returns the contents of `rax` or the fault it raised.
+///
+/// # Safety
+/// `entry` must be a function in `code` taking `args` and any pointer
+/// among them must be valid for the accesses the function makes.
+///
+/// # Panics
+/// Panics if more than five arguments are passed.
+pub unsafe fn call(
+    code: &ExecutableBuffer,
+    entry: AssemblyOffset,
+    args: &[usize],
+) -> Result<i64, Fault> {
+    assert!(args.len() <= 5, "generated code takes at most 5 arguments");
+    let mut registers = [0usize; 5];
+    registers[..args.len()].copy_from_slice(args);
+    guarded::call(code, entry, &registers)
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+mod guarded {
+    use std::cell::Cell;
+    use std::mem;
+    use std::ptr;
+    use std::sync::OnceLock;
+
+    use dynasmrt::x64::Assembler;
+    use dynasmrt::{
+        dynasm, AssemblyOffset, DynasmApi, DynasmLabelApi, ExecutableBuffer,
+    };
+
+    use super::{Fault, Signal};
+
+    const SIGNALS: [(libc::c_int, Signal); 5] = [
+        (libc::SIGSEGV, Signal::Segv),
+        (libc::SIGBUS, Signal::Bus),
+        (libc::SIGILL, Signal::Ill),
+        (libc::SIGFPE, Signal::Fpe),
+        (libc::SIGTRAP, Signal::Trap),
+    ];
+
+    /// Code run by the current thread and where to resume it on a fault.
+    #[derive(Debug, Clone, Copy)]
+    struct Guard {
+        start: usize,
+        end: usize,
+        recover: usize,
+    }
+
+    /// Signal number, address and faulting instruction.
+    #[derive(Debug, Clone, Copy)]
+    struct Raised {
+        signal: libc::c_int,
+        address: usize,
+        rip: usize,
+    }
+
+    // Signal handlers run on the faulting thread so the state is thread
+    // local, it is initialized at compile time and never dropped which
+    // makes it safe to access from the handler.
+    thread_local! {
+        static GUARD: Cell<Option<Guard>> = const { Cell::new(None) };
+        // Stack pointer saved by the trampoline before the call.
+        static STACK: Cell<usize> = const { Cell::new(0) };
+        static RAISED: Cell<Option<Raised>> = const { Cell::new(None) };
+    }
+
+    /// `Trampoline` saves the callee saved registers and the stack pointer
+    /// before calling generated code, the handler resumes it at `recover`
+    /// which returns as if the call did.
+    struct Trampoline {
+        code: ExecutableBuffer,
+        entry: AssemblyOffset,
+        recover: AssemblyOffset,
+    }
+
+    type Entry = extern "sysv64" fn(*const usize, *const u8, *mut usize) -> i64;
+
+    static TRAMPOLINE: OnceLock<Trampoline> = OnceLock::new();
+    static PREVIOUS: OnceLock<Vec<(libc::c_int, libc::sigaction)>> =
+        OnceLock::new();
+
+    fn assemble() -> Trampoline {
+        let mut ops = Assembler::new().expect("failed to map the trampoline");
+        let entry = ops.offset();
+        // rdi points to the arguments, rsi to the code and rdx to the slot
+        // the stack pointer is saved in.
+        dynasm!(ops
+            ; .arch x64
+            ; push rbp
+            ; mov rbp, rsp
+            ; push rbx
+            ; push r12
+            ; push r13
+            ; push r14
+            ; push r15
+            ; sub rsp, 8
+            ; mov [rdx], rsp
+            ; mov rax, rsi
+            ; mov r10, rdi
+            ; mov rdi, [r10]
+            ; mov rsi, [r10 + 8]
+            ; mov rdx, [r10 + 16]
+            ; mov rcx, [r10 + 24]
+            ; mov r8, [r10 + 32]
+            ; call rax
+            ; ->leave:
+            ; add rsp, 8
+            ; pop r15
+            ; pop r14
+            ; pop r13
+            ; pop r12
+            ; pop rbx
+            ; pop rbp
+            ; ret
+        );
+        let recover = ops.offset();
+        dynasm!(ops
+            ; .arch x64
+            ; xor eax, eax
+            ; jmp ->leave
+        );
+        Trampoline {
+            code: ops.finalize().expect("failed to assemble the trampoline"),
+            entry,
+            recover,
+        }
+    }
+
+    /// Install the signal handlers, the handlers found are kept to forward
+    /// the faults that aren't ours.
+    fn install() {
+        PREVIOUS.get_or_init(|| {
+            let previous = SIGNALS
+                .iter()
+                .map(|(signal, _)| unsafe {
+                    let mut action: libc::sigaction = mem::zeroed();
+                    libc::sigaction(*signal, ptr::null(), &mut action);
+                    (*signal, action)
+                })
+                .collect();
+            for (signal, _) in SIGNALS {
+                unsafe {
+                    let mut action: libc::sigaction = mem::zeroed();
+                    action.sa_sigaction = handle as *const () as usize;
+                    action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+                    libc::sigemptyset(&mut action.sa_mask);
+                    libc::sigaction(signal, &action, ptr::null_mut());
+                }
+            }
+            previous
+        });
+    }
+
+    extern "C" fn handle(
+        signal: libc::c_int,
+        info: *mut libc::siginfo_t,
+        context: *mut libc::c_void,
+    ) {
+        unsafe {
+            let context = &mut *context.cast::<libc::ucontext_t>();
+            let registers = &mut context.uc_mcontext.gregs;
+            let rip = registers[libc::REG_RIP as usize] as usize;
+            let guard = GUARD.with(Cell::get);
+            match guard {
+                Some(guard) if (guard.start..guard.end).contains(&rip) => {
+                    RAISED.with(|raised| {
+                        raised.set(Some(Raised {
+                            signal,
+                            address: (*info).si_addr() as usize,
+                            rip,
+                        }))
+                    });
+                    registers[libc::REG_RSP as usize] =
+                        STACK.with(Cell::get) as i64;
+                    registers[libc::REG_RIP as usize] = guard.recover as i64;
+                }
+                // Restore the previous handler, it runs when the faulting
+                // instruction is retried.
+                _ => {
+                    let previous = PREVIOUS.get().and_then(|previous| {
+                        previous.iter().find(|(s, _)| *s == signal)
+                    });
+                    match previous {
+                        Some((_, action)) => {
+                            libc::sigaction(signal, action, ptr::null_mut());
+                        }
+                        None => {
+                            libc::signal(signal, libc::SIG_DFL);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub(super) unsafe fn call(
+        code: &ExecutableBuffer,
+        entry: AssemblyOffset,
+        args: &[usize; 5],
+    ) -> Result<i64, Fault> {
+        install();
+        let trampoline = TRAMPOLINE.get_or_init(assemble);
+        let start = code.ptr(AssemblyOffset(0)) as usize;
+        // Generated code doesn't call generated code, but the state is
+        // restored anyway so that calls can nest.
+        let outer = GUARD.with(|guard| {
+            guard.replace(Some(Guard {
+                start,
+                end: start + code.len(),
+                recover: trampoline.code.ptr(trampoline.recover) as usize,
+            }))
+        });
+        let stack = STACK.with(Cell::get);
+        let run: Entry = mem::transmute(trampoline.code.ptr(trampoline.entry));
+        let value =
+            run(args.as_ptr(), code.ptr(entry), STACK.with(Cell::as_ptr));
+        GUARD.with(|guard| guard.set(outer));
+        STACK.with(|saved| saved.set(stack));
+        match RAISED.with(Cell::take) {
+            Some(raised) => Err(Fault {
+                signal: SIGNALS
+                    .iter()
+                    .find(|(signal, _)| *signal == raised.signal)
+                    .map_or(Signal::Segv, |(_, signal)| *signal),
+                address: raised.address,
+                offset: raised.rip - start,
+                code: code.to_vec(),
+            }),
+            None => Ok(value),
+        }
+    }
+}
+
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+mod guarded {
+    use dynasmrt::{AssemblyOffset, ExecutableBuffer};
+
+    use super::Fault;
+
+    type Entry = extern "sysv64" fn(usize, usize, usize, usize, usize) -> i64;
+
+    pub(super) unsafe fn call(
+        code: &ExecutableBuffer,
+        entry: AssemblyOffset,
+        args: &[usize; 5],
+    ) -> Result<i64, Fault> {
+        let run: Entry = std::mem::transmute(code.ptr(entry));
+        Ok(run(args[0], args[1], args[2], args[3], args[4]))
+    }
+}
+
+#[cfg(all(test, target_os = "linux", target_arch = "x86_64"))]
+mod tests {
+    use super::*;
+    use dynasmrt::x64::Assembler;
+    use dynasmrt::DynasmApi;
+
+    fn assemble(bytes: &[u8]) -> (ExecutableBuffer, AssemblyOffset) {
+        let mut ops = Assembler::new().unwrap();
+        let entry = ops.offset();
+        ops.extend(bytes);
+        (ops.finalize().unwrap(), entry)
+    }
+
+    #[test]
+    fn returns_the_value_of_the_call() {
+        // lea eax, [rdi + rsi]; ret
+        let (code, entry) = assemble(&[0x8d, 0x04, 0x37, 0xc3]);
+        let value = unsafe { call(&code, entry, &[40, 2]) };
+        assert_eq!(value, Ok(42));
+    }
+
+    #[test]
+    fn recovers_from_faults() {
+        // push rbx; mov rbx, 7; xor eax, eax; mov eax, [rax]; pop rbx; ret
+        let (code, entry) = assemble(&[
+            0x53, 0x48, 0xc7, 0xc3, 0x07, 0x00, 0x00, 0x00, 0x31, 0xc0, 0x8b,
+            0x00, 0x5b, 0xc3,
+        ]);
+        let fault = unsafe { call(&code, entry, &[]) }.unwrap_err();
+        assert_eq!(fault.signal, Signal::Segv);
+        assert_eq!(fault.address, 0);
+        assert_eq!(fault.offset, 10);
+        assert_eq!(fault.to_string(), "SIGSEGV at offset 0xa accessing 0x0");
+        assert_eq!(
+            fault.dump(),
+            "0000  53 48 c7 c3 07 00 00 00 31 c0[8b]00 5b c3\n"
+        );
+        // ud2
+        let (code, entry) = assemble(&[0x0f, 0x0b]);
+        let fault = unsafe { call(&code, entry, &[]) }.unwrap_err();
+        assert_eq!(fault.signal, Signal::Ill);
+        assert_eq!(fault.offset, 0);
+        // Calls still work after a fault.
+        let (code, entry) = assemble(&[0x8d, 0x04, 0x37, 0xc3]);
+        assert_eq!(unsafe { call(&code, entry, &[1, 2]) }, Ok(3));
+    }
+}
@@ -0,0 +1,272 @@
+//! Superinstructions for the interpreter.
+//!
+//! Sequences of instructions that show up at the top of most loops are
+//! fused into a single superinstruction when a method is first run, the
+//! interpreter then dispatches once for the whole sequence instead of once
+//! per instruction :
+//!
+//! ```text
+//! iload a; iload b; iadd         -> LoadArith { op: iadd, lhs: a, rhs: b }
+//! iload a; bipush 10; if_icmpge  -> LoadBranch { lhs: a, rhs: 10, .. }
+//! iinc a 1; goto header          -> IncGoto { local: a, constant: 1, .. }
+//! ```
+//!
+//! The second operand of loads and compares is either a local or a small
+//! constant (`iconst_<n>`, `bipush` and `sipush`). Sequences never span a
+//! basic block boundary, only their first instruction may be a branch
+//! target, so a fused sequence is always run from its start.
+use std::collections::{HashMap, HashSet};
+
+use crate::bytecode::OPCode;
+use crate::disasm::{self, DecodedInstruction};
+use crate::jvm::ExceptionEntry;
+
+/// Second operand of a fused arithmetic or compare instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Local(usize),
+    Const(i32),
+}
+
+/// A sequence of instructions executed as one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Superinstruction {
+    /// Push the result of `op`, one of `iadd`, `isub` or `imul`, on the
+    /// local `lhs` and `rhs`.
+    LoadArith {
+        op: OPCode,
+        lhs: usize,
+        rhs: Operand,
+    },
+    /// Compare the local `lhs` with `rhs` using `op`, one of the
+    /// `if_icmp<cond>` instructions at offset `branch`, and jump to
+    /// `target` when the condition holds.
+    LoadBranch {
+        op: OPCode,
+        lhs: usize,
+        rhs: Operand,
+        branch: usize,
+        target: usize,
+    },
+    /// Add `constant` to the local `local` and jump to `target`.
+    IncGoto {
+        local: usize,
+        constant: i32,
+        target: usize,
+    },
+}
+
+/// A superinstruction along with the code it replaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fused {
+    pub superinstruction: Superinstruction,
+    /// Number of instructions fused.
+    pub instructions: usize,
+    /// Offset of the instruction following the sequence.
+    pub next: usize,
+}
+
+/// Returns the superinstructions of `code` keyed by the offset of the
+/// first instruction they replace. Code that doesn't decode isn't fused.
+pub fn fuse(
+    code: &[u8],
+    exception_table: &[ExceptionEntry],
+) -> HashMap<usize, Fused> {
+    let Ok(instructions) = disasm::disassemble(code) else {
+        return HashMap::new();
+    };
+    let targets: HashSet<usize> = instructions
+        .iter()
+        .flat_map(|inst| inst.targets.iter().copied())
+        .chain(
+            exception_table
+                .iter()
+                .map(|entry| usize::from(entry.handler_pc())),
+        )
+        .collect();
+    let mut fused = HashMap::new();
+    let mut index = 0;
+    while index < instructions.len() {
+        let window = &instructions[index..];
+        let Some((superinstruction, count)) = match_sequence(window) else {
+            index += 1;
+            continue;
+        };
+        // Jumping into the middle of the sequence would skip its start.
+        if window[1..count]
+            .iter()
+            .any(|inst| targets.contains(&inst.offset))
+        {
+            index += 1;
+            continue;
+        }
+        let next = instructions
+            .get(index + count)
+            .map_or(code.len(), |inst| inst.offset);
+        fused.insert(
+            window[0].offset,
+            Fused {
+                superinstruction,
+                instructions: count,
+                next,
+            },
+        );
+        index += count;
+    }
+    fused
+}
+
+/// Returns the superinstruction starting `window` and the number of
+/// instructions it replaces.
+fn match_sequence(
+    window: &[DecodedInstruction],
+) -> Option<(Superinstruction, usize)> {
+    match window {
+        [inc, goto, ..]
+            if inc.opcode == OPCode::IInc && goto.opcode == OPCode::Goto =>
+        {
+            let superinstruction = Superinstruction::IncGoto {
+                local: inc.operands[0] as usize,
+                constant: inc.operands[1] as i32,
+                target: goto.targets[0],
+            };
+            Some((superinstruction, 2))
+        }
+        [load, rhs, op, ..] => {
+            let lhs = int_local(load)?;
+            let rhs = operand(rhs)?;
+            let superinstruction = match op.opcode {
+                OPCode::IAdd | OPCode::ISub | OPCode::IMul => {
+                    Superinstruction::LoadArith {
+                        op: op.opcode,
+                        lhs,
+                        rhs,
+                    }
+                }
+                OPCode::IfICmpEq
+                | OPCode::IfICmpNe
+                | OPCode::IfICmpLt
+                | OPCode::IfICmpGe
+                | OPCode::IfICmpGt
+                | OPCode::IfICmpLe => Superinstruction::LoadBranch {
+                    op: op.opcode,
+                    lhs,
+                    rhs,
+                    branch: op.offset,
+                    target: op.targets[0],
+                },
+                _ => return None,
+            };
+            Some((superinstruction, 3))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the local an `iload` reads.
+fn int_local(inst: &DecodedInstruction) -> Option<usize> {
+    match inst.opcode {
+        OPCode::ILoad0 => Some(0),
+        OPCode::ILoad1 => Some(1),
+        OPCode::ILoad2 => Some(2),
+        OPCode::ILoad3 => Some(3),
+        OPCode::ILoad => Some(inst.operands[0] as usize),
+        _ => None,
+    }
+}
+
+/// Returns the operand an `iload` or int constant pushes.
+fn operand(inst: &DecodedInstruction) -> Option<Operand> {
+    let constant = match inst.opcode {
+        OPCode::IconstM1 => -1,
+        OPCode::Iconst0 => 0,
+        OPCode::Iconst1 => 1,
+        OPCode::Iconst2 => 2,
+        OPCode::Iconst3 => 3,
+        OPCode::Iconst4 => 4,
+        OPCode::Iconst5 => 5,
+        OPCode::BiPush | OPCode::SiPush => inst.operands[0] as i32,
+        _ => return int_local(inst).map(Operand::Local),
+    };
+    Some(Operand::Const(constant))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    #[test]
+    fn fuses_loop_headers_and_latches() {
+        // for (int i = 0; i < 100; i++) { sum = sum + i; }
+        let code = assemble(
+            "
+                iconst_0
+                istore_1
+            loop:
+                iload_1
+                bipush 100
+                if_icmpge exit
+                iload_2
+                iload_1
+                iadd
+                istore_2
+                iinc 1 1
+                goto loop
+            exit:
+                return
+            ",
+        )
+        .unwrap();
+        let fused = fuse(&code, &[]);
+        assert_eq!(fused.len(), 3);
+        assert_eq!(
+            fused[&2].superinstruction,
+            Superinstruction::LoadBranch {
+                op: OPCode::IfICmpGe,
+                lhs: 1,
+                rhs: Operand::Const(100),
+                branch: 5,
+                target: 18,
+            }
+        );
+        assert_eq!(fused[&2].next, 8);
+        assert_eq!(
+            fused[&8].superinstruction,
+            Superinstruction::LoadArith {
+                op: OPCode::IAdd,
+                lhs: 2,
+                rhs: Operand::Local(1),
+            }
+        );
+        assert_eq!(
+            fused[&12],
+            Fused {
+                superinstruction: Superinstruction::IncGoto {
+                    local: 1,
+                    constant: 1,
+                    target: 2,
+                },
+                instructions: 2,
+                next: 18,
+            }
+        );
+    }
+
+    #[test]
+    fn does_not_fuse_across_branch_targets() {
+        // The loop jumps back to the second load.
+        let code = assemble(
+            "
+                iload_1
+            loop:
+                iload_2
+                iadd
+                istore_1
+                goto loop
+            ",
+        )
+        .unwrap();
+        assert!(fuse(&code, &[]).is_empty());
+    }
+}
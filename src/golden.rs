@@ -0,0 +1,244 @@
+//! Golden test harness for the Java programs in `support/`.
+//!
+//! Every `Foo.java` that has a sibling `Foo.expected` file is a test case,
+//! sources are compiled with `javac` into a cache directory (only when the
+//! cached class file is missing or older than the source), without `javac`
+//! the checked-in `Foo.class` is used instead. Each case is run under the
+//! interpreter and with the tracing JIT, the captured stdout is compared
+//! against the expected file and the exit code against `Foo.exit`, zero
+//! when the case doesn't have one.
+//!
+//! Setting `COLDBREW_BLESS=1` overwrites the expected files with the
+//! current output of the interpreter instead of comparing them.
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::diff::run_coldbrew;
+
+/// Modes every test case is run in, labeled and whether the JIT is on.
+pub const MODES: [(&str, bool); 2] = [("interpreter", false), ("jit", true)];
+
+/// A single golden test case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoldenCase {
+    /// Path to the Java source.
+    pub source: PathBuf,
+    /// Path to the file holding the expected stdout.
+    pub expected: PathBuf,
+}
+
+impl GoldenCase {
+    /// Returns the class name of the test case.
+    pub fn name(&self) -> String {
+        self.source
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Returns the path of the compiled class in `cache_dir`.
+    pub fn class_file(&self, cache_dir: &Path) -> PathBuf {
+        cache_dir.join(self.name()).with_extension("class")
+    }
+
+    /// Returns the path of the file holding the expected exit code.
+    pub fn exit_code_file(&self) -> PathBuf {
+        self.source.with_extension("exit")
+    }
+
+    /// Returns the expected exit code, zero unless the case has an exit
+    /// code file.
+    /// # Errors
+    /// Returns an error if the exit code file can't be read or doesn't
+    /// hold an integer.
+    pub fn expected_exit_code(&self) -> io::Result<i32> {
+        match fs::read_to_string(self.exit_code_file()) {
+            Ok(code) => code.trim().parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid exit code `{}`", code.trim()),
+                )
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Failure of a single golden test case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoldenFailure {
+    pub name: String,
+    /// Label of the mode the case failed in, see `MODES`.
+    pub mode: String,
+    pub expected: String,
+    pub actual: String,
+    pub expected_exit_code: i32,
+    pub exit_code: i32,
+}
+
+impl fmt::Display for GoldenFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} [{}] (exit code {}, expected {}) :",
+            self.name, self.mode, self.exit_code, self.expected_exit_code
+        )?;
+        writeln!(f, "--- expected\n{}", self.expected)?;
+        writeln!(f, "--- actual\n{}", self.actual)
+    }
+}
+
+/// Returns true if a `javac` executable is available.
+pub fn javac_available() -> bool {
+    Command::new("javac")
+        .arg("-version")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Returns the golden test cases found in `dir` sorted by name.
+/// # Errors
+/// Returns an error if `dir` can't be read.
+pub fn discover(dir: &Path) -> io::Result<Vec<GoldenCase>> {
+    let mut cases = Vec::new();
+    for entry in dir.read_dir()? {
+        let source = entry?.path();
+        if source.extension().is_some_and(|ext| ext == "java") {
+            let expected = source.with_extension("expected");
+            if expected.exists() {
+                cases.push(GoldenCase { source, expected });
+            }
+        }
+    }
+    cases.sort_by(|lhs, rhs| lhs.source.cmp(&rhs.source));
+    Ok(cases)
+}
+
+/// Returns true if the cached class file is missing or older than its
+/// source.
+fn is_stale(case: &GoldenCase, cache_dir: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path)?.modified();
+    match (
+        modified(&case.source),
+        modified(&case.class_file(cache_dir)),
+    ) {
+        (Ok(source), Ok(class_file)) => class_file < source,
+        _ => true,
+    }
+}
+
+/// Compile the stale test cases into `cache_dir` with a single `javac`
+/// invocation.
+/// # Errors
+/// Returns an error if `javac` can't be spawned or fails to compile.
+pub fn compile(cases: &[GoldenCase], cache_dir: &Path) -> io::Result<()> {
+    let stale: Vec<&Path> = cases
+        .iter()
+        .filter(|case| is_stale(case, cache_dir))
+        .map(|case| case.source.as_path())
+        .collect();
+    if stale.is_empty() {
+        return Ok(());
+    }
+    fs::create_dir_all(cache_dir)?;
+    let output = Command::new("javac")
+        .arg("-d")
+        .arg(cache_dir)
+        .args(stale)
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ));
+    }
+    Ok(())
+}
+
+/// Overwrite the expectations of a test case with the output of the
+/// interpreter, the exit code file is only kept for non zero exit codes.
+/// # Errors
+/// Returns an error if the expectations can't be written.
+pub fn bless(case: &GoldenCase, class_dir: &Path) -> io::Result<()> {
+    let outcome = run_coldbrew(&case.class_file(class_dir), false);
+    fs::write(&case.expected, &outcome.stdout)?;
+    if outcome.exit_code != 0 {
+        fs::write(case.exit_code_file(), format!("{}\n", outcome.exit_code))
+    } else if case.exit_code_file().exists() {
+        fs::remove_file(case.exit_code_file())
+    } else {
+        Ok(())
+    }
+}
+
+/// Run a compiled test case in every mode and compare its outcome with the
+/// expectations, returns a failure for every mode that diverged.
+/// # Errors
+/// Returns an error if the expectations can't be read.
+pub fn check(
+    case: &GoldenCase,
+    class_dir: &Path,
+) -> io::Result<Vec<GoldenFailure>> {
+    let expected = fs::read_to_string(&case.expected)?;
+    let expected_exit_code = case.expected_exit_code()?;
+    let mut failures = Vec::new();
+    for (mode, jit_mode) in MODES {
+        let outcome = run_coldbrew(&case.class_file(class_dir), jit_mode);
+        if expected != outcome.stdout || expected_exit_code != outcome.exit_code
+        {
+            failures.push(GoldenFailure {
+                name: case.name(),
+                mode: mode.to_string(),
+                expected: expected.clone(),
+                actual: outcome.stdout,
+                expected_exit_code,
+                exit_code: outcome.exit_code,
+            });
+        }
+    }
+    Ok(failures)
+}
+
+/// Compile and check every golden test case in `dir`, returns the failures.
+/// Without `javac` the class files checked in next to the sources are run.
+/// # Errors
+/// Returns an error if the test cases can't be discovered or compiled.
+pub fn run(dir: &Path, cache_dir: &Path) -> io::Result<Vec<GoldenFailure>> {
+    let cases = discover(dir)?;
+    let class_dir = if javac_available() {
+        compile(&cases, cache_dir)?;
+        cache_dir
+    } else {
+        dir
+    };
+    let mut failures = Vec::new();
+    for case in &cases {
+        if env::var_os("COLDBREW_BLESS").is_some() {
+            bless(case, class_dir)?;
+        }
+        failures.extend(check(case, class_dir)?);
+    }
+    Ok(failures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn support_programs_match_expected_output() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let failures = run(
+            &root.join("support/tests"),
+            &root.join("target/golden/tests"),
+        )
+        .unwrap();
+        let report: Vec<String> =
+            failures.iter().map(ToString::to_string).collect();
+        assert!(failures.is_empty(), "{}", report.join("\n"));
+    }
+}
@@ -0,0 +1,900 @@
+//! Objects allocated by the interpreter.
+//!
+//! Values refer to objects through handles into the heap, handle 0 is
+//! `null`. The heap holds the objects the runtime materializes itself,
+//! strings loaded from the constant pool, `java.lang.Class` instances,
+//! a single one per class, method types and handles, and the arrays created
+//! by the program. The few library classes we support natively, boxed
+//! primitives, `StringBuilder`, `ArrayList` and `HashMap`, live here as
+//! well, next to the instances of the program's own classes. Throwables
+//! are instances too, their message is kept in the `MESSAGE_FIELD` field.
+use std::collections::HashMap;
+use std::mem;
+
+use crate::jvm::{
+    REF_GET_FIELD, REF_GET_STATIC, REF_INVOKE_STATIC, REF_NEW_INVOKE_SPECIAL,
+    REF_PUT_FIELD, REF_PUT_STATIC,
+};
+use crate::runtime::Value;
+
+/// Field of throwables holding their message, `null` if they have none.
+pub const MESSAGE_FIELD: &str = "detailMessage";
+
+/// Bytes every object takes besides its contents, see `Object::size`.
+const OBJECT_HEADER: usize = 16;
+
+/// The library throwables the runtime raises or programs can create and
+/// extend, with their superclass. `java/lang/Throwable` is the root.
+const THROWABLES: &[(&str, &str)] = &[
+    ("java/lang/Exception", "java/lang/Throwable"),
+    ("java/lang/Error", "java/lang/Throwable"),
+    ("java/lang/RuntimeException", "java/lang/Exception"),
+    (
+        "java/lang/ArithmeticException",
+        "java/lang/RuntimeException",
+    ),
+    (
+        "java/lang/ArrayStoreException",
+        "java/lang/RuntimeException",
+    ),
+    ("java/lang/ClassCastException", "java/lang/RuntimeException"),
+    (
+        "java/lang/IllegalArgumentException",
+        "java/lang/RuntimeException",
+    ),
+    (
+        "java/lang/IllegalStateException",
+        "java/lang/RuntimeException",
+    ),
+    (
+        "java/lang/IndexOutOfBoundsException",
+        "java/lang/RuntimeException",
+    ),
+    (
+        "java/lang/ArrayIndexOutOfBoundsException",
+        "java/lang/IndexOutOfBoundsException",
+    ),
+    (
+        "java/lang/NegativeArraySizeException",
+        "java/lang/RuntimeException",
+    ),
+    (
+        "java/lang/NullPointerException",
+        "java/lang/RuntimeException",
+    ),
+    (
+        "java/lang/UnsupportedOperationException",
+        "java/lang/RuntimeException",
+    ),
+    (
+        "java/util/NoSuchElementException",
+        "java/lang/RuntimeException",
+    ),
+    (
+        "java/lang/invoke/WrongMethodTypeException",
+        "java/lang/RuntimeException",
+    ),
+    ("java/lang/LinkageError", "java/lang/Error"),
+    (
+        "java/lang/IncompatibleClassChangeError",
+        "java/lang/LinkageError",
+    ),
+    (
+        "java/lang/AbstractMethodError",
+        "java/lang/IncompatibleClassChangeError",
+    ),
+    (
+        "java/lang/NoSuchMethodError",
+        "java/lang/IncompatibleClassChangeError",
+    ),
+];
+
+/// An object on the heap.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Object {
+    /// Instance of `java.lang.String`.
+    String(String),
+    /// Instance of `java.lang.Class` for the class with this binary name,
+    /// array classes use their descriptor e.g `[I` and primitive types
+    /// their keyword e.g `int`.
+    Class(String),
+    /// Array of the type with this descriptor e.g `I`, elements of
+    /// `boolean`, `byte`, `char` and `short` arrays are kept narrowed to
+    /// their type.
+    Array {
+        component: String,
+        elements: Vec<Value>,
+    },
+    /// Instance of `java.lang.invoke.MethodType` with this descriptor.
+    MethodType(String),
+    /// Instance of `java.lang.invoke.MethodHandle` of kind `kind`, one of
+    /// the `REF_` constants, on a member.
+    MethodHandle {
+        kind: u8,
+        class: String,
+        name: String,
+        descriptor: String,
+    },
+    /// Instance of the primitive wrapper `class` e.g `java/lang/Integer`
+    /// holding `value`.
+    Boxed { class: String, value: Value },
+    /// Instance of `java.util.ArrayList`.
+    ArrayList(Vec<Value>),
+    /// Instance of `java.util.HashMap`, entries are kept in insertion order
+    /// and `capacity` is the size of the table a JVM would have, together
+    /// they give the iteration order, see `Heap::map_entries`.
+    HashMap {
+        entries: Vec<(Value, Value)>,
+        capacity: usize,
+    },
+    /// Instance of `java.util.Iterator` over the list `list`, `next` is the
+    /// index of the next element.
+    Iterator { list: Value, next: usize },
+    /// Instance of `java.lang.StringBuilder` holding its contents.
+    StringBuilder(String),
+    /// Instance of the program class `class`, fields are keyed by name and
+    /// laid out in the order the class and its superclasses declare them,
+    /// see `Heap::new_instance`.
+    Instance {
+        class: String,
+        fields: Vec<(String, Value)>,
+    },
+}
+
+impl Object {
+    /// Returns the binary name of the object's class.
+    pub fn class_name(&self) -> String {
+        match self {
+            Self::String(_) => "java/lang/String".to_string(),
+            Self::Class(_) => "java/lang/Class".to_string(),
+            Self::Array { component, .. } => format!("[{component}"),
+            Self::MethodType(_) => "java/lang/invoke/MethodType".to_string(),
+            Self::MethodHandle { .. } => {
+                "java/lang/invoke/MethodHandle".to_string()
+            }
+            Self::Boxed { class, .. } => class.clone(),
+            Self::ArrayList(_) => "java/util/ArrayList".to_string(),
+            Self::HashMap { .. } => "java/util/HashMap".to_string(),
+            Self::Iterator { .. } => "java/util/Iterator".to_string(),
+            Self::StringBuilder(_) => "java/lang/StringBuilder".to_string(),
+            Self::Instance { class, .. } => class.clone(),
+        }
+    }
+
+    /// Returns an estimate of the bytes the object takes, a header followed
+    /// by its values and the bytes of its strings.
+    pub fn size(&self) -> usize {
+        let value = mem::size_of::<Value>();
+        let contents = match self {
+            Self::String(string)
+            | Self::Class(string)
+            | Self::MethodType(string)
+            | Self::StringBuilder(string) => string.len(),
+            Self::Array { elements, .. } | Self::ArrayList(elements) => {
+                elements.len() * value
+            }
+            Self::MethodHandle {
+                class,
+                name,
+                descriptor,
+                ..
+            } => class.len() + name.len() + descriptor.len(),
+            Self::Boxed { .. } | Self::Iterator { .. } => value,
+            Self::HashMap { entries, .. } => entries.len() * 2 * value,
+            Self::Instance { fields, .. } => fields.len() * value,
+        };
+        OBJECT_HEADER + contents
+    }
+}
+
+/// Objects of a run, indexed by handle.
+#[derive(Debug, Clone, Default)]
+pub struct Heap {
+    objects: Vec<Object>,
+    // Handles of the `Class` objects keyed by class name.
+    classes: HashMap<String, u32>,
+    // Handles of the interned strings keyed by content.
+    strings: HashMap<String, u32>,
+    // Handles of the method types keyed by descriptor.
+    method_types: HashMap<String, u32>,
+    // Handles of the method handles keyed by kind and member.
+    method_handles: HashMap<(u8, String, String, String), u32>,
+    // Estimated bytes allocated so far, see `Object::size`.
+    size: usize,
+    // Bytes the heap may grow to, unbounded when unset.
+    limit: Option<usize>,
+    // Set when an allocation went past the limit.
+    exceeded: bool,
+}
+
+impl Heap {
+    /// Build an empty heap.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build a heap holding `objects`, handles are given in order starting
+    /// at 1. Strings and classes are interned again.
+    pub fn from_objects(objects: Vec<Object>) -> Self {
+        let mut heap = Self::new();
+        for object in objects {
+            let handle = heap.objects.len() as u32 + 1;
+            match &object {
+                Object::String(string) => {
+                    heap.strings.entry(string.clone()).or_insert(handle);
+                }
+                Object::Class(name) => {
+                    heap.classes.entry(name.clone()).or_insert(handle);
+                }
+                Object::MethodType(descriptor) => {
+                    heap.method_types
+                        .entry(descriptor.clone())
+                        .or_insert(handle);
+                }
+                Object::MethodHandle {
+                    kind,
+                    class,
+                    name,
+                    descriptor,
+                } => {
+                    let key = (
+                        *kind,
+                        class.clone(),
+                        name.clone(),
+                        descriptor.clone(),
+                    );
+                    heap.method_handles.entry(key).or_insert(handle);
+                }
+                Object::Array { .. }
+                | Object::Boxed { .. }
+                | Object::ArrayList(_)
+                | Object::HashMap { .. }
+                | Object::Iterator { .. }
+                | Object::StringBuilder(_)
+                | Object::Instance { .. } => {}
+            }
+            heap.size += object.size();
+            heap.objects.push(object);
+        }
+        heap
+    }
+
+    /// Returns the objects in handle order.
+    pub fn objects(&self) -> &[Object] {
+        &self.objects
+    }
+
+    /// Bound the estimated size of the heap to `limit` bytes, allocations
+    /// past it are reported by `take_exceeded`.
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+    }
+
+    /// Returns the estimated bytes allocated so far, objects are counted
+    /// at their size when they were allocated.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns true if an allocation went past the limit since the last
+    /// call. The object is allocated anyway, arrays without their elements,
+    /// and the runtime fails the instruction that allocated it.
+    pub fn take_exceeded(&mut self) -> bool {
+        mem::take(&mut self.exceeded)
+    }
+
+    /// Count `bytes` against the limit, returns false if they don't fit.
+    fn charge(&mut self, bytes: usize) -> bool {
+        self.size = self.size.saturating_add(bytes);
+        let fits = self.limit.is_none_or(|limit| self.size <= limit);
+        self.exceeded |= !fits;
+        fits
+    }
+
+    /// Allocate `object` and return a reference to it.
+    pub fn alloc(&mut self, object: Object) -> Value {
+        self.charge(object.size());
+        self.objects.push(object);
+        Value::Reference(self.objects.len() as u32)
+    }
+
+    /// Returns the object `reference` points to, `None` for `null` and
+    /// values that aren't references.
+    pub fn get(&self, reference: Value) -> Option<&Object> {
+        match reference {
+            Value::Reference(handle) => {
+                self.objects.get((handle as usize).checked_sub(1)?)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the object `reference` points to for mutation.
+    pub fn get_mut(&mut self, reference: Value) -> Option<&mut Object> {
+        match reference {
+            Value::Reference(handle) => {
+                self.objects.get_mut((handle as usize).checked_sub(1)?)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the string `reference` points to.
+    pub fn string(&self, reference: Value) -> Option<&str> {
+        match self.get(reference)? {
+            Object::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// Returns the elements of the array `reference` points to.
+    pub fn array(&self, reference: Value) -> Option<&[Value]> {
+        match self.get(reference)? {
+            Object::Array { elements, .. } => Some(elements),
+            _ => None,
+        }
+    }
+
+    /// Returns the mutable elements of the array `reference` points to.
+    pub fn array_mut(&mut self, reference: Value) -> Option<&mut [Value]> {
+        match self.get_mut(reference)? {
+            Object::Array { elements, .. } => Some(elements),
+            _ => None,
+        }
+    }
+
+    /// Allocate an array of `length` default values of the type with
+    /// descriptor `component`.
+    pub fn new_array(&mut self, component: &str, length: usize) -> Value {
+        // The elements are only created if they fit.
+        let bytes = length.saturating_mul(mem::size_of::<Value>());
+        let length = if self.charge(OBJECT_HEADER.saturating_add(bytes)) {
+            length
+        } else {
+            0
+        };
+        self.objects.push(Object::Array {
+            component: component.to_string(),
+            elements: vec![default_value(component); length],
+        });
+        Value::Reference(self.objects.len() as u32)
+    }
+
+    /// Allocate an instance of `class` with `fields`, pairs of field name
+    /// and descriptor, set to their default values.
+    pub fn new_instance(
+        &mut self,
+        class: &str,
+        fields: &[(&str, &str)],
+    ) -> Value {
+        self.alloc(Object::Instance {
+            class: class.to_string(),
+            fields: fields
+                .iter()
+                .map(|(name, descriptor)| {
+                    (name.to_string(), default_value(descriptor))
+                })
+                .collect(),
+        })
+    }
+
+    /// Allocate a throwable of class `class` with `fields`, see
+    /// `new_instance`, and the message `message`.
+    pub fn new_throwable(
+        &mut self,
+        class: &str,
+        fields: &[(&str, &str)],
+        message: Option<&str>,
+    ) -> Value {
+        let mut fields = fields.to_vec();
+        fields.push((MESSAGE_FIELD, "Ljava/lang/String;"));
+        let throwable = self.new_instance(class, &fields);
+        if let Some(message) = message {
+            let message = self.intern(message);
+            self.set_field(throwable, MESSAGE_FIELD, message);
+        }
+        throwable
+    }
+
+    /// Returns the field `name` of the instance `reference` points to, the
+    /// default value of `descriptor` until it's stored. `None` if
+    /// `reference` isn't an instance.
+    pub fn field(
+        &self,
+        reference: Value,
+        name: &str,
+        descriptor: &str,
+    ) -> Option<Value> {
+        let Object::Instance { fields, .. } = self.get(reference)? else {
+            return None;
+        };
+        let value = fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map_or_else(|| default_value(descriptor), |(_, value)| *value);
+        Some(value)
+    }
+
+    /// Store `value` in the field `name` of the instance `reference` points
+    /// to, returns false if it isn't an instance.
+    pub fn set_field(
+        &mut self,
+        reference: Value,
+        name: &str,
+        value: Value,
+    ) -> bool {
+        let Some(Object::Instance { fields, .. }) = self.get_mut(reference)
+        else {
+            return false;
+        };
+        match fields.iter_mut().find(|(field, _)| field == name) {
+            Some((_, slot)) => *slot = value,
+            None => fields.push((name.to_string(), value)),
+        }
+        true
+    }
+
+    /// Returns the field in slot `slot` of the instance `reference` points
+    /// to, slots follow the order of the fields `new_instance` was given.
+    /// `None` if `reference` isn't an instance or has no such slot.
+    pub fn field_at(&self, reference: Value, slot: usize) -> Option<Value> {
+        let Object::Instance { fields, .. } = self.get(reference)? else {
+            return None;
+        };
+        fields.get(slot).map(|(_, value)| *value)
+    }
+
+    /// Store `value` in slot `slot` of the instance `reference` points to,
+    /// see `field_at`. Returns false if it isn't an instance or has no such
+    /// slot.
+    pub fn set_field_at(
+        &mut self,
+        reference: Value,
+        slot: usize,
+        value: Value,
+    ) -> bool {
+        let Some(Object::Instance { fields, .. }) = self.get_mut(reference)
+        else {
+            return false;
+        };
+        match fields.get_mut(slot) {
+            Some((_, field)) => {
+                *field = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns a reference to the `MethodType` with descriptor
+    /// `descriptor`, equal types share an object.
+    pub fn method_type(&mut self, descriptor: &str) -> Value {
+        if let Some(handle) = self.method_types.get(descriptor) {
+            return Value::Reference(*handle);
+        }
+        let reference = self.alloc(Object::MethodType(descriptor.to_string()));
+        if let Value::Reference(handle) = reference {
+            self.method_types.insert(descriptor.to_string(), handle);
+        }
+        reference
+    }
+
+    /// Returns a reference to the `MethodHandle` of kind `kind` on the
+    /// member `class.name:descriptor`, equal handles share an object.
+    pub fn method_handle(
+        &mut self,
+        kind: u8,
+        class: &str,
+        name: &str,
+        descriptor: &str,
+    ) -> Value {
+        let key = (
+            kind,
+            class.to_string(),
+            name.to_string(),
+            descriptor.to_string(),
+        );
+        if let Some(handle) = self.method_handles.get(&key) {
+            return Value::Reference(*handle);
+        }
+        let reference = self.alloc(Object::MethodHandle {
+            kind,
+            class: key.1.clone(),
+            name: key.2.clone(),
+            descriptor: key.3.clone(),
+        });
+        if let Value::Reference(handle) = reference {
+            self.method_handles.insert(key, handle);
+        }
+        reference
+    }
+
+    /// Returns what `String.valueOf` gives for `reference`.
+    pub fn java_string(&self, reference: Value) -> String {
+        match self.get(reference) {
+            Some(Object::String(string) | Object::StringBuilder(string)) => {
+                string.clone()
+            }
+            Some(Object::Class(name)) if is_primitive(name) => name.clone(),
+            Some(Object::Class(name)) => format!("class {}", java_name(name)),
+            Some(Object::MethodType(descriptor)) => {
+                method_type_name(descriptor)
+                    .unwrap_or_else(|| descriptor.clone())
+            }
+            Some(Object::MethodHandle {
+                kind,
+                class,
+                descriptor,
+                ..
+            }) => {
+                let handle_type = handle_type(*kind, class, descriptor);
+                let name =
+                    method_type_name(&handle_type).unwrap_or(handle_type);
+                format!("MethodHandle{name}")
+            }
+            Some(Object::Boxed { class, value }) => match class.as_str() {
+                "java/lang/Boolean" => (*value != Value::Int(0)).to_string(),
+                "java/lang/Character" => match value {
+                    Value::Int(code) => char::from_u32(*code as u32)
+                        .unwrap_or(char::REPLACEMENT_CHARACTER)
+                        .to_string(),
+                    _ => value.to_string(),
+                },
+                _ => value.to_string(),
+            },
+            Some(Object::ArrayList(elements)) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| self.java_string(*element))
+                    .collect::<Vec<_>>();
+                format!("[{}]", elements.join(", "))
+            }
+            Some(Object::HashMap { .. }) => {
+                let entries = self
+                    .map_entries(reference)
+                    .into_iter()
+                    .map(|(key, value)| {
+                        let key = self.java_string(key);
+                        format!("{key}={}", self.java_string(value))
+                    })
+                    .collect::<Vec<_>>();
+                format!("{{{}}}", entries.join(", "))
+            }
+            Some(object @ (Object::Array { .. } | Object::Iterator { .. })) => {
+                format!("{}{reference}", java_name(&object.class_name()))
+            }
+            // `Throwable.toString` names the class and the message.
+            Some(Object::Instance { class, fields })
+                if fields.iter().any(|(name, _)| name == MESSAGE_FIELD) =>
+            {
+                let message = self.field(reference, MESSAGE_FIELD, "L");
+                match message.and_then(|message| self.string(message)) {
+                    Some(message) => format!("{}: {message}", java_name(class)),
+                    None => java_name(class),
+                }
+            }
+            // `Object.toString`, overrides are dispatched by the runtime.
+            Some(Object::Instance { class, .. }) => {
+                format!("{}@{:x}", java_name(class), self.hash_code(reference))
+            }
+            None => reference.to_string(),
+        }
+    }
+
+    /// Returns what `String.valueOf` gives for `value` of the type with
+    /// descriptor `kind`, `char` and `boolean` values are held as ints.
+    pub fn value_string(&self, value: Value, kind: char) -> String {
+        match (kind, value.convert('C')) {
+            ('C', Some(Value::Int(code))) => char::from_u32(code as u32)
+                .unwrap_or(char::REPLACEMENT_CHARACTER)
+                .to_string(),
+            ('Z', _) => (value.convert('Z') == Some(Value::Int(1))).to_string(),
+            ('L' | '[', _) => self.java_string(value),
+            _ => value.to_string(),
+        }
+    }
+
+    /// Returns what `hashCode` gives for `reference`, strings and boxed
+    /// primitives hash their content like the library does, other objects
+    /// hash their handle.
+    pub fn hash_code(&self, reference: Value) -> i32 {
+        match self.get(reference) {
+            Some(Object::String(string)) => {
+                string.encode_utf16().fold(0i32, |h, c| {
+                    h.wrapping_mul(31).wrapping_add(i32::from(c))
+                })
+            }
+            Some(Object::Boxed { class, value }) => {
+                match (class.as_str(), value) {
+                    ("java/lang/Boolean", Value::Int(0)) => 1237,
+                    ("java/lang/Boolean", _) => 1231,
+                    (_, Value::Int(v)) => *v,
+                    (_, Value::Long(v)) => (v ^ v >> 32) as i32,
+                    (_, Value::Float(v)) => v.to_bits() as i32,
+                    (_, Value::Double(v)) => {
+                        let bits = v.to_bits();
+                        (bits ^ bits >> 32) as i32
+                    }
+                    (_, Value::Reference(handle)) => *handle as i32,
+                }
+            }
+            _ => match reference {
+                Value::Reference(handle) => handle as i32,
+                _ => 0,
+            },
+        }
+    }
+
+    /// Returns what `a.equals(b)` gives, strings and boxed primitives
+    /// compare their content and other objects their identity.
+    pub fn equals(&self, a: Value, b: Value) -> bool {
+        if a == b {
+            return true;
+        }
+        match (self.get(a), self.get(b)) {
+            (Some(Object::String(a)), Some(Object::String(b))) => a == b,
+            (
+                Some(Object::Boxed { class, value }),
+                Some(Object::Boxed {
+                    class: other,
+                    value: other_value,
+                }),
+            ) => class == other && value == other_value,
+            _ => false,
+        }
+    }
+
+    /// Returns the entries of the map `reference` points to in the order a
+    /// JVM iterates them, by bucket then insertion order.
+    pub fn map_entries(&self, reference: Value) -> Vec<(Value, Value)> {
+        let Some(Object::HashMap { entries, capacity }) = self.get(reference)
+        else {
+            return vec![];
+        };
+        let mut entries = entries.clone();
+        entries.sort_by_key(|(key, _)| {
+            let hash = self.hash_code(*key);
+            (hash ^ ((hash as u32) >> 16) as i32) as usize & (capacity - 1)
+        });
+        entries
+    }
+
+    /// Returns a reference to the string `string`, equal strings share an
+    /// object like string literals do.
+    pub fn intern(&mut self, string: &str) -> Value {
+        if let Some(handle) = self.strings.get(string) {
+            return Value::Reference(*handle);
+        }
+        let reference = self.alloc(Object::String(string.to_string()));
+        if let Value::Reference(handle) = reference {
+            self.strings.insert(string.to_string(), handle);
+        }
+        reference
+    }
+
+    /// Returns a reference to the `Class` object of the class called
+    /// `name`, it is created the first time it's asked for.
+    pub fn class_object(&mut self, name: &str) -> Value {
+        if let Some(handle) = self.classes.get(name) {
+            return Value::Reference(*handle);
+        }
+        let reference = self.alloc(Object::Class(name.to_string()));
+        if let Value::Reference(handle) = reference {
+            self.classes.insert(name.to_string(), handle);
+        }
+        reference
+    }
+}
+
+/// Returns the value fields and array elements of the type with
+/// descriptor `descriptor` start with.
+pub fn default_value(descriptor: &str) -> Value {
+    match descriptor {
+        "J" => Value::Long(0),
+        "F" => Value::Float(0.),
+        "D" => Value::Double(0.),
+        _ if descriptor.starts_with(['L', '[']) => Value::NULL,
+        _ => Value::Int(0),
+    }
+}
+
+/// Returns the superclass of the library throwable `class`, `None` for
+/// `java/lang/Throwable` and the classes that aren't library throwables.
+pub fn throwable_superclass(class: &str) -> Option<&'static str> {
+    THROWABLES
+        .iter()
+        .find(|(throwable, _)| *throwable == class)
+        .map(|(_, superclass)| *superclass)
+}
+
+/// Returns the name `Class.getName` reports for the class called `name`,
+/// packages are separated with dots.
+pub fn java_name(name: &str) -> String {
+    name.replace('/', ".")
+}
+
+/// Returns the name `Class.getSimpleName` reports for the class called
+/// `name`, array names are their component's followed by `[]`.
+pub fn simple_name(name: &str) -> String {
+    if name.starts_with('[') {
+        return type_name(name).unwrap_or_else(|| name.to_string());
+    }
+    let name = name.rsplit('/').next().unwrap_or(name);
+    name.rsplit('$').next().unwrap_or(name).to_string()
+}
+
+/// Returns the simple name of the type with field descriptor `descriptor`
+/// e.g `int[]` for `[I`.
+pub fn type_name(descriptor: &str) -> Option<String> {
+    let name = match descriptor {
+        "Z" => "boolean",
+        "B" => "byte",
+        "C" => "char",
+        "S" => "short",
+        "I" => "int",
+        "J" => "long",
+        "F" => "float",
+        "D" => "double",
+        "V" => "void",
+        _ => {
+            if let Some(component) = descriptor.strip_prefix('[') {
+                return Some(type_name(component)? + "[]");
+            }
+            let class = descriptor.strip_prefix('L')?.strip_suffix(';')?;
+            return Some(simple_name(class));
+        }
+    };
+    Some(name.to_string())
+}
+
+/// Returns the name `MethodType.toString` gives to the method descriptor
+/// `descriptor` e.g `(String,int)void`.
+pub fn method_type_name(descriptor: &str) -> Option<String> {
+    let (args, ret) = parameter_types(descriptor)?;
+    let names = args
+        .into_iter()
+        .map(type_name)
+        .collect::<Option<Vec<_>>>()?;
+    Some(format!("({}){}", names.join(","), type_name(ret)?))
+}
+
+/// Split the method descriptor `descriptor` into the descriptors of its
+/// parameters and return type.
+pub fn parameter_types(descriptor: &str) -> Option<(Vec<&str>, &str)> {
+    let (mut rest, ret) = descriptor.strip_prefix('(')?.split_once(')')?;
+    let mut args = vec![];
+    while !rest.is_empty() {
+        let dims = rest.len() - rest.trim_start_matches('[').len();
+        let end = match rest[dims..].chars().next()? {
+            'L' => dims + rest[dims..].find(';')? + 1,
+            _ => dims + 1,
+        };
+        args.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    Some((args, ret))
+}
+
+/// Returns the descriptor of the type of a method handle of kind `kind` on
+/// a member of `class` with descriptor `descriptor`, receivers are the
+/// first argument.
+pub fn handle_type(kind: u8, class: &str, descriptor: &str) -> String {
+    let receiver = format!("L{class};");
+    match kind {
+        REF_GET_FIELD => format!("({receiver}){descriptor}"),
+        REF_GET_STATIC => format!("(){descriptor}"),
+        REF_PUT_FIELD => format!("({receiver}{descriptor})V"),
+        REF_PUT_STATIC => format!("({descriptor})V"),
+        REF_INVOKE_STATIC => descriptor.to_string(),
+        REF_NEW_INVOKE_SPECIAL => match descriptor.rsplit_once(')') {
+            Some((args, _)) => format!("{args}){receiver}"),
+            None => descriptor.to_string(),
+        },
+        _ => descriptor.replacen('(', &format!("({receiver}"), 1),
+    }
+}
+
+/// Returns true if `name` is the name of a primitive type's `Class`.
+pub fn is_primitive(name: &str) -> bool {
+    matches!(
+        name,
+        "boolean"
+            | "byte"
+            | "char"
+            | "short"
+            | "int"
+            | "long"
+            | "float"
+            | "double"
+            | "void"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interns_strings_and_classes() {
+        let mut heap = Heap::new();
+        let hello = heap.intern("hello");
+        assert_eq!(heap.intern("hello"), hello);
+        let class = heap.class_object("java/lang/String");
+        assert_ne!(class, hello);
+        assert_eq!(heap.class_object("java/lang/String"), class);
+        assert_eq!(heap.string(hello), Some("hello"));
+        assert_eq!(heap.get(Value::NULL), None);
+
+        let restored = Heap::from_objects(heap.objects().to_vec());
+        assert_eq!(restored.clone().intern("hello"), hello);
+        assert_eq!(
+            restored.get(class),
+            Some(&Object::Class("java/lang/String".to_string()))
+        );
+    }
+
+    #[test]
+    fn keeps_instance_fields() {
+        let mut heap = Heap::new();
+        let point = heap.alloc(Object::Instance {
+            class: "geometry/Point".to_string(),
+            fields: vec![],
+        });
+        assert_eq!(heap.field(point, "x", "D"), Some(Value::Double(0.)));
+        assert_eq!(heap.field(point, "next", "LPoint;"), Some(Value::NULL));
+        assert!(heap.set_field(point, "x", Value::Double(1.5)));
+        assert!(heap.set_field(point, "x", Value::Double(2.5)));
+        assert_eq!(heap.field(point, "x", "D"), Some(Value::Double(2.5)));
+        // Declared fields start with their default values.
+        let point =
+            heap.new_instance("geometry/Point", &[("x", "D"), ("y", "J")]);
+        assert_eq!(
+            heap.get(point),
+            Some(&Object::Instance {
+                class: "geometry/Point".to_string(),
+                fields: vec![
+                    ("x".to_string(), Value::Double(0.)),
+                    ("y".to_string(), Value::Long(0)),
+                ],
+            })
+        );
+        // Slots index the fields in declaration order.
+        assert!(heap.set_field_at(point, 1, Value::Long(7)));
+        assert_eq!(heap.field(point, "y", "J"), Some(Value::Long(7)));
+        assert_eq!(heap.field_at(point, 0), Some(Value::Double(0.)));
+        assert!(!heap.set_field_at(point, 2, Value::Int(1)));
+        assert_eq!(heap.field_at(point, 2), None);
+        let hello = heap.intern("hello");
+        assert!(!heap.set_field(hello, "x", Value::Int(1)));
+        assert_eq!(heap.field(Value::NULL, "x", "I"), None);
+        assert_eq!(heap.field_at(hello, 0), None);
+        // `Object.toString` names the class and the hash code in hex.
+        let Value::Reference(handle) = point else {
+            unreachable!()
+        };
+        assert_eq!(
+            heap.java_string(point),
+            format!("geometry.Point@{handle:x}")
+        );
+    }
+
+    #[test]
+    fn can_name_classes() {
+        assert_eq!(java_name("java/util/Map$Entry"), "java.util.Map$Entry");
+        assert_eq!(java_name("[Ljava/lang/String;"), "[Ljava.lang.String;");
+        assert_eq!(simple_name("java/util/Map$Entry"), "Entry");
+        assert_eq!(simple_name("[[I"), "int[][]");
+        assert_eq!(simple_name("[Ljava/lang/String;"), "String[]");
+        assert_eq!(
+            method_type_name("([Ljava/lang/String;IJ)V").as_deref(),
+            Some("(String[],int,long)void")
+        );
+        assert_eq!(
+            handle_type(5, "java/lang/Object", "()I"),
+            "(Ljava/lang/Object;)I"
+        );
+        assert_eq!(handle_type(8, "Point", "(II)V"), "(II)LPoint;");
+        assert!(is_primitive("int"));
+        assert!(!is_primitive("java/lang/Integer"));
+    }
+}
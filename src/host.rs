@@ -0,0 +1,137 @@
+//! Calls from native traces back into the runtime.
+//!
+//! Native traces reach runtime state they can't model in registers, such as
+//! the heap, through helpers written in Rust. The helpers follow the System
+//! V AMD64 calling convention, they take the `HostContext` of the running
+//! trace in `rdi` and a single argument in `rsi` and return their result in
+//! `rax`. Their addresses are looked up in a table when a trace is compiled
+//! and baked into the generated code.
+//!
+//! The registers the allocator hands out that the callee may clobber (`rax`,
+//! `rcx` and `r8` to `r11`) are saved around the call by the trace, `rdi` and
+//! `rsi` are reloaded from the native frame after it. A helper returning
+//! `THROW` makes the trace leave through a stub raising the exception of the
+//! instruction the helper implements.
+use crate::heap::Heap;
+use crate::runtime::Value;
+
+/// Value returned by a helper when the instruction it implements throws.
+pub const THROW: i64 = -1;
+
+/// `HostContext` is the runtime state native traces hand to helpers, a
+/// pointer to it is the third argument of a native trace.
+#[repr(C)]
+#[derive(Debug)]
+pub struct HostContext {
+    heap: *mut Heap,
+    // Argument of the last helper that returned `THROW`.
+    operand: i64,
+}
+
+impl HostContext {
+    /// Create a context for a trace running on `heap`, the heap must outlive
+    /// the trace.
+    pub fn new(heap: &mut Heap) -> Self {
+        Self { heap, operand: 0 }
+    }
+
+    /// Returns the argument of the last helper that threw, the exception
+    /// is reported with it e.g the negative length of an array.
+    pub fn operand(&self) -> i64 {
+        self.operand
+    }
+}
+
+/// Signature of the helpers native traces call.
+pub type HostFn = extern "C" fn(*mut HostContext, i64) -> i64;
+
+/// Runtime helpers native traces can call, indexes into the helper table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Helper {
+    /// Allocate an `int[]` of the given length, the slow path of `newarray`.
+    /// Returns the reference to the array, `THROW` if the length is
+    /// negative.
+    NewIntArray,
+    /// Returns the length of the array the reference points to, `THROW` if
+    /// it's null or not an array.
+    ArrayLength,
+}
+
+/// Addresses of the helpers indexed by `Helper`.
+const HELPERS: [HostFn; 2] = [new_int_array, array_length];
+
+impl Helper {
+    /// Returns the address of the helper, baked into the traces calling it.
+    pub fn address(self) -> usize {
+        HELPERS[self as usize] as usize
+    }
+
+    /// Call the helper from Rust like a native trace would.
+    pub fn call(self, context: &mut HostContext, arg: i64) -> i64 {
+        HELPERS[self as usize](context, arg)
+    }
+}
+
+/// Returns the heap of `context`.
+///
+/// # Safety
+/// `context` must point to a live `HostContext` whose heap isn't borrowed
+/// elsewhere for the duration of the helper.
+unsafe fn heap<'a>(context: *mut HostContext) -> &'a mut Heap {
+    &mut *(*context).heap
+}
+
+/// Returns `THROW` after recording `operand` as the argument of the
+/// failed call.
+///
+/// # Safety
+/// Same as `heap`.
+unsafe fn throw(context: *mut HostContext, operand: i64) -> i64 {
+    (*context).operand = operand;
+    THROW
+}
+
+extern "C" fn new_int_array(context: *mut HostContext, length: i64) -> i64 {
+    let Ok(size) = usize::try_from(length) else {
+        return unsafe { throw(context, length) };
+    };
+    // Traces pass the context `JitCache::execute` created for them.
+    match unsafe { heap(context) }.new_array("I", size) {
+        Value::Reference(reference) => i64::from(reference),
+        _ => unsafe { throw(context, length) },
+    }
+}
+
+extern "C" fn array_length(context: *mut HostContext, reference: i64) -> i64 {
+    let array = u32::try_from(reference).ok().and_then(|array| {
+        unsafe { heap(context) }
+            .array(Value::Reference(array))
+            .map(<[Value]>::len)
+    });
+    match array {
+        Some(length) => length as i64,
+        None => unsafe { throw(context, reference) },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn helpers_reach_the_heap() {
+        let mut heap = Heap::new();
+        let mut context = HostContext::new(&mut heap);
+        let array = Helper::NewIntArray.call(&mut context, 7);
+        assert!(array > 0);
+        assert_eq!(Helper::ArrayLength.call(&mut context, array), 7);
+        assert_eq!(Helper::ArrayLength.call(&mut context, 0), THROW);
+        assert_eq!(Helper::NewIntArray.call(&mut context, -1), THROW);
+        assert_eq!(context.operand(), -1);
+        assert_ne!(Helper::NewIntArray.address(), 0);
+        assert_eq!(
+            heap.array(Value::Reference(array as u32)).unwrap().len(),
+            7
+        );
+    }
+}
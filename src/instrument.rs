@@ -0,0 +1,222 @@
+//! Per instruction instrumentation.
+//!
+//! An `Instrument` is called before every instruction the interpreter
+//! dispatches. A `Runtime` is generic over its instrument and defaults to
+//! `()` which does nothing, the call is inlined away so runtimes built with
+//! `Runtime::new` pay nothing for it. Instruments compose with tuples and
+//! `Option`, bytecode coverage, the execution log and `Watchpoints` are all
+//! instruments.
+//!
+//! Instructions running in native traces aren't dispatched by the
+//! interpreter and aren't seen by instruments. Superinstructions, see
+//! `coldbrew::fusion`, are only run while no instrument is active.
+use std::collections::{BTreeMap, HashMap};
+
+use crate::program::Program;
+use crate::runtime::{Frame, Instruction, ProgramCounter, Value};
+
+/// `Instrument` observes the instructions executed by the interpreter.
+pub trait Instrument {
+    /// Called once with the program of the runtime the instrument is
+    /// attached to.
+    fn attach(&mut self, _program: &Program) {}
+
+    /// Called before `inst` executes in `frame`, `frame.pc` is the program
+    /// counter of `inst`.
+    fn before_instruction(&mut self, frame: &Frame, inst: &Instruction);
+
+    /// Returns true if the instrument has to see every instruction, the
+    /// interpreter doesn't fuse instructions while it does.
+    fn is_active(&self) -> bool {
+        true
+    }
+}
+
+impl Instrument for () {
+    #[inline(always)]
+    fn before_instruction(&mut self, _frame: &Frame, _inst: &Instruction) {}
+
+    #[inline(always)]
+    fn is_active(&self) -> bool {
+        false
+    }
+}
+
+impl<T: Instrument> Instrument for Option<T> {
+    fn attach(&mut self, program: &Program) {
+        if let Some(instrument) = self {
+            instrument.attach(program);
+        }
+    }
+
+    #[inline]
+    fn before_instruction(&mut self, frame: &Frame, inst: &Instruction) {
+        if let Some(instrument) = self {
+            instrument.before_instruction(frame, inst);
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.as_ref().is_some_and(T::is_active)
+    }
+}
+
+impl<A: Instrument, B: Instrument> Instrument for (A, B) {
+    fn attach(&mut self, program: &Program) {
+        self.0.attach(program);
+        self.1.attach(program);
+    }
+
+    fn before_instruction(&mut self, frame: &Frame, inst: &Instruction) {
+        self.0.before_instruction(frame, inst);
+        self.1.before_instruction(frame, inst);
+    }
+
+    fn is_active(&self) -> bool {
+        self.0.is_active() || self.1.is_active()
+    }
+}
+
+impl<T: Instrument + ?Sized> Instrument for Box<T> {
+    fn attach(&mut self, program: &Program) {
+        (**self).attach(program);
+    }
+
+    fn before_instruction(&mut self, frame: &Frame, inst: &Instruction) {
+        (**self).before_instruction(frame, inst);
+    }
+
+    fn is_active(&self) -> bool {
+        (**self).is_active()
+    }
+}
+
+/// A write to a watched local variable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchHit {
+    /// Program counter of the instruction that wrote the variable, the
+    /// method entry for arguments.
+    pub pc: ProgramCounter,
+    pub method: String,
+    pub slot: usize,
+    /// Value before the write, `None` if the variable wasn't set yet.
+    pub old: Option<Value>,
+    pub new: Value,
+}
+
+/// `Watchpoints` records every change of watched local variables.
+#[derive(Debug, Default, Clone)]
+pub struct Watchpoints {
+    // Watched slots keyed by method name.
+    watched: BTreeMap<String, Vec<usize>>,
+    // Method name and watched slots keyed by method index, resolved on
+    // attach.
+    resolved: HashMap<usize, (String, Vec<usize>)>,
+    // Last value seen for every watched (method index, slot).
+    values: HashMap<(usize, usize), Value>,
+    // Program counter of the previous instruction.
+    previous: Option<ProgramCounter>,
+    hits: Vec<WatchHit>,
+}
+
+impl Watchpoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Watch the local variable at `slot` of the method called `method`.
+    #[must_use]
+    pub fn watch(mut self, method: &str, slot: usize) -> Self {
+        self.watched
+            .entry(method.to_string())
+            .or_default()
+            .push(slot);
+        self
+    }
+
+    /// Returns the writes recorded so far in execution order.
+    pub fn hits(&self) -> &[WatchHit] {
+        &self.hits
+    }
+}
+
+impl Instrument for Watchpoints {
+    fn attach(&mut self, program: &Program) {
+        self.resolved = self
+            .watched
+            .iter()
+            .filter_map(|(name, slots)| {
+                let method_index = program.method_index(name)?;
+                Some((method_index, (name.clone(), slots.clone())))
+            })
+            .collect();
+    }
+
+    fn before_instruction(&mut self, frame: &Frame, _inst: &Instruction) {
+        let pc = frame.pc;
+        let method_index = pc.get_method_index();
+        if let Some((method, slots)) = self.resolved.get(&method_index) {
+            // A change seen before this instruction was made by the previous
+            // one, unless the method was just entered.
+            let writer = self
+                .previous
+                .filter(|previous| previous.get_method_index() == method_index)
+                .unwrap_or(pc);
+            for slot in slots {
+                let Some(new) = frame.locals.get(*slot) else {
+                    continue;
+                };
+                let old = self.values.insert((method_index, *slot), new);
+                if old != Some(new) {
+                    self.hits.push(WatchHit {
+                        pc: writer,
+                        method: method.clone(),
+                        slot: *slot,
+                        old,
+                        new,
+                    });
+                }
+            }
+        }
+        self.previous = Some(pc);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coverage::Coverage;
+    use crate::jvm::{read_class_file, JVMParser};
+    use crate::runtime::Runtime;
+    use crate::sink::SharedBuffer;
+    use std::env;
+    use std::path::Path;
+
+    #[test]
+    fn can_instrument_runtime() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/tests/Factorial.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let instrument =
+            (Watchpoints::new().watch("factorial", 1), Coverage::new());
+        let mut runtime =
+            Runtime::with_instrument(Program::new(&class_file), instrument);
+        runtime.set_stdout(Box::new(SharedBuffer::new()));
+        runtime.run(false).unwrap();
+
+        let (watchpoints, coverage) = runtime.instrument();
+        // `accumulator` is set to 1 then multiplied for i = 2..=12.
+        let values: Vec<Value> =
+            watchpoints.hits().iter().map(|hit| hit.new).collect();
+        assert_eq!(values.len(), 12);
+        assert_eq!(values[0], Value::Int(1));
+        assert_eq!(values[11], Value::Int(479_001_600));
+        assert!(watchpoints
+            .hits()
+            .iter()
+            .all(|hit| hit.method == "factorial"));
+        assert_eq!(watchpoints.hits()[1].old, Some(Value::Int(1)));
+        assert!(coverage.hits(&watchpoints.hits()[1].pc) > 0);
+    }
+}
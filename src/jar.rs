@@ -0,0 +1,582 @@
+//! Reader for JAR archives.
+//!
+//! A JAR is a ZIP archive holding class files and a manifest. Entries are
+//! listed from the central directory at the end of the archive and are
+//! either stored as is or compressed with deflate (RFC 1951), which is
+//! decoded here so the reader, like the class file parser, only needs
+//! `core` and `alloc`. Opening archives from disk with `JarLoader::open`
+//! requires `std`.
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+use crate::jvm::{ClassFormatError, JVMClassFile, JVMParser};
+
+/// Signature of the end of central directory record.
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+/// Signature of a central directory file header.
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x0201_4b50;
+/// Signature of a local file header.
+const LOCAL_FILE_HEADER: u32 = 0x0403_4b50;
+/// Compression methods supported for entries.
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+/// Path of the manifest in the archive.
+const MANIFEST: &str = "META-INF/MANIFEST.MF";
+
+/// `JarErrorKind` represents the ways reading a JAR can fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JarErrorKind {
+    /// The archive has no end of central directory record.
+    NotAnArchive,
+    /// A header or the data of an entry runs past the end of the archive.
+    UnexpectedEnd,
+    /// The entry is compressed with a method other than stored or deflate.
+    UnsupportedCompression(u16),
+    /// The deflate stream of the entry is malformed.
+    InvalidDeflate,
+    /// The CRC-32 or size of the entry doesn't match its contents.
+    ChecksumMismatch,
+    /// The archive has no entry with this name.
+    MissingEntry,
+    /// The entry isn't a valid class file.
+    Class(ClassFormatError),
+}
+
+/// `JarError` is returned when a JAR or one of its entries can't be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JarError {
+    /// Name of the entry being read, empty for errors in the archive
+    /// layout itself.
+    pub entry: String,
+    pub kind: JarErrorKind,
+}
+
+impl fmt::Display for JarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.entry.is_empty() {
+            write!(f, "{} : ", self.entry)?;
+        }
+        match self.kind {
+            JarErrorKind::NotAnArchive => write!(f, "not a JAR archive"),
+            JarErrorKind::UnexpectedEnd => {
+                write!(f, "unexpected end of archive")
+            }
+            JarErrorKind::UnsupportedCompression(method) => {
+                write!(f, "unsupported compression method {method}")
+            }
+            JarErrorKind::InvalidDeflate => write!(f, "invalid deflate data"),
+            JarErrorKind::ChecksumMismatch => write!(f, "checksum mismatch"),
+            JarErrorKind::MissingEntry => write!(f, "no such entry"),
+            JarErrorKind::Class(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for JarError {}
+
+#[cfg(feature = "std")]
+impl From<JarError> for io::Error {
+    fn from(err: JarError) -> Self {
+        let kind = match err.kind {
+            JarErrorKind::MissingEntry => io::ErrorKind::NotFound,
+            JarErrorKind::UnexpectedEnd => io::ErrorKind::UnexpectedEof,
+            _ => io::ErrorKind::InvalidData,
+        };
+        Self::new(kind, err)
+    }
+}
+
+/// An entry of the central directory.
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    method: u16,
+    crc: u32,
+    compressed_size: usize,
+    size: usize,
+    // Offset of the entry's local file header.
+    offset: usize,
+}
+
+/// `JarLoader` lists and reads the entries of a JAR archive.
+#[derive(Debug, Clone)]
+pub struct JarLoader {
+    bytes: Vec<u8>,
+    entries: BTreeMap<String, Entry>,
+}
+
+impl JarLoader {
+    /// Read the central directory of the archive held in `bytes`.
+    /// # Errors
+    /// Returns an error if `bytes` isn't a ZIP archive or if its central
+    /// directory is truncated.
+    pub fn new(bytes: Vec<u8>) -> Result<Self, JarError> {
+        let archive_error = |kind| JarError {
+            entry: String::new(),
+            kind,
+        };
+        // The record is 22 bytes followed by a comment of up to 64K.
+        let end = (0..=bytes.len().saturating_sub(22))
+            .rev()
+            .take(0x1_0000)
+            .find(|offset| {
+                u32_at(&bytes, *offset) == Some(END_OF_CENTRAL_DIRECTORY)
+            })
+            .ok_or_else(|| archive_error(JarErrorKind::NotAnArchive))?;
+        let count = u16_at(&bytes, end + 10);
+        let directory = u32_at(&bytes, end + 16);
+        let (Some(count), Some(directory)) = (count, directory) else {
+            return Err(archive_error(JarErrorKind::UnexpectedEnd));
+        };
+        let mut offset = directory as usize;
+        let mut entries = BTreeMap::new();
+        for _ in 0..count {
+            let header = (|| {
+                if u32_at(&bytes, offset)? != CENTRAL_DIRECTORY_HEADER {
+                    return None;
+                }
+                let name_length = usize::from(u16_at(&bytes, offset + 28)?);
+                let extra_length = usize::from(u16_at(&bytes, offset + 30)?);
+                let comment_length = usize::from(u16_at(&bytes, offset + 32)?);
+                let name = bytes.get(offset + 46..offset + 46 + name_length)?;
+                let entry = Entry {
+                    method: u16_at(&bytes, offset + 10)?,
+                    crc: u32_at(&bytes, offset + 16)?,
+                    compressed_size: u32_at(&bytes, offset + 20)? as usize,
+                    size: u32_at(&bytes, offset + 24)? as usize,
+                    offset: u32_at(&bytes, offset + 42)? as usize,
+                };
+                let next =
+                    offset + 46 + name_length + extra_length + comment_length;
+                Some((String::from_utf8_lossy(name).to_string(), entry, next))
+            })();
+            let Some((name, entry, next)) = header else {
+                return Err(archive_error(JarErrorKind::UnexpectedEnd));
+            };
+            entries.insert(name, entry);
+            offset = next;
+        }
+        Ok(Self { bytes, entries })
+    }
+
+    /// Open the JAR at `path`.
+    /// # Errors
+    /// Returns an error if the file can't be read or isn't a ZIP archive.
+    #[cfg(feature = "std")]
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self::new(std::fs::read(path)?)?)
+    }
+
+    /// Returns the names of the entries sorted by name.
+    pub fn entries(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    /// Returns the binary names, such as `com/example/Main`, of the classes
+    /// in the archive sorted by name.
+    pub fn classes(&self) -> impl Iterator<Item = &str> {
+        self.entries()
+            .filter_map(|name| name.strip_suffix(".class"))
+    }
+
+    /// Returns the uncompressed contents of the entry `name`.
+    /// # Errors
+    /// Returns an error if the archive has no such entry or if its data is
+    /// truncated, corrupted or compressed with an unsupported method.
+    pub fn read(&self, name: &str) -> Result<Vec<u8>, JarError> {
+        let error = |kind| JarError {
+            entry: name.to_string(),
+            kind,
+        };
+        let entry = self
+            .entries
+            .get(name)
+            .ok_or_else(|| error(JarErrorKind::MissingEntry))?;
+        // Sizes are taken from the central directory, local headers may
+        // leave them to a data descriptor following the data.
+        let data = (|| {
+            if u32_at(&self.bytes, entry.offset)? != LOCAL_FILE_HEADER {
+                return None;
+            }
+            let name_length = u16_at(&self.bytes, entry.offset + 26)?;
+            let extra_length = u16_at(&self.bytes, entry.offset + 28)?;
+            let start = entry.offset
+                + 30
+                + usize::from(name_length)
+                + usize::from(extra_length);
+            self.bytes.get(start..start + entry.compressed_size)
+        })()
+        .ok_or_else(|| error(JarErrorKind::UnexpectedEnd))?;
+        let contents = match entry.method {
+            STORED => data.to_vec(),
+            DEFLATED => inflate(data, entry.size).map_err(error)?,
+            method => {
+                return Err(error(JarErrorKind::UnsupportedCompression(method)))
+            }
+        };
+        if contents.len() != entry.size || crc32(&contents) != entry.crc {
+            return Err(error(JarErrorKind::ChecksumMismatch));
+        }
+        Ok(contents)
+    }
+
+    /// Parse the class `class`, given by its binary name.
+    /// # Errors
+    /// Returns an error if the class can't be read or parsed.
+    pub fn parse_class(&self, class: &str) -> Result<JVMClassFile, JarError> {
+        let name = alloc::format!("{class}.class");
+        let bytes = self.read(&name)?;
+        JVMParser::parse(&bytes).map_err(|err| JarError {
+            entry: name,
+            kind: JarErrorKind::Class(err),
+        })
+    }
+
+    /// Parse every class of the archive sorted by name.
+    /// # Errors
+    /// Returns the first error reading or parsing a class.
+    pub fn parse_classes(&self) -> Result<Vec<JVMClassFile>, JarError> {
+        self.classes()
+            .map(|class| self.parse_class(class))
+            .collect()
+    }
+
+    /// Returns the binary name of the `Main-Class` declared by the
+    /// manifest, `None` if the archive has no manifest or it declares none.
+    /// # Errors
+    /// Returns an error if the manifest can't be read.
+    pub fn main_class(&self) -> Result<Option<String>, JarError> {
+        if !self.entries.contains_key(MANIFEST) {
+            return Ok(None);
+        }
+        let manifest = self.read(MANIFEST)?;
+        let manifest = String::from_utf8_lossy(&manifest);
+        // Long values continue on lines starting with a space.
+        let mut attributes: Vec<String> = Vec::new();
+        for line in manifest.lines() {
+            match (line.strip_prefix(' '), attributes.last_mut()) {
+                (Some(continuation), Some(last)) => last.push_str(continuation),
+                _ => attributes.push(line.to_string()),
+            }
+        }
+        Ok(attributes.iter().find_map(|attribute| {
+            let (key, value) = attribute.split_once(':')?;
+            (key.trim() == "Main-Class").then(|| value.trim().replace('.', "/"))
+        }))
+    }
+}
+
+/// Read the little endian `u16` at `offset`.
+fn u16_at(bytes: &[u8], offset: usize) -> Option<u16> {
+    let bytes = bytes.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+/// Read the little endian `u32` at `offset`.
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// CRC-32 of `bytes` as used by ZIP archives.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Base lengths and extra bits of the length symbols 257 to 285.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59,
+    67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5,
+    5, 5, 5, 0,
+];
+/// Base distances and extra bits of the distance symbols 0 to 29.
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513,
+    769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10,
+    11, 11, 12, 12, 13, 13,
+];
+/// Order the code length code lengths of a dynamic block are sent in.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Least significant bit first reader over a deflate stream.
+struct Bits<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl Bits<'_> {
+    /// Read `n` bits, `n` is at most 16.
+    fn read(&mut self, n: u32) -> Result<u32, JarErrorKind> {
+        while self.count < n {
+            let byte = self
+                .bytes
+                .get(self.position)
+                .ok_or(JarErrorKind::InvalidDeflate)?;
+            self.position += 1;
+            self.buffer |= u32::from(*byte) << self.count;
+            self.count += 8;
+        }
+        let bits = self.buffer & ((1 << n) - 1);
+        self.buffer >>= n;
+        self.count -= n;
+        Ok(bits)
+    }
+
+    /// Drop the bits left in the current byte.
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+/// Canonical Huffman code given by the number of codes of each length and
+/// the symbols ordered by code.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for length in lengths {
+            counts[usize::from(*length)] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0usize; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + usize::from(counts[length]);
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[usize::from(*length)]] = symbol as u16;
+                offsets[usize::from(*length)] += 1;
+            }
+        }
+        Self { counts, symbols }
+    }
+
+    /// Decode a symbol, codes are read one bit at a time starting with the
+    /// most significant one.
+    fn decode(&self, bits: &mut Bits<'_>) -> Result<u16, JarErrorKind> {
+        // Code being read, first code of the current length and index of
+        // its symbol.
+        let (mut code, mut first, mut index) = (0usize, 0usize, 0usize);
+        for count in &self.counts[1..] {
+            code |= bits.read(1)? as usize;
+            let count = usize::from(*count);
+            if code < first + count {
+                return self
+                    .symbols
+                    .get(index + code - first)
+                    .copied()
+                    .ok_or(JarErrorKind::InvalidDeflate);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(JarErrorKind::InvalidDeflate)
+    }
+}
+
+/// Decompress the raw deflate stream `data`, `size` is the expected size
+/// of the output.
+fn inflate(data: &[u8], size: usize) -> Result<Vec<u8>, JarErrorKind> {
+    let mut bits = Bits {
+        bytes: data,
+        position: 0,
+        buffer: 0,
+        count: 0,
+    };
+    let mut out = Vec::with_capacity(size);
+    loop {
+        let last = bits.read(1)? == 1;
+        match bits.read(2)? {
+            0 => {
+                bits.align();
+                let header = data
+                    .get(bits.position..bits.position + 4)
+                    .ok_or(JarErrorKind::InvalidDeflate)?;
+                let length = u16::from_le_bytes([header[0], header[1]]);
+                let complement = u16::from_le_bytes([header[2], header[3]]);
+                if length != !complement {
+                    return Err(JarErrorKind::InvalidDeflate);
+                }
+                let start = bits.position + 4;
+                let block = data
+                    .get(start..start + usize::from(length))
+                    .ok_or(JarErrorKind::InvalidDeflate)?;
+                out.extend_from_slice(block);
+                bits.position = start + usize::from(length);
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                inflate_block(&mut bits, &literals, &distances, &mut out)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &literals, &distances, &mut out)?;
+            }
+            _ => return Err(JarErrorKind::InvalidDeflate),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+/// Read the literal/length and distance codes of a dynamic block.
+fn dynamic_codes(
+    bits: &mut Bits<'_>,
+) -> Result<(Huffman, Huffman), JarErrorKind> {
+    let literal_count = bits.read(5)? as usize + 257;
+    let distance_count = bits.read(5)? as usize + 1;
+    let code_length_count = bits.read(4)? as usize + 4;
+    let mut code_lengths = [0u8; 19];
+    for index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[*index] = bits.read(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (length, repeat) = match code_lengths.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous =
+                    *lengths.last().ok_or(JarErrorKind::InvalidDeflate)?;
+                (previous, 3 + bits.read(2)?)
+            }
+            17 => (0, 3 + bits.read(3)?),
+            _ => (0, 11 + bits.read(7)?),
+        };
+        for _ in 0..repeat {
+            lengths.push(length);
+        }
+    }
+    if lengths.len() != literal_count + distance_count {
+        return Err(JarErrorKind::InvalidDeflate);
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+/// Decode the symbols of a compressed block until its end of block symbol.
+fn inflate_block(
+    bits: &mut Bits<'_>,
+    literals: &Huffman,
+    distances: &Huffman,
+    out: &mut Vec<u8>,
+) -> Result<(), JarErrorKind> {
+    loop {
+        let symbol = usize::from(literals.decode(bits)?);
+        if symbol < 256 {
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+        let symbol = symbol - 257;
+        if symbol >= LENGTH_BASE.len() {
+            return Err(JarErrorKind::InvalidDeflate);
+        }
+        let length = usize::from(LENGTH_BASE[symbol])
+            + bits.read(u32::from(LENGTH_EXTRA[symbol]))? as usize;
+        let symbol = usize::from(distances.decode(bits)?);
+        if symbol >= DISTANCE_BASE.len() {
+            return Err(JarErrorKind::InvalidDeflate);
+        }
+        let distance = usize::from(DISTANCE_BASE[symbol])
+            + bits.read(u32::from(DISTANCE_EXTRA[symbol]))? as usize;
+        if distance > out.len() {
+            return Err(JarErrorKind::InvalidDeflate);
+        }
+        // Copies may overlap the bytes they produce.
+        let start = out.len() - distance;
+        for index in start..start + length {
+            out.push(out[index]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn loads_classes_from_jar() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/jar/App.jar");
+        let jar = JarLoader::open(&path).unwrap();
+        assert_eq!(jar.classes().collect::<Vec<_>>(), ["Main", "Ops"]);
+        assert_eq!(jar.main_class().unwrap().as_deref(), Some("Main"));
+        let classes = jar.parse_classes().unwrap();
+        assert_eq!(classes.len(), 2);
+        let ops = std::fs::read(
+            Path::new(&env_var).join("support/linking/Ops.class"),
+        )
+        .unwrap();
+        assert_eq!(jar.read("Ops.class").unwrap(), ops);
+        let err = jar.parse_class("Missing").unwrap_err();
+        assert_eq!(err.kind, JarErrorKind::MissingEntry);
+        assert_eq!(err.entry, "Missing.class");
+        assert_eq!(
+            JarLoader::new(ops).unwrap_err().kind,
+            JarErrorKind::NotAnArchive
+        );
+    }
+
+    #[test]
+    fn inflates_stored_and_fixed_blocks() {
+        // A stored block holding "ab" that isn't the last block.
+        let stored = [0x00, 0x02, 0x00, 0xfd, 0xff, b'a', b'b'];
+        assert_eq!(inflate(&stored, 2), Err(JarErrorKind::InvalidDeflate));
+        let stored = [0x01, 0x02, 0x00, 0xfd, 0xff, b'a', b'b'];
+        assert_eq!(inflate(&stored, 2).unwrap(), b"ab");
+        // Fixed block holding "ab" then a copy of 4 bytes at distance 2,
+        // as written by zlib.
+        let fixed = [0x4b, 0x4c, 0x4a, 0x04, 0x42, 0x00];
+        assert_eq!(inflate(&fixed, 6).unwrap(), b"ababab");
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+}
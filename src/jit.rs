@@ -1,9 +1,17 @@
 //! JIT compiler for coldrew targeting x86_64.
+use std::cmp::Reverse;
 use std::collections::{HashMap, VecDeque};
 
 use crate::bytecode::OPCode;
+pub use crate::error::{JitError, JitErrorKind};
+use crate::fault::{self, Fault};
+use crate::heap::Heap;
+use crate::host::{self, Helper, HostContext};
+use crate::log;
 use crate::runtime::{Frame, ProgramCounter, Value};
-use crate::trace::Trace;
+use crate::trace::{KeyRange, RangeGuard, Record, SwitchGuard, Trace};
+use crate::validate::LocalType;
+use crate::vectorize::{self, Element, Expr, LoopBound, VectorLoop, VectorOp};
 
 use dynasmrt::x64::Assembler;
 use dynasmrt::{
@@ -52,11 +60,39 @@ enum Inst {
     IMul,
     IDiv,
     IRem,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+    UShr,
     Jge,
     Jg,
     Jle,
 }
 
+impl Inst {
+    /// Returns the bytecode instruction the operation was emitted for.
+    const fn opcode(self) -> OPCode {
+        match self {
+            Self::Add => OPCode::IAdd,
+            Self::Sub => OPCode::ISub,
+            Self::IMul => OPCode::IMul,
+            Self::IDiv => OPCode::IDiv,
+            Self::IRem => OPCode::IRem,
+            Self::And => OPCode::Iand,
+            Self::Or => OPCode::IOr,
+            Self::Xor => OPCode::IXor,
+            Self::Shl => OPCode::IShl,
+            Self::Shr => OPCode::IShr,
+            Self::UShr => OPCode::IUShr,
+            Self::Jge => OPCode::IfICmpGe,
+            Self::Jg => OPCode::IfICmpGt,
+            Self::Jle => OPCode::IfICmpLe,
+        }
+    }
+}
+
 /// Generic representation of assembly operands that allows for supporting
 /// both x86 and ARM64.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -70,23 +106,113 @@ enum Operand {
     Memory(Register, i32),
 }
 
-/// x86_64 function prologue, allocates `max_locals` space on the stack even
-/// though they might not be all used.
+/// Callee saved registers the allocator hands out, they are saved below
+/// the frame pointer by the prologue in this order.
+const CALLEE_SAVED: [Register; 5] = [
+    Register::Rbx,
+    Register::R12,
+    Register::R13,
+    Register::R14,
+    Register::R15,
+];
+
+/// Offsets from the frame pointer of the saved `execute` arguments, spill
+/// slots are below them.
+const LOCALS_SLOT: i32 = -48;
+const FUEL_SLOT: i32 = -56;
+const CONTEXT_SLOT: i32 = -64;
+
+/// Registers the allocator hands out that runtime helpers may clobber, see
+/// `host`.
+const CALLER_SAVED: [Register; 6] = [
+    Register::Rax,
+    Register::Rcx,
+    Register::R8,
+    Register::R9,
+    Register::R10,
+    Register::R11,
+];
+
+/// Returns the branch taken when `cond` doesn't hold.
+fn negate(cond: OPCode) -> OPCode {
+    match cond {
+        OPCode::IfICmpEq => OPCode::IfICmpNe,
+        OPCode::IfICmpNe => OPCode::IfICmpEq,
+        OPCode::IfICmpLt => OPCode::IfICmpGe,
+        OPCode::IfICmpGe => OPCode::IfICmpLt,
+        OPCode::IfICmpGt => OPCode::IfICmpLe,
+        OPCode::IfICmpLe => OPCode::IfICmpGt,
+        OPCode::IfEq => OPCode::IfNe,
+        OPCode::IfNe => OPCode::IfEq,
+        OPCode::IfLt => OPCode::IfGe,
+        OPCode::IfGe => OPCode::IfLt,
+        OPCode::IfGt => OPCode::IfLe,
+        OPCode::IfLe => OPCode::IfGt,
+        _ => cond,
+    }
+}
+
+/// `int3`, fills the code of invalidated traces in debug mode.
+const TRAP: u8 = 0xCC;
+
+/// Returns the FNV-1a hash of `code`.
+fn checksum(code: &[u8]) -> u64 {
+    code.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Returns the frame pointer offset of the spill slot `slot`.
+const fn spill_offset(slot: usize) -> i32 {
+    CONTEXT_SLOT - 8 * (slot as i32 + 1)
+}
+
+/// Returns the bytes the prologue reserves below the callee saved registers
+/// for the saved arguments and `spills` spill slots. The stack pointer stays
+/// 16 bytes aligned so that runtime helpers can be called from a trace.
+const fn frame_size(spills: usize) -> i32 {
+    let saved = 8 * CALLEE_SAVED.len() as i32;
+    let used = saved + 24 + 8 * spills as i32;
+    (used + 15) / 16 * 16 - saved
+}
+
+/// x86_64 function prologue, saves the callee saved registers and reserves
+/// `frame` bytes for the arguments and the spill slots.
+///
+/// The native frame looks as follows :
+///
+/// ```text
+/// [rbp + 8]        return address
+/// [rbp]            caller's rbp
+/// [rbp - 8..40]    rbx, r12, r13, r14, r15
+/// [rbp - 48]       locals (rdi)
+/// [rbp - 56]       fuel (rsi)
+/// [rbp - 64]       host context (rdx)
+/// [rbp - 72 - 8n]  spill slot n
+/// ```
 macro_rules! prologue {
-    ($ops:ident) => {{
+    ($ops:ident, $frame:expr) => {{
         #[cfg(target_arch = "x86_64")]
         {
         let start = $ops.offset();
         dynasm!($ops
             ; push rbp
             ; mov rbp, rsp
-            ; mov QWORD [rbp-24], rdi
-            ; mov QWORD [rbp-32], rsi
+            ; push rbx
+            ; push r12
+            ; push r13
+            ; push r14
+            ; push r15
+            ; sub rsp, $frame
+            ; mov QWORD [rbp + LOCALS_SLOT], rdi
+            ; mov QWORD [rbp + FUEL_SLOT], rsi
+            ; mov QWORD [rbp + CONTEXT_SLOT], rdx
         );
         start
         }
         #[cfg(target_arch = "aarch64")]
         {
+        let _ = $frame;
         let start = $ops.offset();
         dynasm!($ops
             ; sub sp, sp, #32
@@ -98,12 +224,18 @@ macro_rules! prologue {
     }};
 }
 
-/// aarch64 function epilogue.
+/// Function epilogue, restores the callee saved registers and returns.
 macro_rules! epilogue {
     ($ops:ident) => {{
         let epilogue = $ops.offset();
         #[cfg(target_arch = "x86_64")]
         dynasm!($ops
+            ; lea rsp, [rbp - 8 * CALLEE_SAVED.len() as i32]
+            ; pop r15
+            ; pop r14
+            ; pop r13
+            ; pop r12
+            ; pop rbx
             ; pop rbp
             ; ret
         );
@@ -119,9 +251,66 @@ macro_rules! epilogue {
 }
 
 /// `NativeTrace` is a pair of `usize` and `Assembler` that represents an entry
-/// point in the `Assembler` buffer.
+/// point in the `Assembler` buffer, along with the number of recorded
+/// instructions which is the fuel burned by each iteration.
 #[derive(Debug)]
-pub struct NativeTrace(AssemblyOffset, ExecutableBuffer);
+pub struct NativeTrace(AssemblyOffset, ExecutableBuffer, usize);
+
+/// `ExitStub` is the out of line code a native trace jumps to when one of
+/// its guards fails, it returns to the interpreter at `exit`. Stubs are the
+/// patch points where a trace compiled for `exit` would be linked.
+///
+/// Stubs return their index in the stubs of the trace, `execute` rebuilds
+/// the frame at `exit` from the locals they hold, see `JitCache::deopt`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExitStub {
+    /// Program counter the interpreter resumes at.
+    pub exit: ProgramCounter,
+    /// Offset of the stub in the code of the trace.
+    pub offset: usize,
+    /// Number of times the interpreter took the exit before compilation.
+    pub count: usize,
+    /// The stub raises the exception of the instruction at `exit` instead
+    /// of resuming it, such stubs are never linked.
+    pub throws: bool,
+    /// Locals the trace holds at the exit along with the type it accessed
+    /// them as, each in its slot of the locals passed in `rdi`, see
+    /// `Trace::exit_locals`. The operand stack is empty when the
+    /// interpreter resumes.
+    pub locals: Vec<(usize, LocalType)>,
+}
+
+/// `TraceExit` is how a native trace returned to the interpreter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceExit {
+    /// Interpreting resumes at the offset.
+    Resume(usize),
+    /// The instruction at the offset raises its exception, see
+    /// `Record::may_throw`, along with the operand it failed on when a
+    /// runtime helper raised it such as the length given to `newarray`.
+    Throw(usize, i64),
+}
+
+/// Value a native trace returns when it runs out of fuel, exit stubs return
+/// their index which is never negative.
+const SAFEPOINT: i32 = -1;
+
+/// `atype` of `newarray` creating an `int[]`.
+const T_INT: i32 = 10;
+
+/// Returns the local an `aload` or `astore` accesses.
+fn reference_local(record: &Record) -> Option<i32> {
+    match record.opcode() {
+        OPCode::ALoad0 | OPCode::AStore0 => Some(0),
+        OPCode::ALoad1 | OPCode::AStore1 => Some(1),
+        OPCode::ALoad2 | OPCode::AStore2 => Some(2),
+        OPCode::ALoad3 | OPCode::AStore3 => Some(3),
+        _ => match record.instruction().nth(0)? {
+            Value::Int(local) => Some(local),
+            _ => None,
+        },
+    }
+}
 
 /// `JitCache` is responsible for compiling, caching and executing the native
 /// traces.
@@ -129,22 +318,52 @@ pub struct NativeTrace(AssemblyOffset, ExecutableBuffer);
 /// The calling convention for our Jit is the following :
 ///
 /// - Rdi & Rsi are used to pass input arguments which are the local variables
-/// in the current frame and a guard program counter which is the entry point
-/// of our native trace.
+///   in the current frame and a guard program counter which is the entry point
+///   of our native trace.
+///
+/// - Rdx passes the `HostContext` runtime helpers are called with, see
+///   `host`.
 ///
-/// - Rax, Rbx, Rcx and R9-R15 are used for intermediate operations.
+/// - Rax, Rbx, Rcx and R8-R15 are used for intermediate operations.
 ///
 /// Since every trace is self contained all register allocation is local and
-/// done with a simple queue based scheme.
+/// done with a simple queue based scheme. When every register is taken the
+/// deepest operand held in a register is spilled to a slot of the trace's
+/// native frame.
 pub struct JitCache {
     // Internal cache of available registers.
     registers: VecDeque<Register>,
     // Operand stack.
     operands: Vec<Operand>,
+    // Spill slots in use, indexed by slot.
+    spills: Vec<bool>,
     // Cache of native traces.
     traces: HashMap<ProgramCounter, NativeTrace>,
     // Cache of `pc` entries to labels.
     labels: HashMap<ProgramCounter, DynamicLabel>,
+    // Native traces compiled from vectorized loops.
+    vector_loops: HashMap<ProgramCounter, VectorLoop>,
+    // Exit stubs of each native trace in the order they are laid out.
+    exit_stubs: HashMap<ProgramCounter, Vec<ExitStub>>,
+    // Locals each native trace accesses, read back when it runs out of
+    // fuel.
+    locals: HashMap<ProgramCounter, Vec<(usize, LocalType)>>,
+    // Locals each native trace checks the type of before it's entered.
+    entry_guards: HashMap<ProgramCounter, Vec<(usize, LocalType)>>,
+    // Poison invalidated traces and verify checksums before entry.
+    checks: bool,
+    // Checksums of the code of native traces compiled with checks on.
+    checksums: HashMap<ProgramCounter, u64>,
+    // Poisoned code of invalidated traces, kept mapped so that stale
+    // jumps into it trap.
+    poisoned: Vec<ExecutableBuffer>,
+    // Maximum size in bytes of the code of the cached traces.
+    code_limit: Option<usize>,
+    // Tick of the last entry to each native trace.
+    last_entered: HashMap<ProgramCounter, u64>,
+    ticks: u64,
+    // Traces evicted since the last call to `take_evicted`.
+    evicted: Vec<ProgramCounter>,
 }
 
 impl Default for JitCache {
@@ -173,7 +392,19 @@ impl JitCache {
             registers: VecDeque::from(registers),
             traces: HashMap::new(),
             operands: Vec::new(),
+            spills: Vec::new(),
             labels: HashMap::new(),
+            vector_loops: HashMap::new(),
+            exit_stubs: HashMap::new(),
+            locals: HashMap::new(),
+            entry_guards: HashMap::new(),
+            checks: false,
+            checksums: HashMap::new(),
+            poisoned: vec![],
+            code_limit: None,
+            last_entered: HashMap::new(),
+            ticks: 0,
+            evicted: vec![],
         }
     }
 
@@ -183,9 +414,51 @@ impl JitCache {
     /// Ideally we can just return the updated `locals` and exit but for now
     /// let's take in the entire execution frame of VM and update it.
     ///
-    /// Following the x86-64 convention the locals are passed in `rdi`, exit
-    /// information is passed in `rsi`.
-    pub fn execute(&mut self, pc: ProgramCounter, frame: &mut Frame) -> usize {
+    /// Following the x86-64 convention the locals are passed in `rdi` and
+    /// a pointer to the fuel in `rsi`. Every loop iteration burns one unit
+    /// of fuel per instruction in the trace, when it runs out the trace
+    /// exits at its entry point.
+    ///
+    /// Returns `None` when the trace can't make progress and the iteration
+    /// has to be interpreted, see `execute_vector`.
+    ///
+    /// # Errors
+    /// Returns the fault raised by the machine code, `frame` is left as it
+    /// was on entry.
+    pub fn execute(
+        &mut self,
+        pc: ProgramCounter,
+        frame: &mut Frame,
+        heap: &mut Heap,
+        fuel: &mut i32,
+    ) -> Result<Option<TraceExit>, Fault> {
+        self.verify(pc);
+        self.touch(pc);
+        if self.vector_loops.contains_key(&pc) {
+            return self
+                .execute_vector(pc, frame, heap, fuel)
+                .map(|exit| exit.map(TraceExit::Resume))
+                .transpose();
+        }
+        // The iteration is interpreted when a local the trace reads holds
+        // another type than the one it was recorded with.
+        let typed = |(local, kind): &(usize, LocalType)| {
+            matches!(
+                (kind, frame.locals.get(*local)),
+                (LocalType::Int, Some(Value::Int(_)))
+                    | (LocalType::Long, Some(Value::Long(_)))
+                    | (LocalType::Float, Some(Value::Float(_)))
+                    | (LocalType::Double, Some(Value::Double(_)))
+                    | (LocalType::Reference, Some(Value::Reference(_)))
+            )
+        };
+        if self
+            .entry_guards
+            .get(&pc)
+            .is_some_and(|guards| !guards.iter().all(typed))
+        {
+            return Ok(None);
+        }
         if self.traces.contains_key(&pc) {
             // execute the assembled trace.
             let trace = self
@@ -193,43 +466,357 @@ impl JitCache {
                 .get_mut(&pc)
                 .expect("Expected a native trace @ {pc}");
 
-            // Flatten the locals `HashMap` into a `i32` slice.
+            // Flatten the locals into a `i32` slice, longs and doubles
+            // span their slot and the next one like they do in the JVM.
             let mut locals = vec![0i32; frame.max_locals as usize * 8];
-            // Exit information, for now is empty.
-            let exits = [0i32; 0];
 
             for (key, val) in frame.locals.iter() {
-                locals[*key] = match val {
-                    Value::Int(x) => *x,
-                    Value::Long(x) => *x as i32,
-                    Value::Float(x) => *x as i32,
-                    Value::Double(x) => *x as i32,
+                let (low, high) = match val {
+                    Value::Int(x) => (x, None),
+                    Value::Long(x) => (x as i32, Some((x >> 32) as i32)),
+                    Value::Float(x) => (x.to_bits() as i32, None),
+                    Value::Double(x) => {
+                        let bits = x.to_bits();
+                        (bits as i32, Some((bits >> 32) as i32))
+                    }
+                    Value::Reference(x) => (x as i32, None),
                 };
+                locals[key] = low;
+                if let Some(high) = high {
+                    locals[key + 1] = high;
+                }
             }
 
-            let entry = trace.0;
-            let buf = &trace.1;
-            let execute: fn(*mut i32, *const i32) -> i32 =
-                unsafe { std::mem::transmute(buf.ptr(entry)) };
+            let mut context = HostContext::new(heap);
+            let args = [
+                locals.as_mut_ptr() as usize,
+                fuel as *mut i32 as usize,
+                &mut context as *mut HostContext as usize,
+            ];
+            // A fault leaves the frame as it was on entry.
+            let code = unsafe { fault::call(&trace.1, trace.0, &args) }? as i32;
+            Ok(Some(self.deopt(
+                pc,
+                code,
+                &locals,
+                context.operand(),
+                frame,
+            )))
+        } else {
+            Ok(Some(TraceExit::Resume(pc.get_instruction_index())))
+        }
+    }
+
+    /// Rebuild `frame` at the exit the native trace at `pc` returned `code`
+    /// from. The locals the trace holds at the exit are read back from
+    /// `buffer` as the type the trace accessed them as, longs and doubles
+    /// from their slot and the next one, the others keep the value they
+    /// had on entry. `operand` is the operand a runtime helper raised its
+    /// exception on.
+    fn deopt(
+        &self,
+        pc: ProgramCounter,
+        code: i32,
+        buffer: &[i32],
+        operand: i64,
+        frame: &mut Frame,
+    ) -> TraceExit {
+        let stub = usize::try_from(code)
+            .ok()
+            .and_then(|index| self.exit_stubs.get(&pc)?.get(index));
+        let (exit, locals) = match stub {
+            // Locals are stored as they're computed so they hold their
+            // values at the throwing instruction.
+            Some(stub) if stub.throws => (
+                TraceExit::Throw(stub.exit.get_instruction_index(), operand),
+                Some(&stub.locals),
+            ),
+            Some(stub) => (
+                TraceExit::Resume(stub.exit.get_instruction_index()),
+                Some(&stub.locals),
+            ),
+            // Out of fuel, interpreting resumes at the start of the trace.
+            None => (
+                TraceExit::Resume(pc.get_instruction_index()),
+                self.locals.get(&pc),
+            ),
+        };
+        for (local, kind) in locals.into_iter().flatten() {
+            let Some(low) = buffer.get(*local).map(|low| *low as u32) else {
+                continue;
+            };
+            let wide = || {
+                let high = *buffer.get(*local + 1)? as u32;
+                Some(u64::from(high) << 32 | u64::from(low))
+            };
+            let value = match kind {
+                LocalType::Int => Value::Int(low as i32),
+                LocalType::Float => Value::Float(f32::from_bits(low)),
+                LocalType::Reference => Value::Reference(low),
+                LocalType::Long => match wide() {
+                    Some(bits) => Value::Long(bits as i64),
+                    None => continue,
+                },
+                LocalType::Double => match wide() {
+                    Some(bits) => Value::Double(f64::from_bits(bits)),
+                    None => continue,
+                },
+            };
+            // The trace only uses locals of the frame's method.
+            let _ = frame.locals.insert(*local, value);
+        }
+        let (TraceExit::Resume(exit_pc) | TraceExit::Throw(exit_pc, _)) = exit;
+        frame.pc.instruction_index = exit_pc;
+        exit
+    }
 
-            let exit_pc = execute(locals.as_mut_ptr(), exits.as_ptr()) as usize;
-            frame.locals.clear();
-            for (index, value) in locals.iter().enumerate() {
-                frame.locals.insert(index, Value::Int(*value));
+    /// Execute the vectorized loop at `pc`, the arrays it uses are copied
+    /// to contiguous buffers passed in `rdx` and the elements it stored are
+    /// written back. Arrays held by several locals share a buffer so loops
+    /// storing to an array they also read see their own stores.
+    ///
+    /// The native loop runs while the index is below the loop bound and
+    /// the length of every array, once it can't run a single iteration the
+    /// interpreter takes over which exits the loop or raises the exception.
+    fn execute_vector(
+        &mut self,
+        pc: ProgramCounter,
+        frame: &mut Frame,
+        heap: &mut Heap,
+        fuel: &mut i32,
+    ) -> Option<Result<usize, Fault>> {
+        let vector_loop = self.vector_loops.get(&pc)?;
+        let trace = self.traces.get(&pc)?;
+        let int = |local| match frame.locals.get(local) {
+            Some(Value::Int(value)) => Some(value),
+            _ => None,
+        };
+        let start = int(vector_loop.index)?;
+        let bound = match vector_loop.bound {
+            LoopBound::Local(local) => int(local)?,
+            LoopBound::Const(value) => value,
+            LoopBound::Length(local) => {
+                let array = heap.array(frame.locals.get(local)?)?;
+                i32::try_from(array.len()).ok()?
+            }
+        };
+        let mut limit = bound;
+        let mut references = vec![];
+        let mut buffers: Vec<Vec<u32>> = vec![];
+        let mut slots = vec![];
+        for local in vector_loop.arrays() {
+            let reference = frame.locals.get(local)?;
+            let elements = heap.array(reference)?;
+            limit = limit.min(i32::try_from(elements.len()).ok()?);
+            let slot = match references.iter().position(|r| *r == reference) {
+                Some(slot) => slot,
+                None => {
+                    references.push(reference);
+                    buffers.push(
+                        elements
+                            .iter()
+                            .map(|element| match element {
+                                Value::Int(value) => *value as u32,
+                                Value::Float(value) => value.to_bits(),
+                                _ => 0,
+                            })
+                            .collect(),
+                    );
+                    buffers.len() - 1
+                }
+            };
+            slots.push(slot);
+        }
+        if start < 0 || start >= limit {
+            return None;
+        }
+        let mut pointers: Vec<*mut u32> = slots
+            .iter()
+            .map(|slot| buffers[*slot].as_mut_ptr())
+            .collect();
+        let mut locals = vec![0i32; frame.max_locals as usize * 8];
+        for (key, val) in frame.locals.iter() {
+            if let Value::Int(x) = val {
+                locals[key] = x;
             }
+        }
+
+        let args = [
+            locals.as_mut_ptr() as usize,
+            fuel as *mut i32 as usize,
+            pointers.as_mut_ptr() as usize,
+            limit as usize,
+        ];
+        if let Err(fault) = unsafe { fault::call(&trace.1, trace.0, &args) } {
+            return Some(Err(fault));
+        }
 
-            frame.pc.instruction_index = exit_pc as usize;
-            exit_pc
+        let end = locals[vector_loop.index];
+        let stored = &buffers[slots[0]];
+        if let Some(elements) = heap.array_mut(references[slots[0]]) {
+            for index in start as usize..end as usize {
+                elements[index] = match vector_loop.element {
+                    Element::Int => Value::Int(stored[index] as i32),
+                    Element::Float => {
+                        Value::Float(f32::from_bits(stored[index]))
+                    }
+                };
+            }
+        }
+        let _ = frame.locals.insert(vector_loop.index, Value::Int(end));
+        // Out of fuel or the arrays are too short, re-entering the loop
+        // hands the next iteration to the interpreter.
+        let exit_pc = if end >= bound {
+            vector_loop.exit.get_instruction_index()
         } else {
             pc.get_instruction_index()
+        };
+        frame.pc.instruction_index = exit_pc;
+        Some(Ok(exit_pc))
+    }
+
+    /// Enable the code cache debug checks, the code of invalidated traces
+    /// is filled with `int3` and kept mapped and the code of every trace is
+    /// checksummed at compile time and verified before each entry. Stale or
+    /// corrupted code then crashes instead of computing garbage.
+    pub fn set_checks(&mut self, enabled: bool) {
+        self.checks = enabled;
+    }
+
+    /// Drop the native trace at `pc`, returns false if there is none.
+    pub fn invalidate(&mut self, pc: ProgramCounter) -> bool {
+        let Some(NativeTrace(_, buffer, _)) = self.traces.remove(&pc) else {
+            return false;
+        };
+        self.vector_loops.remove(&pc);
+        self.exit_stubs.remove(&pc);
+        self.locals.remove(&pc);
+        self.entry_guards.remove(&pc);
+        self.checksums.remove(&pc);
+        self.last_entered.remove(&pc);
+        if self.checks {
+            let poisoned = buffer.make_mut().and_then(|mut buffer| {
+                buffer.fill(TRAP);
+                buffer.make_exec()
+            });
+            match poisoned {
+                Ok(buffer) => self.poisoned.push(buffer),
+                Err(err) => {
+                    log::warning!("jit", "failed to poison {pc} : {err}")
+                }
+            }
+        }
+        true
+    }
+
+    /// Cache `trace` as the native trace at `pc`, replacing any previous one.
+    fn install(&mut self, pc: ProgramCounter, trace: NativeTrace) {
+        self.invalidate(pc);
+        if self.checks {
+            self.checksums.insert(pc, checksum(&trace.1));
+        }
+        self.traces.insert(pc, trace);
+        self.touch(pc);
+        self.evict(pc);
+    }
+
+    /// Cap the size in bytes of the machine code of the cached traces, the
+    /// least recently entered traces are evicted when a new one doesn't
+    /// fit. A trace larger than the limit on its own is still cached.
+    /// `None` leaves the cache unbounded, the default.
+    pub fn set_code_limit(&mut self, limit: Option<usize>) {
+        self.code_limit = limit;
+    }
+
+    /// Returns the size in bytes of the machine code of every cached trace.
+    pub fn total_code_size(&self) -> usize {
+        self.traces.values().map(|trace| trace.1.len()).sum()
+    }
+
+    /// Returns the traces evicted since the last call, in eviction order.
+    pub fn take_evicted(&mut self) -> Vec<ProgramCounter> {
+        std::mem::take(&mut self.evicted)
+    }
+
+    /// Mark the native trace at `pc` as the most recently entered one.
+    fn touch(&mut self, pc: ProgramCounter) {
+        if self.traces.contains_key(&pc) {
+            self.ticks += 1;
+            self.last_entered.insert(pc, self.ticks);
+        }
+    }
+
+    /// Evict the least recently entered traces other than the one at `pc`
+    /// until the cached code fits in the limit.
+    fn evict(&mut self, pc: ProgramCounter) {
+        let Some(limit) = self.code_limit else {
+            return;
+        };
+        while self.total_code_size() > limit {
+            let Some(lru) = self
+                .traces
+                .keys()
+                .filter(|other| **other != pc)
+                .min_by_key(|other| (self.last_entered.get(other), **other))
+                .copied()
+            else {
+                break;
+            };
+            log::debug!("jit", "evicting native trace @ {lru}");
+            self.invalidate(lru);
+            self.evicted.push(lru);
+        }
+    }
+
+    /// Verify the checksum of the native trace at `pc`.
+    /// # Panics
+    /// Panics if the code of the trace changed since it was compiled.
+    fn verify(&self, pc: ProgramCounter) {
+        if !self.checks {
+            return;
+        }
+        if let (Some(trace), Some(expected)) =
+            (self.traces.get(&pc), self.checksums.get(&pc))
+        {
+            let found = checksum(&trace.1);
+            assert_eq!(
+                found, *expected,
+                "native trace @ {pc} was modified after compilation"
+            );
         }
     }
 
+    /// Returns the exit stubs of the native trace at `pc` in the order they
+    /// are laid out after the loop body.
+    pub fn exit_stubs(&self, pc: ProgramCounter) -> Option<&[ExitStub]> {
+        self.exit_stubs.get(&pc).map(Vec::as_slice)
+    }
+
+    /// Returns the locals whose type the native trace at `pc` checks before
+    /// it's entered, those the verifier couldn't prove the type of.
+    pub fn entry_guards(
+        &self,
+        pc: ProgramCounter,
+    ) -> Option<&[(usize, LocalType)]> {
+        self.entry_guards.get(&pc).map(Vec::as_slice)
+    }
+
     /// Checks if a native trace exists at this `pc`.
     pub fn has_native_trace(&self, pc: ProgramCounter) -> bool {
         self.traces.contains_key(&pc)
     }
 
+    /// Returns the size in bytes of the machine code of the native trace at
+    /// `pc`.
+    pub fn code_size(&self, pc: ProgramCounter) -> Option<usize> {
+        self.traces.get(&pc).map(|trace| trace.1.len())
+    }
+
+    /// Returns the fuel burned by an iteration of the native trace at `pc`.
+    pub fn fuel_per_iteration(&self, pc: ProgramCounter) -> Option<usize> {
+        self.traces.get(&pc).map(|trace| trace.2)
+    }
+
     /// Compile the trace given as argument and prepare a native trace
     /// for execution.
     ///
@@ -238,10 +825,10 @@ impl JitCache {
     /// 2. Emits a static prologue for the jitted code.
     /// 3. For each recorded instruction generate its equivalent x86 or arm64
     ///    instruction and create a label for it.
-    ///   3.1 If the instruction is a jump i.e `Goto` check if we have a label
-    ///   for it, since all recorded traces are straight lines with backward
-    ///   jumps we must have one, then emit the equivalent jump with the label
-    ///   as the target.
+    ///    3.1 If the instruction is a jump i.e `Goto` check if we have a label
+    ///    for it, since all recorded traces are straight lines with backward
+    ///    jumps we must have one, then emit the equivalent jump with the label
+    ///    as the target.
     /// 4. Emits a static epilogue for the jitted code.
     /// 5. When a trace recording is looked, run the jitted code.
     ///
@@ -250,33 +837,110 @@ impl JitCache {
     ///
     /// How jumps are handled (in more details) :
     /// 1. At each trace.instruction()
-    ///     1.1 Create a DynasmLabel `inst_label_{pc}`
-    ///     1.2 Append the new label to the `global_jump_table`
+    ///    1.1 Create a DynasmLabel `inst_label_{pc}`
+    ///    1.2 Append the new label to the `global_jump_table`
     /// 2. If the trace.instruction() is a branch:
-    ///     1.1 Check if we have an existing entry in the `global_jump_table`.
-    ///     1.2 If an entry exists it means we've compiled a trace for this block.
-    ///         1.2.1 Fetch the label and mark the native trace with this label
-    ///         the trace will either be stitched if the jump is outside this trace
-    ///         or it will be local if it is inside this trace.
-    ///     1.3 If an entry doesn't exists it means we're exiting the JIT so we
-    ///     preserve the target `pc` in `rax` and return, when calling `execute`
-    ///     the assumption is that we will always exit back to the interpreter
-    ///     since we currently don't support trace stitching.
-    pub fn compile(&mut self, recording: &Trace) {
+    ///    1.1 Check if we have an existing entry in the `global_jump_table`.
+    ///    1.2 If an entry exists it means we've compiled a trace for this block.
+    ///    1.2.1 Fetch the label and mark the native trace with this label
+    ///    the trace will either be stitched if the jump is outside this trace
+    ///    or it will be local if it is inside this trace.
+    ///    1.3 If an entry doesn't exists it means we're exiting the JIT so we
+    ///    preserve the target `pc` in `rax` and return, when calling `execute`
+    ///    the assumption is that we will always exit back to the interpreter
+    ///    since we currently don't support trace stitching.
+    /// # Errors
+    /// Returns an error if the trace can't be compiled, the cache is left as
+    /// it was before the call.
+    pub fn compile(&mut self, recording: &Trace) -> Result<(), JitError> {
+        #[cfg(target_arch = "x86_64")]
+        if let Some(vector_loop) = vectorize::vectorize(recording) {
+            log::debug!(
+                "jit",
+                "vectorized trace @ {} : {vector_loop}",
+                recording.start()
+            );
+            return self.assemble_vector(recording, vector_loop);
+        }
+        let result = self.assemble(recording);
+        self.registers = Self::new().registers;
+        self.operands.clear();
+        self.spills.clear();
+        result
+    }
+
+    /// Assemble `recording` and cache the native trace.
+    fn assemble(&mut self, recording: &Trace) -> Result<(), JitError> {
         // Reset Jit state.
-        let pc = recording.start;
-        let mut ops = dynasmrt::x64::Assembler::new().unwrap();
-        // Prologue for dynamically compiled code.
-        let offset = prologue!(ops);
-        let mut exit_pc = 0i32;
+        let pc = recording.start();
+        let mut ops =
+            dynasmrt::x64::Assembler::new().map_err(|err| JitError {
+                pc,
+                kind: JitErrorKind::Assembler(err.to_string()),
+            })?;
+        // The prologue is emitted after the body once the number of spill
+        // slots is known, it jumps back here.
+        #[cfg(target_arch = "x86_64")]
+        dynasm!(ops
+            ; ->body:
+        );
+        // Traces compute on ints and references, longs, floats and doubles
+        // would be computed on as ints.
+        if let Some(record) = recording.iter().find(|record| {
+            record.local().is_some_and(|(_, kind)| {
+                !matches!(kind, LocalType::Int | LocalType::Reference)
+            })
+        }) {
+            return Err(JitError {
+                pc: record.pc(),
+                kind: JitErrorKind::Unsupported(record.opcode()),
+            });
+        }
+        // Exit stubs, the number of times the interpreter took them and the
+        // locals they hold.
+        let mut exits = vec![];
+        let mut covered = 0;
         // Trace compilation :
         // For now we compile only the prologue and epilogue and ensure that
         // entering the Jit executing the assembled code and leaving the Jit
         // works correct.
-        for entry in &recording.trace {
+        for (index, entry) in recording.iter().enumerate() {
+            if covered > 0 {
+                covered -= 1;
+                continue;
+            }
             // Record the instruction program counter to a new label.
             let inst_label = ops.new_dynamic_label();
             let _ = self.labels.insert(entry.pc(), inst_label);
+            let at = |kind| JitError {
+                pc: entry.pc(),
+                kind,
+            };
+            // The interpreter resumes with an empty operand stack.
+            if let Some(guard) = recording
+                .range_guard(entry.pc())
+                .filter(|_| self.operands.is_empty())
+            {
+                let exit = ops.new_dynamic_label();
+                #[cfg(target_arch = "x86_64")]
+                dynasm!(ops
+                    ; =>inst_label
+                );
+                self.emit_range_guard(&mut ops, guard, exit).map_err(at)?;
+                // Range guards exit where their first guard started, they
+                // are taken whenever one of their guards would have been.
+                let count = recording
+                    .iter()
+                    .skip_while(|record| record.pc() != guard.exit)
+                    .take(guard.len)
+                    .filter_map(|record| recording.guard(record.pc()))
+                    .map(|branch| branch.count)
+                    .sum();
+                let locals = recording.exit_locals(index);
+                exits.push((exit, guard.exit, count, false, locals));
+                covered = guard.len - 1;
+                continue;
+            }
             match entry.instruction().get_mnemonic() {
                 // Load operation loads a constant from the locals array at
                 // the position given by the opcode's operand.
@@ -294,12 +958,13 @@ impl JitCache {
                         Some(Value::Int(x)) => x,
                         _ => unreachable!("Operand to iload (index in locals) must be int in current implementation")
                     };
-                    let dst = self.first_available_register();
-
                     #[cfg(target_arch = "x86_64")]
                     dynasm!(ops
                         ; =>inst_label
                     );
+                    let dst =
+                        self.first_available_register(&mut ops).map_err(at)?;
+
                     Self::emit_mov(
                         &mut ops,
                         &dst,
@@ -327,10 +992,52 @@ impl JitCache {
                         );
                     }
                 }
-                OPCode::BiPush | OPCode::SiPush | OPCode::Ldc => {
+                // References are held in locals like integers, the heap
+                // is reached through runtime helpers.
+                opcode @ (OPCode::ALoad
+                | OPCode::ALoad0
+                | OPCode::ALoad1
+                | OPCode::ALoad2
+                | OPCode::ALoad3) => {
+                    let index = reference_local(entry)
+                        .ok_or_else(|| at(JitErrorKind::Unsupported(opcode)))?;
+                    #[cfg(target_arch = "x86_64")]
+                    dynasm!(ops
+                        ; =>inst_label
+                    );
+                    let dst =
+                        self.first_available_register(&mut ops).map_err(at)?;
+                    Self::emit_mov(
+                        &mut ops,
+                        &dst,
+                        &Operand::Memory(Register::Rdi, 4 * index),
+                    );
+                    self.operands.push(dst);
+                }
+                opcode @ (OPCode::AStore
+                | OPCode::AStore0
+                | OPCode::AStore1
+                | OPCode::AStore2
+                | OPCode::AStore3) => {
+                    let index = reference_local(entry)
+                        .ok_or_else(|| at(JitErrorKind::Unsupported(opcode)))?;
+                    if let Some(src) = self.free_register() {
+                        #[cfg(target_arch = "x86_64")]
+                        dynasm!(ops
+                            ; =>inst_label
+                        );
+                        Self::emit_mov(
+                            &mut ops,
+                            &Operand::Memory(Register::Rdi, 4 * index),
+                            &src,
+                        );
+                    }
+                }
+                // `ldc` of a float, a string or a class isn't compiled.
+                opcode @ (OPCode::BiPush | OPCode::SiPush | OPCode::Ldc) => {
                     let imm = match entry.instruction().nth(0) {
                         Some(Value::Int(imm)) => imm,
-                        _ => unreachable!("Operand to {} must be an int in current implementation", entry.instruction().get_mnemonic())
+                        _ => return Err(at(JitErrorKind::Unsupported(opcode))),
                     };
                     self.operands.push(Operand::Immediate(imm));
                 }
@@ -339,35 +1046,70 @@ impl JitCache {
                     dynasm!(ops
                         ; =>inst_label
                     );
-                    self.emit_arithmetic(&mut ops, Inst::Add);
+                    self.emit_arithmetic(&mut ops, Inst::Add).map_err(at)?;
                 }
                 OPCode::ISub => {
                     #[cfg(target_arch = "x86_64")]
                     dynasm!(ops
                         ; =>inst_label
                     );
-                    self.emit_arithmetic(&mut ops, Inst::Sub);
+                    self.emit_arithmetic(&mut ops, Inst::Sub).map_err(at)?;
                 }
                 OPCode::IMul => {
                     #[cfg(target_arch = "x86_64")]
                     dynasm!(ops
                         ; =>inst_label
                     );
-                    self.emit_arithmetic(&mut ops, Inst::IMul);
+                    self.emit_arithmetic(&mut ops, Inst::IMul).map_err(at)?;
+                }
+                opcode @ (OPCode::Iand | OPCode::IOr | OPCode::IXor) => {
+                    #[cfg(target_arch = "x86_64")]
+                    dynasm!(ops
+                        ; =>inst_label
+                    );
+                    let op = match opcode {
+                        OPCode::Iand => Inst::And,
+                        OPCode::IOr => Inst::Or,
+                        _ => Inst::Xor,
+                    };
+                    self.emit_arithmetic(&mut ops, op).map_err(at)?;
+                }
+                opcode @ (OPCode::IShl | OPCode::IShr | OPCode::IUShr) => {
+                    #[cfg(target_arch = "x86_64")]
+                    dynasm!(ops
+                        ; =>inst_label
+                    );
+                    let op = match opcode {
+                        OPCode::IShl => Inst::Shl,
+                        OPCode::IShr => Inst::Shr,
+                        _ => Inst::UShr,
+                    };
+                    self.emit_shift(&mut ops, op).map_err(at)?;
                 }
-                OPCode::IDiv => {
+                OPCode::INeg => {
                     #[cfg(target_arch = "x86_64")]
                     dynasm!(ops
                         ; =>inst_label
                     );
-                    self.emit_div(&mut ops, Inst::IDiv);
+                    self.emit_neg(&mut ops).map_err(at)?;
                 }
-                OPCode::IRem => {
+
+                // Dividing by zero leaves the trace through a stub raising
+                // `ArithmeticException` at the division.
+                opcode @ (OPCode::IDiv | OPCode::IRem) => {
+                    let throw = ops.new_dynamic_label();
                     #[cfg(target_arch = "x86_64")]
                     dynasm!(ops
                         ; =>inst_label
                     );
-                    self.emit_div(&mut ops, Inst::IRem);
+                    let op = match opcode {
+                        OPCode::IDiv => Inst::IDiv,
+                        _ => Inst::IRem,
+                    };
+                    if self.emit_div(&mut ops, op, throw).map_err(at)? {
+                        let locals = recording.exit_locals(index);
+                        exits.push((throw, entry.pc(), 0, true, locals));
+                    }
                 }
                 OPCode::IInc => {
                     let index = match entry.instruction().nth(0) {
@@ -383,7 +1125,7 @@ impl JitCache {
                         ; =>inst_label
                     );
                     dynasm!(ops
-                        ; add [Rq(Register::Rdi as u8) + 4* index], constant as _
+                        ; add DWORD [Rq(Register::Rdi as u8) + 4 * index], constant
                     );
                 }
                 OPCode::Goto => {
@@ -411,91 +1153,225 @@ impl JitCache {
                         (entry.pc().get_instruction_index() as isize
                             + target as isize) as usize,
                     )) {
+                        // Loop back edges are safepoints where the trace is
+                        // charged for an iteration.
                         #[cfg(target_arch = "x86_64")]
                         dynasm!(ops
+                            ; sub DWORD [rsi], recording.len() as _
+                            ; jle ->safepoint
                             ; jmp =>*label
                         );
                     }
                 }
-                // if_icmp{cond} compares the top two values on the stack
-                // and branches to the target offset given as an operand
-                // if the comparison is not true.
-                // Since our traces are self contained to the loop code
-                // the target offset will be the exit pc value at which
-                // the interpreter should continue execution.
-                OPCode::IfICmpGe
+                // Conditional branches stay on the direction they went while
+                // recording, the trace jumps to an exit stub laid out after
+                // the loop body when they go the other way.
+                OPCode::IfICmpEq
+                | OPCode::IfICmpNe
+                | OPCode::IfICmpLt
+                | OPCode::IfICmpGe
                 | OPCode::IfICmpGt
                 | OPCode::IfICmpLe
-                | OPCode::IfICmpEq => {
-                    let target = match entry.instruction().nth(0) {
-                        Some(Value::Int(x)) => x,
-                            _ => unreachable!("First operand to if_icmpge (relative offset) must be int")
-                    };
-                    let mnemonic = entry.instruction().get_mnemonic();
-                    exit_pc = (entry.pc().get_instruction_index() as isize
-                        + target as isize) as i32;
-
-                    self.emit_cond_branch(&mut ops, mnemonic);
-                }
-                OPCode::IfEq => {
-                    let operand = self.free_register();
-                    match operand {
-                        Some(Operand::Register(reg)) => {
-                            #[cfg(target_arch = "x86_64")]
-                            dynasm!(ops
-                                ; cmp Rq(reg as u8), 0
-                                ; je ->abort_guard
-                            );
-                        }
-                        Some(Operand::Memory(base, offset)) => {
-                            #[cfg(target_arch = "x86_64")]
-                            dynasm!(ops
-                                ; cmp [Rq(base as u8) + offset], 0
-                                ; je ->abort_guard
-                            );
-                        }
-                        _ => unreachable!("expected operand for if_eq to be either `Operand::Memory` or `Operand::Register`"),
+                | OPCode::IfEq
+                | OPCode::IfNe
+                | OPCode::IfLt
+                | OPCode::IfGe
+                | OPCode::IfGt
+                | OPCode::IfLe => {
+                    let guard =
+                        recording.guard(entry.pc()).ok_or_else(|| {
+                            at(JitErrorKind::Unsupported(
+                                entry.instruction().get_mnemonic(),
+                            ))
+                        })?;
+                    // if<cond> compares its operand with zero.
+                    if !matches!(
+                        guard.condition,
+                        OPCode::IfICmpEq
+                            | OPCode::IfICmpNe
+                            | OPCode::IfICmpLt
+                            | OPCode::IfICmpGe
+                            | OPCode::IfICmpGt
+                            | OPCode::IfICmpLe
+                    ) {
+                        self.operands.push(Operand::Immediate(0));
+                    }
+                    let exit = ops.new_dynamic_label();
+                    #[cfg(target_arch = "x86_64")]
+                    dynasm!(ops
+                        ; =>inst_label
+                    );
+                    self.emit_cond_branch(
+                        &mut ops,
+                        guard.condition,
+                        guard.taken,
+                        exit,
+                    )
+                    .map_err(at)?;
+                    // The interpreter resumes with an empty operand stack.
+                    if !self.operands.is_empty() {
+                        return Err(at(JitErrorKind::LiveOperands(
+                            guard.condition,
+                        )));
                     }
+                    let locals = guard.locals.clone();
+                    exits.push((exit, guard.exit, guard.count, false, locals));
                 }
-                OPCode::IfNe => {
-                    let operand = self.free_register();
-                    match operand {
-                        Some(Operand::Register(reg)) => {
-                            #[cfg(target_arch = "x86_64")]
-                            dynasm!(ops
-                                ; cmp Rq(reg as u8), 0
-                                ; jz ->abort_guard
-                            );
-                        }
-                        Some(Operand::Memory(base, offset)) => {
-                            #[cfg(target_arch = "x86_64")]
-                            dynasm!(ops
-                                ; cmp [Rq(base as u8) + offset], 0
-                                ; jz ->abort_guard
-                            );
+                // Array accesses are only compiled in vectorized loops.
+                opcode @ (OPCode::IALoad
+                | OPCode::LALoad
+                | OPCode::FALoad
+                | OPCode::DALoad
+                | OPCode::AALoad
+                | OPCode::BALoad
+                | OPCode::CALoad
+                | OPCode::SALoad
+                | OPCode::IAStore
+                | OPCode::LAStore
+                | OPCode::FAStore
+                | OPCode::DAStore
+                | OPCode::AAStore
+                | OPCode::BAStore
+                | OPCode::CAStore
+                | OPCode::SAStore) => {
+                    return Err(at(JitErrorKind::Unsupported(opcode)));
+                }
+                // Arrays are allocated and measured by runtime helpers, a
+                // negative length or a null array leaves the trace through
+                // a stub raising the exception.
+                opcode @ (OPCode::ArrayLength | OPCode::NewArray) => {
+                    let helper = match entry.instruction().nth(0) {
+                        None => Helper::ArrayLength,
+                        Some(Value::Int(T_INT)) => Helper::NewIntArray,
+                        Some(_) => {
+                            return Err(at(JitErrorKind::Unsupported(opcode)))
                         }
-                        _ => unreachable!("expected operand for if_eq to be either `Operand::Memory` or `Operand::Register`"),
+                    };
+                    let throw = ops.new_dynamic_label();
+                    #[cfg(target_arch = "x86_64")]
+                    dynasm!(ops
+                        ; =>inst_label
+                    );
+                    self.emit_host_call(&mut ops, opcode, helper, throw)
+                        .map_err(at)?;
+                    let locals = recording.exit_locals(index);
+                    exits.push((throw, entry.pc(), 0, true, locals));
+                }
+                // Switches stay on the case they took while recording, the
+                // other cases leave through exit stubs at their target.
+                opcode @ (OPCode::TableSwitch | OPCode::LookupSwitch) => {
+                    let guard = recording
+                        .switch_guard(entry.pc())
+                        .filter(|guard| guard.taken > guard.pc)
+                        .ok_or_else(|| at(JitErrorKind::Unsupported(opcode)))?;
+                    #[cfg(target_arch = "x86_64")]
+                    dynasm!(ops
+                        ; =>inst_label
+                    );
+                    let targets: Vec<_> = guard
+                        .exits()
+                        .into_iter()
+                        .map(|(exit, ranges)| {
+                            (ops.new_dynamic_label(), exit, ranges)
+                        })
+                        .collect();
+                    self.emit_switch_guard(&mut ops, opcode, guard, &targets)
+                        .map_err(at)?;
+                    if !self.operands.is_empty() {
+                        return Err(at(JitErrorKind::LiveOperands(opcode)));
                     }
+                    for (label, exit, _) in targets {
+                        let locals = recording.exit_locals(index);
+                        exits.push((label, exit, 0, false, locals));
+                    }
+                }
+                // Objects live on the interpreter's heap.
+                opcode
+                @ (OPCode::New | OPCode::GetField | OPCode::PutField) => {
+                    return Err(at(JitErrorKind::Unsupported(opcode)));
                 }
-                _ => (),
+                // Inlined calls keep their arguments and locals in frames
+                // the trace doesn't model yet.
+                opcode @ (OPCode::InvokeStatic
+                | OPCode::InvokeSpecial
+                | OPCode::InvokeVirtual
+                | OPCode::InvokeInterface
+                | OPCode::Return
+                | OPCode::IReturn
+                | OPCode::LReturn
+                | OPCode::FReturn
+                | OPCode::DReturn
+                | OPCode::AReturn) => {
+                    return Err(at(JitErrorKind::Unsupported(opcode)));
+                }
+                OPCode::Nop => (),
+                // Compiling a trace without an instruction would compute
+                // different values than the interpreter, the loop stays
+                // interpreted.
+                opcode => return Err(at(JitErrorKind::Unsupported(opcode))),
             }
         }
+        // Exit stubs are laid out after the loop body so that the hot path
+        // is straight line code, the most frequently taken ones first.
+        exits.sort_by_key(|(_, _, count, _, _)| Reverse(*count));
+        let mut stubs = Vec::with_capacity(exits.len());
+        for (index, (label, exit, count, throws, locals)) in
+            exits.into_iter().enumerate()
+        {
+            stubs.push(ExitStub {
+                exit,
+                offset: ops.offset().0,
+                count,
+                throws,
+                locals,
+            });
+            #[cfg(target_arch = "x86_64")]
+            dynasm!(ops
+                ; =>label
+                ; mov rax, index as i32
+            );
+            // Epilogue for dynamically compiled code.
+            epilogue!(ops);
+        }
+        // Out of fuel, resume interpreting at the start of the trace.
         #[cfg(target_arch = "x86_64")]
         dynasm!(ops
-            ; ->abort_guard:
-            ; mov rax, exit_pc as _
+            ; ->safepoint:
+            ; mov rax, SAFEPOINT
         );
-        // Epilogue for dynamically compiled code.
         epilogue!(ops);
+        // Prologue for dynamically compiled code.
+        let offset = prologue!(ops, frame_size(self.spills.len()));
+        #[cfg(target_arch = "x86_64")]
+        dynasm!(ops
+            ; jmp ->body
+        );
 
-        let buf = ops.finalize().unwrap();
+        let buf = ops.finalize().map_err(|_| JitError {
+            pc,
+            kind: JitErrorKind::Assembler(
+                "failed to finalize executable buffer".to_string(),
+            ),
+        })?;
 
-        let native_trace = NativeTrace(offset, buf);
-        self.traces.insert(pc, native_trace);
+        self.install(pc, NativeTrace(offset, buf, recording.len()));
+        self.exit_stubs.insert(pc, stubs);
+        self.locals
+            .insert(pc, recording.exit_locals(recording.len()));
+        // Locals the verifier proved the type of aren't checked.
+        let guards = recording
+            .live_in()
+            .into_iter()
+            .filter(|(local, kind)| recording.entry_type(*local) != Some(*kind))
+            .collect();
+        self.entry_guards.insert(pc, guards);
+        Ok(())
     }
 
     /// Emit a move operation, this includes all data movement operations
-    /// register to register and immediate to register.
+    /// register to register and immediate to register. Locals and spill
+    /// slots hold 32-bit integers, memory is moved as DWORDs so that stores
+    /// don't clobber the next slot.
     fn emit_mov(ops: &mut Assembler, dst: &Operand, src: &Operand) {
         match (dst, src) {
             (Operand::Register(dst), Operand::Register(src)) => {
@@ -513,13 +1389,13 @@ impl JitCache {
             (Operand::Register(dst), Operand::Memory(base, offset)) => {
                 #[cfg(target_arch = "x86_64")]
                 dynasm!(ops
-                    ;mov Rq(*dst as u8), [Rq(*base as u8) + *offset]
+                    ;mov Rd(*dst as u8), DWORD [Rq(*base as u8) + *offset]
                 );
             }
             (Operand::Memory(base, offset), Operand::Register(src)) => {
                 #[cfg(target_arch = "x86_64")]
                 dynasm!(ops
-                    ; mov [Rq(*base as u8) + *offset], Rq(*src as u8)
+                    ; mov DWORD [Rq(*base as u8) + *offset], Rd(*src as u8)
                 );
             }
             (Operand::Memory(base, offset), Operand::Immediate(imm)) => {
@@ -528,6 +1404,15 @@ impl JitCache {
                         ; mov DWORD [Rq(*base as u8) + *offset], *imm as _
                 );
             }
+            // Spilled values are stored through `rdx` which the allocator
+            // never hands out.
+            (Operand::Memory(base, offset), Operand::Memory(src, from)) => {
+                #[cfg(target_arch = "x86_64")]
+                dynasm!(ops
+                    ; mov edx, DWORD [Rq(*src as u8) + *from]
+                    ; mov DWORD [Rq(*base as u8) + *offset], edx
+                );
+            }
             _ => unreachable!(
                 "Unexpected operands for `mov` `dst`={:?}, `src`={:?})",
                 dst, src
@@ -536,29 +1421,26 @@ impl JitCache {
     }
 
     /// Emit an arithmetic operation, covers only simple instructions such as
-    /// `add`, `mul` and `sub`.
-    fn emit_arithmetic(&mut self, ops: &mut Assembler, op: Inst) {
-        let rhs = match self.operands.pop() {
-            Some(rhs) => rhs,
-            None => panic!("expected operand found None"),
-        };
-        let lhs = match self.operands.pop() {
-            Some(lhs) => lhs,
-            None => panic!("expected operand found None"),
-        };
+    /// `add`, `mul`, `sub` and the bitwise `and`, `or` and `xor`.
+    fn emit_arithmetic(
+        &mut self,
+        ops: &mut Assembler,
+        op: Inst,
+    ) -> Result<(), JitErrorKind> {
+        let missing = JitErrorKind::MissingOperand(op.opcode());
+        let rhs = self.operands.pop().ok_or_else(|| missing.clone())?;
+        let lhs = self.operands.pop().ok_or(missing)?;
 
         let dst = match &lhs {
             &Operand::Register(reg) => Operand::Register(reg),
-            // TODO: need to mov lhs operand to the first free register.
             _ => {
-                let dst = self.first_available_register();
+                let dst = self.first_available_register(ops)?;
                 JitCache::emit_mov(ops, &dst, &lhs);
+                self.release(lhs);
                 dst
             }
         };
-        if let Operand::Register(reg) = &rhs {
-            self.registers.push_back(*reg)
-        }
+        self.release(rhs);
 
         self.operands.push(dst);
 
@@ -640,137 +1522,858 @@ impl JitCache {
                     },
                 }
             }
-            _ => unreachable!("emit_arithmetic only supports simple x86-64 arithmetic (add, sub and mul).)"),
-        }
-    }
-
-    /// Emit division operation.
-    fn emit_div(&mut self, ops: &mut Assembler, op: Inst) {
-        let rdx = Register::Rdx;
-        let rax = Register::Rax;
-
-        let denom = match self.operands.pop() {
-            Some(operand) => operand,
-            _ => {
-                unreachable!("Expected operand for `idiv` and `irem` got None")
+            Inst::And => {
+                let Operand::Register(dst) = dst else {
+                    unreachable!("Unexpected enum variant for `Operand` expected `Register` got {:?}", dst)
+                };
+                match rhs {
+                    Operand::Register(src) => {
+                        #[cfg(target_arch = "x86_64")]
+                        dynasm!(ops
+                                ; and Rq(dst as u8), Rq(src as u8)
+                        );
+                    },
+                    Operand::Immediate(val) => {
+                        #[cfg(target_arch = "x86_64")]
+                        dynasm!(ops
+                                ; and Rq(dst as u8), val as _
+                        );
+                    },
+                    Operand::Memory(base, offset) => {
+                        #[cfg(target_arch = "x86_64")]
+                        dynasm!(ops
+                                ; and Rq(dst as u8), [Rq(base as u8) + offset]
+                        );
+                    },
+                }
             }
-        };
-
-        if let Some(nom) = self.free_register() {
-            JitCache::emit_mov(ops, &Operand::Register(Register::Rax), &nom);
-        }
-        let dst = match denom {
-            Operand::Register(reg) => Operand::Register(reg),
-            _ => {
-                let reg = self.first_available_register();
-                JitCache::emit_mov(ops, &reg, &denom);
-                reg
+            Inst::Or => {
+                let Operand::Register(dst) = dst else {
+                    unreachable!("Unexpected enum variant for `Operand` expected `Register` got {:?}", dst)
+                };
+                match rhs {
+                    Operand::Register(src) => {
+                        #[cfg(target_arch = "x86_64")]
+                        dynasm!(ops
+                                ; or Rq(dst as u8), Rq(src as u8)
+                        );
+                    },
+                    Operand::Immediate(val) => {
+                        #[cfg(target_arch = "x86_64")]
+                        dynasm!(ops
+                                ; or Rq(dst as u8), val as _
+                        );
+                    },
+                    Operand::Memory(base, offset) => {
+                        #[cfg(target_arch = "x86_64")]
+                        dynasm!(ops
+                                ; or Rq(dst as u8), [Rq(base as u8) + offset]
+                        );
+                    },
+                }
             }
-        };
-
-        let src = match op {
-            // x86 division rax holds divident rdx holds modulo.
-            Inst::IDiv => rax,
-            Inst::IRem => rdx,
-            _ => unreachable!("emit_div expected op to be idiv or irem"),
-        };
-
-        #[cfg(target_arch = "x86_64")]
-        let Operand::Register(dst_reg) = dst
-        else {
-            unreachable!("Unexpected enum variant for `Operand` expected `Register` got {:?}", dst)
-        };
-        dynasm!(ops
-            ; mov Rq(rdx as u8), 0
-            ; div Rq(dst_reg as u8)
-        );
-        JitCache::emit_mov(ops, &dst, &Operand::Register(src));
-        self.operands.push(dst);
+            Inst::Xor => {
+                let Operand::Register(dst) = dst else {
+                    unreachable!("Unexpected enum variant for `Operand` expected `Register` got {:?}", dst)
+                };
+                match rhs {
+                    Operand::Register(src) => {
+                        #[cfg(target_arch = "x86_64")]
+                        dynasm!(ops
+                                ; xor Rq(dst as u8), Rq(src as u8)
+                        );
+                    },
+                    Operand::Immediate(val) => {
+                        #[cfg(target_arch = "x86_64")]
+                        dynasm!(ops
+                                ; xor Rq(dst as u8), val as _
+                        );
+                    },
+                    Operand::Memory(base, offset) => {
+                        #[cfg(target_arch = "x86_64")]
+                        dynasm!(ops
+                                ; xor Rq(dst as u8), [Rq(base as u8) + offset]
+                        );
+                    },
+                }
+            }
+            _ => unreachable!("emit_arithmetic only supports simple x86-64 arithmetic (add, sub, mul and bitwise ops).)"),
+        }
+        Ok(())
     }
 
-    /// Emit conditional branch for the given instruction.
-    fn emit_cond_branch(&mut self, ops: &mut Assembler, cond: OPCode) {
-        let rhs = match self.free_register() {
-            Some(operand) => operand,
-            None => panic!("expected operand found None"),
+    /// Emit division operation, x86 divides `edx:eax` so `rax` is taken
+    /// from the allocator for the result. The numerator is sign extended
+    /// into `edx` like Java's signed 32-bit `idiv`, a zero divisor jumps to
+    /// `throw`. Returns false if the divisor is a constant that isn't zero
+    /// and `throw` isn't used.
+    fn emit_div(
+        &mut self,
+        ops: &mut Assembler,
+        op: Inst,
+        throw: DynamicLabel,
+    ) -> Result<bool, JitErrorKind> {
+        let rdx = Register::Rdx;
+        let rax = Register::Rax;
+
+        let missing = JitErrorKind::MissingOperand(op.opcode());
+        let denom = self.operands.pop().ok_or_else(|| missing.clone())?;
+        let may_throw =
+            !matches!(denom, Operand::Immediate(value) if value != 0);
+        let mut nom = self.operands.pop().ok_or(missing)?;
+        // Keep the divisor out of `rax`.
+        let denom = match denom {
+            Operand::Register(reg) if reg != rax => denom,
+            _ => {
+                self.operands.push(nom);
+                self.reserve(ops, rax)?;
+                let dst = self.first_available_register(ops)?;
+                // The numerator may have been moved by the allocator.
+                nom = self.operands.pop().expect("numerator was pushed");
+                JitCache::emit_mov(ops, &dst, &denom);
+                if denom != Operand::Register(rax) {
+                    self.release(denom);
+                }
+                dst
+            }
         };
-        let lhs = match self.free_register() {
-            Some(operand) => operand,
-            None => todo!("Expected register in operand stack found None"),
+        if nom != Operand::Register(rax) {
+            self.reserve(ops, rax)?;
+            JitCache::emit_mov(ops, &Operand::Register(rax), &nom);
+            self.release(nom);
+        }
+
+        let Operand::Register(denom_reg) = denom else {
+            unreachable!("Unexpected enum variant for `Operand` expected `Register` got {:?}", denom)
+        };
+        // `idiv` faults on `i32::MIN / -1` which Java wraps, dividing by
+        // -1 negates the numerator and leaves no remainder.
+        if may_throw {
+            #[cfg(target_arch = "x86_64")]
+            dynasm!(ops
+                ; test Rd(denom_reg as u8), Rd(denom_reg as u8)
+                ; jz =>throw
+            );
+        }
+        #[cfg(target_arch = "x86_64")]
+        dynasm!(ops
+            ; cmp Rd(denom_reg as u8), -1
+            ; jne >divide
+            ; neg eax
+            ; xor edx, edx
+            ; jmp >done
+            ; divide:
+            ; cdq
+            ; idiv Rd(denom_reg as u8)
+            ; done:
+        );
+        // x86 division rax holds the quotient rdx the remainder.
+        if let Inst::IRem = op {
+            JitCache::emit_mov(
+                ops,
+                &Operand::Register(rax),
+                &Operand::Register(rdx),
+            );
+        }
+        self.release(denom);
+        self.operands.push(Operand::Register(rax));
+        Ok(may_throw)
+    }
+
+    /// Emit a shift, x86 shifts by an immediate or by `cl` so `rcx` is taken
+    /// from the allocator for variable counts. Shifts operate on the 32-bit
+    /// register which masks the count to its low five bits like Java does.
+    fn emit_shift(
+        &mut self,
+        ops: &mut Assembler,
+        op: Inst,
+    ) -> Result<(), JitErrorKind> {
+        let rcx = Register::Rcx;
+
+        let missing = JitErrorKind::MissingOperand(op.opcode());
+        let mut count = self.operands.pop().ok_or_else(|| missing.clone())?;
+        let mut value = self.operands.pop().ok_or(missing)?;
+        if !matches!(
+            count,
+            Operand::Immediate(_) | Operand::Register(Register::Rcx)
+        ) {
+            self.operands.push(value);
+            self.operands.push(count);
+            self.reserve(ops, rcx)?;
+            // The operands may have been moved by the allocator.
+            count = self.operands.pop().expect("count was pushed");
+            value = self.operands.pop().expect("value was pushed");
+            JitCache::emit_mov(ops, &Operand::Register(rcx), &count);
+            self.release(count);
+            count = Operand::Register(rcx);
+        }
+        let dst = match value {
+            Operand::Register(reg) => reg,
+            _ => {
+                let Operand::Register(dst) =
+                    self.first_available_register(ops)?
+                else {
+                    unreachable!("the allocator hands out registers")
+                };
+                JitCache::emit_mov(ops, &Operand::Register(dst), &value);
+                self.release(value);
+                dst
+            }
+        };
+        match (op, count) {
+            (Inst::Shl, Operand::Immediate(bits)) => {
+                #[cfg(target_arch = "x86_64")]
+                dynasm!(ops
+                    ; shl Rd(dst as u8), (bits & 31) as i8
+                );
+            }
+            (Inst::Shr, Operand::Immediate(bits)) => {
+                #[cfg(target_arch = "x86_64")]
+                dynasm!(ops
+                    ; sar Rd(dst as u8), (bits & 31) as i8
+                );
+            }
+            (Inst::UShr, Operand::Immediate(bits)) => {
+                #[cfg(target_arch = "x86_64")]
+                dynasm!(ops
+                    ; shr Rd(dst as u8), (bits & 31) as i8
+                );
+            }
+            (Inst::Shl, _) => {
+                #[cfg(target_arch = "x86_64")]
+                dynasm!(ops
+                    ; shl Rd(dst as u8), cl
+                );
+            }
+            (Inst::Shr, _) => {
+                #[cfg(target_arch = "x86_64")]
+                dynasm!(ops
+                    ; sar Rd(dst as u8), cl
+                );
+            }
+            (Inst::UShr, _) => {
+                #[cfg(target_arch = "x86_64")]
+                dynasm!(ops
+                    ; shr Rd(dst as u8), cl
+                );
+            }
+            _ => unreachable!("emit_shift only supports shl, sar and shr"),
+        }
+        self.release(count);
+        self.operands.push(Operand::Register(dst));
+        Ok(())
+    }
+
+    /// Emit `ineg`, the operand is negated in a register.
+    fn emit_neg(&mut self, ops: &mut Assembler) -> Result<(), JitErrorKind> {
+        let operand = self
+            .operands
+            .pop()
+            .ok_or(JitErrorKind::MissingOperand(OPCode::INeg))?;
+        let dst = match operand {
+            Operand::Register(reg) => reg,
+            _ => {
+                let Operand::Register(dst) =
+                    self.first_available_register(ops)?
+                else {
+                    unreachable!("the allocator hands out registers")
+                };
+                JitCache::emit_mov(ops, &Operand::Register(dst), &operand);
+                self.release(operand);
+                dst
+            }
+        };
+        #[cfg(target_arch = "x86_64")]
+        dynasm!(ops
+            ; neg Rd(dst as u8)
+        );
+        self.operands.push(Operand::Register(dst));
+        Ok(())
+    }
+
+    /// Assemble the vectorized form of `recording`.
+    ///
+    /// The native loop takes the locals in `rdi`, the fuel in `rsi`, the
+    /// addresses of the arrays in `rdx` and the number of elements it may
+    /// process in `ecx`, the index is kept in `eax` and the arrays in `r8`
+    /// to `r11`. The vector loop handles `LANES` elements per iteration
+    /// using SSE2 and the scalar epilogue the remaining ones, every element
+    /// burns the fuel of an iteration of the trace.
+    #[cfg(target_arch = "x86_64")]
+    fn assemble_vector(
+        &mut self,
+        recording: &Trace,
+        vector_loop: VectorLoop,
+    ) -> Result<(), JitError> {
+        let pc = recording.start();
+        let mut ops = Assembler::new().map_err(|err| JitError {
+            pc,
+            kind: JitErrorKind::Assembler(err.to_string()),
+        })?;
+        let bases = [Register::R8, Register::R9, Register::R10, Register::R11];
+        let index = 4 * vector_loop.index as i32;
+        let fuel = recording.len() as i32;
+        let lanes = vectorize::LANES as i32;
+        let (vector, scalar, done, iota) = (
+            ops.new_dynamic_label(),
+            ops.new_dynamic_label(),
+            ops.new_dynamic_label(),
+            ops.new_dynamic_label(),
+        );
+
+        let entry = ops.offset();
+        dynasm!(ops
+            ; mov eax, [rdi + index]
+        );
+        for (slot, base) in bases.iter().enumerate() {
+            if slot < vector_loop.arrays().len() {
+                dynasm!(ops
+                    ; mov Rq(*base as u8), [rdx + 8 * slot as i32]
+                );
+            }
+        }
+        dynasm!(ops
+            ; movdqu xmm15, [=>iota]
+            ; =>vector
+            ; lea edx, [rax + lanes]
+            ; cmp edx, ecx
+            ; jg =>scalar
+        );
+        Self::emit_vector_expr(
+            &mut ops,
+            &vector_loop,
+            &vector_loop.expr,
+            0,
+            true,
+        );
+        Self::emit_vector_store(&mut ops, &vector_loop, true);
+        dynasm!(ops
+            ; add eax, lanes
+            ; sub DWORD [rsi], fuel * lanes
+            ; jle =>done
+            ; jmp =>vector
+            ; =>scalar
+            ; cmp eax, ecx
+            ; jge =>done
+        );
+        Self::emit_vector_expr(
+            &mut ops,
+            &vector_loop,
+            &vector_loop.expr,
+            0,
+            false,
+        );
+        Self::emit_vector_store(&mut ops, &vector_loop, false);
+        dynasm!(ops
+            ; add eax, 1
+            ; sub DWORD [rsi], fuel
+            ; jle =>done
+            ; jmp =>scalar
+            ; =>done
+            ; mov [rdi + index], eax
+            ; ret
+            ; .align 16
+            ; =>iota
+            ; .dword 0, 1, 2, 3
+        );
+
+        let buf = ops.finalize().map_err(|_| JitError {
+            pc,
+            kind: JitErrorKind::Assembler(
+                "failed to finalize executable buffer".to_string(),
+            ),
+        })?;
+        self.install(pc, NativeTrace(entry, buf, recording.len()));
+        self.vector_loops.insert(pc, vector_loop);
+        Ok(())
+    }
+
+    /// Emit `expr` into `xmm<depth>`, operands go to the registers above
+    /// it. Packed code computes every lane, scalar code only the lowest.
+    #[cfg(target_arch = "x86_64")]
+    fn emit_vector_expr(
+        ops: &mut Assembler,
+        vector_loop: &VectorLoop,
+        expr: &Expr,
+        depth: u8,
+        packed: bool,
+    ) {
+        let float = vector_loop.element == Element::Float;
+        match expr {
+            Expr::Load(local) => {
+                let base = Self::vector_base(vector_loop, *local);
+                match (packed, float) {
+                    (true, _) => dynasm!(ops
+                        ; movdqu Rx(depth), [Rq(base as u8) + rax * 4]
+                    ),
+                    (false, false) => dynasm!(ops
+                        ; movd Rx(depth), [Rq(base as u8) + rax * 4]
+                    ),
+                    (false, true) => dynasm!(ops
+                        ; movss Rx(depth), [Rq(base as u8) + rax * 4]
+                    ),
+                }
+            }
+            Expr::Index => {
+                dynasm!(ops
+                    ; movd Rx(depth), eax
+                );
+                if packed {
+                    dynasm!(ops
+                        ; pshufd Rx(depth), Rx(depth), 0
+                        ; paddd Rx(depth), xmm15
+                    );
+                }
+            }
+            Expr::Local(local) => {
+                dynasm!(ops
+                    ; movd Rx(depth), [rdi + 4 * *local as i32]
+                );
+                if packed {
+                    dynasm!(ops
+                        ; pshufd Rx(depth), Rx(depth), 0
+                    );
+                }
+            }
+            Expr::Const(bits) => {
+                dynasm!(ops
+                    ; mov edx, *bits as i32
+                    ; movd Rx(depth), edx
+                );
+                if packed {
+                    dynasm!(ops
+                        ; pshufd Rx(depth), Rx(depth), 0
+                    );
+                }
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                Self::emit_vector_expr(ops, vector_loop, lhs, depth, packed);
+                Self::emit_vector_expr(
+                    ops,
+                    vector_loop,
+                    rhs,
+                    depth + 1,
+                    packed,
+                );
+                let (dst, src) = (depth, depth + 1);
+                match (op, float, packed) {
+                    (VectorOp::Add, false, _) => dynasm!(ops
+                        ; paddd Rx(dst), Rx(src)
+                    ),
+                    (VectorOp::Sub, false, _) => dynasm!(ops
+                        ; psubd Rx(dst), Rx(src)
+                    ),
+                    (VectorOp::Add, true, true) => dynasm!(ops
+                        ; addps Rx(dst), Rx(src)
+                    ),
+                    (VectorOp::Sub, true, true) => dynasm!(ops
+                        ; subps Rx(dst), Rx(src)
+                    ),
+                    (VectorOp::Mul, true, true) => dynasm!(ops
+                        ; mulps Rx(dst), Rx(src)
+                    ),
+                    (VectorOp::Div, true, true) => dynasm!(ops
+                        ; divps Rx(dst), Rx(src)
+                    ),
+                    (VectorOp::Add, true, false) => dynasm!(ops
+                        ; addss Rx(dst), Rx(src)
+                    ),
+                    (VectorOp::Sub, true, false) => dynasm!(ops
+                        ; subss Rx(dst), Rx(src)
+                    ),
+                    (VectorOp::Mul, true, false) => dynasm!(ops
+                        ; mulss Rx(dst), Rx(src)
+                    ),
+                    (VectorOp::Div, true, false) => dynasm!(ops
+                        ; divss Rx(dst), Rx(src)
+                    ),
+                    (VectorOp::Mul | VectorOp::Div, false, _) => {
+                        unreachable!("integer {op:?} isn't vectorized")
+                    }
+                }
+            }
+        }
+    }
+
+    /// Emit the store of `xmm0` to the stored array at the index.
+    #[cfg(target_arch = "x86_64")]
+    fn emit_vector_store(
+        ops: &mut Assembler,
+        vector_loop: &VectorLoop,
+        packed: bool,
+    ) {
+        let base = Self::vector_base(vector_loop, vector_loop.store);
+        match (packed, vector_loop.element) {
+            (true, _) => dynasm!(ops
+                ; movdqu [Rq(base as u8) + rax * 4], xmm0
+            ),
+            (false, Element::Int) => dynasm!(ops
+                ; movd [Rq(base as u8) + rax * 4], xmm0
+            ),
+            (false, Element::Float) => dynasm!(ops
+                ; movss [Rq(base as u8) + rax * 4], xmm0
+            ),
+        }
+    }
+
+    /// Returns the register holding the address of the array in `local`.
+    fn vector_base(vector_loop: &VectorLoop, local: usize) -> Register {
+        let bases = [Register::R8, Register::R9, Register::R10, Register::R11];
+        let slot = vector_loop
+            .arrays()
+            .iter()
+            .position(|array| *array == local)
+            .expect("arrays of the loop have a slot");
+        bases[slot]
+    }
+
+    /// Emit a range guard, the local is checked against both bounds with a
+    /// single unsigned comparison and the trace leaves through `exit` when
+    /// it's out of range.
+    fn emit_range_guard(
+        &mut self,
+        ops: &mut Assembler,
+        guard: &RangeGuard,
+        exit: DynamicLabel,
+    ) -> Result<(), JitErrorKind> {
+        let Operand::Register(reg) = self.first_available_register(ops)? else {
+            unreachable!("first_available_register returns registers")
         };
+        let span = guard.high.wrapping_sub(guard.low);
+        #[cfg(target_arch = "x86_64")]
+        dynasm!(ops
+            ; mov Rd(reg as u8), DWORD [Rq(Register::Rdi as u8) + 4 * guard.local]
+            ; sub Rd(reg as u8), guard.low
+            ; cmp Rd(reg as u8), span
+            ; ja =>exit
+        );
+        self.registers.push_back(reg);
+        Ok(())
+    }
+
+    /// Emit the guard of a switch on the key on top of the operand stack.
+    /// Keys of the recorded case stay on trace, the others jump to the
+    /// label of their target in `targets` and the keys no case matches to
+    /// the default's, last, unless the switch took its default.
+    fn emit_switch_guard(
+        &mut self,
+        ops: &mut Assembler,
+        opcode: OPCode,
+        guard: &SwitchGuard,
+        targets: &[(DynamicLabel, ProgramCounter, Vec<KeyRange>)],
+    ) -> Result<(), JitErrorKind> {
+        let key = self
+            .free_register()
+            .ok_or(JitErrorKind::MissingOperand(opcode))?;
+        // The key is kept in `edx` which the allocator never hands out.
+        #[cfg(target_arch = "x86_64")]
+        match key {
+            Operand::Register(reg) => dynasm!(ops
+                ; mov edx, Rd(reg as u8)
+            ),
+            Operand::Memory(base, offset) => dynasm!(ops
+                ; mov edx, DWORD [Rq(base as u8) + offset]
+            ),
+            Operand::Immediate(imm) => dynasm!(ops
+                ; mov edx, imm
+            ),
+        }
+        let stay = ops.new_dynamic_label();
+        let taken = guard.taken_keys();
+        for (low, high) in taken.iter().flatten() {
+            Self::emit_key_check(ops, *low, *high, stay);
+        }
+        for (label, _, ranges) in targets {
+            for (low, high) in ranges {
+                Self::emit_key_check(ops, *low, *high, *label);
+            }
+        }
+        if let (Some(_), Some((default, _, _))) = (taken, targets.last()) {
+            #[cfg(target_arch = "x86_64")]
+            dynasm!(ops
+                ; jmp =>*default
+            );
+        }
+        #[cfg(target_arch = "x86_64")]
+        dynasm!(ops
+            ; =>stay
+        );
+        Ok(())
+    }
+
+    /// Emit a jump to `label` taken when `low <= edx <= high`.
+    fn emit_key_check(
+        ops: &mut Assembler,
+        low: i32,
+        high: i32,
+        label: DynamicLabel,
+    ) {
+        #[cfg(target_arch = "x86_64")]
+        if low == high {
+            dynasm!(ops
+                ; cmp edx, low
+                ; je =>label
+            );
+        } else {
+            dynasm!(ops
+                ; cmp edx, low
+                ; jl >outside
+                ; cmp edx, high
+                ; jle =>label
+                ; outside:
+            );
+        }
+    }
+
+    /// Emit the guard of a conditional branch, the code jumps to `exit` when
+    /// the branch doesn't go the way it went while recording, `taken`.
+    ///
+    /// Only the low 32 bits of the operands are compared since locals are
+    /// loaded with their neighbour in the upper half.
+    fn emit_cond_branch(
+        &mut self,
+        ops: &mut Assembler,
+        cond: OPCode,
+        taken: bool,
+        exit: DynamicLabel,
+    ) -> Result<(), JitErrorKind> {
+        let rhs = self
+            .free_register()
+            .ok_or(JitErrorKind::MissingOperand(cond))?;
+        let lhs = self
+            .free_register()
+            .ok_or(JitErrorKind::MissingOperand(cond))?;
 
         match (lhs, rhs) {
             (Operand::Register(lhs), Operand::Register(rhs)) => {
                 dynasm!(ops
-                    ; cmp Rq(lhs as u8), Rq(rhs as u8)
+                    ; cmp Rd(lhs as u8), Rd(rhs as u8)
                 );
             }
             (Operand::Register(lhs), Operand::Memory(base, offset)) => {
                 dynasm!(ops
-                    ; cmp Rq(lhs as u8), [Rq(base as u8) + offset]
+                    ; cmp Rd(lhs as u8), DWORD [Rq(base as u8) + offset]
                 );
             }
             (Operand::Register(lhs), Operand::Immediate(imm)) => {
                 dynasm!(ops
-                    ; cmp Rq(lhs as u8), imm as _
+                    ; cmp Rd(lhs as u8), imm
                 );
             }
             (Operand::Memory(base, offset), Operand::Register(rhs)) => {
                 dynasm!(ops
-                    ; cmp [Rq(base as u8) + offset], Rq(rhs as u8)
+                    ; cmp DWORD [Rq(base as u8) + offset], Rd(rhs as u8)
                 );
             }
             (Operand::Memory(base, offset), Operand::Immediate(imm)) => {
                 dynasm!(ops
-                    ; cmp [Rq(base as u8) + offset], imm as _
+                    ; cmp DWORD [Rq(base as u8) + offset], imm
                 );
             }
+            // Both operands can be spilled, x86 compares at most one
+            // memory operand.
+            (Operand::Memory(..), Operand::Memory(base, offset)) => {
+                self.operands.push(rhs);
+                let Operand::Register(lhs_reg) =
+                    self.first_available_register(ops)?
+                else {
+                    unreachable!("first_available_register returns registers")
+                };
+                self.operands.pop();
+                Self::emit_mov(ops, &Operand::Register(lhs_reg), &lhs);
+                dynasm!(ops
+                    ; cmp Rd(lhs_reg as u8), DWORD [Rq(base as u8) + offset]
+                );
+                self.registers.push_back(lhs_reg);
+            }
             _ => unreachable!(
                 "unsupported comparison between operands {:?} and {:?}",
                 lhs, rhs
             ),
         }
 
-        match cond {
-            OPCode::IfICmpGt => {
+        let exit_when = if taken { negate(cond) } else { cond };
+        match exit_when {
+            OPCode::IfICmpEq | OPCode::IfEq => {
                 dynasm!(ops
-                    ; jg ->abort_guard
+                    ; je =>exit
                 );
             }
-            OPCode::IfICmpGe => {
+            OPCode::IfICmpNe | OPCode::IfNe => {
                 dynasm!(ops
-                    ; jge ->abort_guard
+                    ; jne =>exit
                 );
             }
-            OPCode::IfICmpLe => {
+            OPCode::IfICmpLt | OPCode::IfLt => {
+                dynasm!(ops
+                    ; jl =>exit
+                );
+            }
+            OPCode::IfICmpGe | OPCode::IfGe => {
+                dynasm!(ops
+                    ; jge =>exit
+                );
+            }
+            OPCode::IfICmpGt | OPCode::IfGt => {
+                dynasm!(ops
+                    ; jg =>exit
+                );
+            }
+            OPCode::IfICmpLe | OPCode::IfLe => {
+                dynasm!(ops
+                    ; jle =>exit
+                );
+            }
+            _ => unreachable!("Expected instruction for conditional branch to be a if<cond> or if_icmp<cond> {:?}", cond)
+        }
+        Ok(())
+    }
+
+    /// Returns the first available register, spilling the deepest operand
+    /// held in a register when none is left.
+    /// Call the runtime helper `helper` on the operand at the top of the
+    /// stack and push its result, see `host`. The caller saved registers
+    /// holding operands are pushed around the call, padded to keep the stack
+    /// 16 bytes aligned, and the arguments of the trace are reloaded from
+    /// the frame. A helper returning `host::THROW` jumps to `throw`.
+    fn emit_host_call(
+        &mut self,
+        ops: &mut Assembler,
+        opcode: OPCode,
+        helper: Helper,
+        throw: DynamicLabel,
+    ) -> Result<(), JitErrorKind> {
+        let arg = self
+            .free_register()
+            .ok_or(JitErrorKind::MissingOperand(opcode))?;
+        let saved: Vec<Register> = self
+            .operands
+            .iter()
+            .filter_map(|operand| match operand {
+                Operand::Register(reg) if CALLER_SAVED.contains(reg) => {
+                    Some(*reg)
+                }
+                _ => None,
+            })
+            .collect();
+        let padding = if saved.len() % 2 == 1 { 8 } else { 0 };
+        Self::emit_mov(ops, &Operand::Register(Register::Rsi), &arg);
+        #[cfg(target_arch = "x86_64")]
+        {
+            // Helpers take 64-bit arguments.
+            dynasm!(ops
+                ; movsxd rsi, esi
+            );
+            for reg in &saved {
                 dynasm!(ops
-                    ; jle -> abort_guard
+                    ; push Rq(*reg as u8)
                 );
             }
-            OPCode::IfICmpEq => {
+            dynasm!(ops
+                ; sub rsp, padding
+                ; mov rdi, QWORD [rbp + CONTEXT_SLOT]
+                ; mov rax, QWORD helper.address() as i64
+                ; call rax
+                ; mov rdx, rax
+                ; add rsp, padding
+            );
+            for reg in saved.iter().rev() {
                 dynasm!(ops
-                    ; je -> abort_guard
+                    ; pop Rq(*reg as u8)
                 );
             }
-            _ => unreachable!("Expected instruction for conditional branch to be a if_icmp<cond> {:?}", cond)
+            dynasm!(ops
+                ; mov rdi, QWORD [rbp + LOCALS_SLOT]
+                ; mov rsi, QWORD [rbp + FUEL_SLOT]
+                ; cmp rdx, host::THROW as i32
+                ; je =>throw
+            );
+        }
+        let dst = self.first_available_register(ops)?;
+        Self::emit_mov(ops, &dst, &Operand::Register(Register::Rdx));
+        self.operands.push(dst);
+        Ok(())
+    }
+
+    fn first_available_register(
+        &mut self,
+        ops: &mut Assembler,
+    ) -> Result<Operand, JitErrorKind> {
+        if let Some(reg) = self.registers.pop_front() {
+            return Ok(Operand::Register(reg));
         }
+        let (index, reg) = self
+            .operands
+            .iter()
+            .enumerate()
+            .find_map(|(index, operand)| match operand {
+                Operand::Register(reg) => Some((index, *reg)),
+                _ => None,
+            })
+            .ok_or(JitErrorKind::OutOfRegisters)?;
+        let slot = Operand::Memory(Register::Rbp, self.spill_slot());
+        Self::emit_mov(ops, &slot, &Operand::Register(reg));
+        self.operands[index] = slot;
+        Ok(Operand::Register(reg))
     }
 
-    /// Returns the first available register.
-    fn first_available_register(&mut self) -> Operand {
-        if !self.registers.is_empty() {
-            let reg = self.registers.pop_front().unwrap();
-            Operand::Register(reg)
+    /// Returns the offset of a free spill slot, the frame grows by a slot
+    /// when every slot is in use.
+    fn spill_slot(&mut self) -> i32 {
+        let slot = match self.spills.iter().position(|used| !used) {
+            Some(slot) => slot,
+            None => {
+                self.spills.push(false);
+                self.spills.len() - 1
+            }
+        };
+        self.spills[slot] = true;
+        spill_offset(slot)
+    }
+
+    /// Move `reg` out of the way, the operand it holds is moved to another
+    /// register or spilled, and take it out of the available registers.
+    fn reserve(
+        &mut self,
+        ops: &mut Assembler,
+        reg: Register,
+    ) -> Result<(), JitErrorKind> {
+        if let Some(index) = self
+            .operands
+            .iter()
+            .position(|operand| *operand == Operand::Register(reg))
+        {
+            let dst = match self.registers.pop_front() {
+                Some(dst) => Operand::Register(dst),
+                None => Operand::Memory(Register::Rbp, self.spill_slot()),
+            };
+            Self::emit_mov(ops, &dst, &Operand::Register(reg));
+            self.operands[index] = dst;
         } else {
-            panic!("no available registers")
+            self.registers.retain(|free| *free != reg);
+        }
+        Ok(())
+    }
+
+    /// Release the register or spill slot holding `operand`.
+    fn release(&mut self, operand: Operand) {
+        match operand {
+            Operand::Register(reg) => self.registers.push_back(reg),
+            Operand::Memory(Register::Rbp, offset) => {
+                let slot = ((CONTEXT_SLOT - offset) / 8 - 1) as usize;
+                if let Some(used) = self.spills.get_mut(slot) {
+                    *used = false;
+                }
+            }
+            _ => {}
         }
     }
 
     /// Free the top most register in the operand stack.
     fn free_register(&mut self) -> Option<Operand> {
         let op = self.operands.pop();
-        if let Some(Operand::Register(reg)) = op {
-            self.registers.push_back(reg)
+        if let Some(op) = op {
+            self.release(op);
         }
         op
     }
@@ -811,4 +2414,834 @@ mod tests {
         "support/tests/HotLoop.class",
         Some(Value::Int(55))
     );
+
+    #[test]
+    fn spills_under_register_pressure() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/jit/Pressure.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.run(true).unwrap();
+        assert!(runtime.jit_errors().is_empty());
+        assert_eq!(stdout.to_string_lossy(), "70700\n");
+    }
+
+    #[test]
+    fn exits_through_range_guards() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/jit/Range.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.enable_stats();
+        runtime.run(true).unwrap();
+        assert!(runtime.jit_errors().is_empty());
+        assert_eq!(stdout.to_string_lossy(), "1730\n");
+        let (_, stats) = runtime.stats().unwrap().loops().next().unwrap();
+        assert!(stats.native_iterations > 0);
+    }
+
+    #[test]
+    fn stays_on_taken_branches() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/jit/Layout.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.enable_stats();
+        runtime.run(true).unwrap();
+        assert!(runtime.jit_errors().is_empty());
+        assert_eq!(stdout.to_string_lossy(), "18908\n");
+        let (_, stats) = runtime.stats().unwrap().loops().next().unwrap();
+        assert!(stats.native_iterations > stats.iterations);
+    }
+
+    #[test]
+    fn restores_locals_at_guard_exits() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/jit/Deopt.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.run(true).unwrap();
+        assert!(runtime.jit_errors().is_empty());
+        // The long and the double the loop doesn't touch keep their value.
+        assert_eq!(stdout.to_string_lossy(), "18908\n5000000000\n0.5\n");
+    }
+
+    #[test]
+    fn calls_runtime_helpers() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/jit/Alloc.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.enable_stats();
+        // The second loop allocates until the length turns negative.
+        let err = runtime.run(true).unwrap_err();
+        assert_eq!(
+            *err.kind(),
+            crate::runtime::RuntimeErrorKind::NegativeArraySize(-1)
+        );
+        assert!(runtime.jit_errors().is_empty());
+        assert_eq!(stdout.to_string_lossy(), "61168\n7\n");
+        for (_, stats) in runtime.stats().unwrap().loops() {
+            assert!(stats.native_iterations > stats.iterations);
+        }
+    }
+
+    #[test]
+    fn lays_out_hot_exits_first() {
+        use crate::bytecode::OPCode;
+        use crate::profiler::Profiler;
+        use crate::runtime::{Instruction, ProgramCounter};
+        use crate::trace::Recorder;
+
+        let pc = |index| ProgramCounter::new(0, index);
+        let inst = |opcode, operands: &[i32]| {
+            Instruction::new(
+                opcode,
+                Some(operands.iter().copied().map(Value::Int).collect()),
+            )
+        };
+        // while (i < 1000) { if (i % 100 == 99) { .. } else { s += i; } i++; }
+        let mut recorder = Recorder::new();
+        recorder.init(pc(4), pc(4));
+        for (index, inst) in [
+            (4, inst(OPCode::ILoad2, &[])),
+            (5, inst(OPCode::SiPush, &[1000])),
+            (8, inst(OPCode::IfICmpGe, &[28])),
+            (11, inst(OPCode::ILoad2, &[])),
+            (12, inst(OPCode::BiPush, &[100])),
+            (14, inst(OPCode::IRem, &[])),
+            (15, inst(OPCode::BiPush, &[99])),
+            (17, inst(OPCode::IfICmpNe, &[9])),
+            (26, inst(OPCode::ILoad1, &[])),
+            (27, inst(OPCode::ILoad2, &[])),
+            (28, inst(OPCode::IAdd, &[])),
+            (29, inst(OPCode::IStore1, &[])),
+            (30, inst(OPCode::IInc, &[2, 1])),
+            (33, inst(OPCode::Goto, &[-29])),
+        ] {
+            recorder.record(pc(index), inst, None);
+        }
+        let mut trace = recorder.recording();
+        let mut profiler = Profiler::new();
+        profiler.count_branch(&pc(8), true);
+        for taken in [true, true, false, false, false] {
+            profiler.count_branch(&pc(17), taken);
+        }
+        trace.profile_exits(&profiler);
+
+        let mut cache = super::JitCache::new();
+        cache.compile(&trace).unwrap();
+        let stubs = cache.exit_stubs(pc(4)).unwrap();
+        let exits: Vec<_> =
+            stubs.iter().map(|stub| (stub.exit, stub.count)).collect();
+        assert_eq!(exits, vec![(pc(20), 3), (pc(36), 1)]);
+        assert!(stubs[0].offset < stubs[1].offset);
+        assert!(stubs.iter().all(|stub| !stub.throws));
+    }
+
+    #[test]
+    fn rejects_traces_over_wide_locals() {
+        use crate::bytecode::OPCode;
+        use crate::error::JitErrorKind;
+        use crate::runtime::{Instruction, ProgramCounter};
+        use crate::trace::Recorder;
+
+        let pc = |index| ProgramCounter::new(0, index);
+        let inst = |opcode, operands: &[i32]| {
+            Instruction::new(
+                opcode,
+                Some(operands.iter().copied().map(Value::Int).collect()),
+            )
+        };
+        // while (true) { l = l; i++; }
+        let mut recorder = Recorder::new();
+        recorder.init(pc(0), pc(0));
+        for (index, inst) in [
+            (0, inst(OPCode::IInc, &[3, 1])),
+            (3, inst(OPCode::LLoad1, &[])),
+            (4, inst(OPCode::LStore1, &[])),
+            (5, inst(OPCode::Goto, &[-5])),
+        ] {
+            recorder.record(pc(index), inst, None);
+        }
+        let trace = recorder.recording();
+
+        let mut cache = super::JitCache::new();
+        let err = cache.compile(&trace).unwrap_err();
+        assert_eq!(err.pc, pc(3));
+        assert_eq!(err.kind, JitErrorKind::Unsupported(OPCode::LLoad));
+        assert!(!cache.has_native_trace(pc(0)));
+    }
+
+    #[test]
+    fn rejects_non_int_constants() {
+        use crate::bytecode::OPCode;
+        use crate::error::JitErrorKind;
+        use crate::runtime::{Instruction, ProgramCounter};
+        use crate::trace::Recorder;
+
+        let pc = |index| ProgramCounter::new(0, index);
+        // while (true) { s = "abc"; } and while (true) { i = (int) 0.5f; }
+        for (constant, store) in [
+            (Value::Reference(7), OPCode::AStore1),
+            (Value::Float(0.5), OPCode::F2I),
+        ] {
+            let mut recorder = Recorder::new();
+            recorder.init(pc(0), pc(0));
+            for (index, inst) in [
+                (0, Instruction::new(OPCode::Ldc, Some(vec![constant]))),
+                (2, Instruction::new(store, None)),
+                (
+                    3,
+                    Instruction::new(OPCode::Goto, Some(vec![Value::Int(-3)])),
+                ),
+            ] {
+                recorder.record(pc(index), inst, None);
+            }
+            let trace = recorder.recording();
+
+            let mut cache = super::JitCache::new();
+            let err = cache.compile(&trace).unwrap_err();
+            assert_eq!(err.pc, pc(0));
+            assert_eq!(err.kind, JitErrorKind::Unsupported(OPCode::Ldc));
+        }
+    }
+
+    #[test]
+    fn vectorizes_array_loops() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/jit/Vector.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.enable_stats();
+        runtime.run(true).unwrap();
+        assert!(runtime.jit_errors().is_empty());
+        // The arrays hold 103 elements, the last three are stored by the
+        // scalar epilogue.
+        assert_eq!(
+            stdout.to_string_lossy(),
+            "102\n7\n211\n104\n105\n154\n205\n206\n1.5\n0.875\n0.875\n"
+        );
+        let stats = runtime.stats().unwrap();
+        assert_eq!(stats.loops().count(), 6);
+        for (_, stats) in stats.loops() {
+            assert_eq!(stats.iterations + stats.native_iterations, 103);
+        }
+    }
+
+    fn native_trace(ops: &[u8]) -> super::NativeTrace {
+        let mut asm = dynasmrt::x64::Assembler::new().unwrap();
+        let entry = dynasmrt::DynasmApi::offset(&asm);
+        asm.extend(ops);
+        super::NativeTrace(entry, asm.finalize().unwrap(), 1)
+    }
+
+    #[test]
+    fn poisons_invalidated_traces() {
+        let pc = crate::runtime::ProgramCounter::new(0, 3);
+        let mut cache = super::JitCache::new();
+        cache.set_checks(true);
+        // mov eax, 1; ret
+        cache.install(pc, native_trace(&[0xb8, 0x01, 0x00, 0x00, 0x00, 0xc3]));
+        assert!(cache.has_native_trace(pc));
+        assert!(cache.invalidate(pc));
+        assert!(!cache.has_native_trace(pc));
+        assert!(!cache.invalidate(pc));
+        let poisoned = &cache.poisoned[0];
+        assert!(poisoned.iter().all(|byte| *byte == super::TRAP));
+    }
+
+    #[test]
+    fn evicts_least_recently_entered_traces() {
+        let pc = |index| crate::runtime::ProgramCounter::new(0, index);
+        let mut cache = super::JitCache::new();
+        cache.set_code_limit(Some(8));
+        // nop; nop; nop; ret
+        let code = [0x90, 0x90, 0x90, 0xc3];
+        cache.install(pc(1), native_trace(&code));
+        cache.install(pc(2), native_trace(&code));
+        assert_eq!(cache.total_code_size(), 8);
+        cache.touch(pc(1));
+        cache.install(pc(3), native_trace(&code));
+        assert!(!cache.has_native_trace(pc(2)));
+        assert_eq!(cache.take_evicted(), vec![pc(2)]);
+        assert!(cache.take_evicted().is_empty());
+        // A trace larger than the limit evicts every other one.
+        cache.install(pc(4), native_trace(&[0x90; 16]));
+        assert_eq!(cache.take_evicted(), vec![pc(1), pc(3)]);
+        assert_eq!(cache.total_code_size(), 16);
+    }
+
+    #[test]
+    #[should_panic(expected = "modified after compilation")]
+    fn verifies_trace_checksums() {
+        let pc = crate::runtime::ProgramCounter::new(0, 3);
+        let mut cache = super::JitCache::new();
+        cache.set_checks(true);
+        cache.install(pc, native_trace(&[0xc3]));
+        cache.verify(pc);
+        *cache.checksums.get_mut(&pc).unwrap() ^= 1;
+        cache.verify(pc);
+    }
+
+    mod differential {
+        use proptest::prelude::*;
+
+        use crate::bytecode::OPCode;
+        use crate::error::JitErrorKind;
+        use crate::runtime::{Instruction, ProgramCounter, Value};
+        use crate::trace::{Recorder, Trace};
+
+        /// Int locals come first, followed by a long, a float and a double
+        /// local. Longs and doubles take two slots like they do in the JVM.
+        const LOCALS: usize = 4;
+        const LONG: usize = 4;
+        const FLOAT: usize = 6;
+        const DOUBLE: usize = 7;
+        const SLOTS: usize = 9;
+
+        /// Right hand side of a random operation.
+        #[derive(Debug, Clone, Copy)]
+        enum Operand {
+            Local(usize),
+            Const(i16),
+        }
+
+        /// A random operation of the trace.
+        #[derive(Debug, Clone, Copy)]
+        enum Step {
+            /// `dst = lhs <op> rhs` on int locals.
+            Binary {
+                opcode: OPCode,
+                lhs: usize,
+                rhs: Operand,
+                dst: usize,
+            },
+            /// `dst = <op> src` on int locals, e.g `ineg` or `i2b`.
+            Unary {
+                opcode: OPCode,
+                src: usize,
+                dst: usize,
+            },
+            /// `local = local <op> local` on the long, float or double
+            /// local, e.g `ladd`.
+            Wide(OPCode),
+            /// Converts the int local to the long, float or double local
+            /// or back, e.g `i2l` or `d2i`.
+            Convert(OPCode, usize),
+        }
+
+        impl Step {
+            /// Returns true if the trace compiler lowers the operation.
+            fn is_compiled(self) -> bool {
+                match self {
+                    Self::Binary { .. } => true,
+                    Self::Unary { opcode, .. } => opcode == OPCode::INeg,
+                    Self::Wide(_) | Self::Convert(..) => false,
+                }
+            }
+        }
+
+        fn int(value: i32) -> Value {
+            Value::Int(value)
+        }
+
+        fn binary() -> impl Strategy<Value = Step> {
+            let opcode = proptest::sample::select(vec![
+                OPCode::IAdd,
+                OPCode::ISub,
+                OPCode::IMul,
+                OPCode::IDiv,
+                OPCode::IRem,
+                OPCode::Iand,
+                OPCode::IOr,
+                OPCode::IXor,
+                OPCode::IShl,
+                OPCode::IShr,
+                OPCode::IUShr,
+            ]);
+            let rhs = prop_oneof![
+                (0..LOCALS).prop_map(Operand::Local),
+                any::<i16>().prop_map(Operand::Const),
+            ];
+            (opcode, 0..LOCALS, rhs, 0..LOCALS).prop_map(
+                |(opcode, lhs, rhs, dst)| Step::Binary {
+                    opcode,
+                    lhs,
+                    rhs,
+                    dst,
+                },
+            )
+        }
+
+        fn unary() -> impl Strategy<Value = Step> {
+            let opcode = proptest::sample::select(vec![
+                OPCode::INeg,
+                OPCode::I2B,
+                OPCode::I2C,
+                OPCode::I2S,
+            ]);
+            (opcode, 0..LOCALS, 0..LOCALS)
+                .prop_map(|(opcode, src, dst)| Step::Unary { opcode, src, dst })
+        }
+
+        /// Operations on ints only.
+        fn int_step() -> impl Strategy<Value = Step> {
+            prop_oneof![3 => binary(), 1 => unary()]
+        }
+
+        /// Operations on locals of every type.
+        fn typed_step() -> impl Strategy<Value = Step> {
+            let wide = proptest::sample::select(vec![
+                OPCode::LAdd,
+                OPCode::LSub,
+                OPCode::LMul,
+                OPCode::LDiv,
+                OPCode::LRem,
+                OPCode::FAdd,
+                OPCode::FMul,
+                OPCode::FDiv,
+                OPCode::FRem,
+                OPCode::DAdd,
+                OPCode::DSub,
+                OPCode::DDiv,
+                OPCode::DRem,
+            ]);
+            let convert = proptest::sample::select(vec![
+                OPCode::I2L,
+                OPCode::I2F,
+                OPCode::I2D,
+                OPCode::L2I,
+                OPCode::F2I,
+                OPCode::D2I,
+            ]);
+            prop_oneof![
+                2 => int_step(),
+                1 => wide.prop_map(Step::Wide),
+                1 => (convert, 0..LOCALS)
+                    .prop_map(|(opcode, local)| Step::Convert(opcode, local)),
+            ]
+        }
+
+        /// Inputs biased towards the values that overflow.
+        fn input() -> impl Strategy<Value = i32> {
+            prop_oneof![
+                any::<i32>(),
+                Just(i32::MIN),
+                Just(i32::MAX),
+                Just(-1),
+                Just(0),
+                Just(1),
+            ]
+        }
+
+        /// Inputs of the long, float and double locals biased towards the
+        /// values that overflow and NaN.
+        fn typed_inputs() -> impl Strategy<Value = [Value; 3]> {
+            let long = prop_oneof![
+                any::<i64>(),
+                Just(i64::MIN),
+                Just(i64::MAX),
+                Just(-1),
+                Just(0),
+            ];
+            let float = prop_oneof![
+                any::<f32>(),
+                Just(f32::NAN),
+                Just(f32::INFINITY),
+                Just(f32::MAX),
+                Just(-0.0),
+            ];
+            let double = prop_oneof![
+                any::<f64>(),
+                Just(f64::NAN),
+                Just(f64::NEG_INFINITY),
+                Just(f64::MAX),
+                Just(-0.0),
+            ];
+            (long, float, double).prop_map(|(long, float, double)| {
+                [
+                    Value::Long(long),
+                    Value::Float(float),
+                    Value::Double(double),
+                ]
+            })
+        }
+
+        /// Returns the load, the store and the local of the type `opcode`
+        /// computes on.
+        fn wide_local(opcode: OPCode) -> (OPCode, OPCode, usize) {
+            match opcode {
+                OPCode::LAdd
+                | OPCode::LSub
+                | OPCode::LMul
+                | OPCode::LDiv
+                | OPCode::LRem
+                | OPCode::I2L
+                | OPCode::L2I => (OPCode::LLoad, OPCode::LStore, LONG),
+                OPCode::FAdd
+                | OPCode::FMul
+                | OPCode::FDiv
+                | OPCode::FRem
+                | OPCode::I2F
+                | OPCode::F2I => (OPCode::FLoad, OPCode::FStore, FLOAT),
+                _ => (OPCode::DLoad, OPCode::DStore, DOUBLE),
+            }
+        }
+
+        /// Record the loop `steps; goto start` as a trace.
+        fn record(steps: &[Step]) -> Trace {
+            let pc = |index| ProgramCounter::new(0, index);
+            let inst = |opcode, operand: Option<usize>| {
+                Instruction::new(opcode, operand.map(|x| vec![int(x as i32)]))
+            };
+            let mut insts = vec![];
+            for step in steps {
+                match *step {
+                    Step::Binary {
+                        opcode,
+                        lhs,
+                        rhs,
+                        dst,
+                    } => {
+                        insts.push(inst(OPCode::ILoad, Some(lhs)));
+                        insts.push(match rhs {
+                            Operand::Local(local) => {
+                                inst(OPCode::ILoad, Some(local))
+                            }
+                            Operand::Const(value) => Instruction::new(
+                                OPCode::SiPush,
+                                Some(vec![int(i32::from(value))]),
+                            ),
+                        });
+                        insts.push(inst(opcode, None));
+                        insts.push(inst(OPCode::IStore, Some(dst)));
+                    }
+                    Step::Unary { opcode, src, dst } => {
+                        insts.push(inst(OPCode::ILoad, Some(src)));
+                        insts.push(inst(opcode, None));
+                        insts.push(inst(OPCode::IStore, Some(dst)));
+                    }
+                    Step::Wide(opcode) => {
+                        let (load, store, local) = wide_local(opcode);
+                        insts.push(inst(load, Some(local)));
+                        insts.push(inst(load, Some(local)));
+                        insts.push(inst(opcode, None));
+                        insts.push(inst(store, Some(local)));
+                    }
+                    Step::Convert(opcode, int_local) => {
+                        let (load, store, local) = wide_local(opcode);
+                        let (load, store) = match opcode {
+                            OPCode::I2L | OPCode::I2F | OPCode::I2D => (
+                                inst(OPCode::ILoad, Some(int_local)),
+                                inst(store, Some(local)),
+                            ),
+                            _ => (
+                                inst(load, Some(local)),
+                                inst(OPCode::IStore, Some(int_local)),
+                            ),
+                        };
+                        insts.push(load);
+                        insts.push(inst(opcode, None));
+                        insts.push(store);
+                    }
+                }
+            }
+            insts.push(Instruction::new(
+                OPCode::Goto,
+                Some(vec![int(-(insts.len() as i32))]),
+            ));
+
+            let mut recorder = Recorder::new();
+            recorder.init(pc(0), pc(0));
+            for (index, inst) in insts.into_iter().enumerate() {
+                recorder.record(pc(index), inst, None);
+            }
+            recorder.recording()
+        }
+
+        /// Run an iteration of `trace` with the interpreter's arithmetic,
+        /// returns the offset of the division by zero if there is one.
+        fn interpret(trace: &Trace, locals: &mut [Value]) -> Result<(), usize> {
+            let mut stack = vec![];
+            for record in trace {
+                let inst = record.instruction();
+                let operand = || match inst.nth(0) {
+                    Some(Value::Int(x)) => x as usize,
+                    _ => unreachable!("recorded with an int operand"),
+                };
+                let value = match record.opcode() {
+                    OPCode::ILoad
+                    | OPCode::LLoad
+                    | OPCode::FLoad
+                    | OPCode::DLoad => locals[operand()],
+                    OPCode::SiPush => int(operand() as i32),
+                    OPCode::IStore
+                    | OPCode::LStore
+                    | OPCode::FStore
+                    | OPCode::DStore => {
+                        locals[operand()] = stack.pop().unwrap();
+                        continue;
+                    }
+                    OPCode::Goto => break,
+                    opcode @ (OPCode::INeg
+                    | OPCode::I2B
+                    | OPCode::I2C
+                    | OPCode::I2S
+                    | OPCode::I2L
+                    | OPCode::I2F
+                    | OPCode::I2D
+                    | OPCode::L2I
+                    | OPCode::F2I
+                    | OPCode::D2I) => {
+                        let value = stack.pop().unwrap();
+                        match opcode {
+                            OPCode::INeg => Value::neg(&value),
+                            OPCode::I2B => value.convert('B').unwrap(),
+                            OPCode::I2C => value.convert('C').unwrap(),
+                            OPCode::I2S => value.convert('S').unwrap(),
+                            OPCode::I2L => value.to_long(),
+                            OPCode::I2F => value.to_float(),
+                            OPCode::I2D => value.to_double(),
+                            _ => value.to_int(),
+                        }
+                    }
+                    opcode => {
+                        let rhs = stack.pop().unwrap();
+                        let lhs = stack.pop().unwrap();
+                        match opcode {
+                            OPCode::IAdd
+                            | OPCode::LAdd
+                            | OPCode::FAdd
+                            | OPCode::DAdd => Value::add(&lhs, &rhs),
+                            OPCode::ISub | OPCode::LSub | OPCode::DSub => {
+                                Value::sub(&lhs, &rhs)
+                            }
+                            OPCode::IMul | OPCode::LMul | OPCode::FMul => {
+                                Value::mul(&lhs, &rhs)
+                            }
+                            OPCode::Iand => Value::and(&lhs, &rhs),
+                            OPCode::IOr => Value::or(&lhs, &rhs),
+                            OPCode::IXor => Value::xor(&lhs, &rhs),
+                            OPCode::IShl => Value::shl(&lhs, &rhs),
+                            OPCode::IShr => Value::shr(&lhs, &rhs),
+                            OPCode::IUShr => Value::ushr(&lhs, &rhs),
+                            // Integer divisions by zero throw.
+                            _ if matches!(
+                                rhs,
+                                Value::Int(0) | Value::Long(0)
+                            ) =>
+                            {
+                                return Err(record.pc().get_instruction_index())
+                            }
+                            OPCode::IDiv
+                            | OPCode::LDiv
+                            | OPCode::FDiv
+                            | OPCode::DDiv => Value::div(&lhs, &rhs),
+                            _ => Value::rem(&lhs, &rhs),
+                        }
+                    }
+                };
+                stack.push(value);
+            }
+            Ok(())
+        }
+
+        /// Flatten `locals` into 32-bit slots like `JitCache::execute`
+        /// does, padded like it pads them.
+        fn flatten(locals: &[Value]) -> Vec<i32> {
+            let mut slots = vec![0; 8 * SLOTS];
+            for (slot, value) in locals
+                .iter()
+                .enumerate()
+                .filter(|(slot, _)| !is_second_slot(locals, *slot))
+            {
+                let (low, high) = match *value {
+                    Value::Int(x) => (x, None),
+                    Value::Long(x) => (x as i32, Some((x >> 32) as i32)),
+                    Value::Float(x) => (x.to_bits() as i32, None),
+                    Value::Double(x) => {
+                        let bits = x.to_bits();
+                        (bits as i32, Some((bits >> 32) as i32))
+                    }
+                    Value::Reference(x) => (x as i32, None),
+                };
+                slots[slot] = low;
+                if let Some(high) = high {
+                    slots[slot + 1] = high;
+                }
+            }
+            slots
+        }
+
+        /// Returns true if `slot` is the second slot of a long or a double
+        /// of `locals`.
+        fn is_second_slot(locals: &[Value], slot: usize) -> bool {
+            slot.checked_sub(1).is_some_and(|first| {
+                matches!(locals[first], Value::Long(_) | Value::Double(_))
+            })
+        }
+
+        /// Read the locals back from `slots` as the type they have in
+        /// `like`, second slots keep their value.
+        fn unflatten(slots: &[i32], like: &[Value]) -> Vec<Value> {
+            let wide = |slot: usize| {
+                u64::from(slots[slot + 1] as u32) << 32
+                    | u64::from(slots[slot] as u32)
+            };
+            like.iter()
+                .enumerate()
+                .map(|(slot, value)| match value {
+                    _ if is_second_slot(like, slot) => *value,
+                    Value::Long(_) => Value::Long(wide(slot) as i64),
+                    Value::Float(_) => {
+                        Value::Float(f32::from_bits(slots[slot] as u32))
+                    }
+                    Value::Double(_) => {
+                        Value::Double(f64::from_bits(wide(slot)))
+                    }
+                    _ => Value::Int(slots[slot]),
+                })
+                .collect()
+        }
+
+        /// Returns true if both locals hold the same value, NaNs being
+        /// equal whatever their payload.
+        fn same(lhs: &Value, rhs: &Value) -> bool {
+            match (lhs, rhs) {
+                (Value::Float(lhs), Value::Float(rhs)) => {
+                    lhs.to_bits() == rhs.to_bits()
+                        || lhs.is_nan() && rhs.is_nan()
+                }
+                (Value::Double(lhs), Value::Double(rhs)) => {
+                    lhs.to_bits() == rhs.to_bits()
+                        || lhs.is_nan() && rhs.is_nan()
+                }
+                _ => lhs == rhs,
+            }
+        }
+
+        /// Compile `trace` and run a single iteration of it natively,
+        /// returns the exit of the stub it left through, `None` when it ran
+        /// out of fuel, or the reason the trace wasn't compiled.
+        fn execute(
+            trace: &Trace,
+            locals: &mut [i32],
+        ) -> Result<Option<usize>, JitErrorKind> {
+            let mut cache = super::super::JitCache::new();
+            cache.compile(trace).map_err(|err| err.kind)?;
+            let native = &cache.traces[&trace.start()];
+            let execute: fn(*mut i32, *mut i32) -> i32 =
+                unsafe { std::mem::transmute(native.1.ptr(native.0)) };
+            // Loop back edges charge the length of the trace.
+            let mut fuel = trace.len() as i32;
+            let code = execute(locals.as_mut_ptr(), &mut fuel);
+            let stubs = cache.exit_stubs(trace.start()).unwrap();
+            let Ok(index) = usize::try_from(code) else {
+                return Ok(None);
+            };
+            let stub = &stubs[index];
+            assert!(stub.throws);
+            Ok(Some(stub.exit.get_instruction_index()))
+        }
+
+        /// Run `steps` on `inputs` natively and with the interpreter, the
+        /// trace must either be rejected or compute what the interpreter
+        /// does. Traces of operations the compiler lowers must compile.
+        fn check(
+            steps: &[Step],
+            inputs: &[Value],
+        ) -> Result<(), TestCaseError> {
+            let trace = record(steps);
+            let mut expected = inputs.to_vec();
+            // Java throws on division by zero, traces leave through a
+            // stub raising the exception at the division with the
+            // locals stored so far.
+            let expected_exit = interpret(&trace, &mut expected).err();
+
+            let mut slots = flatten(inputs);
+            match execute(&trace, &mut slots) {
+                Ok(exit) => {
+                    prop_assert_eq!(exit, expected_exit);
+                    let actual = unflatten(&slots, inputs);
+                    prop_assert!(
+                        actual
+                            .iter()
+                            .zip(&expected)
+                            .all(|(lhs, rhs)| same(lhs, rhs)),
+                        "{:?} != {:?}",
+                        actual,
+                        expected
+                    );
+                }
+                Err(kind) => {
+                    prop_assert!(
+                        matches!(kind, JitErrorKind::Unsupported(_)),
+                        "{:?}",
+                        kind
+                    );
+                    prop_assert!(!steps.iter().all(|step| step.is_compiled()));
+                }
+            }
+            Ok(())
+        }
+
+        proptest! {
+            // Straight line traces must compute what the interpreter does,
+            // wrapping on overflow and masking shift counts like Java.
+            #[test]
+            fn traces_match_the_interpreter(
+                steps in proptest::collection::vec(int_step(), 1..24),
+                inputs in proptest::collection::vec(input(), LOCALS),
+            ) {
+                let inputs: Vec<Value> = inputs.into_iter().map(int).collect();
+                check(&steps, &inputs)?;
+            }
+
+            // Traces over longs, floats and doubles, including NaN and
+            // overflowing inputs, are rejected or match the interpreter.
+            #[test]
+            fn typed_traces_are_rejected_or_match_the_interpreter(
+                steps in proptest::collection::vec(typed_step(), 1..12),
+                inputs in proptest::collection::vec(input(), LOCALS),
+                typed in typed_inputs(),
+            ) {
+                let [long, float, double] = typed;
+                let mut locals: Vec<Value> = inputs.into_iter().map(int).collect();
+                // The second slots of the long and the double stay unused.
+                locals.extend([long, int(0), float, double, int(0)]);
+                check(&steps, &locals)?;
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_the_stack_aligned() {
+        for spills in 0..8 {
+            let pushed = 8 * (super::CALLEE_SAVED.len() as i32 + 1);
+            assert_eq!((pushed + super::frame_size(spills)) % 16, 8);
+            assert!(
+                super::spill_offset(spills)
+                    >= -pushed - super::frame_size(spills)
+            );
+        }
+    }
 }
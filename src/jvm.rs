@@ -1,9 +1,19 @@
 //! Lightweight binary parser for Java class files.
-use byteorder::{BigEndian, ReadBytesExt};
+//!
+//! The parser only needs `core` and `alloc`, it works on byte slices and
+//! is available without the `std` feature. Reading class files from disk
+//! with `read_class_file` requires `std`.
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
 
-use std::collections::HashMap;
+pub use crate::jar::{JarError, JarErrorKind, JarLoader};
+
+#[cfg(feature = "std")]
 use std::io;
-use std::io::{Cursor, Read, Seek};
+#[cfg(feature = "std")]
 use std::path::Path;
 
 /// Values of magic bytes of a JVM class file.
@@ -66,6 +76,51 @@ pub enum CPInfo {
     Unspecified,
 }
 
+impl CPInfo {
+    /// Returns the value of a `CONSTANT_Integer` entry.
+    pub const fn int_value(&self) -> Option<i32> {
+        match self {
+            Self::ConstantInteger { bytes } => Some(*bytes as i32),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a `CONSTANT_Float` entry.
+    pub fn float_value(&self) -> Option<f32> {
+        match self {
+            Self::ConstantFloat { bytes } => Some(f32::from_bits(*bytes)),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a `CONSTANT_Long` entry, stored as its high and
+    /// low 32 bits in two's complement.
+    pub const fn long_value(&self) -> Option<i64> {
+        match self {
+            Self::ConstantLong { hi_bytes, lo_bytes } => {
+                Some(wide(*hi_bytes, *lo_bytes) as i64)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a `CONSTANT_Double` entry, stored as the high
+    /// and low 32 bits of its IEEE 754 bit pattern.
+    pub fn double_value(&self) -> Option<f64> {
+        match self {
+            Self::ConstantDouble { hi_bytes, lo_bytes } => {
+                Some(f64::from_bits(wide(*hi_bytes, *lo_bytes)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Combine the two halves of an 8 byte constant.
+const fn wide(hi: u32, lo: u32) -> u64 {
+    (hi as u64) << 32 | lo as u64
+}
+
 /// `ConstantKind` encodes the kind of a constant in the constants pool.
 #[repr(u8)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -191,6 +246,56 @@ pub struct ExceptionEntry {
     catch_type: u16,
 }
 
+impl ExceptionEntry {
+    /// Returns the offset of the first instruction covered by the handler.
+    #[must_use]
+    pub const fn start_pc(&self) -> u16 {
+        self.start_pc
+    }
+
+    /// Returns the offset following the last instruction covered by the
+    /// handler.
+    #[must_use]
+    pub const fn end_pc(&self) -> u16 {
+        self.end_pc
+    }
+
+    /// Returns the offset of the handler.
+    #[must_use]
+    pub const fn handler_pc(&self) -> u16 {
+        self.handler_pc
+    }
+
+    /// Returns the constant pool index of the caught class, 0 catches
+    /// everything.
+    #[must_use]
+    pub const fn catch_type(&self) -> u16 {
+        self.catch_type
+    }
+}
+
+/// Line number table entry, the instructions starting at `start_pc` come
+/// from `line_number` in the source file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LineNumber {
+    start_pc: u16,
+    line_number: u16,
+}
+
+impl LineNumber {
+    /// Returns the offset of the first instruction of the line.
+    #[must_use]
+    pub const fn start_pc(&self) -> u16 {
+        self.start_pc
+    }
+
+    /// Returns the line in the source file.
+    #[must_use]
+    pub const fn line_number(&self) -> u16 {
+        self.line_number
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AttributeInfo {
     ConstantValueAttribute {
@@ -202,13 +307,17 @@ pub enum AttributeInfo {
         max_locals: u16,
         code: Vec<u8>,
         exception_table: Vec<ExceptionEntry>,
-        attributes: HashMap<String, AttributeInfo>,
+        attributes: BTreeMap<String, AttributeInfo>,
         attribute_name: String,
     },
     StackMapTableAttribute {
         entries: Vec<StackMapFrame>,
         attribute_name: String,
     },
+    LineNumberTableAttribute {
+        line_numbers: Vec<LineNumber>,
+        attribute_name: String,
+    },
     SourceFileAttribute {
         source_file_index: u16,
         attribute_name: String,
@@ -232,7 +341,60 @@ pub struct FieldInfo {
     access_flag: u16,
     name_index: u16,
     descriptor_index: u16,
-    attributes: HashMap<String, AttributeInfo>,
+    attributes: BTreeMap<String, AttributeInfo>,
+}
+
+/// Access flag of public classes and members.
+pub const ACC_PUBLIC: u16 = 0x0001;
+/// Access flag of private members.
+pub const ACC_PRIVATE: u16 = 0x0002;
+/// Access flag of static members.
+pub const ACC_STATIC: u16 = 0x0008;
+/// Access flag of abstract classes and methods.
+pub const ACC_ABSTRACT: u16 = 0x0400;
+
+/// Kinds of `ConstantMethodHandle` entries.
+pub const REF_GET_FIELD: u8 = 1;
+pub const REF_GET_STATIC: u8 = 2;
+pub const REF_PUT_FIELD: u8 = 3;
+pub const REF_PUT_STATIC: u8 = 4;
+pub const REF_INVOKE_VIRTUAL: u8 = 5;
+pub const REF_INVOKE_STATIC: u8 = 6;
+pub const REF_INVOKE_SPECIAL: u8 = 7;
+pub const REF_NEW_INVOKE_SPECIAL: u8 = 8;
+pub const REF_INVOKE_INTERFACE: u8 = 9;
+
+impl FieldInfo {
+    /// Returns field info descriptor index.
+    #[must_use]
+    pub const fn descriptor_index(&self) -> u16 {
+        self.descriptor_index
+    }
+
+    /// Returns field info name index.
+    #[must_use]
+    pub const fn name_index(&self) -> u16 {
+        self.name_index
+    }
+
+    /// Returns field info access flags.
+    #[must_use]
+    pub const fn access_flags(&self) -> u16 {
+        self.access_flag
+    }
+
+    /// Returns the constant pool index of the field's `ConstantValue`
+    /// attribute, the initial value of constant static fields.
+    #[must_use]
+    pub fn constant_value(&self) -> Option<u16> {
+        match self.attributes.get("ConstantValue") {
+            Some(AttributeInfo::ConstantValueAttribute {
+                constant_value_index,
+                ..
+            }) => Some(*constant_value_index),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -240,7 +402,7 @@ pub struct MethodInfo {
     access_flag: u16,
     name_index: u16,
     descriptor_index: u16,
-    attributes: HashMap<String, AttributeInfo>,
+    attributes: BTreeMap<String, AttributeInfo>,
 }
 
 impl MethodInfo {
@@ -258,9 +420,15 @@ impl MethodInfo {
 
     /// Returns a copy of the method info attributes.
     #[must_use]
-    pub fn attributes(&self) -> HashMap<String, AttributeInfo> {
+    pub fn attributes(&self) -> BTreeMap<String, AttributeInfo> {
         self.attributes.clone()
     }
+
+    /// Returns method info access flags.
+    #[must_use]
+    pub const fn access_flags(&self) -> u16 {
+        self.access_flag
+    }
 }
 
 /// `JVMClassFile` represents a Java class file.
@@ -281,7 +449,7 @@ pub struct JVMClassFile {
     _methods_count: u16,
     methods: Vec<MethodInfo>,
     _attributes_count: u16,
-    _attributes: HashMap<String, AttributeInfo>,
+    _attributes: BTreeMap<String, AttributeInfo>,
 }
 
 impl JVMClassFile {
@@ -296,6 +464,187 @@ impl JVMClassFile {
     pub fn methods(&self) -> Vec<MethodInfo> {
         self.methods.clone()
     }
+
+    /// Returns the fields declared by the class.
+    #[must_use]
+    pub fn fields(&self) -> &[FieldInfo] {
+        &self._fields
+    }
+
+    /// Returns the class file version as a `(major, minor)` pair.
+    #[must_use]
+    pub const fn version(&self) -> (u16, u16) {
+        (self._major_version, self._minor_version)
+    }
+
+    /// Returns the class access flags.
+    #[must_use]
+    pub const fn access_flags(&self) -> u16 {
+        self._access_flags
+    }
+
+    /// Returns the constant pool index of this class.
+    #[must_use]
+    pub const fn this_class(&self) -> u16 {
+        self._this_class
+    }
+
+    /// Returns the constant pool index of the super class.
+    #[must_use]
+    pub const fn super_class(&self) -> u16 {
+        self._super_class
+    }
+
+    /// Returns the constant pool indexes of the direct superinterfaces.
+    #[must_use]
+    pub fn interfaces(&self) -> &[u16] {
+        &self._interfaces
+    }
+
+    /// Returns a copy of the class level attributes.
+    #[must_use]
+    pub fn attributes(&self) -> BTreeMap<String, AttributeInfo> {
+        self._attributes.clone()
+    }
+
+    /// Returns the string stored in the `ConstantUtf8` entry at `index`.
+    #[must_use]
+    pub fn utf8(&self, index: u16) -> Option<&str> {
+        match self.constant_pool.get(index as usize) {
+            Some(CPInfo::ConstantUtf8 { bytes }) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Returns the binary name of the class, `this_class` resolved.
+    #[must_use]
+    pub fn class_name(&self) -> Option<&str> {
+        match self.constant_pool.get(usize::from(self._this_class)) {
+            Some(CPInfo::ConstantClass { name_index }) => {
+                self.utf8(*name_index)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// `ClassFormatErrorKind` represents the ways a class file can be malformed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClassFormatErrorKind {
+    /// The class file ends in the middle of a structure.
+    UnexpectedEnd,
+    /// The class file doesn't start with `0xCAFEBABE`.
+    InvalidMagic(u32),
+    /// A `ConstantUtf8` entry doesn't hold valid UTF-8.
+    InvalidUtf8,
+    /// The constant pool holds an entry with an unknown tag.
+    UnknownConstantTag(u8),
+    /// An attribute name index doesn't point to a `ConstantUtf8` entry.
+    InvalidAttributeName(u16),
+    /// A class index doesn't point to a `ConstantClass` entry.
+    InvalidClassIndex(u16),
+    /// A constant pool index is zero or past the end of the constant pool.
+    IndexOutOfRange(u16),
+    /// A constant pool index points to an entry of another kind than the
+    /// one the reference needs.
+    InvalidConstantIndex(u16),
+    /// A stack map frame has a reserved frame type.
+    UnknownStackMapFrame(u8),
+}
+
+/// `ClassFormatError` is returned when a class file can't be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClassFormatError {
+    /// Offset in the class file the error was detected at.
+    pub offset: usize,
+    pub kind: ClassFormatErrorKind,
+}
+
+impl fmt::Display for ClassFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            ClassFormatErrorKind::UnexpectedEnd => {
+                write!(f, "unexpected end of class file")?;
+            }
+            ClassFormatErrorKind::InvalidMagic(magic) => {
+                write!(f, "invalid magic {magic:#010x}")?;
+            }
+            ClassFormatErrorKind::InvalidUtf8 => {
+                write!(f, "invalid UTF-8 constant")?;
+            }
+            ClassFormatErrorKind::UnknownConstantTag(tag) => {
+                write!(f, "unknown constant tag {tag}")?;
+            }
+            ClassFormatErrorKind::InvalidAttributeName(index) => {
+                write!(f, "attribute name #{index} isn't a UTF-8 constant")?;
+            }
+            ClassFormatErrorKind::InvalidClassIndex(index) => {
+                write!(f, "class #{index} isn't a class constant")?;
+            }
+            ClassFormatErrorKind::IndexOutOfRange(index) => {
+                write!(f, "constant pool index #{index} is out of range")?;
+            }
+            ClassFormatErrorKind::InvalidConstantIndex(index) => {
+                write!(f, "constant #{index} has an unexpected kind")?;
+            }
+            ClassFormatErrorKind::UnknownStackMapFrame(tag) => {
+                write!(f, "unknown stack map frame type {tag}")?;
+            }
+        }
+        write!(f, " at offset {}", self.offset)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ClassFormatError {}
+
+#[cfg(feature = "std")]
+impl From<ClassFormatError> for io::Error {
+    fn from(err: ClassFormatError) -> Self {
+        let kind = match err.kind {
+            ClassFormatErrorKind::UnexpectedEnd => io::ErrorKind::UnexpectedEof,
+            _ => io::ErrorKind::InvalidData,
+        };
+        Self::new(kind, err)
+    }
+}
+
+/// Big endian cursor over the bytes of a class file.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    const fn error(&self, kind: ClassFormatErrorKind) -> ClassFormatError {
+        ClassFormatError {
+            offset: self.position,
+            kind,
+        }
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], ClassFormatError> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + len)
+            .ok_or_else(|| self.error(ClassFormatErrorKind::UnexpectedEnd))?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, ClassFormatError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, ClassFormatError> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, ClassFormatError> {
+        let bytes = self.read_bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
 }
 
 /// `JVMParser` namespaces functions that handle parsing of Java class files.
@@ -305,43 +654,52 @@ pub struct JVMParser;
 impl JVMParser {
     /// Parse a Java class file.
     /// # Errors
-    /// Returns `io::Error` in case a `std::io::Read` fails.
-    /// # Panics
-    /// Can panic if file isn't valid, since we don't handle some
-    /// `std::io::Read` failures.
-    pub fn parse(class_file_bytes: &[u8]) -> io::Result<JVMClassFile> {
+    /// Returns an error if the class file is truncated or malformed.
+    pub fn parse(
+        class_file_bytes: &[u8],
+    ) -> Result<JVMClassFile, ClassFormatError> {
         // Create a new cursor on the class file bytes.
-        let mut buffer = Cursor::new(class_file_bytes);
+        let mut buffer = Reader {
+            bytes: class_file_bytes,
+            position: 0,
+        };
         // Read magic header..
-        let magic = buffer.read_u32::<BigEndian>()?;
+        let magic = buffer.read_u32()?;
+        if magic != JVM_CLASS_FILE_MAGIC {
+            return Err(buffer.error(ClassFormatErrorKind::InvalidMagic(magic)));
+        }
         // Read the class file version numbers.
-        let minor_version = buffer.read_u16::<BigEndian>()?;
-        let major_version = buffer.read_u16::<BigEndian>()?;
+        let minor_version = buffer.read_u16()?;
+        let major_version = buffer.read_u16()?;
         // Read the number of constants in the pool.
-        let cp_size = buffer.read_u16::<BigEndian>()?;
+        let cp_size = buffer.read_u16()?;
         // Parse the constant pool.
-        let constant_pool = parse_constant_pool(&mut buffer, cp_size as usize);
+        let constant_pool = parse_constant_pool(&mut buffer, cp_size as usize)?;
         // Extra class file metdata.
-        let access_flags = buffer.read_u16::<BigEndian>()?;
-        let this_class = buffer.read_u16::<BigEndian>()?;
-        let super_class = buffer.read_u16::<BigEndian>()?;
+        let access_flags = buffer.read_u16()?;
+        let this_class = read_class_index(&mut buffer, &constant_pool)?;
+        let super_class = buffer.read_u16()?;
+        // Only `java/lang/Object` has no super class.
+        if super_class != 0 {
+            check_class_index(&buffer, &constant_pool, super_class)?;
+        }
         // Interface definitions
-        let interfaces_count = buffer.read_u16::<BigEndian>()?;
+        let interfaces_count = buffer.read_u16()?;
         let mut interfaces = Vec::new();
 
         for _ in 0..interfaces_count {
-            let interface = buffer.read_u16::<BigEndian>()?;
+            let interface = read_class_index(&mut buffer, &constant_pool)?;
             interfaces.push(interface);
         }
         // Field information.
         let (fields_count, fields) =
-            parse_field_information(&mut buffer, &constant_pool);
+            parse_field_information(&mut buffer, &constant_pool)?;
         // Methods.
         let (methods_count, methods) =
-            parse_method_information(&mut buffer, &constant_pool);
+            parse_method_information(&mut buffer, &constant_pool)?;
         // Attributes.
         let (attributes_count, attributes) =
-            parse_attribute_info(&mut buffer, &constant_pool);
+            parse_attribute_info(&mut buffer, &constant_pool)?;
 
         Ok(JVMClassFile {
             _magic: magic,
@@ -366,136 +724,302 @@ impl JVMParser {
 
 /// Parse constants pool.
 fn parse_constant_pool(
-    reader: &mut (impl Read + Seek),
+    reader: &mut Reader<'_>,
     pool_size: usize,
-) -> Vec<CPInfo> {
+) -> Result<Vec<CPInfo>, ClassFormatError> {
     // We preallocate because indexing is shifted and we know the pool size.
     let mut constant_pool = vec![CPInfo::Unspecified; pool_size];
+    // Entries may reference later ones, references are checked once the
+    // whole pool is read and reported at the offset of their entry.
+    let mut offsets = vec![0; pool_size];
     // The first entry in the pool is at index 1 according to JVM
     // spec.
-    #[allow(unused_assignments)]
-    (1..pool_size).for_each(|mut ii| {
-        let tag = reader.read_u8().unwrap();
+    let mut ii = 1;
+    while ii < pool_size {
+        offsets[ii] = reader.position;
+        let tag = reader.read_u8()?;
         match ConstantKind::from(tag) {
             ConstantKind::Class => {
                 constant_pool[ii] = CPInfo::ConstantClass {
-                    name_index: reader.read_u16::<BigEndian>().unwrap(),
+                    name_index: reader.read_u16()?,
                 };
             }
             ConstantKind::FieldRef => {
                 constant_pool[ii] = CPInfo::ConstantFieldRef {
-                    class_index: reader.read_u16::<BigEndian>().unwrap(),
-                    name_and_type_index: reader
-                        .read_u16::<BigEndian>()
-                        .unwrap(),
+                    class_index: reader.read_u16()?,
+                    name_and_type_index: reader.read_u16()?,
                 };
             }
             ConstantKind::MethodRef => {
                 constant_pool[ii] = CPInfo::ConstantMethodRef {
-                    class_index: reader.read_u16::<BigEndian>().unwrap(),
-                    name_and_type_index: reader
-                        .read_u16::<BigEndian>()
-                        .unwrap(),
+                    class_index: reader.read_u16()?,
+                    name_and_type_index: reader.read_u16()?,
                 };
             }
             ConstantKind::InterfaceMethodRef => {
                 constant_pool[ii] = CPInfo::ConstantInterfaceMethodRef {
-                    class_index: reader.read_u16::<BigEndian>().unwrap(),
-                    name_and_type_index: reader
-                        .read_u16::<BigEndian>()
-                        .unwrap(),
+                    class_index: reader.read_u16()?,
+                    name_and_type_index: reader.read_u16()?,
                 };
             }
             ConstantKind::String => {
                 constant_pool[ii] = CPInfo::ConstantString {
-                    string_index: reader.read_u16::<BigEndian>().unwrap(),
+                    string_index: reader.read_u16()?,
                 };
             }
             ConstantKind::Integer => {
                 constant_pool[ii] = CPInfo::ConstantInteger {
-                    bytes: reader.read_u32::<BigEndian>().unwrap(),
+                    bytes: reader.read_u32()?,
                 };
             }
             ConstantKind::Float => {
                 constant_pool[ii] = CPInfo::ConstantFloat {
-                    bytes: reader.read_u32::<BigEndian>().unwrap(),
+                    bytes: reader.read_u32()?,
                 };
             }
             ConstantKind::Long => {
                 constant_pool[ii] = CPInfo::ConstantLong {
-                    hi_bytes: reader.read_u32::<BigEndian>().unwrap(),
-                    lo_bytes: reader.read_u32::<BigEndian>().unwrap(),
+                    hi_bytes: reader.read_u32()?,
+                    lo_bytes: reader.read_u32()?,
                 };
+                // Longs and doubles take two slots.
                 ii += 1;
             }
             ConstantKind::Double => {
                 constant_pool[ii] = CPInfo::ConstantDouble {
-                    hi_bytes: reader.read_u32::<BigEndian>().unwrap(),
-                    lo_bytes: reader.read_u32::<BigEndian>().unwrap(),
+                    hi_bytes: reader.read_u32()?,
+                    lo_bytes: reader.read_u32()?,
                 };
+                // Longs and doubles take two slots.
                 ii += 1;
             }
             ConstantKind::NameAndType => {
                 constant_pool[ii] = CPInfo::ConstantNameAndType {
-                    name_index: reader.read_u16::<BigEndian>().unwrap(),
-                    descriptor_index: reader.read_u16::<BigEndian>().unwrap(),
+                    name_index: reader.read_u16()?,
+                    descriptor_index: reader.read_u16()?,
                 };
             }
             ConstantKind::Utf8 => {
-                let length = reader.read_u16::<BigEndian>().unwrap();
-                let mut buf = vec![0u8; length as usize];
-                reader.read_exact(&mut buf).unwrap();
+                let length = reader.read_u16()?;
+                let buf = reader.read_bytes(length as usize)?;
+                let bytes = core::str::from_utf8(buf).map_err(|_| {
+                    reader.error(ClassFormatErrorKind::InvalidUtf8)
+                })?;
                 constant_pool[ii] = CPInfo::ConstantUtf8 {
-                    bytes: String::from_utf8(buf).unwrap(),
+                    bytes: bytes.to_string(),
                 };
             }
             ConstantKind::MethodHandle => {
-                let ref_kind = reader.read_u8().unwrap();
-                let ref_index = reader.read_u16::<BigEndian>().unwrap();
+                let ref_kind = reader.read_u8()?;
+                let ref_index = reader.read_u16()?;
                 constant_pool[ii] = CPInfo::ConstantMethodHandle {
                     reference_kind: ref_kind,
                     reference_index: ref_index,
                 };
             }
             ConstantKind::MethodType => {
-                let desc_index = reader.read_u16::<BigEndian>().unwrap();
+                let desc_index = reader.read_u16()?;
                 constant_pool[ii] = CPInfo::ConstantMethodType {
                     descriptor_index: desc_index,
                 };
             }
             ConstantKind::InvokeDynamic => {
-                let bootstrap_method_attr_index =
-                    reader.read_u16::<BigEndian>().unwrap();
-                let name_and_type_index =
-                    reader.read_u16::<BigEndian>().unwrap();
+                let bootstrap_method_attr_index = reader.read_u16()?;
+                let name_and_type_index = reader.read_u16()?;
                 constant_pool[ii] = CPInfo::ConstantInvokeDynamic {
                     bootstrap_method_attr_index,
                     name_and_type_index,
                 };
             }
-            _ => panic!(
-                "Unexpected constant kind {:?} with tag {}",
-                ConstantKind::from(tag),
-                tag
-            ),
+            _ => {
+                return Err(
+                    reader.error(ClassFormatErrorKind::UnknownConstantTag(tag))
+                );
+            }
         }
-    });
-    constant_pool
+        ii += 1;
+    }
+    check_constant_pool(&constant_pool, &offsets)?;
+    Ok(constant_pool)
+}
+
+/// Check the references between the entries of `constant_pool`, `offsets`
+/// holds the offset of every entry.
+fn check_constant_pool(
+    constant_pool: &[CPInfo],
+    offsets: &[usize],
+) -> Result<(), ClassFormatError> {
+    let is_class =
+        |entry: &CPInfo| matches!(entry, CPInfo::ConstantClass { .. });
+    let is_name_and_type =
+        |entry: &CPInfo| matches!(entry, CPInfo::ConstantNameAndType { .. });
+    let is_member = |entry: &CPInfo| {
+        matches!(
+            entry,
+            CPInfo::ConstantFieldRef { .. }
+                | CPInfo::ConstantMethodRef { .. }
+                | CPInfo::ConstantInterfaceMethodRef { .. }
+        )
+    };
+    for (entry, &offset) in constant_pool.iter().zip(offsets) {
+        let utf8 = |index| check_utf8_index(offset, constant_pool, index);
+        let name_and_type = |index| {
+            check_index(
+                offset,
+                constant_pool,
+                index,
+                is_name_and_type,
+                ClassFormatErrorKind::InvalidConstantIndex(index),
+            )
+        };
+        match *entry {
+            CPInfo::ConstantClass { name_index } => utf8(name_index)?,
+            CPInfo::ConstantFieldRef {
+                class_index,
+                name_and_type_index,
+            }
+            | CPInfo::ConstantMethodRef {
+                class_index,
+                name_and_type_index,
+            }
+            | CPInfo::ConstantInterfaceMethodRef {
+                class_index,
+                name_and_type_index,
+            } => {
+                check_index(
+                    offset,
+                    constant_pool,
+                    class_index,
+                    is_class,
+                    ClassFormatErrorKind::InvalidClassIndex(class_index),
+                )?;
+                name_and_type(name_and_type_index)?;
+            }
+            CPInfo::ConstantString { string_index } => utf8(string_index)?,
+            CPInfo::ConstantNameAndType {
+                name_index,
+                descriptor_index,
+            } => {
+                utf8(name_index)?;
+                utf8(descriptor_index)?;
+            }
+            CPInfo::ConstantMethodHandle {
+                reference_index, ..
+            } => check_index(
+                offset,
+                constant_pool,
+                reference_index,
+                is_member,
+                ClassFormatErrorKind::InvalidConstantIndex(reference_index),
+            )?,
+            CPInfo::ConstantMethodType { descriptor_index } => {
+                utf8(descriptor_index)?;
+            }
+            CPInfo::ConstantInvokeDynamic {
+                name_and_type_index,
+                ..
+            } => name_and_type(name_and_type_index)?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Returns the error for `index` not pointing to an entry of the expected
+/// kind, `invalid` unless it's outside of `constant_pool`.
+fn index_error(
+    constant_pool: &[CPInfo],
+    index: u16,
+    invalid: ClassFormatErrorKind,
+) -> ClassFormatErrorKind {
+    if index == 0 || usize::from(index) >= constant_pool.len() {
+        ClassFormatErrorKind::IndexOutOfRange(index)
+    } else {
+        invalid
+    }
+}
+
+/// Check that `index`, read at `offset`, points to an entry `expected`
+/// accepts.
+fn check_index(
+    offset: usize,
+    constant_pool: &[CPInfo],
+    index: u16,
+    expected: impl Fn(&CPInfo) -> bool,
+    invalid: ClassFormatErrorKind,
+) -> Result<(), ClassFormatError> {
+    match constant_pool.get(usize::from(index)) {
+        Some(entry) if index != 0 && expected(entry) => Ok(()),
+        _ => Err(ClassFormatError {
+            offset,
+            kind: index_error(constant_pool, index, invalid),
+        }),
+    }
+}
+
+/// Check that `index`, read at `offset`, points to a `ConstantUtf8` entry.
+fn check_utf8_index(
+    offset: usize,
+    constant_pool: &[CPInfo],
+    index: u16,
+) -> Result<(), ClassFormatError> {
+    check_index(
+        offset,
+        constant_pool,
+        index,
+        |entry| matches!(entry, CPInfo::ConstantUtf8 { .. }),
+        ClassFormatErrorKind::InvalidConstantIndex(index),
+    )
+}
+
+/// Read an index that must point to a `ConstantUtf8` entry.
+fn read_utf8_index(
+    reader: &mut Reader<'_>,
+    constant_pool: &[CPInfo],
+) -> Result<u16, ClassFormatError> {
+    let index = reader.read_u16()?;
+    check_utf8_index(reader.position, constant_pool, index)?;
+    Ok(index)
+}
+
+/// Read an index that must point to a `ConstantClass` entry.
+fn read_class_index(
+    reader: &mut Reader<'_>,
+    constant_pool: &[CPInfo],
+) -> Result<u16, ClassFormatError> {
+    let index = reader.read_u16()?;
+    check_class_index(reader, constant_pool, index)?;
+    Ok(index)
+}
+
+/// Check that `index` points to a `ConstantClass` entry.
+fn check_class_index(
+    reader: &Reader<'_>,
+    constant_pool: &[CPInfo],
+    index: u16,
+) -> Result<(), ClassFormatError> {
+    check_index(
+        reader.position,
+        constant_pool,
+        index,
+        |entry| matches!(entry, CPInfo::ConstantClass { .. }),
+        ClassFormatErrorKind::InvalidClassIndex(index),
+    )
 }
 
 /// Parse field information.
 fn parse_field_information(
-    reader: &mut (impl Read + Seek),
+    reader: &mut Reader<'_>,
     constant_pool: &[CPInfo],
-) -> (u16, Vec<FieldInfo>) {
-    let fields_count = reader.read_u16::<BigEndian>().unwrap();
+) -> Result<(u16, Vec<FieldInfo>), ClassFormatError> {
+    let fields_count = reader.read_u16()?;
     let mut fields: Vec<FieldInfo> = Vec::new();
 
     for _ in 0..fields_count {
-        let access_flag = reader.read_u16::<BigEndian>().unwrap();
-        let name_index = reader.read_u16::<BigEndian>().unwrap();
-        let descriptor_index = reader.read_u16::<BigEndian>().unwrap();
-        let (_, attributes) = parse_attribute_info(reader, constant_pool);
+        let access_flag = reader.read_u16()?;
+        let name_index = read_utf8_index(reader, constant_pool)?;
+        let descriptor_index = read_utf8_index(reader, constant_pool)?;
+        let (_, attributes) = parse_attribute_info(reader, constant_pool)?;
         fields.push(FieldInfo {
             access_flag,
             name_index,
@@ -504,22 +1028,22 @@ fn parse_field_information(
         });
     }
 
-    (fields_count, fields)
+    Ok((fields_count, fields))
 }
 
 /// Parse method infromation.
 fn parse_method_information(
-    reader: &mut (impl Read + Seek),
+    reader: &mut Reader<'_>,
     constant_pool: &[CPInfo],
-) -> (u16, Vec<MethodInfo>) {
-    let methods_count = reader.read_u16::<BigEndian>().unwrap();
+) -> Result<(u16, Vec<MethodInfo>), ClassFormatError> {
+    let methods_count = reader.read_u16()?;
     let mut methods: Vec<MethodInfo> = Vec::new();
 
     for _ in 0..methods_count {
-        let access_flag = reader.read_u16::<BigEndian>().unwrap();
-        let name_index = reader.read_u16::<BigEndian>().unwrap();
-        let descriptor_index = reader.read_u16::<BigEndian>().unwrap();
-        let (_, attributes) = parse_attribute_info(reader, constant_pool);
+        let access_flag = reader.read_u16()?;
+        let name_index = read_utf8_index(reader, constant_pool)?;
+        let descriptor_index = read_utf8_index(reader, constant_pool)?;
+        let (_, attributes) = parse_attribute_info(reader, constant_pool)?;
         methods.push(MethodInfo {
             access_flag,
             name_index,
@@ -528,26 +1052,25 @@ fn parse_method_information(
         });
     }
 
-    (methods_count, methods)
+    Ok((methods_count, methods))
 }
 
 /// Parse code attribute
 fn parse_code_attribute(
-    reader: &mut (impl Read + Seek),
+    reader: &mut Reader<'_>,
     constant_pool: &[CPInfo],
-) -> AttributeInfo {
-    let max_stack = reader.read_u16::<BigEndian>().unwrap();
-    let max_locals = reader.read_u16::<BigEndian>().unwrap();
-    let code_length = reader.read_u32::<BigEndian>().unwrap();
-    let mut buf = vec![0u8; code_length as usize];
-    reader.read_exact(&mut buf).unwrap();
-    let exception_table_length = reader.read_u16::<BigEndian>().unwrap();
+) -> Result<AttributeInfo, ClassFormatError> {
+    let max_stack = reader.read_u16()?;
+    let max_locals = reader.read_u16()?;
+    let code_length = reader.read_u32()?;
+    let buf = reader.read_bytes(code_length as usize)?.to_vec();
+    let exception_table_length = reader.read_u16()?;
     let mut exception_table_entries: Vec<ExceptionEntry> = Vec::new();
     for _ in 0..exception_table_length {
-        let start_pc = reader.read_u16::<BigEndian>().unwrap();
-        let end_pc = reader.read_u16::<BigEndian>().unwrap();
-        let handler_pc = reader.read_u16::<BigEndian>().unwrap();
-        let catch_type = reader.read_u16::<BigEndian>().unwrap();
+        let start_pc = reader.read_u16()?;
+        let end_pc = reader.read_u16()?;
+        let handler_pc = reader.read_u16()?;
+        let catch_type = reader.read_u16()?;
 
         exception_table_entries.push(ExceptionEntry {
             start_pc,
@@ -556,46 +1079,52 @@ fn parse_code_attribute(
             catch_type,
         });
     }
-    let (_, attributes) = parse_attribute_info(reader, constant_pool);
-    AttributeInfo::CodeAttribute {
+    let (_, attributes) = parse_attribute_info(reader, constant_pool)?;
+    Ok(AttributeInfo::CodeAttribute {
         max_stack,
         max_locals,
         code: buf,
         exception_table: exception_table_entries,
         attributes,
         attribute_name: "Code".to_string(),
-    }
+    })
 }
 
 /// Parse attributes.
 fn parse_attribute_info(
-    reader: &mut (impl Read + Seek),
+    reader: &mut Reader<'_>,
     constant_pool: &[CPInfo],
-) -> (u16, HashMap<String, AttributeInfo>) {
-    let attribute_count = reader.read_u16::<BigEndian>().unwrap();
-    let mut attributes: HashMap<String, AttributeInfo> = HashMap::new();
+) -> Result<(u16, BTreeMap<String, AttributeInfo>), ClassFormatError> {
+    let attribute_count = reader.read_u16()?;
+    let mut attributes: BTreeMap<String, AttributeInfo> = BTreeMap::new();
     for _ in 0..attribute_count {
-        let attribute_name_index = reader.read_u16::<BigEndian>().unwrap();
-        let attr_name = &constant_pool[attribute_name_index as usize];
-        let attribute_name = match attr_name {
-            CPInfo::ConstantUtf8 { bytes } => bytes.clone(),
-            _ => panic!(
-                "Expected attribute name to be CPInfo::ConstantUtf8 got {attr_name:?}",
-            ),
-        };
-        let attribute_length = reader.read_u32::<BigEndian>().unwrap();
+        let attribute_name_index = reader.read_u16()?;
+        let attribute_name =
+            match constant_pool.get(attribute_name_index as usize) {
+                Some(CPInfo::ConstantUtf8 { bytes }) => bytes.clone(),
+                _ => {
+                    return Err(reader.error(index_error(
+                        constant_pool,
+                        attribute_name_index,
+                        ClassFormatErrorKind::InvalidAttributeName(
+                            attribute_name_index,
+                        ),
+                    )))
+                }
+            };
+        let attribute_length = reader.read_u32()?;
         let attribute_info = match attribute_name.as_str() {
             "ConstantValue" => Some(AttributeInfo::ConstantValueAttribute {
-                constant_value_index: reader.read_u16::<BigEndian>().unwrap(),
+                constant_value_index: reader.read_u16()?,
                 attribute_name: attribute_name.clone(),
             }),
-            "Code" => Some(parse_code_attribute(reader, constant_pool)),
+            "Code" => Some(parse_code_attribute(reader, constant_pool)?),
             "StackMapTable" => {
-                let number_of_entries = reader.read_u16::<BigEndian>().unwrap();
+                let number_of_entries = reader.read_u16()?;
                 let mut stack_map_entries: Vec<StackMapFrame> = Vec::new();
                 for _ in 0..number_of_entries {
-                    let tag = reader.read_u8().unwrap();
-                    let frame = parse_stack_frame_entry(reader, tag);
+                    let tag = reader.read_u8()?;
+                    let frame = parse_stack_frame_entry(reader, tag)?;
                     stack_map_entries.push(frame);
                 }
                 Some(AttributeInfo::StackMapTableAttribute {
@@ -603,23 +1132,35 @@ fn parse_attribute_info(
                     attribute_name: "StackMapTable".to_string(),
                 })
             }
+            "LineNumberTable" => {
+                let line_number_table_length = reader.read_u16()?;
+                let mut line_numbers = Vec::new();
+                for _ in 0..line_number_table_length {
+                    line_numbers.push(LineNumber {
+                        start_pc: reader.read_u16()?,
+                        line_number: reader.read_u16()?,
+                    });
+                }
+                Some(AttributeInfo::LineNumberTableAttribute {
+                    line_numbers,
+                    attribute_name: "LineNumberTable".to_string(),
+                })
+            }
             "SourceFile" => Some(AttributeInfo::SourceFileAttribute {
-                source_file_index: reader.read_u16::<BigEndian>().unwrap(),
+                source_file_index: reader.read_u16()?,
                 attribute_name: "SourceFile".to_string(),
             }),
             "BootstrapMethods" => {
-                let num_bootstrap_methods =
-                    reader.read_u16::<BigEndian>().unwrap();
+                let num_bootstrap_methods = reader.read_u16()?;
                 let mut bootstrap_method_table: Vec<BootstrapMethod> =
                     Vec::new();
 
                 for _ in 0..num_bootstrap_methods {
-                    let method_ref = reader.read_u16::<BigEndian>().unwrap();
-                    let argument_count =
-                        reader.read_u16::<BigEndian>().unwrap();
+                    let method_ref = reader.read_u16()?;
+                    let argument_count = reader.read_u16()?;
                     let mut arguments = Vec::new();
                     for _ in 0..argument_count {
-                        let arg = reader.read_u16::<BigEndian>().unwrap();
+                        let arg = reader.read_u16()?;
                         arguments.push(arg);
                     }
                     bootstrap_method_table.push(BootstrapMethod {
@@ -634,14 +1175,14 @@ fn parse_attribute_info(
                 })
             }
             "NestHost" => Some(AttributeInfo::NestHostAttribute {
-                host_class_index: reader.read_u16::<BigEndian>().unwrap(),
+                host_class_index: reader.read_u16()?,
                 attribute_name: "NestHost".to_string(),
             }),
             "NestMembers" => {
-                let num_classes = reader.read_u16::<BigEndian>().unwrap();
+                let num_classes = reader.read_u16()?;
                 let mut classes = Vec::new();
                 for _ in 0..num_classes {
-                    let class_index = reader.read_u16::<BigEndian>().unwrap();
+                    let class_index = reader.read_u16()?;
                     classes.push(class_index);
                 }
                 Some(AttributeInfo::NestMembersAttribute {
@@ -650,24 +1191,23 @@ fn parse_attribute_info(
                 })
             }
             _ => {
-                reader
-                    .seek(std::io::SeekFrom::Current(i64::from(
-                        attribute_length,
-                    )))
-                    .unwrap();
+                reader.read_bytes(attribute_length as usize)?;
                 None
             }
         };
-        let _ = attribute_info.map_or((), |attr| {
+        if let Some(attr) = attribute_info {
             attributes.insert(attribute_name.clone(), attr);
-        });
+        }
     }
-    (attribute_count, attributes)
+    Ok((attribute_count, attributes))
 }
 
 /// Helper function to parse the `StackMapFrameTable` entry give a tag.
-fn parse_stack_frame_entry(reader: &mut impl Read, tag: u8) -> StackMapFrame {
-    match tag {
+fn parse_stack_frame_entry(
+    reader: &mut Reader<'_>,
+    tag: u8,
+) -> Result<StackMapFrame, ClassFormatError> {
+    let frame = match tag {
         0..=63 => StackMapFrame {
             t: StackMapFrameType::Same,
             offset_delta: 0,
@@ -678,38 +1218,38 @@ fn parse_stack_frame_entry(reader: &mut impl Read, tag: u8) -> StackMapFrame {
             t: StackMapFrameType::SameLocals,
             offset_delta: 0,
             locals: vec![],
-            stack: parse_verification_info(reader, 1),
+            stack: parse_verification_info(reader, 1)?,
         },
         247 => StackMapFrame {
             t: StackMapFrameType::SameLocalsExtended,
-            offset_delta: 0,
+            offset_delta: reader.read_u16()?,
             locals: vec![],
-            stack: parse_verification_info(reader, 1),
+            stack: parse_verification_info(reader, 1)?,
         },
         248..=250 => StackMapFrame {
             t: StackMapFrameType::Chop,
-            offset_delta: reader.read_u16::<BigEndian>().unwrap(),
+            offset_delta: reader.read_u16()?,
             locals: vec![],
             stack: vec![],
         },
         251 => StackMapFrame {
             t: StackMapFrameType::SameExtended,
-            offset_delta: reader.read_u16::<BigEndian>().unwrap(),
+            offset_delta: reader.read_u16()?,
             locals: vec![],
             stack: vec![],
         },
         252..=254 => StackMapFrame {
             t: StackMapFrameType::Append,
-            offset_delta: reader.read_u16::<BigEndian>().unwrap(),
-            locals: parse_verification_info(reader, (tag - 251).into()),
+            offset_delta: reader.read_u16()?,
+            locals: parse_verification_info(reader, (tag - 251).into())?,
             stack: vec![],
         },
         255 => {
-            let offset_delta = reader.read_u16::<BigEndian>().unwrap();
-            let n_locals_entries = reader.read_u16::<BigEndian>().unwrap();
-            let locals = parse_verification_info(reader, n_locals_entries);
-            let n_stack_entries = reader.read_u16::<BigEndian>().unwrap();
-            let stack = parse_verification_info(reader, n_stack_entries);
+            let offset_delta = reader.read_u16()?;
+            let n_locals_entries = reader.read_u16()?;
+            let locals = parse_verification_info(reader, n_locals_entries)?;
+            let n_stack_entries = reader.read_u16()?;
+            let stack = parse_verification_info(reader, n_stack_entries)?;
             StackMapFrame {
                 t: StackMapFrameType::Full,
                 offset_delta,
@@ -717,23 +1257,28 @@ fn parse_stack_frame_entry(reader: &mut impl Read, tag: u8) -> StackMapFrame {
                 stack,
             }
         }
-        _ => unreachable!("Unexpected tag entry {tag}"),
-    }
+        _ => {
+            return Err(
+                reader.error(ClassFormatErrorKind::UnknownStackMapFrame(tag))
+            )
+        }
+    };
+    Ok(frame)
 }
 
 /// Helper function parse verification info.
 fn parse_verification_info(
-    reader: &mut impl Read,
+    reader: &mut Reader<'_>,
     num_entries: u16,
-) -> Vec<VerificationInfo> {
+) -> Result<Vec<VerificationInfo>, ClassFormatError> {
     let mut verifications: Vec<VerificationInfo> = Vec::new();
     for _ in 0..num_entries {
-        let tag = VerificationType::from(reader.read_u8().unwrap());
+        let tag = VerificationType::from(reader.read_u8()?);
         let cpool_index_or_offset = if tag
             == VerificationType::ObjectVerification
             || tag == VerificationType::UninitializedVerification
         {
-            reader.read_u16::<BigEndian>().unwrap()
+            reader.read_u16()?
         } else {
             0
         };
@@ -742,31 +1287,16 @@ fn parse_verification_info(
             cpool_index_or_offset,
         });
     }
-    verifications
+    Ok(verifications)
 }
 
-/// Helper function to read file into a buffer.
-/// # Panics
-/// Function panics on any `File::open` error.
+/// Helper function to read file into a buffer, the bytes are checked by
+/// `JVMParser::parse`.
+/// # Errors
+/// Returns an error if the file can't be opened or read.
+#[cfg(feature = "std")]
 pub fn read_class_file(fp: &Path) -> io::Result<Vec<u8>> {
-    use std::fs::File;
-    use std::io::prelude::*;
-
-    let mut f = File::open(fp).unwrap();
-    let mut buffer = Vec::new();
-
-    match f.read_to_end(&mut buffer) {
-        Ok(bytes_read) => {
-            assert!(bytes_read == f.metadata()?.len() as usize);
-            assert!(
-                u32::from_be_bytes(
-                    buffer[0..4].try_into().expect("slice with wrong length")
-                ) == JVM_CLASS_FILE_MAGIC
-            );
-            Ok(buffer)
-        }
-        Err(err) => Err(err),
-    }
+    std::fs::read(fp)
 }
 
 #[cfg(test)]
@@ -794,6 +1324,204 @@ mod tests {
         assert!(class_file._major_version > 61);
     }
 
+    #[test]
+    fn parses_field_constants() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/tests/Statics.class");
+        // `main` has a `same_locals_1_stack_item_frame_extended` frame.
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let field = |name: &str| {
+            class_file
+                .fields()
+                .iter()
+                .find(|field| class_file.utf8(field.name_index()) == Some(name))
+                .unwrap()
+        };
+        let index = field("SCALE").constant_value().unwrap();
+        assert_eq!(
+            class_file.constant_pool()[usize::from(index)].long_value(),
+            Some(1000)
+        );
+        assert_eq!(field("counter").constant_value(), None);
+    }
+
+    #[test]
+    fn decodes_wide_constants() {
+        let long = |value: i64| CPInfo::ConstantLong {
+            hi_bytes: (value >> 32) as u32,
+            lo_bytes: value as u32,
+        };
+        for value in [-1, -2, i64::MIN, i64::MAX, -(1 << 32), 1 << 32] {
+            assert_eq!(long(value).long_value(), Some(value));
+        }
+        assert_eq!(
+            CPInfo::ConstantLong {
+                hi_bytes: 0xffff_ffff,
+                lo_bytes: 0x0000_0000,
+            }
+            .long_value(),
+            Some(-4_294_967_296)
+        );
+        let double = |bits: u64| CPInfo::ConstantDouble {
+            hi_bytes: (bits >> 32) as u32,
+            lo_bytes: bits as u32,
+        };
+        // Smallest and largest subnormals.
+        assert_eq!(double(1).double_value(), Some(f64::from_bits(1)));
+        assert_eq!(double(1).double_value(), Some(4.9e-324));
+        let largest = double(0x000f_ffff_ffff_ffff).double_value().unwrap();
+        assert!(largest.is_subnormal());
+        assert!(largest < f64::MIN_POSITIVE);
+        assert_eq!(double((-2.5f64).to_bits()).double_value(), Some(-2.5));
+        assert_eq!(long(1).double_value(), None);
+        assert_eq!(double(1).long_value(), None);
+        assert_eq!(
+            CPInfo::ConstantInteger { bytes: 0xffff_fffe }.int_value(),
+            Some(-2)
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_class_files() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path =
+            Path::new(&env_var).join("support/tests/SingleFuncCall.class");
+        let class_file_bytes = read_class_file(&path).unwrap();
+        let truncated = &class_file_bytes[..class_file_bytes.len() / 2];
+        let err = JVMParser::parse(truncated).unwrap_err();
+        assert_eq!(err.kind, ClassFormatErrorKind::UnexpectedEnd);
+        assert!(err.offset <= truncated.len());
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path =
+            Path::new(&env_var).join("support/tests/SingleFuncCall.class");
+        let class_file_bytes = read_class_file(&path).unwrap();
+        let mut bytes = class_file_bytes.clone();
+        bytes[0] = 0xBE;
+        let err = JVMParser::parse(&bytes).unwrap_err();
+        assert_eq!(err.kind, ClassFormatErrorKind::InvalidMagic(0xBEFE_BABE));
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::InvalidData);
+        // Point `this_class` at the first method reference.
+        let class_file = JVMParser::parse(&class_file_bytes).unwrap();
+        let mut reader = Reader {
+            bytes: &class_file_bytes,
+            position: 10,
+        };
+        parse_constant_pool(&mut reader, class_file.constant_pool.len())
+            .unwrap();
+        let mut bytes = class_file_bytes.clone();
+        bytes[reader.position + 2..reader.position + 4]
+            .copy_from_slice(&1u16.to_be_bytes());
+        let err = JVMParser::parse(&bytes).unwrap_err();
+        assert_eq!(err.kind, ClassFormatErrorKind::InvalidClassIndex(1));
+        assert_eq!(err.offset, reader.position + 4);
+    }
+
+    /// Returns the bytes of the class file at `path` in `support`, its
+    /// parsed form and the offset of every constant pool entry.
+    fn class_file_layout(path: &str) -> (Vec<u8>, JVMClassFile, Vec<usize>) {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let bytes = read_class_file(&Path::new(&env_var).join(path)).unwrap();
+        let class_file = JVMParser::parse(&bytes).unwrap();
+        let mut offsets = vec![];
+        let mut offset = 10;
+        for entry in &class_file.constant_pool {
+            offsets.push(offset);
+            offset += match entry {
+                CPInfo::Unspecified => 0,
+                CPInfo::ConstantUtf8 { bytes } => 3 + bytes.len(),
+                CPInfo::ConstantClass { .. }
+                | CPInfo::ConstantString { .. }
+                | CPInfo::ConstantMethodType { .. } => 3,
+                CPInfo::ConstantMethodHandle { .. } => 4,
+                CPInfo::ConstantLong { .. } | CPInfo::ConstantDouble { .. } => {
+                    9
+                }
+                _ => 5,
+            };
+        }
+        // The end of the pool.
+        offsets.push(offset);
+        (bytes, class_file, offsets)
+    }
+
+    #[test]
+    fn rejects_invalid_constant_references() {
+        let (bytes, class_file, offsets) =
+            class_file_layout("support/clinit/Config.class");
+        let corrupt = |offset: usize, index: u16| {
+            let mut bytes = bytes.clone();
+            bytes[offset..offset + 2].copy_from_slice(&index.to_be_bytes());
+            JVMParser::parse(&bytes).unwrap_err().kind
+        };
+        let pool = &class_file.constant_pool;
+        let find =
+            |kind: fn(&CPInfo) -> bool| pool.iter().position(kind).unwrap();
+        let class = find(|entry| matches!(entry, CPInfo::ConstantClass { .. }));
+        let class = u16::try_from(class).unwrap();
+        let out_of_range = u16::try_from(pool.len()).unwrap();
+        let wrong_kind = ClassFormatErrorKind::InvalidConstantIndex(class);
+
+        // `Object.<init>` is the first method reference.
+        let method_ref =
+            find(|entry| matches!(entry, CPInfo::ConstantMethodRef { .. }));
+        assert_eq!(corrupt(offsets[method_ref] + 3, class), wrong_kind);
+        let name_and_type =
+            find(|entry| matches!(entry, CPInfo::ConstantNameAndType { .. }));
+        assert_eq!(
+            corrupt(offsets[name_and_type] + 1, out_of_range),
+            ClassFormatErrorKind::IndexOutOfRange(out_of_range)
+        );
+        assert_eq!(corrupt(offsets[name_and_type] + 3, class), wrong_kind);
+
+        // Config declares the `limit` field without attributes, its methods
+        // follow.
+        assert!(class_file._fields[0].attributes.is_empty());
+        let field = offsets[pool.len()] + 10;
+        assert_eq!(
+            corrupt(field + 2, 0),
+            ClassFormatErrorKind::IndexOutOfRange(0)
+        );
+        assert_eq!(corrupt(field + 4, class), wrong_kind);
+        let method = field + 10;
+        assert_eq!(
+            corrupt(method + 2, out_of_range),
+            ClassFormatErrorKind::IndexOutOfRange(out_of_range)
+        );
+        assert_eq!(corrupt(method + 4, class), wrong_kind);
+
+        let (bytes, class_file, offsets) =
+            class_file_layout("support/tests/Collections.class");
+        let string = class_file
+            .constant_pool
+            .iter()
+            .position(|entry| matches!(entry, CPInfo::ConstantString { .. }))
+            .unwrap();
+        let mut corrupted = bytes.clone();
+        corrupted[offsets[string] + 1..offsets[string] + 3]
+            .copy_from_slice(&u16::try_from(string).unwrap().to_be_bytes());
+        let err = JVMParser::parse(&corrupted).unwrap_err();
+        assert_eq!(
+            err.kind,
+            ClassFormatErrorKind::InvalidConstantIndex(
+                u16::try_from(string).unwrap()
+            )
+        );
+        assert_eq!(err.offset, offsets[string]);
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "constant #{string} has an unexpected kind at offset {}",
+                offsets[string]
+            )
+        );
+    }
+
     #[test]
     fn can_parse_class_file_header() {
         let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
@@ -916,14 +1644,24 @@ mod tests {
                     access_flag: 1,
                     name_index: 5,
                     descriptor_index: 6,
-                    attributes: HashMap::from([(
+                    attributes: BTreeMap::from([(
                         "Code".to_string(),
                         AttributeInfo::CodeAttribute {
                             max_stack: 1,
                             max_locals: 1,
                             code: vec![42, 183, 0, 1, 177],
                             exception_table: vec![],
-                            attributes: HashMap::new(),
+                            attributes: BTreeMap::from([(
+                                "LineNumberTable".to_string(),
+                                AttributeInfo::LineNumberTableAttribute {
+                                    line_numbers: vec![LineNumber {
+                                        start_pc: 0,
+                                        line_number: 1,
+                                    }],
+                                    attribute_name: "LineNumberTable"
+                                        .to_string(),
+                                },
+                            )]),
                             attribute_name: "Code".to_string(),
                         },
                     )]),
@@ -932,7 +1670,7 @@ mod tests {
                     access_flag: 9,
                     name_index: 27,
                     descriptor_index: 28,
-                    attributes: HashMap::from([(
+                    attributes: BTreeMap::from([(
                         "Code".to_string(),
                         AttributeInfo::CodeAttribute {
                             max_stack: 2,
@@ -942,7 +1680,27 @@ mod tests {
                                 19, 177,
                             ],
                             exception_table: vec![],
-                            attributes: HashMap::new(),
+                            attributes: BTreeMap::from([(
+                                "LineNumberTable".to_string(),
+                                AttributeInfo::LineNumberTableAttribute {
+                                    line_numbers: vec![
+                                        LineNumber {
+                                            start_pc: 0,
+                                            line_number: 3,
+                                        },
+                                        LineNumber {
+                                            start_pc: 6,
+                                            line_number: 4,
+                                        },
+                                        LineNumber {
+                                            start_pc: 13,
+                                            line_number: 5,
+                                        },
+                                    ],
+                                    attribute_name: "LineNumberTable"
+                                        .to_string(),
+                                },
+                            )]),
                             attribute_name: "Code".to_string(),
                         },
                     )]),
@@ -951,21 +1709,31 @@ mod tests {
                     access_flag: 8,
                     name_index: 11,
                     descriptor_index: 12,
-                    attributes: HashMap::from([(
+                    attributes: BTreeMap::from([(
                         "Code".to_string(),
                         AttributeInfo::CodeAttribute {
                             max_stack: 2,
                             max_locals: 2,
                             code: vec![26, 27, 96, 172],
                             exception_table: vec![],
-                            attributes: HashMap::new(),
+                            attributes: BTreeMap::from([(
+                                "LineNumberTable".to_string(),
+                                AttributeInfo::LineNumberTableAttribute {
+                                    line_numbers: vec![LineNumber {
+                                        start_pc: 0,
+                                        line_number: 8,
+                                    }],
+                                    attribute_name: "LineNumberTable"
+                                        .to_string(),
+                                },
+                            )]),
                             attribute_name: "Code".to_string(),
                         },
                     )]),
                 },
             ],
             _attributes_count: 1,
-            _attributes: HashMap::from([(
+            _attributes: BTreeMap::from([(
                 "SourceFile".to_string(),
                 AttributeInfo::SourceFileAttribute {
                     source_file_index: 30,
@@ -1,9 +1,129 @@
+//! A toy JVM with a tracing JIT.
+//!
+//! Embedders should only need the types in `prelude`, they are the stable
+//! surface of the crate. The other modules expose the internals used by the
+//! `coldbrew` binary and may change between releases.
+//!
+//! ```no_run
+//! use coldbrew::prelude::*;
+//!
+//! # fn main() -> coldbrew::Result<()> {
+//! let bytes = std::fs::read("Factorial.class")?;
+//! let class_file = JVMParser::parse(&bytes).map_err(std::io::Error::from)?;
+//! let mut runtime = Runtime::new(Program::new(&class_file));
+//! runtime.configure(&RuntimeOptions::default())?;
+//! runtime.run(false).map_err(|err| runtime.runtime_error(err))?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The class file parser (`jvm`), the JAR reader (`jar`) and the bytecode
+//! decoder (`bytecode` and `disasm`) only need `core` and `alloc`, the rest
+//! of the crate requires the default `std` feature.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
 pub mod arm64;
+#[cfg(feature = "std")]
+pub mod assembler;
+#[cfg(feature = "jit")]
+pub mod baseline;
+#[cfg(feature = "std")]
+pub mod bench;
+#[cfg(feature = "std")]
+pub mod builder;
 pub mod bytecode;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "std")]
+pub mod check;
+#[cfg(feature = "std")]
+pub mod collections;
+#[cfg(feature = "std")]
+pub mod coverage;
+#[cfg(feature = "std")]
+pub mod deps;
+#[cfg(feature = "std")]
+pub mod diff;
+pub mod disasm;
+#[cfg(feature = "std")]
+pub mod error;
+#[cfg(feature = "std")]
+pub mod events;
+#[cfg(feature = "std")]
+pub mod exec_log;
+#[cfg(feature = "std")]
+pub mod execution;
+#[cfg(feature = "jit")]
+pub mod fault;
+#[cfg(feature = "std")]
+pub mod fusion;
+#[cfg(feature = "std")]
+pub mod golden;
+#[cfg(feature = "std")]
+pub mod heap;
+#[cfg(feature = "jit")]
+pub mod host;
+#[cfg(feature = "std")]
+pub mod instrument;
+pub mod jar;
+#[cfg(feature = "jit")]
 pub mod jit;
 pub mod jvm;
+#[cfg(feature = "std")]
+pub mod loader;
+#[cfg(feature = "std")]
+pub mod log;
+#[cfg(feature = "std")]
+pub mod options;
+#[cfg(feature = "std")]
 pub mod profiler;
+#[cfg(feature = "std")]
 pub mod program;
+#[cfg(feature = "std")]
+pub mod regression;
+#[cfg(feature = "std")]
+pub mod repl;
+#[cfg(feature = "std")]
 pub mod runtime;
+#[cfg(feature = "std")]
+pub mod sink;
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "std")]
+pub mod tiering;
+#[cfg(feature = "std")]
+pub mod timing;
+#[cfg(feature = "std")]
 pub mod trace;
+#[cfg(feature = "tui")]
+pub mod tui;
+#[cfg(feature = "std")]
+pub mod validate;
+#[cfg(feature = "std")]
+pub mod vectorize;
+#[cfg(feature = "std")]
+pub mod watch;
+#[cfg(feature = "std")]
 pub mod x86;
+
+#[cfg(feature = "std")]
+pub use error::{Error, Result};
+#[cfg(feature = "std")]
+pub use execution::{run_class, run_class_bytes, Execution};
+
+/// The stable public surface, `use coldbrew::prelude::*` brings in what is
+/// needed to parse, configure and run a program.
+#[cfg(feature = "std")]
+pub mod prelude {
+    pub use crate::error::{Error, Result};
+    pub use crate::jvm::{JVMClassFile, JVMParser};
+    pub use crate::options::{Limits, RuntimeOptions};
+    pub use crate::program::Program;
+    pub use crate::runtime::{Runtime, Value};
+    pub use crate::trace::Trace;
+}
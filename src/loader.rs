@@ -0,0 +1,210 @@
+//! Class loading from a class path.
+//!
+//! A `ClassLoader` looks binary class names such as `java/lang/Object` up
+//! in a list of directories and JAR archives, the first entry holding the
+//! class wins like with `java -cp`. Loaded classes are parsed once and kept,
+//! so are the names that couldn't be found.
+//!
+//! Classes are loaded ahead of the run : `link` follows the `ConstantClass`
+//! entries of the constant pools from the main class and links every class
+//! found into the program, so the runtime reaches them as linked classes.
+//! Classes missing from the class path, which includes the JDK unless it's
+//! on it, are left to the library methods and natives of the runtime.
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::deps::referenced_classes;
+use crate::error::{Error, ParseError, Result};
+use crate::jar::{JarErrorKind, JarLoader};
+use crate::jvm::{read_class_file, JVMClassFile, JVMParser};
+use crate::program::Program;
+
+/// An entry of the class path.
+#[derive(Debug)]
+enum Source {
+    Directory(PathBuf),
+    Jar(PathBuf, JarLoader),
+}
+
+/// `ClassLoader` loads and caches the classes of a class path.
+#[derive(Debug)]
+pub struct ClassLoader {
+    sources: Vec<Source>,
+    // Classes looked up so far, `None` for the ones that weren't found.
+    classes: BTreeMap<String, Option<JVMClassFile>>,
+}
+
+impl ClassLoader {
+    /// Build a loader for `classpath`, entries ending in `.jar` are opened
+    /// as archives and the others are directories.
+    /// # Errors
+    /// Returns an error if a JAR can't be read.
+    pub fn new(classpath: &[PathBuf]) -> io::Result<Self> {
+        let sources = classpath
+            .iter()
+            .map(|entry| {
+                if entry.extension().is_some_and(|ext| ext == "jar") {
+                    Ok(Source::Jar(entry.clone(), JarLoader::open(entry)?))
+                } else {
+                    Ok(Source::Directory(entry.clone()))
+                }
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self {
+            sources,
+            classes: BTreeMap::new(),
+        })
+    }
+
+    /// Returns the class `class`, `None` if no entry of the class path
+    /// holds it.
+    /// # Errors
+    /// Returns an error if the class was found but can't be read or parsed.
+    pub fn load(&mut self, class: &str) -> Result<Option<&JVMClassFile>> {
+        if !self.classes.contains_key(class) {
+            let class_file = self.find(class)?;
+            self.classes.insert(class.to_string(), class_file);
+        }
+        Ok(self.classes[class].as_ref())
+    }
+
+    /// Look `class` up in the class path.
+    fn find(&self, class: &str) -> Result<Option<JVMClassFile>> {
+        let file = format!("{class}.class");
+        for source in &self.sources {
+            let (path, bytes) = match source {
+                Source::Directory(dir) => {
+                    let path = dir.join(&file);
+                    if !path.is_file() {
+                        continue;
+                    }
+                    let bytes = read_class_file(&path);
+                    (path, bytes)
+                }
+                Source::Jar(jar_path, jar) => {
+                    let bytes = match jar.read(&file) {
+                        Err(err) if err.kind == JarErrorKind::MissingEntry => {
+                            continue;
+                        }
+                        bytes => bytes.map_err(io::Error::from),
+                    };
+                    (jar_path.join(&file), bytes)
+                }
+            };
+            let class_file = bytes
+                .and_then(|bytes| Ok(JVMParser::parse(&bytes)?))
+                .map_err(|source| ParseError { path, source })?;
+            return Ok(Some(class_file));
+        }
+        Ok(None)
+    }
+
+    /// Link `main` with every class reachable from it that's on the class
+    /// path, `main` holds the entry point.
+    /// # Errors
+    /// Returns an error if a class can't be loaded or if the classes don't
+    /// link.
+    pub fn link(&mut self, main: &JVMClassFile) -> Result<Program> {
+        let mut seen: BTreeSet<String> = main
+            .class_name()
+            .map(ToString::to_string)
+            .into_iter()
+            .collect();
+        let mut pending: Vec<String> =
+            referenced_classes(main).into_iter().rev().collect();
+        let mut class_files = vec![main.clone()];
+        while let Some(class) = pending.pop() {
+            if !seen.insert(class.clone()) {
+                continue;
+            }
+            let Some(class_file) = self.load(&class)? else {
+                continue;
+            };
+            pending.extend(referenced_classes(class_file).into_iter().rev());
+            class_files.push(class_file.clone());
+        }
+        // Programs made of a single class keep references to members it
+        // doesn't declare unresolved instead of failing to link.
+        match &class_files[..] {
+            [class_file] => Ok(Program::new(class_file)),
+            _ => Program::link(&class_files).map_err(Error::Link),
+        }
+    }
+
+    /// Link the class file `main` read from `path` with the classes found on
+    /// its default class path, see `default_classpath`.
+    /// # Errors
+    /// Returns an error if a class can't be loaded or if the classes don't
+    /// link.
+    pub fn link_file(path: &Path, main: &JVMClassFile) -> Result<Program> {
+        let class = main.class_name().unwrap_or_default();
+        Self::new(&Self::default_classpath(path, class))?.link(main)
+    }
+
+    /// Returns the class path of the class file at `path` declaring the
+    /// class `class` run without one, the root of its package directories
+    /// such as `out` for `out/com/x/Foo.class` declaring `com/x/Foo`. Class
+    /// files outside their package directories use the directory they're
+    /// in.
+    pub fn default_classpath(path: &Path, class: &str) -> Vec<PathBuf> {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let root = class.rsplit('/').skip(1).try_fold(dir, |dir, package| {
+            dir.ends_with(package).then(|| dir.parent()).flatten()
+        });
+        match root.unwrap_or(dir) {
+            root if root.as_os_str().is_empty() => vec![PathBuf::from(".")],
+            root => vec![root.to_path_buf()],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn links_classes_from_the_classpath() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let dir = Path::new(&env_var).join("support/linking");
+        let main = JVMParser::parse(
+            &read_class_file(&dir.join("Main.class")).unwrap(),
+        )
+        .unwrap();
+        let mut loader = ClassLoader::new(&[dir]).unwrap();
+        let program = loader.link(&main).unwrap();
+        assert_eq!(program.class_names().collect::<Vec<_>>(), ["Main", "Ops"]);
+        assert!(loader.load("Ops").unwrap().is_some());
+        assert!(loader.load("java/lang/Object").unwrap().is_none());
+        // Misses are cached along with the classes that were found.
+        assert!(loader.classes.contains_key("java/lang/System"));
+
+        let jar = Path::new(&env_var).join("support/jar/App.jar");
+        let mut loader = ClassLoader::new(&[jar]).unwrap();
+        let program = loader.link(&main).unwrap();
+        assert_eq!(program.class_names().count(), 2);
+    }
+
+    #[test]
+    fn defaults_classpath_to_the_package_root() {
+        let classpath = |path: &str, class: &str| {
+            ClassLoader::default_classpath(Path::new(path), class)
+        };
+        assert_eq!(
+            classpath("out/com/x/Foo.class", "com/x/Foo"),
+            [PathBuf::from("out")]
+        );
+        assert_eq!(
+            classpath("com/x/Foo.class", "com/x/Foo"),
+            [PathBuf::from(".")]
+        );
+        assert_eq!(classpath("out/Foo.class", "Foo"), [PathBuf::from("out")]);
+        // Class files outside their package directories use their own.
+        assert_eq!(
+            classpath("out/y/Foo.class", "com/x/Foo"),
+            [PathBuf::from("out/y")]
+        );
+        assert_eq!(classpath("Foo.class", "com/x/Foo"), [PathBuf::from(".")]);
+    }
+}
@@ -0,0 +1,171 @@
+//! Diagnostic logging.
+//!
+//! Runtime, recorder and JIT diagnostics go through this module so that the
+//! guest program's stdout only ever holds what the program printed. Records
+//! are written to stderr by default, `set_sink` redirects them. The level
+//! is read from the `COLDBREW_LOG` environment variable (`error`, `warn`,
+//! `info`, `debug`, `trace` or `off`) unless set with `set_level`, only
+//! warnings and errors are logged by default.
+//!
+//! ```text
+//! [DEBUG jit] entering native trace @ Instruction Index 6 @ Method Index: 23
+//! ```
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+
+/// Environment variable holding the log level.
+pub const LOG_ENV: &str = "COLDBREW_LOG";
+
+/// Severity of a log record, from the most to the least severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Error => "ERROR",
+            Self::Warn => "WARN",
+            Self::Info => "INFO",
+            Self::Debug => "DEBUG",
+            Self::Trace => "TRACE",
+        };
+        f.pad(name)
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(Self::Error),
+            "warn" => Ok(Self::Warn),
+            "info" => Ok(Self::Info),
+            "debug" => Ok(Self::Debug),
+            "trace" => Ok(Self::Trace),
+            _ => Err(format!("unknown log level `{s}`")),
+        }
+    }
+}
+
+// Maximum level logged, `OFF` disables logging and `UNSET` means the
+// environment wasn't read yet.
+const OFF: u8 = 0;
+const UNSET: u8 = u8::MAX;
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(UNSET);
+
+// Sink records are written to, stderr when `None`.
+static SINK: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+/// Log records up to `level`, `None` disables logging.
+pub fn set_level(level: Option<Level>) {
+    MAX_LEVEL.store(level.map_or(OFF, |level| level as u8), Ordering::Relaxed);
+}
+
+/// Write records to `sink` instead of stderr.
+pub fn set_sink(sink: Box<dyn Write + Send>) {
+    if let Ok(mut current) = SINK.lock() {
+        *current = Some(sink);
+    }
+}
+
+/// Returns true if records at `level` are logged.
+pub fn enabled(level: Level) -> bool {
+    let mut max = MAX_LEVEL.load(Ordering::Relaxed);
+    if max == UNSET {
+        max = match std::env::var(LOG_ENV) {
+            Ok(value) if value.eq_ignore_ascii_case("off") => OFF,
+            Ok(value) => value.parse().unwrap_or(Level::Warn) as u8,
+            Err(_) => Level::Warn as u8,
+        };
+        MAX_LEVEL.store(max, Ordering::Relaxed);
+    }
+    level as u8 <= max
+}
+
+/// Write a record, use the `log!` macro instead which skips formatting when
+/// the level is disabled.
+pub fn write(level: Level, target: &str, args: fmt::Arguments<'_>) {
+    let Ok(mut sink) = SINK.lock() else {
+        return;
+    };
+    // Diagnostics are best effort, failing to write them is ignored.
+    let _ = match sink.as_mut() {
+        Some(sink) => writeln!(sink, "[{level} {target}] {args}"),
+        None => writeln!(io::stderr(), "[{level} {target}] {args}"),
+    };
+}
+
+/// Log a record at `level` for `target`.
+macro_rules! log {
+    ($level:expr, $target:expr, $($arg:tt)+) => {
+        if $crate::log::enabled($level) {
+            $crate::log::write($level, $target, format_args!($($arg)+));
+        }
+    };
+}
+
+// Named `warning` since `warn` clashes with the builtin attribute.
+macro_rules! warning {
+    ($target:expr, $($arg:tt)+) => {
+        $crate::log::log!($crate::log::Level::Warn, $target, $($arg)+)
+    };
+}
+
+macro_rules! debug {
+    ($target:expr, $($arg:tt)+) => {
+        $crate::log::log!($crate::log::Level::Debug, $target, $($arg)+)
+    };
+}
+
+macro_rules! trace {
+    ($target:expr, $($arg:tt)+) => {
+        $crate::log::log!($crate::log::Level::Trace, $target, $($arg)+)
+    };
+}
+
+pub(crate) use {debug, log, trace, warning};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jvm::{read_class_file, JVMParser};
+    use crate::program::Program;
+    use crate::runtime::Runtime;
+    use crate::sink::SharedBuffer;
+    use std::env;
+    use std::path::Path;
+
+    #[test]
+    #[cfg_attr(not(feature = "jit"), ignore = "requires the jit feature")]
+    fn diagnostics_stay_out_of_program_output() {
+        let logs = SharedBuffer::new();
+        set_sink(Box::new(logs.clone()));
+        set_level(Some(Level::Trace));
+
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/jit/Loop100.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let stdout = SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.run(true).unwrap();
+        set_level(Some(Level::Warn));
+
+        assert!(!stdout.to_string_lossy().contains("Jit"));
+        let logs = logs.to_string_lossy();
+        assert!(logs.contains("[DEBUG jit] entering native trace @"));
+        assert!(logs.contains("[TRACE recorder]"));
+        assert_eq!("debug".parse::<Level>(), Ok(Level::Debug));
+    }
+}
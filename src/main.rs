@@ -1,9 +1,18 @@
 use std::env;
 use std::process::exit;
+use std::time::Instant;
 
-use coldbrew::jvm::{read_class_file, JVMParser};
+use coldbrew::bench;
+use coldbrew::deps::{ClassPath, DependencyGraph};
+use coldbrew::diff;
+use coldbrew::jvm::{read_class_file, JVMClassFile, JVMParser, JarLoader};
+use coldbrew::loader::ClassLoader;
+use coldbrew::options::RuntimeOptions;
 use coldbrew::program::Program;
+use coldbrew::regression::Baseline;
+use coldbrew::repl::Repl;
 use coldbrew::runtime::Runtime;
+use coldbrew::timing::Timings;
 
 const USAGE_CMD: &str = "
     Coldbrew Tracing JIT usage guide :
@@ -11,33 +20,386 @@ const USAGE_CMD: &str = "
     Run `coldbrew unit` to run small test programs (interpreter only).
     Run `coldbrew integration` to run end to end CPU intensive test programs (interpreter only).
     Run `coldbrew jit` to run small test programs with hot loops (interpreter + tracing jit).
+    Run `coldbrew run [--jit] <class> [args...]` to run a single class file, `args` are passed to `main`.
+    Run `coldbrew [run] [--jit] <jar> [args...]` to run the `Main-Class` of a JAR.
+    Run `coldbrew bench <class> [runs]` to compare the interpreter and the tracing jit on a class file.
+    Run `coldbrew --diff-against <java> <class>` to compare the output of a class file with a system JVM.
+    Run `coldbrew repl` to evaluate instructions and expressions interactively.
+    Run `coldbrew inspect <class>` to browse a class file (requires the `tui` feature).
+    Run `coldbrew --watch <class>` to re-run a class file every time it changes.
+    Run `coldbrew coverage <class> [--lcov <file>]` to report which instructions of a class file were executed.
+    Run `coldbrew check <class>` to list the instructions and classes of a class file coldbrew doesn't support yet.
+    Run `coldbrew deps <class> [--classpath <dirs>] [--json]` to print the dependency graph of a class in DOT or JSON.
     Run `coldbrew help` to see this message.
+
+    Flags :
+
+    Flags go before the class or JAR that's run, the arguments following it are passed to `main`.
+
+    `--trace-exec[:method,...]` logs every interpreted instruction, optionally only for the given methods.
+    `--trace-exec-opcodes=<mnemonic,...>` only logs the given opcodes.
+    `--trace-exec-file=<path>` sets the log file (defaults to `coldbrew-exec.log`).
+    `--events <path>` writes compile, abort, deopt, tier and blacklist events to `path` as JSON lines.
+    `--time` prints the time spent in each phase of every run.
+    `--stats` prints how many iterations of each loop ran natively and the time their traces saved.
+    `--jit` runs with the tracing jit enabled.
+    `--jit-checks` fills invalidated native traces with traps and verifies trace checksums before entering them.
+    `--code-cache-size=<bytes>` caps the machine code of native traces, the least recently entered traces are evicted past it.
+    `--hot-threshold=<n>` sets the number of loop entries after which a loop is hot.
+    `--baseline-invocations=<n>` and `--baseline-aborts=<n>` set the invocations and aborted trace recordings after which a method is compiled by the baseline compiler.
+    `--trace-back-edges=<n>` sets the back edges a baseline method runs before its loops are left to the tracing jit.
+    `--deopt-limit=<n>` sets the deopts after which baseline code or a native trace is dropped.
+    `--max-instructions=<n>` fails the run after `n` executed instructions.
+    `--max-stack-depth=<n>` fails the run when the call stack grows past `n` frames.
+    `--max-heap=<bytes>` fails the run when the objects it allocates grow past `bytes`.
+    `--classpath <path>` or `-cp <path>` sets the directories and JARs, separated by `:`, referenced classes are loaded from (defaults to the root of the class file's package directories).
+    `-D<key>=<value>` sets a system property read with `System.getProperty`.
+    `--no-fusion` interprets every instruction on its own instead of fusing common sequences into superinstructions.
+    `--validate` checks the descriptors, branches and stack sizes of every method before running.
+    `--log=<level>` logs diagnostics to stderr (error, warn, info, debug or trace), also set with `COLDBREW_LOG`.
+    `--config=<path>` loads options from a config file (defaults to `coldbrew.toml` when present).
+
+    `--record-output <file>` records the stdout and exit code of every program to a baseline file.
+    `--check-output <file>` fails if any program's stdout or exit code differs from the baseline.
+
+    Flags can also be set in the `COLDBREW_OPTS` environment variable, command line flags take precedence.
 ";
 
 fn main() {
     // Decide which test files to run.
-    let args: Vec<String> = env::args().collect();
-    let jit_mode = args[1].as_str() == "jit";
-    assert!(
-        (args.len() >= 2),
-        "Unexpected argument use `coldbrew help` to see usage guide."
-    );
-    let folder = match args[1].as_str() {
-        "unit" => "./support/tests/",
-        "integration" => "./support/integration/",
-        "jit" => "./support/jit/",
+    let mut args: Vec<String> = env::args().collect();
+    let options = match RuntimeOptions::load(&mut args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("Error : {err}");
+            exit(64);
+        }
+    };
+    if options.trace_exec {
+        // Runs append to the log, start from an empty one.
+        if let Err(err) = std::fs::File::create(&options.trace_exec_file) {
+            eprintln!("Failed to create execution log : {err}");
+            exit(1);
+        }
+    }
+    let output_mode = match OutputMode::parse(&mut args) {
+        Ok(mode) => mode,
+        Err(flag) => {
+            eprintln!("Usage : coldbrew <tests> {flag} <baseline>");
+            exit(64);
+        }
+    };
+    if args.len() < 2 {
+        eprintln!(
+            "Unexpected argument use `coldbrew help` to see usage guide."
+        );
+        exit(64);
+    }
+    let jit_mode = args[1].as_str() == "jit" || options.jit;
+    let paths = match args[1].as_str() {
+        "unit" => class_files("./support/tests/"),
+        "integration" => class_files("./support/integration/"),
+        "jit" => class_files("./support/jit/"),
+        "run" => {
+            let Some(path) = args.get(2) else {
+                eprintln!("Usage : coldbrew run [--jit] <class> [args...]");
+                exit(64);
+            };
+            vec![std::path::PathBuf::from(path)]
+        }
+        "bench" => {
+            let Some(path) = args.get(2) else {
+                eprintln!("Usage : coldbrew bench <class> [runs]");
+                exit(64);
+            };
+            let runs = match args.get(3).map(|runs| runs.parse::<usize>()) {
+                Some(Ok(runs)) => runs,
+                Some(Err(err)) => {
+                    eprintln!("Invalid number of runs : {err}");
+                    exit(64);
+                }
+                None => 5,
+            };
+            match bench::run(std::path::Path::new(path), runs) {
+                Ok(report) => {
+                    println!("{report}");
+                    exit(0);
+                }
+                Err(err) => {
+                    eprintln!("Error : {err}");
+                    exit(1);
+                }
+            }
+        }
+        "--diff-against" => {
+            let (Some(java), Some(path)) = (args.get(2), args.get(3)) else {
+                eprintln!("Usage : coldbrew --diff-against <java> <class>");
+                exit(64);
+            };
+            match diff::diff(java, std::path::Path::new(path)) {
+                Ok(report) => {
+                    print!("{report}");
+                    exit(i32::from(!report.is_match()));
+                }
+                Err(err) => {
+                    eprintln!("Failed to run {java} : {err}");
+                    exit(1);
+                }
+            }
+        }
+        "repl" => {
+            let stdin = std::io::stdin();
+            match Repl::new().run(stdin.lock(), std::io::stdout()) {
+                Ok(()) => exit(0),
+                Err(err) => {
+                    eprintln!("Error : {err}");
+                    exit(1);
+                }
+            }
+        }
+        "inspect" => {
+            let Some(path) = args.get(2) else {
+                eprintln!("Usage : coldbrew inspect <class>");
+                exit(64);
+            };
+            inspect(std::path::Path::new(path));
+        }
+        "--watch" => {
+            let Some(path) = args.get(2) else {
+                eprintln!("Usage : coldbrew --watch <class>");
+                exit(64);
+            };
+            let interval = std::time::Duration::from_millis(250);
+            let path = std::path::Path::new(path);
+            if let Err(err) =
+                coldbrew::watch::run(path, interval, &mut std::io::stdout())
+            {
+                eprintln!("Error : {err}");
+            }
+            exit(1);
+        }
+        "coverage" => {
+            let Some(path) = args.get(2) else {
+                eprintln!("Usage : coldbrew coverage <class> [--lcov <file>]");
+                exit(64);
+            };
+            let lcov = match (args.get(3).map(String::as_str), args.get(4)) {
+                (Some("--lcov"), Some(file)) => Some(file),
+                (None, _) => None,
+                _ => {
+                    eprintln!(
+                        "Usage : coldbrew coverage <class> [--lcov <file>]"
+                    );
+                    exit(64);
+                }
+            };
+            coverage(std::path::Path::new(path), lcov.map(String::as_str));
+        }
+        "check" => {
+            let Some(path) = args.get(2) else {
+                eprintln!("Usage : coldbrew check <class>");
+                exit(64);
+            };
+            check(std::path::Path::new(path));
+        }
+        "deps" => deps(&args[2..], &options.classpath),
+        jar if jar.ends_with(".jar") => vec![std::path::PathBuf::from(jar)],
         "help" => {
             println!("{USAGE_CMD}");
             exit(0);
         }
         _ => {
-            println!(
+            eprintln!(
                 "Unexpected argument use `coldbrew help` to see usage guide."
             );
             exit(64);
         }
     };
 
+    if let Some(mode) = output_mode {
+        mode.run(&paths, jit_mode);
+    }
+    // Arguments following the class file or the JAR are passed to `main`.
+    let program_args = match args[1].as_str() {
+        "run" => args.get(3..),
+        jar if jar.ends_with(".jar") => args.get(2..),
+        _ => None,
+    }
+    .unwrap_or_default();
+
+    for path in &paths {
+        let start = Instant::now();
+        let (class_file, classpath) = parse_main_class(path, &options)
+            .unwrap_or_else(|err| {
+                eprintln!("Failed to load {} : {err}", path.display());
+                exit(1);
+            });
+        let parse = start.elapsed();
+
+        let start = Instant::now();
+        let program = ClassLoader::new(&classpath)
+            .map_err(coldbrew::Error::from)
+            .and_then(|mut loader| loader.link(&class_file))
+            .unwrap_or_else(|err| {
+                eprintln!("Failed to link {} : {err}", path.display());
+                exit(1);
+            });
+        let program_time = start.elapsed();
+        let mut runtime = Runtime::new(program);
+        if let Err(err) = runtime.configure(&options) {
+            eprintln!("Failed to open execution log : {err}");
+        }
+        runtime.set_args(program_args);
+        let failed = match runtime.run(jit_mode) {
+            // Status goes to stderr, stdout only holds the program output.
+            Ok(()) => {
+                eprintln!(
+                    "[+] Program {:?} finished running successfully !",
+                    path.file_name().unwrap()
+                );
+                false
+            }
+            Err(err) => {
+                match runtime.java_stack_trace(&err) {
+                    Some(report) => eprint!("{report}"),
+                    None => {
+                        eprintln!("Error : {}", runtime.runtime_error(err));
+                    }
+                }
+                true
+            }
+        };
+        if let Some(timings) = runtime.timings() {
+            let timings = Timings {
+                parse,
+                program: program_time,
+                ..timings.clone()
+            };
+            eprintln!("{timings}");
+        }
+        if let Some(stats) = runtime.stats() {
+            eprintln!("{stats}");
+        }
+        // Like a JVM the run fails when the program does.
+        if failed {
+            exit(1);
+        }
+    }
+}
+
+/// Parse the class file at `path`, or the `Main-Class` of the JAR at
+/// `path`, and returns it along with the class path the classes it
+/// references are loaded from.
+fn parse_main_class(
+    path: &std::path::Path,
+    options: &RuntimeOptions,
+) -> Result<(JVMClassFile, Vec<std::path::PathBuf>), String> {
+    if path.extension().is_some_and(|ext| ext == "jar") {
+        let jar = JarLoader::open(path).map_err(|err| err.to_string())?;
+        let main = jar
+            .main_class()
+            .map_err(|err| err.to_string())?
+            .ok_or("no Main-Class in the manifest")?;
+        let class_file =
+            jar.parse_class(&main).map_err(|err| err.to_string())?;
+        let mut classpath = vec![path.to_path_buf()];
+        classpath.extend(options.classpath.iter().cloned());
+        return Ok((class_file, classpath));
+    }
+    let bytes = read_class_file(path).map_err(|err| err.to_string())?;
+    let class_file = JVMParser::parse(&bytes).map_err(|err| err.to_string())?;
+    let classpath = if options.classpath.is_empty() {
+        let class = class_file.class_name().unwrap_or_default();
+        ClassLoader::default_classpath(path, class)
+    } else {
+        options.classpath.clone()
+    };
+    Ok((class_file, classpath))
+}
+
+/// Output regression flags, `--record-output` writes a baseline of the
+/// programs' outcomes and `--check-output` compares against one.
+enum OutputMode {
+    Record(std::path::PathBuf),
+    Check(std::path::PathBuf),
+}
+
+impl OutputMode {
+    /// Remove the output regression flag and its argument from `args`,
+    /// returns the flag if its argument is missing.
+    fn parse(args: &mut Vec<String>) -> Result<Option<Self>, String> {
+        for flag in ["--record-output", "--check-output"] {
+            let Some(index) = args.iter().position(|arg| arg == flag) else {
+                continue;
+            };
+            if index + 1 >= args.len() {
+                return Err(flag.to_string());
+            }
+            let path = std::path::PathBuf::from(args.remove(index + 1));
+            args.remove(index);
+            return Ok(Some(if flag == "--record-output" {
+                Self::Record(path)
+            } else {
+                Self::Check(path)
+            }));
+        }
+        Ok(None)
+    }
+
+    /// Run the corpus and record or check its outcomes, exits with a non
+    /// zero code if any program regressed.
+    fn run(&self, paths: &[std::path::PathBuf], jit_mode: bool) -> ! {
+        let actual = Baseline::record(paths, jit_mode);
+        match self {
+            Self::Record(baseline) => {
+                if let Err(err) = std::fs::write(baseline, actual.to_string()) {
+                    eprintln!("Failed to write baseline : {err}");
+                    exit(1);
+                }
+                println!(
+                    "Recorded {} programs to {}",
+                    actual.outcomes.len(),
+                    baseline.display()
+                );
+                exit(0);
+            }
+            Self::Check(baseline) => {
+                let expected = match std::fs::read_to_string(baseline)
+                    .map_err(|err| err.to_string())
+                    .and_then(|source| {
+                        Baseline::parse(&source).map_err(|err| err.to_string())
+                    }) {
+                    Ok(expected) => expected,
+                    Err(err) => {
+                        eprintln!(
+                            "Failed to read baseline {} : {err}",
+                            baseline.display()
+                        );
+                        exit(1);
+                    }
+                };
+                let regressions = expected.compare(&actual);
+                for regression in &regressions {
+                    print!("{regression}");
+                }
+                if regressions.is_empty() {
+                    println!(
+                        "{} programs match {}",
+                        actual.outcomes.len(),
+                        baseline.display()
+                    );
+                    exit(0);
+                }
+                println!("{} programs regressed", regressions.len());
+                exit(1);
+            }
+        }
+    }
+}
+
+/// Returns the class files in `folder` that the runtime supports sorted by
+/// name.
+fn class_files(folder: &str) -> Vec<std::path::PathBuf> {
     let mut paths: Vec<std::path::PathBuf> = Vec::new();
     let to_skip: Vec<&str> = vec![
         "DoubleFibonacci.class",
@@ -51,7 +413,7 @@ fn main() {
         let path = match path {
             Ok(entry) => entry.path(),
             Err(err) => {
-                println!("Error occured when reading file paths : {err}");
+                eprintln!("Error occured when reading file paths : {err}");
                 exit(1);
             }
         };
@@ -64,25 +426,140 @@ fn main() {
             }
         }
     }
-    for path in &paths {
-        let class_file_bytes = read_class_file(path).unwrap_or_else(|_| {
-            panic!("Failed to read class file : {:?}", path.as_os_str())
-        });
-        let class_file =
-            JVMParser::parse(&class_file_bytes).unwrap_or_else(|_| {
-                panic!("Failed to parse class file {:?}", path.as_os_str())
-            });
+    paths.sort();
+    paths
+}
 
-        let program = Program::new(&class_file);
-        let mut runtime = Runtime::new(program);
-        match runtime.run(jit_mode) {
-            Ok(()) => {
-                println!(
-                    "[+] Program {:?} finished running successfully !",
-                    path.file_name().unwrap()
-                );
+/// Print the dependency graph of a class, `args` holds the class file or
+/// class name followed by the optional `--json` flag. The `--classpath`
+/// flag is read along with the other runtime options.
+fn deps(args: &[String], classpath: &[std::path::PathBuf]) -> ! {
+    const USAGE: &str =
+        "Usage : coldbrew deps <class> [--classpath <dirs>] [--json]";
+    let Some(class) = args.first() else {
+        eprintln!("{USAGE}");
+        exit(64);
+    };
+    let mut json = false;
+    for flag in &args[1..] {
+        if flag != "--json" {
+            eprintln!("{USAGE}");
+            exit(64);
+        }
+        json = true;
+    }
+    // A class file path is resolved on the root of its package
+    // directories by default.
+    let path = std::path::Path::new(class);
+    let (root, default_classpath) = if path.is_file() {
+        let Some(root) = coldbrew::deps::class_name(path) else {
+            eprintln!("Failed to read class file {:?}", path.as_os_str());
+            exit(1);
+        };
+        let classpath = ClassLoader::default_classpath(path, &root);
+        (root, ClassPath::new(classpath))
+    } else {
+        (class.clone(), ClassPath::parse("."))
+    };
+    let classpath = if classpath.is_empty() {
+        default_classpath
+    } else {
+        ClassPath::new(classpath.to_vec())
+    };
+    let graph = DependencyGraph::resolve(&root, &classpath);
+    if json {
+        print!("{}", graph.to_json());
+    } else {
+        print!("{}", graph.to_dot());
+    }
+    for class in graph.missing() {
+        eprintln!("Missing class : {class}");
+    }
+    exit(0);
+}
+
+/// Browse a class file in the terminal inspector.
+#[cfg(feature = "tui")]
+fn inspect(path: &std::path::Path) -> ! {
+    let class_file = match read_class_file(path)
+        .and_then(|bytes| Ok(JVMParser::parse(&bytes)?))
+    {
+        Ok(class_file) => class_file,
+        Err(err) => {
+            eprintln!(
+                "Failed to read class file {:?} : {err}",
+                path.as_os_str()
+            );
+            exit(1);
+        }
+    };
+    match coldbrew::tui::run(class_file) {
+        Ok(()) => exit(0),
+        Err(err) => {
+            eprintln!("Error : {err}");
+            exit(1);
+        }
+    }
+}
+
+#[cfg(not(feature = "tui"))]
+fn inspect(_path: &std::path::Path) -> ! {
+    eprintln!("coldbrew was built without the inspector, rebuild with `--features tui`.");
+    exit(64);
+}
+
+/// Print the unsupported features a class file uses, exits with 1 if there
+/// are any.
+fn check(path: &std::path::Path) -> ! {
+    let report = coldbrew::check::check(&link_class_file(path));
+    print!("{report}");
+    exit(i32::from(!report.is_supported()));
+}
+
+/// Run a class file with coverage enabled and report it, as an lcov
+/// tracefile written to `lcov` or as an annotated disassembly on stdout.
+fn coverage(path: &std::path::Path, lcov: Option<&str>) -> ! {
+    let program = link_class_file(path);
+    let mut runtime = Runtime::new(program.clone());
+    runtime.enable_coverage();
+    let result = runtime.run(false);
+    let coverage = runtime.coverage().cloned().unwrap_or_default();
+    match lcov {
+        Some(file) => {
+            let source = path.display().to_string();
+            if let Err(err) =
+                std::fs::write(file, coverage.lcov(&program, &source))
+            {
+                eprintln!("Failed to write {file} : {err}");
+                exit(1);
             }
-            Err(err) => println!("Error : {err}"),
         }
+        None => print!("{}", coverage.annotate(&program)),
     }
+    if let Err(err) = result {
+        eprintln!("Error : {err}");
+        exit(1);
+    }
+    exit(0);
+}
+
+/// Read the class file at `path` and link it with the classes on its default
+/// class path, exits with 1 if it fails.
+fn link_class_file(path: &std::path::Path) -> Program {
+    let class_file = match read_class_file(path)
+        .and_then(|bytes| Ok(JVMParser::parse(&bytes)?))
+    {
+        Ok(class_file) => class_file,
+        Err(err) => {
+            eprintln!(
+                "Failed to read class file {:?} : {err}",
+                path.as_os_str()
+            );
+            exit(1);
+        }
+    };
+    ClassLoader::link_file(path, &class_file).unwrap_or_else(|err| {
+        eprintln!("Failed to link {} : {err}", path.display());
+        exit(1);
+    })
 }
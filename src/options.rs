@@ -0,0 +1,777 @@
+//! Runtime options and where they come from.
+//!
+//! Options are layered, each source overriding the previous one :
+//!
+//! 1. Built-in defaults.
+//! 2. A TOML config file, `--config=<path>` or `coldbrew.toml` in the
+//!    working directory when it exists.
+//! 3. The `COLDBREW_OPTS` environment variable, holding flags separated by
+//!    whitespace the same way `JAVA_TOOL_OPTIONS` works for the JVM.
+//! 4. Flags given on the command line.
+//!
+//! Config files only support the subset of TOML we need, top level
+//! `key = value` pairs where values are booleans, integers, strings or
+//! arrays of strings :
+//!
+//! ```toml
+//! jit = true
+//! hot_threshold = 10
+//! trace_exec = true
+//! trace_exec_methods = ["factorial"]
+//! properties = ["app.mode=fast"]
+//! classpath = ["classes", "lib/app.jar"]
+//! ```
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+use crate::bytecode::OPCode;
+use crate::events::EventLog;
+use crate::exec_log::ExecLog;
+use crate::log::Level;
+
+/// Name of the config file looked up in the working directory.
+pub const CONFIG_FILE: &str = "coldbrew.toml";
+
+/// Environment variable holding extra flags.
+pub const OPTS_ENV: &str = "COLDBREW_OPTS";
+
+/// `OptionsError` reports an invalid option and where it was set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptionsError {
+    /// Source of the option, e.g. `coldbrew.toml:3` or `COLDBREW_OPTS`.
+    pub origin: String,
+    pub message: String,
+}
+
+impl fmt::Display for OptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} : {}", self.origin, self.message)
+    }
+}
+
+impl std::error::Error for OptionsError {}
+
+/// Resource limits of a run, `None` leaves a resource unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum number of executed instructions. Native traces are charged
+    /// for the instructions they run at every loop back edge.
+    pub instructions: Option<usize>,
+    /// Maximum number of frames on the call stack.
+    pub stack_depth: Option<usize>,
+    /// Maximum estimated size of the heap in bytes, see `Object::size`.
+    /// Objects are counted when they're allocated, collections and string
+    /// builders growing afterwards aren't.
+    pub heap: Option<usize>,
+}
+
+/// Thresholds of the tiering policy, see `coldbrew::tiering`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TierThresholds {
+    /// Invocations after which a method is compiled by the baseline
+    /// compiler.
+    pub baseline_invocations: usize,
+    /// Aborted trace recordings after which a method is compiled by the
+    /// baseline compiler, its loops are never left to the tracing JIT again.
+    pub baseline_aborts: usize,
+    /// Back edges run by the baseline code of a method after which its
+    /// loops are left to the tracing JIT.
+    pub trace_back_edges: usize,
+    /// Deopts after which baseline code or a native trace is dropped.
+    pub deopts: usize,
+}
+
+impl Default for TierThresholds {
+    fn default() -> Self {
+        Self {
+            baseline_invocations: 1000,
+            baseline_aborts: 3,
+            trace_back_edges: 1000,
+            deopts: 100,
+        }
+    }
+}
+
+/// Flags setting the tiering thresholds and their config keys.
+const TIER_FLAGS: [(&str, &str); 4] = [
+    ("--baseline-invocations=", "baseline_invocations"),
+    ("--baseline-aborts=", "baseline_aborts"),
+    ("--trace-back-edges=", "trace_back_edges"),
+    ("--deopt-limit=", "deopt_limit"),
+];
+
+/// `LimitKind` names the limit a run exceeded along with its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    Instructions(usize),
+    StackDepth(usize),
+    Heap(usize),
+}
+
+impl fmt::Display for LimitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Instructions(limit) => {
+                write!(f, "instruction limit of {limit}")
+            }
+            Self::StackDepth(limit) => {
+                write!(f, "stack depth limit of {limit}")
+            }
+            Self::Heap(limit) => write!(f, "heap limit of {limit} bytes"),
+        }
+    }
+}
+
+/// Knobs controlling how programs are run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeOptions {
+    /// Compile hot loops with the tracing JIT.
+    pub jit: bool,
+    /// Number of loop entries after which a loop is considered hot.
+    pub hot_threshold: usize,
+    /// Print the time spent in each phase of the run.
+    pub time: bool,
+    /// Print per loop execution counters and the time native traces saved.
+    pub stats: bool,
+    /// Poison invalidated native traces and verify trace checksums.
+    pub jit_checks: bool,
+    /// Maximum size in bytes of the machine code of native traces, the
+    /// least recently entered traces are evicted past it.
+    pub code_cache_size: Option<usize>,
+    /// Log every interpreted instruction.
+    pub trace_exec: bool,
+    /// Only log instructions of these methods, all methods when empty.
+    pub trace_exec_methods: Vec<String>,
+    /// Only log these opcodes, all opcodes when empty.
+    pub trace_exec_opcodes: Vec<OPCode>,
+    /// File the execution log is written to.
+    pub trace_exec_file: PathBuf,
+    /// File the JSON lines event log is written to, no log when unset.
+    pub events: Option<PathBuf>,
+    /// Level of diagnostics written to stderr, `COLDBREW_LOG` when unset.
+    pub log_level: Option<Level>,
+    /// Resource limits enforced while running.
+    pub limits: Limits,
+    /// Refuse to run programs that don't pass `Program::validate`.
+    pub validate: bool,
+    /// When code moves between the interpreter, the baseline compiler and
+    /// native traces.
+    pub tiers: TierThresholds,
+    /// Run common instruction sequences as superinstructions in the
+    /// interpreter, see `coldbrew::fusion`.
+    pub fusion: bool,
+    /// System properties set on top of the defaults, in the order they
+    /// were given.
+    pub properties: Vec<(String, String)>,
+    /// Directories and JARs the classes a program references are loaded
+    /// from, see `coldbrew::loader`. Runs of a class file default to its
+    /// directory when empty.
+    pub classpath: Vec<PathBuf>,
+}
+
+impl Default for RuntimeOptions {
+    fn default() -> Self {
+        Self {
+            jit: false,
+            hot_threshold: 1,
+            time: false,
+            stats: false,
+            jit_checks: false,
+            code_cache_size: None,
+            trace_exec: false,
+            trace_exec_methods: vec![],
+            trace_exec_opcodes: vec![],
+            trace_exec_file: PathBuf::from("coldbrew-exec.log"),
+            events: None,
+            log_level: None,
+            limits: Limits::default(),
+            validate: false,
+            tiers: TierThresholds::default(),
+            fusion: true,
+            properties: vec![],
+            classpath: vec![],
+        }
+    }
+}
+
+impl RuntimeOptions {
+    /// Build the options from every source, the flags understood here are
+    /// removed from `args` and the remaining arguments are left in place.
+    /// Flags are only read up to the class or JAR that's run, the arguments
+    /// following it are passed to its `main` untouched.
+    /// # Errors
+    /// Returns an error if any of the sources holds an invalid option.
+    pub fn load(args: &mut Vec<String>) -> Result<Self, OptionsError> {
+        let mut options = Self::default();
+        let program_args = args.split_off(program_args_start(args));
+        let config = args
+            .iter()
+            .find_map(|arg| arg.strip_prefix("--config="))
+            .map(PathBuf::from);
+        args.retain(|arg| !arg.starts_with("--config="));
+        match config {
+            Some(path) => options.apply_config_file(&path)?,
+            None if Path::new(CONFIG_FILE).exists() => {
+                options.apply_config_file(Path::new(CONFIG_FILE))?;
+            }
+            None => {}
+        }
+        if let Ok(opts) = env::var(OPTS_ENV) {
+            let flags = opts.split_whitespace().map(ToString::to_string);
+            let rest = options.apply_flags(flags, OPTS_ENV)?;
+            if let Some(flag) = rest.first() {
+                return Err(OptionsError {
+                    origin: OPTS_ENV.to_string(),
+                    message: format!("unknown flag `{flag}`"),
+                });
+            }
+        }
+        *args = options.apply_flags(args.drain(..), "command line")?;
+        args.extend(program_args);
+        Ok(options)
+    }
+
+    /// Apply the options set in the config file at `path`.
+    /// # Errors
+    /// Returns an error if the file can't be read or is invalid.
+    pub fn apply_config_file(
+        &mut self,
+        path: &Path,
+    ) -> Result<(), OptionsError> {
+        let source = fs::read_to_string(path).map_err(|err| OptionsError {
+            origin: path.display().to_string(),
+            message: err.to_string(),
+        })?;
+        self.apply_config(&source, &path.display().to_string())
+    }
+
+    /// Apply the options set in the TOML `source`, `origin` names the
+    /// source in error messages.
+    /// # Errors
+    /// Returns an error on syntax errors, unknown keys or invalid values.
+    pub fn apply_config(
+        &mut self,
+        source: &str,
+        origin: &str,
+    ) -> Result<(), OptionsError> {
+        for (index, line) in source.lines().enumerate() {
+            let error = |message: String| OptionsError {
+                origin: format!("{origin}:{}", index + 1),
+                message,
+            };
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error(format!(
+                    "expected `key = value`, got `{line}`"
+                )));
+            };
+            let value = TomlValue::parse(value.trim()).map_err(error)?;
+            self.set(key.trim(), value).map_err(error)?;
+        }
+        Ok(())
+    }
+
+    /// Apply command line style flags, returns the arguments that aren't
+    /// runtime flags in their original order.
+    /// # Errors
+    /// Returns an error if a flag has an invalid value.
+    pub fn apply_flags(
+        &mut self,
+        flags: impl IntoIterator<Item = String>,
+        origin: &str,
+    ) -> Result<Vec<String>, OptionsError> {
+        let mut rest = vec![];
+        let mut flags = flags.into_iter();
+        while let Some(flag) = flags.next() {
+            let error = |message: String| OptionsError {
+                origin: origin.to_string(),
+                message,
+            };
+            let (key, value) = match flag.as_str() {
+                "--jit" => ("jit", TomlValue::Bool(true)),
+                "--time" => ("time", TomlValue::Bool(true)),
+                "--stats" => ("stats", TomlValue::Bool(true)),
+                "--jit-checks" => ("jit_checks", TomlValue::Bool(true)),
+                "--trace-exec" => ("trace_exec", TomlValue::Bool(true)),
+                "--validate" => ("validate", TomlValue::Bool(true)),
+                "--no-fusion" => ("fusion", TomlValue::Bool(false)),
+                // The event log path may also be the next argument.
+                "--events" => {
+                    let path = flags.next().ok_or_else(|| {
+                        error("`--events` expects a path".to_string())
+                    })?;
+                    ("events", TomlValue::String(path))
+                }
+                "--classpath" | "-cp" => {
+                    let classpath = flags.next().ok_or_else(|| {
+                        error(format!("`{flag}` expects a class path"))
+                    })?;
+                    ("classpath", split_classpath(&classpath))
+                }
+                _ => {
+                    if let Some(methods) = flag.strip_prefix("--trace-exec:") {
+                        self.trace_exec = true;
+                        ("trace_exec_methods", split_list(methods))
+                    } else if let Some(v) =
+                        flag.strip_prefix("--trace-exec-opcodes=")
+                    {
+                        ("trace_exec_opcodes", split_list(v))
+                    } else if let Some(v) =
+                        flag.strip_prefix("--trace-exec-file=")
+                    {
+                        ("trace_exec_file", TomlValue::String(v.to_string()))
+                    } else if let Some(v) = flag.strip_prefix("-D") {
+                        ("properties", TomlValue::List(vec![v.to_string()]))
+                    } else if let Some(v) = flag.strip_prefix("--classpath=") {
+                        ("classpath", split_classpath(v))
+                    } else if let Some(v) = flag.strip_prefix("--events=") {
+                        ("events", TomlValue::String(v.to_string()))
+                    } else if let Some(v) = flag.strip_prefix("--log=") {
+                        ("log", TomlValue::String(v.to_string()))
+                    } else if let Some(v) =
+                        flag.strip_prefix("--hot-threshold=")
+                    {
+                        let threshold = v.parse().map_err(|_| {
+                            error(format!("invalid hot threshold `{v}`"))
+                        })?;
+                        ("hot_threshold", TomlValue::Integer(threshold))
+                    } else if let Some(v) =
+                        flag.strip_prefix("--max-instructions=")
+                    {
+                        let limit = v.parse().map_err(|_| {
+                            error(format!("invalid instruction limit `{v}`"))
+                        })?;
+                        ("max_instructions", TomlValue::Integer(limit))
+                    } else if let Some(v) =
+                        flag.strip_prefix("--max-stack-depth=")
+                    {
+                        let limit = v.parse().map_err(|_| {
+                            error(format!("invalid stack depth limit `{v}`"))
+                        })?;
+                        ("max_stack_depth", TomlValue::Integer(limit))
+                    } else if let Some(v) = flag.strip_prefix("--max-heap=") {
+                        let limit = v.parse().map_err(|_| {
+                            error(format!("invalid heap limit `{v}`"))
+                        })?;
+                        ("max_heap", TomlValue::Integer(limit))
+                    } else if let Some(v) =
+                        flag.strip_prefix("--code-cache-size=")
+                    {
+                        let size = v.parse().map_err(|_| {
+                            error(format!("invalid code cache size `{v}`"))
+                        })?;
+                        ("code_cache_size", TomlValue::Integer(size))
+                    } else if let Some((key, v)) =
+                        TIER_FLAGS.iter().find_map(|(prefix, key)| {
+                            Some((*key, flag.strip_prefix(prefix)?))
+                        })
+                    {
+                        let threshold = v.parse().map_err(|_| {
+                            error(format!("invalid `{key}` value `{v}`"))
+                        })?;
+                        (key, TomlValue::Integer(threshold))
+                    } else {
+                        rest.push(flag);
+                        continue;
+                    }
+                }
+            };
+            self.set(key, value).map_err(error)?;
+        }
+        Ok(rest)
+    }
+
+    /// Set the option named `key`.
+    fn set(&mut self, key: &str, value: TomlValue) -> Result<(), String> {
+        match key {
+            "jit" => self.jit = value.as_bool(key)?,
+            "time" => self.time = value.as_bool(key)?,
+            "stats" => self.stats = value.as_bool(key)?,
+            "jit_checks" => self.jit_checks = value.as_bool(key)?,
+            "trace_exec" => self.trace_exec = value.as_bool(key)?,
+            "validate" => self.validate = value.as_bool(key)?,
+            "fusion" => self.fusion = value.as_bool(key)?,
+            "hot_threshold" => {
+                self.hot_threshold = usize::try_from(value.as_integer(key)?)
+                    .map_err(|_| format!("`{key}` must be positive"))?;
+            }
+            "baseline_invocations"
+            | "baseline_aborts"
+            | "trace_back_edges"
+            | "deopt_limit" => {
+                let threshold = usize::try_from(value.as_integer(key)?)
+                    .map_err(|_| format!("`{key}` must be positive"))?;
+                match key {
+                    "baseline_invocations" => {
+                        self.tiers.baseline_invocations = threshold;
+                    }
+                    "baseline_aborts" => self.tiers.baseline_aborts = threshold,
+                    "trace_back_edges" => {
+                        self.tiers.trace_back_edges = threshold;
+                    }
+                    _ => self.tiers.deopts = threshold,
+                }
+            }
+            "max_instructions" => {
+                self.limits.instructions = Some(
+                    usize::try_from(value.as_integer(key)?)
+                        .map_err(|_| format!("`{key}` must be positive"))?,
+                );
+            }
+            "max_stack_depth" => {
+                self.limits.stack_depth = Some(
+                    usize::try_from(value.as_integer(key)?)
+                        .map_err(|_| format!("`{key}` must be positive"))?,
+                );
+            }
+            "max_heap" => {
+                self.limits.heap = Some(
+                    usize::try_from(value.as_integer(key)?)
+                        .map_err(|_| format!("`{key}` must be positive"))?,
+                );
+            }
+            "code_cache_size" => {
+                self.code_cache_size = Some(
+                    usize::try_from(value.as_integer(key)?)
+                        .map_err(|_| format!("`{key}` must be positive"))?,
+                );
+            }
+            "trace_exec_methods" => {
+                self.trace_exec_methods = value.as_list(key)?;
+            }
+            "trace_exec_opcodes" => {
+                self.trace_exec_opcodes = value
+                    .as_list(key)?
+                    .iter()
+                    .map(|mnemonic| {
+                        OPCode::from_mnemonic(mnemonic).ok_or_else(|| {
+                            format!("unknown opcode `{mnemonic}`")
+                        })
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            "trace_exec_file" => {
+                self.trace_exec_file = PathBuf::from(value.as_string(key)?);
+            }
+            "events" => {
+                self.events = Some(PathBuf::from(value.as_string(key)?));
+            }
+            "log" => self.log_level = Some(value.as_string(key)?.parse()?),
+            // Properties given without a value are set to the empty string
+            // like `java -Dkey` does.
+            "properties" => {
+                self.properties.extend(value.as_list(key)?.iter().map(
+                    |property| {
+                        let (key, value) =
+                            property.split_once('=').unwrap_or((property, ""));
+                        (key.to_string(), value.to_string())
+                    },
+                ));
+            }
+            "classpath" => {
+                self.classpath =
+                    value.as_list(key)?.iter().map(PathBuf::from).collect();
+            }
+            _ => return Err(format!("unknown option `{key}`")),
+        }
+        Ok(())
+    }
+
+    /// Returns the execution log to install on the runtime if it was
+    /// requested, the log file is created or appended to so that several
+    /// runs can share it.
+    /// # Errors
+    /// Returns an error if the log file can't be opened.
+    pub fn exec_log(&self) -> io::Result<Option<ExecLog>> {
+        if !self.trace_exec {
+            return Ok(None);
+        }
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.trace_exec_file)?;
+        let mut exec_log = ExecLog::new(Box::new(BufWriter::new(file)));
+        for method in &self.trace_exec_methods {
+            exec_log = exec_log.with_method(method);
+        }
+        for opcode in &self.trace_exec_opcodes {
+            exec_log = exec_log.with_opcode(*opcode);
+        }
+        Ok(Some(exec_log))
+    }
+
+    /// Returns the event log to install on the runtime if it was requested,
+    /// the file is truncated so that it only holds the events of this run.
+    /// # Errors
+    /// Returns an error if the event log file can't be created.
+    pub fn event_log(&self) -> io::Result<Option<EventLog>> {
+        let Some(path) = &self.events else {
+            return Ok(None);
+        };
+        let file = fs::File::create(path)?;
+        Ok(Some(EventLog::new(Box::new(BufWriter::new(file)))))
+    }
+}
+
+/// Values supported in config files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TomlValue {
+    Bool(bool),
+    Integer(i64),
+    String(String),
+    List(Vec<String>),
+}
+
+impl TomlValue {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "true" => return Ok(Self::Bool(true)),
+            "false" => return Ok(Self::Bool(false)),
+            _ => {}
+        }
+        if let Some(string) = parse_string(value) {
+            return Ok(Self::String(string));
+        }
+        if let Some(items) =
+            value.strip_prefix('[').and_then(|v| v.strip_suffix(']'))
+        {
+            return items
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| {
+                    parse_string(item).ok_or_else(|| {
+                        format!("expected a string, got `{item}`")
+                    })
+                })
+                .collect::<Result<_, _>>()
+                .map(Self::List);
+        }
+        value
+            .replace('_', "")
+            .parse()
+            .map(Self::Integer)
+            .map_err(|_| format!("invalid value `{value}`"))
+    }
+
+    fn as_bool(&self, key: &str) -> Result<bool, String> {
+        match self {
+            Self::Bool(value) => Ok(*value),
+            _ => Err(format!("`{key}` expects a boolean")),
+        }
+    }
+
+    fn as_integer(&self, key: &str) -> Result<i64, String> {
+        match self {
+            Self::Integer(value) => Ok(*value),
+            _ => Err(format!("`{key}` expects an integer")),
+        }
+    }
+
+    fn as_string(&self, key: &str) -> Result<String, String> {
+        match self {
+            Self::String(value) => Ok(value.clone()),
+            _ => Err(format!("`{key}` expects a string")),
+        }
+    }
+
+    fn as_list(&self, key: &str) -> Result<Vec<String>, String> {
+        match self {
+            Self::List(values) => Ok(values.clone()),
+            _ => Err(format!("`{key}` expects an array of strings")),
+        }
+    }
+}
+
+/// Parse a basic or literal TOML string, escape sequences aren't
+/// supported.
+fn parse_string(value: &str) -> Option<String> {
+    ['"', '\'']
+        .iter()
+        .find_map(|quote| value.strip_prefix(*quote)?.strip_suffix(*quote))
+        .map(ToString::to_string)
+}
+
+/// Strip a trailing `#` comment that isn't inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (index, c) in line.char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('#', None) => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Returns the index of the first argument of the program's `main` in the
+/// command line `args`, the ones following the class of `run <class>` or
+/// the JAR of `<jar>`. `args.len()` for the other commands.
+fn program_args_start(args: &[String]) -> usize {
+    let mut positional = vec![];
+    // The program name comes first.
+    let mut index = 1;
+    while index < args.len() {
+        let arg = args[index].as_str();
+        index += 1;
+        match arg {
+            // Flags taking the next argument as their value.
+            "--events" | "--classpath" | "-cp" => index += 1,
+            _ if arg.starts_with('-') => {}
+            _ => positional.push(arg),
+        }
+        match positional[..] {
+            ["run", _] => return index,
+            [jar] if jar.ends_with(".jar") => return index,
+            _ => {}
+        }
+    }
+    args.len()
+}
+
+/// Turn a comma separated flag value into a list.
+fn split_list(value: &str) -> TomlValue {
+    TomlValue::List(
+        value
+            .split(',')
+            .filter(|item| !item.is_empty())
+            .map(ToString::to_string)
+            .collect(),
+    )
+}
+
+/// Split a class path made of entries separated by `:`.
+fn split_classpath(value: &str) -> TomlValue {
+    TomlValue::List(
+        value
+            .split(':')
+            .filter(|entry| !entry.is_empty())
+            .map(ToString::to_string)
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_parse_config() {
+        let mut options = RuntimeOptions::default();
+        options
+            .apply_config(
+                r#"
+                # Run everything through the JIT.
+                jit = true
+                hot_threshold = 1_0
+                trace_exec_methods = ["factorial", 'main'] # comment
+                trace_exec_opcodes = ["imul"]
+                trace_exec_file = "exec#1.log"
+                max_stack_depth = 64
+                max_heap = 65536
+                deopt_limit = 8
+                code_cache_size = 4096
+                properties = ["app.mode=fast", "app.debug"]
+                "#,
+                "coldbrew.toml",
+            )
+            .unwrap();
+        assert!(options.jit);
+        assert_eq!(options.hot_threshold, 10);
+        assert_eq!(options.trace_exec_methods, vec!["factorial", "main"]);
+        assert_eq!(options.trace_exec_opcodes, vec![OPCode::IMul]);
+        assert_eq!(options.trace_exec_file, PathBuf::from("exec#1.log"));
+        assert_eq!(options.limits.stack_depth, Some(64));
+        assert_eq!(options.limits.heap, Some(65536));
+        assert_eq!(options.tiers.deopts, 8);
+        assert_eq!(options.code_cache_size, Some(4096));
+        assert_eq!(
+            options.properties,
+            vec![
+                ("app.mode".to_string(), "fast".to_string()),
+                ("app.debug".to_string(), String::new()),
+            ]
+        );
+
+        let err = options.apply_config("\njit = 1", "coldbrew.toml");
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "coldbrew.toml:2 : `jit` expects a boolean"
+        );
+        let err = options.apply_config("colour = true", "coldbrew.toml");
+        assert!(err.unwrap_err().message.contains("unknown option"));
+    }
+
+    #[test]
+    fn later_sources_take_precedence() {
+        let mut options = RuntimeOptions::default();
+        options
+            .apply_config("hot_threshold = 5\ntime = true", "coldbrew.toml")
+            .unwrap();
+        let rest = options
+            .apply_flags(
+                ["--hot-threshold=7", "--jit"].map(String::from),
+                OPTS_ENV,
+            )
+            .unwrap();
+        assert!(rest.is_empty());
+        let rest = options
+            .apply_flags(
+                [
+                    "run",
+                    "Foo.class",
+                    "--hot-threshold=9",
+                    "--events",
+                    "out.jsonl",
+                    "--trace-exec:main",
+                    "-Dline.separator=|",
+                    "--no-fusion",
+                    "-cp",
+                    "classes:lib/app.jar",
+                ]
+                .map(String::from),
+                "command line",
+            )
+            .unwrap();
+        assert_eq!(rest, vec!["run", "Foo.class"]);
+        assert_eq!(options.hot_threshold, 9);
+        assert!(options.jit && options.time && options.trace_exec);
+        assert!(!options.fusion);
+        assert_eq!(
+            options.classpath,
+            vec![PathBuf::from("classes"), PathBuf::from("lib/app.jar")]
+        );
+        assert_eq!(options.trace_exec_methods, vec!["main"]);
+        assert_eq!(options.events, Some(PathBuf::from("out.jsonl")));
+        assert_eq!(
+            options.properties,
+            vec![("line.separator".to_string(), "|".to_string())]
+        );
+    }
+
+    #[test]
+    fn stops_at_the_program_args() {
+        let start = |args: &[&str]| {
+            let args: Vec<String> =
+                args.iter().map(ToString::to_string).collect();
+            program_args_start(&args)
+        };
+        assert_eq!(start(&["coldbrew", "run", "Echo.class", "--jit"]), 3);
+        assert_eq!(
+            start(&["coldbrew", "--jit", "run", "-cp", "run", "Echo.class"]),
+            6
+        );
+        assert_eq!(start(&["coldbrew", "app.jar", "--time"]), 2);
+        assert_eq!(start(&["coldbrew", "run"]), 2);
+        // Other commands take flags anywhere.
+        assert_eq!(start(&["coldbrew", "deps", "Foo.class", "-cp", "out"]), 5);
+    }
+}
@@ -13,6 +13,15 @@ pub struct Profiler {
     last_pc: ProgramCounter,
     // Record of loop entries and their access counts.
     records: HashMap<ProgramCounter, usize>,
+    // Outcomes of the conditional branches executed by the interpreter.
+    branches: HashMap<ProgramCounter, BranchProfile>,
+}
+
+/// Number of times a conditional branch was taken and not taken.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BranchProfile {
+    pub taken: usize,
+    pub not_taken: usize,
 }
 
 impl Profiler {
@@ -21,9 +30,15 @@ impl Profiler {
             threshold: 1,
             last_pc: ProgramCounter::default(),
             records: HashMap::new(),
+            branches: HashMap::new(),
         }
     }
 
+    // Set the number of entries after which a loop header is hot.
+    pub fn set_threshold(&mut self, threshold: usize) {
+        self.threshold = threshold;
+    }
+
     // Count an entry to a loop header, since JVM bytecode is organized by
     // two indexes, the first `method_index` points to the method we are
     // currently executing and the second `instruction_index` actually points
@@ -65,6 +80,28 @@ impl Profiler {
         self.last_pc = *pc
     }
 
+    // Count an execution of the conditional branch at `pc`, the recorder
+    // only sees the direction taken once while the trace compiler lays out
+    // side exits by how often they are taken.
+    pub fn count_branch(&mut self, pc: &ProgramCounter, taken: bool) {
+        let profile = self.branches.entry(*pc).or_default();
+        if taken {
+            profile.taken += 1;
+        } else {
+            profile.not_taken += 1;
+        }
+    }
+
+    // Returns the outcomes of the conditional branch at `pc`.
+    pub fn branch_profile(&self, pc: &ProgramCounter) -> Option<BranchProfile> {
+        self.branches.get(pc).copied()
+    }
+
+    // Returns the number of entries counted for the loop header at `pc`.
+    pub fn loop_entries(&self, pc: &ProgramCounter) -> usize {
+        self.records.get(pc).copied().unwrap_or_default()
+    }
+
     // Returns whether a given `pc` is considered "hot" which just signals
     // to the recorder to start recording a trace.
     pub fn is_hot(&self, pc: &ProgramCounter) -> bool {
@@ -1,17 +1,61 @@
 //! Abstract representation of a Java program.
-use crate::jvm::{AttributeInfo, CPInfo, JVMClassFile, StackMapFrame};
+//!
+//! A program is made of one or more linked classes, the first one holds the
+//! entry point. Methods of every class are stored in a single table in
+//! declaration order and identified by their position, the method index held
+//! by program counters. Overloaded methods share a name so lookups go through
+//! the `(class, name, descriptor)` triple.
+//!
+//! Method and field references of every constant pool are resolved once when
+//! the program is built into a global symbol table. References to members of
+//! linked classes point at their method or field, the others are left
+//! unresolved for the library methods and natives the runtime provides.
+//! Methods a class doesn't declare are looked up in its superclasses, then
+//! among the default methods of its superinterfaces where the most specific
+//! one wins. Fields are looked up in the superclasses and superinterfaces
+//! the same way.
+//!
+//! Static initializers are found at the same time, each class tracks
+//! whether its initializer ran so the runtime runs it once.
+//!
+//! Loadable constants are decoded at the same time into a table of runtime
+//! constants per class, so `ldc` and `ldc2_w` only index into it.
+//!
+//! The code of every method is then decoded once into an instruction stream
+//! with its references already resolved, the interpreter fetches decoded
+//! instructions instead of decoding the bytecode each time it runs it.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::bytecode::OPCode;
+use crate::disasm::{self, DecodedInstruction};
+use crate::error::{LinkError, LinkErrorKind};
+use crate::jvm::{
+    AttributeInfo, CPInfo, ExceptionEntry, JVMClassFile, LineNumber,
+    StackMapFrame, ACC_ABSTRACT, ACC_PRIVATE, ACC_PUBLIC, ACC_STATIC,
+    REF_GET_FIELD, REF_INVOKE_INTERFACE,
+};
+use crate::runtime::{Instruction, Value};
+use crate::validate::{self, Diagnostic};
 
 use regex::Regex;
 
-/// Primitive types supported by the JVM.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Types of JVM field descriptors.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum BaseTypeKind {
     Int,
     Long,
     Float,
     Double,
+    Byte,
+    Char,
+    Short,
+    Boolean,
     Void,
     String,
+    /// Instance of the class with the given binary name, other than
+    /// `java/lang/String`.
+    Object(String),
     List,
 }
 
@@ -22,6 +66,12 @@ pub struct Type {
     sub_t: Option<Box<Type>>,
 }
 
+impl Default for Type {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Type {
     /// Empty constructor, we could use `Default` but hey.
     pub fn new() -> Self {
@@ -33,86 +83,388 @@ impl Type {
     /// Returns the size in WORD (4 bytes) of a given type.
     pub fn size(&self) -> usize {
         match self.t {
-            BaseTypeKind::Int | BaseTypeKind::Float => 1,
             BaseTypeKind::Long | BaseTypeKind::Double => 2,
-            _ => 0,
+            BaseTypeKind::Void => 0,
+            // Sub word primitives are stored as ints and references take a
+            // single slot.
+            _ => 1,
         }
     }
 }
 
+/// Index of a method in the method table of a program.
+pub type MethodId = usize;
+
+/// Name and descriptor of the standard entry point.
+const MAIN: (&str, &str) = ("main", "([Ljava/lang/String;)V");
+
+/// Name and descriptor of static initializers.
+const CLINIT: (&str, &str) = ("<clinit>", "()V");
+
 /// Representation of Java programs that we want to run.
 #[derive(Debug, Clone)]
 pub struct Program {
-    // Constant pool.
-    pub constant_pool: Vec<CPInfo>,
-    // Methods.
-    // pub methods: HashMap<usize, Method>,
-    pub methods: Vec<Method>,
+    // Linked classes, the first one holds the entry point.
+    pub(crate) classes: Vec<Class>,
+    // Methods of every class, indexed by method index.
+    pub(crate) methods: Vec<Method>,
+    // Fields of every class, indexed by field index.
+    fields: Vec<Field>,
+    // Method and field indexes keyed by kind, class index, name and
+    // descriptor.
+    members: HashMap<(SymbolKind, usize, String, String), usize>,
+    // Symbols referenced by the constant pools, indexed by symbol id.
+    symbols: Vec<Symbol>,
+    // Interned string constants and class names, indexed by string id.
+    strings: Vec<String>,
+    // Class indexes keyed by binary name.
+    class_indexes: HashMap<String, usize>,
+    // Name and descriptor of the method to start from instead of `main`.
+    entry: Option<(String, String)>,
 }
 
-/// Java class method representation for the interpreter.
+/// A class linked into a program.
 #[derive(Debug, Clone)]
-pub struct Method {
-    _name_index: u16,
+pub(crate) struct Class {
+    // Binary name of the class, empty for programs built from raw code.
+    pub(crate) name: String,
+    // Binary names of the super class and the direct superinterfaces.
+    superclass: Option<String>,
+    interfaces: Vec<String>,
+    pub(crate) constant_pool: Vec<CPInfo>,
+    // Symbol ids keyed by the constant pool index of the references.
+    symbols: HashMap<usize, usize>,
+    // Constant pool entries decoded for the interpreter, indexed like the
+    // constant pool.
+    constants: Vec<RuntimeConstant>,
+    // Method index of the static initializer.
+    clinit: Option<usize>,
+    state: ClassState,
+    // Name of the source file from the `SourceFile` attribute.
+    source_file: Option<String>,
+    // Methods that virtual calls through a symbol run on instances of the
+    // class keyed by symbol id, filled as calls are dispatched.
+    dispatch: HashMap<usize, Option<MethodId>>,
+    // Slots in instances of the class of the fields symbols resolve to
+    // keyed by symbol id, filled as fields are accessed.
+    field_slots: HashMap<usize, Option<usize>>,
+}
+
+/// Initialization state of a linked class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClassState {
+    /// The static initializer hasn't run yet.
+    Uninitialized,
+    /// The static initializer is running, the class can be used by the
+    /// initializer itself.
+    Initializing,
+    /// The static initializer ran or neither the class nor its
+    /// superclasses have one.
+    Initialized,
+}
+
+/// A constant pool entry decoded when the program is built.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RuntimeConstant {
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    /// Id of the interned string, see `Program::string`.
+    String(usize),
+    /// Class reference, `name` is the id of the interned binary name and
+    /// `index` the linked class, `None` if the class isn't part of the
+    /// program.
+    Class {
+        name: usize,
+        index: Option<usize>,
+    },
+    /// Id of the symbol of a method reference.
+    Method(usize),
+    /// Id of the symbol of a field reference.
+    Field(usize),
+    /// Id of the interned method descriptor.
+    MethodType(usize),
+    /// Method handle of kind `kind`, one of the `REF_` constants, on the
+    /// member with symbol id `symbol`.
+    MethodHandle {
+        kind: u8,
+        symbol: usize,
+    },
+    /// Entries that can't be loaded such as names and descriptors, and the
+    /// slot following longs and doubles.
+    Unusable,
+}
+
+/// Java class field representation.
+#[derive(Debug, Clone)]
+struct Field {
+    class: usize,
+    name: String,
+    descriptor: String,
+    access_flags: u16,
+    // Constant pool index of the initial value of constant static fields.
+    constant: Option<u16>,
+}
+
+/// Kind of member a symbol refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolKind {
+    Method,
+    Field,
+}
+
+/// A method or field referenced by the constant pool of a linked class.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub kind: SymbolKind,
+    /// Binary name of the class declaring the member.
+    pub class: String,
+    pub name: String,
+    pub descriptor: String,
+    /// Method or field index of the member, `None` if its class isn't part
+    /// of the program.
+    pub target: Option<usize>,
+    /// Number of arguments a method takes, the receiver excluded, zero for
+    /// fields.
+    pub arguments: usize,
+}
+
+impl Symbol {
+    /// Returns the symbol as a method reference.
+    pub fn method_ref(&self) -> MethodRef<'_> {
+        MethodRef {
+            class: &self.class,
+            name: &self.name,
+            descriptor: &self.descriptor,
+        }
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            SymbolKind::Method => write!(f, "{}", self.method_ref()),
+            SymbolKind::Field => {
+                write!(f, "{}.{}:{}", self.class, self.name, self.descriptor)
+            }
+        }
+    }
+}
+
+/// A method reference resolved from the constant pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MethodRef<'a> {
+    /// Binary name of the class declaring the method.
+    pub class: &'a str,
+    pub name: &'a str,
+    pub descriptor: &'a str,
+}
+
+impl fmt::Display for MethodRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}{}", self.class, self.name, self.descriptor)
+    }
+}
+
+/// Java class method representation for the interpreter.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Method {
+    // Index of the declaring class.
+    pub(crate) class: usize,
+    pub(crate) name: String,
+    pub(crate) access_flags: u16,
     _return_type: Type,
-    pub arg_types: Vec<Type>,
-    _max_stack: u16,
-    pub max_locals: u16,
-    pub code: Vec<u8>,
+    pub(crate) arg_types: Vec<Type>,
+    pub(crate) descriptor: String,
+    pub(crate) max_stack: u16,
+    pub(crate) max_locals: u16,
+    pub(crate) code: Vec<u8>,
+    // `code` decoded for the interpreter.
+    instructions: InstructionStream,
     _constant: Option<u16>,
-    _stack_map_table: Option<Vec<StackMapFrame>>,
+    exception_table: Vec<ExceptionEntry>,
+    line_numbers: Vec<LineNumber>,
+    stack_map_table: Vec<StackMapFrame>,
 }
 
-impl Default for Method {
-    fn default() -> Self {
-        Self {
-            _name_index: 0,
-            _return_type: Type::new(),
-            arg_types: Vec::new(),
-            _max_stack: 0,
-            max_locals: 0,
-            code: Vec::new(),
-            _constant: None,
-            _stack_map_table: None,
-        }
+/// Instructions of a method decoded for the interpreter.
+///
+/// Operands are laid out the way the interpreter consumes them, method and
+/// field references hold the method or field index and the symbol id, `-1`
+/// when unresolved, and class references the id of the interned name. `ldc`
+/// keeps the constant pool index, strings and classes are materialized on
+/// the heap when they run. Branch operands stay relative offsets as program
+/// counters, traces and native code are keyed by offset, `index` maps an
+/// offset to the position of its instruction in the stream.
+#[derive(Debug, Clone, Default)]
+pub struct InstructionStream {
+    instructions: Vec<Instruction>,
+    // Offset of every instruction followed by the end of the last one.
+    offsets: Vec<usize>,
+    // Position of the instruction starting at each offset of the code,
+    // `None` for offsets in the middle of an instruction.
+    indexes: Vec<Option<u32>>,
+}
+
+impl InstructionStream {
+    /// Returns the instruction starting at `offset` along with the offset
+    /// of the next one.
+    pub fn get(&self, offset: usize) -> Option<(&Instruction, usize)> {
+        let index = self.index(offset)?;
+        Some((&self.instructions[index], self.offsets[index + 1]))
+    }
+
+    /// Returns the position in the stream of the instruction at `offset`.
+    pub fn index(&self, offset: usize) -> Option<usize> {
+        self.indexes
+            .get(offset)
+            .copied()
+            .flatten()
+            .map(|index| index as usize)
+    }
+
+    /// Returns the offset of the instruction at position `index`.
+    pub fn offset(&self, index: usize) -> Option<usize> {
+        (index < self.instructions.len()).then(|| self.offsets[index])
+    }
+
+    /// Returns the number of decoded instructions.
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Returns true if the method has no code.
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    /// Returns an iterator over the decoded instructions in code order.
+    pub fn iter(&self) -> std::slice::Iter<'_, Instruction> {
+        self.instructions.iter()
     }
 }
 
 impl Program {
-    /// Build a new program from a parsed class file.
-    /// # Panics
-    /// Can panic if class file is missing Code attribute.
+    /// Build a new program from a parsed class file, abstract and native
+    /// methods have no code. References to the class's own members that
+    /// don't exist are left unresolved.
     #[must_use]
     pub fn new(class_file: &JVMClassFile) -> Self {
+        Self::with_classes(vec![Self::load(class_file)])
+    }
+
+    /// Link several parsed classes into a single program, the first class
+    /// holds the entry point.
+    /// # Errors
+    /// Returns an error if two classes share a name or if a class references
+    /// a field that a linked class doesn't declare. References to other
+    /// classes and to methods that don't resolve are left unresolved, see
+    /// `unresolved`.
+    pub fn link(class_files: &[JVMClassFile]) -> Result<Self, LinkError> {
+        let mut loaded: Vec<(Class, Vec<Method>, Vec<Field>)> = Vec::new();
+        for class_file in class_files {
+            let class = Self::load(class_file);
+            if loaded
+                .iter()
+                .any(|(other, _, _)| other.name == class.0.name)
+            {
+                return Err(LinkError {
+                    class: class.0.name,
+                    method: String::new(),
+                    kind: LinkErrorKind::DuplicateClass,
+                });
+            }
+            loaded.push(class);
+        }
+        let program = Self::with_classes(loaded);
+        // Missing and conflicting methods only fail the calls that reach
+        // them, the runtime throws `NoSuchMethodError` or
+        // `IncompatibleClassChangeError` at invoke time.
+        let missing = program.unresolved().find(|symbol| {
+            symbol.kind == SymbolKind::Field
+                && program.class_index(&symbol.class).is_some()
+        });
+        if let Some(symbol) = missing {
+            return Err(LinkError {
+                class: symbol.class.clone(),
+                method: format!("{}{}", symbol.name, symbol.descriptor),
+                kind: LinkErrorKind::UnknownField,
+            });
+        }
+        Ok(program)
+    }
+
+    /// Build a program holding a single `main` method made of `code`, used
+    /// to run bytecode that doesn't come from a class file.
+    #[must_use]
+    pub fn from_code(code: Vec<u8>, max_locals: u16) -> Self {
+        let class = Class {
+            name: String::new(),
+            superclass: None,
+            interfaces: vec![],
+            constant_pool: vec![CPInfo::Unspecified],
+            symbols: HashMap::new(),
+            constants: vec![RuntimeConstant::Unusable],
+            clinit: None,
+            state: ClassState::Initialized,
+            source_file: None,
+            dispatch: HashMap::new(),
+            field_slots: HashMap::new(),
+        };
+        let main = Method {
+            name: MAIN.0.to_string(),
+            access_flags: ACC_PUBLIC | ACC_STATIC,
+            descriptor: MAIN.1.to_string(),
+            max_locals,
+            code,
+            ..Method::default()
+        };
+        Self::with_classes(vec![(class, vec![main], vec![])])
+    }
+
+    /// Read the class, methods and fields of `class_file`.
+    fn load(class_file: &JVMClassFile) -> (Class, Vec<Method>, Vec<Field>) {
         let constants = class_file.constant_pool();
-        // let mut methods: HashMap<usize, Method> = HashMap::new();
-        let mut methods: Vec<Method> = vec![Method::default(); 256];
+        let mut methods: Vec<Method> = Vec::new();
         for method_info in &class_file.methods() {
             let mut arg_types: Vec<Type> = Vec::new();
             let mut return_type: Type = Type {
                 t: BaseTypeKind::Void,
                 sub_t: None,
             };
-            let descriptor =
-                &constants[method_info.descriptor_index() as usize];
-            let _method_name = &constants[method_info.name_index() as usize];
-
-            if let CPInfo::ConstantUtf8 { bytes } = descriptor {
-                (arg_types, return_type) = Self::parse_method_types(bytes);
+            let name = class_file
+                .utf8(method_info.name_index())
+                .unwrap_or_default()
+                .to_string();
+            let mut method_descriptor = String::new();
+            if let Some(descriptor) =
+                class_file.utf8(method_info.descriptor_index())
+            {
+                (arg_types, return_type) = Self::parse_method_types(descriptor);
+                method_descriptor = descriptor.to_string();
             }
             let attr = method_info.attributes();
 
-            let (max_stack, max_locals, code) =
+            let (max_stack, max_locals, code, exception_table, code_attr) =
                 if let Some(AttributeInfo::CodeAttribute {
                     max_stack,
                     max_locals,
                     code,
+                    exception_table,
+                    attributes,
                     ..
                 }) = attr.get("Code")
                 {
-                    (*max_stack, *max_locals, code.clone())
+                    (
+                        *max_stack,
+                        *max_locals,
+                        code.clone(),
+                        exception_table.clone(),
+                        attributes.clone(),
+                    )
                 } else {
-                    panic!("Expected at least one code attribute")
+                    (0, 0, vec![], vec![], BTreeMap::new())
                 };
 
             let constant =
@@ -126,76 +478,953 @@ impl Program {
                     None
                 };
 
+            // Stack maps and line numbers are attributes of the code.
             let stack_map_table =
                 if let Some(AttributeInfo::StackMapTableAttribute {
                     entries,
                     ..
-                }) = attr.get("StackMapTable")
+                }) = code_attr.get("StackMapTable")
                 {
-                    Some(entries.clone())
+                    entries.clone()
                 } else {
-                    None
+                    vec![]
                 };
 
-            let method = Method {
-                _name_index: method_info.name_index(),
+            let line_numbers =
+                if let Some(AttributeInfo::LineNumberTableAttribute {
+                    line_numbers,
+                    ..
+                }) = code_attr.get("LineNumberTable")
+                {
+                    line_numbers.clone()
+                } else {
+                    vec![]
+                };
+
+            methods.push(Method {
+                class: 0,
+                name,
+                access_flags: method_info.access_flags(),
                 _return_type: return_type,
                 arg_types,
-                _max_stack: max_stack,
+                descriptor: method_descriptor,
+                max_stack,
                 max_locals,
                 code,
+                instructions: InstructionStream::default(),
                 _constant: constant,
-                _stack_map_table: stack_map_table,
-            };
-            // methods.insert(method_info.name_index() as usize, method);
-            methods[method_info.name_index() as usize] = method;
+                exception_table,
+                line_numbers,
+                stack_map_table,
+            });
         }
 
-        Self {
-            // Get a copy of the constant pool.
-            constant_pool: class_file.constant_pool(),
-            // Get a copy of the program methods.
-            methods,
+        let fields = class_file
+            .fields()
+            .iter()
+            .map(|field| Field {
+                class: 0,
+                name: class_file
+                    .utf8(field.name_index())
+                    .unwrap_or_default()
+                    .to_string(),
+                descriptor: class_file
+                    .utf8(field.descriptor_index())
+                    .unwrap_or_default()
+                    .to_string(),
+                access_flags: field.access_flags(),
+                constant: field.constant_value(),
+            })
+            .collect();
+
+        let name = class_name_at(&constants, class_file.this_class() as usize)
+            .unwrap_or_default()
+            .to_string();
+        let superclass =
+            class_name_at(&constants, class_file.super_class() as usize)
+                .map(ToString::to_string);
+        let interfaces = class_file
+            .interfaces()
+            .iter()
+            .filter_map(|index| class_name_at(&constants, *index as usize))
+            .map(ToString::to_string)
+            .collect();
+        let source_file = match class_file.attributes().get("SourceFile") {
+            Some(AttributeInfo::SourceFileAttribute {
+                source_file_index,
+                ..
+            }) => class_file.utf8(*source_file_index).map(ToString::to_string),
+            _ => None,
+        };
+        let class = Class {
+            name,
+            superclass,
+            interfaces,
+            constant_pool: constants,
+            symbols: HashMap::new(),
+            constants: Vec::new(),
+            clinit: None,
+            state: ClassState::Initialized,
+            source_file,
+            dispatch: HashMap::new(),
+            field_slots: HashMap::new(),
+        };
+        (class, methods, fields)
+    }
+
+    /// Merge the members of `classes` into the global tables, find their
+    /// static initializers, resolve the method and field references of their
+    /// constant pools and decode their loadable constants.
+    fn with_classes(classes: Vec<(Class, Vec<Method>, Vec<Field>)>) -> Self {
+        let mut program = Self {
+            classes: Vec::new(),
+            methods: Vec::new(),
+            fields: Vec::new(),
+            members: HashMap::new(),
+            symbols: Vec::new(),
+            strings: Vec::new(),
+            class_indexes: HashMap::new(),
+            entry: None,
+        };
+        for (index, (class, methods, fields)) in classes.into_iter().enumerate()
+        {
+            program.class_indexes.insert(class.name.clone(), index);
+            program.classes.push(class);
+            for method in methods {
+                if (method.name.as_str(), method.descriptor.as_str()) == CLINIT
+                {
+                    program.classes[index].clinit = Some(program.methods.len());
+                }
+                let key = (
+                    SymbolKind::Method,
+                    index,
+                    method.name.clone(),
+                    method.descriptor.clone(),
+                );
+                program.members.insert(key, program.methods.len());
+                program.methods.push(Method {
+                    class: index,
+                    ..method
+                });
+            }
+            for field in fields {
+                let key = (
+                    SymbolKind::Field,
+                    index,
+                    field.name.clone(),
+                    field.descriptor.clone(),
+                );
+                program.members.insert(key, program.fields.len());
+                program.fields.push(Field {
+                    class: index,
+                    ..field
+                });
+            }
+        }
+        // Initializing a class initializes its superclasses first, classes
+        // that inherit a static initializer need initializing too.
+        for class in 0..program.classes.len() {
+            if program
+                .superclasses(class)
+                .iter()
+                .any(|index| program.classes[*index].clinit.is_some())
+            {
+                program.classes[class].state = ClassState::Uninitialized;
+            }
+        }
+
+        // The same member referenced by several classes gets a single
+        // symbol.
+        let mut ids: HashMap<(SymbolKind, String, String, String), usize> =
+            HashMap::new();
+        for class in 0..program.classes.len() {
+            let pool = &program.classes[class].constant_pool;
+            let references: Vec<(usize, SymbolKind, String, String, String)> =
+                (0..pool.len())
+                    .filter_map(|index| {
+                        let (kind, class, name, descriptor) =
+                            member_ref(pool, index)?;
+                        Some((
+                            index,
+                            kind,
+                            class.to_string(),
+                            name.to_string(),
+                            descriptor.to_string(),
+                        ))
+                    })
+                    .collect();
+            for (index, kind, class_name, name, descriptor) in references {
+                let key = (
+                    kind,
+                    class_name.clone(),
+                    name.clone(),
+                    descriptor.clone(),
+                );
+                let id = match ids.get(&key) {
+                    Some(id) => *id,
+                    None => {
+                        let target = program.class_index(&class_name).and_then(
+                            |owner| match kind {
+                                SymbolKind::Method => program
+                                    .resolve_method(owner, &name, &descriptor)
+                                    .ok()
+                                    .flatten(),
+                                SymbolKind::Field => program.resolve_field(
+                                    owner,
+                                    &name,
+                                    &descriptor,
+                                ),
+                            },
+                        );
+                        let arguments = match kind {
+                            SymbolKind::Method => {
+                                Self::parse_method_types(&descriptor).0.len()
+                            }
+                            SymbolKind::Field => 0,
+                        };
+                        program.symbols.push(Symbol {
+                            kind,
+                            class: class_name,
+                            name,
+                            descriptor,
+                            target,
+                            arguments,
+                        });
+                        ids.insert(key, program.symbols.len() - 1);
+                        program.symbols.len() - 1
+                    }
+                };
+                program.classes[class].symbols.insert(index, id);
+            }
+        }
+
+        let mut strings: HashMap<String, usize> = HashMap::new();
+        for class in 0..program.classes.len() {
+            let constants = (0..program.classes[class].constant_pool.len())
+                .map(|index| {
+                    program.resolve_constant(class, index, &mut strings)
+                })
+                .collect();
+            program.classes[class].constants = constants;
+        }
+        for method_index in 0..program.methods.len() {
+            program.methods[method_index].instructions =
+                program.decode(method_index);
         }
+        program
     }
 
-    // Find method name index in the constant pool by reference.
-    pub fn find_method(&self, method_ref: usize) -> i32 {
-        match self.constant_pool[method_ref] {
-            CPInfo::ConstantMethodRef {
-                name_and_type_index,
-                ..
-            } => {
-                if let CPInfo::ConstantNameAndType { name_index, .. } =
-                    self.constant_pool[name_and_type_index as usize]
+    /// Decode the code of the method at `method_index`. Code ending in the
+    /// middle of an instruction is decoded up to that instruction.
+    fn decode(&self, method_index: usize) -> InstructionStream {
+        let code = &self.methods[method_index].code;
+        let (decoded, length) = match disasm::disassemble(code) {
+            Ok(decoded) => (decoded, code.len()),
+            Err(err) => (
+                disasm::disassemble(&code[..err.offset]).unwrap_or_default(),
+                err.offset,
+            ),
+        };
+        let mut stream = InstructionStream {
+            instructions: Vec::with_capacity(decoded.len()),
+            offsets: Vec::with_capacity(decoded.len() + 1),
+            indexes: vec![None; code.len()],
+        };
+        for (index, inst) in decoded.iter().enumerate() {
+            let end = decoded.get(index + 1).map_or(length, |next| next.offset);
+            stream.indexes[inst.offset] = Some(index as u32);
+            stream.offsets.push(inst.offset);
+            // `wide` is folded into the instruction it modifies, which
+            // then takes a 16 bit local index.
+            let inst = match inst.opcode {
+                OPCode::Wide => Instruction::new(
+                    OPCode::from(inst.operands[0] as u8),
+                    Some(
+                        inst.operands[1..]
+                            .iter()
+                            .map(|operand| Value::Int(*operand as i32))
+                            .collect(),
+                    ),
+                ),
+                opcode => Instruction::new(
+                    opcode,
+                    self.decode_operands(method_index, inst, end),
+                ),
+            };
+            stream.instructions.push(inst);
+        }
+        stream.offsets.push(length);
+        stream
+    }
+
+    /// Returns the operands of `inst` as the interpreter consumes them,
+    /// `end` is the offset following the instruction.
+    fn decode_operands(
+        &self,
+        method_index: usize,
+        inst: &DecodedInstruction,
+        end: usize,
+    ) -> Option<Vec<Value>> {
+        let operand = |index: usize| inst.operands[index] as i32;
+        let operands = match inst.opcode {
+            OPCode::SiPush
+            | OPCode::IfEq
+            | OPCode::IfNe
+            | OPCode::IfLt
+            | OPCode::IfLe
+            | OPCode::IfGt
+            | OPCode::IfGe
+            | OPCode::IfICmpEq
+            | OPCode::IfICmpNe
+            | OPCode::IfICmpLt
+            | OPCode::IfICmpLe
+            | OPCode::IfICmpGt
+            | OPCode::IfICmpGe
+            | OPCode::IfNull
+            | OPCode::IfNonNull
+            | OPCode::Goto
+            | OPCode::BiPush
+            | OPCode::NewArray
+            | OPCode::ILoad
+            | OPCode::FLoad
+            | OPCode::LLoad
+            | OPCode::DLoad
+            | OPCode::ALoad
+            | OPCode::IStore
+            | OPCode::FStore
+            | OPCode::LStore
+            | OPCode::DStore
+            | OPCode::AStore
+            | OPCode::Ldc
+            | OPCode::LdcW
+            | OPCode::Ldc2W => vec![operand(0)],
+            // Switches are preceded by the length of the instruction.
+            OPCode::TableSwitch | OPCode::LookupSwitch => {
+                std::iter::once((end - inst.offset) as i32)
+                    .chain(inst.operands.iter().map(|operand| *operand as i32))
+                    .collect()
+            }
+            OPCode::IInc => vec![operand(0), operand(1)],
+            // Class references are decoded to the class name, followed by
+            // the number of dimensions for `multianewarray`.
+            OPCode::New
+            | OPCode::CheckCast
+            | OPCode::ANewArray
+            | OPCode::MultiANewArray => {
+                match self.constant(method_index, operand(0) as usize) {
+                    Some(RuntimeConstant::Class { name, .. }) => {
+                        let mut operands = vec![name as i32];
+                        operands.extend(
+                            inst.operands[1..].iter().map(|dims| *dims as i32),
+                        );
+                        operands
+                    }
+                    _ => return None,
+                }
+            }
+            // Members that aren't part of the program are left to the
+            // library and natives, -1 marks them.
+            OPCode::InvokeStatic
+            | OPCode::InvokeSpecial
+            | OPCode::InvokeVirtual
+            | OPCode::InvokeInterface
+            | OPCode::GetField
+            | OPCode::PutField
+            | OPCode::GetStatic
+            | OPCode::PutStatic => {
+                let symbol = self.symbol_id(method_index, operand(0) as usize);
+                let target = symbol
+                    .and_then(|id| self.symbol(id)?.target)
+                    .map_or(-1, |index| index as i32);
+                vec![target, symbol.map_or(-1, |id| id as i32)]
+            }
+            _ => return None,
+        };
+        Some(operands.into_iter().map(Value::Int).collect())
+    }
+
+    /// Decode the constant pool entry at `index` of the class at `class`,
+    /// strings and class names are interned through `strings`.
+    fn resolve_constant(
+        &mut self,
+        class: usize,
+        index: usize,
+        strings: &mut HashMap<String, usize>,
+    ) -> RuntimeConstant {
+        let pool = &self.classes[class].constant_pool;
+        let entry = &pool[index];
+        let number = entry
+            .int_value()
+            .map(RuntimeConstant::Int)
+            .or_else(|| entry.float_value().map(RuntimeConstant::Float))
+            .or_else(|| entry.long_value().map(RuntimeConstant::Long))
+            .or_else(|| entry.double_value().map(RuntimeConstant::Double));
+        if let Some(constant) = number {
+            return constant;
+        }
+        match entry {
+            CPInfo::ConstantString { string_index } => {
+                match utf8_at(pool, *string_index as usize) {
+                    Some(string) => {
+                        let string = string.to_string();
+                        RuntimeConstant::String(self.intern(string, strings))
+                    }
+                    None => RuntimeConstant::Unusable,
+                }
+            }
+            CPInfo::ConstantClass { .. } => match class_name_at(pool, index) {
+                Some(name) => {
+                    let name = name.to_string();
+                    let linked = self.class_index(&name);
+                    RuntimeConstant::Class {
+                        name: self.intern(name, strings),
+                        index: linked,
+                    }
+                }
+                None => RuntimeConstant::Unusable,
+            },
+            CPInfo::ConstantMethodRef { .. }
+            | CPInfo::ConstantInterfaceMethodRef { .. }
+            | CPInfo::ConstantFieldRef { .. } => {
+                let Some(&id) = self.classes[class].symbols.get(&index) else {
+                    return RuntimeConstant::Unusable;
+                };
+                match self.symbols[id].kind {
+                    SymbolKind::Method => RuntimeConstant::Method(id),
+                    SymbolKind::Field => RuntimeConstant::Field(id),
+                }
+            }
+            CPInfo::ConstantMethodType { descriptor_index } => {
+                match utf8_at(pool, *descriptor_index as usize) {
+                    Some(descriptor) => {
+                        let descriptor = descriptor.to_string();
+                        RuntimeConstant::MethodType(
+                            self.intern(descriptor, strings),
+                        )
+                    }
+                    None => RuntimeConstant::Unusable,
+                }
+            }
+            CPInfo::ConstantMethodHandle {
+                reference_kind,
+                reference_index,
+            } => match self.classes[class]
+                .symbols
+                .get(&(*reference_index as usize))
+            {
+                Some(&symbol)
+                    if (REF_GET_FIELD..=REF_INVOKE_INTERFACE)
+                        .contains(reference_kind) =>
                 {
-                    return name_index.into();
+                    RuntimeConstant::MethodHandle {
+                        kind: *reference_kind,
+                        symbol,
+                    }
+                }
+                _ => RuntimeConstant::Unusable,
+            },
+            _ => RuntimeConstant::Unusable,
+        }
+    }
+
+    /// Returns the id of `string`, adding it to the string table the first
+    /// time it's seen.
+    fn intern(
+        &mut self,
+        string: String,
+        strings: &mut HashMap<String, usize>,
+    ) -> usize {
+        if let Some(id) = strings.get(&string) {
+            return *id;
+        }
+        self.strings.push(string.clone());
+        strings.insert(string, self.strings.len() - 1);
+        self.strings.len() - 1
+    }
+
+    /// Returns the method `name` with `descriptor` that a call through the
+    /// class at `class` runs. The class and its superclasses come first,
+    /// then the maximally specific default methods of its superinterfaces,
+    /// falling back to an abstract declaration. Classes outside the program
+    /// end the search.
+    /// # Errors
+    /// Returns `IncompatibleClassChange` if several default methods are
+    /// maximally specific.
+    pub fn resolve_method(
+        &self,
+        class: usize,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<Option<MethodId>, LinkErrorKind> {
+        let declared = |class: usize| {
+            self.members
+                .get(&(
+                    SymbolKind::Method,
+                    class,
+                    name.to_string(),
+                    descriptor.to_string(),
+                ))
+                .copied()
+        };
+        let mut superclasses = vec![];
+        let mut current = Some(class);
+        while let Some(index) = current {
+            if let Some(method) = declared(index) {
+                return Ok(Some(method));
+            }
+            superclasses.push(index);
+            current = self.classes[index]
+                .superclass
+                .as_deref()
+                .and_then(|name| self.class_index(name));
+        }
+
+        // Interface methods that are neither static nor private, declared
+        // by one of the superinterfaces.
+        let mut candidates: Vec<(usize, MethodId)> = vec![];
+        for interface in self.superinterfaces(&superclasses) {
+            if let Some(method) = declared(interface) {
+                let flags = self.methods[method].access_flags;
+                if flags & (ACC_STATIC | ACC_PRIVATE) == 0 {
+                    candidates.push((interface, method));
                 }
-                0
             }
-            _ => panic!("Expected ConstantMethodRef"),
         }
+        // Maximally specific methods aren't overridden by another candidate.
+        let specific: Vec<(usize, MethodId)> = candidates
+            .iter()
+            .filter(|(interface, _)| {
+                !candidates.iter().any(|(other, _)| {
+                    other != interface
+                        && self.superinterfaces(&[*other]).contains(interface)
+                })
+            })
+            .copied()
+            .collect();
+        let defaults: Vec<MethodId> = specific
+            .iter()
+            .map(|(_, method)| *method)
+            .filter(|method| {
+                self.methods[*method].access_flags & ACC_ABSTRACT == 0
+            })
+            .collect();
+        match defaults.as_slice() {
+            [method] => Ok(Some(*method)),
+            [] => Ok(specific.first().map(|(_, method)| *method)),
+            _ => Err(LinkErrorKind::IncompatibleClassChange),
+        }
+    }
+
+    /// Returns the field `name` with `descriptor` that an access through
+    /// the class at `class` reaches, declared by the class, one of its
+    /// superclasses or one of their superinterfaces.
+    pub fn resolve_field(
+        &self,
+        class: usize,
+        name: &str,
+        descriptor: &str,
+    ) -> Option<usize> {
+        let declared = |class: usize| {
+            self.members
+                .get(&(
+                    SymbolKind::Field,
+                    class,
+                    name.to_string(),
+                    descriptor.to_string(),
+                ))
+                .copied()
+        };
+        let superclasses = self.superclasses(class);
+        superclasses
+            .iter()
+            .copied()
+            .chain(self.superinterfaces(&superclasses))
+            .find_map(declared)
+    }
+
+    /// Returns the class at `class` followed by its linked superclasses.
+    fn superclasses(&self, class: usize) -> Vec<usize> {
+        let mut classes = vec![];
+        let mut current = Some(class);
+        while let Some(index) = current {
+            classes.push(index);
+            current = self.classes[index]
+                .superclass
+                .as_deref()
+                .and_then(|name| self.class_index(name));
+        }
+        classes
     }
 
-    // Returns program entry point, in this case the index of the method
-    // main.
-    pub fn entry_point(&self) -> usize {
-        for (index, _) in self.methods.iter().enumerate() {
-            match self.constant_pool.get(index) {
-                Some(constant) => {
-                    if let CPInfo::ConstantUtf8 { bytes } = constant {
-                        if bytes == "main" {
-                            return index;
+    /// Returns the name and descriptor of the instance fields of the class
+    /// at `class`, the fields of its superclasses come first. Superclasses
+    /// outside the program don't contribute fields.
+    pub fn instance_fields(&self, class: usize) -> Vec<(&str, &str)> {
+        self.instance_field_indexes(class)
+            .into_iter()
+            .map(|field| {
+                let field = &self.fields[field];
+                (field.name.as_str(), field.descriptor.as_str())
+            })
+            .collect()
+    }
+
+    /// Returns the field indexes of the instance fields of the class at
+    /// `class` in the order of `instance_fields`.
+    fn instance_field_indexes(&self, class: usize) -> Vec<usize> {
+        self.superclasses(class)
+            .iter()
+            .rev()
+            .flat_map(|class| {
+                self.fields.iter().enumerate().filter(move |(_, field)| {
+                    field.class == *class
+                        && field.access_flags & ACC_STATIC == 0
+                })
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Returns the index of the class declaring the field at `field`.
+    pub fn field_class(&self, field: usize) -> Option<usize> {
+        self.fields.get(field).map(|field| field.class)
+    }
+
+    /// Returns the initial value of the static field at `field`, the
+    /// constant of its `ConstantValue` attribute.
+    pub fn constant_value(&self, field: usize) -> Option<RuntimeConstant> {
+        let field = self.fields.get(field)?;
+        let index = usize::from(field.constant?);
+        self.classes[field.class].constants.get(index).copied()
+    }
+
+    /// Returns the linked interfaces the classes at `classes` implement,
+    /// directly or through other interfaces, in breadth first order.
+    fn superinterfaces(&self, classes: &[usize]) -> Vec<usize> {
+        let mut interfaces: Vec<usize> = vec![];
+        let mut pending: Vec<usize> = classes.to_vec();
+        while !pending.is_empty() {
+            let mut next = vec![];
+            for class in pending {
+                for name in &self.classes[class].interfaces {
+                    match self.class_index(name) {
+                        Some(interface) if !interfaces.contains(&interface) => {
+                            interfaces.push(interface);
+                            next.push(interface);
                         }
+                        _ => {}
                     }
                 }
-                None => panic!("method \"main\" was not found"),
             }
+            pending = next;
+        }
+        interfaces
+    }
+
+    /// Returns the index of the linked class called `name`.
+    pub fn class_index(&self, name: &str) -> Option<usize> {
+        self.class_indexes.get(name).copied()
+    }
+
+    /// Returns the method a virtual call through the method symbol `symbol`
+    /// runs on instances of the class at `class`, see `resolve_method`. The
+    /// result is memoized per class so later calls skip the lookup.
+    pub fn dispatch(
+        &mut self,
+        class: usize,
+        symbol: usize,
+    ) -> Option<MethodId> {
+        if let Some(method) = self.classes.get(class)?.dispatch.get(&symbol) {
+            return *method;
+        }
+        let Symbol {
+            kind: SymbolKind::Method,
+            name,
+            descriptor,
+            ..
+        } = self.symbols.get(symbol)?
+        else {
+            return None;
+        };
+        let method =
+            self.resolve_method(class, name, descriptor).ok().flatten();
+        self.classes[class].dispatch.insert(symbol, method);
+        method
+    }
+
+    /// Returns the slot the field the field symbol `symbol` resolves to has
+    /// in instances of the class at `class`, its position in
+    /// `instance_fields`. The result is memoized per class so later
+    /// accesses skip the lookup.
+    pub fn field_slot(&mut self, class: usize, symbol: usize) -> Option<usize> {
+        if let Some(slot) = self.classes.get(class)?.field_slots.get(&symbol) {
+            return *slot;
+        }
+        let Symbol {
+            kind: SymbolKind::Field,
+            target,
+            ..
+        } = self.symbols.get(symbol)?
+        else {
+            return None;
+        };
+        let slot = target.and_then(|field| {
+            self.instance_field_indexes(class)
+                .iter()
+                .position(|index| *index == field)
+        });
+        self.classes[class].field_slots.insert(symbol, slot);
+        slot
+    }
+
+    /// Check the descriptor and the code of every method, see `validate`.
+    /// Returns the problems found, an empty list if the program is valid.
+    #[must_use]
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        validate::validate(self)
+    }
+
+    /// Returns the method index of the static initializer of the class at
+    /// `class`.
+    pub fn clinit(&self, class: usize) -> Option<MethodId> {
+        self.classes.get(class)?.clinit
+    }
+
+    /// Returns the initialization state of the class at `class`.
+    pub fn class_state(&self, class: usize) -> Option<ClassState> {
+        self.classes.get(class).map(|class| class.state)
+    }
+
+    /// Records the initialization state of the class at `class`.
+    pub fn set_class_state(&mut self, class: usize, state: ClassState) {
+        if let Some(class) = self.classes.get_mut(class) {
+            class.state = state;
+        }
+    }
+
+    /// Returns the indexes of the classes whose static initializer hasn't
+    /// run, in link order.
+    pub fn uninitialized(&self) -> impl Iterator<Item = usize> + '_ {
+        self.classes
+            .iter()
+            .enumerate()
+            .filter(|(_, class)| class.state == ClassState::Uninitialized)
+            .map(|(index, _)| index)
+    }
+
+    /// Returns the symbol with id `symbol`.
+    pub fn symbol(&self, symbol: usize) -> Option<&Symbol> {
+        self.symbols.get(symbol)
+    }
+
+    /// Returns the id of the symbol of kind `kind` for the member
+    /// `class.name:descriptor`, `None` if no linked class references it.
+    pub fn find_symbol(
+        &self,
+        kind: SymbolKind,
+        class: &str,
+        name: &str,
+        descriptor: &str,
+    ) -> Option<usize> {
+        self.symbols.iter().position(|symbol| {
+            symbol.kind == kind
+                && symbol.class == class
+                && symbol.name == name
+                && symbol.descriptor == descriptor
+        })
+    }
+
+    /// Returns the id of the symbol referenced by the constant pool entry at
+    /// `index` of the class declaring the method at `method_index`.
+    pub fn symbol_id(
+        &self,
+        method_index: usize,
+        index: usize,
+    ) -> Option<usize> {
+        let class = self.methods.get(method_index)?.class;
+        self.classes[class].symbols.get(&index).copied()
+    }
+
+    /// Returns the symbols whose class isn't part of the program, they are
+    /// left to the library methods and natives of the runtime.
+    pub fn unresolved(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter().filter(|symbol| symbol.target.is_none())
+    }
+
+    /// Resolve the `ConstantMethodRef` or `ConstantInterfaceMethodRef` at
+    /// `method_ref` in the constant pool of the first class.
+    pub fn method_ref(&self, method_ref: usize) -> Option<MethodRef<'_>> {
+        let id = self.classes.first()?.symbols.get(&method_ref)?;
+        let symbol = &self.symbols[*id];
+        (symbol.kind == SymbolKind::Method).then(|| symbol.method_ref())
+    }
+
+    /// Returns the index of the method referenced by the `ConstantMethodRef`
+    /// at `method_ref` in the constant pool of the first class.
+    pub fn find_method(&self, method_ref: usize) -> Option<usize> {
+        let id = self.classes.first()?.symbols.get(&method_ref)?;
+        let symbol = &self.symbols[*id];
+        (symbol.kind == SymbolKind::Method)
+            .then_some(symbol.target)
+            .flatten()
+    }
+
+    /// Returns the index of the method of the first class called `name` with
+    /// `descriptor`.
+    pub fn method(&self, name: &str, descriptor: &str) -> Option<usize> {
+        self.class_method(self.class_name(), name, descriptor)
+    }
+
+    /// Returns the index of the method of `class` called `name` with
+    /// `descriptor`.
+    pub fn class_method(
+        &self,
+        class: &str,
+        name: &str,
+        descriptor: &str,
+    ) -> Option<usize> {
+        let class = self.class_index(class)?;
+        self.members
+            .get(&(
+                SymbolKind::Method,
+                class,
+                name.to_string(),
+                descriptor.to_string(),
+            ))
+            .copied()
+    }
+
+    /// Start the program from the static method of the first class called
+    /// `name` with `descriptor` instead of `main`.
+    pub fn set_entry_point(&mut self, name: &str, descriptor: &str) {
+        self.entry = Some((name.to_string(), descriptor.to_string()));
+    }
+
+    /// Returns the program entry point, `public static void main(String[])`
+    /// of the first class or the method set with `set_entry_point`. Test
+    /// programs return their result from `main` so any return type is
+    /// accepted, `void` is preferred.
+    /// # Errors
+    /// Returns an error if the first class doesn't declare the entry point
+    /// or if it isn't static, `main` must be public as well.
+    pub fn entry_point(&self) -> Result<MethodId, LinkError> {
+        let (name, descriptor, flags) = match &self.entry {
+            Some((name, descriptor)) => {
+                (name.as_str(), descriptor.as_str(), ACC_STATIC)
+            }
+            None => (MAIN.0, MAIN.1, ACC_PUBLIC | ACC_STATIC),
+        };
+        let error = |kind| LinkError {
+            class: self.class_name().to_string(),
+            method: format!("{name}{descriptor}"),
+            kind,
+        };
+        let method_index = self
+            .method(name, descriptor)
+            .or_else(|| {
+                let args = MAIN.1.trim_end_matches('V');
+                self.entry.is_none().then_some(())?;
+                self.methods.iter().position(|method| {
+                    method.class == 0
+                        && method.name == MAIN.0
+                        && method.descriptor.starts_with(args)
+                })
+            })
+            .ok_or_else(|| error(LinkErrorKind::UnknownMethod))?;
+        if self.methods[method_index].access_flags & flags != flags {
+            return Err(error(LinkErrorKind::InvalidEntryPoint));
         }
-        // This might cause some issues but since the input to our runtime
-        // is a class file that already passed the Java compiler we should
-        // assume a main function already exists.
-        0
+        Ok(method_index)
+    }
+
+    /// Returns the binary name of the first class, empty for programs built
+    /// from raw code.
+    pub fn class_name(&self) -> &str {
+        self.classes.first().map_or("", |class| class.name.as_str())
+    }
+
+    /// Returns the binary name of the superclass of the class at `class`,
+    /// `None` for `java/lang/Object`.
+    pub fn superclass(&self, class: usize) -> Option<&str> {
+        self.classes.get(class)?.superclass.as_deref()
+    }
+
+    /// Returns the names of the linked classes.
+    pub fn class_names(&self) -> impl Iterator<Item = &str> {
+        self.classes.iter().map(|class| class.name.as_str())
+    }
+
+    /// Returns the constant pool of the first class.
+    pub fn constant_pool(&self) -> &[CPInfo] {
+        self.classes
+            .first()
+            .map_or(&[], |class| class.constant_pool.as_slice())
+    }
+
+    /// Returns the runtime constant at `index` of the constant pool of the
+    /// class declaring the method at `method_index`.
+    pub fn constant(
+        &self,
+        method_index: usize,
+        index: usize,
+    ) -> Option<RuntimeConstant> {
+        let method = self.methods.get(method_index)?;
+        self.classes[method.class].constants.get(index).copied()
+    }
+
+    /// Returns the interned string with id `string`.
+    pub fn string(&self, string: usize) -> Option<&str> {
+        self.strings.get(string).map(String::as_str)
+    }
+
+    /// Returns the name of the class declaring the method at
+    /// `method_index`.
+    pub fn method_class(&self, method_index: usize) -> Option<&str> {
+        let method = self.methods.get(method_index)?;
+        Some(&self.classes[method.class].name)
+    }
+
+    /// Returns the source file of the class declaring the method at
+    /// `method_index`, if the class was compiled with one.
+    pub fn source_file(&self, method_index: usize) -> Option<&str> {
+        let method = self.methods.get(method_index)?;
+        self.classes[method.class].source_file.as_deref()
+    }
+
+    /// Returns the descriptor of the method at `method_index`.
+    pub fn descriptor(&self, method_index: usize) -> Option<&str> {
+        self.methods
+            .get(method_index)
+            .map(|method| method.descriptor.as_str())
+    }
+
+    /// Returns the name of the method at `method_index`.
+    pub fn method_name(&self, method_index: usize) -> Option<&str> {
+        self.methods
+            .get(method_index)
+            .map(|method| method.name.as_str())
+    }
+
+    /// Returns the method at `method_index` as a reference to itself, used
+    /// to name it in reports.
+    pub fn declared_method(
+        &self,
+        method_index: usize,
+    ) -> Option<MethodRef<'_>> {
+        let method = self.methods.get(method_index)?;
+        Some(MethodRef {
+            class: &self.classes[method.class].name,
+            name: &method.name,
+            descriptor: &method.descriptor,
+        })
+    }
+
+    /// Returns the index of the first method called `name` that has code,
+    /// use `method` to pick an overload.
+    pub fn method_index(&self, name: &str) -> Option<usize> {
+        self.methods
+            .iter()
+            .position(|method| !method.code.is_empty() && method.name == name)
     }
 
     // Returns a slice containing code of method pointed at by `method_index`.
@@ -203,18 +1432,65 @@ impl Program {
         &self.methods[method_index].code
     }
 
+    /// Returns the decoded instructions of a method.
+    pub fn instructions(&self, method_index: usize) -> &InstructionStream {
+        &self.methods[method_index].instructions
+    }
+
+    /// Replace the code of a method and decode it again.
+    pub fn set_code(&mut self, method_index: usize, code: Vec<u8>) {
+        self.methods[method_index].code = code;
+        self.methods[method_index].instructions = self.decode(method_index);
+    }
+
     // Return the declared max locals for a method.
     pub fn max_locals(&self, method_index: usize) -> u16 {
         self.methods[method_index].max_locals
     }
 
+    // Return the declared max stack for a method, in words.
+    pub fn max_stack(&self, method_index: usize) -> u16 {
+        self.methods[method_index].max_stack
+    }
+
+    /// Returns the exception handlers of the method at `method_index` in
+    /// declaration order.
+    pub fn exception_table(&self, method_index: usize) -> &[ExceptionEntry] {
+        self.methods
+            .get(method_index)
+            .map_or(&[], |method| method.exception_table.as_slice())
+    }
+
+    /// Returns the stack map frames of the method at `method_index`.
+    pub fn stack_map_table(&self, method_index: usize) -> &[StackMapFrame] {
+        self.methods
+            .get(method_index)
+            .map_or(&[], |method| method.stack_map_table.as_slice())
+    }
+
+    /// Returns the source line of the instruction at `offset` in the method
+    /// at `method_index`, if the class was compiled with line numbers.
+    pub fn line_number(
+        &self,
+        method_index: usize,
+        offset: usize,
+    ) -> Option<u16> {
+        self.methods
+            .get(method_index)?
+            .line_numbers
+            .iter()
+            .filter(|line| usize::from(line.start_pc()) <= offset)
+            .max_by_key(|line| line.start_pc())
+            .map(LineNumber::line_number)
+    }
+
     // Parse constant method types, returns a tuple of argument types and
     // return types.
     fn parse_method_types(bytes: &str) -> (Vec<Type>, Type) {
         let re = Regex::new(r"\(([^\)]*)\)([^$]+)").unwrap();
         let caps = re.captures(bytes).unwrap();
-        let arg_string = caps.get(1).map_or("", |m| return m.as_str());
-        let return_type_string = caps.get(2).map_or("", |m| return m.as_str());
+        let arg_string = caps.get(1).map_or("", |m| m.as_str());
+        let return_type_string = caps.get(2).map_or("", |m| m.as_str());
         let mut types: Vec<Type> = Vec::new();
         let ret_type = Self::decode_type(return_type_string);
 
@@ -238,18 +1514,22 @@ impl Program {
     /// type.
     #[must_use]
     pub fn decode_type_string_length(t: &Type) -> usize {
-        match t.t {
-            BaseTypeKind::String => 18,
+        match &t.t {
+            // `L` and `;` surround the class name.
+            BaseTypeKind::String => "java/lang/String".len() + 2,
+            BaseTypeKind::Object(name) => name.len() + 2,
             BaseTypeKind::List => {
-                return 1 + Self::decode_type_string_length(
-                    t.sub_t.as_ref().unwrap(),
-                )
+                1 + Self::decode_type_string_length(t.sub_t.as_ref().unwrap())
             }
             _ => 1,
         }
     }
 
-    /// Returns the Java equivalent type from a type's string representation.
+    /// Returns the Java equivalent type from a type's string representation,
+    /// only the first type of `type_str` is decoded.
+    /// # Panics
+    /// Function panics if `type_str` doesn't start with a valid field
+    /// descriptor.
     #[must_use]
     pub fn decode_type(type_str: &str) -> Type {
         match &type_str[0..1] {
@@ -269,30 +1549,100 @@ impl Program {
                 t: BaseTypeKind::Double,
                 sub_t: None,
             },
+            "B" => Type {
+                t: BaseTypeKind::Byte,
+                sub_t: None,
+            },
+            "C" => Type {
+                t: BaseTypeKind::Char,
+                sub_t: None,
+            },
+            "S" => Type {
+                t: BaseTypeKind::Short,
+                sub_t: None,
+            },
+            "Z" => Type {
+                t: BaseTypeKind::Boolean,
+                sub_t: None,
+            },
             "V" => Type {
                 t: BaseTypeKind::Void,
                 sub_t: None,
             },
-            "[" => {
-                let st = Self::decode_type(&type_str[1..(type_str.len() - 1)]);
-                let subtype = Type {
-                    t: st.t,
-                    sub_t: st.sub_t,
+            "[" => Type {
+                t: BaseTypeKind::List,
+                sub_t: Some(Box::new(Self::decode_type(&type_str[1..]))),
+            },
+            "L" => {
+                let end = type_str.find(';').unwrap_or_else(|| {
+                    panic!("unterminated object type {type_str}")
+                });
+                let t = match &type_str[1..end] {
+                    "java/lang/String" => BaseTypeKind::String,
+                    name => BaseTypeKind::Object(name.to_string()),
                 };
-                Type {
-                    t: BaseTypeKind::List,
-                    sub_t: Some(Box::new(subtype)),
-                }
+                Type { t, sub_t: None }
             }
-            // We can support byte, char... later
-            _ => Type {
-                t: BaseTypeKind::String,
-                sub_t: None,
-            },
+            _ => panic!("invalid type descriptor {type_str}"),
         }
     }
 }
 
+/// Returns the name of the `ConstantClass` at `index`.
+fn class_name_at(constant_pool: &[CPInfo], index: usize) -> Option<&str> {
+    match constant_pool.get(index) {
+        Some(CPInfo::ConstantClass { name_index }) => {
+            utf8_at(constant_pool, *name_index as usize)
+        }
+        _ => None,
+    }
+}
+
+/// Returns the string of the `ConstantUtf8` at `index`.
+fn utf8_at(constant_pool: &[CPInfo], index: usize) -> Option<&str> {
+    match constant_pool.get(index) {
+        Some(CPInfo::ConstantUtf8 { bytes }) => Some(bytes),
+        _ => None,
+    }
+}
+
+/// Returns the kind, class, name and descriptor of the method or field
+/// reference at `index`.
+fn member_ref(
+    constant_pool: &[CPInfo],
+    index: usize,
+) -> Option<(SymbolKind, &str, &str, &str)> {
+    let (kind, class_index, name_and_type_index) =
+        match constant_pool.get(index)? {
+            CPInfo::ConstantMethodRef {
+                class_index,
+                name_and_type_index,
+            }
+            | CPInfo::ConstantInterfaceMethodRef {
+                class_index,
+                name_and_type_index,
+            } => (SymbolKind::Method, class_index, name_and_type_index),
+            CPInfo::ConstantFieldRef {
+                class_index,
+                name_and_type_index,
+            } => (SymbolKind::Field, class_index, name_and_type_index),
+            _ => return None,
+        };
+    let CPInfo::ConstantNameAndType {
+        name_index,
+        descriptor_index,
+    } = constant_pool.get(*name_and_type_index as usize)?
+    else {
+        return None;
+    };
+    Some((
+        kind,
+        class_name_at(constant_pool, *class_index as usize)?,
+        utf8_at(constant_pool, *name_index as usize)?,
+        utf8_at(constant_pool, *descriptor_index as usize)?,
+    ))
+}
+
 fn substr(s: &str, start: usize, length: usize) -> &str {
     let end = start + length;
     &s[start..end]
@@ -302,7 +1652,7 @@ fn substr(s: &str, start: usize, length: usize) -> &str {
 mod tests {
     use super::*;
 
-    use crate::jvm::{read_class_file, JVMParser};
+    use crate::jvm::{read_class_file, JVMParser, REF_INVOKE_STATIC};
     use std::env;
     use std::path::Path;
 
@@ -320,7 +1670,9 @@ mod tests {
 
         let methods = vec![
             Method {
-                _name_index: 27,
+                class: 0,
+                name: "main".to_string(),
+                access_flags: 9,
                 _return_type: Type {
                     t: BaseTypeKind::Void,
                     sub_t: None,
@@ -332,29 +1684,41 @@ mod tests {
                         sub_t: None,
                     })),
                 }],
-                _max_stack: 2,
+                descriptor: "([Ljava/lang/String;)V".to_string(),
+                max_stack: 2,
                 max_locals: 2,
                 code: vec![
                     16, 12, 184, 0, 7, 60, 178, 0, 13, 27, 182, 0, 19, 177,
                 ],
+                instructions: InstructionStream::default(),
                 _constant: None,
-                _stack_map_table: None,
+                exception_table: vec![],
+                line_numbers: vec![],
+                stack_map_table: vec![],
             },
             Method {
-                _name_index: 5,
+                class: 0,
+                name: "<init>".to_string(),
+                access_flags: 1,
                 _return_type: Type {
                     t: BaseTypeKind::Void,
                     sub_t: None,
                 },
                 arg_types: vec![],
-                _max_stack: 1,
+                descriptor: "()V".to_string(),
+                max_stack: 1,
                 max_locals: 1,
                 code: vec![42, 183, 0, 1, 177],
+                instructions: InstructionStream::default(),
                 _constant: None,
-                _stack_map_table: None,
+                exception_table: vec![],
+                line_numbers: vec![],
+                stack_map_table: vec![],
             },
             Method {
-                _name_index: 11,
+                class: 0,
+                name: "factorial".to_string(),
+                access_flags: 9,
                 _return_type: Type {
                     t: BaseTypeKind::Int,
                     sub_t: None,
@@ -363,22 +1727,377 @@ mod tests {
                     t: BaseTypeKind::Int,
                     sub_t: None,
                 }],
-                _max_stack: 2,
+                descriptor: "(I)I".to_string(),
+                max_stack: 2,
                 max_locals: 3,
                 code: vec![
                     4, 60, 5, 61, 28, 26, 163, 0, 13, 27, 28, 104, 60, 132, 2,
                     1, 167, 255, 244, 27, 172,
                 ],
+                instructions: InstructionStream::default(),
                 _constant: None,
-                _stack_map_table: None,
+                exception_table: vec![],
+                line_numbers: vec![],
+                stack_map_table: vec![],
             },
         ];
 
         for method in methods {
-            let name_index = method._name_index;
-            let program_method = &program.methods[name_index as usize];
+            let index = program.method(&method.name, &method.descriptor);
+            let program_method = &program.methods[index.unwrap()];
             assert_eq!(method.code, program_method.code);
         }
-        assert_eq!(program.entry_point(), 27);
+        // Methods are indexed in declaration order.
+        assert_eq!(program.entry_point(), Ok(1));
+        assert_eq!(program.method_name(2), Some("factorial"));
+        // main calls factorial through the method reference at #7.
+        assert_eq!(
+            program.method_ref(7),
+            Some(MethodRef {
+                class: "Factorial",
+                name: "factorial",
+                descriptor: "(I)I",
+            })
+        );
+        assert_eq!(program.find_method(7), Some(2));
+    }
+
+    fn parse_class(path: &str) -> JVMClassFile {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join(path);
+        JVMParser::parse(&read_class_file(&path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn can_link_classes() {
+        let main = parse_class("support/linking/Main.class");
+        let ops = parse_class("support/linking/Ops.class");
+        let program = Program::link(&[main.clone(), ops.clone()]).unwrap();
+        assert_eq!(program.class_name(), "Main");
+        let square = program.class_method("Ops", "square", "(I)I").unwrap();
+        assert_eq!(program.method_class(square), Some("Ops"));
+        assert_eq!(
+            program.method_class(program.entry_point().unwrap()),
+            Some("Main")
+        );
+
+        // Only references to the library are left unresolved.
+        let mut unresolved: Vec<String> =
+            program.unresolved().map(ToString::to_string).collect();
+        unresolved.sort();
+        assert_eq!(
+            unresolved,
+            vec![
+                "java/io/PrintStream.println(I)V",
+                "java/lang/Object.<init>()V",
+                "java/lang/System.out:Ljava/io/PrintStream;",
+            ]
+        );
+
+        let err = Program::link(&[main.clone(), ops.clone(), ops]).unwrap_err();
+        assert_eq!(err.kind, LinkErrorKind::DuplicateClass);
+
+        // Methods a linked class doesn't declare are left to their calls.
+        let stale = parse_class("support/linking/stale/Ops.class");
+        let program = Program::link(&[main, stale]).unwrap();
+        assert!(program
+            .unresolved()
+            .any(|symbol| symbol.to_string() == "Ops.add(II)I"));
+    }
+
+    #[test]
+    fn resolves_default_methods() {
+        let link = |names: &[&str]| {
+            let classes: Vec<JVMClassFile> = names
+                .iter()
+                .map(|name| {
+                    parse_class(&format!("support/defaults/{name}.class"))
+                })
+                .collect();
+            Program::link(&classes)
+        };
+        let mut program =
+            link(&["Main", "Greeter", "Polite", "Impl", "Custom"]).unwrap();
+        let greet = |class: &str| {
+            let class = program.class_index(class).unwrap();
+            let method = program.resolve_method(class, "greet", "()I");
+            program.method_class(method.unwrap().unwrap())
+        };
+        assert_eq!(greet("Greeter"), Some("Greeter"));
+        // Polite extends Greeter, its default wins.
+        assert_eq!(greet("Impl"), Some("Polite"));
+        assert_eq!(greet("Custom"), Some("Custom"));
+        // Main.polite calls Impl.greet through the method reference at #7.
+        let polite = program.class_method("Main", "polite", "(LImpl;)I");
+        let symbol = program.symbol_id(polite.unwrap(), 7).unwrap();
+        assert_eq!(
+            program.symbol(symbol).unwrap().target,
+            program.class_method("Polite", "greet", "()I")
+        );
+        assert_eq!(program.symbol(symbol).unwrap().arguments, 0);
+        // Calls through the reference on a Custom run its override.
+        let custom = program.class_index("Custom").unwrap();
+        let greet = program.class_method("Custom", "greet", "()I");
+        assert_eq!(program.dispatch(custom, symbol), greet);
+        assert_eq!(program.classes[custom].dispatch.get(&symbol), Some(&greet));
+        assert_eq!(program.dispatch(custom, symbol), greet);
+
+        // Without Loud, Clash only inherits Greeter.greet.
+        let program = link(&["Main", "Greeter", "Impl", "Custom", "Clash"]);
+        assert!(program.is_ok());
+        // With it, the conflict is left for the calls to Clash.greet.
+        let program =
+            link(&["Main", "Greeter", "Impl", "Custom", "Clash", "Loud"])
+                .unwrap();
+        let clash = program.class_index("Clash").unwrap();
+        assert_eq!(
+            program.resolve_method(clash, "greet", "()I"),
+            Err(LinkErrorKind::IncompatibleClassChange)
+        );
+        assert!(program
+            .unresolved()
+            .any(|symbol| symbol.to_string() == "Clash.greet()I"));
+    }
+
+    #[test]
+    fn lays_out_instance_fields() {
+        let point = parse_class("support/objects/Point.class");
+        let point3d = parse_class("support/objects/Point3D.class");
+        let mut program = Program::link(&[point3d, point]).unwrap();
+        let class = program.class_index("Point3D").unwrap();
+        assert_eq!(
+            program.instance_fields(class),
+            [("x", "I"), ("y", "I"), ("z", "J"), ("next", "LPoint3D;")]
+        );
+        // Fields accessed through a subclass resolve to the superclass.
+        let x = program.resolve_field(class, "x", "I").unwrap();
+        assert_eq!(
+            program.resolve_field(
+                program.class_index("Point").unwrap(),
+                "x",
+                "I"
+            ),
+            Some(x)
+        );
+        assert_eq!(program.resolve_field(class, "x", "J"), None);
+        // Accesses through a symbol find the field's slot in instances.
+        let field = |name: &str, descriptor: &str| {
+            program
+                .find_symbol(SymbolKind::Field, "Point3D", name, descriptor)
+                .unwrap()
+        };
+        let (y, z) = (field("y", "I"), field("z", "J"));
+        let out = program
+            .find_symbol(
+                SymbolKind::Field,
+                "java/lang/System",
+                "out",
+                "Ljava/io/PrintStream;",
+            )
+            .unwrap();
+        assert_eq!(program.field_slot(class, y), Some(1));
+        assert_eq!(program.field_slot(class, z), Some(2));
+        assert_eq!(program.classes[class].field_slots.get(&z), Some(&Some(2)));
+        assert_eq!(program.field_slot(class, out), None);
+        // Points don't have the fields Point3D declares.
+        let point = program.class_index("Point").unwrap();
+        assert_eq!(program.field_slot(point, y), Some(1));
+        assert_eq!(program.field_slot(point, z), None);
+    }
+
+    #[test]
+    fn finds_entry_point() {
+        let mut program =
+            Program::new(&parse_class("support/linking/Ops.class"));
+        let err = program.entry_point().unwrap_err();
+        assert_eq!(err.kind, LinkErrorKind::UnknownMethod);
+        assert_eq!(err.method, "main([Ljava/lang/String;)V");
+
+        program.set_entry_point("square", "(I)I");
+        assert_eq!(program.entry_point(), Ok(1));
+        // Constructors aren't static.
+        program.set_entry_point("<init>", "()V");
+        let err = program.entry_point().unwrap_err();
+        assert_eq!(err.kind, LinkErrorKind::InvalidEntryPoint);
+    }
+
+    #[test]
+    fn keeps_code_attributes() {
+        let program =
+            Program::new(&parse_class("support/exceptions/TryCatch.class"));
+        let main = program.entry_point().unwrap();
+        let handlers = program.exception_table(main);
+        assert_eq!(handlers.len(), 1);
+        assert_eq!(
+            (
+                handlers[0].start_pc(),
+                handlers[0].end_pc(),
+                handlers[0].handler_pc()
+            ),
+            (3, 10, 13)
+        );
+        assert!(matches!(
+            program
+                .constant_pool()
+                .get(usize::from(handlers[0].catch_type())),
+            Some(CPInfo::ConstantClass { .. })
+        ));
+        assert_eq!(program.line_number(main, 0), Some(3));
+        assert_eq!(program.line_number(main, 8), Some(5));
+        assert_eq!(program.line_number(main, 13), Some(6));
+        // The handler and the join point are branch targets.
+        assert_eq!(program.stack_map_table(main).len(), 2);
+        assert!(program.exception_table(99).is_empty());
+    }
+
+    #[test]
+    fn decodes_runtime_constants() {
+        let program =
+            Program::new(&parse_class("support/integration/MEDouble.class"));
+        let main = program.entry_point().unwrap();
+        assert_eq!(
+            program.constant(main, 7),
+            Some(RuntimeConstant::Double(1.3))
+        );
+        // Doubles take two slots.
+        assert_eq!(program.constant(main, 8), Some(RuntimeConstant::Unusable));
+        assert_eq!(
+            program.constant(main, 13),
+            Some(RuntimeConstant::Double(2.5))
+        );
+
+        let main = parse_class("support/linking/Main.class");
+        let ops = parse_class("support/linking/Ops.class");
+        let program = Program::link(&[main, ops]).unwrap();
+        let main = program.entry_point().unwrap();
+        let Some(RuntimeConstant::Class { name, index }) =
+            program.constant(main, 8)
+        else {
+            panic!("expected a class constant");
+        };
+        assert_eq!(program.string(name), Some("Ops"));
+        assert_eq!(index, Some(1));
+        let Some(RuntimeConstant::Class { index, .. }) =
+            program.constant(main, 14)
+        else {
+            panic!("expected a class constant");
+        };
+        assert_eq!(index, None);
+        let Some(RuntimeConstant::Method(id)) = program.constant(main, 7)
+        else {
+            panic!("expected a method constant");
+        };
+        assert_eq!(program.symbol(id).unwrap().name, "square");
+        assert!(matches!(
+            program.constant(main, 13),
+            Some(RuntimeConstant::Field(_))
+        ));
+
+        // Bootstrap arguments of lambdas.
+        let program =
+            Program::new(&parse_class("support/handles/Lambdas.class"));
+        let main = program.entry_point().unwrap();
+        let Some(RuntimeConstant::MethodType(descriptor)) =
+            program.constant(main, 45)
+        else {
+            panic!("expected a method type constant");
+        };
+        assert_eq!(program.string(descriptor), Some("(II)I"));
+        let Some(RuntimeConstant::MethodHandle { kind, symbol }) =
+            program.constant(main, 46)
+        else {
+            panic!("expected a method handle constant");
+        };
+        assert_eq!(kind, REF_INVOKE_STATIC);
+        assert_eq!(program.symbol(symbol).unwrap().name, "add");
+        assert_eq!(program.constant(main, 10), Some(RuntimeConstant::Unusable));
+        assert_eq!(program.constant(main, 99), None);
+    }
+
+    #[test]
+    fn decodes_instruction_streams() {
+        // The body of `Factorial.factorial`.
+        let mut program = Program::from_code(
+            vec![
+                4, 60, 5, 61, 28, 26, 163, 0, 13, 27, 28, 104, 60, 132, 2, 1,
+                167, 255, 244, 27, 172,
+            ],
+            3,
+        );
+        let main = program.entry_point().unwrap();
+        let stream = program.instructions(main);
+        assert_eq!(stream.len(), 15);
+        let (inst, next) = stream.get(6).unwrap();
+        assert_eq!(inst.get_mnemonic(), OPCode::IfICmpGt);
+        assert_eq!(inst.nth(0), Some(Value::Int(13)));
+        assert_eq!(next, 9);
+        assert_eq!(stream.index(6), Some(6));
+        assert_eq!(stream.offset(6), Some(6));
+        // Offsets in the middle of an instruction don't decode.
+        assert!(stream.get(7).is_none());
+        let (inst, _) = stream.get(13).unwrap();
+        assert_eq!(inst.nth(0), Some(Value::Int(2)));
+        assert_eq!(inst.nth(1), Some(Value::Int(1)));
+
+        // Truncated code decodes up to the last whole instruction.
+        program.set_code(main, vec![4, 60, 16]);
+        assert_eq!(program.instructions(main).len(), 2);
+        assert!(program.instructions(main).get(2).is_none());
+    }
+
+    #[test]
+    fn finds_static_initializers() {
+        let mut program =
+            Program::new(&parse_class("support/clinit/Config.class"));
+        let clinit = program.clinit(0).unwrap();
+        assert_eq!(program.method_name(clinit), Some("<clinit>"));
+        assert_eq!(program.class_state(0), Some(ClassState::Uninitialized));
+        assert_eq!(program.uninitialized().collect::<Vec<_>>(), vec![0]);
+        program.set_class_state(0, ClassState::Initialized);
+        assert_eq!(program.uninitialized().count(), 0);
+
+        // Classes without a static initializer start initialized.
+        let main = parse_class("support/linking/Main.class");
+        let ops = parse_class("support/linking/Ops.class");
+        let program = Program::link(&[main, ops]).unwrap();
+        let ops = program.class_index("Ops").unwrap();
+        assert_eq!(program.clinit(ops), None);
+        assert_eq!(program.class_state(ops), Some(ClassState::Initialized));
+        assert_eq!(program.class_state(2), None);
+
+        // Unless one of their superclasses has one.
+        let program = Program::link(&[
+            parse_class("support/clinit/Middle.class"),
+            parse_class("support/clinit/Base.class"),
+        ])
+        .unwrap();
+        assert_eq!(program.clinit(0), None);
+        assert_eq!(program.class_state(0), Some(ClassState::Uninitialized));
+    }
+
+    #[test]
+    fn can_decode_types() {
+        let (args, ret) = Program::parse_method_types(
+            "(BCSZLcom/foo/Bar;[JI)[Ljava/lang/String;",
+        );
+        let kinds: Vec<BaseTypeKind> =
+            args.iter().map(|arg| arg.t.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                BaseTypeKind::Byte,
+                BaseTypeKind::Char,
+                BaseTypeKind::Short,
+                BaseTypeKind::Boolean,
+                BaseTypeKind::Object("com/foo/Bar".to_string()),
+                BaseTypeKind::List,
+                BaseTypeKind::Int,
+            ]
+        );
+        assert_eq!(args[5].sub_t.as_ref().unwrap().t, BaseTypeKind::Long);
+        assert_eq!(Program::decode_type_string_length(&args[4]), 13);
+        assert_eq!(ret.t, BaseTypeKind::List);
+        assert_eq!(ret.sub_t.unwrap().t, BaseTypeKind::String);
+        assert_eq!(args.iter().map(Type::size).sum::<usize>(), 7);
     }
 }
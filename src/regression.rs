@@ -0,0 +1,227 @@
+//! Output regression testing over a corpus of class files.
+//!
+//! Every class file of the corpus is run and its standard output and exit
+//! code are recorded in a baseline file, later runs are checked against the
+//! baseline so that CI fails as soon as a program's observable behaviour
+//! changes. Baselines are plain text so changes show up in review :
+//!
+//! ```text
+//! === support/tests/Factorial.class exit=0 bytes=10
+//! 479001600
+//! ```
+//!
+//! The header is followed by exactly `bytes` bytes of stdout and a newline,
+//! lines starting with `#` between records are comments.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+use crate::diff::{run_coldbrew, Divergence, Outcome};
+
+/// Exit code recorded for programs that panic the runtime.
+pub const PANIC_EXIT_CODE: i32 = 101;
+
+/// `BaselineError` reports a malformed baseline file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BaselineError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for BaselineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {} : {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for BaselineError {}
+
+/// `Baseline` holds the outcome of every program of a corpus keyed by the
+/// path it was run from.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Baseline {
+    pub outcomes: BTreeMap<String, Outcome>,
+}
+
+/// Difference between a baseline and a new run of the corpus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Regression {
+    /// The program is in the baseline but wasn't run.
+    Missing(String),
+    /// The program was run but isn't in the baseline.
+    Unrecorded(String),
+    /// The program's output or exit code changed.
+    Changed {
+        name: String,
+        expected: Outcome,
+        actual: Outcome,
+    },
+}
+
+impl fmt::Display for Regression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing(name) => writeln!(f, "[{name}] missing from run"),
+            Self::Unrecorded(name) => writeln!(f, "[{name}] not in baseline"),
+            Self::Changed {
+                name,
+                expected,
+                actual,
+            } => write!(f, "{}", Divergence::new(name, expected, actual)),
+        }
+    }
+}
+
+/// Run the class file at `path` under coldbrew, panics are reported with
+/// `PANIC_EXIT_CODE` so that one broken program doesn't abort the corpus.
+pub fn run_program(path: &Path, jit_mode: bool) -> Outcome {
+    panic::catch_unwind(AssertUnwindSafe(|| run_coldbrew(path, jit_mode)))
+        .unwrap_or_else(|_| Outcome {
+            stdout: String::new(),
+            exit_code: PANIC_EXIT_CODE,
+        })
+}
+
+impl Baseline {
+    /// Run every class file in `paths` and record their outcomes.
+    pub fn record<P: AsRef<Path>>(paths: &[P], jit_mode: bool) -> Self {
+        let outcomes = paths
+            .iter()
+            .map(|path| {
+                let path = path.as_ref();
+                (path.display().to_string(), run_program(path, jit_mode))
+            })
+            .collect();
+        Self { outcomes }
+    }
+
+    /// Parse a baseline previously written with `Display`.
+    /// # Errors
+    /// Returns an error if a record header is malformed or its output is
+    /// truncated.
+    pub fn parse(source: &str) -> Result<Self, BaselineError> {
+        let mut outcomes = BTreeMap::new();
+        let mut rest = source;
+        let mut line = 1;
+        while !rest.is_empty() {
+            let header_line = line;
+            let error = move |message: String| BaselineError {
+                line: header_line,
+                message,
+            };
+            let (header, tail) = rest.split_once('\n').unwrap_or((rest, ""));
+            rest = tail;
+            line += 1;
+            if header.trim().is_empty() || header.starts_with('#') {
+                continue;
+            }
+            let Some(header) = header.strip_prefix("=== ") else {
+                return Err(error(format!(
+                    "expected a record, got `{header}`"
+                )));
+            };
+            let (name, exit_code, bytes) = parse_header(header)
+                .ok_or_else(|| error(format!("malformed header `{header}`")))?;
+            let stdout = rest
+                .get(..bytes)
+                .filter(|_| rest[bytes..].starts_with('\n'))
+                .ok_or_else(|| error(format!("truncated output of {name}")))?;
+            line += stdout.matches('\n').count() + 1;
+            rest = &rest[bytes + 1..];
+            outcomes.insert(
+                name.to_string(),
+                Outcome {
+                    stdout: stdout.to_string(),
+                    exit_code,
+                },
+            );
+        }
+        Ok(Self { outcomes })
+    }
+
+    /// Compare a new run of the corpus against the baseline.
+    pub fn compare(&self, actual: &Baseline) -> Vec<Regression> {
+        let mut regressions = Vec::new();
+        for (name, expected) in &self.outcomes {
+            match actual.outcomes.get(name) {
+                None => regressions.push(Regression::Missing(name.clone())),
+                Some(outcome) if outcome != expected => {
+                    regressions.push(Regression::Changed {
+                        name: name.clone(),
+                        expected: expected.clone(),
+                        actual: outcome.clone(),
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+        for name in actual.outcomes.keys() {
+            if !self.outcomes.contains_key(name) {
+                regressions.push(Regression::Unrecorded(name.clone()));
+            }
+        }
+        regressions
+    }
+}
+
+/// Parse `<name> exit=<code> bytes=<length>`, names may contain spaces.
+fn parse_header(header: &str) -> Option<(&str, i32, usize)> {
+    let (rest, bytes) = header.rsplit_once(" bytes=")?;
+    let (name, exit_code) = rest.rsplit_once(" exit=")?;
+    Some((name, exit_code.parse().ok()?, bytes.parse().ok()?))
+}
+
+impl fmt::Display for Baseline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# coldbrew output baseline")?;
+        for (name, outcome) in &self.outcomes {
+            writeln!(
+                f,
+                "=== {name} exit={} bytes={}",
+                outcome.exit_code,
+                outcome.stdout.len()
+            )?;
+            writeln!(f, "{}", outcome.stdout)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn detects_changed_output() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let root = Path::new(&env_var).join("support/tests");
+        let paths =
+            [root.join("Factorial.class"), root.join("Fibonacci.class")];
+        let baseline = Baseline::record(&paths, false);
+        let factorial = paths[0].display().to_string();
+        assert_eq!(baseline.outcomes[&factorial].exit_code, 0);
+
+        let parsed = Baseline::parse(&baseline.to_string()).unwrap();
+        assert_eq!(parsed, baseline);
+        assert!(parsed.compare(&Baseline::record(&paths, false)).is_empty());
+
+        let mut changed = parsed.clone();
+        changed.outcomes.get_mut(&factorial).unwrap().stdout = "1\n".into();
+        changed.outcomes.insert(
+            "Gone.class".into(),
+            Outcome {
+                stdout: String::new(),
+                exit_code: 0,
+            },
+        );
+        let regressions = changed.compare(&baseline);
+        assert_eq!(regressions.len(), 2);
+        assert!(regressions[0].to_string().contains("stdout line 1"));
+        assert!(regressions[1]
+            .to_string()
+            .starts_with("[Gone.class] missing"));
+        assert!(Baseline::parse("=== Foo.class exit=0 bytes=8\n1\n").is_err());
+    }
+}
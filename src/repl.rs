@@ -0,0 +1,397 @@
+//! Interactive read-eval-print loop on top of the interpreter.
+//!
+//! Each line is either an instruction in the assembler syntax (see
+//! `assembler`) or a small integer expression such as `(1 + 2) * 3` which
+//! is compiled down to bytecode. The resulting code is appended to a single
+//! `main` method and interpreted against a frame that persists between
+//! lines, the operand stack is printed after every line.
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::assembler::{assemble, AssemblerError};
+use crate::bytecode::{OPCode, OperandKind};
+use crate::program::Program;
+use crate::runtime::{Runtime, RuntimeError, Value};
+
+const HELP: &str = "\
+Enter an instruction (e.g. `bipush 12`, `istore_1`) or an integer
+expression (e.g. `(1 + 2) * 3`), the operand stack is printed after
+each line.
+
+    :locals   print the local variables
+    :reset    discard the current frame
+    :help     print this message
+    :q        exit the repl";
+
+/// Number of local variable slots available in the repl frame.
+const MAX_LOCALS: u16 = 256;
+
+/// `ReplError` represents the possible failures when evaluating a line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplError {
+    Assembler(AssemblerError),
+    Runtime(RuntimeError),
+    // Malformed expression.
+    Syntax(String),
+    // Instructions that reference the constant pool or jump elsewhere have
+    // no meaning outside of a class file.
+    Unsupported(OPCode),
+    // The interpreter panicked, the frame was discarded.
+    Crashed(String),
+}
+
+impl fmt::Display for ReplError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Assembler(err) => write!(f, "{err}"),
+            Self::Runtime(err) => write!(f, "{err}"),
+            Self::Syntax(message) => write!(f, "syntax error : {message}"),
+            Self::Unsupported(opcode) => {
+                write!(f, "instruction {opcode} is not supported in the repl")
+            }
+            Self::Crashed(message) => {
+                write!(f, "interpreter crashed ({message}), frame was reset")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplError {}
+
+impl From<AssemblerError> for ReplError {
+    fn from(err: AssemblerError) -> Self {
+        Self::Assembler(err)
+    }
+}
+
+impl From<RuntimeError> for ReplError {
+    fn from(err: RuntimeError) -> Self {
+        Self::Runtime(err)
+    }
+}
+
+/// `Repl` owns the runtime and the frame that lines are evaluated against.
+pub struct Repl {
+    runtime: Runtime,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Self {
+            runtime: Runtime::new(Program::from_code(vec![], MAX_LOCALS)),
+        }
+    }
+
+    /// Discard the current frame and start from an empty one.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Returns the operand stack of the repl frame.
+    pub fn stack(&self) -> &[Value] {
+        self.runtime.frame().map_or(&[], |frame| frame.stack())
+    }
+
+    /// Returns the local variables of the repl frame sorted by index.
+    pub fn locals(&self) -> Vec<(usize, Value)> {
+        self.runtime
+            .frame()
+            .map(|frame| frame.locals.iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Evaluate a single line of input.
+    /// # Errors
+    /// Returns an error if the line can't be compiled or fails to run, the
+    /// instructions following a failing one are discarded.
+    pub fn eval_line(&mut self, line: &str) -> Result<(), ReplError> {
+        let mnemonic = line.split_whitespace().next().unwrap_or_default();
+        let source = if OPCode::from_mnemonic(mnemonic).is_some() {
+            line.to_string()
+        } else {
+            compile_expression(line)?
+        };
+        let code = assemble(&source)?;
+        check_supported(&code)?;
+
+        let Ok(main) = self.runtime.program_mut().entry_point() else {
+            unreachable!("repl programs are built with a main method");
+        };
+        let program = self.runtime.program_mut();
+        let mut method_code = program.code(main).to_vec();
+        method_code.extend(code);
+        let end = method_code.len();
+        program.set_code(main, method_code);
+
+        let runtime = &mut self.runtime;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            while runtime
+                .frame()
+                .is_some_and(|frame| frame.pc.get_instruction_index() < end)
+            {
+                runtime.step()?;
+            }
+            Ok(())
+        }));
+        match result {
+            Ok(Ok(())) if self.runtime.frame().is_none() => {
+                // The frame returned, start over with a fresh one.
+                self.reset();
+                Ok(())
+            }
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(err)) => {
+                // Drop what's left of the line so it doesn't run with the
+                // next one.
+                if let Some(frame) = self.runtime.frame() {
+                    let pc = frame.pc.get_instruction_index();
+                    let program = self.runtime.program_mut();
+                    let code = program.code(main)[..pc].to_vec();
+                    program.set_code(main, code);
+                }
+                Err(ReplError::Runtime(err))
+            }
+            Err(payload) => {
+                self.reset();
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(ToString::to_string)
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                Err(ReplError::Crashed(message))
+            }
+        }
+    }
+
+    /// Run the repl reading lines from `input` until end of input or `:q`.
+    /// # Errors
+    /// Returns an error if reading input or writing output fails.
+    pub fn run<R: BufRead, W: Write>(
+        &mut self,
+        input: R,
+        mut output: W,
+    ) -> io::Result<()> {
+        write!(output, "> ")?;
+        output.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            match line.trim() {
+                "" => {}
+                ":q" | ":quit" => break,
+                ":help" => writeln!(output, "{HELP}")?,
+                ":reset" => self.reset(),
+                ":locals" => {
+                    for (index, value) in self.locals() {
+                        writeln!(output, "{index} : {value}")?;
+                    }
+                }
+                line => match self.eval_line(line) {
+                    Ok(()) => writeln!(output, "{}", render(self.stack()))?,
+                    Err(err) => writeln!(output, "error : {err}")?,
+                },
+            }
+            write!(output, "> ")?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Render the operand stack from bottom to top.
+fn render(stack: &[Value]) -> String {
+    let values: Vec<String> = stack.iter().map(ToString::to_string).collect();
+    format!("[{}]", values.join(", "))
+}
+
+/// Reject instructions that depend on a class file, such as constant pool
+/// references, or that would branch outside of the current line.
+fn check_supported(code: &[u8]) -> Result<(), ReplError> {
+    let mut offset = 0;
+    while offset < code.len() {
+        let opcode = OPCode::from(code[offset]);
+        let operands = opcode.operands().unwrap_or_default();
+        if opcode.is_branch()
+            || matches!(opcode, OPCode::Ldc)
+            || operands.contains(&OperandKind::U16)
+        {
+            return Err(ReplError::Unsupported(opcode));
+        }
+        offset += 1 + operands.iter().map(OperandKind::size).sum::<usize>();
+    }
+    Ok(())
+}
+
+/// Compile an integer expression to assembler source.
+fn compile_expression(line: &str) -> Result<String, ReplError> {
+    let tokens = tokenize(line)?;
+    let mut parser = Parser {
+        tokens: &tokens,
+        position: 0,
+        code: Vec::new(),
+    };
+    parser.expression()?;
+    if let Some(token) = tokens.get(parser.position) {
+        return Err(ReplError::Syntax(format!("unexpected `{token}`")));
+    }
+    Ok(parser.code.join("\n"))
+}
+
+/// Split an expression into integer literals, operators and parentheses.
+fn tokenize(line: &str) -> Result<Vec<String>, ReplError> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '+' | '-' | '*' | '/' | '%' | '(' | ')' => {
+                tokens.push(c.to_string())
+            }
+            c if c.is_ascii_digit() => {
+                let mut literal = c.to_string();
+                while let Some(d) = chars.next_if(char::is_ascii_digit) {
+                    literal.push(d);
+                }
+                tokens.push(literal);
+            }
+            c => {
+                return Err(ReplError::Syntax(format!(
+                    "unknown mnemonic or unexpected `{c}`"
+                )))
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser emitting stack code in postfix order.
+struct Parser<'a> {
+    tokens: &'a [String],
+    position: usize,
+    code: Vec<String>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(String::as_str)
+    }
+
+    fn advance(&mut self) -> Option<&str> {
+        self.position += 1;
+        self.tokens.get(self.position - 1).map(String::as_str)
+    }
+
+    // expression := term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<(), ReplError> {
+        self.term()?;
+        while let Some(op @ ("+" | "-")) = self.peek() {
+            let mnemonic = if op == "+" { "iadd" } else { "isub" };
+            self.advance();
+            self.term()?;
+            self.code.push(mnemonic.to_string());
+        }
+        Ok(())
+    }
+
+    // term := unary (('*' | '/' | '%') unary)*
+    fn term(&mut self) -> Result<(), ReplError> {
+        self.unary()?;
+        while let Some(op @ ("*" | "/" | "%")) = self.peek() {
+            let mnemonic = match op {
+                "*" => "imul",
+                "/" => "idiv",
+                _ => "irem",
+            };
+            self.advance();
+            self.unary()?;
+            self.code.push(mnemonic.to_string());
+        }
+        Ok(())
+    }
+
+    // unary := '-' unary | '(' expression ')' | literal
+    fn unary(&mut self) -> Result<(), ReplError> {
+        match self.advance() {
+            Some("-") => {
+                self.unary()?;
+                self.code.push("ineg".to_string());
+                Ok(())
+            }
+            Some("(") => {
+                self.expression()?;
+                match self.advance() {
+                    Some(")") => Ok(()),
+                    _ => Err(ReplError::Syntax("expected `)`".to_string())),
+                }
+            }
+            Some(literal) => {
+                let value = literal.parse::<i16>().map_err(|_| {
+                    ReplError::Syntax(format!("invalid literal `{literal}`"))
+                })?;
+                self.code.push(match value {
+                    0..=5 => format!("iconst_{value}"),
+                    v if i8::try_from(v).is_ok() => format!("bipush {v}"),
+                    v => format!("sipush {v}"),
+                });
+                Ok(())
+            }
+            None => Err(ReplError::Syntax(
+                "unexpected end of expression".to_string(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_persists_between_lines() {
+        let mut repl = Repl::new();
+        repl.eval_line("iconst_3").unwrap();
+        repl.eval_line("istore_1").unwrap();
+        assert!(repl.stack().is_empty());
+        repl.eval_line("iload_1").unwrap();
+        repl.eval_line("(1 + 2) * 4 - 10 % 4").unwrap();
+        assert_eq!(repl.stack(), &[Value::Int(3), Value::Int(10)]);
+        repl.eval_line("iadd").unwrap();
+        assert_eq!(repl.stack(), &[Value::Int(13)]);
+        // The frame is the one of `main`, its arguments are in local 0.
+        assert_eq!(
+            repl.locals(),
+            vec![(0, Value::Reference(1)), (1, Value::Int(3))]
+        );
+    }
+
+    #[test]
+    fn reports_errors() {
+        let mut repl = Repl::new();
+        assert!(matches!(
+            repl.eval_line("invokestatic 2"),
+            Err(ReplError::Unsupported(OPCode::InvokeStatic))
+        ));
+        assert!(matches!(repl.eval_line("1 +"), Err(ReplError::Syntax(_))));
+        assert!(matches!(
+            repl.eval_line("bipush"),
+            Err(ReplError::Assembler(_))
+        ));
+    }
+
+    #[test]
+    fn can_run_session() {
+        let mut repl = Repl::new();
+        let mut output = Vec::new();
+        repl.run("2 * 21\nbogus\n:q\n".as_bytes(), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("[42]"));
+        assert!(output.contains("error : syntax error"));
+    }
+}
@@ -1,14 +1,50 @@
 //! JVM runtime module responsible for creating a new runtime
 //! environment and running programs.
+#[cfg(feature = "jit")]
+use crate::baseline::{BaselineCache, BaselineExit, ExitKind};
 use crate::bytecode::OPCode;
-use crate::jit;
-use crate::jvm::CPInfo;
+use crate::collections;
+use crate::coverage::Coverage;
+use crate::error::JitError;
+use crate::error::{Error, LinkError, LinkErrorKind};
+#[cfg(feature = "jit")]
+use crate::events::ExitReason;
+use crate::events::{Event, EventLog};
+use crate::exec_log::ExecLog;
+#[cfg(feature = "jit")]
+use crate::fault::Fault;
+use crate::fusion::{self, Fused, Operand, Superinstruction};
+use crate::heap::{self, Heap, Object};
+use crate::instrument::Instrument;
+#[cfg(feature = "jit")]
+use crate::jit::{self, TraceExit};
+use crate::jvm::{
+    ACC_ABSTRACT, ACC_STATIC, REF_GET_FIELD, REF_GET_STATIC,
+    REF_INVOKE_INTERFACE, REF_INVOKE_SPECIAL, REF_INVOKE_STATIC,
+    REF_INVOKE_VIRTUAL, REF_NEW_INVOKE_SPECIAL, REF_PUT_FIELD, REF_PUT_STATIC,
+};
+use crate::log;
+use crate::options::{LimitKind, Limits, RuntimeOptions};
 use crate::profiler;
-use crate::program::{BaseTypeKind, Program};
+use crate::program::{
+    BaseTypeKind, ClassState, Program, RuntimeConstant, Symbol, SymbolKind,
+};
+#[cfg(feature = "snapshot")]
+use crate::snapshot::{FrameState, Snapshot, SnapshotError};
+use crate::stats::Stats;
+#[cfg(feature = "jit")]
+use crate::tiering::{Reason, Tier, TierPolicy, Transition};
+#[cfg(feature = "jit")]
+use crate::timing::TraceTiming;
+use crate::timing::{Instant, Timings};
 use crate::trace;
+use crate::validate::{self, Diagnostic, LocalTypes};
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 /// `RuntimeErrorKind` represents the possible errors that can occur
 /// during runtime
@@ -17,6 +53,88 @@ pub enum RuntimeErrorKind {
     InvalidValue,
     InvalidOperandType(OPCode),
     MissingOperands(OPCode),
+    /// The run was cancelled through a `CancelHandle`, `stack` holds the
+    /// program counter of every frame, the innermost one last.
+    Cancelled {
+        stack: Vec<ProgramCounter>,
+    },
+    /// The program has no valid entry point.
+    NoEntryPoint {
+        reason: String,
+    },
+    /// A called method isn't declared by the program nor registered as a
+    /// native, `method` is the resolved method reference.
+    UnknownMethod {
+        method: String,
+    },
+    /// A called method resolved to an abstract method.
+    AbstractMethod {
+        method: String,
+    },
+    /// A called method resolved to several unrelated default methods.
+    IncompatibleClassChange {
+        method: String,
+    },
+    /// A resource limit was exceeded by the instruction at `at_pc`.
+    LimitExceeded {
+        kind: LimitKind,
+        at_pc: ProgramCounter,
+    },
+    /// Validation is required and `Program::validate` found problems.
+    InvalidProgram {
+        diagnostics: Vec<Diagnostic>,
+    },
+    /// An instruction expecting an object got `null`.
+    NullPointer(OPCode),
+    /// An array was indexed outside of its bounds.
+    ArrayIndexOutOfBounds {
+        index: i32,
+        length: usize,
+    },
+    /// An array was created with a negative length.
+    NegativeArraySize(i32),
+    /// An integer or long was divided by zero.
+    DivisionByZero,
+    /// A list was indexed outside of its bounds.
+    IndexOutOfBounds {
+        index: i32,
+        length: usize,
+    },
+    /// An iterator was advanced past its last element.
+    NoSuchElement,
+    /// `new` was used on a class whose instances can't be created, only
+    /// the collections we implement natively can be.
+    UnsupportedClass {
+        class: String,
+    },
+    /// A method handle was invoked with a type it can't be adapted to,
+    /// `expected` is the handle's type and `got` the call site's.
+    WrongMethodType {
+        expected: String,
+        got: String,
+    },
+    /// A method handle has a reference kind `kind` the JVM doesn't define.
+    UnsupportedMethodHandle {
+        kind: u8,
+    },
+    /// An exception thrown by `athrow` wasn't caught, `class` is its
+    /// binary name and `message` is boxed to keep errors small.
+    Thrown {
+        class: String,
+        message: Option<Box<str>>,
+    },
+    /// A local variable past the `max_locals` of its method was stored to.
+    InvalidLocal {
+        slot: usize,
+        max_locals: usize,
+    },
+    /// The instruction at `pc` isn't implemented by the interpreter,
+    /// `method` is the method holding it, boxed to keep errors small.
+    Unsupported {
+        opcode: OPCode,
+        pc: ProgramCounter,
+        method: Box<str>,
+    },
 }
 
 /// `RuntimeError` is a custom type used to handle and represents
@@ -26,9 +144,55 @@ pub struct RuntimeError {
     kind: RuntimeErrorKind,
 }
 
+impl RuntimeError {
+    /// Returns the kind of failure.
+    pub const fn kind(&self) -> &RuntimeErrorKind {
+        &self.kind
+    }
+
+    /// Returns the binary name of the error a JVM would throw for this
+    /// failure, `None` if it has no Java counterpart.
+    pub fn java_error(&self) -> Option<&str> {
+        match &self.kind {
+            RuntimeErrorKind::UnknownMethod { .. } => {
+                Some("java/lang/NoSuchMethodError")
+            }
+            RuntimeErrorKind::AbstractMethod { .. } => {
+                Some("java/lang/AbstractMethodError")
+            }
+            RuntimeErrorKind::IncompatibleClassChange { .. } => {
+                Some("java/lang/IncompatibleClassChangeError")
+            }
+            RuntimeErrorKind::NullPointer(_) => {
+                Some("java/lang/NullPointerException")
+            }
+            RuntimeErrorKind::ArrayIndexOutOfBounds { .. } => {
+                Some("java/lang/ArrayIndexOutOfBoundsException")
+            }
+            RuntimeErrorKind::NegativeArraySize(_) => {
+                Some("java/lang/NegativeArraySizeException")
+            }
+            RuntimeErrorKind::DivisionByZero => {
+                Some("java/lang/ArithmeticException")
+            }
+            RuntimeErrorKind::IndexOutOfBounds { .. } => {
+                Some("java/lang/IndexOutOfBoundsException")
+            }
+            RuntimeErrorKind::NoSuchElement => {
+                Some("java/util/NoSuchElementException")
+            }
+            RuntimeErrorKind::WrongMethodType { .. } => {
+                Some("java/lang/invoke/WrongMethodTypeException")
+            }
+            RuntimeErrorKind::Thrown { class, .. } => Some(class),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for RuntimeError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self.kind {
+        match &self.kind {
             RuntimeErrorKind::InvalidValue => {
                 write!(f, "Expected value of type (int, float, long, double)")
             }
@@ -38,6 +202,259 @@ impl fmt::Display for RuntimeError {
             RuntimeErrorKind::InvalidOperandType(opcode) => {
                 write!(f, "Invalid operand type for instruction {opcode}")
             }
+            RuntimeErrorKind::Cancelled { stack } => {
+                write!(f, "Cancelled")?;
+                if let Some(pc) = stack.last() {
+                    write!(f, " at {pc}")?;
+                }
+                Ok(())
+            }
+            RuntimeErrorKind::NoEntryPoint { reason } => {
+                write!(f, "No entry point, {reason}")
+            }
+            RuntimeErrorKind::UnknownMethod { method } => {
+                write!(f, "Unknown method {method}")
+            }
+            RuntimeErrorKind::AbstractMethod { method } => {
+                write!(f, "Abstract method {method}")
+            }
+            RuntimeErrorKind::IncompatibleClassChange { method } => {
+                write!(f, "Conflicting default methods for {method}")
+            }
+            RuntimeErrorKind::LimitExceeded { kind, at_pc } => {
+                write!(f, "Exceeded the {kind} at {at_pc}")
+            }
+            RuntimeErrorKind::InvalidProgram { diagnostics } => {
+                write!(f, "Invalid program")?;
+                for diagnostic in diagnostics {
+                    write!(f, "\n  {diagnostic}")?;
+                }
+                Ok(())
+            }
+            RuntimeErrorKind::NullPointer(opcode) => {
+                write!(f, "Instruction {opcode} got a null reference")
+            }
+            RuntimeErrorKind::ArrayIndexOutOfBounds { index, length } => {
+                write!(f, "Index {index} out of bounds for length {length}")
+            }
+            RuntimeErrorKind::NegativeArraySize(length) => {
+                write!(f, "Negative array size {length}")
+            }
+            RuntimeErrorKind::DivisionByZero => write!(f, "/ by zero"),
+            RuntimeErrorKind::IndexOutOfBounds { index, length } => {
+                write!(f, "Index {index} out of bounds for length {length}")
+            }
+            RuntimeErrorKind::NoSuchElement => {
+                write!(f, "No more elements to iterate")
+            }
+            RuntimeErrorKind::UnsupportedClass { class } => {
+                write!(f, "Can't create instances of {class}")
+            }
+            RuntimeErrorKind::WrongMethodType { expected, got } => {
+                write!(f, "Expected method type {expected} but got {got}")
+            }
+            RuntimeErrorKind::UnsupportedMethodHandle { kind } => {
+                write!(f, "Unsupported method handle kind {kind}")
+            }
+            RuntimeErrorKind::Thrown { class, message } => {
+                write!(f, "{}", heap::java_name(class))?;
+                if let Some(message) = message {
+                    write!(f, ": {message}")?;
+                }
+                Ok(())
+            }
+            RuntimeErrorKind::InvalidLocal { slot, max_locals } => {
+                write!(f, "Local {slot} out of bounds for {max_locals} locals")
+            }
+            RuntimeErrorKind::Unsupported { opcode, pc, method } => {
+                write!(
+                    f,
+                    "Unsupported instruction {opcode} at {pc} in {method}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// Returns true if the interpreter implements `opcode`, the others fail
+/// with `RuntimeErrorKind::Unsupported` when they are reached.
+pub const fn supports(opcode: OPCode) -> bool {
+    !matches!(
+        opcode,
+        OPCode::Breakpoint
+            | OPCode::Dup2
+            | OPCode::Dup2X1
+            | OPCode::Dup2X2
+            | OPCode::DupX1
+            | OPCode::DupX2
+            | OPCode::GotoW
+            | OPCode::IfACmpEq
+            | OPCode::IfACmpNe
+            | OPCode::InstanceOf
+            | OPCode::InvokeDynamic
+            | OPCode::Jsr
+            | OPCode::JsrW
+            | OPCode::MonitorEnter
+            | OPCode::MonitorExit
+            | OPCode::Pop2
+            | OPCode::Ret
+            | OPCode::Swap
+            | OPCode::Unspecified
+    )
+}
+
+/// Returns the result of `fcmp<op>` or `dcmp<op>` when an operand is NaN.
+const fn nan_comparison(opcode: OPCode) -> i32 {
+    match opcode {
+        OPCode::FCmpG | OPCode::DCmpG => 1,
+        _ => -1,
+    }
+}
+
+/// Native implementation of a static method, receives the arguments in
+/// declaration order and returns `None` for `void` methods. The result is
+/// converted to the declared return type, see `Value::convert`.
+pub type NativeMethod = Box<dyn FnMut(&[Value]) -> Option<Value> + Send>;
+
+/// Hook called when a method is entered with its name, descriptor and
+/// arguments in declaration order.
+pub type MethodEnterHook = Box<dyn FnMut(&str, &str, &[Value]) + Send>;
+
+/// Hook called when a method returns with its name, descriptor and the
+/// returned value, `None` for `void` methods and methods an exception
+/// unwinds.
+pub type MethodExitHook = Box<dyn FnMut(&str, &str, Option<Value>) + Send>;
+
+/// Maximum number of instructions a native trace runs before returning to
+/// the interpreter.
+#[cfg(feature = "jit")]
+const SAFEPOINT_INTERVAL: usize = 1 << 20;
+
+/// Root of the classes that can be thrown.
+const THROWABLE: &str = "java/lang/Throwable";
+
+/// Component type of the arguments of `main`.
+const STRING_ARRAY: &str = "Ljava/lang/String;";
+
+/// `CancelHandle` stops a running `Runtime` from another thread, see
+/// `Runtime::cancel_handle`.
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    /// Ask the runtime to stop, it returns a `Cancelled` error before the
+    /// next interpreted instruction.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true if cancellation was requested and not yet honored.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Clear the request, returns whether one was pending.
+    fn take(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Split a method descriptor such as `(IJ)D` into its argument and return
+/// type characters, only primitive types are supported.
+fn parse_descriptor(descriptor: &str) -> Option<(Vec<char>, char)> {
+    let (args, ret) = descriptor.strip_prefix('(')?.split_once(')')?;
+    let args: Vec<char> = args.chars().collect();
+    let mut ret = ret.chars();
+    let ret = match (ret.next(), ret.next()) {
+        (Some(ret), None) => ret,
+        _ => return None,
+    };
+    (args.iter().all(|arg| "IJFDBCSZ".contains(*arg))
+        && "IJFDBCSZV".contains(ret))
+    .then_some((args, ret))
+}
+
+/// Returns true if the method at `method_index` takes the `String[]` of
+/// arguments `main` is called with.
+fn takes_args(program: &Program, method_index: usize) -> bool {
+    program.descriptor(method_index).is_some_and(|descriptor| {
+        descriptor.starts_with("([Ljava/lang/String;)")
+    })
+}
+
+/// Returns true if a `from` argument can be passed as a `to` parameter by
+/// a primitive widening conversion, `boolean` widens to nothing else.
+fn widens(from: &str, to: &str) -> bool {
+    let widened = match from {
+        "B" => "SIJFD",
+        "S" | "C" => "IJFD",
+        "I" => "JFD",
+        "J" => "FD",
+        "F" => "D",
+        _ => "",
+    };
+    to.len() == 1 && widened.contains(to)
+}
+
+/// Returns the system properties a run starts with, the values a JVM on
+/// the host would report for the platform and user.
+fn system_properties() -> HashMap<String, String> {
+    let (os_name, file_separator, path_separator, line_separator) =
+        match std::env::consts::OS {
+            "windows" => ("Windows", "\\", ";", "\r\n"),
+            "macos" => ("Mac OS X", "/", ":", "\n"),
+            "linux" => ("Linux", "/", ":", "\n"),
+            os => (os, "/", ":", "\n"),
+        };
+    let os_arch = match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "x86" => "x86",
+        arch => arch,
+    };
+    let mut properties: HashMap<String, String> = [
+        ("os.name", os_name),
+        ("os.arch", os_arch),
+        ("file.separator", file_separator),
+        ("path.separator", path_separator),
+        ("line.separator", line_separator),
+        ("java.vm.name", "coldbrew"),
+        ("java.version", "1.8"),
+    ]
+    .into_iter()
+    .map(|(key, value)| (key.to_string(), value.to_string()))
+    .collect();
+    if let Ok(dir) = std::env::current_dir() {
+        properties.insert("user.dir".into(), dir.display().to_string());
+    }
+    for (key, var) in [("user.home", "HOME"), ("user.name", "USER")] {
+        if let Ok(value) = std::env::var(var) {
+            properties.insert(key.to_string(), value);
+        }
+    }
+    properties
+}
+
+/// A frame of a Java stack trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackTraceElement {
+    /// Name of the class with dots separating packages.
+    pub class: String,
+    pub method: String,
+    pub file: Option<String>,
+    pub line: Option<u16>,
+}
+
+/// Elements are displayed like `StackTraceElement.toString` e.g
+/// `Main.run(Main.java:12)`.
+impl fmt::Display for StackTraceElement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}(", self.class, self.method)?;
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "{file}:{line})"),
+            (Some(file), None) => write!(f, "{file})"),
+            (None, _) => write!(f, "Unknown Source)"),
         }
     }
 }
@@ -50,8 +467,139 @@ pub enum Value {
     Long(i64),
     Float(f32),
     Double(f64),
+    /// Handle of an object on the heap, see `Heap`.
+    Reference(u32),
+}
+
+/// Values are displayed the way `System.out.println` would print them,
+/// references are shown as their handle.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int(v) => write!(f, "{v}"),
+            Self::Long(v) => write!(f, "{v}"),
+            Self::Float(v) => {
+                write!(
+                    f,
+                    "{}",
+                    java_float(format!("{v:e}"), format!("{v:.1e}"))
+                )
+            }
+            Self::Double(v) => {
+                write!(
+                    f,
+                    "{}",
+                    java_float(format!("{v:e}"), format!("{v:.1e}"))
+                )
+            }
+            Self::Reference(0) => write!(f, "null"),
+            Self::Reference(v) => write!(f, "@{v}"),
+        }
+    }
+}
+
+/// Rewrite the shortest scientific representation of a float, as printed
+/// by `{:e}`, the way `Double.toString` does : plain decimals between
+/// `1e-3` and `1e7` and `d.dddEn` outside, always with a fractional digit.
+/// Like Java a single digit is replaced by the closest two digits, given
+/// by `{:.1e}`, so `Float.MIN_VALUE` is `1.4E-45`.
+fn java_float(shortest: String, two_digits: String) -> String {
+    let scientific = if shortest.contains('.') {
+        shortest
+    } else {
+        two_digits
+    };
+    let (sign, magnitude) = match scientific.strip_prefix('-') {
+        Some(magnitude) => ("-", magnitude),
+        None => ("", scientific.as_str()),
+    };
+    let Some((mantissa, exponent)) = magnitude.split_once('e') else {
+        return match magnitude {
+            "inf" => format!("{sign}Infinity"),
+            _ => "NaN".to_string(),
+        };
+    };
+    let digits = mantissa.replace('.', "");
+    let digits = match digits.trim_end_matches('0') {
+        "" => "0",
+        digits => digits,
+    };
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    if digits == "0" {
+        return format!("{sign}0.0");
+    }
+    let number = if (-3..7).contains(&exponent) {
+        if exponent < 0 {
+            let zeros = "0".repeat((-exponent - 1) as usize);
+            format!("0.{zeros}{digits}")
+        } else {
+            let point = exponent as usize + 1;
+            let padded = format!("{digits:0<point$}");
+            let (integer, fraction) = padded.split_at(point);
+            let fraction = if fraction.is_empty() { "0" } else { fraction };
+            format!("{integer}.{fraction}")
+        }
+    } else {
+        let (first, rest) = digits.split_at(1);
+        let rest = if rest.is_empty() { "0" } else { rest };
+        format!("{first}.{rest}E{exponent}")
+    };
+    format!("{sign}{number}")
+}
+
+macro_rules! value_conversions {
+    ($($t:ty => $variant:ident, $kind:ident;)+) => {
+        $(
+            impl From<$t> for Value {
+                fn from(value: $t) -> Self {
+                    Self::$variant(value)
+                }
+            }
+
+            impl TryFrom<Value> for $t {
+                type Error = ValueTypeError;
+
+                fn try_from(value: Value) -> Result<Self, Self::Error> {
+                    match value {
+                        Value::$variant(v) => Ok(v),
+                        got => Err(ValueTypeError {
+                            expected: BaseTypeKind::$kind,
+                            got,
+                        }),
+                    }
+                }
+            }
+        )+
+    };
+}
+
+value_conversions! {
+    i32 => Int, Int;
+    i64 => Long, Long;
+    f32 => Float, Float;
+    f64 => Double, Double;
+}
+
+/// `ValueTypeError` is returned when converting a `Value` to a Rust type
+/// that doesn't match its variant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueTypeError {
+    pub expected: BaseTypeKind,
+    pub got: Value,
+}
+
+impl fmt::Display for ValueTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Expected value of type {:?}, got {:?}",
+            self.expected, self.got
+        )
+    }
 }
 
+impl std::error::Error for ValueTypeError {}
+
 /// Trait used to represent a JVM value.
 pub trait TypedValue {
     /// Returns the base type of the value.
@@ -63,13 +611,56 @@ pub trait TypedValue {
 /// We could use operator overloading for all the arithmetic operators
 /// but to keep things simple we chose to implement them as functions.
 impl Value {
-    /// Returns the type of the value.
+    /// The `null` reference.
+    pub const NULL: Value = Value::Reference(0);
+
+    /// Returns the type of the value, the class of references isn't known
+    /// without the heap.
     pub const fn t(&self) -> BaseTypeKind {
         match self {
             Self::Int(_) => BaseTypeKind::Int,
             Self::Long(_) => BaseTypeKind::Long,
             Self::Float(_) => BaseTypeKind::Float,
             Self::Double(_) => BaseTypeKind::Double,
+            Self::Reference(_) => BaseTypeKind::Object(String::new()),
+        }
+    }
+
+    /// Returns true if the value can be passed as an argument of descriptor
+    /// type `kind`, `byte`, `char`, `short` and `boolean` are held in ints.
+    pub const fn has_type(&self, kind: char) -> bool {
+        matches!(
+            (self, kind),
+            (Self::Int(_), 'I' | 'B' | 'C' | 'S' | 'Z')
+                | (Self::Long(_), 'J')
+                | (Self::Float(_), 'F')
+                | (Self::Double(_), 'D')
+                | (Self::Reference(_), 'L' | '[')
+        )
+    }
+
+    /// Converts the value to descriptor type `kind` the way the JVM
+    /// conversion instructions would, `byte`, `char` and `short` are
+    /// truncated and `boolean` is 1 for any non zero value. Returns `None`
+    /// for `void` and non primitive types, and for references.
+    pub fn convert(&self, kind: char) -> Option<Value> {
+        let int = match *self {
+            Self::Int(v) => v,
+            Self::Long(v) => v as i32,
+            Self::Float(v) => v as i32,
+            Self::Double(v) => v as i32,
+            Self::Reference(_) => return None,
+        };
+        match kind {
+            'I' => Some(self.to_int()),
+            'J' => Some(self.to_long()),
+            'F' => Some(self.to_float()),
+            'D' => Some(self.to_double()),
+            'B' => Some(Self::Int(i32::from(int as i8))),
+            'C' => Some(Self::Int(i32::from(int as u16))),
+            'S' => Some(Self::Int(i32::from(int as i16))),
+            'Z' => Some(Self::Int(i32::from(int != 0))),
+            _ => None,
         }
     }
 
@@ -85,6 +676,7 @@ impl Value {
             Self::Long(val) => Value::Long(val),
             Self::Float(val) => Value::Long(val as i64),
             Self::Double(val) => Value::Long(val as i64),
+            Self::Reference(_) => panic!("Expected numeric value"),
         }
     }
     /// Converts an existing value from it's base type to `BaseTypeKind::Int`.
@@ -94,6 +686,7 @@ impl Value {
             Self::Long(val) => Value::Int(val as i32),
             Self::Float(val) => Value::Int(val as i32),
             Self::Double(val) => Value::Int(val as i32),
+            Self::Reference(_) => panic!("Expected numeric value"),
         }
     }
     /// Converts an existing value from it's base type to `BaseTypeKind::Double`.
@@ -103,6 +696,7 @@ impl Value {
             Self::Long(val) => Value::Double(val as f64),
             Self::Float(val) => Value::Double(val as f64),
             Self::Double(val) => Value::Double(val),
+            Self::Reference(_) => panic!("Expected numeric value"),
         }
     }
     /// Converts an existing value from it's base type to `BaseTypeKind::Float`.
@@ -112,6 +706,7 @@ impl Value {
             Self::Long(val) => Value::Float(val as f32),
             Self::Float(val) => Value::Float(val),
             Self::Double(val) => Value::Float(val as f32),
+            Self::Reference(_) => panic!("Expected numeric value"),
         }
     }
 
@@ -121,7 +716,9 @@ impl Value {
             (Self::Int(lhs), Self::Int(rhs)) => {
                 Self::Int(lhs.wrapping_add(*rhs))
             }
-            (Self::Long(lhs), Self::Long(rhs)) => Self::Long(lhs + rhs),
+            (Self::Long(lhs), Self::Long(rhs)) => {
+                Self::Long(lhs.wrapping_add(*rhs))
+            }
             (Self::Float(lhs), Self::Float(rhs)) => Self::Float(lhs + rhs),
             (Self::Double(lhs), Self::Double(rhs)) => Self::Double(lhs + rhs),
             _ => panic!("Expected value type"),
@@ -131,8 +728,12 @@ impl Value {
     /// Computes the difference of two values of the same type.
     pub fn sub(lhs: &Self, rhs: &Self) -> Self {
         match (lhs, rhs) {
-            (Self::Int(lhs), Self::Int(rhs)) => Self::Int(lhs - rhs),
-            (Self::Long(lhs), Self::Long(rhs)) => Self::Long(lhs - rhs),
+            (Self::Int(lhs), Self::Int(rhs)) => {
+                Self::Int(lhs.wrapping_sub(*rhs))
+            }
+            (Self::Long(lhs), Self::Long(rhs)) => {
+                Self::Long(lhs.wrapping_sub(*rhs))
+            }
             (Self::Float(lhs), Self::Float(rhs)) => Self::Float(lhs - rhs),
             (Self::Double(lhs), Self::Double(rhs)) => Self::Double(lhs - rhs),
             _ => panic!("Expected value type"),
@@ -142,8 +743,12 @@ impl Value {
     /// Computes the product of two values of the same type.
     pub fn mul(lhs: &Self, rhs: &Self) -> Self {
         match (lhs, rhs) {
-            (Self::Int(lhs), Self::Int(rhs)) => Self::Int(lhs * rhs),
-            (Self::Long(lhs), Self::Long(rhs)) => Self::Long(lhs * rhs),
+            (Self::Int(lhs), Self::Int(rhs)) => {
+                Self::Int(lhs.wrapping_mul(*rhs))
+            }
+            (Self::Long(lhs), Self::Long(rhs)) => {
+                Self::Long(lhs.wrapping_mul(*rhs))
+            }
             (Self::Float(lhs), Self::Float(rhs)) => Self::Float(lhs * rhs),
             (Self::Double(lhs), Self::Double(rhs)) => Self::Double(lhs * rhs),
             _ => panic!("Expected value type"),
@@ -153,8 +758,12 @@ impl Value {
     /// Computes the division of two values of the same type.
     pub fn div(lhs: &Self, rhs: &Self) -> Self {
         match (lhs, rhs) {
-            (Self::Int(lhs), Self::Int(rhs)) => Self::Int(lhs / rhs),
-            (Self::Long(lhs), Self::Long(rhs)) => Self::Long(lhs / rhs),
+            (Self::Int(lhs), Self::Int(rhs)) => {
+                Self::Int(lhs.wrapping_div(*rhs))
+            }
+            (Self::Long(lhs), Self::Long(rhs)) => {
+                Self::Long(lhs.wrapping_div(*rhs))
+            }
             (Self::Float(lhs), Self::Float(rhs)) => Self::Float(lhs / rhs),
             (Self::Double(lhs), Self::Double(rhs)) => Self::Double(lhs / rhs),
             _ => panic!("Expected value type"),
@@ -164,14 +773,96 @@ impl Value {
     /// Computes the remainder of the division of two values of the same type.
     pub fn rem(lhs: &Self, rhs: &Self) -> Self {
         match (lhs, rhs) {
-            (Self::Int(lhs), Self::Int(rhs)) => Self::Int(lhs % rhs),
-            (Self::Long(lhs), Self::Long(rhs)) => Self::Long(lhs % rhs),
+            (Self::Int(lhs), Self::Int(rhs)) => {
+                Self::Int(lhs.wrapping_rem(*rhs))
+            }
+            (Self::Long(lhs), Self::Long(rhs)) => {
+                Self::Long(lhs.wrapping_rem(*rhs))
+            }
             (Self::Float(lhs), Self::Float(rhs)) => Self::Float(lhs % rhs),
             (Self::Double(lhs), Self::Double(rhs)) => Self::Double(lhs % rhs),
             _ => panic!("Expected value type"),
         }
     }
 
+    /// Computes the negation of a value.
+    pub fn neg(value: &Self) -> Self {
+        match value {
+            Self::Int(value) => Self::Int(value.wrapping_neg()),
+            Self::Long(value) => Self::Long(value.wrapping_neg()),
+            Self::Float(value) => Self::Float(-value),
+            Self::Double(value) => Self::Double(-value),
+            _ => panic!("Expected value type"),
+        }
+    }
+
+    /// Shifts lhs left by the low 5 bits of rhs for ints and the low 6 bits
+    /// for longs.
+    pub fn shl(lhs: &Self, rhs: &Self) -> Self {
+        match (lhs, rhs) {
+            (Self::Int(lhs), Self::Int(rhs)) => {
+                Self::Int(lhs.wrapping_shl(*rhs as u32))
+            }
+            (Self::Long(lhs), Self::Int(rhs)) => {
+                Self::Long(lhs.wrapping_shl(*rhs as u32))
+            }
+            _ => panic!("Expected value type"),
+        }
+    }
+
+    /// Arithmetic shift of lhs right, the sign is extended.
+    pub fn shr(lhs: &Self, rhs: &Self) -> Self {
+        match (lhs, rhs) {
+            (Self::Int(lhs), Self::Int(rhs)) => {
+                Self::Int(lhs.wrapping_shr(*rhs as u32))
+            }
+            (Self::Long(lhs), Self::Int(rhs)) => {
+                Self::Long(lhs.wrapping_shr(*rhs as u32))
+            }
+            _ => panic!("Expected value type"),
+        }
+    }
+
+    /// Logical shift of lhs right, zeroes are shifted in.
+    pub fn ushr(lhs: &Self, rhs: &Self) -> Self {
+        match (lhs, rhs) {
+            (Self::Int(lhs), Self::Int(rhs)) => {
+                Self::Int((*lhs as u32).wrapping_shr(*rhs as u32) as i32)
+            }
+            (Self::Long(lhs), Self::Int(rhs)) => {
+                Self::Long((*lhs as u64).wrapping_shr(*rhs as u32) as i64)
+            }
+            _ => panic!("Expected value type"),
+        }
+    }
+
+    /// Computes the bitwise and of two values of the same type.
+    pub fn and(lhs: &Self, rhs: &Self) -> Self {
+        match (lhs, rhs) {
+            (Self::Int(lhs), Self::Int(rhs)) => Self::Int(lhs & rhs),
+            (Self::Long(lhs), Self::Long(rhs)) => Self::Long(lhs & rhs),
+            _ => panic!("Expected value type"),
+        }
+    }
+
+    /// Computes the bitwise or of two values of the same type.
+    pub fn or(lhs: &Self, rhs: &Self) -> Self {
+        match (lhs, rhs) {
+            (Self::Int(lhs), Self::Int(rhs)) => Self::Int(lhs | rhs),
+            (Self::Long(lhs), Self::Long(rhs)) => Self::Long(lhs | rhs),
+            _ => panic!("Expected value type"),
+        }
+    }
+
+    /// Computes the bitwise exclusive or of two values of the same type.
+    pub fn xor(lhs: &Self, rhs: &Self) -> Self {
+        match (lhs, rhs) {
+            (Self::Int(lhs), Self::Int(rhs)) => Self::Int(lhs ^ rhs),
+            (Self::Long(lhs), Self::Long(rhs)) => Self::Long(lhs ^ rhs),
+            _ => panic!("Expected value type"),
+        }
+    }
+
     /// Compares two values of the same type, returns 1 if rhs is greater than lhs
     /// -1 if rhs is less than lhs and 0 otherwise.
     pub fn compare(lhs: &Self, rhs: &Self) -> i32 {
@@ -238,11 +929,92 @@ impl Instruction {
     pub fn get_params(&self) -> Option<Vec<Value>> {
         self.operands.clone()
     }
+
+    /// Returns the operands of a `tableswitch` or `lookupswitch` as ints,
+    /// the length of the instruction comes first then its operands as
+    /// `disasm` decodes them.
+    fn switch_operands(&self) -> Option<Vec<i32>> {
+        self.operands
+            .as_ref()?
+            .iter()
+            .map(|operand| match operand {
+                Value::Int(value) => Some(*value),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the length in bytes of a switch instruction, padding
+    /// included.
+    pub fn switch_length(&self) -> Option<i32> {
+        self.switch_operands()?.first().copied()
+    }
+
+    /// Returns the offset relative to the switch instruction it jumps to
+    /// when `key` is on the stack.
+    pub fn switch_offset(&self, key: i32) -> Option<i32> {
+        let default = self.switch_default()?;
+        Some(
+            self.switch_cases()?
+                .iter()
+                .find(|(low, high, _)| (*low..=*high).contains(&key))
+                .map_or(default, |(_, _, offset)| *offset),
+        )
+    }
+
+    /// Returns the offset relative to the switch instruction that the keys
+    /// no case matches jump to.
+    pub fn switch_default(&self) -> Option<i32> {
+        self.switch_operands()?.get(1).copied()
+    }
+
+    /// Returns the cases of a switch instruction as inclusive key ranges
+    /// and the offset they jump to, in key order. Consecutive keys jumping
+    /// to the same offset share a range and keys jumping to the default
+    /// are left out.
+    pub fn switch_cases(&self) -> Option<Vec<(i32, i32, i32)>> {
+        let operands = self.switch_operands()?;
+        let default = *operands.get(1)?;
+        let keys: Vec<(i32, i32)> = match self.mnemonic {
+            // length, default, low, high, offsets...
+            OPCode::TableSwitch => {
+                let low = *operands.get(2)?;
+                operands
+                    .get(4..)?
+                    .iter()
+                    .zip(0..)
+                    .map(|(offset, index)| (low.wrapping_add(index), *offset))
+                    .collect()
+            }
+            // length, default, npairs, (key, offset)...
+            OPCode::LookupSwitch => operands
+                .get(3..)?
+                .chunks_exact(2)
+                .map(|pair| (pair[0], pair[1]))
+                .collect(),
+            _ => return None,
+        };
+        let mut cases: Vec<(i32, i32, i32)> = vec![];
+        for (key, offset) in keys {
+            if offset == default {
+                continue;
+            }
+            match cases.last_mut() {
+                Some((_, high, last))
+                    if *last == offset && high.checked_add(1) == Some(key) =>
+                {
+                    *high = key;
+                }
+                _ => cases.push((key, key, offset)),
+            }
+        }
+        Some(cases)
+    }
 }
 
 /// Program counter for the runtime points to the current instruction
 /// and method we're executing.
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
 pub struct ProgramCounter {
     pub method_index: usize,
     pub instruction_index: usize,
@@ -293,11 +1065,91 @@ impl Default for ProgramCounter {
 pub struct Frame {
     pub pc: ProgramCounter,
     stack: Vec<Value>,
-    pub locals: HashMap<usize, Value>,
+    pub locals: Locals,
     pub max_locals: u16,
 }
 
+/// Local variables of a frame, one slot per local index sized by the
+/// method's `max_locals`. Longs and doubles live in their first slot, the
+/// second one stays empty like locals that weren't stored to.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Locals {
+    slots: Vec<Option<Value>>,
+}
+
+impl Locals {
+    /// Returns empty locals with room for `max_locals` slots.
+    #[must_use]
+    pub fn new(max_locals: usize) -> Self {
+        Self {
+            slots: vec![None; max_locals],
+        }
+    }
+
+    /// Returns the value of the local at `slot`, `None` if it wasn't
+    /// stored to.
+    #[inline]
+    pub fn get(&self, slot: usize) -> Option<Value> {
+        self.slots.get(slot).copied().flatten()
+    }
+
+    /// Store `value` in the local at `slot`, longs and doubles take the next
+    /// slot as well. Storing to either half of a long or a double clears it.
+    /// # Errors
+    /// Returns an error if the value doesn't fit in the `max_locals` slots.
+    #[inline]
+    pub fn insert(
+        &mut self,
+        slot: usize,
+        value: Value,
+    ) -> Result<(), RuntimeError> {
+        let wide = matches!(value, Value::Long(_) | Value::Double(_));
+        if slot + usize::from(wide) >= self.slots.len() {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidLocal {
+                    slot,
+                    max_locals: self.slots.len(),
+                },
+            });
+        }
+        if let Some(first) = slot.checked_sub(1) {
+            if matches!(
+                self.slots[first],
+                Some(Value::Long(_) | Value::Double(_))
+            ) {
+                self.slots[first] = None;
+            }
+        }
+        self.slots[slot] = Some(value);
+        if wide {
+            self.slots[slot + 1] = None;
+        }
+        Ok(())
+    }
+
+    /// Returns the locals that were stored to along with their slot, in
+    /// slot order.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, Value)> + '_ {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, value)| Some((slot, (*value)?)))
+    }
+}
+
 impl Frame {
+    /// Returns a frame at `pc` with an empty operand stack and `max_locals`
+    /// locals that weren't stored to.
+    #[must_use]
+    pub fn new(pc: ProgramCounter, max_locals: u16) -> Self {
+        Self {
+            pc,
+            stack: vec![],
+            locals: Locals::new(max_locals.into()),
+            max_locals,
+        }
+    }
+
     /// Returns current method index pointed at by the program counter.
     const fn method_index(&self) -> usize {
         self.pc.method_index
@@ -307,9 +1159,10 @@ impl Frame {
     const fn instruction_index(&self) -> usize {
         self.pc.instruction_index
     }
-    /// Increment program counter instruction index.
-    fn inc_instruction_index(&mut self) {
-        self.pc.instruction_index += 1;
+
+    /// Returns the operand stack, the top of the stack is the last value.
+    pub fn stack(&self) -> &[Value] {
+        &self.stack
     }
 }
 
@@ -319,9 +1172,11 @@ impl Frame {
 /// execution to the `Jit` when a block is considered hot.
 ///
 /// `Trace` structure :
+/// ```text
 /// +-------------------------
 /// + `Profile`   | `Record` +
 /// +------------------------+
+/// ```
 ///
 /// `Profile` has all the profiling information for a trace, such
 /// as how many times the trace was executed at this pc value and
@@ -332,115 +1187,1503 @@ impl Frame {
 /// `JitContext`is a minimal struct used to encode a record to execute
 /// and is responsible for keeping track of the CPU <> Runtime context
 /// switching.
-pub struct Runtime {
+///
+/// A `Runtime` owns its program, code cache and sinks and shares nothing
+/// with other runtimes, so it can be built on one thread and run on
+/// another. Native traces are compiled into the runtime's own executable
+/// buffers and are never shared between runtimes.
+pub struct Runtime<I: Instrument = ()> {
     // Program to run.
     program: Program,
     // Stack frames.
     frames: Vec<Frame>,
     // Trace recorder.
-    pub recorder: trace::Recorder,
+    recorder: trace::Recorder,
     // Execution profiler.
     profiler: profiler::Profiler,
     // Jit cache.
+    #[cfg(feature = "jit")]
     jit_cache: jit::JitCache,
+    // Baseline compiled methods.
+    #[cfg(feature = "jit")]
+    baseline: BaselineCache,
+    // Decides which tier runs each method.
+    #[cfg(feature = "jit")]
+    tiers: TierPolicy,
     // Cached bytecode traces.
-    traces: HashMap<ProgramCounter, trace::Trace>,
+    traces: BTreeMap<ProgramCounter, trace::Trace>,
+    // Types of the locals of the methods traces were recorded in, see
+    // `validate::local_types`.
+    local_types: HashMap<usize, Option<LocalTypes>>,
+    // Run superinstructions when nothing observes single instructions.
+    fusion: bool,
+    // Superinstructions of the methods run so far keyed by method then
+    // offset, see `fusion::fuse`.
+    superinstructions: HashMap<usize, HashMap<usize, Fused>>,
     // Used to store return values of the VM.
     return_values: Vec<Value>,
+    // Objects references point to.
+    heap: Heap,
+    // Execution statistics, only collected when enabled.
+    stats: Option<Stats>,
+    // Sink for the program's standard output.
+    stdout: Box<dyn Write + Send>,
+    // Source of the program's standard input.
+    stdin: Box<dyn Read + Send>,
+    // System properties, see `system_properties` for the defaults.
+    properties: HashMap<String, String>,
+    // Bytecode coverage, only collected when enabled.
+    coverage: Option<Coverage>,
+    // Execution log, only written when enabled.
+    exec_log: Option<ExecLog>,
+    // Structured event log, only written when enabled.
+    events: Option<EventLog>,
+    // Phase timings, only measured when enabled.
+    timings: Option<Timings>,
+    // Default JIT mode used by `start`.
+    jit_mode: bool,
+    // Resource limits enforced while running.
+    limits: Limits,
+    // Set from other threads to stop the run.
+    cancel: CancelHandle,
+    // Number of instructions interpreted so far.
+    executed: usize,
+    // Native methods keyed by name along with their number of arguments.
+    natives: HashMap<String, (usize, char, NativeMethod)>,
+    // Hooks called on method entry and exit.
+    enter_hooks: Vec<MethodEnterHook>,
+    exit_hooks: Vec<MethodExitHook>,
+    // Program counter of the instruction that failed, if any.
+    fault: Option<ProgramCounter>,
+    // Exception being thrown by `athrow` until a handler catches it.
+    thrown: Option<Value>,
+    // Values of the static fields stored so far keyed by field index.
+    statics: HashMap<usize, Value>,
+    // Traces that failed to compile, their loops keep being interpreted.
+    jit_errors: Vec<JitError>,
+    // Called before every interpreted instruction.
+    instrument: I,
+    // Why the program's entry point couldn't be found.
+    entry_error: Option<String>,
+    // Refuse to run programs that don't pass `Program::validate`.
+    validate: bool,
 }
 
+// Embedders move runtimes to worker threads, keep them `Send`.
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    assert_send::<Runtime>();
+    assert_send::<Error>();
+};
+
 impl Runtime {
     // TODO: considering moving Program to JVM module instead
     // to avoid repetition here and keeps things tight.
     pub fn new(program: Program) -> Self {
-        let main = program.entry_point();
-        let pc = ProgramCounter {
-            instruction_index: 0,
-            method_index: main,
-        };
-        let initial_frame = Frame {
-            pc,
-            stack: Vec::new(),
-            locals: HashMap::new(),
-            max_locals: 0,
+        Self::with_instrument(program, ())
+    }
+}
+
+impl<I: Instrument> Runtime<I> {
+    /// Create a runtime calling `instrument` before every interpreted
+    /// instruction.
+    pub fn with_instrument(program: Program, mut instrument: I) -> Self {
+        instrument.attach(&program);
+        let mut heap = Heap::new();
+        // Without an entry point there is nothing to run, `run` reports why.
+        let (frames, entry_error) = match program.entry_point() {
+            Ok(main) => {
+                let mut frame = Frame::new(
+                    ProgramCounter::new(main, 0),
+                    program.max_locals(main),
+                );
+                // `main` is called without arguments until `set_args`, the
+                // ones that don't fit the locals are reported by
+                // `Program::validate`.
+                if takes_args(&program, main) {
+                    let args = heap.new_array(STRING_ARRAY, 0);
+                    let _ = frame.locals.insert(0, args);
+                }
+                (vec![frame], None)
+            }
+            Err(err) => (vec![], Some(err.to_string())),
         };
         Self {
             program,
-            frames: vec![initial_frame],
+            frames,
+            entry_error,
             recorder: trace::Recorder::new(),
             profiler: profiler::Profiler::new(),
+            #[cfg(feature = "jit")]
             jit_cache: jit::JitCache::new(),
-            traces: HashMap::new(),
+            #[cfg(feature = "jit")]
+            baseline: BaselineCache::new(),
+            #[cfg(feature = "jit")]
+            tiers: TierPolicy::default(),
+            traces: BTreeMap::new(),
+            local_types: HashMap::new(),
+            fusion: true,
+            superinstructions: HashMap::new(),
             return_values: vec![],
+            heap,
+            stats: None,
+            stdout: Box::new(io::stdout()),
+            stdin: Box::new(io::stdin()),
+            properties: system_properties(),
+            coverage: None,
+            exec_log: None,
+            events: None,
+            timings: None,
+            jit_mode: false,
+            limits: Limits::default(),
+            cancel: CancelHandle::default(),
+            executed: 0,
+            natives: HashMap::new(),
+            enter_hooks: vec![],
+            exit_hooks: vec![],
+            fault: None,
+            thrown: None,
+            statics: HashMap::new(),
+            jit_errors: vec![],
+            instrument,
+            validate: false,
         }
     }
 
-    pub fn run(&mut self, jit_mode: bool) -> Result<(), RuntimeError> {
-        loop {
-            // No more frames, exit.
-            if self.frames.is_empty() {
-                break;
-            }
-            // Fetch the next instruction.
-            let pc = self.frames.last().unwrap().pc;
-            if self.recorder.is_recording()
-                && self.recorder.is_done_recording(pc)
-                && !self.traces.contains_key(&pc)
-            {
-                // TODO: Clean up the naming on trace recoder implementation.
-                let recorded_trace = self.recorder.recording();
-                // TODO: Remove clone once we get rid of stdout trace dump
-                // Cache the trace.
-                self.traces.insert(pc, recorded_trace.clone());
-                // Dump trace to stdout.
-                #[cfg(debug_assertions)]
-                for entry in &recorded_trace.trace {
-                    println!("{entry}");
-                }
-                // Compile recorded trace.
-                if jit_mode {
-                    self.jit_cache.compile(&recorded_trace);
-                }
-            }
-            if self.jit_cache.has_native_trace(pc) && jit_mode {
-                #[cfg(debug_assertions)]
-                println!("Jit entry @ {pc}");
-                // If we have a native trace at this pc run it
-                // and capture the return value which is the next
-                // pc to execute and restore the stack frame.
-                let mut frame = self.frames.pop().unwrap();
-                let _cont_pc = self.jit_cache.execute(pc, &mut frame);
-                self.frames.push(frame);
-                #[cfg(debug_assertions)]
-                println!("Jit exit @ {_cont_pc}");
-                // Return execution to the interpreter.
-                continue;
-            } else {
-                let inst = self.fetch();
-                self.profiler.count_entry(&pc);
+    /// Returns the objects allocated by the program.
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    /// Returns the runtime's instrument.
+    pub fn instrument(&self) -> &I {
+        &self.instrument
+    }
+
+    /// Returns the runtime's instrument mutably.
+    pub fn instrument_mut(&mut self) -> &mut I {
+        &mut self.instrument
+    }
+
+    /// Apply `options` to the runtime, whether the JIT is used is decided
+    /// for each call to `run`.
+    /// # Errors
+    /// Returns an error if the execution log can't be opened.
+    pub fn configure(&mut self, options: &RuntimeOptions) -> io::Result<()> {
+        self.jit_mode = options.jit;
+        self.profiler.set_threshold(options.hot_threshold);
+        self.set_limits(options.limits);
+        self.validate = options.validate;
+        self.fusion = options.fusion;
+        if let Some(level) = options.log_level {
+            log::set_level(Some(level));
+        }
+        if options.time {
+            self.enable_timings();
+        }
+        if options.stats {
+            self.enable_stats();
+        }
+        #[cfg(feature = "jit")]
+        {
+            self.jit_cache.set_checks(options.jit_checks);
+            self.jit_cache.set_code_limit(options.code_cache_size);
+            self.tiers = TierPolicy::new(options.tiers);
+        }
+        if let Some(exec_log) = options.exec_log()? {
+            self.set_exec_log(exec_log);
+        }
+        if let Some(events) = options.event_log()? {
+            self.set_event_log(events);
+        }
+        self.properties.extend(options.properties.iter().cloned());
+        Ok(())
+    }
+
+    /// Set the JIT mode used by `start`.
+    pub fn set_jit_mode(&mut self, jit_mode: bool) {
+        self.jit_mode = jit_mode;
+    }
+
+    /// Set the number of loop entries after which a loop is hot.
+    pub fn set_hot_threshold(&mut self, threshold: usize) {
+        self.profiler.set_threshold(threshold);
+    }
+
+    /// Returns the number of times the interpreter entered the loop header
+    /// at `pc` through a back edge.
+    pub fn loop_entries(&self, pc: ProgramCounter) -> usize {
+        self.profiler.loop_entries(&pc)
+    }
+
+    /// Limit the resources used by the program, the run fails with
+    /// `RuntimeErrorKind::LimitExceeded` once a limit is exceeded.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        self.heap.set_limit(limits.heap);
+    }
+
+    /// Run common instruction sequences as superinstructions, enabled by
+    /// default. Superinstructions are skipped while instruments, coverage,
+    /// the execution log, statistics or the trace recorder need to see
+    /// every instruction.
+    pub fn set_fusion(&mut self, enabled: bool) {
+        self.fusion = enabled;
+    }
+
+    /// Validate the program before running it, the first run fails with
+    /// `RuntimeErrorKind::InvalidProgram` if `Program::validate` finds
+    /// problems.
+    pub fn set_validation(&mut self, enabled: bool) {
+        self.validate = enabled;
+    }
+
+    /// Returns a handle other threads can use to stop the run. Cancelling
+    /// leaves the frames in place, the request is consumed when the run
+    /// stops so that calling `run` again resumes the program.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    /// Returns the number of instructions interpreted so far.
+    pub fn executed(&self) -> usize {
+        self.executed
+    }
+
+    /// Implement the static method `name` with a Rust function, calls to
+    /// it are dispatched to `method` instead of the bytecode.
+    /// # Errors
+    /// Returns an error if `descriptor` isn't made of primitive types.
+    pub fn register_native(
+        &mut self,
+        name: &str,
+        descriptor: &str,
+        method: NativeMethod,
+    ) -> crate::error::Result<()> {
+        let (args, ret) = parse_descriptor(descriptor).ok_or_else(|| {
+            Self::link_error(
+                self.program.class_name(),
+                name,
+                LinkErrorKind::InvalidDescriptor(descriptor.into()),
+            )
+        })?;
+        self.natives
+            .insert(name.to_string(), (args.len(), ret, method));
+        Ok(())
+    }
+
+    /// Call `hook` every time a method is entered, including the entry point
+    /// and native methods. Native traces don't compile calls, hooks only see
+    /// the calls made by the interpreter.
+    pub fn on_method_enter(&mut self, hook: MethodEnterHook) {
+        self.enter_hooks.push(hook);
+    }
+
+    /// Call `hook` every time a method returns, see `on_method_enter`.
+    pub fn on_method_exit(&mut self, hook: MethodExitHook) {
+        self.exit_hooks.push(hook);
+    }
+
+    /// Run the enter hooks for the method at `method_index`.
+    fn enter_method(&mut self, method_index: usize, args: &[Value]) {
+        let name = self.program.method_name(method_index).unwrap_or_default();
+        let descriptor = self
+            .program
+            .methods
+            .get(method_index)
+            .map_or("", |method| method.descriptor.as_str());
+        for hook in &mut self.enter_hooks {
+            hook(name, descriptor, args);
+        }
+    }
+
+    /// Run the exit hooks for the method at `method_index`.
+    fn exit_method(&mut self, method_index: usize, value: Option<Value>) {
+        let name = self.program.method_name(method_index).unwrap_or_default();
+        let descriptor = self
+            .program
+            .methods
+            .get(method_index)
+            .map_or("", |method| method.descriptor.as_str());
+        for hook in &mut self.exit_hooks {
+            hook(name, descriptor, value);
+        }
+    }
+
+    /// Run the program with the JIT mode set by `set_jit_mode` or the
+    /// options the runtime was configured with.
+    /// # Errors
+    /// Returns an error if the program fails.
+    pub fn start(&mut self) -> Result<(), RuntimeError> {
+        self.run(self.jit_mode)
+    }
+
+    /// Returns the program counter of the instruction the last run failed
+    /// at, if any.
+    pub fn fault_pc(&self) -> Option<ProgramCounter> {
+        self.fault
+    }
+
+    /// Returns the traces recorded so far ordered by loop header.
+    pub fn recorded_traces(&self) -> impl Iterator<Item = &trace::Trace> {
+        self.traces.values()
+    }
+
+    /// Returns the errors of the traces that failed to compile.
+    pub fn jit_errors(&self) -> &[JitError] {
+        &self.jit_errors
+    }
+
+    /// Wrap `error` with the class, method and program counter it was
+    /// raised at.
+    pub fn runtime_error(&self, error: RuntimeError) -> Error {
+        let method_index = self.fault.map(|pc| pc.get_method_index());
+        let method = method_index
+            .and_then(|index| self.program.method_name(index))
+            .unwrap_or_default();
+        let class = method_index
+            .and_then(|index| self.program.method_class(index))
+            .unwrap_or_else(|| self.program.class_name());
+        Error::Runtime {
+            class: class.to_string(),
+            method: method.to_string(),
+            pc: self.fault,
+            source: error,
+        }
+    }
+
+    /// Returns the report a JVM prints when `error` escapes `main` : the
+    /// Java error and the stack trace. `None` for errors that have no Java
+    /// counterpart.
+    pub fn java_stack_trace(&self, error: &RuntimeError) -> Option<String> {
+        let name = heap::java_name(error.java_error()?);
+        let mut report = match error.kind() {
+            RuntimeErrorKind::Thrown { .. } => {
+                format!("Exception in thread \"main\" {error}\n")
+            }
+            _ => format!("Exception in thread \"main\" {name}: {error}\n"),
+        };
+        for element in self.stack_trace() {
+            report += &format!("\tat {element}\n");
+        }
+        Some(report)
+    }
+
+    /// Returns the Java stack trace of the frames, innermost first. The
+    /// innermost frame is at the instruction the last run failed at and
+    /// the others at their call.
+    pub fn stack_trace(&self) -> Vec<StackTraceElement> {
+        let innermost = self.frames.len().saturating_sub(1);
+        self.frames
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(depth, frame)| {
+                let (method_index, offset) = match self.fault {
+                    Some(pc) if depth == innermost => {
+                        (pc.get_method_index(), pc.get_instruction_index())
+                    }
+                    // Callers already moved past the invoke instruction.
+                    _ => (
+                        frame.method_index(),
+                        frame.instruction_index().saturating_sub(1),
+                    ),
+                };
+                StackTraceElement {
+                    class: heap::java_name(
+                        self.program.method_class(method_index).unwrap_or(""),
+                    ),
+                    method: self
+                        .program
+                        .method_name(method_index)
+                        .unwrap_or_default()
+                        .to_string(),
+                    file: self
+                        .program
+                        .source_file(method_index)
+                        .map(ToString::to_string),
+                    line: self.program.line_number(method_index, offset),
+                }
+            })
+            .collect()
+    }
+
+    /// Build a link error for `method` of `class`.
+    fn link_error(class: &str, method: &str, kind: LinkErrorKind) -> Error {
+        Error::Link(LinkError {
+            class: class.to_string(),
+            method: method.to_string(),
+            kind,
+        })
+    }
+
+    /// Count the instruction at `pc` against the instruction limit, fails
+    /// without counting it if the run was cancelled.
+    fn charge(&mut self, pc: ProgramCounter) -> Result<(), RuntimeError> {
+        if self.cancel.take() {
+            self.fault = Some(pc);
+            let stack = self.frames.iter().map(|frame| frame.pc).collect();
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::Cancelled { stack },
+            });
+        }
+        self.executed += 1;
+        match self.limits.instructions {
+            Some(limit) if self.executed > limit => {
+                Err(self.limit_exceeded(LimitKind::Instructions(limit), pc))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fail the instruction at `pc` if the objects it allocated went past
+    /// the heap limit.
+    fn check_heap(&mut self, pc: ProgramCounter) -> Result<(), RuntimeError> {
+        match self.limits.heap {
+            Some(limit) if self.heap.take_exceeded() => {
+                Err(self.limit_exceeded(LimitKind::Heap(limit), pc))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Record a fault at `pc` and return the error for exceeding `kind`.
+    fn limit_exceeded(
+        &mut self,
+        kind: LimitKind,
+        pc: ProgramCounter,
+    ) -> RuntimeError {
+        self.fault = Some(pc);
+        RuntimeError {
+            kind: RuntimeErrorKind::LimitExceeded { kind, at_pc: pc },
+        }
+    }
+
+    /// Redirect the program's standard output to `sink`.
+    pub fn set_stdout(&mut self, sink: Box<dyn Write + Send>) {
+        self.stdout = sink;
+    }
+
+    /// Read the program's standard input, `System.in`, from `source`.
+    pub fn set_stdin(&mut self, source: Box<dyn Read + Send>) {
+        self.stdin = source;
+    }
+
+    /// Pass `args` to the entry point as its `String[]` argument, it has no
+    /// effect on entry points that don't take one or once the program
+    /// started.
+    pub fn set_args(&mut self, args: &[String]) {
+        let [frame] = self.frames.as_slice() else {
+            return;
+        };
+        let main = frame.method_index();
+        if frame.instruction_index() != 0 || !takes_args(&self.program, main) {
+            return;
+        }
+        let elements = args
+            .iter()
+            .map(|arg| self.heap.alloc(Object::String(arg.clone())))
+            .collect();
+        let array = self.heap.alloc(Object::Array {
+            component: STRING_ARRAY.to_string(),
+            elements,
+        });
+        let _ = self.frames[0].locals.insert(0, array);
+    }
+
+    /// Set the system property `key` the program reads with
+    /// `System.getProperty`.
+    pub fn set_property(&mut self, key: &str, value: &str) {
+        self.properties.insert(key.to_string(), value.to_string());
+    }
+
+    /// Enable collection of execution statistics, timing every dispatched
+    /// instruction has a cost so this is disabled by default.
+    pub fn enable_stats(&mut self) {
+        self.stats = Some(Stats::new());
+    }
+
+    /// Returns the statistics collected so far if they were enabled.
+    pub fn stats(&self) -> Option<&Stats> {
+        self.stats.as_ref()
+    }
+
+    /// Enable bytecode coverage collection.
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage::new());
+    }
+
+    /// Returns the coverage collected so far if it was enabled.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Enable measuring the time spent in each phase of the run.
+    pub fn enable_timings(&mut self) {
+        self.timings = Some(Timings::default());
+    }
+
+    /// Returns the phase timings if they were enabled, the parse and program
+    /// phases are left for the caller to fill in.
+    pub fn timings(&self) -> Option<&Timings> {
+        self.timings.as_ref()
+    }
+
+    /// Log every interpreted instruction to `exec_log`.
+    pub fn set_exec_log(&mut self, mut exec_log: ExecLog) {
+        exec_log.attach(&self.program);
+        self.exec_log = Some(exec_log);
+    }
+
+    /// Write significant VM events to `events`.
+    pub fn set_event_log(&mut self, events: EventLog) {
+        self.events = Some(events);
+    }
 
-                if self.profiler.is_hot(&pc) {
-                    // println!("Found a hot loop...");
-                    // println!("Recording...");
+    /// Write the event built by `event` to the event log if it's enabled.
+    fn emit(&mut self, event: impl FnOnce() -> Event) {
+        if let Some(events) = self.events.as_mut() {
+            events.emit(&event());
+        }
+    }
+
+    /// Returns the heap location `inst` is about to access, read from the
+    /// operands on the stack of the current frame.
+    fn memory_access(
+        &mut self,
+        inst: &Instruction,
+    ) -> Option<trace::MemoryAccess> {
+        match inst.get_mnemonic() {
+            OPCode::GetField => return self.field_access(inst, 0),
+            OPCode::PutField => return self.field_access(inst, 1),
+            _ => {}
+        }
+        let stack = self.frames.last()?.stack();
+        // Returns the reference `depth` values below the top of the stack.
+        let reference = |depth: usize| match stack.iter().rev().nth(depth)? {
+            Value::Reference(reference) => Some(*reference),
+            _ => None,
+        };
+        let element = |depth: usize| {
+            let array = reference(depth)?;
+            let Some(Value::Int(index)) = stack.iter().rev().nth(depth - 1)
+            else {
+                return None;
+            };
+            let Some(Object::Array { component, .. }) =
+                self.heap.get(Value::Reference(array))
+            else {
+                return None;
+            };
+            Some(trace::MemoryAccess::Element {
+                array,
+                component: component.clone(),
+                index: *index,
+            })
+        };
+        match inst.get_mnemonic() {
+            OPCode::IALoad
+            | OPCode::LALoad
+            | OPCode::FALoad
+            | OPCode::DALoad
+            | OPCode::AALoad
+            | OPCode::BALoad
+            | OPCode::CALoad
+            | OPCode::SALoad => element(1),
+            OPCode::IAStore
+            | OPCode::LAStore
+            | OPCode::FAStore
+            | OPCode::DAStore
+            | OPCode::AAStore
+            | OPCode::BAStore
+            | OPCode::CAStore
+            | OPCode::SAStore => element(2),
+            OPCode::ArrayLength => Some(trace::MemoryAccess::Length {
+                array: reference(0)?,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns the field `inst` is about to access on the object `depth`
+    /// values below the top of the stack, its slot is the one the
+    /// interpreter accesses.
+    fn field_access(
+        &mut self,
+        inst: &Instruction,
+        depth: usize,
+    ) -> Option<trace::MemoryAccess> {
+        let stack = self.frames.last()?.stack();
+        let Some(Value::Reference(object)) = stack.iter().rev().nth(depth)
+        else {
+            return None;
+        };
+        let object = *object;
+        let Some(Value::Int(symbol)) = inst.nth(1) else {
+            return None;
+        };
+        let symbol = usize::try_from(symbol).ok()?;
+        let slot = self.field_slot(Value::Reference(object), symbol);
+        let field = self.program.symbol(symbol)?;
+        Some(trace::MemoryAccess::Field {
+            object,
+            class: field.class.clone(),
+            name: field.name.clone(),
+            descriptor: field.descriptor.clone(),
+            slot,
+        })
+    }
+
+    /// Compile `recorded_trace` to native code, traces that fail to
+    /// compile keep being interpreted.
+    #[cfg(feature = "jit")]
+    fn compile_trace(&mut self, recorded_trace: &trace::Trace) {
+        let start = Instant::now();
+        let pc = recorded_trace.start();
+        match self.jit_cache.compile(recorded_trace) {
+            Ok(()) => {
+                let bytes = self.jit_cache.code_size(pc).unwrap_or_default();
+                self.emit(|| Event::TraceCompiled {
+                    pc,
+                    instructions: recorded_trace.len(),
+                    bytes,
+                });
+                // Evicted loops are recorded and compiled again once
+                // they're hot.
+                for evicted in self.jit_cache.take_evicted() {
+                    self.traces.remove(&evicted);
+                    self.emit(|| Event::TraceEvicted { pc: evicted });
+                }
+            }
+            Err(err) => {
+                log::warning!("jit", "{err}");
+                self.emit(|| Event::CompileFailed {
+                    pc,
+                    reason: err.to_string(),
+                });
+                self.jit_errors.push(err);
+            }
+        }
+        if let Some(timings) = self.timings.as_mut() {
+            let compile = start.elapsed();
+            timings.codegen += compile;
+            timings.traces.push(TraceTiming {
+                pc: recorded_trace.start(),
+                instructions: recorded_trace.len(),
+                compile,
+            });
+        }
+    }
+
+    #[cfg(not(feature = "jit"))]
+    fn compile_trace(&mut self, _recorded_trace: &trace::Trace) {}
+
+    /// Run the native trace at `pc` if there is one, returns false if the
+    /// instruction at `pc` has to be interpreted and the exception raised
+    /// by the trace if any.
+    #[cfg(feature = "jit")]
+    fn enter_native_trace(
+        &mut self,
+        pc: ProgramCounter,
+    ) -> Result<bool, RuntimeError> {
+        if !self.jit_cache.has_native_trace(pc) {
+            return Ok(false);
+        }
+        // Native traces burn fuel at every loop back edge and return to the
+        // interpreter when it runs out, which then reports the limit.
+        let remaining = self
+            .limits
+            .instructions
+            .map_or(usize::MAX, |limit| limit.saturating_sub(self.executed));
+        if remaining == 0 {
+            return Ok(false);
+        }
+        log::debug!("jit", "entering native trace @ {pc}");
+        // If we have a native trace at this pc run it
+        // and capture the return value which is the next
+        // pc to execute and restore the stack frame.
+        // Traces also return regularly so that cancellation is noticed.
+        let mut fuel = i32::try_from(remaining.min(SAFEPOINT_INTERVAL))
+            .unwrap_or(i32::MAX);
+        let initial_fuel = fuel;
+        let mut frame = self.frames.pop().unwrap();
+        let start = Instant::now();
+        let exit = match self.jit_cache.execute(
+            pc,
+            &mut frame,
+            &mut self.heap,
+            &mut fuel,
+        ) {
+            // The iterations run natively aren't recorded, a trace of the
+            // enclosing loop would skip the inner one.
+            Ok(Some(exit)) => {
+                if self.recorder.is_recording() {
+                    self.recorder.abort();
+                    let header = self.recorder.loop_header();
+                    self.emit(|| Event::RecordingAborted { pc: header });
+                }
+                exit
+            }
+            Ok(None) => {
+                self.frames.push(frame);
+                return Ok(false);
+            }
+            Err(fault) => {
+                self.frames.push(frame);
+                self.recover_from_fault(pc, "native trace", &fault);
+                return Ok(false);
+            }
+        };
+        let burned = usize::try_from(initial_fuel - fuel).unwrap_or(0);
+        self.executed += burned;
+        // Each iteration burns the length of the trace in fuel.
+        let per_iteration =
+            self.jit_cache.fuel_per_iteration(pc).unwrap_or(1).max(1);
+        self.emit(|| Event::TraceExit {
+            pc,
+            exit: frame.pc,
+            iterations: burned / per_iteration,
+            reason: match exit {
+                TraceExit::Throw(..) => ExitReason::Exception,
+                TraceExit::Resume(offset)
+                    if offset == pc.get_instruction_index() =>
+                {
+                    ExitReason::Safepoint
+                }
+                TraceExit::Resume(_) => ExitReason::Guard,
+            },
+        });
+        // Traces leaving before their first back edge don't pay off.
+        if burned == 0 {
+            if let Some(transition) = self.tiers.count_trace_deopt(pc) {
+                self.apply_transition(transition);
+            }
+        }
+        if let Some(stats) = self.stats.as_mut() {
+            stats.count_native_entry(
+                pc,
+                burned / per_iteration,
+                start.elapsed(),
+            );
+        }
+        if let Some(timings) = self.timings.as_mut() {
+            timings.native += start.elapsed();
+        }
+        let exit_pc = frame.pc;
+        self.frames.push(frame);
+        log::debug!("jit", "leaving native trace @ {exit_pc}");
+        // The exception is reported at the throwing instruction like the
+        // interpreter does.
+        if let TraceExit::Throw(offset, operand) = exit {
+            self.fault = Some(exit_pc);
+            let code = self.program.code(exit_pc.get_method_index());
+            let kind = match code.get(offset).copied().map(OPCode::from) {
+                Some(OPCode::NewArray) => {
+                    RuntimeErrorKind::NegativeArraySize(operand as i32)
+                }
+                Some(OPCode::ArrayLength) => {
+                    RuntimeErrorKind::NullPointer(OPCode::ArrayLength)
+                }
+                _ => RuntimeErrorKind::DivisionByZero,
+            };
+            return Err(RuntimeError { kind });
+        }
+        Ok(true)
+    }
+
+    #[cfg(not(feature = "jit"))]
+    fn enter_native_trace(
+        &mut self,
+        _pc: ProgramCounter,
+    ) -> Result<bool, RuntimeError> {
+        Ok(false)
+    }
+
+    /// Move code between tiers as decided by the tiering policy, methods
+    /// promoted to the baseline tier are compiled.
+    #[cfg(feature = "jit")]
+    fn apply_transition(&mut self, transition: Transition) {
+        let method_index = transition.pc.get_method_index();
+        match (transition.from, transition.to) {
+            (_, Tier::Baseline) if !self.baseline.has_method(method_index) => {
+                let start = Instant::now();
+                let compiled =
+                    self.baseline.compile(&self.program, method_index);
+                if let Some(timings) = self.timings.as_mut() {
+                    timings.codegen += start.elapsed();
+                }
+                // Methods the baseline compiler can't handle stay where
+                // they are.
+                if let Err(err) = compiled {
+                    log::debug!("jit", "{err}");
+                    self.tiers.reject(transition);
+                    self.emit(|| Event::Blacklist {
+                        pc: transition.pc,
+                        reason: err.to_string(),
+                    });
+                    return;
+                }
+            }
+            (Tier::Trace, Tier::Interpreter) => {
+                self.jit_cache.invalidate(transition.pc);
+            }
+            _ => {}
+        }
+        log::debug!("jit", "tier transition {transition}");
+        self.emit(|| Event::Transition(transition));
+        // Demoted code is never promoted again.
+        if matches!(transition.reason, Reason::Deopts | Reason::Fault) {
+            self.emit(|| Event::Blacklist {
+                pc: transition.pc,
+                reason: transition.reason.to_string(),
+            });
+        }
+        if let Some(stats) = self.stats.as_mut() {
+            stats.count_transition(transition);
+        }
+    }
+
+    /// Drop the native code entered at `pc` which raised `fault`, the frame
+    /// is left as it was on entry so the interpreter resumes at `pc`.
+    #[cfg(feature = "jit")]
+    fn recover_from_fault(
+        &mut self,
+        pc: ProgramCounter,
+        code: &str,
+        fault: &Fault,
+    ) {
+        log::warning!(
+            "jit",
+            "{code} @ {pc} faulted : {fault}, resuming in the interpreter\n{}",
+            fault.dump().trim_end()
+        );
+        let transition = self.tiers.fault(pc);
+        self.apply_transition(transition);
+    }
+
+    /// Count an aborted trace recording in the method at `method_index`.
+    #[cfg(feature = "jit")]
+    fn count_recording_abort(&mut self, method_index: usize) {
+        if let Some(transition) = self.tiers.count_recording_abort(method_index)
+        {
+            self.apply_transition(transition);
+        }
+    }
+
+    #[cfg(not(feature = "jit"))]
+    fn count_recording_abort(&mut self, _method_index: usize) {}
+
+    /// Count an invocation of the method at `method_index`.
+    #[cfg(feature = "jit")]
+    fn count_invocation(&mut self, method_index: usize) {
+        if let Some(transition) = self.tiers.count_invocation(method_index) {
+            self.apply_transition(transition);
+        }
+    }
+
+    #[cfg(not(feature = "jit"))]
+    fn count_invocation(&mut self, _method_index: usize) {}
+
+    /// Run the baseline code of the method at `pc` if it can be entered
+    /// at `pc`, returns false if the instruction at `pc` has to be
+    /// interpreted.
+    #[cfg(feature = "jit")]
+    fn enter_baseline(&mut self, pc: ProgramCounter) -> bool {
+        if self.tiers.tier(pc.method_index) != Tier::Baseline
+            || !self.baseline.has_entry(pc)
+        {
+            return false;
+        }
+        let remaining = self
+            .limits
+            .instructions
+            .map_or(usize::MAX, |limit| limit.saturating_sub(self.executed));
+        if remaining == 0 {
+            return false;
+        }
+        let mut fuel = i32::try_from(remaining.min(SAFEPOINT_INTERVAL))
+            .unwrap_or(i32::MAX);
+        let initial_fuel = fuel;
+        let mut frame = self.frames.pop().unwrap();
+        let start = Instant::now();
+        let mut back_edges = 0;
+        let exit = match self.baseline.execute(
+            pc,
+            &mut frame.locals,
+            &frame.stack,
+            &mut fuel,
+            &mut back_edges,
+        ) {
+            Some(Ok(exit)) => exit,
+            None => {
+                self.frames.push(frame);
+                return false;
+            }
+            Some(Err(fault)) => {
+                self.frames.push(frame);
+                self.recover_from_fault(pc, "baseline method", &fault);
+                return false;
+            }
+        };
+        self.executed += usize::try_from(initial_fuel - fuel).unwrap_or(0);
+        if let Some(timings) = self.timings.as_mut() {
+            timings.native += start.elapsed();
+        }
+        if let Some(transition) =
+            self.tiers.count_back_edges(pc.method_index, back_edges)
+        {
+            self.apply_transition(transition);
+        }
+        match exit {
+            BaselineExit::Return(value) => {
+                if let Some(value) = value {
+                    self.return_values.push(value);
+                    self.push(value);
+                }
+                if !self.exit_hooks.is_empty() {
+                    self.exit_method(pc.method_index, value);
+                }
+            }
+            BaselineExit::Interpret {
+                kind,
+                pc: resume,
+                stack,
+            } => {
+                log::debug!(
+                    "jit",
+                    "leaving baseline method {} @ {resume}",
+                    pc.method_index
+                );
+                if kind == ExitKind::Deopt {
+                    let at = ProgramCounter::new(pc.method_index, resume);
+                    self.emit(|| Event::Deopt { pc: at });
+                    if let Some(transition) =
+                        self.tiers.count_deopt(pc.method_index)
+                    {
+                        self.apply_transition(transition);
+                    }
+                }
+                frame.pc = ProgramCounter::new(pc.method_index, resume);
+                frame.stack = stack;
+                self.frames.push(frame);
+            }
+        }
+        true
+    }
+
+    #[cfg(not(feature = "jit"))]
+    fn enter_baseline(&mut self, _pc: ProgramCounter) -> bool {
+        false
+    }
+
+    pub fn run(&mut self, jit_mode: bool) -> Result<(), RuntimeError> {
+        if jit_mode && !cfg!(feature = "jit") {
+            log::warning!(
+                "jit",
+                "built without the `jit` feature, interpreting"
+            );
+        }
+        if self.executed == 0 && self.frames.is_empty() {
+            if let Some(reason) = &self.entry_error {
+                return Err(RuntimeError {
+                    kind: RuntimeErrorKind::NoEntryPoint {
+                        reason: reason.clone(),
+                    },
+                });
+            }
+        }
+        if self.executed == 0 && self.validate {
+            let diagnostics = self.program.validate();
+            if !diagnostics.is_empty() {
+                return Err(RuntimeError {
+                    kind: RuntimeErrorKind::InvalidProgram { diagnostics },
+                });
+            }
+        }
+        // The entry point is pushed by `new`, it's entered on the first run.
+        if self.executed == 0 && !self.enter_hooks.is_empty() {
+            if let Some(frame) = self.frames.first() {
+                self.enter_method(frame.pc.method_index, &[]);
+            }
+        }
+        self.initialize_entry()?;
+        let run_start = Instant::now();
+        loop {
+            // No more frames, exit.
+            if self.frames.is_empty() {
+                break;
+            }
+            // Fetch the next instruction.
+            let pc = self.frames.last().unwrap().pc;
+            if let Some(stats) = self.stats.as_mut() {
+                stats.tick(pc);
+            }
+            if self.recorder.is_recording()
+                && self.recorder.is_done_recording(pc)
+                && !self.traces.contains_key(&pc)
+            {
+                // TODO: Clean up the naming on trace recoder implementation.
+                let start = Instant::now();
+                let mut recorded_trace = self.recorder.recording();
+                recorded_trace.profile_exits(&self.profiler);
+                let method_index = pc.get_method_index();
+                let types =
+                    self.local_types.entry(method_index).or_insert_with(|| {
+                        validate::local_types(&self.program, method_index)
+                    });
+                if let Some(types) = types {
+                    recorded_trace.annotate(types);
+                }
+                let coalesced = recorded_trace.coalesce_guards();
+                if coalesced > 0 {
+                    log::debug!(
+                        "recorder",
+                        "coalesced {coalesced} guards @ {pc}"
+                    );
+                }
+                // TODO: Remove clone once we get rid of stdout trace dump
+                // Cache the trace.
+                self.traces.insert(pc, recorded_trace.clone());
+                log::debug!(
+                    "recorder",
+                    "recorded trace @ {pc} ({} instructions)",
+                    recorded_trace.len()
+                );
+                self.emit(|| Event::TraceRecorded {
+                    pc,
+                    instructions: recorded_trace.len(),
+                });
+                for entry in &recorded_trace {
+                    log::trace!("recorder", "{entry}");
+                }
+                if let Some(timings) = self.timings.as_mut() {
+                    timings.record += start.elapsed();
+                }
+                // Compile recorded trace, traces that don't branch back to
+                // their start would exit where they were entered.
+                if jit_mode && recorded_trace.is_loop() {
+                    self.compile_trace(&recorded_trace);
+                }
+            }
+            // Exceptions raised by native code are thrown at the
+            // instruction that raised them.
+            let native = jit_mode
+                && (self
+                    .enter_native_trace(pc)
+                    .or_else(|err| self.raise(err, 0).map(|()| true))?
+                    || self.enter_baseline(pc));
+            if native {
+                // Return execution to the interpreter.
+                continue;
+            } else if self
+                .run_superinstruction(pc)
+                .or_else(|err| self.raise(err, 0).map(|()| true))?
+            {
+                continue;
+            } else {
+                self.charge(pc)?;
+                let inst = self.fetch();
+                self.before_instruction(pc, &inst);
+                self.profiler.count_entry(&pc);
+
+                if self.starts_recording(pc) {
+                    log::debug!("profiler", "hot loop @ {pc}, recording");
                     self.recorder.init(pc, pc);
                 }
 
                 if self.recorder.is_recording() {
-                    self.recorder.record(pc, inst.clone());
+                    let start = Instant::now();
+                    let memory = self.memory_access(&inst);
+                    self.recorder.record(pc, inst.clone(), memory);
+                    if let Some(timings) = self.timings.as_mut() {
+                        timings.record += start.elapsed();
+                    }
+                    if !self.recorder.is_recording() {
+                        let header = self.recorder.loop_header();
+                        self.emit(|| Event::RecordingAborted { pc: header });
+                        if jit_mode {
+                            self.count_recording_abort(pc.method_index);
+                        }
+                    }
                 }
-                #[cfg(debug_assertions)]
-                println!("eval {inst} @ {pc}");
                 // Evaluate the instruction.
-                self.eval(&inst)?
+                let depth = self.frames.len();
+                if let Err(err) =
+                    self.eval(&inst, pc).and_then(|()| self.check_heap(pc))
+                {
+                    self.fault = Some(pc);
+                    self.raise(err, 0)?;
+                    continue;
+                }
+                if jit_mode && self.frames.len() > depth {
+                    let callee = self.frames.last().unwrap().pc.method_index;
+                    self.count_invocation(callee);
+                }
+                if inst.get_mnemonic().is_conditional_branch() {
+                    // Branches fall through to the instruction after them.
+                    let taken = self.frames.last().is_some_and(|frame| {
+                        frame.pc.get_instruction_index()
+                            != pc.get_instruction_index() + 3
+                    });
+                    self.profiler.count_branch(&pc, taken);
+                }
             }
         }
+        if let Some(exec_log) = self.exec_log.as_mut() {
+            let _ = exec_log.flush();
+        }
+        if let Some(events) = self.events.as_mut() {
+            let _ = events.flush();
+        }
+        if let Some(timings) = self.timings.as_mut() {
+            timings.interpret = run_start.elapsed().saturating_sub(
+                timings.record + timings.codegen + timings.native,
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns true if a trace should be recorded from the loop header at
+    /// `pc`, loops are recorded once.
+    fn starts_recording(&self, pc: ProgramCounter) -> bool {
+        self.profiler.is_hot(&pc) && !self.traces.contains_key(&pc)
+    }
+
+    /// Run the superinstruction starting at `pc` if there is one and
+    /// nothing needs to see its instructions one by one, returns false if
+    /// the instruction at `pc` has to be interpreted on its own.
+    fn run_superinstruction(
+        &mut self,
+        pc: ProgramCounter,
+    ) -> Result<bool, RuntimeError> {
+        if !self.fusion
+            || self.recorder.is_recording()
+            || self.stats.is_some()
+            || self.coverage.is_active()
+            || self.exec_log.is_active()
+            || self.instrument.is_active()
+        {
+            return Ok(false);
+        }
+        let method_index = pc.method_index;
+        let fused = match self.superinstructions.get(&method_index) {
+            Some(fused) => fused.get(&pc.instruction_index).copied(),
+            None => {
+                let fused = fusion::fuse(
+                    self.program.code(method_index),
+                    self.program.exception_table(method_index),
+                );
+                let at = fused.get(&pc.instruction_index).copied();
+                self.superinstructions.insert(method_index, fused);
+                at
+            }
+        };
+        let Some(fused) = fused else {
+            return Ok(false);
+        };
+        // Limits are reported at the instruction that exceeds them.
+        if self
+            .limits
+            .instructions
+            .is_some_and(|limit| self.executed + fused.instructions > limit)
+        {
+            return Ok(false);
+        }
+        self.profiler.count_entry(&pc);
+        if self.starts_recording(pc) {
+            return Ok(false);
+        }
+        self.charge(pc)?;
+        self.executed += fused.instructions - 1;
+        self.eval_superinstruction(pc, fused)
+            .inspect_err(|_| self.fault = Some(pc))?;
+        Ok(true)
+    }
+
+    /// Evaluate the superinstruction `fused` starting at `pc`, it behaves
+    /// like the instructions it replaces.
+    fn eval_superinstruction(
+        &mut self,
+        pc: ProgramCounter,
+        fused: Fused,
+    ) -> Result<(), RuntimeError> {
+        let invalid = || RuntimeError {
+            kind: RuntimeErrorKind::InvalidValue,
+        };
+        let frame = self.frames.last_mut().ok_or_else(invalid)?;
+        let local = |frame: &Frame, index: usize| {
+            frame.locals.get(index).ok_or_else(invalid)
+        };
+        let operand = |frame: &Frame, operand: Operand| match operand {
+            Operand::Local(index) => local(frame, index),
+            Operand::Const(value) => Ok(Value::Int(value)),
+        };
+        frame.pc.instruction_index = fused.next;
+        match fused.superinstruction {
+            Superinstruction::LoadArith { op, lhs, rhs } => {
+                let lhs = local(frame, lhs)?;
+                let rhs = operand(frame, rhs)?;
+                let value = match op {
+                    OPCode::IAdd => Value::add(&lhs, &rhs),
+                    OPCode::ISub => Value::sub(&lhs, &rhs),
+                    _ => Value::mul(&lhs, &rhs),
+                };
+                frame.stack.push(value);
+            }
+            Superinstruction::LoadBranch {
+                op,
+                lhs,
+                rhs,
+                branch,
+                target,
+            } => {
+                let lhs = local(frame, lhs)?;
+                let rhs = operand(frame, rhs)?;
+                let taken = match op {
+                    OPCode::IfICmpEq => lhs == rhs,
+                    OPCode::IfICmpNe => lhs != rhs,
+                    OPCode::IfICmpLt => lhs < rhs,
+                    OPCode::IfICmpGe => lhs >= rhs,
+                    OPCode::IfICmpGt => lhs > rhs,
+                    _ => lhs <= rhs,
+                };
+                if taken {
+                    self.jump(target as i32 - fused.next as i32);
+                }
+                let branch = ProgramCounter::new(pc.method_index, branch);
+                self.profiler.count_branch(&branch, taken);
+            }
+            Superinstruction::IncGoto {
+                local,
+                constant,
+                target,
+            } => {
+                let value = frame
+                    .locals
+                    .get(local)
+                    .map_or(Value::Int(constant), |value| {
+                        Value::add(&value, &Value::Int(constant))
+                    });
+                frame.locals.insert(local, value)?;
+                self.jump(target as i32 - fused.next as i32);
+            }
+        }
+        Ok(())
+    }
+
+    /// Interpret the next instruction of the current frame, the profiler
+    /// and the JIT are bypassed. Does nothing once every frame returned.
+    pub fn step(&mut self) -> Result<(), RuntimeError> {
+        self.initialize_entry()?;
+        self.step_above(0)
+    }
+
+    /// Interpret the next instruction, exceptions it raises are only caught
+    /// by the frames from `floor` up.
+    fn step_above(&mut self, floor: usize) -> Result<(), RuntimeError> {
+        if self.frames.is_empty() {
+            return Ok(());
+        }
+        let pc = self.frames.last().unwrap().pc;
+        self.charge(pc)?;
+        let inst = self.fetch();
+        self.eval(&inst, pc)
+            .and_then(|()| self.check_heap(pc))
+            .or_else(|err| {
+                self.fault = Some(pc);
+                self.raise(err, floor)
+            })
+    }
+
+    /// Returns the frame currently executing.
+    pub fn frame(&self) -> Option<&Frame> {
+        self.frames.last()
+    }
+
+    /// Returns a mutable reference to the program, used to append code to
+    /// a method that is being executed.
+    pub fn program_mut(&mut self) -> &mut Program {
+        // The code may change, superinstructions are fused again.
+        self.superinstructions.clear();
+        &mut self.program
+    }
+
+    /// Call the static method `method` of `class` with `args` and return the
+    /// value it returns, `None` for `void` methods.
+    ///
+    /// `descriptor` must match the method's declared descriptor and `args`
+    /// must match its parameter types, `int`, `short`, `char`, `byte` and
+    /// `boolean` are all passed as `Value::Int`. The method is interpreted,
+    /// the JIT is not used, and the runtime's own frames are left untouched
+    /// so calls can be made before or after `run`.
+    /// # Errors
+    /// Returns a link error if the method can't be found or the arguments
+    /// don't match the descriptor, and a runtime error if the method fails.
+    pub fn call_static(
+        &mut self,
+        class: &str,
+        method: &str,
+        descriptor: &str,
+        args: &[Value],
+    ) -> crate::error::Result<Option<Value>> {
+        if !self.program.class_names().any(|name| name == class) {
+            return Err(Self::link_error(
+                class,
+                method,
+                LinkErrorKind::UnknownClass,
+            ));
+        }
+        // Fall back to another overload to report the descriptor mismatch.
+        let method_index = self
+            .program
+            .class_method(class, method, descriptor)
+            .or_else(|| {
+                (0..self.program.methods.len()).find(|index| {
+                    self.program.method_class(*index) == Some(class)
+                        && self.program.method_name(*index) == Some(method)
+                })
+            })
+            .ok_or_else(|| {
+                Self::link_error(class, method, LinkErrorKind::UnknownMethod)
+            })?;
+        let declared = &self.program.methods[method_index].descriptor;
+        if declared != descriptor {
+            let kind = LinkErrorKind::DescriptorMismatch {
+                expected: declared.clone(),
+                got: descriptor.to_string(),
+            };
+            return Err(Self::link_error(class, method, kind));
+        }
+        let (arg_types, return_type) = parse_descriptor(descriptor)
+            .ok_or_else(|| {
+                Self::link_error(
+                    class,
+                    method,
+                    LinkErrorKind::InvalidDescriptor(descriptor.into()),
+                )
+            })?;
+        if arg_types.len() != args.len() {
+            let kind = LinkErrorKind::ArgumentCount {
+                expected: arg_types.len(),
+                got: args.len(),
+            };
+            return Err(Self::link_error(class, method, kind));
+        }
+        let mut locals =
+            Locals::new(self.program.max_locals(method_index).into());
+        let mut slot = 0;
+        for (index, (arg, kind)) in args.iter().zip(&arg_types).enumerate() {
+            if !arg.has_type(*kind) {
+                let kind = LinkErrorKind::ArgumentType {
+                    index,
+                    expected: *kind,
+                    got: *arg,
+                };
+                return Err(Self::link_error(class, method, kind));
+            }
+            locals
+                .insert(slot, *arg)
+                .map_err(|err| self.runtime_error(err))?;
+            // Longs and doubles take two local variable slots.
+            slot += if matches!(kind, 'J' | 'D') { 2 } else { 1 };
+        }
+
+        // The callee returns into an empty frame, without locals, that
+        // collects the result.
+        let saved = std::mem::take(&mut self.frames);
+        self.frames.push(Frame::new(ProgramCounter::default(), 0));
+        // Like `invokestatic`, the class declaring the method is initialized
+        // before it runs.
+        let pc = ProgramCounter::new(method_index, 0);
+        let class = self.program.methods[method_index].class;
+        let mut result = self.initialize(class, pc);
+        if result.is_ok() {
+            self.frames.push(Frame {
+                pc,
+                stack: vec![],
+                locals,
+                max_locals: self.program.max_locals(method_index),
+            });
+            if !self.enter_hooks.is_empty() {
+                self.enter_method(method_index, args);
+            }
+        }
+        while self.frames.len() > 1 && result.is_ok() {
+            result = self.step_above(0);
+        }
+        let value = self.frames.first_mut().and_then(|frame| frame.stack.pop());
+        self.frames = saved;
+        result.map_err(|err| self.runtime_error(err))?;
+        Ok(value.filter(|_| return_type != 'V'))
+    }
+
+    /// Save the interpreter state, restoring it into a runtime for the same
+    /// program continues execution where it stopped.
+    #[cfg(feature = "snapshot")]
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            class_name: self.program.class_name().to_string(),
+            executed: self.executed,
+            frames: self.frames.iter().map(FrameState::from).collect(),
+            return_values: self.return_values.clone(),
+            objects: self.heap.objects().to_vec(),
+            statics: self.statics.iter().map(|(&k, &v)| (k, v)).collect(),
+            class_states: (0..)
+                .map_while(|class| self.program.class_state(class))
+                .collect(),
+        }
+    }
+
+    /// Replace the interpreter state with `snapshot`, the next call to `run`
+    /// resumes from it. Profiles and compiled traces are kept.
+    /// # Errors
+    /// Returns an error if the snapshot was taken from another program.
+    #[cfg(feature = "snapshot")]
+    pub fn restore(&mut self, snapshot: Snapshot) -> Result<(), SnapshotError> {
+        if snapshot.class_name != self.program.class_name() {
+            return Err(SnapshotError::ClassMismatch {
+                expected: self.program.class_name().to_string(),
+                found: snapshot.class_name,
+            });
+        }
+        let frames = snapshot
+            .frames
+            .into_iter()
+            .map(|frame| {
+                let method_index = frame.pc.get_method_index();
+                match self.program.methods.get(method_index) {
+                    Some(method) if !method.code.is_empty() => {
+                        let max_locals = self.program.max_locals(method_index);
+                        let mut restored = Frame::new(frame.pc, max_locals);
+                        restored.stack = frame.stack;
+                        for (slot, value) in frame.locals {
+                            restored.locals.insert(slot, value).map_err(
+                                |_| SnapshotError::InvalidLocal(slot),
+                            )?;
+                        }
+                        Ok(restored)
+                    }
+                    _ => Err(SnapshotError::UnknownMethod(method_index)),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        self.frames = frames;
+        self.executed = snapshot.executed;
+        self.return_values = snapshot.return_values;
+        self.heap = Heap::from_objects(snapshot.objects);
+        self.heap.set_limit(self.limits.heap);
+        self.statics = snapshot.statics.into_iter().collect();
+        for (class, state) in snapshot.class_states.into_iter().enumerate() {
+            self.program.set_class_state(class, state);
+        }
+        self.fault = None;
         Ok(())
     }
 
     /// Returns the top value in the return values stack.
     /// Used for testing only
     pub fn top_return_value(&self) -> Option<Value> {
-        return self.return_values.last().copied();
+        self.return_values.last().copied()
     }
 
     /// Push a JVM value into the stack
@@ -458,20 +2701,111 @@ impl Runtime {
         }
     }
 
+    /// Pop an index and an array reference and push the element at the
+    /// index.
+    fn array_load(&mut self, opcode: OPCode) -> Result<(), RuntimeError> {
+        let (index, array) = (self.pop(), self.pop());
+        let (Some(Value::Int(index)), Some(array)) = (index, array) else {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidValue,
+            });
+        };
+        let Some(elements) = self.heap.array(array) else {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::NullPointer(opcode),
+            });
+        };
+        let Some(value) = usize::try_from(index)
+            .ok()
+            .and_then(|index| elements.get(index))
+        else {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::ArrayIndexOutOfBounds {
+                    index,
+                    length: elements.len(),
+                },
+            });
+        };
+        let value = *value;
+        self.push(value);
+        Ok(())
+    }
+
+    /// Pop a value, an index and an array reference and store the value at
+    /// the index, narrowed to the array's type.
+    fn array_store(&mut self, opcode: OPCode) -> Result<(), RuntimeError> {
+        let (value, index, array) = (self.pop(), self.pop(), self.pop());
+        let (Some(value), Some(Value::Int(index)), Some(array)) =
+            (value, index, array)
+        else {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidValue,
+            });
+        };
+        let Some(Object::Array {
+            component,
+            elements,
+        }) = self.heap.get_mut(array)
+        else {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::NullPointer(opcode),
+            });
+        };
+        let length = elements.len();
+        let Some(element) = usize::try_from(index)
+            .ok()
+            .and_then(|index| elements.get_mut(index))
+        else {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::ArrayIndexOutOfBounds { index, length },
+            });
+        };
+        // `bastore` keeps the lowest bit for boolean arrays.
+        *element = match (component.as_str(), value) {
+            ("Z", Value::Int(v)) => Value::Int(v & 1),
+            ("B" | "C" | "S", value) => value
+                .convert(component.chars().next().unwrap_or('I'))
+                .unwrap_or(value),
+            (_, value) => value,
+        };
+        Ok(())
+    }
+
+    /// Allocate an array of type `class`, an array descriptor, with the
+    /// lengths of its outermost `lengths.len()` dimensions. Inner arrays of
+    /// the dimensions that aren't given are left `null`.
+    fn new_multi_array(&mut self, class: &str, lengths: &[usize]) -> Value {
+        let component = class.strip_prefix('[').unwrap_or(class);
+        let Some((length, inner)) = lengths.split_first() else {
+            return Value::NULL;
+        };
+        let array = self.heap.new_array(component, *length);
+        if !inner.is_empty() {
+            for index in 0..*length {
+                let element = self.new_multi_array(component, inner);
+                if let Some(elements) = self.heap.array_mut(array) {
+                    elements[index] = element;
+                }
+            }
+        }
+        array
+    }
+
     /// Store the topmost value in the stack as local value.
-    fn store(&mut self, index: usize) {
+    fn store(&mut self, index: usize) -> Result<(), RuntimeError> {
         if let Some(value) = self.pop() {
             if let Some(frame) = self.frames.last_mut() {
-                frame.locals.insert(index, value);
+                frame.locals.insert(index, value)?;
             }
         }
+        Ok(())
     }
 
     /// Load a local value and push it to the stack.
     fn load(&mut self, index: usize) {
         if let Some(frame) = self.frames.last_mut() {
-            if let Some(value) = frame.locals.get(&index) {
-                frame.stack.push(*value);
+            if let Some(value) = frame.locals.get(index) {
+                frame.stack.push(value);
             }
         }
     }
@@ -479,14 +2813,25 @@ impl Runtime {
     /// Jump with a relative offset.
     fn jump(&mut self, offset: i32) {
         if let Some(frame) = self.frames.last_mut() {
+            let back_edge = frame.pc.instruction_index;
             frame.pc.instruction_index = (frame.pc.instruction_index as isize
                 + offset as isize)
                 as usize;
+            if let Some(stats) = self.stats.as_mut() {
+                if offset < 0 {
+                    stats.count_iteration(frame.pc, back_edge);
+                }
+            }
         }
     }
 
     /// Evaluate a given instruction.
-    fn eval(&mut self, inst: &Instruction) -> Result<(), RuntimeError> {
+    /// Evaluate `inst`, the instruction at `pc`.
+    fn eval(
+        &mut self,
+        inst: &Instruction,
+        pc: ProgramCounter,
+    ) -> Result<(), RuntimeError> {
         if let Some(_frame) = self.frames.last_mut() {
             match inst.mnemonic {
                 OPCode::IconstM1 => {
@@ -545,9 +2890,14 @@ impl Runtime {
                     self.push(Value::Double(1.));
                     Ok(())
                 }
+                OPCode::AConstNull => {
+                    self.push(Value::NULL);
+                    Ok(())
+                }
                 OPCode::BiPush
                 | OPCode::SiPush
                 | OPCode::Ldc
+                | OPCode::LdcW
                 | OPCode::Ldc2W => match &inst.operands {
                     Some(params) => {
                         self.push(params[0]);
@@ -561,7 +2911,8 @@ impl Runtime {
                 OPCode::ILoad
                 | OPCode::LLoad
                 | OPCode::FLoad
-                | OPCode::DLoad => inst.operands.as_ref().map_or_else(
+                | OPCode::DLoad
+                | OPCode::ALoad => inst.operands.as_ref().map_or_else(
                     || {
                         Err(RuntimeError {
                             kind: RuntimeErrorKind::MissingOperands(
@@ -569,7 +2920,7 @@ impl Runtime {
                             ),
                         })
                     },
-                    |params| match params.get(0) {
+                    |params| match params.first() {
                         Some(Value::Int(v)) => {
                             self.load(*v as usize);
                             Ok(())
@@ -584,28 +2935,32 @@ impl Runtime {
                 OPCode::ILoad0
                 | OPCode::LLoad0
                 | OPCode::FLoad0
-                | OPCode::DLoad0 => {
+                | OPCode::DLoad0
+                | OPCode::ALoad0 => {
                     self.load(0);
                     Ok(())
                 }
                 OPCode::ILoad1
                 | OPCode::LLoad1
                 | OPCode::FLoad1
-                | OPCode::DLoad1 => {
+                | OPCode::DLoad1
+                | OPCode::ALoad1 => {
                     self.load(1);
                     Ok(())
                 }
                 OPCode::ILoad2
                 | OPCode::LLoad2
                 | OPCode::FLoad2
-                | OPCode::DLoad2 => {
+                | OPCode::DLoad2
+                | OPCode::ALoad2 => {
                     self.load(2);
                     Ok(())
                 }
                 OPCode::ILoad3
                 | OPCode::LLoad3
                 | OPCode::FLoad3
-                | OPCode::DLoad3 => {
+                | OPCode::DLoad3
+                | OPCode::ALoad3 => {
                     self.load(3);
                     Ok(())
                 }
@@ -613,7 +2968,8 @@ impl Runtime {
                 OPCode::IStore
                 | OPCode::LStore
                 | OPCode::FStore
-                | OPCode::DStore => inst.operands.as_ref().map_or_else(
+                | OPCode::DStore
+                | OPCode::AStore => inst.operands.as_ref().map_or_else(
                     || {
                         Err(RuntimeError {
                             kind: RuntimeErrorKind::MissingOperands(
@@ -621,11 +2977,8 @@ impl Runtime {
                             ),
                         })
                     },
-                    |params| match params.get(0) {
-                        Some(Value::Int(v)) => {
-                            self.store(*v as usize);
-                            Ok(())
-                        }
+                    |params| match params.first() {
+                        Some(Value::Int(v)) => self.store(*v as usize),
                         _ => Err(RuntimeError {
                             kind: RuntimeErrorKind::InvalidOperandType(
                                 inst.mnemonic,
@@ -636,31 +2989,23 @@ impl Runtime {
                 OPCode::IStore0
                 | OPCode::LStore0
                 | OPCode::FStore0
-                | OPCode::DStore0 => {
-                    self.store(0);
-                    Ok(())
-                }
+                | OPCode::DStore0
+                | OPCode::AStore0 => self.store(0),
                 OPCode::IStore1
                 | OPCode::LStore1
                 | OPCode::FStore1
-                | OPCode::DStore1 => {
-                    self.store(1);
-                    Ok(())
-                }
+                | OPCode::DStore1
+                | OPCode::AStore1 => self.store(1),
                 OPCode::IStore2
                 | OPCode::LStore2
                 | OPCode::FStore2
-                | OPCode::DStore2 => {
-                    self.store(2);
-                    Ok(())
-                }
+                | OPCode::DStore2
+                | OPCode::AStore2 => self.store(2),
                 OPCode::IStore3
                 | OPCode::LStore3
                 | OPCode::FStore3
-                | OPCode::DStore3 => {
-                    self.store(3);
-                    Ok(())
-                }
+                | OPCode::DStore3
+                | OPCode::AStore3 => self.store(3),
                 // Arithmetic operations.
                 OPCode::IAdd | OPCode::LAdd | OPCode::FAdd | OPCode::DAdd => {
                     let rhs = self.pop();
@@ -705,6 +3050,11 @@ impl Runtime {
                     let rhs = self.pop();
                     let lhs = self.pop();
 
+                    if matches!(rhs, Some(Value::Int(0) | Value::Long(0))) {
+                        return Err(RuntimeError {
+                            kind: RuntimeErrorKind::DivisionByZero,
+                        });
+                    }
                     if let (Some(a), Some(b)) = (lhs, rhs) {
                         self.push(Value::div(&a, &b));
                         Ok(())
@@ -718,6 +3068,11 @@ impl Runtime {
                     let rhs = self.pop();
                     let lhs = self.pop();
 
+                    if matches!(rhs, Some(Value::Int(0) | Value::Long(0))) {
+                        return Err(RuntimeError {
+                            kind: RuntimeErrorKind::DivisionByZero,
+                        });
+                    }
                     if let (Some(a), Some(b)) = (lhs, rhs) {
                         self.push(Value::rem(&a, &b));
                         Ok(())
@@ -727,6 +3082,66 @@ impl Runtime {
                         })
                     }
                 }
+                OPCode::INeg | OPCode::LNeg | OPCode::FNeg | OPCode::DNeg => {
+                    let Some(value) = self.pop() else {
+                        return Err(RuntimeError {
+                            kind: RuntimeErrorKind::InvalidValue,
+                        });
+                    };
+                    self.push(Value::neg(&value));
+                    Ok(())
+                }
+                // Shift and bitwise operations.
+                OPCode::IShl
+                | OPCode::LShl
+                | OPCode::IShr
+                | OPCode::LShr
+                | OPCode::IUShr
+                | OPCode::LUShr
+                | OPCode::Iand
+                | OPCode::Land
+                | OPCode::IOr
+                | OPCode::LOr
+                | OPCode::IXor
+                | OPCode::LXor => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+
+                    let op = match inst.mnemonic {
+                        OPCode::IShl | OPCode::LShl => Value::shl,
+                        OPCode::IShr | OPCode::LShr => Value::shr,
+                        OPCode::IUShr | OPCode::LUShr => Value::ushr,
+                        OPCode::Iand | OPCode::Land => Value::and,
+                        OPCode::IOr | OPCode::LOr => Value::or,
+                        _ => Value::xor,
+                    };
+                    if let (Some(a), Some(b)) = (lhs, rhs) {
+                        self.push(op(&a, &b));
+                        Ok(())
+                    } else {
+                        Err(RuntimeError {
+                            kind: RuntimeErrorKind::InvalidValue,
+                        })
+                    }
+                }
+                OPCode::TableSwitch | OPCode::LookupSwitch => {
+                    let Some(Value::Int(key)) = self.pop() else {
+                        return Err(RuntimeError {
+                            kind: RuntimeErrorKind::InvalidValue,
+                        });
+                    };
+                    let (Some(length), Some(offset)) =
+                        (inst.switch_length(), inst.switch_offset(key))
+                    else {
+                        return Err(RuntimeError {
+                            kind: RuntimeErrorKind::MissingOperands(
+                                inst.mnemonic,
+                            ),
+                        });
+                    };
+                    self.jump(offset - length);
+                    Ok(())
+                }
                 OPCode::IInc => {
                     if let Some(params) = &inst.operands {
                         if params.len() < 2 {
@@ -738,19 +3153,22 @@ impl Runtime {
                         } else {
                             match (params[0], params[1]) {
                                 (Value::Int(index), Value::Int(constant)) => {
-                                    self.frames
+                                    let locals = &mut self
+                                        .frames
                                         .last_mut()
                                         .unwrap()
-                                        .locals
-                                        .entry(index as usize)
-                                        .and_modify(|val| {
-                                            *val = Value::add(
-                                                val,
+                                        .locals;
+                                    let index = index as usize;
+                                    let value = locals.get(index).map_or(
+                                        Value::Int(constant),
+                                        |value| {
+                                            Value::add(
+                                                &value,
                                                 &Value::Int(constant),
                                             )
-                                        })
-                                        .or_insert(Value::Int(constant));
-                                    Ok(())
+                                        },
+                                    );
+                                    locals.insert(index, value)
                                 }
                                 _ => Err(RuntimeError {
                                     kind: RuntimeErrorKind::InvalidOperandType(
@@ -788,6 +3206,149 @@ impl Runtime {
                     self.push(val.expect("expected value").to_long());
                     Ok(())
                 }
+                OPCode::I2B | OPCode::I2C | OPCode::I2S => {
+                    let kind = match inst.mnemonic {
+                        OPCode::I2B => 'B',
+                        OPCode::I2C => 'C',
+                        _ => 'S',
+                    };
+                    match self.pop().and_then(|val| val.convert(kind)) {
+                        Some(val) => {
+                            self.push(val);
+                            Ok(())
+                        }
+                        None => Err(RuntimeError {
+                            kind: RuntimeErrorKind::InvalidValue,
+                        }),
+                    }
+                }
+                // Array operations.
+                OPCode::NewArray => {
+                    let component = match inst.operands.as_deref() {
+                        Some([Value::Int(4)]) => "Z",
+                        Some([Value::Int(5)]) => "C",
+                        Some([Value::Int(6)]) => "F",
+                        Some([Value::Int(7)]) => "D",
+                        Some([Value::Int(8)]) => "B",
+                        Some([Value::Int(9)]) => "S",
+                        Some([Value::Int(10)]) => "I",
+                        Some([Value::Int(11)]) => "J",
+                        _ => {
+                            return Err(RuntimeError {
+                                kind: RuntimeErrorKind::InvalidOperandType(
+                                    inst.mnemonic,
+                                ),
+                            })
+                        }
+                    };
+                    let Some(Value::Int(length)) = self.pop() else {
+                        return Err(RuntimeError {
+                            kind: RuntimeErrorKind::InvalidValue,
+                        });
+                    };
+                    let Ok(length) = usize::try_from(length) else {
+                        return Err(RuntimeError {
+                            kind: RuntimeErrorKind::NegativeArraySize(length),
+                        });
+                    };
+                    let array = self.heap.new_array(component, length);
+                    self.push(array);
+                    Ok(())
+                }
+                // Arrays of references hold their component descriptor.
+                OPCode::ANewArray => {
+                    let Some(Value::Int(class)) = inst.nth(0) else {
+                        return Err(RuntimeError {
+                            kind: RuntimeErrorKind::MissingOperands(
+                                inst.mnemonic,
+                            ),
+                        });
+                    };
+                    let Some(Value::Int(length)) = self.pop() else {
+                        return Err(RuntimeError {
+                            kind: RuntimeErrorKind::InvalidValue,
+                        });
+                    };
+                    let Ok(length) = usize::try_from(length) else {
+                        return Err(RuntimeError {
+                            kind: RuntimeErrorKind::NegativeArraySize(length),
+                        });
+                    };
+                    let class =
+                        self.program.string(class as usize).unwrap_or_default();
+                    let component = if class.starts_with('[') {
+                        class.to_string()
+                    } else {
+                        format!("L{class};")
+                    };
+                    let array = self.heap.new_array(&component, length);
+                    self.push(array);
+                    Ok(())
+                }
+                OPCode::MultiANewArray => {
+                    let (Some(Value::Int(class)), Some(Value::Int(dimensions))) =
+                        (inst.nth(0), inst.nth(1))
+                    else {
+                        return Err(RuntimeError {
+                            kind: RuntimeErrorKind::MissingOperands(
+                                inst.mnemonic,
+                            ),
+                        });
+                    };
+                    let mut lengths = vec![];
+                    for _ in 0..dimensions {
+                        let Some(Value::Int(length)) = self.pop() else {
+                            return Err(RuntimeError {
+                                kind: RuntimeErrorKind::InvalidValue,
+                            });
+                        };
+                        let Ok(length) = usize::try_from(length) else {
+                            return Err(RuntimeError {
+                                kind: RuntimeErrorKind::NegativeArraySize(
+                                    length,
+                                ),
+                            });
+                        };
+                        lengths.push(length);
+                    }
+                    // The outermost length was pushed first.
+                    lengths.reverse();
+                    let class = self
+                        .program
+                        .string(class as usize)
+                        .unwrap_or_default()
+                        .to_string();
+                    let array = self.new_multi_array(&class, &lengths);
+                    self.push(array);
+                    Ok(())
+                }
+                OPCode::IALoad
+                | OPCode::LALoad
+                | OPCode::FALoad
+                | OPCode::DALoad
+                | OPCode::AALoad
+                | OPCode::BALoad
+                | OPCode::CALoad
+                | OPCode::SALoad => self.array_load(inst.mnemonic),
+                OPCode::IAStore
+                | OPCode::LAStore
+                | OPCode::FAStore
+                | OPCode::DAStore
+                | OPCode::AAStore
+                | OPCode::BAStore
+                | OPCode::CAStore
+                | OPCode::SAStore => self.array_store(inst.mnemonic),
+                OPCode::ArrayLength => {
+                    let array = self.pop().unwrap_or(Value::NULL);
+                    let Some(elements) = self.heap.array(array) else {
+                        return Err(RuntimeError {
+                            kind: RuntimeErrorKind::NullPointer(inst.mnemonic),
+                        });
+                    };
+                    let length = Value::Int(elements.len() as i32);
+                    self.push(length);
+                    Ok(())
+                }
                 // Comparison operations.
                 OPCode::LCmp
                 | OPCode::FCmpL
@@ -798,7 +3359,22 @@ impl Runtime {
                     let lhs = self.pop();
 
                     if let (Some(a), Some(b)) = (lhs, rhs) {
-                        self.push(Value::Int(Value::compare(&a, &b)));
+                        // Comparisons with NaN give -1 for fcmpl and dcmpl,
+                        // and 1 for fcmpg and dcmpg.
+                        let result = match (a, b) {
+                            (Value::Float(a), Value::Float(b))
+                                if a.is_nan() || b.is_nan() =>
+                            {
+                                nan_comparison(inst.mnemonic)
+                            }
+                            (Value::Double(a), Value::Double(b))
+                                if a.is_nan() || b.is_nan() =>
+                            {
+                                nan_comparison(inst.mnemonic)
+                            }
+                            _ => Value::compare(&a, &b),
+                        };
+                        self.push(Value::Int(result));
                         Ok(())
                     } else {
                         Err(RuntimeError {
@@ -843,6 +3419,27 @@ impl Runtime {
                     }
                     Ok(())
                 }
+                OPCode::IfNull | OPCode::IfNonNull => {
+                    let Some(Value::Reference(handle)) = self.pop() else {
+                        return Err(RuntimeError {
+                            kind: RuntimeErrorKind::InvalidOperandType(
+                                inst.mnemonic,
+                            ),
+                        });
+                    };
+                    let Some(params) = inst.operands.as_ref() else {
+                        return Err(RuntimeError {
+                            kind: RuntimeErrorKind::MissingOperands(
+                                inst.mnemonic,
+                            ),
+                        });
+                    };
+                    let relative_offset = Self::get_relative_offset(params);
+                    if (handle == 0) == (inst.mnemonic == OPCode::IfNull) {
+                        self.jump(relative_offset);
+                    }
+                    Ok(())
+                }
                 OPCode::IfLt => {
                     let Some(Value::Int(value)) = self.pop() else {
                         panic!("expected value to be integer")
@@ -1063,109 +3660,1154 @@ impl Runtime {
                         })
                     }
                 }
-                // Goto
-                OPCode::Goto => {
-                    let relative_offset = inst.operands.as_ref().map_or_else(
-                        || {
-                            panic!(
-                             "Expected instruction to have parameters got None"
-                         )
-                        },
-                        |params| Self::get_relative_offset(params),
-                    );
-
-                    self.jump(relative_offset);
-                    Ok(())
-                }
-                // Return with value.
-                OPCode::IReturn
-                | OPCode::LReturn
-                | OPCode::FReturn
-                | OPCode::DReturn => {
-                    if let Some(mut frame) = self.frames.pop() {
-                        let value = frame.stack.pop().unwrap();
-                        // This is for debugging purposes.
-                        self.return_values.push(value);
-                        self.push(value);
-                        Ok(())
+                // Goto
+                OPCode::Goto => {
+                    let relative_offset = inst.operands.as_ref().map_or_else(
+                        || {
+                            panic!(
+                             "Expected instruction to have parameters got None"
+                         )
+                        },
+                        |params| Self::get_relative_offset(params),
+                    );
+
+                    self.jump(relative_offset);
+                    Ok(())
+                }
+                // Return with value.
+                OPCode::IReturn
+                | OPCode::LReturn
+                | OPCode::FReturn
+                | OPCode::DReturn
+                | OPCode::AReturn => {
+                    if let Some(mut frame) = self.frames.pop() {
+                        let value = frame.stack.pop().unwrap();
+                        // This is for debugging purposes.
+                        self.return_values.push(value);
+                        self.push(value);
+                        if !self.exit_hooks.is_empty() {
+                            self.exit_method(
+                                frame.pc.method_index,
+                                Some(value),
+                            );
+                        }
+                        Ok(())
+                    } else {
+                        Err(RuntimeError {
+                            kind: RuntimeErrorKind::InvalidValue,
+                        })
+                    }
+                }
+                // Void return
+                OPCode::Return => {
+                    if let Some(frame) = self.frames.pop() {
+                        if !self.exit_hooks.is_empty() {
+                            self.exit_method(frame.pc.method_index, None);
+                        }
+                    }
+                    Ok(())
+                }
+                // Function calls.
+                OPCode::InvokeStatic
+                | OPCode::InvokeSpecial
+                | OPCode::InvokeVirtual
+                | OPCode::InvokeInterface => match inst.operands.as_deref() {
+                    Some([Value::Int(method_index), Value::Int(symbol)]) => {
+                        let method_index = if matches!(
+                            inst.mnemonic,
+                            OPCode::InvokeVirtual | OPCode::InvokeInterface
+                        ) {
+                            self.dispatch(*method_index, *symbol)
+                        } else {
+                            *method_index
+                        };
+                        if inst.mnemonic == OPCode::InvokeStatic {
+                            if let Ok(method) = usize::try_from(method_index) {
+                                self.initialize(
+                                    self.program.methods[method].class,
+                                    pc,
+                                )?;
+                            }
+                        }
+                        self.call(method_index, *symbol, pc)
+                    }
+                    _ => Err(RuntimeError {
+                        kind: RuntimeErrorKind::MissingOperands(inst.mnemonic),
+                    }),
+                },
+                // Objects, the program's classes and the collections in
+                // `collections` can be created and casts aren't checked.
+                OPCode::New => {
+                    let class = match inst.operands.as_deref() {
+                        Some([Value::Int(class)]) => self
+                            .program
+                            .string(*class as usize)
+                            .unwrap_or_default(),
+                        _ => {
+                            return Err(RuntimeError {
+                                kind: RuntimeErrorKind::MissingOperands(
+                                    inst.mnemonic,
+                                ),
+                            })
+                        }
+                    };
+                    let class = class.to_string();
+                    let object = self.new_object(&class, pc)?;
+                    self.push(object);
+                    Ok(())
+                }
+                OPCode::CheckCast => Ok(()),
+                // Fields are accessed at their slot in instances of program
+                // classes, by name on the others.
+                OPCode::GetField | OPCode::PutField => {
+                    let value = (inst.mnemonic == OPCode::PutField)
+                        .then(|| self.pop().unwrap_or(Value::NULL));
+                    let object = self.pop().unwrap_or(Value::NULL);
+                    let symbol = match inst.operands.as_deref() {
+                        Some([_, Value::Int(symbol)]) => {
+                            usize::try_from(*symbol).ok().filter(|symbol| {
+                                self.program.symbol(*symbol).is_some_and(
+                                    |symbol| symbol.kind == SymbolKind::Field,
+                                )
+                            })
+                        }
+                        _ => None,
+                    };
+                    let Some(symbol) = symbol else {
+                        return Err(RuntimeError {
+                            kind: RuntimeErrorKind::MissingOperands(
+                                inst.mnemonic,
+                            ),
+                        });
+                    };
+                    let null = RuntimeError {
+                        kind: RuntimeErrorKind::NullPointer(inst.mnemonic),
+                    };
+                    if let Some(value) = value {
+                        if !self.put_field(object, symbol, value) {
+                            return Err(null);
+                        }
+                    } else {
+                        let value =
+                            self.get_field(object, symbol).ok_or(null)?;
+                        self.push(value);
+                    }
+                    Ok(())
+                }
+                // Static fields of the program are kept by the runtime,
+                // library ones aren't modelled : `System.out` is implied by
+                // the print calls so getting it pushes nothing.
+                OPCode::GetStatic | OPCode::PutStatic => {
+                    let (field, symbol) = match inst.operands.as_deref() {
+                        Some([Value::Int(field), Value::Int(symbol)]) => {
+                            (*field, *symbol)
+                        }
+                        _ => {
+                            return Err(RuntimeError {
+                                kind: RuntimeErrorKind::MissingOperands(
+                                    inst.mnemonic,
+                                ),
+                            })
+                        }
+                    };
+                    let put = inst.mnemonic == OPCode::PutStatic;
+                    let Ok(field) = usize::try_from(field) else {
+                        if put {
+                            self.pop();
+                        }
+                        return Ok(());
+                    };
+                    if let Some(class) = self.program.field_class(field) {
+                        self.initialize(class, pc)?;
+                    }
+                    if put {
+                        let value = self.pop().unwrap_or(Value::NULL);
+                        self.statics.insert(field, value);
+                    } else {
+                        let value = self.static_value(field, symbol as usize);
+                        self.push(value);
+                    }
+                    Ok(())
+                }
+                // The handler is looked up by `catch` once the exception
+                // is raised.
+                OPCode::AThrow => {
+                    let exception = self.pop().unwrap_or(Value::NULL);
+                    let Some(class) =
+                        self.heap.get(exception).map(Object::class_name)
+                    else {
+                        return Err(RuntimeError {
+                            kind: RuntimeErrorKind::NullPointer(inst.mnemonic),
+                        });
+                    };
+                    let message = self
+                        .heap
+                        .field(exception, heap::MESSAGE_FIELD, "L")
+                        .and_then(|message| self.heap.string(message))
+                        .map(Box::from);
+                    self.thrown = Some(exception);
+                    Err(RuntimeError {
+                        kind: RuntimeErrorKind::Thrown { class, message },
+                    })
+                }
+                // Stack operations.
+                OPCode::Pop => match self.pop() {
+                    Some(_) => Ok(()),
+                    None => Err(RuntimeError {
+                        kind: RuntimeErrorKind::InvalidValue,
+                    }),
+                },
+                OPCode::Dup => match self.pop() {
+                    Some(value) => {
+                        self.push(value);
+                        self.push(value);
+                        Ok(())
+                    }
+                    None => Err(RuntimeError {
+                        kind: RuntimeErrorKind::InvalidValue,
+                    }),
+                },
+                OPCode::Nop => Ok(()),
+                // `fetch` doesn't decode the operands of the instructions
+                // we don't implement, only their opcode was read.
+                _ => {
+                    let mut pc = self.frames.last().unwrap().pc;
+                    pc.instruction_index -= 1;
+                    let method = self
+                        .program
+                        .declared_method(pc.method_index)
+                        .map(|method| method.to_string().into())
+                        .unwrap_or_default();
+                    Err(RuntimeError {
+                        kind: RuntimeErrorKind::Unsupported {
+                            opcode: inst.mnemonic,
+                            pc,
+                            method,
+                        },
+                    })
+                }
+            }
+        } else {
+            log::debug!("runtime", "reached last frame, leaving");
+            Ok(())
+        }
+    }
+
+    /// Call the method of the program symbol `symbol`, `method_index` is the
+    /// method it resolved to or -1 if it isn't part of the program. The few
+    /// library methods we support come first, then natives and program
+    /// methods.
+    fn call(
+        &mut self,
+        method_index: i32,
+        symbol: i32,
+        at_pc: ProgramCounter,
+    ) -> Result<(), RuntimeError> {
+        let Some(target) = usize::try_from(symbol)
+            .ok()
+            .and_then(|symbol| self.program.symbol(symbol))
+            .filter(|symbol| symbol.kind == SymbolKind::Method)
+            .map(Symbol::method_ref)
+        else {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::UnknownMethod {
+                    method: format!("#{symbol}"),
+                },
+            });
+        };
+        match (target.class, target.name, target.descriptor) {
+            ("java/io/PrintStream", "println", "()V") => {
+                // Failing to write the program output isn't a runtime error,
+                // like `PrintStream` we ignore it.
+                let _ = writeln!(self.stdout);
+                return Ok(());
+            }
+            (
+                "java/io/PrintStream",
+                method @ ("print" | "println"),
+                desc @ ("(I)V"
+                | "(J)V"
+                | "(F)V"
+                | "(D)V"
+                | "(B)V"
+                | "(S)V"
+                | "(C)V"
+                | "(Z)V"
+                | "(Ljava/lang/String;)V"
+                | "(Ljava/lang/Object;)V"
+                | "([C)V"),
+            ) => {
+                let newline = method == "println";
+                let kind = desc.chars().nth(1).unwrap_or('V');
+                let Some(value) = self.pop() else {
+                    return Ok(());
+                };
+                let text = match kind {
+                    'L' => self.string_value(value, at_pc)?,
+                    '[' => match self.heap.array(value) {
+                        Some(chars) => chars
+                            .iter()
+                            .map(|code| match code {
+                                Value::Int(code) => {
+                                    char::from_u32(*code as u32)
+                                        .unwrap_or(char::REPLACEMENT_CHARACTER)
+                                }
+                                _ => char::REPLACEMENT_CHARACTER,
+                            })
+                            .collect(),
+                        None => {
+                            return Err(RuntimeError {
+                                kind: RuntimeErrorKind::NullPointer(
+                                    OPCode::InvokeVirtual,
+                                ),
+                            })
+                        }
+                    },
+                    kind => self.heap.value_string(value, kind),
+                };
+                let _ = if newline {
+                    writeln!(self.stdout, "{text}")
+                } else {
+                    write!(self.stdout, "{text}")
+                };
+                return Ok(());
+            }
+            ("java/lang/Object", "<init>", "()V") => {
+                self.pop();
+                return Ok(());
+            }
+            (
+                class,
+                "<init>",
+                descriptor @ ("()V" | "(Ljava/lang/String;)V"),
+            ) if class == THROWABLE
+                || heap::throwable_superclass(class).is_some() =>
+            {
+                let message = if descriptor == "()V" {
+                    Value::NULL
+                } else {
+                    self.pop().unwrap_or(Value::NULL)
+                };
+                let receiver = self.pop().unwrap_or(Value::NULL);
+                self.heap.set_field(receiver, heap::MESSAGE_FIELD, message);
+                return Ok(());
+            }
+            (
+                _,
+                "getMessage" | "getLocalizedMessage",
+                "()Ljava/lang/String;",
+            ) if method_index < 0 => {
+                let receiver = self.pop().unwrap_or(Value::NULL);
+                let Some(message) =
+                    self.heap.field(receiver, heap::MESSAGE_FIELD, "L")
+                else {
+                    return Err(RuntimeError {
+                        kind: RuntimeErrorKind::NullPointer(
+                            OPCode::InvokeVirtual,
+                        ),
+                    });
+                };
+                self.push(message);
+                return Ok(());
+            }
+            // Overrides were dispatched to already, this is the library's
+            // `toString`.
+            (_, "toString", "()Ljava/lang/String;") if method_index < 0 => {
+                let receiver = self.pop().unwrap_or(Value::NULL);
+                if self.heap.get(receiver).is_none() {
+                    return Err(RuntimeError {
+                        kind: RuntimeErrorKind::NullPointer(
+                            OPCode::InvokeVirtual,
+                        ),
+                    });
+                }
+                let string = self.heap.java_string(receiver);
+                let string = self.heap.intern(&string);
+                self.push(string);
+                return Ok(());
+            }
+            (
+                "java/lang/String",
+                "valueOf",
+                descriptor @ ("(I)Ljava/lang/String;"
+                | "(J)Ljava/lang/String;"
+                | "(F)Ljava/lang/String;"
+                | "(D)Ljava/lang/String;"
+                | "(C)Ljava/lang/String;"
+                | "(Z)Ljava/lang/String;"
+                | "(Ljava/lang/Object;)Ljava/lang/String;"),
+            ) => {
+                let kind = descriptor.chars().nth(1).unwrap_or('L');
+                let value = self.pop().unwrap_or(Value::NULL);
+                let string = if kind == 'L' {
+                    self.string_value(value, at_pc)?
+                } else {
+                    self.heap.value_string(value, kind)
+                };
+                let string = self.heap.intern(&string);
+                self.push(string);
+                return Ok(());
+            }
+            (
+                "java/lang/StringBuilder",
+                "append",
+                "(Ljava/lang/Object;)Ljava/lang/StringBuilder;",
+            ) => {
+                // Objects are appended as the string `toString` returns.
+                let value = self.pop().unwrap_or(Value::NULL);
+                let string = self.string_value(value, at_pc)?;
+                let string = self.heap.intern(&string);
+                let receiver = self.pop().unwrap_or(Value::NULL);
+                let builder = collections::call(
+                    &mut self.heap,
+                    "java/lang/StringBuilder",
+                    "append",
+                    "(Ljava/lang/String;)Ljava/lang/StringBuilder;",
+                    &[receiver, string],
+                )
+                .map_err(|kind| RuntimeError { kind })?;
+                if let Some(builder) = builder {
+                    self.push(builder);
+                }
+                return Ok(());
+            }
+            (_, "getClass", "()Ljava/lang/Class;") => {
+                let object = self.pop().and_then(|value| self.heap.get(value));
+                if let Some(name) = object.map(Object::class_name) {
+                    let class = self.heap.class_object(&name);
+                    self.push(class);
+                }
+                return Ok(());
+            }
+            (
+                "java/lang/Class",
+                method @ ("getName" | "getSimpleName" | "isArray"
+                | "isPrimitive"),
+                "()Ljava/lang/String;" | "()Z",
+            ) => {
+                let method = method.to_string();
+                let Some(Object::Class(class)) =
+                    self.pop().and_then(|value| self.heap.get(value)).cloned()
+                else {
+                    return Ok(());
+                };
+                let value = match method.as_str() {
+                    "getName" => self.heap.intern(&heap::java_name(&class)),
+                    "getSimpleName" => {
+                        self.heap.intern(&heap::simple_name(&class))
+                    }
+                    "isArray" => Value::Int(i32::from(class.starts_with('['))),
+                    _ => Value::Int(i32::from(heap::is_primitive(&class))),
+                };
+                self.push(value);
+                return Ok(());
+            }
+            (
+                "java/lang/invoke/MethodHandle",
+                method @ ("invokeExact" | "invoke"),
+                descriptor,
+            ) => {
+                let exact = method == "invokeExact";
+                let descriptor = descriptor.to_string();
+                return self.invoke_handle(&descriptor, exact, at_pc);
+            }
+            ("java/io/InputStream", "read", "()I") => {
+                let value = self.read_stdin();
+                self.push(Value::Int(value));
+                return Ok(());
+            }
+            ("java/lang/System", "lineSeparator", "()Ljava/lang/String;") => {
+                let separator = self
+                    .properties
+                    .get("line.separator")
+                    .cloned()
+                    .unwrap_or_default();
+                let separator = self.heap.intern(&separator);
+                self.push(separator);
+                return Ok(());
+            }
+            (
+                "java/lang/System",
+                method @ ("getProperty" | "setProperty" | "clearProperty"
+                | "getenv"),
+                descriptor @ ("(Ljava/lang/String;)Ljava/lang/String;"
+                | "(Ljava/lang/String;Ljava/lang/String;)Ljava/lang/String;"),
+            ) => {
+                let method = method.to_string();
+                let arity = heap::parameter_types(descriptor)
+                    .map_or(1, |(params, _)| params.len());
+                let argument =
+                    (arity == 2).then(|| self.pop().unwrap_or(Value::NULL));
+                let key = self.pop().unwrap_or(Value::NULL);
+                let Some(key) = self.heap.string(key).map(str::to_string)
+                else {
+                    return Err(RuntimeError {
+                        kind: RuntimeErrorKind::NullPointer(
+                            OPCode::InvokeStatic,
+                        ),
+                    });
+                };
+                let argument = argument.map(|value| {
+                    (value, self.heap.string(value).map(str::to_string))
+                });
+                let value = match (method.as_str(), argument) {
+                    ("getenv", _) => std::env::var(&key).ok(),
+                    ("clearProperty", _) => self.properties.remove(&key),
+                    ("setProperty", Some((_, Some(value)))) => {
+                        self.properties.insert(key, value)
+                    }
+                    ("setProperty", _) => {
+                        return Err(RuntimeError {
+                            kind: RuntimeErrorKind::NullPointer(
+                                OPCode::InvokeStatic,
+                            ),
+                        })
+                    }
+                    // The default is returned as is.
+                    (_, Some((default, _))) => {
+                        match self.properties.get(&key) {
+                            Some(value) => Some(value.clone()),
+                            None => {
+                                self.push(default);
+                                return Ok(());
+                            }
+                        }
+                    }
+                    (_, None) => self.properties.get(&key).cloned(),
+                };
+                let value =
+                    value.map_or(Value::NULL, |value| self.heap.intern(&value));
+                self.push(value);
+                return Ok(());
+            }
+            (class, name, descriptor)
+                if method_index < 0 && collections::supports(class) =>
+            {
+                let (params, ret) =
+                    heap::parameter_types(descriptor).ok_or(RuntimeError {
+                        kind: RuntimeErrorKind::InvalidValue,
+                    })?;
+                let arity = params.len()
+                    + usize::from(!collections::is_static(class, name));
+                let frame = self.frames.last_mut().unwrap();
+                let args = frame
+                    .stack
+                    .split_off(frame.stack.len().saturating_sub(arity));
+                let value = collections::call(
+                    &mut self.heap,
+                    class,
+                    name,
+                    descriptor,
+                    &args,
+                )
+                .map_err(|kind| RuntimeError { kind })?;
+                if let (Some(value), false) = (value, ret == "V") {
+                    self.push(value);
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+        let native = self
+            .natives
+            .get(target.name)
+            .map(|(arity, ret, _)| (*arity, *ret));
+        if let Some((arity, ret)) = native {
+            let frame = self.frames.last_mut().unwrap();
+            let args = frame
+                .stack
+                .split_off(frame.stack.len().saturating_sub(arity));
+            for hook in &mut self.enter_hooks {
+                hook(target.name, target.descriptor, &args);
+            }
+            let (_, _, native) = self.natives.get_mut(target.name).unwrap();
+            // Results are converted to the declared return type and dropped
+            // for void methods.
+            let value = native(&args).and_then(|value| value.convert(ret));
+            if let (Some(value), Some(frame)) = (value, self.frames.last_mut())
+            {
+                frame.stack.push(value);
+            }
+            for hook in &mut self.exit_hooks {
+                hook(target.name, target.descriptor, value);
+            }
+            return Ok(());
+        }
+        let method = target.to_string();
+        let Ok(method_index) = usize::try_from(method_index) else {
+            // `Program::link` leaves missing methods and conflicting
+            // defaults to the calls that reach them.
+            let conflict =
+                self.program.class_index(target.class).is_some_and(|class| {
+                    self.program
+                        .resolve_method(class, target.name, target.descriptor)
+                        .is_err()
+                });
+            let kind = if conflict {
+                RuntimeErrorKind::IncompatibleClassChange { method }
+            } else {
+                RuntimeErrorKind::UnknownMethod { method }
+            };
+            return Err(RuntimeError { kind });
+        };
+        if self.program.code(method_index).is_empty() {
+            // Abstract methods and natives that weren't registered have no
+            // code to run.
+            let kind = if self.program.methods[method_index].access_flags
+                & ACC_ABSTRACT
+                != 0
+            {
+                RuntimeErrorKind::AbstractMethod { method }
+            } else {
+                RuntimeErrorKind::UnknownMethod { method }
+            };
+            return Err(RuntimeError { kind });
+        }
+        self.invoke(method_index, at_pc)
+    }
+
+    /// Returns the method an `invokevirtual` or `invokeinterface` of
+    /// `symbol` runs, the override declared for the receiver's class when
+    /// it's an instance of a program class, `method_index` otherwise. The
+    /// override is memoized by `Program::dispatch`.
+    fn dispatch(&mut self, method_index: i32, symbol: i32) -> i32 {
+        let Some((id, arguments)) = usize::try_from(symbol)
+            .ok()
+            .and_then(|id| Some((id, self.program.symbol(id)?.arguments)))
+        else {
+            return method_index;
+        };
+        let receiver = self.frames.last().and_then(|frame| {
+            let at = frame.stack.len().checked_sub(arguments + 1)?;
+            Some(frame.stack[at])
+        });
+        let Some(Object::Instance { class, .. }) =
+            receiver.and_then(|receiver| self.heap.get(receiver))
+        else {
+            return method_index;
+        };
+        self.program
+            .class_index(class)
+            .and_then(|class| self.program.dispatch(class, id))
+            .map_or(method_index, |method| method as i32)
+    }
+
+    /// Returns the slot of the field the field symbol `symbol` refers to in
+    /// `object` when it's an instance of a program class, memoized by
+    /// `Program::field_slot`.
+    fn field_slot(&mut self, object: Value, symbol: usize) -> Option<usize> {
+        let Some(Object::Instance { class, .. }) = self.heap.get(object) else {
+            return None;
+        };
+        self.program
+            .class_index(class)
+            .and_then(|class| self.program.field_slot(class, symbol))
+    }
+
+    /// Returns the field the field symbol `symbol` refers to of `object`,
+    /// `None` if `object` isn't an instance. Instances of program classes
+    /// are read at the field's slot, other objects by name.
+    fn get_field(&mut self, object: Value, symbol: usize) -> Option<Value> {
+        if let Some(slot) = self.field_slot(object, symbol) {
+            return self.heap.field_at(object, slot);
+        }
+        let field = self.program.symbol(symbol)?;
+        self.heap.field(object, &field.name, &field.descriptor)
+    }
+
+    /// Store `value` in the field the field symbol `symbol` refers to of
+    /// `object`, see `get_field`. Returns false if `object` isn't an
+    /// instance.
+    fn put_field(
+        &mut self,
+        object: Value,
+        symbol: usize,
+        value: Value,
+    ) -> bool {
+        if let Some(slot) = self.field_slot(object, symbol) {
+            return self.heap.set_field_at(object, slot, value);
+        }
+        self.program.symbol(symbol).is_some_and(|field| {
+            self.heap.set_field(object, &field.name, value)
+        })
+    }
+
+    /// Returns true if the class `class` is `target` or one of its
+    /// subclasses. The superclasses of program classes and of the library
+    /// throwables are known, the others end the search.
+    fn is_subclass(&self, class: &str, target: &str) -> bool {
+        let mut current = Some(class);
+        while let Some(class) = current {
+            if class == target {
+                return true;
+            }
+            current = match self.program.class_index(class) {
+                Some(index) => self.program.superclass(index),
+                None => heap::throwable_superclass(class),
+            };
+        }
+        false
+    }
+
+    /// Allocate a throwable of class `class` with the message `message`,
+    /// fields declared by program classes are laid out first.
+    fn new_throwable(&mut self, class: &str, message: Option<&str>) -> Value {
+        let fields = self
+            .program
+            .class_index(class)
+            .map(|index| self.program.instance_fields(index))
+            .unwrap_or_default();
+        self.heap.new_throwable(class, &fields, message)
+    }
+
+    /// Allocate an uninitialized object of class `class` like `new`, the
+    /// class is initialized first. `at_pc` is the instruction allocating it.
+    fn new_object(
+        &mut self,
+        class: &str,
+        at_pc: ProgramCounter,
+    ) -> Result<Value, RuntimeError> {
+        if let Some(index) = self.program.class_index(class) {
+            self.initialize(index, at_pc)?;
+        }
+        if self.is_subclass(class, THROWABLE) {
+            Ok(self.new_throwable(class, None))
+        } else if let Some(index) = self.program.class_index(class) {
+            let fields = self.program.instance_fields(index);
+            Ok(self.heap.new_instance(class, &fields))
+        } else if let Some(object) = collections::new_object(class) {
+            Ok(self.heap.alloc(object))
+        } else {
+            Err(RuntimeError {
+                kind: RuntimeErrorKind::UnsupportedClass {
+                    class: class.to_string(),
+                },
+            })
+        }
+    }
+
+    /// Initialize the class at `class` on its first use like a JVM does :
+    /// its superclass is initialized first, then its static initializer
+    /// runs. Classes being initialized can be used by their initializers.
+    fn initialize(
+        &mut self,
+        class: usize,
+        at_pc: ProgramCounter,
+    ) -> Result<(), RuntimeError> {
+        if self.program.class_state(class) != Some(ClassState::Uninitialized) {
+            return Ok(());
+        }
+        self.program
+            .set_class_state(class, ClassState::Initializing);
+        let superclass = self
+            .program
+            .superclass(class)
+            .and_then(|name| self.program.class_index(name));
+        if let Some(superclass) = superclass {
+            self.initialize(superclass, at_pc)?;
+        }
+        if let Some(clinit) = self.program.clinit(class) {
+            self.call_method(clinit, &[], at_pc)?;
+        }
+        self.program.set_class_state(class, ClassState::Initialized);
+        Ok(())
+    }
+
+    /// Initialize the class declaring the entry point before its first
+    /// instruction runs, `call_static` may have run other code before.
+    fn initialize_entry(&mut self) -> Result<(), RuntimeError> {
+        let Some(pc) = self.frames.first().map(|frame| frame.pc) else {
+            return Ok(());
+        };
+        match self.program.methods.get(pc.method_index) {
+            Some(method) => self.initialize(method.class, pc),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the value of the static field at `field`, fields that weren't
+    /// stored to hold their `ConstantValue` or the default value of the
+    /// descriptor of the field symbol `symbol`.
+    fn static_value(&mut self, field: usize, symbol: usize) -> Value {
+        if let Some(value) = self.statics.get(&field) {
+            return *value;
+        }
+        if let Some(constant) = self.program.constant_value(field) {
+            return self.materialize(constant);
+        }
+        self.program.symbol(symbol).map_or(Value::NULL, |symbol| {
+            heap::default_value(&symbol.descriptor)
+        })
+    }
+
+    /// Throw `err`, raised by the instruction the run faulted at, to the
+    /// handlers of the frames from `floor` up. Returns it back if none
+    /// catches it.
+    fn raise(
+        &mut self,
+        err: RuntimeError,
+        floor: usize,
+    ) -> Result<(), RuntimeError> {
+        let pc = self.fault.unwrap_or_default();
+        if self.catch(&err, pc, floor) {
+            Ok(())
+        } else {
+            Err(err)
+        }
+    }
+
+    /// Look a handler for `err`, raised by the instruction at `pc`, up in
+    /// the frames from the innermost down to the frame at `floor`. Returns
+    /// true if one catches it : the frames above the handler's are dropped,
+    /// its operand stack is replaced by the exception and it resumes at the
+    /// handler. Failures without a Java counterpart aren't caught, runtime
+    /// failures that have one are turned into exceptions.
+    fn catch(
+        &mut self,
+        err: &RuntimeError,
+        pc: ProgramCounter,
+        floor: usize,
+    ) -> bool {
+        let Some(class) = err.java_error() else {
+            return false;
+        };
+        let innermost = self.frames.len().saturating_sub(1);
+        let handler = (floor..self.frames.len()).rev().find_map(|depth| {
+            let frame = &self.frames[depth];
+            let method_index = frame.method_index();
+            // Callers already moved past the invoke instruction.
+            let offset = if depth == innermost {
+                pc.get_instruction_index()
+            } else {
+                frame.instruction_index().saturating_sub(1)
+            };
+            self.program
+                .exception_table(method_index)
+                .iter()
+                .filter(|entry| {
+                    (usize::from(entry.start_pc())..usize::from(entry.end_pc()))
+                        .contains(&offset)
+                })
+                .find(|entry| {
+                    entry.catch_type() == 0
+                        || match self
+                            .program
+                            .constant(method_index, entry.catch_type().into())
+                        {
+                            Some(RuntimeConstant::Class { name, .. }) => {
+                                self.program.string(name).is_some_and(|name| {
+                                    self.is_subclass(class, name)
+                                })
+                            }
+                            _ => false,
+                        }
+                })
+                .map(|entry| (depth, usize::from(entry.handler_pc())))
+        });
+        let Some((depth, handler)) = handler else {
+            return false;
+        };
+        let exception = match (err.kind(), self.thrown.take()) {
+            (RuntimeErrorKind::Thrown { .. }, Some(exception)) => exception,
+            _ => {
+                let class = class.to_string();
+                self.new_throwable(&class, Some(&err.to_string()))
+            }
+        };
+        if self.recorder.is_recording() {
+            self.recorder.abort();
+            let header = self.recorder.loop_header();
+            self.emit(|| Event::RecordingAborted { pc: header });
+        }
+        // Frames the exception unwinds exit without a value.
+        while self.frames.len() > depth + 1 {
+            let frame = self.frames.pop().unwrap();
+            if !self.exit_hooks.is_empty() {
+                self.exit_method(frame.method_index(), None);
+            }
+        }
+        let frame = self.frames.last_mut().unwrap();
+        frame.stack.clear();
+        frame.stack.push(exception);
+        frame.pc.instruction_index = handler;
+        self.fault = None;
+        true
+    }
+
+    /// Returns what `String.valueOf(value)` gives, instances of program
+    /// classes that override `toString` have it called and the library's
+    /// implementation formats everything else.
+    fn string_value(
+        &mut self,
+        value: Value,
+        at_pc: ProgramCounter,
+    ) -> Result<String, RuntimeError> {
+        let method = match self.heap.get(value) {
+            Some(Object::Instance { class, .. }) => {
+                self.program.class_index(class).and_then(|class| {
+                    self.program
+                        .resolve_method(
+                            class,
+                            "toString",
+                            "()Ljava/lang/String;",
+                        )
+                        .ok()
+                        .flatten()
+                })
+            }
+            _ => None,
+        };
+        let Some(method) =
+            method.filter(|method| !self.program.code(*method).is_empty())
+        else {
+            return Ok(self.heap.java_string(value));
+        };
+        let string = self.call_method(method, &[value], at_pc)?;
+        Ok(self.heap.java_string(string.unwrap_or(Value::NULL)))
+    }
+
+    /// Run the program method at `method_index` on `args` to completion
+    /// and return its result, used by library methods that call back into
+    /// the program. The frames of a call that fails are unwound so that the
+    /// error is reported at the library call.
+    fn call_method(
+        &mut self,
+        method_index: usize,
+        args: &[Value],
+        at_pc: ProgramCounter,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let depth = self.frames.len();
+        for arg in args {
+            self.push(*arg);
+        }
+        self.invoke(method_index, at_pc)?;
+        let mut result = Ok(());
+        while self.frames.len() > depth && result.is_ok() {
+            result = self.step_above(depth);
+        }
+        if let Err(err) = result {
+            self.frames.truncate(depth);
+            return Err(err);
+        }
+        let returns =
+            !self.program.methods[method_index].descriptor.ends_with('V');
+        Ok(if returns { self.pop() } else { None })
+    }
+
+    /// Call the method handle below the arguments of a call site of type
+    /// `descriptor`. `invokeExact` requires the handle's type to match the
+    /// call site's, `invoke` converts primitive arguments to the handle's
+    /// parameter types.
+    fn invoke_handle(
+        &mut self,
+        descriptor: &str,
+        exact: bool,
+        at_pc: ProgramCounter,
+    ) -> Result<(), RuntimeError> {
+        let invalid = || RuntimeError {
+            kind: RuntimeErrorKind::InvalidValue,
+        };
+        let (params, _) =
+            heap::parameter_types(descriptor).ok_or_else(invalid)?;
+        let frame = self.frames.last_mut().ok_or_else(invalid)?;
+        let receiver = frame
+            .stack
+            .len()
+            .checked_sub(params.len() + 1)
+            .ok_or_else(invalid)?;
+        let handle = frame.stack.remove(receiver);
+        let Some(Object::MethodHandle {
+            kind,
+            class,
+            name,
+            descriptor: member,
+        }) = self.heap.get(handle).cloned()
+        else {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::NullPointer(OPCode::InvokeVirtual),
+            });
+        };
+        let handle_type = heap::handle_type(kind, &class, &member);
+        let wrong_type = || RuntimeError {
+            kind: RuntimeErrorKind::WrongMethodType {
+                expected: handle_type.clone(),
+                got: descriptor.to_string(),
+            },
+        };
+        if exact && handle_type != descriptor {
+            return Err(wrong_type());
+        }
+        let (targets, target_ret) =
+            heap::parameter_types(&handle_type).ok_or_else(invalid)?;
+        let (_, ret) = heap::parameter_types(descriptor).ok_or_else(invalid)?;
+        if targets.len() != params.len() || target_ret != ret {
+            return Err(wrong_type());
+        }
+        // Widen primitive arguments in place, references are passed as is.
+        let frame = self.frames.last_mut().ok_or_else(invalid)?;
+        let args = frame.stack.len() - params.len();
+        for ((value, param), target) in
+            frame.stack[args..].iter_mut().zip(params).zip(targets)
+        {
+            if param == target {
+                continue;
+            }
+            let kind = target.chars().next().unwrap_or('V');
+            match value.convert(kind) {
+                Some(converted) if widens(param, target) => *value = converted,
+                _ => return Err(wrong_type()),
+            }
+        }
+        // Handles are taken on members the program references, the access
+        // goes through the same symbol.
+        let symbol_kind = if kind <= REF_PUT_STATIC {
+            SymbolKind::Field
+        } else {
+            SymbolKind::Method
+        };
+        let symbol = self
+            .program
+            .find_symbol(symbol_kind, &class, &name, &member)
+            .ok_or_else(|| RuntimeError {
+                kind: RuntimeErrorKind::UnknownMethod {
+                    method: format!("{class}.{name}:{member}"),
+                },
+            })?;
+        let target =
+            self.program.symbol(symbol).and_then(|symbol| symbol.target);
+        match kind {
+            REF_GET_FIELD | REF_PUT_FIELD => {
+                let value = (kind == REF_PUT_FIELD)
+                    .then(|| self.pop().unwrap_or(Value::NULL));
+                let object = self.pop().unwrap_or(Value::NULL);
+                let null = || RuntimeError {
+                    kind: RuntimeErrorKind::NullPointer(if value.is_some() {
+                        OPCode::PutField
+                    } else {
+                        OPCode::GetField
+                    }),
+                };
+                if let Some(value) = value {
+                    if !self.put_field(object, symbol, value) {
+                        return Err(null());
+                    }
+                } else {
+                    let value =
+                        self.get_field(object, symbol).ok_or_else(null)?;
+                    self.push(value);
+                }
+                Ok(())
+            }
+            REF_GET_STATIC | REF_PUT_STATIC => {
+                let put = kind == REF_PUT_STATIC;
+                // Library statics aren't modelled, like `getstatic` does.
+                let Some(field) = target else {
+                    if put {
+                        self.pop();
                     } else {
-                        Err(RuntimeError {
-                            kind: RuntimeErrorKind::InvalidValue,
-                        })
+                        self.push(heap::default_value(&member));
                     }
+                    return Ok(());
+                };
+                if let Some(class) = self.program.field_class(field) {
+                    self.initialize(class, at_pc)?;
                 }
-                // Void return
-                OPCode::Return => {
-                    self.frames.pop();
-                    Ok(())
-                }
-                // Function calls.
-                OPCode::InvokeStatic => {
-                    let name_index = match &inst.operands {
-                        Some(params) => match params.get(0) {
-                            Some(Value::Int(index)) => index,
-                            _ => panic!(
-                                "InvokeStatic expected integer parameter"
-                            ),
-                        },
-                        _ => panic!("InvokeStatic expected parameters"),
-                    };
-                    self.invoke(*name_index as usize);
-                    Ok(())
-                }
-                // Currently only supports System.out.println.
-                OPCode::InvokeVirtual => {
-                    let value = self.pop();
-                    println!("System.out.println : {value:?}");
-                    Ok(())
+                if put {
+                    let value = self.pop().unwrap_or(Value::NULL);
+                    self.statics.insert(field, value);
+                } else {
+                    let value = self.static_value(field, symbol);
+                    self.push(value);
                 }
-                OPCode::GetStatic | OPCode::Nop | OPCode::Dup => Ok(()),
-                _ => todo!(),
+                Ok(())
             }
-        } else {
-            Ok(println!("Reached last frame...leaving"))
+            REF_NEW_INVOKE_SPECIAL => {
+                // The constructor gets the new object below its arguments
+                // and leaves the copy under it as the result.
+                let object = self.new_object(&class, at_pc)?;
+                let frame = self.frames.last_mut().ok_or_else(invalid)?;
+                frame.stack.splice(args..args, [object, object]);
+                let target = target.map_or(-1, |index| index as i32);
+                self.call(target, symbol as i32, at_pc)
+            }
+            REF_INVOKE_STATIC | REF_INVOKE_VIRTUAL | REF_INVOKE_SPECIAL
+            | REF_INVOKE_INTERFACE => {
+                let target = target.map_or(-1, |index| index as i32);
+                self.call(target, symbol as i32, at_pc)
+            }
+            kind => Err(RuntimeError {
+                kind: RuntimeErrorKind::UnsupportedMethodHandle { kind },
+            }),
         }
     }
 
-    /// Returns the opcode parameter encoded as two `u8` values in the bytecode
-    /// as an `i32`.
-    const fn encode_arg(lo: u8, hi: u8) -> i32 {
-        let arg = (lo as i16) << 8 | hi as i16;
-        arg as i32
-    }
-
-    /// Returns the next bytecode value in the current method.
-    fn next(&mut self, frame: &mut Frame) -> u8 {
-        let method_index = frame.method_index();
-        let code = self.program.code(method_index);
-        let bc = code[frame.instruction_index()];
-        frame.max_locals = self.program.max_locals(method_index);
-        frame.inc_instruction_index();
-        bc
+    /// Read a byte from the standard input like `InputStream.read`, returns
+    /// -1 at the end of the input. Read errors end the input as well.
+    fn read_stdin(&mut self) -> i32 {
+        let mut byte = [0];
+        loop {
+            match self.stdin.read(&mut byte) {
+                Ok(1) => return i32::from(byte[0]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                _ => return -1,
+            }
+        }
     }
 
     /// Returns the relative offset from the mnemonics parameters list.
     fn get_relative_offset(params: &[Value]) -> i32 {
-        match params.get(0) {
+        match params.first() {
             Some(Value::Int(v)) => v - 3,
             _ => panic!("Expected parameter to be of type Value::Int"),
         }
     }
 
     /// Invoke a function by creating a new stack frame, building the locals
-    /// and pushing the new frame into the runtime stack.
-    fn invoke(&mut self, method_name_index: usize) {
+    /// and pushing the new frame into the runtime stack. `at_pc` is the
+    /// instruction making the call.
+    fn invoke(
+        &mut self,
+        method_name_index: usize,
+        at_pc: ProgramCounter,
+    ) -> Result<(), RuntimeError> {
+        if let Some(limit) = self.limits.stack_depth {
+            if self.frames.len() >= limit {
+                return Err(
+                    self.limit_exceeded(LimitKind::StackDepth(limit), at_pc)
+                );
+            }
+        }
         let method = &self.program.methods[method_name_index];
         let max_locals = method.max_locals;
         let stack = vec![];
-        let mut locals = HashMap::new();
+        let mut locals = Locals::new(max_locals.into());
         let arg_types = method.arg_types.clone();
-        let mut key = arg_types.iter().map(|arg_type| arg_type.size()).sum();
+        // Instance methods find their receiver in local 0.
+        let receiver = usize::from(method.access_flags & ACC_STATIC == 0);
+        let mut key = receiver
+            + arg_types
+                .iter()
+                .map(|arg_type| arg_type.size())
+                .sum::<usize>();
+        // Arguments are only collected for the enter hooks.
+        let hooked = !self.enter_hooks.is_empty();
+        let mut args = vec![];
 
         for arg_type in arg_types.iter().rev() {
             key -= arg_type.size();
             let val = self.pop().unwrap();
-            locals.insert(key, val);
+            if hooked {
+                args.push(val);
+            }
+            locals.insert(key, val)?;
+        }
+        if receiver == 1 {
+            key -= 1;
+            let val = self.pop().unwrap();
+            if hooked {
+                args.push(val);
+            }
+            locals.insert(key, val)?;
         }
         assert_eq!(key, 0);
         let pc = ProgramCounter {
@@ -1179,109 +4821,94 @@ impl Runtime {
             max_locals,
         };
         self.frames.push(frame);
+        if hooked {
+            args.reverse();
+            self.enter_method(method_name_index, &args);
+        }
+        Ok(())
+    }
+
+    /// Call the instruments before the instruction `inst` at `pc` executes.
+    fn before_instruction(&mut self, pc: ProgramCounter, inst: &Instruction) {
+        let Some(frame) = self.frames.last_mut() else {
+            return;
+        };
+        // Fetching advanced the program counter, instruments see the frame
+        // as it was before.
+        let next = std::mem::replace(&mut frame.pc, pc);
+        self.coverage.before_instruction(frame, inst);
+        self.exec_log.before_instruction(frame, inst);
+        self.instrument.before_instruction(frame, inst);
+        frame.pc = next;
     }
 
-    /// Returns the next instruction to execute.
+    /// Returns the next instruction to execute, instructions are decoded
+    /// when the program is built, see `InstructionStream`.
     fn fetch(&mut self) -> Instruction {
-        // Ugly hack, since we can't borrow frame as mutable more than once
-        // we pop it out, do what we want then push it back.
-        let current_frame = self.frames.pop();
-        match current_frame {
-            Some(mut frame) => {
-                let mnemonic = OPCode::from(self.next(&mut frame));
-                let params = match mnemonic {
-                    OPCode::SiPush
-                    | OPCode::IfEq
-                    | OPCode::IfNe
-                    | OPCode::IfLt
-                    | OPCode::IfLe
-                    | OPCode::IfGt
-                    | OPCode::IfGe
-                    | OPCode::IfICmpEq
-                    | OPCode::IfICmpNe
-                    | OPCode::IfICmpLt
-                    | OPCode::IfICmpLe
-                    | OPCode::IfICmpGt
-                    | OPCode::IfICmpGe
-                    | OPCode::Goto => {
-                        let lo = self.next(&mut frame);
-                        let hi = self.next(&mut frame);
-                        let param = Self::encode_arg(lo, hi);
-                        Some(vec![Value::Int(param)])
-                    }
-                    OPCode::InvokeSpecial
-                    | OPCode::GetStatic
-                    | OPCode::InvokeVirtual
-                    | OPCode::IInc => {
-                        let first = i32::from(self.next(&mut frame));
-                        let second = i32::from(self.next(&mut frame));
-                        Some(vec![Value::Int(first), Value::Int(second)])
-                    }
-                    OPCode::BiPush
-                    | OPCode::ILoad
-                    | OPCode::FLoad
-                    | OPCode::LLoad
-                    | OPCode::DLoad
-                    | OPCode::IStore
-                    | OPCode::FStore
-                    | OPCode::LStore
-                    | OPCode::DStore => {
-                        let arg = i32::from(self.next(&mut frame));
-                        Some(vec![Value::Int(arg)])
-                    }
-                    OPCode::InvokeStatic => {
-                        let lo = self.next(&mut frame);
-                        let hi = self.next(&mut frame);
-                        let method_ref_index =
-                            Self::encode_arg(lo, hi) as usize;
-                        let method_name_index =
-                            self.program.find_method(method_ref_index);
-                        Some(vec![Value::Int(method_name_index)])
-                    }
-                    OPCode::Ldc2W => {
-                        let lo = self.next(&mut frame);
-                        let hi = self.next(&mut frame);
-                        let index = Self::encode_arg(lo, hi);
-                        let entry = &self.program.constant_pool[index as usize];
-
-                        match entry {
-                            CPInfo::ConstantDouble { hi_bytes, lo_bytes } => {
-                                let result = ((*hi_bytes as i64) << 32)
-                                    + (*lo_bytes as i64);
-                                Some(vec![Value::Double(result as f64)])
-                            }
-                            CPInfo::ConstantLong { hi_bytes, lo_bytes } => {
-                                let result = ((*hi_bytes as i64) << 32)
-                                    + (*lo_bytes as i64);
-                                Some(vec![Value::Long(result)])
-                            }
-                            _ => panic!("unexpected entry in constant pool"),
-                        }
-                    }
-                    OPCode::Ldc => {
-                        let index = self.next(&mut frame);
-                        let entry = &self.program.constant_pool[index as usize];
+        let Some(frame) = self.frames.last_mut() else {
+            panic!("no next instruction");
+        };
+        let method_index = frame.method_index();
+        let Some((inst, next)) = self
+            .program
+            .instructions(method_index)
+            .get(frame.instruction_index())
+        else {
+            panic!("no instruction at {}", frame.pc);
+        };
+        let mut inst = inst.clone();
+        frame.pc.instruction_index = next;
+        if matches!(inst.mnemonic, OPCode::Ldc | OPCode::LdcW | OPCode::Ldc2W) {
+            let Some(Value::Int(index)) = inst.nth(0) else {
+                unreachable!("ldc is decoded with its constant pool index");
+            };
+            inst.operands =
+                Some(vec![self.load_constant(method_index, index as usize)]);
+        }
+        inst
+    }
 
-                        match entry {
-                            CPInfo::ConstantFloat { bytes } => {
-                                Some(vec![Value::Float(*bytes as f32)])
-                            }
-                            CPInfo::ConstantInteger { bytes } => {
-                                Some(vec![Value::Int(*bytes as i32)])
-                            }
-                            _ => panic!("unexpected entry in constant pool"),
-                        }
-                    }
-                    _ => None,
-                };
-                self.frames.push(frame);
+    /// Returns the value `ldc` pushes for the constant at `index` of the
+    /// constant pool of the method at `method_index`.
+    fn load_constant(&mut self, method_index: usize, index: usize) -> Value {
+        // Constants are decoded when the program is built.
+        match self.program.constant(method_index, index) {
+            Some(constant) => self.materialize(constant),
+            None => panic!("unexpected entry in constant pool"),
+        }
+    }
 
-                Instruction {
-                    mnemonic,
-                    operands: params,
-                }
+    /// Returns the value of the runtime constant `constant`.
+    fn materialize(&mut self, constant: RuntimeConstant) -> Value {
+        match constant {
+            RuntimeConstant::Int(v) => Value::Int(v),
+            RuntimeConstant::Long(v) => Value::Long(v),
+            RuntimeConstant::Float(v) => Value::Float(v),
+            RuntimeConstant::Double(v) => Value::Double(v),
+            // Strings and classes are materialized on first
+            // use and shared afterwards.
+            RuntimeConstant::String(id) => {
+                let string = self.program.string(id).unwrap();
+                self.heap.intern(string)
+            }
+            RuntimeConstant::Class { name, .. } => {
+                let name = self.program.string(name).unwrap();
+                self.heap.class_object(name)
+            }
+            RuntimeConstant::MethodType(descriptor) => {
+                let descriptor = self.program.string(descriptor).unwrap();
+                self.heap.method_type(descriptor)
             }
-            None => panic!("no next instruction"),
+            RuntimeConstant::MethodHandle { kind, symbol } => {
+                let symbol = self.program.symbol(symbol).unwrap();
+                self.heap.method_handle(
+                    kind,
+                    &symbol.class,
+                    &symbol.name,
+                    &symbol.descriptor,
+                )
+            }
+            _ => panic!("unexpected entry in constant pool"),
         }
     }
 }
@@ -1293,6 +4920,7 @@ mod tests {
     use crate::jvm::JVMParser;
     use crate::program::Program;
     use std::env;
+    use std::mem;
     use std::path::Path;
 
     #[test]
@@ -1319,6 +4947,29 @@ mod tests {
         }
     }
 
+    #[test]
+    fn can_convert_values() {
+        assert_eq!(Value::from(7), Value::Int(7));
+        assert_eq!(Value::from(7i64), Value::Long(7));
+        assert_eq!(Value::from(0.5f64), Value::Double(0.5));
+        assert_eq!(i64::try_from(Value::Long(-3)), Ok(-3));
+        assert_eq!(
+            f32::try_from(Value::Int(1)),
+            Err(ValueTypeError {
+                expected: BaseTypeKind::Float,
+                got: Value::Int(1),
+            })
+        );
+
+        assert!(Value::Int(1).has_type('Z'));
+        assert!(!Value::Int(1).has_type('J'));
+        assert_eq!(Value::Int(300).convert('B'), Some(Value::Int(44)));
+        assert_eq!(Value::Int(-1).convert('C'), Some(Value::Int(65535)));
+        assert_eq!(Value::Long(5).convert('Z'), Some(Value::Int(1)));
+        assert_eq!(Value::Double(2.5).convert('I'), Some(Value::Int(2)));
+        assert_eq!(Value::Int(1).convert('V'), None);
+    }
+
     // Macro to generate unit tests for the runtime.
     macro_rules! test_runtime_case {
         ($name: ident, $test_files:expr, $expected:expr) => {
@@ -1381,4 +5032,1106 @@ mod tests {
         ["support/tests/MultiFuncCall.class"],
         Some(Value::Int(50))
     );
+
+    #[test]
+    fn calls_method_hooks() {
+        use std::sync::{Arc, Mutex};
+
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/tests/Factorial.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(crate::sink::SharedBuffer::new()));
+        let events = Arc::new(Mutex::new(vec![]));
+        let enters = events.clone();
+        runtime.on_method_enter(Box::new(move |name, descriptor, args| {
+            enters
+                .lock()
+                .unwrap()
+                .push(format!("enter {name}{descriptor} {args:?}"));
+        }));
+        let exits = events.clone();
+        runtime.on_method_exit(Box::new(move |name, _, value| {
+            exits.lock().unwrap().push(format!("exit {name} {value:?}"));
+        }));
+        runtime.run(false).unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "enter main([Ljava/lang/String;)V []",
+                "enter factorial(I)I [Int(12)]",
+                "exit factorial Some(Int(479001600))",
+                "exit main None",
+            ]
+        );
+    }
+
+    #[test]
+    fn exits_methods_unwound_by_exceptions() {
+        use std::sync::{Arc, Mutex};
+
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/exceptions/Unwind.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(stdout.clone()));
+        let exits = Arc::new(Mutex::new(vec![]));
+        let hook = exits.clone();
+        runtime.on_method_exit(Box::new(move |name, _, value| {
+            hook.lock().unwrap().push(format!("{name} {value:?}"));
+        }));
+        runtime.run(false).unwrap();
+        assert_eq!(stdout.to_string_lossy(), "-1\n");
+        assert_eq!(
+            *exits.lock().unwrap(),
+            vec!["divide None", "half None", "main None"]
+        );
+    }
+
+    #[test]
+    fn can_run_on_worker_thread() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/jit/Loop100.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(stdout.clone()));
+        let runtime = std::thread::spawn(move || {
+            runtime.run(true).unwrap();
+            runtime
+        })
+        .join()
+        .unwrap();
+        assert!(runtime.jit_errors().is_empty());
+        assert_eq!(stdout.to_string_lossy(), "5050\n");
+    }
+
+    #[test]
+    fn can_call_static_methods() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/tests/Factorial.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        let value = runtime
+            .call_static("Factorial", "factorial", "(I)I", &[Value::Int(10)])
+            .unwrap();
+        assert_eq!(value, Some(Value::Int(3_628_800)));
+        assert!(matches!(
+            runtime.call_static("Factorial", "factorial", "(J)I", &[]),
+            Err(Error::Link(LinkError {
+                kind: LinkErrorKind::DescriptorMismatch { .. },
+                ..
+            }))
+        ));
+        assert!(matches!(
+            runtime.call_static(
+                "Factorial",
+                "factorial",
+                "(I)I",
+                &[Value::Long(10)]
+            ),
+            Err(Error::Link(LinkError {
+                kind: LinkErrorKind::ArgumentType { index: 0, .. },
+                ..
+            }))
+        ));
+        assert!(matches!(
+            runtime.call_static("Other", "factorial", "(I)I", &[]),
+            Err(Error::Link(LinkError {
+                kind: LinkErrorKind::UnknownClass,
+                ..
+            }))
+        ));
+        // The runtime's own frames are untouched by calls.
+        runtime.set_stdout(Box::new(crate::sink::SharedBuffer::new()));
+        assert!(runtime.run(false).is_ok());
+        assert_eq!(runtime.top_return_value(), Some(Value::Int(479_001_600)));
+    }
+
+    #[test]
+    fn can_read_injected_stdin() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/stdin/SumDigits.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.set_stdin(Box::new(&b"12 34\n"[..]));
+        runtime.run(false).unwrap();
+        assert_eq!(stdout.to_string_lossy(), "10\n");
+    }
+
+    #[test]
+    fn can_call_overloaded_methods() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/overload/Overload.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.run(false).unwrap();
+        // (1 + 2) + 3 + 4 - (3 + 100)
+        assert_eq!(stdout.to_string_lossy(), "-93\n");
+    }
+
+    #[test]
+    fn can_call_linked_classes() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let dir = Path::new(&env_var).join("support/linking");
+        let class_files: Vec<_> = ["Main.class", "Ops.class"]
+            .iter()
+            .map(|name| {
+                JVMParser::parse(&read_class_file(&dir.join(name)).unwrap())
+                    .unwrap()
+            })
+            .collect();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::link(&class_files).unwrap());
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.run(false).unwrap();
+        assert_eq!(stdout.to_string_lossy(), "58\n");
+
+        // Without Ops the calls are left to natives.
+        let mut runtime = Runtime::new(Program::new(&class_files[0]));
+        runtime.set_stdout(Box::new(crate::sink::SharedBuffer::new()));
+        let err = runtime.run(false).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &RuntimeErrorKind::UnknownMethod {
+                method: "Ops.square(I)I".to_string()
+            }
+        );
+        assert_eq!(err.java_error(), Some("java/lang/NoSuchMethodError"));
+
+        // Calls landing on an abstract method fail instead of running it.
+        let mut program = Program::link(&class_files).unwrap();
+        let square = program.class_method("Ops", "square", "(I)I").unwrap();
+        program.set_code(square, vec![]);
+        program.methods[square].access_flags |= ACC_ABSTRACT;
+        let mut runtime = Runtime::new(program);
+        let err = runtime.run(false).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &RuntimeErrorKind::AbstractMethod {
+                method: "Ops.square(I)I".to_string()
+            }
+        );
+        assert_eq!(err.java_error(), Some("java/lang/AbstractMethodError"));
+
+        // Against a stale Ops the call to Ops.add throws a `NoSuchMethodError`
+        // the guest catches.
+        let stale: Vec<_> = ["Guarded.class", "stale/Ops.class"]
+            .iter()
+            .map(|name| {
+                JVMParser::parse(&read_class_file(&dir.join(name)).unwrap())
+                    .unwrap()
+            })
+            .collect();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::link(&stale).unwrap());
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.run(false).unwrap();
+        assert_eq!(stdout.to_string_lossy(), "9\n");
+
+        // Ops has no main, nothing runs.
+        let mut runtime = Runtime::new(Program::new(&class_files[1]));
+        let err = runtime.run(false).unwrap_err();
+        assert!(matches!(err.kind(), RuntimeErrorKind::NoEntryPoint { .. }));
+        assert_eq!(runtime.executed(), 0);
+    }
+
+    #[test]
+    fn call_static_initializes_the_class() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let dir = Path::new(&env_var).join("support/clinit");
+        let class_files: Vec<_> =
+            ["Order", "Base", "Middle", "Leaf", "Sibling"]
+                .iter()
+                .map(|name| {
+                    let path = dir.join(format!("{name}.class"));
+                    JVMParser::parse(&read_class_file(&path).unwrap()).unwrap()
+                })
+                .collect();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::link(&class_files).unwrap());
+        runtime.set_stdout(Box::new(stdout.clone()));
+        let value = runtime
+            .call_static("Sibling", "twice", "(I)I", &[Value::Int(4)])
+            .unwrap();
+        assert_eq!(value, Some(Value::Int(8)));
+        // Base and Sibling are initialized, the entry class isn't.
+        assert_eq!(stdout.to_string_lossy(), "Base\nSibling\n");
+
+        // The run still initializes the entry class, not the others again.
+        runtime.run(false).unwrap();
+        assert_eq!(
+            stdout.to_string_lossy(),
+            "Base\nSibling\nOrder\nmain\nLeaf\n7\n8\n1\n"
+        );
+    }
+
+    #[test]
+    fn resolves_default_conflicts_on_invoke() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let dir = Path::new(&env_var).join("support/defaults");
+        let link = |main: &str| {
+            let class_files: Vec<_> =
+                [main, "Greeter", "Polite", "Impl", "Custom", "Clash", "Loud"]
+                    .iter()
+                    .map(|name| {
+                        let path = dir.join(format!("{name}.class"));
+                        JVMParser::parse(&read_class_file(&path).unwrap())
+                            .unwrap()
+                    })
+                    .collect();
+            Program::link(&class_files).unwrap()
+        };
+        // Main.clash is never called, Clash.greet never has to resolve.
+        let mut runtime = Runtime::new(link("Main"));
+        runtime.run(false).unwrap();
+
+        // Calling it throws an `IncompatibleClassChangeError` the guest
+        // catches.
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(link("Guarded"));
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.run(false).unwrap();
+        assert_eq!(stdout.to_string_lossy(), "-1\n");
+    }
+
+    #[test]
+    fn initializes_classes_on_first_use() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let dir = Path::new(&env_var).join("support/clinit");
+        let class_files: Vec<_> =
+            ["Order", "Base", "Middle", "Leaf", "Sibling"]
+                .iter()
+                .map(|name| {
+                    let path = dir.join(format!("{name}.class"));
+                    JVMParser::parse(&read_class_file(&path).unwrap()).unwrap()
+                })
+                .collect();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::link(&class_files).unwrap());
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.run(false).unwrap();
+        // The main class first, superclasses before their subclasses and
+        // each initializer once.
+        assert_eq!(
+            stdout.to_string_lossy(),
+            "Order\nmain\nBase\nLeaf\n7\nSibling\n8\n1\n"
+        );
+        let program = &runtime.program;
+        assert_eq!(program.uninitialized().count(), 0);
+    }
+
+    #[test]
+    fn loads_wide_constants() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path =
+            Path::new(&env_var).join("support/integration/MEDouble.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.run(false).unwrap();
+        assert_eq!(stdout.to_string_lossy(), "11.190000000000001\n");
+    }
+
+    #[test]
+    fn can_reflect_on_classes() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/reflection/Names.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.run(false).unwrap();
+        assert_eq!(
+            stdout.to_string_lossy(),
+            "Names\njava.lang.String\n[Ljava.lang.String;\nString[]\n\
+             int[][]\ntrue\nfalse\njava.util.Map$Entry\nEntry\n"
+        );
+        // Every class has a single `Class` object.
+        let classes = runtime
+            .heap()
+            .objects()
+            .iter()
+            .filter(|object| matches!(object, Object::Class(_)))
+            .count();
+        assert_eq!(classes, 6);
+    }
+
+    #[test]
+    fn superinstructions_behave_like_their_instructions() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/tests/Loop.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let runs: Vec<_> = [true, false]
+            .into_iter()
+            .map(|fusion| {
+                let mut runtime = Runtime::new(Program::new(&class_file));
+                runtime.set_fusion(fusion);
+                runtime.run(false).unwrap();
+                // `i < 1000` is fused into the load at 6.
+                let branch = runtime
+                    .profiler
+                    .branch_profile(&ProgramCounter::new(1, 10));
+                assert_eq!(runtime.superinstructions.contains_key(&1), fusion);
+                (runtime.top_return_value(), runtime.executed(), branch)
+            })
+            .collect();
+        assert_eq!(runs[0], runs[1]);
+        assert_eq!(runs[0].0, Some(Value::Int(1000)));
+    }
+
+    #[test]
+    fn counts_loop_entries() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/tests/Loop.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        // The condition at 6 heads the loop.
+        let header = ProgramCounter::new(1, 6);
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.run(false).unwrap();
+        assert_eq!(runtime.loop_entries(header), 1000);
+        assert_eq!(runtime.loop_entries(ProgramCounter::new(1, 7)), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "jit")]
+    fn stops_counting_native_loops() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/tests/Loop.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let header = ProgramCounter::new(1, 6);
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_hot_threshold(10);
+        runtime.run(true).unwrap();
+        assert_eq!(runtime.top_return_value(), Some(Value::Int(1000)));
+        assert!(runtime.loop_entries(header) > 10);
+        assert!(runtime.loop_entries(header) < 1000);
+    }
+
+    #[test]
+    fn reads_system_properties() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/tests/Properties.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let mut options = RuntimeOptions::default();
+        options
+            .apply_flags(
+                ["-Dapp.greeting=bonjour", "-Dline.separator=|"]
+                    .map(String::from),
+                "command line",
+            )
+            .unwrap();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.configure(&options).unwrap();
+        runtime.set_property("file.separator", "\\");
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.run(false).unwrap();
+        let stdout = stdout.to_string_lossy();
+        assert!(stdout.starts_with("bonjour\nfirst line|\\\ntrue\n"));
+    }
+
+    #[test]
+    fn narrows_small_integers() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/arrays/Narrowing.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.run(false).unwrap();
+        assert_eq!(
+            stdout.to_string_lossy(),
+            "-56\n4464\n65535\n-100\n-1\n65535\nhi!\n-25536\nfalse\ntrue\n"
+        );
+
+        // Stores narrow to the array's type, booleans keep the lowest bit.
+        let mut runtime = Runtime::new(Program::from_code(vec![], 0));
+        let bytes = runtime.heap.new_array("Z", 1);
+        runtime.frames.push(Frame {
+            pc: ProgramCounter::new(0, 0),
+            stack: vec![bytes, Value::Int(0), Value::Int(2)],
+            locals: Locals::default(),
+            max_locals: 0,
+        });
+        runtime.array_store(OPCode::BAStore).unwrap();
+        assert_eq!(runtime.heap.array(bytes), Some(&[Value::Int(0)][..]));
+        runtime.push(Value::NULL);
+        runtime.push(Value::Int(0));
+        let err = runtime.array_load(OPCode::BALoad).unwrap_err();
+        assert_eq!(err.kind(), &RuntimeErrorKind::NullPointer(OPCode::BALoad));
+        runtime.push(bytes);
+        runtime.push(Value::Int(1));
+        let err = runtime.array_load(OPCode::BALoad).unwrap_err();
+        assert_eq!(
+            err.java_error(),
+            Some("java/lang/ArrayIndexOutOfBoundsException")
+        );
+    }
+
+    #[test]
+    fn loads_method_handles() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/handles/Lambdas.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let program = Program::new(&class_file);
+        let main = program.entry_point().unwrap();
+        let run = |program: &Program, index: u8| {
+            let mut program = program.clone();
+            // ldc_w #index, ldc_w #index, areturn
+            program.set_code(main, vec![19, 0, index, 19, 0, index, 176]);
+            let mut runtime = Runtime::new(program);
+            runtime.run(false).unwrap();
+            let value = runtime.top_return_value().unwrap();
+            // Loading a constant twice gives the same object, the other
+            // one holds the arguments of `main`.
+            assert_eq!(runtime.heap().objects().len(), 2);
+            runtime.heap().java_string(value)
+        };
+        assert_eq!(run(&program, 45), "(int,int)int");
+        assert_eq!(run(&program, 46), "MethodHandle(int,int)int");
+    }
+
+    #[test]
+    fn invokes_method_handles() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/handles/Handles.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let program = Program::new(&class_file);
+        let main = program.entry_point().unwrap();
+        let run = |code: Vec<u8>| {
+            let mut program = program.clone();
+            program.set_code(main, code);
+            let mut runtime = Runtime::new(program);
+            runtime.run(false).map(|()| {
+                let value = runtime.top_return_value().unwrap();
+                runtime
+                    .heap()
+                    .string(value)
+                    .map_or_else(|| value.to_string(), ToString::to_string)
+            })
+        };
+        // ldc_w #79 (Handles.add), iconst_1, iconst_2,
+        // invokevirtual #7 (invokeExact:(II)I), ireturn
+        assert_eq!(run(vec![19, 0, 79, 4, 5, 182, 0, 7, 172]).unwrap(), "3");
+        // `invoke` widens the short argument.
+        assert_eq!(
+            run(vec![19, 0, 79, 16, 7, 5, 182, 0, 13, 172]).unwrap(),
+            "9"
+        );
+        // ldc_w #84 (Class.getName), ldc_w #24 (Handles),
+        // invokevirtual #17 (invokeExact:(Ljava/lang/Class;)Ljava/lang/String;)
+        assert_eq!(
+            run(vec![19, 0, 84, 19, 0, 24, 182, 0, 17, 176]).unwrap(),
+            "Handles"
+        );
+        let err = run(vec![19, 0, 84, 4, 5, 182, 0, 7, 172]).unwrap_err();
+        assert_eq!(
+            err.java_error(),
+            Some("java/lang/invoke/WrongMethodTypeException")
+        );
+    }
+
+    #[test]
+    fn invokes_field_and_constructor_handles() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/handles/Members.class");
+        let bytes = read_class_file(&path).unwrap();
+        // javac only emits method handles to methods, the tests point the
+        // handles to `count()` (#108) and `value()` (#112) at the fields.
+        let get_static = ([15, 6, 0, 109], [15, 2, 0, 13]);
+        let put_static = ([15, 6, 0, 109], [15, 4, 0, 13]);
+        let get_field = ([15, 6, 0, 109], [15, 1, 0, 7]);
+        let put_field = ([15, 5, 0, 113], [15, 3, 0, 7]);
+        let run = |patches: &[([u8; 4], [u8; 4])], code: Vec<u8>| {
+            let mut bytes = bytes.clone();
+            for (from, to) in patches {
+                let at = bytes.windows(4).position(|w| w == from).unwrap();
+                bytes[at..at + 4].copy_from_slice(to);
+            }
+            let class_file = JVMParser::parse(&bytes).unwrap();
+            let mut program = Program::new(&class_file);
+            let main = program.entry_point().unwrap();
+            program.set_code(main, code);
+            let mut runtime = Runtime::new(program);
+            runtime
+                .run(false)
+                .map(|()| runtime.top_return_value().unwrap().to_string())
+        };
+        // ldc_w #108, invokevirtual #16 (invokeExact:()I), ireturn
+        assert_eq!(
+            run(&[get_static], vec![19, 0, 108, 182, 0, 16, 172]).unwrap(),
+            "3"
+        );
+        // ldc_w #108, bipush 9, invokevirtual #25 (invokeExact:(I)V),
+        // getstatic #13 (count), ireturn
+        assert_eq!(
+            run(
+                &[put_static],
+                vec![19, 0, 108, 16, 9, 182, 0, 25, 178, 0, 13, 172]
+            )
+            .unwrap(),
+            "9"
+        );
+        // `invoke` doesn't narrow, ldc_w #108, lconst_1,
+        // invokevirtual #28 (invoke:(J)V), return
+        let err = run(&[put_static], vec![19, 0, 108, 10, 182, 0, 28, 177])
+            .unwrap_err();
+        assert_eq!(
+            err.java_error(),
+            Some("java/lang/invoke/WrongMethodTypeException")
+        );
+        // ldc_w #103 (Members.<init>), bipush 7,
+        // invokevirtual #35 (invokeExact:(I)LMembers;), getfield #7, ireturn
+        assert_eq!(
+            run(&[], vec![19, 0, 103, 16, 7, 182, 0, 35, 180, 0, 7, 172])
+                .unwrap(),
+            "7"
+        );
+        // Construct an object in local 1, set its field to 5 through #112
+        // with invokeExact:(LMembers;I)V and read it back through #108 with
+        // invokeExact:(LMembers;)I.
+        let code = vec![
+            19, 0, 103, 16, 7, 182, 0, 35, 76, 19, 0, 112, 43, 16, 5, 182, 0,
+            32, 19, 0, 108, 43, 182, 0, 22, 172,
+        ];
+        assert_eq!(run(&[get_field, put_field], code).unwrap(), "5");
+        // ldc_w #108, aconst_null, invokevirtual #22, ireturn
+        let err = run(&[get_field], vec![19, 0, 108, 1, 182, 0, 22, 172])
+            .unwrap_err();
+        assert_eq!(err.java_error(), Some("java/lang/NullPointerException"));
+    }
+
+    #[test]
+    fn widens_primitive_arguments() {
+        assert!(widens("S", "I"));
+        assert!(widens("C", "J"));
+        assert!(widens("I", "D"));
+        assert!(!widens("J", "I"));
+        assert!(!widens("I", "Z"));
+        assert!(!widens("Z", "I"));
+        assert!(!widens("I", "Ljava/lang/Object;"));
+    }
+
+    #[test]
+    fn reports_java_stack_traces() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/exceptions/Trace.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(crate::sink::SharedBuffer::new()));
+        let err = runtime.run(false).unwrap_err();
+        assert_eq!(
+            runtime.java_stack_trace(&err).unwrap(),
+            "Exception in thread \"main\" \
+             java.lang.ArrayIndexOutOfBoundsException: \
+             Index 2 out of bounds for length 2\n\
+             \tat Trace.get(Trace.java:3)\n\
+             \tat Trace.sum(Trace.java:9)\n\
+             \tat Trace.main(Trace.java:16)\n"
+        );
+
+        // Classes without debug information have unknown sources.
+        let mut runtime = Runtime::new(Program::from_code(vec![4, 176], 0));
+        runtime.fault = Some(ProgramCounter::new(0, 0));
+        let trace = runtime.stack_trace();
+        assert_eq!(trace[0].to_string(), ".main(Unknown Source)");
+    }
+
+    #[test]
+    fn stores_locals_in_slots() {
+        let mut locals = Locals::new(4);
+        assert_eq!(locals.get(1), None);
+        locals.insert(2, Value::Long(7)).unwrap();
+        locals.insert(0, Value::Int(1)).unwrap();
+        assert_eq!(locals.get(2), Some(Value::Long(7)));
+        assert_eq!(
+            locals.iter().collect::<Vec<_>>(),
+            [(0, Value::Int(1)), (2, Value::Long(7))]
+        );
+        // Slots past `max_locals` aren't there to store to.
+        let err = locals.insert(4, Value::Int(3)).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &RuntimeErrorKind::InvalidLocal {
+                slot: 4,
+                max_locals: 4
+            }
+        );
+        // Neither is the second half of a long in the last slot.
+        assert!(locals.insert(3, Value::Double(0.5)).is_err());
+        assert_eq!(locals.get(3), None);
+
+        // Overwriting either half of a long clears it.
+        locals.insert(3, Value::Int(3)).unwrap();
+        assert_eq!(locals.get(2), None);
+        locals.insert(0, Value::Double(0.5)).unwrap();
+        locals.insert(0, Value::Int(1)).unwrap();
+        assert_eq!(
+            locals.iter().collect::<Vec<_>>(),
+            [(0, Value::Int(1)), (3, Value::Int(3))]
+        );
+        locals.insert(1, Value::Long(7)).unwrap();
+        assert_eq!(locals.get(0), Some(Value::Int(1)));
+        assert_eq!(locals.get(2), None);
+
+        // Frames get the locals their method declares.
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/tests/Bitwise.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let program = Program::new(&class_file);
+        let max_locals = program.max_locals(program.entry_point().unwrap());
+        let runtime = Runtime::new(program);
+        assert!(max_locals > 0);
+        assert_eq!(runtime.frames[0].max_locals, max_locals);
+        assert_eq!(runtime.frames[0].locals.slots.len(), max_locals.into());
+    }
+
+    #[test]
+    fn catches_exceptions() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path =
+            Path::new(&env_var).join("support/exceptions/TryCatch.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.run(false).unwrap();
+        assert_eq!(stdout.to_string_lossy(), "-1\n");
+        assert_eq!(runtime.fault, None);
+
+        // Thrown exceptions nobody catches keep their class and message.
+        let err = RuntimeError {
+            kind: RuntimeErrorKind::Thrown {
+                class: "java/lang/IllegalStateException".to_string(),
+                message: Some("closed".into()),
+            },
+        };
+        assert_eq!(err.java_error(), Some("java/lang/IllegalStateException"));
+        assert_eq!(err.to_string(), "java.lang.IllegalStateException: closed");
+    }
+
+    #[test]
+    fn reports_unsupported_instructions() {
+        // aconst_null, monitorenter, return.
+        let mut runtime =
+            Runtime::new(Program::from_code(vec![1, 194, 177], 0));
+        let err = runtime.run(false).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &RuntimeErrorKind::Unsupported {
+                opcode: OPCode::MonitorEnter,
+                pc: ProgramCounter::new(0, 1),
+                method: ".main([Ljava/lang/String;)V".into(),
+            }
+        );
+        assert!(!supports(OPCode::MonitorEnter));
+        assert!(supports(OPCode::IShl));
+    }
+
+    #[test]
+    fn interprets_wide_instructions() {
+        // sipush 5, wide istore 300, wide iinc 300 1000, wide iload 300,
+        // ireturn.
+        let code = vec![
+            17, 0, 5, 196, 54, 1, 44, 196, 132, 1, 44, 3, 232, 196, 21, 1, 44,
+            172,
+        ];
+        let mut runtime = Runtime::new(Program::from_code(code, 301));
+        runtime.run(false).unwrap();
+        assert_eq!(runtime.top_return_value(), Some(Value::Int(1005)));
+    }
+
+    #[test]
+    fn refuses_invalid_programs() {
+        // iconst_1, return without room on the stack.
+        let mut runtime = Runtime::new(Program::from_code(vec![4, 177], 1));
+        runtime.set_validation(true);
+        let err = runtime.run(false).unwrap_err();
+        let RuntimeErrorKind::InvalidProgram { diagnostics } = err.kind()
+        else {
+            panic!("expected an invalid program, got {err}");
+        };
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(runtime.executed(), 0);
+
+        runtime.set_validation(false);
+        runtime.run(false).unwrap();
+    }
+
+    #[test]
+    fn dispatches_println_by_descriptor() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/overload/Println.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.run(false).unwrap();
+        assert_eq!(stdout.to_string_lossy(), "7\n49\nA\ntrue\n\n");
+
+        // `print` and `println` of every type, formatted like Java.
+        let path = Path::new(&env_var).join("support/overload/Overloads.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.run(false).unwrap();
+        assert_eq!(
+            stdout.to_string_lossy(),
+            "1 2 true\n1.0\n0.1\n-0.0\n1.0E7\n1.5E-4\n123456.789\n\
+             Infinity\n-Infinity\nNaN\n1.7976931348623157E308\n1.4E-45\n\
+             x\nno newlineobject\nclass Overloads\n"
+        );
+    }
+
+    #[test]
+    fn formats_floats_like_java() {
+        let cases = [
+            (Value::Double(100.), "100.0"),
+            (Value::Double(0.001), "0.001"),
+            (Value::Double(-1234567.5), "-1234567.5"),
+            (Value::Double(1e-300), "1.0E-300"),
+            (
+                Value::Double(f64::MIN_POSITIVE / 4.),
+                "5.562684646268003E-309",
+            ),
+            (Value::Double(4.9e-324), "4.9E-324"),
+            (Value::Float(3.4028235e38), "3.4028235E38"),
+            (Value::Float(0.), "0.0"),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(value.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn enforces_heap_limit() {
+        // sipush 1000, newarray int, areturn
+        let code = vec![17, 3, 232, 188, 10, 176];
+        let mut runtime = Runtime::new(Program::from_code(code.clone(), 1));
+        runtime.run(false).unwrap();
+        assert!(runtime.heap().size() > 1000 * mem::size_of::<Value>());
+
+        let mut runtime = Runtime::new(Program::from_code(code, 1));
+        runtime.set_limits(Limits {
+            heap: Some(4096),
+            ..Limits::default()
+        });
+        let err = runtime.run(false).unwrap_err();
+        let RuntimeErrorKind::LimitExceeded { kind, at_pc } = err.kind() else {
+            panic!("expected a limit error, got {err:?}");
+        };
+        assert_eq!(kind, &LimitKind::Heap(4096));
+        assert_eq!(at_pc.get_instruction_index(), 3);
+        // The elements of the array weren't created.
+        let array = runtime.heap().objects().last().unwrap();
+        assert_eq!(array.size(), 16);
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "jit"), ignore = "requires the jit feature")]
+    fn enforces_limits() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/tests/Factorial.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_limits(Limits {
+            stack_depth: Some(1),
+            ..Limits::default()
+        });
+        let err = runtime.run(false).unwrap_err();
+        let RuntimeErrorKind::LimitExceeded { kind, at_pc } = err.kind() else {
+            panic!("expected a limit error, got {err:?}");
+        };
+        assert_eq!(kind, &LimitKind::StackDepth(1));
+        let main = Program::new(&class_file).entry_point().unwrap();
+        assert_eq!(*at_pc, ProgramCounter::new(main, 2));
+
+        // Faults point at the call instruction whatever its length, here
+        // the recursive invokeinterface of `next`.
+        let dir = Path::new(&env_var).join("support/limits");
+        let class_files: Vec<_> = ["Countdown", "Step"]
+            .iter()
+            .map(|name| {
+                let path = dir.join(format!("{name}.class"));
+                JVMParser::parse(&read_class_file(&path).unwrap()).unwrap()
+            })
+            .collect();
+        let program = Program::link(&class_files).unwrap();
+        let next = program.class_method("Countdown", "next", "(I)I").unwrap();
+        let mut runtime = Runtime::new(program);
+        runtime.set_limits(Limits {
+            stack_depth: Some(2),
+            ..Limits::default()
+        });
+        let err = runtime.run(false).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            RuntimeErrorKind::LimitExceeded {
+                kind: LimitKind::StackDepth(2),
+                at_pc,
+            } if *at_pc == ProgramCounter::new(next, 14)
+        ));
+
+        // Native traces are stopped at a loop back edge.
+        let path = Path::new(&env_var).join("support/jit/Loop100.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(crate::sink::SharedBuffer::new()));
+        runtime.set_limits(Limits {
+            instructions: Some(200),
+            ..Limits::default()
+        });
+        let err = runtime.run(true).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            RuntimeErrorKind::LimitExceeded {
+                kind: LimitKind::Instructions(200),
+                ..
+            }
+        ));
+        assert!(runtime.executed() > 200);
+    }
+
+    #[test]
+    fn records_array_accesses() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/jit/Vector.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(crate::sink::SharedBuffer::new()));
+        runtime.run(false).unwrap();
+        let mut loops = 0;
+        for trace in runtime.recorded_traces() {
+            let accesses: Vec<_> = trace.memory_accesses().collect();
+            let array_ops = trace
+                .iter()
+                .filter(|record| {
+                    matches!(
+                        record.opcode(),
+                        OPCode::IALoad
+                            | OPCode::FALoad
+                            | OPCode::IAStore
+                            | OPCode::FAStore
+                            | OPCode::ArrayLength
+                    )
+                })
+                .count();
+            assert_eq!(accesses.len(), array_ops);
+            // b[i] = a[i] + a[i] + k and y[i] = x[i] * x[i] / 2.0f - 0.25f
+            if let [(load, _), (again, _), (store, _)] = accesses[..] {
+                if trace.may_alias(load, again) {
+                    assert!(!trace.may_alias(load, store));
+                    loops += 1;
+                }
+            }
+        }
+        assert_eq!(loops, 2);
+    }
+
+    #[test]
+    fn records_through_inlined_calls() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/jit/Inline.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let stdout = crate::sink::SharedBuffer::new();
+        let program = Program::new(&class_file);
+        let total = program.method_index("total").unwrap();
+        let mut runtime = Runtime::new(program);
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.run(false).unwrap();
+        assert_eq!(stdout.to_string_lossy(), "1900\n3725\n");
+        // The loop in `main` is recorded as well, through the loop in
+        // `total`.
+        let trace = runtime
+            .recorded_traces()
+            .find(|trace| trace.start().get_method_index() == total)
+            .unwrap();
+        assert!(trace.is_loop());
+        let returns: Vec<_> = trace
+            .iter()
+            .filter(|record| record.opcode() == OPCode::IReturn)
+            .map(trace::Record::depth)
+            .collect();
+        assert_eq!(returns, vec![1]);
+    }
+
+    #[test]
+    #[cfg(feature = "jit")]
+    fn compiles_methods_that_keep_aborting_recordings() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/jit/Partitions.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let program = Program::new(&class_file);
+        let partitions = (0..program.methods.len())
+            .find(|&index| program.method_name(index) == Some("partitions"))
+            .unwrap();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(program);
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.enable_stats();
+        runtime.run(true).unwrap();
+        assert_eq!(stdout.to_string_lossy(), "683\n5604\n");
+        assert!(runtime.baseline.has_method(partitions));
+        assert_eq!(runtime.tiers.tier(partitions), Tier::Baseline);
+        let transitions = runtime.stats().unwrap().transitions();
+        assert!(transitions.iter().any(|transition| {
+            transition.pc == ProgramCounter::new(partitions, 0)
+                && transition.to == Tier::Baseline
+                && transition.reason == Reason::RecordingAborts
+        }));
+    }
+
+    #[test]
+    #[cfg(feature = "jit")]
+    fn promotes_warm_methods_with_their_thresholds() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/jit/Inline.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let program = Program::new(&class_file);
+        let clamp = (0..program.methods.len())
+            .find(|&index| program.method_name(index) == Some("clamp"))
+            .unwrap();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(program);
+        runtime.set_stdout(Box::new(stdout.clone()));
+        let mut options = RuntimeOptions::default();
+        options.tiers.baseline_invocations = 50;
+        runtime.configure(&options).unwrap();
+        runtime.enable_stats();
+        runtime.run(true).unwrap();
+        assert_eq!(stdout.to_string_lossy(), "1900\n3725\n");
+        assert_eq!(runtime.tiers.tier(clamp), Tier::Baseline);
+        let transition = runtime.stats().unwrap().transitions()[0];
+        assert_eq!(transition.pc, ProgramCounter::new(clamp, 0));
+        assert_eq!(transition.reason, Reason::Invocations);
+    }
+
+    #[test]
+    #[cfg(feature = "jit")]
+    fn skips_guards_on_verified_locals() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/jit/Alloc.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(crate::sink::SharedBuffer::new()));
+        assert!(runtime.run(true).is_err());
+        assert_eq!(runtime.recorded_traces().count(), 2);
+        // javac's code is typed, the ints and arrays the traces read are
+        // known to be ones without checking.
+        for trace in runtime.recorded_traces() {
+            let live_in = trace.live_in();
+            assert!(!live_in.is_empty());
+            for (local, kind) in live_in {
+                assert_eq!(trace.entry_type(local), Some(kind));
+            }
+            assert_eq!(
+                runtime.jit_cache.entry_guards(trace.start()),
+                Some(&[][..])
+            );
+        }
+    }
+
+    #[test]
+    fn records_identical_traces_across_runs() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/jit/Vector.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let dump = || {
+            let mut runtime = Runtime::new(Program::new(&class_file));
+            runtime.set_stdout(Box::new(crate::sink::SharedBuffer::new()));
+            runtime.run(false).unwrap();
+            let starts: Vec<_> = runtime
+                .recorded_traces()
+                .map(|trace| trace.start())
+                .collect();
+            assert!(starts.windows(2).all(|pair| pair[0] < pair[1]));
+            format!("{:?}", runtime.recorded_traces().collect::<Vec<_>>())
+        };
+        assert_eq!(dump(), dump());
+    }
+
+    #[test]
+    fn resolves_lookupswitch_keys() {
+        // length, default, npairs, (key, offset)...
+        let switch = Instruction::new(
+            OPCode::LookupSwitch,
+            Some(
+                [36, 50, 4, -5, 20, 7, 30, 8, 30, 100, 50]
+                    .into_iter()
+                    .map(Value::Int)
+                    .collect(),
+            ),
+        );
+        assert_eq!(switch.switch_length(), Some(36));
+        assert_eq!(switch.switch_cases(), Some(vec![(-5, -5, 20), (7, 8, 30)]));
+        assert_eq!(switch.switch_offset(-5), Some(20));
+        assert_eq!(switch.switch_offset(8), Some(30));
+        assert_eq!(switch.switch_offset(100), Some(50));
+        assert_eq!(switch.switch_offset(0), Some(50));
+    }
+
+    #[test]
+    fn can_cancel_and_resume() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/tests/Factorial.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let stdout = crate::sink::SharedBuffer::new();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.set_stdout(Box::new(stdout.clone()));
+        let handle = runtime.cancel_handle();
+        runtime.on_method_enter(Box::new(move |name, _, _| {
+            if name == "factorial" {
+                handle.cancel();
+            }
+        }));
+        let err = runtime.run(false).unwrap_err();
+        let RuntimeErrorKind::Cancelled { stack } = err.kind() else {
+            panic!("expected a cancellation, got {err:?}");
+        };
+        let main = Program::new(&class_file).entry_point().unwrap();
+        assert_eq!(stack.len(), 2);
+        assert_eq!(stack[0], ProgramCounter::new(main, 5));
+        assert_eq!(stack[1].get_instruction_index(), 0);
+        assert!(!runtime.cancel_handle().is_cancelled());
+
+        runtime.run(false).unwrap();
+        assert_eq!(stdout.to_string_lossy(), "479001600\n");
+    }
 }
@@ -0,0 +1,40 @@
+//! Output sinks for the guest program.
+//!
+//! The runtime writes everything the Java program prints to a sink which
+//! defaults to the process stdout, tests and tools that need to inspect the
+//! output install a `SharedBuffer` instead.
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+/// `SharedBuffer` is an in-memory sink that can be cloned and handed to the
+/// runtime while the caller keeps a handle to read what was written.
+#[derive(Debug, Clone, Default)]
+pub struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a copy of the bytes written so far.
+    pub fn contents(&self) -> Vec<u8> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Returns the bytes written so far as a string, invalid UTF-8
+    /// sequences are replaced.
+    pub fn to_string_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,522 @@
+//! Snapshots of the interpreter state.
+//!
+//! A `Snapshot` holds everything needed to continue a program where it
+//! stopped : the call stack with every frame's program counter, operand
+//! stack and locals, the values returned so far and the number of executed
+//! instructions along with the heap objects references point to, the
+//! static fields and which classes were initialized.
+//!
+//! Snapshots are written in a small big endian binary format :
+//!
+//! ```text
+//! magic "CBSN" | version u8 | class name | executed u64
+//! return values | frames (method u32, offset u32, max locals u16,
+//!                         stack, locals as (slot u32, value) pairs)
+//! heap objects (tag u8, string, array or list elements, method handle
+//!               kind u8, name, descriptor, boxed value, map capacity u32,
+//!               keys, values or iterator list value, next u32)
+//! statics (field u32, value) | class states u8
+//! ```
+//!
+//! Strings and lists are prefixed with their length as a `u32` and values
+//! with a tag byte. Profiles, recorded traces and compiled code aren't part
+//! of a snapshot, hot loops are detected again after a restore.
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::heap::Object;
+use crate::program::ClassState;
+use crate::runtime::{Frame, ProgramCounter, Value};
+
+const MAGIC: &[u8; 4] = b"CBSN";
+// Version 2 indexes methods in declaration order, version 3 saves the heap,
+// version 4 the native collections and version 5 the static fields and
+// class states.
+const VERSION: u8 = 5;
+
+/// `SnapshotError` is returned when a snapshot can't be read or restored.
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// The data doesn't start with the snapshot magic.
+    BadMagic,
+    UnsupportedVersion(u8),
+    InvalidValueTag(u8),
+    InvalidObjectTag(u8),
+    InvalidClassState(u8),
+    InvalidUtf8,
+    /// The snapshot was taken from another class.
+    ClassMismatch {
+        expected: String,
+        found: String,
+    },
+    /// A frame points at a method the program doesn't have.
+    UnknownMethod(usize),
+    /// A frame stores a local past the `max_locals` of its method.
+    InvalidLocal(usize),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::BadMagic => write!(f, "not a coldbrew snapshot"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {version}")
+            }
+            Self::InvalidValueTag(tag) => write!(f, "invalid value tag {tag}"),
+            Self::InvalidObjectTag(tag) => {
+                write!(f, "invalid object tag {tag}")
+            }
+            Self::InvalidClassState(state) => {
+                write!(f, "invalid class state {state}")
+            }
+            Self::InvalidUtf8 => write!(f, "invalid UTF-8 string"),
+            Self::ClassMismatch { expected, found } => {
+                write!(f, "snapshot of class {found} can't restore {expected}")
+            }
+            Self::UnknownMethod(index) => {
+                write!(f, "snapshot frame in unknown method #{index}")
+            }
+            Self::InvalidLocal(slot) => {
+                write!(f, "snapshot frame stores invalid local {slot}")
+            }
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// `Snapshot` is the saved state of a `Runtime`, see `Runtime::snapshot`
+/// and `Runtime::restore`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// Binary name of the class the snapshot was taken from.
+    pub class_name: String,
+    /// Number of instructions interpreted before the snapshot.
+    pub executed: usize,
+    /// Call stack, the innermost frame is the last one.
+    pub frames: Vec<FrameState>,
+    /// Values returned by the program so far.
+    pub return_values: Vec<Value>,
+    /// Heap objects in handle order.
+    pub objects: Vec<Object>,
+    /// Values of the static fields keyed by field index.
+    pub statics: BTreeMap<usize, Value>,
+    /// Initialization state of every class in link order.
+    pub class_states: Vec<ClassState>,
+}
+
+/// Saved state of a single frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameState {
+    pub pc: ProgramCounter,
+    pub stack: Vec<Value>,
+    pub locals: BTreeMap<usize, Value>,
+    pub max_locals: u16,
+}
+
+impl From<&Frame> for FrameState {
+    fn from(frame: &Frame) -> Self {
+        Self {
+            pc: frame.pc,
+            stack: frame.stack().to_vec(),
+            locals: frame.locals.iter().collect(),
+            max_locals: frame.max_locals,
+        }
+    }
+}
+
+impl Snapshot {
+    /// Write the snapshot to the file at `path`.
+    /// # Errors
+    /// Returns an error if the file can't be written.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Read a snapshot from the file at `path`.
+    /// # Errors
+    /// Returns an error if the file can't be read or isn't a snapshot.
+    pub fn load(path: &Path) -> Result<Self, SnapshotError> {
+        Self::read_from(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Encode the snapshot to `writer`.
+    /// # Errors
+    /// Returns an error if writing fails.
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        write_string(writer, &self.class_name)?;
+        writer.write_all(&(self.executed as u64).to_be_bytes())?;
+        write_values(writer, &self.return_values)?;
+        write_len(writer, self.frames.len())?;
+        for frame in &self.frames {
+            write_len(writer, frame.pc.get_method_index())?;
+            write_len(writer, frame.pc.get_instruction_index())?;
+            writer.write_all(&frame.max_locals.to_be_bytes())?;
+            write_values(writer, &frame.stack)?;
+            // Locals are ordered so equal snapshots encode to the same bytes.
+            write_len(writer, frame.locals.len())?;
+            for (slot, value) in &frame.locals {
+                write_len(writer, *slot)?;
+                write_value(writer, *value)?;
+            }
+        }
+        write_len(writer, self.objects.len())?;
+        for object in &self.objects {
+            let (tag, string) = match object {
+                Object::String(string) => (0, string),
+                Object::Class(name) => (1, name),
+                Object::Array { component, .. } => (2, component),
+                Object::MethodType(descriptor) => (3, descriptor),
+                Object::MethodHandle { class, .. } => (4, class),
+                Object::Boxed { class, .. } => (5, class),
+                Object::ArrayList(_) => (6, &String::new()),
+                Object::HashMap { .. } => (7, &String::new()),
+                Object::Iterator { .. } => (8, &String::new()),
+                Object::StringBuilder(string) => (9, string),
+                Object::Instance { class, .. } => (10, class),
+            };
+            writer.write_all(&[tag])?;
+            write_string(writer, string)?;
+            match object {
+                Object::Array { elements, .. } => {
+                    write_values(writer, elements)?;
+                }
+                Object::MethodHandle {
+                    kind,
+                    name,
+                    descriptor,
+                    ..
+                } => {
+                    writer.write_all(&[*kind])?;
+                    write_string(writer, name)?;
+                    write_string(writer, descriptor)?;
+                }
+                Object::Boxed { value, .. } => write_value(writer, *value)?,
+                Object::ArrayList(elements) => {
+                    write_values(writer, elements)?;
+                }
+                Object::HashMap { entries, capacity } => {
+                    write_len(writer, *capacity)?;
+                    let (keys, values): (Vec<_>, Vec<_>) =
+                        entries.iter().copied().unzip();
+                    write_values(writer, &keys)?;
+                    write_values(writer, &values)?;
+                }
+                Object::Iterator { list, next } => {
+                    write_value(writer, *list)?;
+                    write_len(writer, *next)?;
+                }
+                Object::Instance { fields, .. } => {
+                    write_len(writer, fields.len())?;
+                    for (name, value) in fields {
+                        write_string(writer, name)?;
+                        write_value(writer, *value)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        write_len(writer, self.statics.len())?;
+        for (field, value) in &self.statics {
+            write_len(writer, *field)?;
+            write_value(writer, *value)?;
+        }
+        write_len(writer, self.class_states.len())?;
+        for state in &self.class_states {
+            let tag = match state {
+                ClassState::Uninitialized => 0,
+                ClassState::Initializing => 1,
+                ClassState::Initialized => 2,
+            };
+            writer.write_all(&[tag])?;
+        }
+        Ok(())
+    }
+
+    /// Decode a snapshot from `reader`.
+    /// # Errors
+    /// Returns an error if reading fails or the data isn't a snapshot.
+    pub fn read_from(reader: &mut impl Read) -> Result<Self, SnapshotError> {
+        if read_array::<4>(reader)? != *MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let [version] = read_array::<1>(reader)?;
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let class_name = read_string(reader)?;
+        let executed = u64::from_be_bytes(read_array(reader)?) as usize;
+        let return_values = read_values(reader)?;
+        let frames = (0..read_len(reader)?)
+            .map(|_| {
+                let method_index = read_len(reader)?;
+                let instruction_index = read_len(reader)?;
+                let max_locals = u16::from_be_bytes(read_array(reader)?);
+                let stack = read_values(reader)?;
+                let locals = (0..read_len(reader)?)
+                    .map(|_| Ok((read_len(reader)?, read_value(reader)?)))
+                    .collect::<Result<_, SnapshotError>>()?;
+                Ok(FrameState {
+                    pc: ProgramCounter::new(method_index, instruction_index),
+                    stack,
+                    locals,
+                    max_locals,
+                })
+            })
+            .collect::<Result<_, SnapshotError>>()?;
+        let objects = (0..read_len(reader)?)
+            .map(|_| {
+                let [tag] = read_array::<1>(reader)?;
+                let string = read_string(reader)?;
+                match tag {
+                    0 => Ok(Object::String(string)),
+                    1 => Ok(Object::Class(string)),
+                    2 => Ok(Object::Array {
+                        component: string,
+                        elements: read_values(reader)?,
+                    }),
+                    3 => Ok(Object::MethodType(string)),
+                    4 => Ok(Object::MethodHandle {
+                        kind: read_array::<1>(reader)?[0],
+                        class: string,
+                        name: read_string(reader)?,
+                        descriptor: read_string(reader)?,
+                    }),
+                    5 => Ok(Object::Boxed {
+                        class: string,
+                        value: read_value(reader)?,
+                    }),
+                    6 => Ok(Object::ArrayList(read_values(reader)?)),
+                    7 => {
+                        let capacity = read_len(reader)?;
+                        let keys = read_values(reader)?;
+                        let values = read_values(reader)?;
+                        Ok(Object::HashMap {
+                            entries: keys.into_iter().zip(values).collect(),
+                            capacity,
+                        })
+                    }
+                    8 => Ok(Object::Iterator {
+                        list: read_value(reader)?,
+                        next: read_len(reader)?,
+                    }),
+                    9 => Ok(Object::StringBuilder(string)),
+                    10 => {
+                        let fields = (0..read_len(reader)?)
+                            .map(|_| {
+                                Ok((read_string(reader)?, read_value(reader)?))
+                            })
+                            .collect::<Result<_, SnapshotError>>()?;
+                        Ok(Object::Instance {
+                            class: string,
+                            fields,
+                        })
+                    }
+                    tag => Err(SnapshotError::InvalidObjectTag(tag)),
+                }
+            })
+            .collect::<Result<_, SnapshotError>>()?;
+        let statics = (0..read_len(reader)?)
+            .map(|_| Ok((read_len(reader)?, read_value(reader)?)))
+            .collect::<Result<_, SnapshotError>>()?;
+        let class_states = (0..read_len(reader)?)
+            .map(|_| match read_array::<1>(reader)? {
+                [0] => Ok(ClassState::Uninitialized),
+                [1] => Ok(ClassState::Initializing),
+                [2] => Ok(ClassState::Initialized),
+                [state] => Err(SnapshotError::InvalidClassState(state)),
+            })
+            .collect::<Result<_, SnapshotError>>()?;
+        Ok(Self {
+            class_name,
+            executed,
+            frames,
+            return_values,
+            objects,
+            statics,
+            class_states,
+        })
+    }
+}
+
+fn write_len(writer: &mut impl Write, len: usize) -> io::Result<()> {
+    let len = u32::try_from(len)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too long"))?;
+    writer.write_all(&len.to_be_bytes())
+}
+
+fn write_string(writer: &mut impl Write, string: &str) -> io::Result<()> {
+    write_len(writer, string.len())?;
+    writer.write_all(string.as_bytes())
+}
+
+fn write_value(writer: &mut impl Write, value: Value) -> io::Result<()> {
+    match value {
+        Value::Int(v) => {
+            writer.write_all(&[0])?;
+            writer.write_all(&v.to_be_bytes())
+        }
+        Value::Long(v) => {
+            writer.write_all(&[1])?;
+            writer.write_all(&v.to_be_bytes())
+        }
+        Value::Float(v) => {
+            writer.write_all(&[2])?;
+            writer.write_all(&v.to_bits().to_be_bytes())
+        }
+        Value::Double(v) => {
+            writer.write_all(&[3])?;
+            writer.write_all(&v.to_bits().to_be_bytes())
+        }
+        Value::Reference(v) => {
+            writer.write_all(&[4])?;
+            writer.write_all(&v.to_be_bytes())
+        }
+    }
+}
+
+fn write_values(writer: &mut impl Write, values: &[Value]) -> io::Result<()> {
+    write_len(writer, values.len())?;
+    values
+        .iter()
+        .try_for_each(|value| write_value(writer, *value))
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_len(reader: &mut impl Read) -> io::Result<usize> {
+    Ok(u32::from_be_bytes(read_array(reader)?) as usize)
+}
+
+fn read_value(reader: &mut impl Read) -> Result<Value, SnapshotError> {
+    let [tag] = read_array::<1>(reader)?;
+    Ok(match tag {
+        0 => Value::Int(i32::from_be_bytes(read_array(reader)?)),
+        1 => Value::Long(i64::from_be_bytes(read_array(reader)?)),
+        2 => Value::Float(f32::from_bits(u32::from_be_bytes(read_array(
+            reader,
+        )?))),
+        3 => Value::Double(f64::from_bits(u64::from_be_bytes(read_array(
+            reader,
+        )?))),
+        4 => Value::Reference(u32::from_be_bytes(read_array(reader)?)),
+        tag => return Err(SnapshotError::InvalidValueTag(tag)),
+    })
+}
+
+fn read_string(reader: &mut impl Read) -> Result<String, SnapshotError> {
+    let mut bytes = vec![0; read_len(reader)?];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|_| SnapshotError::InvalidUtf8)
+}
+
+fn read_values(reader: &mut impl Read) -> Result<Vec<Value>, SnapshotError> {
+    (0..read_len(reader)?).map(|_| read_value(reader)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jvm::{read_class_file, JVMParser};
+    use crate::options::Limits;
+    use crate::program::Program;
+    use crate::runtime::Runtime;
+    use crate::sink::SharedBuffer;
+    use std::env;
+
+    #[test]
+    fn can_resume_from_snapshot() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/tests/Factorial.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let program = Program::new(&class_file);
+
+        // Stop inside factorial's loop.
+        let mut runtime = Runtime::new(program.clone());
+        runtime.set_stdout(Box::new(SharedBuffer::new()));
+        runtime.set_limits(Limits {
+            instructions: Some(40),
+            ..Limits::default()
+        });
+        assert!(runtime.run(false).is_err());
+        let snapshot = runtime.snapshot();
+        assert_eq!(snapshot.frames.len(), 2);
+
+        let mut bytes = vec![];
+        snapshot.write_to(&mut bytes).unwrap();
+        let decoded = Snapshot::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(decoded, snapshot);
+
+        let stdout = SharedBuffer::new();
+        let mut resumed = Runtime::new(program);
+        resumed.set_stdout(Box::new(stdout.clone()));
+        resumed.restore(decoded).unwrap();
+        resumed.run(false).unwrap();
+        assert_eq!(stdout.to_string_lossy(), "479001600\n");
+
+        assert!(matches!(
+            Snapshot::read_from(&mut &bytes[1..]),
+            Err(SnapshotError::BadMagic)
+        ));
+    }
+
+    #[test]
+    fn restores_static_fields() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/tests/Statics.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let program = Program::new(&class_file);
+
+        // Stop while `main` increments the static counter.
+        let stdout = SharedBuffer::new();
+        let mut runtime = Runtime::new(program.clone());
+        runtime.set_stdout(Box::new(stdout.clone()));
+        runtime.set_limits(Limits {
+            instructions: Some(600),
+            ..Limits::default()
+        });
+        assert!(runtime.run(false).is_err());
+        assert_eq!(
+            stdout.to_string_lossy(),
+            "initializing\nmain\nsquares\n140\n"
+        );
+        let snapshot = runtime.snapshot();
+        assert!(!snapshot.statics.is_empty());
+        assert_eq!(snapshot.class_states, [ClassState::Initialized]);
+
+        let mut bytes = vec![];
+        snapshot.write_to(&mut bytes).unwrap();
+        let decoded = Snapshot::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(decoded, snapshot);
+
+        // The static initializer doesn't run again and the counter goes on.
+        let stdout = SharedBuffer::new();
+        let mut resumed = Runtime::new(program);
+        resumed.set_stdout(Box::new(stdout.clone()));
+        resumed.restore(decoded).unwrap();
+        resumed.run(false).unwrap();
+        assert_eq!(stdout.to_string_lossy(), "100\n25.5\n102\n102000\ntrue\n");
+    }
+}
@@ -0,0 +1,277 @@
+//! Execution statistics collected by the runtime, mainly used to compare
+//! interpreted execution against native traces.
+//!
+//! Loops are identified by their header, the target of a backward branch,
+//! and are bounded by the offset of the branch itself. Wall-clock time is
+//! charged to the innermost loop that contains the instruction executed.
+//!
+//! Native traces count the iterations they run, comparing the average cost
+//! of an interpreted iteration with the time spent in the trace gives an
+//! estimate of the time each trace saved.
+//!
+//! Tier transitions decided by the tiering policy are kept in the order
+//! they happened.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use crate::runtime::ProgramCounter;
+use crate::tiering::Transition;
+use crate::timing::Instant;
+
+/// Statistics for a single loop.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LoopStats {
+    /// Bytecode offset of the furthest back edge seen for this loop.
+    pub back_edge: usize,
+    /// Number of interpreted backward branches to the loop header.
+    pub iterations: usize,
+    /// Number of times a native trace was entered at the loop header.
+    pub native_entries: usize,
+    /// Number of iterations run by the native trace.
+    pub native_iterations: usize,
+    /// Wall-clock time spent executing the loop body.
+    pub time: Duration,
+    /// Part of `time` spent in the native trace.
+    pub native_time: Duration,
+}
+
+impl LoopStats {
+    /// Returns the time spent interpreting the loop body.
+    pub fn interpreted_time(&self) -> Duration {
+        self.time.saturating_sub(self.native_time)
+    }
+
+    /// Returns the share of iterations that ran natively in percent.
+    pub fn native_share(&self) -> f64 {
+        let total = self.iterations + self.native_iterations;
+        if total == 0 {
+            return 0.0;
+        }
+        self.native_iterations as f64 * 100.0 / total as f64
+    }
+
+    /// Returns the estimated time in seconds the native trace saved, that
+    /// is the time its iterations would have taken in the interpreter minus
+    /// the time they took. Negative when the trace is slower than the
+    /// interpreter and `None` when there is nothing to compare.
+    pub fn saved(&self) -> Option<f64> {
+        if self.iterations == 0 || self.native_iterations == 0 {
+            return None;
+        }
+        let per_iteration =
+            self.interpreted_time().as_secs_f64() / self.iterations as f64;
+        Some(
+            per_iteration * self.native_iterations as f64
+                - self.native_time.as_secs_f64(),
+        )
+    }
+}
+
+/// `Stats` accumulates counters and timings during a single run.
+#[derive(Debug, Clone)]
+pub struct Stats {
+    // Number of instructions dispatched by the interpreter.
+    interpreted: usize,
+    // Per loop statistics keyed by the loop header.
+    loops: BTreeMap<ProgramCounter, LoopStats>,
+    // Program counter of the last executed instruction.
+    last_pc: Option<ProgramCounter>,
+    // Timestamp of the last call to `tick`.
+    last_tick: Instant,
+    // Tier transitions in the order they happened.
+    transitions: Vec<Transition>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self {
+            interpreted: 0,
+            loops: BTreeMap::new(),
+            last_pc: None,
+            last_tick: Instant::now(),
+            transitions: vec![],
+        }
+    }
+
+    /// Returns the number of instructions dispatched by the interpreter.
+    pub fn interpreted(&self) -> usize {
+        self.interpreted
+    }
+
+    /// Returns the statistics of the loop with the given header.
+    pub fn loop_stats(&self, header: &ProgramCounter) -> Option<&LoopStats> {
+        self.loops.get(header)
+    }
+
+    /// Returns an iterator over all loops ordered by their header.
+    pub fn loops(&self) -> impl Iterator<Item = (&ProgramCounter, &LoopStats)> {
+        self.loops.iter()
+    }
+
+    /// Returns the tier transitions in the order they happened.
+    pub fn transitions(&self) -> &[Transition] {
+        &self.transitions
+    }
+
+    /// Record a tier transition.
+    pub fn count_transition(&mut self, transition: Transition) {
+        self.transitions.push(transition);
+    }
+
+    /// Count a backward branch from `back_edge` to the loop `header`.
+    pub fn count_iteration(
+        &mut self,
+        header: ProgramCounter,
+        back_edge: usize,
+    ) {
+        let stats = self.loops.entry(header).or_default();
+        stats.back_edge = stats.back_edge.max(back_edge);
+        stats.iterations += 1;
+    }
+
+    /// Mark the dispatch of the instruction at `pc`, the time elapsed since
+    /// the previous tick is charged to the loop enclosing the previously
+    /// dispatched instruction.
+    pub fn tick(&mut self, pc: ProgramCounter) {
+        let now = Instant::now();
+        if let Some(last_pc) = self.last_pc {
+            let elapsed = now.duration_since(self.last_tick);
+            if let Some(stats) = self.enclosing_loop(&last_pc) {
+                stats.time += elapsed;
+            }
+        }
+        self.interpreted += 1;
+        self.last_pc = Some(pc);
+        self.last_tick = now;
+    }
+
+    /// Charge a native trace execution that started at `header`, ran
+    /// `iterations` loop iterations and took `elapsed` to run.
+    pub fn count_native_entry(
+        &mut self,
+        header: ProgramCounter,
+        iterations: usize,
+        elapsed: Duration,
+    ) {
+        let stats = self.loops.entry(header).or_default();
+        stats.native_entries += 1;
+        stats.native_iterations += iterations;
+        stats.time += elapsed;
+        stats.native_time += elapsed;
+        self.last_pc = None;
+        self.last_tick = Instant::now();
+    }
+
+    /// Returns the innermost loop whose body contains `pc`.
+    fn enclosing_loop(
+        &mut self,
+        pc: &ProgramCounter,
+    ) -> Option<&mut LoopStats> {
+        self.loops
+            .iter_mut()
+            .filter(|(header, stats)| {
+                header.get_method_index() == pc.get_method_index()
+                    && header.get_instruction_index()
+                        <= pc.get_instruction_index()
+                    && pc.get_instruction_index() <= stats.back_edge
+            })
+            .max_by_key(|(header, _)| header.get_instruction_index())
+            .map(|(_, stats)| stats)
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "interpreted instructions : {}", self.interpreted)?;
+        writeln!(
+            f,
+            "{:<24} {:>12} {:>12} {:>8} {:>12}",
+            "loop (method:offset)", "interpreted", "native", "share", "saved"
+        )?;
+        for (header, stats) in &self.loops {
+            let saved = stats.saved().map_or_else(
+                || "-".to_string(),
+                |saved| format!("{:.2}us", saved * 1e6),
+            );
+            writeln!(
+                f,
+                "{:<24} {:>12} {:>12} {:>7.1}% {:>12}",
+                format!(
+                    "{}:{}",
+                    header.get_method_index(),
+                    header.get_instruction_index()
+                ),
+                stats.iterations,
+                stats.native_iterations,
+                stats.native_share(),
+                saved
+            )?;
+        }
+        if !self.transitions.is_empty() {
+            writeln!(f, "tier transitions :")?;
+            for transition in &self.transitions {
+                writeln!(f, "  {transition}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn charges_time_to_innermost_loop() {
+        let mut stats = Stats::new();
+        let outer = ProgramCounter::new(1, 2);
+        let inner = ProgramCounter::new(1, 6);
+        stats.count_iteration(outer, 30);
+        stats.count_iteration(inner, 20);
+        stats.count_iteration(inner, 20);
+
+        stats.tick(ProgramCounter::new(1, 10));
+        std::thread::sleep(Duration::from_millis(1));
+        stats.tick(ProgramCounter::new(1, 25));
+        std::thread::sleep(Duration::from_millis(1));
+        stats.tick(ProgramCounter::new(1, 40));
+
+        let inner_stats = stats.loop_stats(&inner).unwrap();
+        let outer_stats = stats.loop_stats(&outer).unwrap();
+        assert_eq!(inner_stats.iterations, 2);
+        assert_eq!(outer_stats.iterations, 1);
+        assert!(inner_stats.time >= Duration::from_millis(1));
+        assert!(outer_stats.time >= Duration::from_millis(1));
+        assert_eq!(stats.interpreted(), 3);
+    }
+
+    #[test]
+    fn estimates_time_saved_by_traces() {
+        let mut stats = Stats::new();
+        let header = ProgramCounter::new(1, 2);
+        stats.count_iteration(header, 20);
+        stats.count_iteration(header, 20);
+        stats.loops.get_mut(&header).unwrap().time = Duration::from_micros(20);
+        stats.count_native_entry(header, 98, Duration::from_micros(49));
+
+        let loop_stats = stats.loop_stats(&header).unwrap();
+        assert_eq!(loop_stats.native_iterations, 98);
+        assert_eq!(loop_stats.interpreted_time(), Duration::from_micros(20));
+        assert!((loop_stats.native_share() - 98.0).abs() < 1e-9);
+        // 98 iterations at 10us each in the interpreter against 49us.
+        let saved = loop_stats.saved().unwrap();
+        assert!((saved - 931e-6).abs() < 1e-9);
+        assert!(stats.to_string().contains("931.00us"));
+
+        let cold = ProgramCounter::new(1, 30);
+        stats.count_iteration(cold, 40);
+        assert_eq!(stats.loop_stats(&cold).unwrap().saved(), None);
+    }
+}
@@ -0,0 +1,376 @@
+//! Tiering policy deciding which tier runs each method.
+//!
+//! Methods start in the interpreter, where the profiler finds hot loops and
+//! the tracing JIT compiles them. Warm methods, and methods whose loops keep
+//! aborting trace recording, are promoted to the baseline compiler. Baseline
+//! methods running many loop iterations go back to the interpreter so that
+//! their loops get optimized traces, unless their recordings aborted.
+//!
+//! Code that keeps bailing out is demoted : baseline methods after repeated
+//! deopts and native traces after repeated exits before completing an
+//! iteration, and any native code after a fault. Demoted code is never
+//! promoted again.
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::options::TierThresholds;
+use crate::runtime::ProgramCounter;
+
+/// `Tier` is how a method or a loop is executed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Tier {
+    /// Interpreted, hot loops aren't compiled once demoted.
+    #[default]
+    Interpreter,
+    /// Compiled whole by the baseline compiler.
+    Baseline,
+    /// Interpreted with its hot loops running as native traces.
+    Trace,
+}
+
+impl fmt::Display for Tier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Interpreter => write!(f, "interpreter"),
+            Self::Baseline => write!(f, "baseline"),
+            Self::Trace => write!(f, "trace"),
+        }
+    }
+}
+
+/// Counter that triggered a tier transition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    Invocations,
+    RecordingAborts,
+    BackEdges,
+    Deopts,
+    Fault,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invocations => write!(f, "invocations"),
+            Self::RecordingAborts => write!(f, "recording aborts"),
+            Self::BackEdges => write!(f, "back edges"),
+            Self::Deopts => write!(f, "deopts"),
+            Self::Fault => write!(f, "fault"),
+        }
+    }
+}
+
+/// `Transition` moves the method or the loop at `pc` between tiers,
+/// methods are identified by their first instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+    pub pc: ProgramCounter,
+    pub from: Tier,
+    pub to: Tier,
+    pub reason: Reason,
+}
+
+impl fmt::Display for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{} {} -> {} ({})",
+            self.pc.get_method_index(),
+            self.pc.get_instruction_index(),
+            self.from,
+            self.to,
+            self.reason
+        )
+    }
+}
+
+/// Counters of a single method.
+#[derive(Debug, Default, Clone, Copy)]
+struct MethodProfile {
+    tier: Tier,
+    invocations: usize,
+    aborts: usize,
+    back_edges: usize,
+    deopts: usize,
+    // Demoted methods aren't compiled again.
+    demoted: bool,
+}
+
+/// `TierPolicy` counts invocations, back edges, aborted recordings and
+/// deopts and returns the transitions they trigger. The runtime applies
+/// them, transitions it can't apply are rejected.
+#[derive(Debug, Default)]
+pub struct TierPolicy {
+    thresholds: TierThresholds,
+    methods: HashMap<usize, MethodProfile>,
+    // Exits of native traces before completing an iteration.
+    trace_deopts: HashMap<ProgramCounter, usize>,
+    // Loops whose native trace was dropped.
+    demoted_loops: HashSet<ProgramCounter>,
+}
+
+impl TierPolicy {
+    pub fn new(thresholds: TierThresholds) -> Self {
+        Self {
+            thresholds,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the tier of the method at `method_index`.
+    pub fn tier(&self, method_index: usize) -> Tier {
+        self.methods
+            .get(&method_index)
+            .map_or(Tier::Interpreter, |method| method.tier)
+    }
+
+    /// Returns true if the native trace of the loop at `pc` was dropped.
+    pub fn is_demoted(&self, pc: ProgramCounter) -> bool {
+        self.demoted_loops.contains(&pc)
+    }
+
+    /// Count an invocation of the method at `method_index`.
+    pub fn count_invocation(
+        &mut self,
+        method_index: usize,
+    ) -> Option<Transition> {
+        let threshold = self.thresholds.baseline_invocations;
+        let method = self.methods.entry(method_index).or_default();
+        method.invocations += 1;
+        (method.tier == Tier::Interpreter && method.invocations >= threshold)
+            .then(|| {
+                self.promote(method_index, Tier::Baseline, Reason::Invocations)
+            })
+            .flatten()
+    }
+
+    /// Count an aborted trace recording in the method at `method_index`.
+    pub fn count_recording_abort(
+        &mut self,
+        method_index: usize,
+    ) -> Option<Transition> {
+        let threshold = self.thresholds.baseline_aborts;
+        let method = self.methods.entry(method_index).or_default();
+        method.aborts += 1;
+        (method.tier != Tier::Baseline && method.aborts >= threshold)
+            .then(|| {
+                self.promote(
+                    method_index,
+                    Tier::Baseline,
+                    Reason::RecordingAborts,
+                )
+            })
+            .flatten()
+    }
+
+    /// Count `back_edges` run by the baseline code of the method at
+    /// `method_index`.
+    pub fn count_back_edges(
+        &mut self,
+        method_index: usize,
+        back_edges: usize,
+    ) -> Option<Transition> {
+        let thresholds = self.thresholds;
+        let method = self.methods.entry(method_index).or_default();
+        method.back_edges += back_edges;
+        (method.tier == Tier::Baseline
+            && method.aborts < thresholds.baseline_aborts
+            && method.back_edges >= thresholds.trace_back_edges)
+            .then(|| self.promote(method_index, Tier::Trace, Reason::BackEdges))
+            .flatten()
+    }
+
+    /// Count a deopt of the baseline code of the method at `method_index`.
+    pub fn count_deopt(&mut self, method_index: usize) -> Option<Transition> {
+        let threshold = self.thresholds.deopts;
+        let method = self.methods.entry(method_index).or_default();
+        method.deopts += 1;
+        if method.tier != Tier::Baseline || method.deopts < threshold {
+            return None;
+        }
+        method.demoted = true;
+        Some(self.set_tier(method_index, Tier::Interpreter, Reason::Deopts))
+    }
+
+    /// Count an exit of the native trace at `pc` before it completed an
+    /// iteration.
+    pub fn count_trace_deopt(
+        &mut self,
+        pc: ProgramCounter,
+    ) -> Option<Transition> {
+        let deopts = self.trace_deopts.entry(pc).or_insert(0);
+        *deopts += 1;
+        if *deopts < self.thresholds.deopts || !self.demoted_loops.insert(pc) {
+            return None;
+        }
+        Some(Transition {
+            pc,
+            from: Tier::Trace,
+            to: Tier::Interpreter,
+            reason: Reason::Deopts,
+        })
+    }
+
+    /// Demote the native trace of the loop at `pc`, or the baseline code of
+    /// its method if the method runs in the baseline tier, after the code
+    /// raised a fault.
+    pub fn fault(&mut self, pc: ProgramCounter) -> Transition {
+        let method_index = pc.get_method_index();
+        if self.tier(method_index) == Tier::Baseline {
+            self.methods.entry(method_index).or_default().demoted = true;
+            return self.set_tier(
+                method_index,
+                Tier::Interpreter,
+                Reason::Fault,
+            );
+        }
+        self.demoted_loops.insert(pc);
+        Transition {
+            pc,
+            from: Tier::Trace,
+            to: Tier::Interpreter,
+            reason: Reason::Fault,
+        }
+    }
+
+    /// Undo `transition`, the runtime couldn't apply it. Methods that fail
+    /// to compile aren't promoted again.
+    pub fn reject(&mut self, transition: Transition) {
+        let method = self
+            .methods
+            .entry(transition.pc.get_method_index())
+            .or_default();
+        method.tier = transition.from;
+        method.demoted = true;
+    }
+
+    fn promote(
+        &mut self,
+        method_index: usize,
+        to: Tier,
+        reason: Reason,
+    ) -> Option<Transition> {
+        if self.methods.get(&method_index)?.demoted {
+            return None;
+        }
+        Some(self.set_tier(method_index, to, reason))
+    }
+
+    fn set_tier(
+        &mut self,
+        method_index: usize,
+        to: Tier,
+        reason: Reason,
+    ) -> Transition {
+        let method = self.methods.entry(method_index).or_default();
+        let from = method.tier;
+        method.tier = to;
+        Transition {
+            pc: ProgramCounter::new(method_index, 0),
+            from,
+            to,
+            reason,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> TierPolicy {
+        TierPolicy::new(TierThresholds {
+            baseline_invocations: 3,
+            baseline_aborts: 2,
+            trace_back_edges: 10,
+            deopts: 2,
+        })
+    }
+
+    #[test]
+    fn promotes_warm_methods() {
+        let mut policy = policy();
+        assert_eq!(policy.count_invocation(1), None);
+        assert_eq!(policy.count_invocation(1), None);
+        let transition = policy.count_invocation(1).unwrap();
+        assert_eq!(
+            (transition.from, transition.to, transition.reason),
+            (Tier::Interpreter, Tier::Baseline, Reason::Invocations)
+        );
+        assert_eq!(policy.tier(1), Tier::Baseline);
+        assert_eq!(policy.count_invocation(1), None);
+
+        // Loops of baseline methods are left to the tracing JIT.
+        assert_eq!(policy.count_back_edges(1, 6), None);
+        let transition = policy.count_back_edges(1, 6).unwrap();
+        assert_eq!(transition.to, Tier::Trace);
+        assert_eq!(
+            transition.to_string(),
+            "1:0 baseline -> trace (back edges)"
+        );
+
+        // Unless their recordings keep aborting.
+        policy.count_recording_abort(1);
+        let transition = policy.count_recording_abort(1).unwrap();
+        assert_eq!(
+            (transition.from, transition.to),
+            (Tier::Trace, Tier::Baseline)
+        );
+        assert_eq!(policy.count_back_edges(1, 100), None);
+    }
+
+    #[test]
+    fn demotes_on_repeated_deopts() {
+        let mut policy = policy();
+        policy.count_recording_abort(2);
+        policy.count_recording_abort(2);
+        assert_eq!(policy.tier(2), Tier::Baseline);
+        assert_eq!(policy.count_deopt(2), None);
+        let transition = policy.count_deopt(2).unwrap();
+        assert_eq!(transition.to, Tier::Interpreter);
+        // Demoted methods stay interpreted.
+        for _ in 0..10 {
+            assert_eq!(policy.count_invocation(2), None);
+            assert_eq!(policy.count_recording_abort(2), None);
+        }
+
+        let header = ProgramCounter::new(3, 4);
+        assert_eq!(policy.count_trace_deopt(header), None);
+        assert!(policy.count_trace_deopt(header).is_some());
+        assert!(policy.is_demoted(header));
+        assert_eq!(policy.count_trace_deopt(header), None);
+    }
+
+    #[test]
+    fn demotes_faulting_code() {
+        let mut policy = policy();
+        let header = ProgramCounter::new(5, 2);
+        let transition = policy.fault(header);
+        assert_eq!(transition.to_string(), "5:2 trace -> interpreter (fault)");
+        assert!(policy.is_demoted(header));
+
+        for _ in 0..3 {
+            policy.count_invocation(6);
+        }
+        let transition = policy.fault(ProgramCounter::new(6, 7));
+        assert_eq!(
+            transition.to_string(),
+            "6:0 baseline -> interpreter (fault)"
+        );
+        assert_eq!(policy.tier(6), Tier::Interpreter);
+        assert_eq!(policy.count_recording_abort(6), None);
+    }
+
+    #[test]
+    fn rejected_promotions_are_not_retried() {
+        let mut policy = policy();
+        for _ in 0..2 {
+            policy.count_invocation(4);
+        }
+        let transition = policy.count_invocation(4).unwrap();
+        policy.reject(transition);
+        assert_eq!(policy.tier(4), Tier::Interpreter);
+        assert_eq!(policy.count_recording_abort(4), None);
+        assert_eq!(policy.count_recording_abort(4), None);
+    }
+}
@@ -0,0 +1,177 @@
+//! Phase timings of a single run.
+//!
+//! The runtime measures the phases it is responsible for (interpretation,
+//! trace recording, code generation and native execution) while parsing
+//! and program construction are measured by the caller. Traces are
+//! compiled straight from their recording, there is no optimization pass
+//! to time yet.
+use std::fmt;
+use std::time::Duration;
+
+use crate::runtime::ProgramCounter;
+
+/// Monotonic clock used to measure phases, `std::time::Instant` panics on
+/// `wasm32-unknown-unknown` which has no clock so every measurement is
+/// zero there.
+#[derive(Debug, Clone, Copy)]
+pub struct Instant {
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    inner: std::time::Instant,
+}
+
+impl Instant {
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    pub fn now() -> Self {
+        Self {
+            inner: std::time::Instant::now(),
+        }
+    }
+
+    #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+    pub fn now() -> Self {
+        Self {}
+    }
+
+    /// Returns the time elapsed since the instant was taken.
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    pub fn elapsed(&self) -> Duration {
+        self.inner.elapsed()
+    }
+
+    #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+    pub fn elapsed(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    /// Returns the time elapsed from `earlier` to this instant.
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    pub fn duration_since(&self, earlier: Self) -> Duration {
+        self.inner.duration_since(earlier.inner)
+    }
+
+    #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+    pub fn duration_since(&self, _earlier: Self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+/// Compile time of a single trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceTiming {
+    /// Loop header the trace was recorded at.
+    pub pc: ProgramCounter,
+    /// Number of recorded instructions.
+    pub instructions: usize,
+    /// Time spent generating and finalizing native code.
+    pub compile: Duration,
+}
+
+/// `Timings` holds the wall-clock time spent in every phase of a run.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Timings {
+    /// Reading and parsing the class file.
+    pub parse: Duration,
+    /// Building the `Program` from the parsed class file.
+    pub program: Duration,
+    /// Interpreting bytecode, excluding the phases below.
+    pub interpret: Duration,
+    /// Recording traces of hot loops.
+    pub record: Duration,
+    /// Generating native code for recorded traces.
+    pub codegen: Duration,
+    /// Running native traces.
+    pub native: Duration,
+    /// Compile times of every trace in compilation order.
+    pub traces: Vec<TraceTiming>,
+}
+
+impl Timings {
+    /// Returns the total time spent in all phases.
+    pub fn total(&self) -> Duration {
+        self.parse
+            + self.program
+            + self.interpret
+            + self.record
+            + self.codegen
+            + self.native
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total();
+        writeln!(f, "{:<12} {:>12} {:>8}", "phase", "time", "share")?;
+        for (phase, time) in [
+            ("parse", self.parse),
+            ("program", self.program),
+            ("interpret", self.interpret),
+            ("record", self.record),
+            ("codegen", self.codegen),
+            ("native", self.native),
+            ("total", total),
+        ] {
+            let share = if total.is_zero() {
+                0.0
+            } else {
+                time.as_secs_f64() * 100.0 / total.as_secs_f64()
+            };
+            writeln!(
+                f,
+                "{phase:<12} {:>12} {share:>7.1}%",
+                format!("{time:.2?}")
+            )?;
+        }
+        if !self.traces.is_empty() {
+            writeln!(f)?;
+            writeln!(
+                f,
+                "{:<24} {:>12} {:>12}",
+                "trace (method:offset)", "instructions", "compile"
+            )?;
+            for trace in &self.traces {
+                writeln!(
+                    f,
+                    "{:<24} {:>12} {:>12}",
+                    format!(
+                        "{}:{}",
+                        trace.pc.get_method_index(),
+                        trace.pc.get_instruction_index()
+                    ),
+                    trace.instructions,
+                    format!("{:.2?}", trace.compile)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::jvm::{read_class_file, JVMParser};
+    use crate::program::Program;
+    use crate::runtime::Runtime;
+    use std::env;
+    use std::path::Path;
+
+    #[test]
+    #[cfg_attr(not(feature = "jit"), ignore = "requires the jit feature")]
+    fn times_jit_phases() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/jit/Loop100.class");
+        let class_file =
+            JVMParser::parse(&read_class_file(&path).unwrap()).unwrap();
+        let mut runtime = Runtime::new(Program::new(&class_file));
+        runtime.enable_timings();
+        runtime.run(true).unwrap();
+
+        let timings = runtime.timings().unwrap();
+        assert_eq!(timings.traces.len(), 1);
+        assert!(timings.traces[0].instructions > 0);
+        assert!(timings.codegen >= timings.traces[0].compile);
+        assert!(!timings.native.is_zero());
+        let report = timings.to_string();
+        assert!(report.contains("codegen"));
+        assert!(report.contains("trace (method:offset)"));
+    }
+}
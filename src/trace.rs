@@ -1,9 +1,12 @@
 //! Runtime tracing module for coldbrew.
 use core::fmt;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
 use crate::bytecode::OPCode;
+use crate::log;
+use crate::profiler::Profiler;
 use crate::runtime::{Instruction, ProgramCounter, Value};
+use crate::validate::{LocalType, LocalTypes};
 
 /// Trace recording involves capturing an execution trace of the program in
 /// various places. Each record entry in the trace is a tuple of (pc, inst)
@@ -13,28 +16,645 @@ use crate::runtime::{Instruction, ProgramCounter, Value};
 pub struct Record {
     pc: ProgramCounter,
     inst: Instruction,
+    memory: Option<MemoryAccess>,
+    depth: usize,
+}
+
+/// `MemoryAccess` is the heap location a recorded instruction read or wrote
+/// along with the identities observed while recording. They hold for the
+/// recorded iteration only, code relying on them has to guard them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemoryAccess {
+    /// Element `index` of `array`, an array of `component` e.g `I`.
+    Element {
+        array: u32,
+        component: String,
+        index: i32,
+    },
+    /// Length of `array`.
+    Length { array: u32 },
+    /// Field `name` of type `descriptor` declared by `class` on `object`,
+    /// `slot` is the field's slot when `object` is an instance of a program
+    /// class, see `Program::field_slot`.
+    Field {
+        object: u32,
+        class: String,
+        name: String,
+        descriptor: String,
+        slot: Option<usize>,
+    },
+}
+
+impl MemoryAccess {
+    /// Returns true if both accesses may touch the same location. Array
+    /// lengths never change so they don't alias any element or field.
+    pub fn may_alias(&self, other: &MemoryAccess) -> bool {
+        match (self, other) {
+            (
+                Self::Element {
+                    array, component, ..
+                },
+                Self::Element {
+                    array: other,
+                    component: other_component,
+                    ..
+                },
+            ) => array == other && component == other_component,
+            // Fields with a slot are told apart by it, the others by name.
+            (
+                Self::Field {
+                    object,
+                    class,
+                    name,
+                    slot,
+                    ..
+                },
+                Self::Field {
+                    object: other,
+                    class: other_class,
+                    name: other_name,
+                    slot: other_slot,
+                    ..
+                },
+            ) => {
+                object == other
+                    && match (slot, other_slot) {
+                        (Some(slot), Some(other)) => slot == other,
+                        _ => class == other_class && name == other_name,
+                    }
+            }
+            (Self::Length { array }, Self::Length { array: other }) => {
+                array == other
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for MemoryAccess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Element {
+                array,
+                component,
+                index,
+            } => write!(f, "[{component} #{array}[{index}]"),
+            Self::Length { array } => write!(f, "#{array}.length"),
+            Self::Field {
+                object,
+                class,
+                name,
+                descriptor,
+                ..
+            } => write!(f, "#{object}.{class}.{name}:{descriptor}"),
+        }
+    }
 }
 
 impl Record {
-    pub fn instruction(&self) -> Instruction {
-        self.inst.clone()
+    pub fn instruction(&self) -> &Instruction {
+        &self.inst
     }
 
     pub fn pc(&self) -> ProgramCounter {
         self.pc
     }
+
+    /// Returns the opcode of the recorded instruction.
+    pub fn opcode(&self) -> OPCode {
+        self.inst.get_mnemonic()
+    }
+
+    /// Returns the number of inlined calls the instruction executed in, zero
+    /// for the method the trace started in. A return at a non zero depth
+    /// pops the frame of an inlined call.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the local the instruction accesses, the type of the value it
+    /// loads or stores and how it's accessed.
+    fn local_access(&self) -> Option<(usize, LocalType, Access)> {
+        let (kind, access) = match self.opcode() {
+            OPCode::ILoad => (LocalType::Int, Access::Load),
+            OPCode::IStore => (LocalType::Int, Access::Store),
+            OPCode::IInc => (LocalType::Int, Access::Update),
+            OPCode::LLoad => (LocalType::Long, Access::Load),
+            OPCode::LStore => (LocalType::Long, Access::Store),
+            OPCode::FLoad => (LocalType::Float, Access::Load),
+            OPCode::FStore => (LocalType::Float, Access::Store),
+            OPCode::DLoad => (LocalType::Double, Access::Load),
+            OPCode::DStore => (LocalType::Double, Access::Store),
+            OPCode::ALoad
+            | OPCode::ALoad0
+            | OPCode::ALoad1
+            | OPCode::ALoad2
+            | OPCode::ALoad3 => (LocalType::Reference, Access::Load),
+            OPCode::AStore
+            | OPCode::AStore0
+            | OPCode::AStore1
+            | OPCode::AStore2
+            | OPCode::AStore3 => (LocalType::Reference, Access::Store),
+            _ => return None,
+        };
+        let local = match self.opcode() {
+            OPCode::ALoad0 | OPCode::AStore0 => 0,
+            OPCode::ALoad1 | OPCode::AStore1 => 1,
+            OPCode::ALoad2 | OPCode::AStore2 => 2,
+            OPCode::ALoad3 | OPCode::AStore3 => 3,
+            _ => match self.inst.nth(0)? {
+                Value::Int(local) => usize::try_from(local).ok()?,
+                _ => return None,
+            },
+        };
+        Some((local, kind, access))
+    }
+
+    /// Returns the local the instruction loads or stores along with the
+    /// type of the value.
+    pub fn local(&self) -> Option<(usize, LocalType)> {
+        self.local_access().map(|(local, kind, _)| (local, kind))
+    }
+
+    /// Returns the heap location the instruction accessed while recording.
+    pub fn memory(&self) -> Option<&MemoryAccess> {
+        self.memory.as_ref()
+    }
+
+    /// Returns true if the instruction may raise an exception. These are
+    /// implicit guards, compiled code checks whether the instruction throws
+    /// and leaves the trace through a stub raising the exception at the
+    /// instruction instead of resuming it, the operand stack is discarded
+    /// like a thrown exception does.
+    pub fn may_throw(&self) -> bool {
+        matches!(
+            self.opcode(),
+            OPCode::IDiv
+                | OPCode::IRem
+                | OPCode::LDiv
+                | OPCode::LRem
+                | OPCode::IALoad
+                | OPCode::LALoad
+                | OPCode::FALoad
+                | OPCode::DALoad
+                | OPCode::AALoad
+                | OPCode::BALoad
+                | OPCode::CALoad
+                | OPCode::SALoad
+                | OPCode::IAStore
+                | OPCode::LAStore
+                | OPCode::FAStore
+                | OPCode::DAStore
+                | OPCode::AAStore
+                | OPCode::BAStore
+                | OPCode::CAStore
+                | OPCode::SAStore
+                | OPCode::ArrayLength
+                | OPCode::NewArray
+                | OPCode::ANewArray
+                | OPCode::GetField
+                | OPCode::PutField
+                | OPCode::InvokeVirtual
+                | OPCode::InvokeInterface
+                | OPCode::AThrow
+                | OPCode::CheckCast
+        )
+    }
 }
 
 impl fmt::Display for Record {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:} @ {:}", self.inst, self.pc)
+        write!(f, "{:} @ {:}", self.inst, self.pc)?;
+        if let Some(memory) = &self.memory {
+            write!(f, " -> {memory}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the locals of the method the trace started in that the records
+/// before `index` access along with their type, in the order they're first
+/// accessed. Loops hold the locals of every record since earlier iterations
+/// ran them.
+fn exit_locals(
+    records: &[Record],
+    index: usize,
+    is_loop: bool,
+) -> Vec<(usize, LocalType)> {
+    let len = if is_loop {
+        records.len()
+    } else {
+        index.min(records.len())
+    };
+    let mut locals: Vec<(usize, LocalType)> = vec![];
+    for record in records[..len].iter().filter(|record| record.depth == 0) {
+        let Some((local, kind, _)) = record.local_access() else {
+            continue;
+        };
+        if !locals.iter().any(|(other, _)| *other == local) {
+            locals.push((local, kind));
+        }
     }
+    locals
 }
 
+/// How an instruction accesses a local variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Load,
+    Store,
+    /// Read and written back like `iinc`.
+    Update,
+}
+
+/// `RangeGuard` replaces consecutive guards that compare the same local
+/// against constants, the trace stays on its path while `low <= local <=
+/// high` and exits at `exit` otherwise. The guards it replaces are evaluated
+/// again by the interpreter after the exit so they branch as they would
+/// have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RangeGuard {
+    /// Index of the compared local.
+    pub local: i32,
+    /// Smallest value that stays on trace.
+    pub low: i32,
+    /// Largest value that stays on trace.
+    pub high: i32,
+    /// Program counter the interpreter resumes at when the check fails,
+    /// the first instruction of the first coalesced guard.
+    pub exit: ProgramCounter,
+    /// Number of guards merged into this one.
+    pub guards: usize,
+    /// Number of records covered starting at `exit`.
+    pub len: usize,
+}
+
+/// `Guard` is a conditional branch of the trace, the trace stays on the
+/// direction the branch went while recording and leaves for `exit`
+/// otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Guard {
+    /// Program counter of the branch.
+    pub pc: ProgramCounter,
+    /// Comparison the branch makes, e.g `IfICmpLt`.
+    pub condition: OPCode,
+    /// Whether the branch was taken while recording, the outcome the trace
+    /// expects.
+    pub taken: bool,
+    /// Program counter the interpreter resumes at when the trace exits.
+    pub exit: ProgramCounter,
+    /// Locals of the trace's method the trace holds when it exits along
+    /// with their type, in the order they were first accessed. Those are
+    /// the locals accessed before the branch, every local the trace
+    /// accesses for loops. The interpreter resumes at `exit` with their
+    /// values.
+    pub locals: Vec<(usize, LocalType)>,
+    /// Number of times the interpreter branched to `exit`, zero until the
+    /// trace is profiled.
+    pub count: usize,
+}
+
+/// Inclusive range of switch keys.
+pub type KeyRange = (i32, i32);
+
+/// `SwitchGuard` is a `tableswitch` or `lookupswitch` of the trace, the
+/// trace stays on the case the switch took while recording and leaves for
+/// the target of any other case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwitchGuard {
+    /// Program counter of the switch.
+    pub pc: ProgramCounter,
+    /// Target the switch jumped to while recording.
+    pub taken: ProgramCounter,
+    /// Target of the keys no case matches.
+    pub default: ProgramCounter,
+    /// Inclusive key ranges of the cases that don't jump to `default` and
+    /// their target, in key order.
+    pub cases: Vec<(i32, i32, ProgramCounter)>,
+}
+
+impl SwitchGuard {
+    /// Returns the key ranges that stay on trace, `None` when the switch
+    /// took its default and every key no case matches stays on trace.
+    pub fn taken_keys(&self) -> Option<Vec<KeyRange>> {
+        (self.taken != self.default).then(|| {
+            self.cases
+                .iter()
+                .filter(|(_, _, target)| *target == self.taken)
+                .map(|(low, high, _)| (*low, *high))
+                .collect()
+        })
+    }
+
+    /// Returns the targets other than the recorded one the switch can jump
+    /// to, each with the key ranges leading there. The default comes last
+    /// and has no ranges, it's reached by the keys no case matches.
+    pub fn exits(&self) -> Vec<(ProgramCounter, Vec<KeyRange>)> {
+        let mut exits: Vec<(ProgramCounter, Vec<KeyRange>)> = vec![];
+        for (low, high, target) in &self.cases {
+            if *target == self.taken {
+                continue;
+            }
+            match exits.iter_mut().find(|(exit, _)| exit == target) {
+                Some((_, ranges)) => ranges.push((*low, *high)),
+                None => exits.push((*target, vec![(*low, *high)])),
+            }
+        }
+        if self.taken != self.default {
+            exits.push((self.default, vec![]));
+        }
+        exits
+    }
+}
+
+/// Bound a single guard puts on a local to stay on trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bound {
+    Lower(i32),
+    Upper(i32),
+}
+
+/// `Trace` is a recorded trace, the straight line sequence of instructions
+/// executed from `start` along with the targets of its backward branches.
 #[derive(Debug, Clone)]
 pub struct Trace {
-    pub start: ProgramCounter,
-    pub trace: Vec<Record>,
+    start: ProgramCounter,
+    records: Vec<Record>,
+    inner_branch_targets: BTreeSet<ProgramCounter>,
+    outer_branch_targets: BTreeSet<ProgramCounter>,
+    range_guards: BTreeMap<ProgramCounter, RangeGuard>,
+    guards: BTreeMap<ProgramCounter, Guard>,
+    switch_guards: BTreeMap<ProgramCounter, SwitchGuard>,
+    // Types of the locals at the start of the trace proven by the verifier.
+    entry_types: BTreeMap<usize, LocalType>,
+}
+
+impl Trace {
+    /// Returns the program counter the trace starts at.
+    pub fn start(&self) -> ProgramCounter {
+        self.start
+    }
+
+    /// Returns the recorded instructions in execution order.
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// Returns an iterator over the recorded instructions.
+    pub fn iter(&self) -> std::slice::Iter<'_, Record> {
+        self.records.iter()
+    }
+
+    /// Returns the number of recorded instructions.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Returns true if no instruction was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Returns true if the trace branches back to its start.
+    pub fn is_loop(&self) -> bool {
+        self.inner_branch_targets.contains(&self.start)
+    }
+
+    /// Returns the targets of backward branches that stay inside the trace.
+    pub fn inner_branch_targets(&self) -> &BTreeSet<ProgramCounter> {
+        &self.inner_branch_targets
+    }
+
+    /// Returns the targets of backward branches that leave the trace.
+    pub fn outer_branch_targets(&self) -> &BTreeSet<ProgramCounter> {
+        &self.outer_branch_targets
+    }
+
+    /// Returns the targets of every backward branch of the trace.
+    pub fn branch_targets(&self) -> impl Iterator<Item = ProgramCounter> + '_ {
+        self.inner_branch_targets
+            .union(&self.outer_branch_targets)
+            .copied()
+    }
+
+    /// Returns the range guard starting at the record at `pc`.
+    pub fn range_guard(&self, pc: ProgramCounter) -> Option<&RangeGuard> {
+        self.range_guards.get(&pc)
+    }
+
+    /// Returns an iterator over the range guards ordered by their exit.
+    pub fn range_guards(&self) -> impl Iterator<Item = &RangeGuard> {
+        self.range_guards.values()
+    }
+
+    /// Returns the heap accesses of the trace in execution order.
+    pub fn memory_accesses(
+        &self,
+    ) -> impl Iterator<Item = (ProgramCounter, &MemoryAccess)> {
+        self.records
+            .iter()
+            .filter_map(|record| Some((record.pc, record.memory.as_ref()?)))
+    }
+
+    /// Returns true if the accesses recorded at `a` and `b` may touch the
+    /// same location, instructions that don't access the heap alias
+    /// nothing.
+    pub fn may_alias(&self, a: ProgramCounter, b: ProgramCounter) -> bool {
+        let access = |pc| {
+            self.records
+                .iter()
+                .find(|record| record.pc == pc)?
+                .memory
+                .as_ref()
+        };
+        match (access(a), access(b)) {
+            (Some(a), Some(b)) => a.may_alias(b),
+            _ => false,
+        }
+    }
+
+    /// Returns the guard of the conditional branch at `pc`.
+    pub fn guard(&self, pc: ProgramCounter) -> Option<&Guard> {
+        self.guards.get(&pc)
+    }
+
+    /// Returns an iterator over the guards ordered by their branch.
+    pub fn guards(&self) -> impl Iterator<Item = &Guard> {
+        self.guards.values()
+    }
+
+    /// Returns the locals the trace reads before storing to them along with
+    /// the type it expects them to hold, in the order they're first read.
+    /// Only the method the trace started in is considered.
+    pub fn live_in(&self) -> Vec<(usize, LocalType)> {
+        let mut live = vec![];
+        let mut stored = HashSet::new();
+        for record in self.records.iter().filter(|record| record.depth == 0) {
+            let Some((local, kind, access)) = record.local_access() else {
+                continue;
+            };
+            if access != Access::Store
+                && !stored.contains(&local)
+                && !live.iter().any(|(other, _)| *other == local)
+            {
+                live.push((local, kind));
+            }
+            if access != Access::Load {
+                stored.insert(local);
+            }
+        }
+        live
+    }
+
+    /// Returns the locals of the trace's method the trace holds when it
+    /// leaves before the record at `index`, see `Guard::locals`.
+    pub fn exit_locals(&self, index: usize) -> Vec<(usize, LocalType)> {
+        exit_locals(&self.records, index, self.is_loop())
+    }
+
+    /// Keep the types `types` of the method the trace started in proves
+    /// for the locals the trace reads, see `validate::local_types`.
+    pub fn annotate(&mut self, types: &LocalTypes) {
+        let offset = self.start.get_instruction_index();
+        self.entry_types = self
+            .live_in()
+            .into_iter()
+            .filter_map(|(local, _)| Some((local, types.get(offset, local)?)))
+            .collect();
+    }
+
+    /// Returns the type of the local at `index` when the trace is entered
+    /// as proven by the verifier, `None` if the trace wasn't annotated or
+    /// the type depends on the path taken to the trace.
+    pub fn entry_type(&self, index: usize) -> Option<LocalType> {
+        self.entry_types.get(&index).copied()
+    }
+
+    /// Returns the guard of the switch at `pc`.
+    pub fn switch_guard(&self, pc: ProgramCounter) -> Option<&SwitchGuard> {
+        self.switch_guards.get(&pc)
+    }
+
+    /// Weigh the guards with the branch outcomes counted by `profiler`.
+    pub fn profile_exits(&mut self, profiler: &Profiler) {
+        for guard in self.guards.values_mut() {
+            guard.count =
+                profiler.branch_profile(&guard.pc).map_or(0, |profile| {
+                    if guard.taken {
+                        profile.not_taken
+                    } else {
+                        profile.taken
+                    }
+                });
+        }
+    }
+
+    /// Merge runs of consecutive guards comparing the same local against
+    /// constants into range guards, returns the number of guards removed.
+    ///
+    /// Only guards that fell through while recording are merged since the
+    /// trace exits when their condition holds. The guard at the start of
+    /// the trace is left alone, exiting there would enter the trace again.
+    pub fn coalesce_guards(&mut self) -> usize {
+        self.range_guards.clear();
+        let mut removed = 0;
+        let mut index = 1;
+        while index < self.records.len() {
+            let Some((local, bound, len)) = self.bound_check(index) else {
+                index += 1;
+                continue;
+            };
+            let (mut low, mut high) = (i32::MIN, i32::MAX);
+            let mut guards = 0;
+            let mut covered = 0;
+            let mut next = Some((local, bound, len));
+            while let Some((other, bound, len)) = next {
+                if other != local {
+                    break;
+                }
+                match bound {
+                    Bound::Lower(value) => low = low.max(value),
+                    Bound::Upper(value) => high = high.min(value),
+                }
+                guards += 1;
+                covered += len;
+                next = self.bound_check(index + covered);
+            }
+            // The recorded values passed every guard so the range can't be
+            // empty, checking keeps the unsigned comparison honest.
+            if guards > 1 && low <= high {
+                let exit = self.records[index].pc;
+                self.range_guards.insert(
+                    exit,
+                    RangeGuard {
+                        local,
+                        low,
+                        high,
+                        exit,
+                        guards,
+                        len: covered,
+                    },
+                );
+                removed += guards - 1;
+            }
+            index += covered;
+        }
+        removed
+    }
+
+    /// Decode the guard starting at `index`, an `iload` followed by either
+    /// a comparison with zero or a constant and an integer comparison.
+    /// Returns the local, the bound it has to satisfy to stay on trace and
+    /// the number of records of the guard.
+    fn bound_check(&self, index: usize) -> Option<(i32, Bound, usize)> {
+        let records = self.records.get(index..)?;
+        let Some(Value::Int(local)) = records
+            .first()
+            .filter(|record| record.opcode() == OPCode::ILoad)?
+            .inst
+            .nth(0)
+        else {
+            return None;
+        };
+        let (value, branch, len) = match records.get(1)?.opcode() {
+            OPCode::BiPush | OPCode::SiPush | OPCode::Ldc => {
+                let Some(Value::Int(value)) = records[1].inst.nth(0) else {
+                    return None;
+                };
+                (value, records.get(2)?, 3)
+            }
+            _ => (0, &records[1], 2),
+        };
+        // The trace exits when the condition of the guard holds.
+        let bound = match (len, branch.opcode()) {
+            (3, OPCode::IfICmpLt) | (2, OPCode::IfLt) => Bound::Lower(value),
+            (3, OPCode::IfICmpLe) | (2, OPCode::IfLe) => {
+                Bound::Lower(value.checked_add(1)?)
+            }
+            (3, OPCode::IfICmpGt) | (2, OPCode::IfGt) => Bound::Upper(value),
+            (3, OPCode::IfICmpGe) | (2, OPCode::IfGe) => {
+                Bound::Upper(value.checked_sub(1)?)
+            }
+            _ => return None,
+        };
+        // The guard has to fall through to the next record and no backward
+        // branch may land inside it.
+        let fallthrough = records.get(len)?.pc.get_instruction_index()
+            == branch.pc.get_instruction_index() + 3;
+        let targeted = records[1..len]
+            .iter()
+            .any(|record| self.inner_branch_targets.contains(&record.pc));
+        (fallthrough && !targeted).then_some((local, bound, len))
+    }
+}
+
+impl<'a> IntoIterator for &'a Trace {
+    type Item = &'a Record;
+    type IntoIter = std::slice::Iter<'a, Record>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
 }
 
 /// Recorder is the runtime component responsible for recording traces.
@@ -42,10 +662,19 @@ pub struct Recorder {
     trace_start: ProgramCounter,
     loop_header: ProgramCounter,
     is_recording: bool,
-    last_instruction_was_branch: bool,
     pub trace: Vec<Record>,
-    inner_branch_targets: HashSet<ProgramCounter>,
-    outer_branch_targets: HashSet<ProgramCounter>,
+    inner_branch_targets: BTreeSet<ProgramCounter>,
+    outer_branch_targets: BTreeSet<ProgramCounter>,
+    // Call sites of the inlined calls being recorded and the methods they
+    // entered, innermost last.
+    inlined: Vec<(ProgramCounter, usize)>,
+    // Program counter of the switch recorded last, the next instruction is
+    // the target it jumped to.
+    switch: Option<ProgramCounter>,
+    // Targets the recorded switches jumped to keyed by their program
+    // counter, forward gotos aren't recorded so the next record may be past
+    // the target.
+    switch_targets: HashMap<ProgramCounter, ProgramCounter>,
 }
 
 impl Default for Recorder {
@@ -60,10 +689,12 @@ impl Recorder {
             trace_start: ProgramCounter::default(),
             loop_header: ProgramCounter::default(),
             is_recording: false,
-            last_instruction_was_branch: false,
             trace: Vec::new(),
-            inner_branch_targets: HashSet::new(),
-            outer_branch_targets: HashSet::new(),
+            inner_branch_targets: BTreeSet::new(),
+            outer_branch_targets: BTreeSet::new(),
+            inlined: Vec::new(),
+            switch: None,
+            switch_targets: HashMap::new(),
         }
     }
 
@@ -72,39 +703,63 @@ impl Recorder {
         self.is_recording
     }
 
-    /// Check if we finished recording a trace.
+    /// Returns the loop header of the trace being recorded or last
+    /// recorded.
+    pub fn loop_header(&self) -> ProgramCounter {
+        self.loop_header
+    }
+
+    /// Check if we finished recording a trace, the loop header has to be
+    /// reached again outside of inlined calls.
     pub fn is_done_recording(&mut self, pc: ProgramCounter) -> bool {
-        if self.trace.is_empty() {
-            return false;
-        }
-        match self.trace.last() {
-            Some(entry) => match entry.inst.get_mnemonic() {
-                OPCode::Return
-                | OPCode::IReturn
-                | OPCode::LReturn
-                | OPCode::FReturn
-                | OPCode::DReturn => {
-                    // If we found a recursive call we need to exit.
-                    if pc.get_method_index() == entry.pc.get_method_index() {
-                        self.is_recording = false;
-                        return false;
-                    }
-                    pc == self.loop_header
-                }
-                _ => pc == self.loop_header,
-            },
-            None => false,
-        }
+        !self.trace.is_empty()
+            && self.inlined.is_empty()
+            && pc == self.loop_header
     }
 
     /// Record the bytecode instruction at the given `pc` and `inst`
     /// the final recorded traces are linear, straight line code with
     /// no loops or function calls (ideally some calls could be inlined).
+    /// Field and array accesses are recorded with the `memory` location
+    /// they touched.
+    ///
+    /// Calls to methods of the program are inlined, their instructions are
+    /// recorded at the depth of the call and their returns pop the inlined
+    /// frame. Returning from the method the trace started in leaves the
+    /// loop and aborts the recording, so do recursive calls.
     ///
     /// During the recording phase if any aborting condition is met we stop
     /// recording and return. The aborting conditions are (1) jumps to outer
-    /// branches, (2) function calls or (3) conditional branches.
-    pub fn record(&mut self, pc: ProgramCounter, mut inst: Instruction) {
+    /// branches, (2) recursive calls or (3) returns from the trace's method.
+    pub fn record(
+        &mut self,
+        pc: ProgramCounter,
+        mut inst: Instruction,
+        memory: Option<MemoryAccess>,
+    ) {
+        if let Some(switch) = self.switch.take() {
+            self.switch_targets.insert(switch, pc);
+        }
+        // Calls to the library or natives don't push a frame, the next
+        // instruction is in the caller.
+        if let Some(call) = self.trace.last().filter(|last| {
+            last.opcode().is_invoke()
+                && last.pc.get_method_index() != pc.get_method_index()
+        }) {
+            let method = pc.get_method_index();
+            if method == self.trace_start.get_method_index()
+                || self.inlined.iter().any(|(_, callee)| *callee == method)
+            {
+                log::debug!(
+                    "recorder",
+                    "{pc} recursive call, aborting recording"
+                );
+                self.is_recording = false;
+                return;
+            }
+            self.inlined.push((call.pc, method));
+        }
+        let depth = self.inlined.len();
         match inst.get_mnemonic() {
             OPCode::Goto => {
                 let offset = match inst.nth(0) {
@@ -115,6 +770,10 @@ impl Recorder {
                 };
                 // Forward branch, aborting.
                 if offset > 0 {
+                    log::debug!(
+                        "recorder",
+                        "{pc} forward branch, not recorded"
+                    );
                     return;
                 } else {
                     let mut branch_target = pc;
@@ -126,16 +785,24 @@ impl Recorder {
                     }
                 }
             }
-            OPCode::IfNe
-            | OPCode::IfEq
-            | OPCode::IfGt
-            | OPCode::IfICmpGe
-            | OPCode::IfICmpGt
-            | OPCode::IfICmpLt
-            | OPCode::IfICmpLe
-            | OPCode::IfICmpNe
-            | OPCode::IfICmpEq => {
-                self.last_instruction_was_branch = true;
+            OPCode::Return
+            | OPCode::IReturn
+            | OPCode::LReturn
+            | OPCode::FReturn
+            | OPCode::DReturn
+            | OPCode::AReturn => {
+                // The frame of the innermost inlined call is popped.
+                let Some(_) = self.inlined.pop() else {
+                    log::debug!(
+                        "recorder",
+                        "{pc} return from the trace's method, aborting recording"
+                    );
+                    self.is_recording = false;
+                    return;
+                };
+            }
+            OPCode::TableSwitch | OPCode::LookupSwitch => {
+                self.switch = Some(pc);
             }
             OPCode::InvokeStatic => {
                 // Check for recursive function calls by comparing the invoked
@@ -149,6 +816,10 @@ impl Recorder {
                 if self.trace_start.get_method_index() == method_index as usize
                 {
                     // Found a recursive call, aborting.
+                    log::debug!(
+                        "recorder",
+                        "{pc} recursive call, aborting recording"
+                    );
                     self.is_recording = false;
                     return;
                 }
@@ -191,6 +862,10 @@ impl Recorder {
             | OPCode::FStore1
             | OPCode::FStore2
             | OPCode::FStore3
+            | OPCode::LStore0
+            | OPCode::LStore1
+            | OPCode::LStore2
+            | OPCode::LStore3
             | OPCode::DStore0
             | OPCode::DStore1
             | OPCode::DStore2
@@ -207,6 +882,8 @@ impl Recorder {
         self.trace.push(Record {
             pc,
             inst: inst.clone(),
+            memory,
+            depth,
         });
     }
 
@@ -324,13 +1001,21 @@ impl Recorder {
             return;
         }
         self.is_recording = true;
-        self.last_instruction_was_branch = false;
         self.trace_start = start;
         self.loop_header = loop_header;
         // Clear existing traces.
         self.trace.clear();
         self.inner_branch_targets.clear();
         self.outer_branch_targets.clear();
+        self.inlined.clear();
+        self.switch = None;
+        self.switch_targets.clear();
+    }
+
+    /// Stop recording, the instructions recorded so far are dropped with
+    /// the next recording.
+    pub fn abort(&mut self) {
+        self.is_recording = false;
     }
 
     /// Return the last recorded trace.
@@ -338,7 +1023,398 @@ impl Recorder {
         self.is_recording = false;
         Trace {
             start: self.trace_start,
-            trace: self.trace.clone(),
+            records: self.trace.clone(),
+            inner_branch_targets: self.inner_branch_targets.clone(),
+            outer_branch_targets: self.outer_branch_targets.clone(),
+            range_guards: BTreeMap::new(),
+            guards: self.guards(),
+            switch_guards: self.switch_guards(),
+            entry_types: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the guards of the recorded switches, the case a switch took
+    /// is the instruction executed after it.
+    fn switch_guards(&self) -> BTreeMap<ProgramCounter, SwitchGuard> {
+        let mut guards = BTreeMap::new();
+        for (index, record) in self.trace.iter().enumerate() {
+            let inst = &record.inst;
+            let (Some(default), Some(cases)) =
+                (inst.switch_default(), inst.switch_cases())
+            else {
+                continue;
+            };
+            let target = |offset: i32| {
+                let mut target = record.pc;
+                target.inc_instruction_index(offset);
+                target
+            };
+            let taken =
+                self.switch_targets.get(&record.pc).copied().unwrap_or_else(
+                    || {
+                        self.trace
+                            .get(index + 1)
+                            .map_or(self.trace_start, |next| next.pc)
+                    },
+                );
+            guards.insert(
+                record.pc,
+                SwitchGuard {
+                    pc: record.pc,
+                    taken,
+                    default: target(default),
+                    cases: cases
+                        .into_iter()
+                        .map(|(low, high, offset)| (low, high, target(offset)))
+                        .collect(),
+                },
+            );
         }
+        guards
+    }
+
+    /// Returns the guards of the recorded conditional branches, a branch is
+    /// taken when the next record isn't the instruction after it.
+    fn guards(&self) -> BTreeMap<ProgramCounter, Guard> {
+        let mut guards = BTreeMap::new();
+        for (index, record) in self.trace.iter().enumerate() {
+            if !record.opcode().is_conditional_branch() {
+                continue;
+            }
+            let Some(Value::Int(offset)) = record.inst.nth(0) else {
+                continue;
+            };
+            let mut fallthrough = record.pc;
+            fallthrough.inc_instruction_index(3);
+            let mut target = record.pc;
+            target.inc_instruction_index(offset);
+            let next = self
+                .trace
+                .get(index + 1)
+                .map_or(self.trace_start, |next| next.pc);
+            let taken = next != fallthrough;
+            guards.insert(
+                record.pc,
+                Guard {
+                    pc: record.pc,
+                    condition: record.opcode(),
+                    taken,
+                    exit: if taken { fallthrough } else { target },
+                    locals: exit_locals(
+                        &self.trace,
+                        index,
+                        self.inner_branch_targets.contains(&self.trace_start),
+                    ),
+                    count: 0,
+                },
+            );
+        }
+        guards
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_which_accesses_may_alias() {
+        let element = |array, component: &str, index| MemoryAccess::Element {
+            array,
+            component: component.to_string(),
+            index,
+        };
+        let field = |object, name: &str| MemoryAccess::Field {
+            object,
+            class: "Point".to_string(),
+            name: name.to_string(),
+            descriptor: "I".to_string(),
+            slot: None,
+        };
+        assert!(element(1, "I", 0).may_alias(&element(1, "I", 3)));
+        assert!(!element(1, "I", 0).may_alias(&element(2, "I", 0)));
+        assert!(
+            !element(1, "I", 0).may_alias(&MemoryAccess::Length { array: 1 })
+        );
+        assert!(field(4, "x").may_alias(&field(4, "x")));
+        assert!(!field(4, "x").may_alias(&field(4, "y")));
+        assert!(!field(4, "x").may_alias(&field(5, "x")));
+        // `Point3D.x` resolves to the `x` Point declares, both have slot 0.
+        let slotted = |class: &str, slot| MemoryAccess::Field {
+            object: 4,
+            class: class.to_string(),
+            name: "x".to_string(),
+            descriptor: "I".to_string(),
+            slot: Some(slot),
+        };
+        assert!(slotted("Point3D", 0).may_alias(&slotted("Point", 0)));
+        assert!(!slotted("Point3D", 3).may_alias(&slotted("Point", 0)));
+
+        let pc = |index| ProgramCounter::new(1, index);
+        let mut recorder = Recorder::new();
+        recorder.init(pc(0), pc(0));
+        let load = Instruction::new(OPCode::IALoad, None);
+        recorder.record(pc(0), load.clone(), Some(element(1, "I", 0)));
+        recorder.record(pc(1), load, Some(element(2, "I", 0)));
+        recorder.record(
+            pc(2),
+            Instruction::new(OPCode::IAStore, None),
+            Some(element(1, "I", 0)),
+        );
+        let trace = recorder.recording();
+        assert_eq!(trace.memory_accesses().count(), 3);
+        assert!(trace.may_alias(pc(0), pc(2)));
+        assert!(!trace.may_alias(pc(1), pc(2)));
+    }
+
+    #[test]
+    fn records_guards() {
+        let pc = |index| ProgramCounter::new(1, index);
+        let branch = |opcode, offset| {
+            Instruction::new(opcode, Some(vec![Value::Int(offset)]))
+        };
+        let mut recorder = Recorder::new();
+        recorder.init(pc(2), pc(2));
+        recorder.record(pc(2), Instruction::new(OPCode::ILoad1, None), None);
+        recorder.record(pc(3), branch(OPCode::IfEq, 12), None);
+        recorder.record(pc(6), Instruction::new(OPCode::ILoad2, None), None);
+        recorder.record(pc(7), branch(OPCode::IfNe, 6), None);
+        recorder.record(pc(13), branch(OPCode::Goto, -11), None);
+        let mut trace = recorder.recording();
+
+        let mut profiler = Profiler::new();
+        profiler.count_branch(&pc(3), false);
+        profiler.count_branch(&pc(3), true);
+        profiler.count_branch(&pc(7), false);
+        profiler.count_branch(&pc(7), false);
+        trace.profile_exits(&profiler);
+        let guards: Vec<_> = trace.guards().cloned().collect();
+        assert_eq!(
+            guards,
+            vec![
+                Guard {
+                    pc: pc(3),
+                    condition: OPCode::IfEq,
+                    taken: false,
+                    exit: pc(15),
+                    locals: vec![(1, LocalType::Int), (2, LocalType::Int)],
+                    count: 1,
+                },
+                Guard {
+                    pc: pc(7),
+                    condition: OPCode::IfNe,
+                    taken: true,
+                    exit: pc(10),
+                    locals: vec![(1, LocalType::Int), (2, LocalType::Int)],
+                    count: 2,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn records_switch_guards() {
+        let pc = |index| ProgramCounter::new(1, index);
+        // Keys 0 and 2 jump to 28, key 1 to 32 and key 3 to the default.
+        let switch = Instruction::new(
+            OPCode::TableSwitch,
+            Some(
+                [28, 40, 0, 3, 28, 32, 28, 40]
+                    .into_iter()
+                    .map(Value::Int)
+                    .collect(),
+            ),
+        );
+        let mut recorder = Recorder::new();
+        recorder.init(pc(2), pc(2));
+        recorder.record(pc(2), Instruction::new(OPCode::ILoad1, None), None);
+        recorder.record(pc(3), switch, None);
+        // The case ends with a forward goto which isn't recorded.
+        recorder.record(
+            pc(31),
+            Instruction::new(OPCode::Goto, Some(vec![Value::Int(20)])),
+            None,
+        );
+        recorder.record(
+            pc(51),
+            Instruction::new(OPCode::Goto, Some(vec![Value::Int(-49)])),
+            None,
+        );
+        let trace = recorder.recording();
+
+        let guard = trace.switch_guard(pc(3)).unwrap();
+        assert_eq!(guard.taken, pc(31));
+        assert_eq!(guard.default, pc(43));
+        assert_eq!(
+            guard.cases,
+            vec![(0, 0, pc(31)), (1, 1, pc(35)), (2, 2, pc(31))]
+        );
+        assert_eq!(guard.taken_keys(), Some(vec![(0, 0), (2, 2)]));
+        assert_eq!(
+            guard.exits(),
+            vec![(pc(35), vec![(1, 1)]), (pc(43), vec![])]
+        );
+    }
+
+    #[test]
+    fn finds_live_in_locals() {
+        let pc = |index| ProgramCounter::new(1, index);
+        let local = |opcode, index| {
+            Instruction::new(opcode, Some(vec![Value::Int(index)]))
+        };
+        let mut recorder = Recorder::new();
+        recorder.init(pc(0), pc(0));
+        recorder.record(pc(0), Instruction::new(OPCode::ILoad1, None), None);
+        recorder.record(
+            pc(1),
+            Instruction::new(OPCode::IInc, Some(vec![Value::Int(2); 2])),
+            None,
+        );
+        recorder.record(pc(4), local(OPCode::IStore, 3), None);
+        recorder.record(pc(6), local(OPCode::ILoad, 3), None);
+        recorder.record(pc(8), Instruction::new(OPCode::ALoad0, None), None);
+        recorder.record(pc(9), local(OPCode::ILoad, 1), None);
+        let trace = recorder.recording();
+        assert_eq!(
+            trace.live_in(),
+            vec![
+                (1, LocalType::Int),
+                (2, LocalType::Int),
+                (0, LocalType::Reference)
+            ]
+        );
+        assert_eq!(trace.entry_type(1), None);
+    }
+
+    #[test]
+    fn records_inlined_returns() {
+        let pc = ProgramCounter::new;
+        let inst = |opcode, operands: &[i32]| {
+            Instruction::new(
+                opcode,
+                Some(operands.iter().copied().map(Value::Int).collect()),
+            )
+        };
+        // for (..) { sum += clamp(i); } calling clamp, method 1.
+        let mut recorder = Recorder::new();
+        recorder.init(pc(2, 4), pc(2, 4));
+        for (pc, inst) in [
+            (pc(2, 4), inst(OPCode::ILoad2, &[])),
+            (pc(2, 5), inst(OPCode::InvokeStatic, &[1, 1])),
+            (pc(1, 0), inst(OPCode::ILoad0, &[])),
+            (pc(1, 1), inst(OPCode::IReturn, &[])),
+            (pc(2, 8), inst(OPCode::IStore1, &[])),
+            (pc(2, 9), inst(OPCode::Goto, &[-5])),
+        ] {
+            assert!(!recorder.is_done_recording(pc));
+            recorder.record(pc, inst, None);
+        }
+        assert!(recorder.is_recording());
+        assert!(recorder.is_done_recording(pc(2, 4)));
+        let trace = recorder.recording();
+        assert!(trace.is_loop());
+        let depths: Vec<usize> = trace.iter().map(Record::depth).collect();
+        assert_eq!(depths, vec![0, 0, 1, 1, 0, 0]);
+
+        // Returning from the method the trace started in leaves the loop.
+        recorder.init(pc(2, 4), pc(2, 4));
+        recorder.record(pc(2, 4), inst(OPCode::ILoad2, &[]), None);
+        recorder.record(pc(2, 5), inst(OPCode::IReturn, &[]), None);
+        assert!(!recorder.is_recording());
+
+        // So do calls back into a method being inlined.
+        recorder.init(pc(2, 4), pc(2, 4));
+        for (pc, inst) in [
+            (pc(2, 4), inst(OPCode::InvokeStatic, &[1, 1])),
+            (pc(1, 0), inst(OPCode::InvokeStatic, &[3, 2])),
+            (pc(3, 0), inst(OPCode::InvokeStatic, &[1, 1])),
+        ] {
+            recorder.record(pc, inst, None);
+        }
+        assert!(recorder.is_recording());
+        recorder.record(pc(1, 0), inst(OPCode::InvokeStatic, &[3, 2]), None);
+        assert!(!recorder.is_recording());
+    }
+
+    #[test]
+    fn can_read_recorded_traces() {
+        let start = ProgramCounter::new(1, 2);
+        let mut recorder = Recorder::new();
+        recorder.init(start, start);
+        recorder.record(start, Instruction::new(OPCode::ILoad1, None), None);
+        recorder.record(
+            ProgramCounter::new(1, 3),
+            Instruction::new(
+                OPCode::IInc,
+                Some(vec![Value::Int(1), Value::Int(1)]),
+            ),
+            None,
+        );
+        recorder.record(
+            ProgramCounter::new(1, 6),
+            Instruction::new(OPCode::Goto, Some(vec![Value::Int(-4)])),
+            None,
+        );
+        let trace = recorder.recording();
+
+        assert_eq!(trace.start(), start);
+        assert_eq!(trace.len(), 3);
+        assert!(trace.is_loop());
+        assert_eq!(trace.branch_targets().collect::<Vec<_>>(), vec![start]);
+        assert!(trace.outer_branch_targets().is_empty());
+        let opcodes: Vec<OPCode> = trace.iter().map(Record::opcode).collect();
+        assert_eq!(opcodes, vec![OPCode::ILoad, OPCode::IInc, OPCode::Goto]);
+        assert_eq!(
+            trace.records()[0].instruction().nth(0),
+            Some(Value::Int(1))
+        );
+    }
+
+    #[test]
+    fn coalesces_adjacent_range_checks() {
+        let start = ProgramCounter::new(1, 4);
+        let mut recorder = Recorder::new();
+        recorder.init(start, start);
+        let int = |value| Some(vec![Value::Int(value)]);
+        for (index, opcode, operands) in [
+            (4, OPCode::ILoad2, None),
+            (5, OPCode::BiPush, int(100)),
+            (7, OPCode::IfICmpGe, int(30)),
+            (10, OPCode::ILoad2, None),
+            (11, OPCode::IfLt, int(16)),
+            (14, OPCode::ILoad2, None),
+            (15, OPCode::BiPush, int(60)),
+            (17, OPCode::IfICmpGe, int(10)),
+            (20, OPCode::ILoad1, None),
+            (21, OPCode::ILoad2, None),
+            (22, OPCode::IAdd, None),
+            (23, OPCode::IStore1, None),
+            (31, OPCode::IInc, Some(vec![Value::Int(2), Value::Int(1)])),
+            (34, OPCode::Goto, int(-30)),
+        ] {
+            recorder.record(
+                ProgramCounter::new(1, index),
+                Instruction::new(opcode, operands),
+                None,
+            );
+        }
+        let mut trace = recorder.recording();
+
+        // The loop condition at the start of the trace is kept.
+        assert_eq!(trace.coalesce_guards(), 1);
+        let guards: Vec<&RangeGuard> = trace.range_guards().collect();
+        assert_eq!(
+            guards,
+            vec![&RangeGuard {
+                local: 2,
+                low: 0,
+                high: 59,
+                exit: ProgramCounter::new(1, 10),
+                guards: 2,
+                len: 5,
+            }]
+        );
+        assert!(trace.range_guard(start).is_none());
+        assert_eq!(trace.len(), 14);
     }
 }
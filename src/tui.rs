@@ -0,0 +1,645 @@
+//! Terminal user interface for browsing a parsed class file, only built
+//! with the `tui` feature.
+//!
+//! The inspector has three panes :
+//! - the constant pool with every entry resolved to a readable value.
+//! - the method list next to the disassembly of the selected method, jumps
+//!   are drawn with arrows in the gutter.
+//! - the attribute tree of the class and its methods.
+//!
+//! Rendering is a pure function of the inspector state so it can be tested
+//! without a terminal, `run` takes care of the raw mode terminal plumbing.
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::mem;
+
+use crate::bytecode::{OPCode, OperandKind};
+use crate::disasm::{disassemble, jump_arrows};
+use crate::jvm::{AttributeInfo, CPInfo, JVMClassFile, MethodInfo};
+
+/// Width of the method list in the methods pane.
+const METHOD_LIST_WIDTH: usize = 32;
+
+/// Panes of the inspector.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Pane {
+    ConstantPool,
+    Methods,
+    Attributes,
+}
+
+impl Pane {
+    const ALL: [Self; 3] =
+        [Self::ConstantPool, Self::Methods, Self::Attributes];
+
+    const fn title(self) -> &'static str {
+        match self {
+            Self::ConstantPool => "Constant pool",
+            Self::Methods => "Methods",
+            Self::Attributes => "Attributes",
+        }
+    }
+}
+
+/// Keys understood by the inspector.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    // Switch to the next pane.
+    Tab,
+    // Switch to the pane with the given number.
+    Select(usize),
+    // Scroll the disassembly.
+    ScrollUp,
+    ScrollDown,
+    Quit,
+}
+
+/// `Inspector` holds the class file being browsed and the view state.
+pub struct Inspector {
+    class_file: JVMClassFile,
+    pane: Pane,
+    // Selected row of each pane.
+    selected: [usize; 3],
+    // Scroll offset of the disassembly.
+    scroll: usize,
+    // Number of rows available to a pane, updated on every render.
+    page: usize,
+}
+
+impl Inspector {
+    pub fn new(class_file: JVMClassFile) -> Self {
+        Self {
+            class_file,
+            pane: Pane::ConstantPool,
+            selected: [1, 0, 0],
+            scroll: 0,
+            page: 20,
+        }
+    }
+
+    /// Returns the pane currently displayed.
+    pub fn pane(&self) -> Pane {
+        self.pane
+    }
+
+    /// Update the state on a key press, returns false when the inspector
+    /// should exit.
+    pub fn handle(&mut self, key: Key) -> bool {
+        let index = self.pane_index();
+        let rows = self.rows(self.pane);
+        match key {
+            Key::Quit => return false,
+            Key::Tab => self.pane = Pane::ALL[(index + 1) % Pane::ALL.len()],
+            Key::Select(n) if n < Pane::ALL.len() => self.pane = Pane::ALL[n],
+            Key::Select(_) => {}
+            Key::Up => self.move_selection(-1, rows),
+            Key::Down => self.move_selection(1, rows),
+            Key::PageUp => self.move_selection(-(self.page as isize), rows),
+            Key::PageDown => self.move_selection(self.page as isize, rows),
+            Key::ScrollUp => self.scroll = self.scroll.saturating_sub(1),
+            Key::ScrollDown => self.scroll += 1,
+        }
+        true
+    }
+
+    /// Render the inspector into `height` lines at most `width` characters
+    /// wide, the selected row is highlighted with reverse video.
+    pub fn render(&mut self, width: usize, height: usize) -> Vec<String> {
+        self.page = height.saturating_sub(2).max(1);
+        let mut lines = vec![self.header()];
+        let selected = self.selected[self.pane_index()];
+        let body = match self.pane {
+            Pane::ConstantPool => {
+                list(&self.constant_pool_lines(), selected, self.page, width)
+            }
+            Pane::Attributes => {
+                list(&self.attribute_lines(), selected, self.page, width)
+            }
+            Pane::Methods => self.methods_pane(width),
+        };
+        lines.extend(body);
+        lines.resize(height.saturating_sub(1), String::new());
+        lines.push(
+            "q quit  tab/1-3 switch pane  j/k move  J/K scroll code"
+                .to_string(),
+        );
+        lines.iter().map(|line| truncate(line, width)).collect()
+    }
+
+    fn pane_index(&self) -> usize {
+        Pane::ALL
+            .iter()
+            .position(|pane| *pane == self.pane)
+            .unwrap_or(0)
+    }
+
+    fn rows(&self, pane: Pane) -> usize {
+        match pane {
+            Pane::ConstantPool => self.class_file.constant_pool().len(),
+            Pane::Methods => self.class_file.methods().len(),
+            Pane::Attributes => self.attribute_lines().len(),
+        }
+    }
+
+    fn move_selection(&mut self, delta: isize, rows: usize) {
+        let index = self.pane_index();
+        let selected = self.selected[index].saturating_add_signed(delta);
+        self.selected[index] = selected.min(rows.saturating_sub(1));
+        if self.pane == Pane::ConstantPool {
+            // Index 0 of the constant pool is unused.
+            self.selected[index] = self.selected[index].max(1);
+        }
+        if self.pane == Pane::Methods {
+            self.scroll = 0;
+        }
+    }
+
+    fn header(&self) -> String {
+        let (major, minor) = self.class_file.version();
+        let mut header = format!(
+            " {} (version {major}.{minor}) ",
+            self.class_name(self.class_file.this_class())
+        );
+        for (n, pane) in Pane::ALL.iter().enumerate() {
+            let title = format!(" [{}] {} ", n + 1, pane.title());
+            if *pane == self.pane {
+                header.push_str(&reverse(&title));
+            } else {
+                header.push_str(&title);
+            }
+        }
+        header
+    }
+
+    fn class_name(&self, index: u16) -> String {
+        describe_constant(&self.class_file.constant_pool(), index)
+    }
+
+    /// Returns one line per constant pool entry.
+    fn constant_pool_lines(&self) -> Vec<String> {
+        let pool = self.class_file.constant_pool();
+        pool.iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                format!(
+                    "#{index:<4} {:<20} {}",
+                    constant_kind(entry),
+                    describe_constant(&pool, index as u16)
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the name and descriptor of a method.
+    fn method_signature(&self, method: &MethodInfo) -> String {
+        format!(
+            "{}{}",
+            self.class_file.utf8(method.name_index()).unwrap_or("?"),
+            self.class_file
+                .utf8(method.descriptor_index())
+                .unwrap_or("?")
+        )
+    }
+
+    /// Returns the disassembly of a method with the jump arrows gutter and
+    /// constant pool references resolved in comments.
+    fn disassembly_lines(&self, method: &MethodInfo) -> Vec<String> {
+        let Some(AttributeInfo::CodeAttribute { code, .. }) =
+            method.attributes().get("Code").cloned()
+        else {
+            return vec!["<no code>".to_string()];
+        };
+        let instructions = match disassemble(&code) {
+            Ok(instructions) => instructions,
+            Err(err) => return vec![err.to_string()],
+        };
+        let pool = self.class_file.constant_pool();
+        instructions
+            .iter()
+            .zip(jump_arrows(&instructions))
+            .map(|(inst, arrows)| {
+                let references_pool = inst.opcode == OPCode::Ldc
+                    || inst.opcode.operands().is_some_and(|kinds| {
+                        kinds.first() == Some(&OperandKind::U16)
+                    });
+                if references_pool {
+                    format!(
+                        "{arrows} {inst}  // {}",
+                        describe_constant(&pool, inst.operands[0] as u16)
+                    )
+                } else {
+                    format!("{arrows} {inst}")
+                }
+            })
+            .collect()
+    }
+
+    fn methods_pane(&self, width: usize) -> Vec<String> {
+        let methods = self.class_file.methods();
+        let selected = self.selected[1];
+        let signatures: Vec<String> = methods
+            .iter()
+            .map(|method| self.method_signature(method))
+            .collect();
+        let left = list(&signatures, selected, self.page, METHOD_LIST_WIDTH);
+        let code = methods
+            .get(selected)
+            .map(|method| self.disassembly_lines(method))
+            .unwrap_or_default();
+        let right_width = width.saturating_sub(METHOD_LIST_WIDTH + 3);
+        (0..self.page)
+            .map(|row| {
+                let name = left.get(row).map_or("", String::as_str);
+                let padding =
+                    METHOD_LIST_WIDTH.saturating_sub(visible_len(name));
+                let code = code
+                    .get(self.scroll + row)
+                    .map(|line| truncate(line, right_width))
+                    .unwrap_or_default();
+                format!("{name}{} | {code}", " ".repeat(padding))
+            })
+            .collect()
+    }
+
+    /// Returns the attribute tree of the class and its methods.
+    fn attribute_lines(&self) -> Vec<String> {
+        let pool = self.class_file.constant_pool();
+        let mut lines = vec![format!(
+            "class {}",
+            self.class_name(self.class_file.this_class())
+        )];
+        attribute_tree(&self.class_file.attributes(), &pool, 1, &mut lines);
+        for method in self.class_file.methods() {
+            lines.push(format!(
+                "  method {} (flags {:#06x})",
+                self.method_signature(&method),
+                method.access_flags()
+            ));
+            attribute_tree(&method.attributes(), &pool, 2, &mut lines);
+        }
+        lines
+    }
+}
+
+/// Append the lines of an attribute tree at the given depth, attributes
+/// are sorted by name.
+fn attribute_tree(
+    attributes: &BTreeMap<String, AttributeInfo>,
+    pool: &[CPInfo],
+    depth: usize,
+    lines: &mut Vec<String>,
+) {
+    let indent = "  ".repeat(depth);
+    for (name, attribute) in attributes {
+        match attribute {
+            AttributeInfo::CodeAttribute {
+                max_stack,
+                max_locals,
+                code,
+                exception_table,
+                attributes,
+                ..
+            } => {
+                lines.push(format!(
+                    "{indent}{name} : max_stack={max_stack} max_locals={max_locals} code_length={} exceptions={}",
+                    code.len(),
+                    exception_table.len()
+                ));
+                attribute_tree(attributes, pool, depth + 1, lines);
+            }
+            AttributeInfo::ConstantValueAttribute {
+                constant_value_index,
+                ..
+            } => lines.push(format!(
+                "{indent}{name} : {}",
+                describe_constant(pool, *constant_value_index)
+            )),
+            AttributeInfo::StackMapTableAttribute { entries, .. } => {
+                lines
+                    .push(format!("{indent}{name} : {} frames", entries.len()));
+            }
+            AttributeInfo::LineNumberTableAttribute {
+                line_numbers, ..
+            } => {
+                lines.push(format!(
+                    "{indent}{name} : {} lines",
+                    line_numbers.len()
+                ));
+            }
+            AttributeInfo::SourceFileAttribute {
+                source_file_index, ..
+            } => lines.push(format!(
+                "{indent}{name} : {}",
+                describe_constant(pool, *source_file_index)
+            )),
+            AttributeInfo::BootstrapMethodsAttribute {
+                bootstrap_methods,
+                ..
+            } => lines.push(format!(
+                "{indent}{name} : {} methods",
+                bootstrap_methods.len()
+            )),
+            AttributeInfo::NestHostAttribute {
+                host_class_index, ..
+            } => lines.push(format!(
+                "{indent}{name} : {}",
+                describe_constant(pool, *host_class_index)
+            )),
+            AttributeInfo::NestMembersAttribute { classes, .. } => {
+                let members: Vec<String> = classes
+                    .iter()
+                    .map(|index| describe_constant(pool, *index))
+                    .collect();
+                lines.push(format!("{indent}{name} : {}", members.join(", ")));
+            }
+        }
+    }
+}
+
+/// Returns the `javap` style name of a constant pool entry kind.
+fn constant_kind(entry: &CPInfo) -> &'static str {
+    match entry {
+        CPInfo::ConstantClass { .. } => "Class",
+        CPInfo::ConstantFieldRef { .. } => "Fieldref",
+        CPInfo::ConstantMethodRef { .. } => "Methodref",
+        CPInfo::ConstantInterfaceMethodRef { .. } => "InterfaceMethodref",
+        CPInfo::ConstantString { .. } => "String",
+        CPInfo::ConstantInteger { .. } => "Integer",
+        CPInfo::ConstantFloat { .. } => "Float",
+        CPInfo::ConstantLong { .. } => "Long",
+        CPInfo::ConstantDouble { .. } => "Double",
+        CPInfo::ConstantNameAndType { .. } => "NameAndType",
+        CPInfo::ConstantUtf8 { .. } => "Utf8",
+        CPInfo::ConstantMethodHandle { .. } => "MethodHandle",
+        CPInfo::ConstantMethodType { .. } => "MethodType",
+        CPInfo::ConstantInvokeDynamic { .. } => "InvokeDynamic",
+        CPInfo::Unspecified => "-",
+    }
+}
+
+/// Resolve the constant pool entry at `index` to a readable value, e.g.
+/// `java/lang/Object.<init>:()V` for a method reference.
+pub fn describe_constant(pool: &[CPInfo], index: u16) -> String {
+    let describe = |index: u16| describe_constant(pool, index);
+    match pool.get(index as usize) {
+        Some(CPInfo::ConstantUtf8 { bytes }) => bytes.clone(),
+        Some(CPInfo::ConstantClass { name_index }) => describe(*name_index),
+        Some(
+            CPInfo::ConstantFieldRef {
+                class_index,
+                name_and_type_index,
+            }
+            | CPInfo::ConstantMethodRef {
+                class_index,
+                name_and_type_index,
+            }
+            | CPInfo::ConstantInterfaceMethodRef {
+                class_index,
+                name_and_type_index,
+            },
+        ) => format!(
+            "{}.{}",
+            describe(*class_index),
+            describe(*name_and_type_index)
+        ),
+        Some(CPInfo::ConstantNameAndType {
+            name_index,
+            descriptor_index,
+        }) => {
+            format!("{}:{}", describe(*name_index), describe(*descriptor_index))
+        }
+        Some(CPInfo::ConstantString { string_index }) => {
+            format!("{:?}", describe(*string_index))
+        }
+        Some(CPInfo::ConstantInteger { bytes }) => (*bytes as i32).to_string(),
+        Some(CPInfo::ConstantFloat { bytes }) => {
+            format!("{:?}", f32::from_bits(*bytes))
+        }
+        Some(CPInfo::ConstantLong { hi_bytes, lo_bytes }) => {
+            ((u64::from(*hi_bytes) << 32 | u64::from(*lo_bytes)) as i64)
+                .to_string()
+        }
+        Some(CPInfo::ConstantDouble { hi_bytes, lo_bytes }) => format!(
+            "{:?}",
+            f64::from_bits(u64::from(*hi_bytes) << 32 | u64::from(*lo_bytes))
+        ),
+        Some(CPInfo::ConstantMethodHandle {
+            reference_kind,
+            reference_index,
+        }) => format!("{reference_kind}:{}", describe(*reference_index)),
+        Some(CPInfo::ConstantMethodType { descriptor_index }) => {
+            describe(*descriptor_index)
+        }
+        Some(CPInfo::ConstantInvokeDynamic {
+            bootstrap_method_attr_index,
+            name_and_type_index,
+        }) => format!(
+            "#{bootstrap_method_attr_index}:{}",
+            describe(*name_and_type_index)
+        ),
+        Some(CPInfo::Unspecified) | None => String::new(),
+    }
+}
+
+/// Render the window of `lines` that keeps `selected` visible.
+fn list(
+    lines: &[String],
+    selected: usize,
+    height: usize,
+    width: usize,
+) -> Vec<String> {
+    let start = selected.saturating_sub(height.saturating_sub(1));
+    lines
+        .iter()
+        .enumerate()
+        .skip(start)
+        .take(height)
+        .map(|(index, line)| {
+            let line = truncate(line, width);
+            if index == selected {
+                reverse(&line)
+            } else {
+                line
+            }
+        })
+        .collect()
+}
+
+/// Wrap `text` in reverse video escape codes.
+fn reverse(text: &str) -> String {
+    format!("\x1b[7m{text}\x1b[0m")
+}
+
+/// Returns the number of printable characters, escape codes are skipped.
+fn visible_len(text: &str) -> usize {
+    text.replace("\x1b[7m", "")
+        .replace("\x1b[0m", "")
+        .chars()
+        .count()
+}
+
+/// Truncate `text` to `width` printable characters.
+fn truncate(text: &str, width: usize) -> String {
+    if visible_len(text) <= width {
+        return text.to_string();
+    }
+    // Escape codes are dropped from truncated lines.
+    let plain = text.replace("\x1b[7m", "").replace("\x1b[0m", "");
+    let truncated: String = plain.chars().take(width).collect();
+    if text.starts_with("\x1b[7m") {
+        reverse(&truncated)
+    } else {
+        truncated
+    }
+}
+
+/// Puts the terminal in raw mode and restores it when dropped.
+struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        // SAFETY: `termios` is plain old data and both calls only write to
+        // the struct we hand them.
+        unsafe {
+            let mut termios: libc::termios = mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let original = termios;
+            libc::cfmakeraw(&mut termios);
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { original })
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: restores the attributes read in `enable`.
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+/// Returns the terminal size as `(width, height)`, defaults to 80x24.
+fn terminal_size() -> (usize, usize) {
+    // SAFETY: `winsize` is plain old data filled in by `ioctl`.
+    unsafe {
+        let mut size: libc::winsize = mem::zeroed();
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) == 0
+            && size.ws_col > 0
+        {
+            (usize::from(size.ws_col), usize::from(size.ws_row))
+        } else {
+            (80, 24)
+        }
+    }
+}
+
+/// Decode a key press from the bytes read from the terminal.
+fn decode_key(bytes: &[u8]) -> Option<Key> {
+    match bytes {
+        b"q" | b"\x1b" | b"\x03" => Some(Key::Quit),
+        b"\t" => Some(Key::Tab),
+        b"k" | b"\x1b[A" => Some(Key::Up),
+        b"j" | b"\x1b[B" => Some(Key::Down),
+        b"\x1b[5~" => Some(Key::PageUp),
+        b"\x1b[6~" => Some(Key::PageDown),
+        b"K" => Some(Key::ScrollUp),
+        b"J" => Some(Key::ScrollDown),
+        [digit @ b'1'..=b'9'] => Some(Key::Select(usize::from(digit - b'1'))),
+        _ => None,
+    }
+}
+
+/// Browse `class_file` interactively until the user quits.
+/// # Errors
+/// Returns an error if the terminal can't be put in raw mode or if reading
+/// or writing to it fails.
+pub fn run(class_file: JVMClassFile) -> io::Result<()> {
+    let mut inspector = Inspector::new(class_file);
+    let mut stdout = io::stdout();
+    let mut stdin = io::stdin();
+    let _raw_mode = RawMode::enable()?;
+    // Switch to the alternate screen and hide the cursor.
+    write!(stdout, "\x1b[?1049h\x1b[?25l")?;
+    let result = (|| -> io::Result<()> {
+        let mut buffer = [0u8; 8];
+        loop {
+            let (width, height) = terminal_size();
+            let lines = inspector.render(width, height);
+            write!(stdout, "\x1b[H\x1b[2J{}", lines.join("\r\n"))?;
+            stdout.flush()?;
+            let read = stdin.read(&mut buffer)?;
+            if read == 0 {
+                return Ok(());
+            }
+            if let Some(key) = decode_key(&buffer[..read]) {
+                if !inspector.handle(key) {
+                    return Ok(());
+                }
+            }
+        }
+    })();
+    write!(stdout, "\x1b[?25h\x1b[?1049l")?;
+    stdout.flush()?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jvm::{read_class_file, JVMParser};
+    use std::env;
+    use std::path::Path;
+
+    fn inspector() -> Inspector {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let path = Path::new(&env_var).join("support/tests/Factorial.class");
+        let bytes = read_class_file(&path).unwrap();
+        Inspector::new(JVMParser::parse(&bytes).unwrap())
+    }
+
+    #[test]
+    fn can_render_panes() {
+        let mut inspector = inspector();
+        let screen = inspector.render(120, 40).join("\n");
+        assert!(screen.contains("Factorial (version 64.0)"));
+        assert!(screen.contains("Methodref"));
+        assert!(screen.contains("java/lang/Object.<init>:()V"));
+
+        assert!(inspector.handle(Key::Select(1)));
+        assert!(inspector.handle(Key::Down));
+        assert!(inspector.handle(Key::Down));
+        let screen = inspector.render(120, 40).join("\n");
+        assert!(screen.contains("factorial(I)I"));
+        assert!(screen.contains(" 6: if_icmpgt 19"));
+        assert!(screen.contains("16: goto 4"));
+
+        assert!(inspector.handle(Key::Tab));
+        assert_eq!(inspector.pane(), Pane::Attributes);
+        let screen = inspector.render(120, 40).join("\n");
+        assert!(screen.contains("SourceFile : Factorial.java"));
+        assert!(screen.contains("Code : max_stack=2 max_locals=3"));
+        assert!(!inspector.handle(Key::Quit));
+    }
+
+    #[test]
+    fn renders_within_bounds() {
+        let mut inspector = inspector();
+        inspector.handle(Key::Select(1));
+        let lines = inspector.render(40, 10);
+        assert_eq!(lines.len(), 10);
+        assert!(lines.iter().all(|line| visible_len(line) <= 40));
+    }
+}
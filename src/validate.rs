@@ -0,0 +1,990 @@
+//! Static checks of the methods of a program.
+//!
+//! Every method descriptor is parsed, and the code of every method is
+//! decoded and walked along all of its paths. Branches and exception
+//! handlers must land on an instruction of the same method, local variables
+//! must fit in `max_locals` and the operand stack must stay within
+//! `max_stack` with the same depth wherever paths meet. Values are only
+//! tracked by size, in words, their types aren't checked.
+//!
+//! `local_types` walks the paths of a method again to infer the type each
+//! local variable holds before every instruction, traces rely on them
+//! instead of checking the types of their locals when they're entered.
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::bytecode::OPCode;
+use crate::disasm::{disassemble, DecodedInstruction};
+use crate::jvm::{CPInfo, ACC_STATIC};
+use crate::program::{Program, RuntimeConstant};
+
+/// `Diagnostic` reports a problem found in a method by `Program::validate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub class: String,
+    /// Name and descriptor of the method.
+    pub method: String,
+    /// Offset of the offending instruction, `None` for problems with the
+    /// method itself.
+    pub offset: Option<usize>,
+    pub kind: DiagnosticKind,
+}
+
+/// `DiagnosticKind` represents the problems `Program::validate` detects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// The method descriptor can't be parsed.
+    InvalidDescriptor,
+    /// The arguments don't fit in the local variables.
+    ArgumentsExceedLocals { words: usize, max_locals: u16 },
+    /// The code ends in the middle of an instruction.
+    Truncated,
+    /// The opcode isn't one the JVM defines.
+    UnknownOpcode(u8),
+    /// A branch or exception handler doesn't land on an instruction.
+    InvalidBranchTarget(usize),
+    /// Execution can run past the end of the code.
+    FallsOffCode,
+    /// The constant pool entry at this index can't be used by the
+    /// instruction.
+    InvalidConstant(usize),
+    /// The local variable at `index` is past `max_locals`.
+    InvalidLocal { index: usize, max_locals: u16 },
+    /// The instruction pops more words than the stack holds.
+    StackUnderflow,
+    /// The stack grows past `max_stack` words.
+    StackOverflow { depth: usize, max_stack: u16 },
+    /// Paths meeting at the instruction have different stack depths.
+    InconsistentStack { expected: usize, got: usize },
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.class, self.method)?;
+        if let Some(offset) = self.offset {
+            write!(f, " @ {offset}")?;
+        }
+        write!(f, " : ")?;
+        match &self.kind {
+            DiagnosticKind::InvalidDescriptor => {
+                write!(f, "invalid descriptor")
+            }
+            DiagnosticKind::ArgumentsExceedLocals { words, max_locals } => {
+                write!(
+                    f,
+                    "arguments take {words} words, max locals is {max_locals}"
+                )
+            }
+            DiagnosticKind::Truncated => write!(f, "truncated instruction"),
+            DiagnosticKind::UnknownOpcode(byte) => {
+                write!(f, "unknown opcode {byte}")
+            }
+            DiagnosticKind::InvalidBranchTarget(target) => {
+                write!(f, "invalid branch target {target}")
+            }
+            DiagnosticKind::FallsOffCode => {
+                write!(f, "execution falls off the end of the code")
+            }
+            DiagnosticKind::InvalidConstant(index) => {
+                write!(f, "invalid constant #{index}")
+            }
+            DiagnosticKind::InvalidLocal { index, max_locals } => {
+                write!(f, "local {index} is past max locals {max_locals}")
+            }
+            DiagnosticKind::StackUnderflow => write!(f, "stack underflow"),
+            DiagnosticKind::StackOverflow { depth, max_stack } => {
+                write!(f, "stack depth {depth} is past max stack {max_stack}")
+            }
+            DiagnosticKind::InconsistentStack { expected, got } => {
+                write!(f, "stack depth {got} where {expected} was expected")
+            }
+        }
+    }
+}
+
+/// Check every method of `program`, see `Program::validate`.
+pub fn validate(program: &Program) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for method_index in 0..program.methods.len() {
+        let mut report = |offset: Option<usize>, kind: DiagnosticKind| {
+            let method = &program.methods[method_index];
+            diagnostics.push(Diagnostic {
+                class: program
+                    .method_class(method_index)
+                    .unwrap_or_default()
+                    .to_string(),
+                method: format!("{}{}", method.name, method.descriptor),
+                offset,
+                kind,
+            });
+        };
+        validate_method(program, method_index, &mut report);
+    }
+    diagnostics
+}
+
+/// Check the method at `method_index`, problems are passed to `report`.
+fn validate_method(
+    program: &Program,
+    method_index: usize,
+    report: &mut impl FnMut(Option<usize>, DiagnosticKind),
+) {
+    let method = &program.methods[method_index];
+    let Some((args, _)) = method_words(&method.descriptor) else {
+        report(None, DiagnosticKind::InvalidDescriptor);
+        return;
+    };
+    let words = args + usize::from(method.access_flags & ACC_STATIC == 0);
+    if !method.code.is_empty() && words > usize::from(method.max_locals) {
+        report(
+            None,
+            DiagnosticKind::ArgumentsExceedLocals {
+                words,
+                max_locals: method.max_locals,
+            },
+        );
+    }
+    if method.code.is_empty() {
+        return;
+    }
+    let instructions = match disassemble(&method.code) {
+        Ok(instructions) => instructions,
+        Err(err) => {
+            report(Some(err.offset), DiagnosticKind::Truncated);
+            return;
+        }
+    };
+    let positions: HashMap<usize, usize> = instructions
+        .iter()
+        .enumerate()
+        .map(|(position, inst)| (inst.offset, position))
+        .collect();
+
+    // Paths to walk as (instruction position, stack depth on entry), the
+    // entry point starts with an empty stack and handlers with the thrown
+    // exception.
+    let mut pending = vec![(0, 0)];
+    for entry in program.exception_table(method_index) {
+        let handler = usize::from(entry.handler_pc());
+        match positions.get(&handler) {
+            Some(position) => pending.push((*position, 1)),
+            None => {
+                report(None, DiagnosticKind::InvalidBranchTarget(handler));
+            }
+        }
+    }
+    let mut depths: Vec<Option<usize>> = vec![None; instructions.len()];
+    while let Some((position, depth)) = pending.pop() {
+        let inst = &instructions[position];
+        let at = Some(inst.offset);
+        match depths[position] {
+            Some(expected) if expected == depth => continue,
+            Some(expected) => {
+                report(
+                    at,
+                    DiagnosticKind::InconsistentStack {
+                        expected,
+                        got: depth,
+                    },
+                );
+                continue;
+            }
+            None => depths[position] = Some(depth),
+        }
+        if let Some((index, size)) = local(inst) {
+            if index + size > usize::from(method.max_locals) {
+                report(
+                    at,
+                    DiagnosticKind::InvalidLocal {
+                        index,
+                        max_locals: method.max_locals,
+                    },
+                );
+            }
+        }
+        let (pops, pushes) = match stack_effect(program, method_index, inst) {
+            Ok(effect) => effect,
+            Err(kind) => {
+                report(at, kind);
+                continue;
+            }
+        };
+        if pops > depth {
+            report(at, DiagnosticKind::StackUnderflow);
+            continue;
+        }
+        let depth = depth - pops + pushes;
+        if depth > usize::from(method.max_stack) {
+            report(
+                at,
+                DiagnosticKind::StackOverflow {
+                    depth,
+                    max_stack: method.max_stack,
+                },
+            );
+            continue;
+        }
+        for target in &inst.targets {
+            match positions.get(target) {
+                // The return address pushed by `jsr` is only on the stack
+                // of the subroutine.
+                Some(target) => pending.push((*target, depth)),
+                None => {
+                    report(at, DiagnosticKind::InvalidBranchTarget(*target));
+                }
+            }
+        }
+        if falls_through(inst.opcode) {
+            let depth = if matches!(inst.opcode, OPCode::Jsr | OPCode::JsrW) {
+                depth - 1
+            } else {
+                depth
+            };
+            if position + 1 < instructions.len() {
+                pending.push((position + 1, depth));
+            } else {
+                report(at, DiagnosticKind::FallsOffCode);
+            }
+        }
+    }
+}
+
+/// Type of the value a local variable holds, see `local_types`. Booleans,
+/// bytes, chars and shorts are held as ints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LocalType {
+    Int,
+    Long,
+    Float,
+    Double,
+    Reference,
+}
+
+/// `LocalTypes` are the types of the local variables of a method before
+/// each of its instructions. A local has no type where it isn't initialized
+/// yet or where paths storing different types to it meet.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LocalTypes {
+    // Types of the locals keyed by instruction offset, indexed by local.
+    types: HashMap<usize, Vec<Option<LocalType>>>,
+}
+
+impl LocalTypes {
+    /// Returns the type of the local at `index` before the instruction at
+    /// `offset`.
+    pub fn get(&self, offset: usize, index: usize) -> Option<LocalType> {
+        self.types.get(&offset)?.get(index).copied().flatten()
+    }
+}
+
+/// Infer the types of the local variables of the method at `method_index`
+/// before each of its instructions, see `LocalTypes`. Arguments get the
+/// types of the descriptor and stores set the type of their local, a long
+/// or double takes two locals and only the first one has its type. Locals
+/// are untyped at the start of exception handlers.
+///
+/// Returns `None` if the method has no code or if its code can't be
+/// decoded, it's expected to validate.
+pub fn local_types(
+    program: &Program,
+    method_index: usize,
+) -> Option<LocalTypes> {
+    let method = program.methods.get(method_index)?;
+    let instructions = disassemble(&method.code).ok()?;
+    let positions: HashMap<usize, usize> = instructions
+        .iter()
+        .enumerate()
+        .map(|(position, inst)| (inst.offset, position))
+        .collect();
+    let max_locals = usize::from(method.max_locals);
+    let mut entry = vec![None; max_locals];
+    let mut index = 0;
+    if method.access_flags & ACC_STATIC == 0 {
+        entry[0] = Some(LocalType::Reference);
+        index = 1;
+    }
+    for (kind, words) in argument_types(&method.descriptor)? {
+        *entry.get_mut(index)? = Some(kind);
+        index += words;
+    }
+
+    let mut states: Vec<Option<Vec<Option<LocalType>>>> =
+        vec![None; instructions.len()];
+    let mut pending = vec![(0, entry)];
+    for handler in program.exception_table(method_index) {
+        if let Some(position) =
+            positions.get(&usize::from(handler.handler_pc()))
+        {
+            pending.push((*position, vec![None; max_locals]));
+        }
+    }
+    while let Some((position, mut locals)) = pending.pop() {
+        // States only lose types when paths meet so the walk ends.
+        if let Some(state) = &states[position] {
+            let joined: Vec<_> = state
+                .iter()
+                .zip(&locals)
+                .map(|(a, b)| if a == b { *a } else { None })
+                .collect();
+            if joined == *state {
+                continue;
+            }
+            locals = joined;
+        }
+        states[position] = Some(locals.clone());
+        let inst = &instructions[position];
+        if let (Some((index, _)), Some(kind)) = (local(inst), stored_type(inst))
+        {
+            // Overwriting the second half of a long or double leaves the
+            // first half untyped.
+            if let Some(previous) = index.checked_sub(1) {
+                if matches!(
+                    locals.get(previous),
+                    Some(Some(LocalType::Long | LocalType::Double))
+                ) {
+                    locals[previous] = None;
+                }
+            }
+            if let Some(slot) = locals.get_mut(index) {
+                *slot = Some(kind);
+            }
+            if matches!(kind, LocalType::Long | LocalType::Double) {
+                if let Some(slot) = locals.get_mut(index + 1) {
+                    *slot = None;
+                }
+            }
+        }
+        for target in &inst.targets {
+            if let Some(target) = positions.get(target) {
+                pending.push((*target, locals.clone()));
+            }
+        }
+        if falls_through(inst.opcode) && position + 1 < instructions.len() {
+            pending.push((position + 1, locals));
+        }
+    }
+    let types = instructions
+        .iter()
+        .zip(states)
+        .filter_map(|(inst, state)| Some((inst.offset, state?)))
+        .collect();
+    Some(LocalTypes { types })
+}
+
+/// Returns the type of the value the instruction stores to a local.
+fn stored_type(inst: &DecodedInstruction) -> Option<LocalType> {
+    let opcode = match inst.opcode {
+        OPCode::Wide => OPCode::from(*inst.operands.first()? as u8),
+        opcode => opcode,
+    };
+    match opcode {
+        OPCode::IStore
+        | OPCode::IStore0
+        | OPCode::IStore1
+        | OPCode::IStore2
+        | OPCode::IStore3
+        | OPCode::IInc => Some(LocalType::Int),
+        OPCode::LStore
+        | OPCode::LStore0
+        | OPCode::LStore1
+        | OPCode::LStore2
+        | OPCode::LStore3 => Some(LocalType::Long),
+        OPCode::FStore
+        | OPCode::FStore0
+        | OPCode::FStore1
+        | OPCode::FStore2
+        | OPCode::FStore3 => Some(LocalType::Float),
+        OPCode::DStore
+        | OPCode::DStore0
+        | OPCode::DStore1
+        | OPCode::DStore2
+        | OPCode::DStore3 => Some(LocalType::Double),
+        OPCode::AStore
+        | OPCode::AStore0
+        | OPCode::AStore1
+        | OPCode::AStore2
+        | OPCode::AStore3 => Some(LocalType::Reference),
+        _ => None,
+    }
+}
+
+/// Returns the type and the size in words of each argument of the method
+/// descriptor `descriptor`.
+fn argument_types(descriptor: &str) -> Option<Vec<(LocalType, usize)>> {
+    let mut rest = descriptor.strip_prefix('(')?;
+    let mut types = vec![];
+    while !rest.starts_with(')') {
+        let kind = match rest.chars().next()? {
+            'J' => LocalType::Long,
+            'F' => LocalType::Float,
+            'D' => LocalType::Double,
+            'L' | '[' => LocalType::Reference,
+            _ => LocalType::Int,
+        };
+        let (words, next) = field_type(rest)?;
+        types.push((kind, words));
+        rest = next;
+    }
+    Some(types)
+}
+
+/// Returns true if execution can continue with the next instruction.
+const fn falls_through(opcode: OPCode) -> bool {
+    !matches!(
+        opcode,
+        OPCode::Goto
+            | OPCode::GotoW
+            | OPCode::Ret
+            | OPCode::TableSwitch
+            | OPCode::LookupSwitch
+            | OPCode::IReturn
+            | OPCode::LReturn
+            | OPCode::FReturn
+            | OPCode::DReturn
+            | OPCode::AReturn
+            | OPCode::Return
+            | OPCode::AThrow
+    )
+}
+
+/// Returns the index and size in words of the local variable the
+/// instruction reads or writes.
+fn local(inst: &DecodedInstruction) -> Option<(usize, usize)> {
+    let (opcode, index) = match inst.opcode {
+        OPCode::Wide => (
+            OPCode::from(inst.operands[0] as u8),
+            inst.operands[1] as usize,
+        ),
+        opcode => {
+            (opcode, inst.operands.first().copied().unwrap_or(0) as usize)
+        }
+    };
+    let byte = opcode.byte();
+    let implicit = |first: OPCode| usize::from(byte - first.byte()) % 4;
+    match opcode {
+        OPCode::ILoad
+        | OPCode::FLoad
+        | OPCode::ALoad
+        | OPCode::IStore
+        | OPCode::FStore
+        | OPCode::AStore
+        | OPCode::IInc
+        | OPCode::Ret => Some((index, 1)),
+        OPCode::LLoad | OPCode::DLoad | OPCode::LStore | OPCode::DStore => {
+            Some((index, 2))
+        }
+        OPCode::ILoad0
+        | OPCode::ILoad1
+        | OPCode::ILoad2
+        | OPCode::ILoad3
+        | OPCode::FLoad0
+        | OPCode::FLoad1
+        | OPCode::FLoad2
+        | OPCode::FLoad3
+        | OPCode::ALoad0
+        | OPCode::ALoad1
+        | OPCode::ALoad2
+        | OPCode::ALoad3 => Some((implicit(OPCode::ILoad0), 1)),
+        OPCode::LLoad0
+        | OPCode::LLoad1
+        | OPCode::LLoad2
+        | OPCode::LLoad3
+        | OPCode::DLoad0
+        | OPCode::DLoad1
+        | OPCode::DLoad2
+        | OPCode::DLoad3 => Some((implicit(OPCode::ILoad0), 2)),
+        OPCode::IStore0
+        | OPCode::IStore1
+        | OPCode::IStore2
+        | OPCode::IStore3
+        | OPCode::FStore0
+        | OPCode::FStore1
+        | OPCode::FStore2
+        | OPCode::FStore3
+        | OPCode::AStore0
+        | OPCode::AStore1
+        | OPCode::AStore2
+        | OPCode::AStore3 => Some((implicit(OPCode::IStore0), 1)),
+        OPCode::LStore0
+        | OPCode::LStore1
+        | OPCode::LStore2
+        | OPCode::LStore3
+        | OPCode::DStore0
+        | OPCode::DStore1
+        | OPCode::DStore2
+        | OPCode::DStore3 => Some((implicit(OPCode::IStore0), 2)),
+        _ => None,
+    }
+}
+
+/// Returns the number of words the instruction pops from the stack and the
+/// number of words it pushes.
+fn stack_effect(
+    program: &Program,
+    method_index: usize,
+    inst: &DecodedInstruction,
+) -> Result<(usize, usize), DiagnosticKind> {
+    let operand = || inst.operands.first().copied().unwrap_or(0) as usize;
+    // Words of the field descriptor of the symbol at the operand.
+    let field = || {
+        program
+            .symbol_id(method_index, operand())
+            .and_then(|id| program.symbol(id))
+            .and_then(|symbol| field_words(&symbol.descriptor))
+            .ok_or(DiagnosticKind::InvalidConstant(operand()))
+    };
+    // Words of the arguments and return value of the invoked method.
+    let invoked = || {
+        let descriptor = if inst.opcode == OPCode::InvokeDynamic {
+            invoke_dynamic_descriptor(program, method_index, operand())
+        } else {
+            program
+                .symbol_id(method_index, operand())
+                .and_then(|id| program.symbol(id))
+                .map(|symbol| symbol.descriptor.as_str())
+        };
+        descriptor
+            .and_then(method_words)
+            .ok_or(DiagnosticKind::InvalidConstant(operand()))
+    };
+    let effect = match inst.opcode {
+        OPCode::Nop
+        | OPCode::IInc
+        | OPCode::Goto
+        | OPCode::GotoW
+        | OPCode::Ret
+        | OPCode::Return => (0, 0),
+        OPCode::AConstNull
+        | OPCode::IconstM1
+        | OPCode::Iconst0
+        | OPCode::Iconst1
+        | OPCode::Iconst2
+        | OPCode::Iconst3
+        | OPCode::Iconst4
+        | OPCode::Iconst5
+        | OPCode::Fconst0
+        | OPCode::Fconst1
+        | OPCode::Fconst2
+        | OPCode::BiPush
+        | OPCode::SiPush
+        | OPCode::ILoad
+        | OPCode::FLoad
+        | OPCode::ALoad
+        | OPCode::ILoad0
+        | OPCode::ILoad1
+        | OPCode::ILoad2
+        | OPCode::ILoad3
+        | OPCode::FLoad0
+        | OPCode::FLoad1
+        | OPCode::FLoad2
+        | OPCode::FLoad3
+        | OPCode::ALoad0
+        | OPCode::ALoad1
+        | OPCode::ALoad2
+        | OPCode::ALoad3
+        | OPCode::New
+        | OPCode::Jsr
+        | OPCode::JsrW => (0, 1),
+        OPCode::Lconst0
+        | OPCode::Lconst1
+        | OPCode::Dconst0
+        | OPCode::Dconst1
+        | OPCode::LLoad
+        | OPCode::DLoad
+        | OPCode::LLoad0
+        | OPCode::LLoad1
+        | OPCode::LLoad2
+        | OPCode::LLoad3
+        | OPCode::DLoad0
+        | OPCode::DLoad1
+        | OPCode::DLoad2
+        | OPCode::DLoad3 => (0, 2),
+        OPCode::Ldc | OPCode::LdcW => {
+            match program.constant(method_index, operand()) {
+                Some(
+                    RuntimeConstant::Int(_)
+                    | RuntimeConstant::Float(_)
+                    | RuntimeConstant::String(_)
+                    | RuntimeConstant::Class { .. }
+                    | RuntimeConstant::MethodType(_)
+                    | RuntimeConstant::MethodHandle { .. },
+                ) => (0, 1),
+                _ => return Err(DiagnosticKind::InvalidConstant(operand())),
+            }
+        }
+        OPCode::Ldc2W => match program.constant(method_index, operand()) {
+            Some(RuntimeConstant::Long(_) | RuntimeConstant::Double(_)) => {
+                (0, 2)
+            }
+            _ => return Err(DiagnosticKind::InvalidConstant(operand())),
+        },
+        OPCode::IALoad
+        | OPCode::FALoad
+        | OPCode::AALoad
+        | OPCode::BALoad
+        | OPCode::CALoad
+        | OPCode::SALoad
+        | OPCode::IAdd
+        | OPCode::FAdd
+        | OPCode::ISub
+        | OPCode::FSub
+        | OPCode::IMul
+        | OPCode::FMul
+        | OPCode::IDiv
+        | OPCode::FDiv
+        | OPCode::IRem
+        | OPCode::FRem
+        | OPCode::IShl
+        | OPCode::IShr
+        | OPCode::IUShr
+        | OPCode::Iand
+        | OPCode::IOr
+        | OPCode::IXor
+        | OPCode::FCmpL
+        | OPCode::FCmpG => (2, 1),
+        OPCode::LALoad | OPCode::DALoad | OPCode::Swap => (2, 2),
+        OPCode::IStore
+        | OPCode::FStore
+        | OPCode::AStore
+        | OPCode::IStore0
+        | OPCode::IStore1
+        | OPCode::IStore2
+        | OPCode::IStore3
+        | OPCode::FStore0
+        | OPCode::FStore1
+        | OPCode::FStore2
+        | OPCode::FStore3
+        | OPCode::AStore0
+        | OPCode::AStore1
+        | OPCode::AStore2
+        | OPCode::AStore3
+        | OPCode::Pop
+        | OPCode::IfEq
+        | OPCode::IfNe
+        | OPCode::IfLt
+        | OPCode::IfGe
+        | OPCode::IfGt
+        | OPCode::IfLe
+        | OPCode::IfNull
+        | OPCode::IfNonNull
+        | OPCode::TableSwitch
+        | OPCode::LookupSwitch
+        | OPCode::IReturn
+        | OPCode::FReturn
+        | OPCode::AReturn
+        | OPCode::AThrow
+        | OPCode::MonitorEnter
+        | OPCode::MonitorExit => (1, 0),
+        OPCode::LStore
+        | OPCode::DStore
+        | OPCode::LStore0
+        | OPCode::LStore1
+        | OPCode::LStore2
+        | OPCode::LStore3
+        | OPCode::DStore0
+        | OPCode::DStore1
+        | OPCode::DStore2
+        | OPCode::DStore3
+        | OPCode::Pop2
+        | OPCode::IfICmpEq
+        | OPCode::IfICmpNe
+        | OPCode::IfICmpLt
+        | OPCode::IfICmpGe
+        | OPCode::IfICmpGt
+        | OPCode::IfICmpLe
+        | OPCode::IfACmpEq
+        | OPCode::IfACmpNe
+        | OPCode::LReturn
+        | OPCode::DReturn => (2, 0),
+        OPCode::IAStore
+        | OPCode::FAStore
+        | OPCode::AAStore
+        | OPCode::BAStore
+        | OPCode::CAStore
+        | OPCode::SAStore => (3, 0),
+        OPCode::LAStore | OPCode::DAStore => (4, 0),
+        OPCode::Dup => (1, 2),
+        OPCode::DupX1 => (2, 3),
+        OPCode::DupX2 => (3, 4),
+        OPCode::Dup2 => (2, 4),
+        OPCode::Dup2X1 => (3, 5),
+        OPCode::Dup2X2 => (4, 6),
+        OPCode::LAdd
+        | OPCode::DAdd
+        | OPCode::LSub
+        | OPCode::DSub
+        | OPCode::LMul
+        | OPCode::DMul
+        | OPCode::LDiv
+        | OPCode::DDiv
+        | OPCode::LRem
+        | OPCode::DRem
+        | OPCode::Land
+        | OPCode::LOr
+        | OPCode::LXor => (4, 2),
+        OPCode::LShl | OPCode::LShr | OPCode::LUShr => (3, 2),
+        OPCode::INeg
+        | OPCode::FNeg
+        | OPCode::I2F
+        | OPCode::F2I
+        | OPCode::I2B
+        | OPCode::I2C
+        | OPCode::I2S
+        | OPCode::NewArray
+        | OPCode::ANewArray
+        | OPCode::ArrayLength
+        | OPCode::CheckCast
+        | OPCode::InstanceOf => (1, 1),
+        OPCode::LNeg | OPCode::DNeg | OPCode::L2D | OPCode::D2L => (2, 2),
+        OPCode::I2L | OPCode::I2D | OPCode::F2L | OPCode::F2D => (1, 2),
+        OPCode::L2I | OPCode::L2F | OPCode::D2I | OPCode::D2F => (2, 1),
+        OPCode::LCmp | OPCode::DCmpL | OPCode::DCmpG => (4, 1),
+        OPCode::GetStatic => (0, field()?),
+        OPCode::PutStatic => (field()?, 0),
+        OPCode::GetField => (1, field()?),
+        OPCode::PutField => (1 + field()?, 0),
+        OPCode::InvokeStatic | OPCode::InvokeDynamic => invoked()?,
+        OPCode::InvokeVirtual
+        | OPCode::InvokeSpecial
+        | OPCode::InvokeInterface => {
+            let (args, ret) = invoked()?;
+            (args + 1, ret)
+        }
+        OPCode::MultiANewArray => (inst.operands[1] as usize, 1),
+        OPCode::Wide => {
+            let modified = OPCode::from(inst.operands[0] as u8);
+            let inst = DecodedInstruction {
+                opcode: modified,
+                operands: inst.operands[1..].to_vec(),
+                ..inst.clone()
+            };
+            return stack_effect(program, method_index, &inst);
+        }
+        OPCode::Breakpoint | OPCode::Unspecified => {
+            let byte = program.code(method_index)[inst.offset];
+            return Err(DiagnosticKind::UnknownOpcode(byte));
+        }
+    };
+    Ok(effect)
+}
+
+/// Returns the method descriptor of the `ConstantInvokeDynamic` at `index`.
+fn invoke_dynamic_descriptor(
+    program: &Program,
+    method_index: usize,
+    index: usize,
+) -> Option<&str> {
+    let class = program.methods.get(method_index)?.class;
+    let pool = &program.classes[class].constant_pool;
+    let CPInfo::ConstantInvokeDynamic {
+        name_and_type_index,
+        ..
+    } = pool.get(index)?
+    else {
+        return None;
+    };
+    let CPInfo::ConstantNameAndType {
+        descriptor_index, ..
+    } = pool.get(usize::from(*name_and_type_index))?
+    else {
+        return None;
+    };
+    match pool.get(usize::from(*descriptor_index))? {
+        CPInfo::ConstantUtf8 { bytes } => Some(bytes),
+        _ => None,
+    }
+}
+
+/// Returns the words taken by the arguments and the return value of the
+/// method descriptor `descriptor`.
+pub(crate) fn method_words(descriptor: &str) -> Option<(usize, usize)> {
+    let mut rest = descriptor.strip_prefix('(')?;
+    let mut args = 0;
+    while !rest.starts_with(')') {
+        let (words, next) = field_type(rest)?;
+        args += words;
+        rest = next;
+    }
+    let rest = &rest[1..];
+    let ret = if rest == "V" { 0 } else { field_words(rest)? };
+    Some((args, ret))
+}
+
+/// Returns the words taken by a value of the field descriptor
+/// `descriptor`.
+pub(crate) fn field_words(descriptor: &str) -> Option<usize> {
+    match field_type(descriptor)? {
+        (words, "") => Some(words),
+        _ => None,
+    }
+}
+
+/// Parse the field type at the start of `descriptor`, returns its size in
+/// words and the rest of the descriptor.
+fn field_type(descriptor: &str) -> Option<(usize, &str)> {
+    let rest = &descriptor[descriptor.chars().next()?.len_utf8()..];
+    match descriptor.chars().next()? {
+        'B' | 'C' | 'F' | 'I' | 'S' | 'Z' => Some((1, rest)),
+        'J' | 'D' => Some((2, rest)),
+        'L' => {
+            let end = rest.find(';')?;
+            if end == 0 {
+                return None;
+            }
+            Some((1, &rest[end + 1..]))
+        }
+        '[' => {
+            let (_, rest) = field_type(rest)?;
+            Some((1, rest))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jvm::{read_class_file, JVMParser};
+    use std::env;
+    use std::path::Path;
+
+    #[test]
+    fn can_parse_descriptors() {
+        assert_eq!(method_words("([Ljava/lang/String;)V"), Some((1, 0)));
+        assert_eq!(method_words("(IJFD)D"), Some((6, 2)));
+        assert_eq!(method_words("()Lcom/foo/Bar;"), Some((0, 1)));
+        assert_eq!(method_words("(I"), None);
+        assert_eq!(method_words("(L;)V"), None);
+        assert_eq!(method_words("(I)Q"), None);
+        assert_eq!(field_words("[[J"), Some(1));
+        assert_eq!(field_words("II"), None);
+    }
+
+    #[test]
+    fn accepts_compiled_classes() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        for dir in [
+            "support/tests",
+            "support/integration",
+            "support/exceptions",
+            "support/linking",
+            "support/overload",
+            "support/stdin",
+            "support/clinit",
+            "support/defaults",
+            "support/reflection",
+            "support/arrays",
+            "support/handles",
+        ] {
+            let dir = Path::new(&env_var).join(dir);
+            for entry in dir.read_dir().unwrap() {
+                let path = entry.unwrap().path();
+                if path.extension().is_none_or(|ext| ext != "class") {
+                    continue;
+                }
+                let bytes = read_class_file(&path).unwrap();
+                let program = Program::new(&JVMParser::parse(&bytes).unwrap());
+                assert_eq!(program.validate(), vec![], "{}", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn reports_invalid_code() {
+        // iconst_1, goto 0
+        let program = Program::from_code(vec![4, 167, 255, 255], 0);
+        let diagnostics = program.validate();
+        let kinds: Vec<&DiagnosticKind> = diagnostics
+            .iter()
+            .map(|diagnostic| &diagnostic.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                &DiagnosticKind::ArgumentsExceedLocals {
+                    words: 1,
+                    max_locals: 0
+                },
+                &DiagnosticKind::StackOverflow {
+                    depth: 1,
+                    max_stack: 0
+                },
+            ]
+        );
+
+        // goto 2, in the middle of the goto.
+        let mut program = Program::from_code(vec![167, 0, 2, 177], 1);
+        program.methods[0].max_stack = 1;
+        assert_eq!(
+            program.validate()[0].kind,
+            DiagnosticKind::InvalidBranchTarget(2)
+        );
+        assert_eq!(
+            program.validate()[0].to_string(),
+            ".main([Ljava/lang/String;)V @ 0 : invalid branch target 2"
+        );
+
+        // iload_3, pop, iadd, return
+        let mut program = Program::from_code(vec![29, 87, 96, 177], 1);
+        program.methods[0].max_stack = 1;
+        let kinds: Vec<DiagnosticKind> = program
+            .validate()
+            .into_iter()
+            .map(|diagnostic| diagnostic.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::InvalidLocal {
+                    index: 3,
+                    max_locals: 1
+                },
+                DiagnosticKind::StackUnderflow,
+            ]
+        );
+
+        // iconst_0, ifeq 5, iconst_1, return: paths meet with 0 and 1 words.
+        let mut program = Program::from_code(vec![3, 153, 0, 4, 4, 177], 1);
+        program.methods[0].max_stack = 1;
+        assert_eq!(
+            program.validate()[0].kind,
+            DiagnosticKind::InconsistentStack {
+                expected: 1,
+                got: 0
+            }
+        );
+
+        // nop, then nothing.
+        let mut program = Program::from_code(vec![0], 1);
+        program.methods[0].max_stack = 1;
+        assert_eq!(program.validate()[0].kind, DiagnosticKind::FallsOffCode);
+    }
+
+    #[test]
+    fn infers_local_types() {
+        // iconst_0, istore_1, iconst_0, istore_2, iload_1, ifeq 11,
+        // aconst_null, astore_1, nop, return
+        let program = Program::from_code(
+            vec![3, 60, 3, 61, 27, 153, 0, 6, 1, 76, 0, 177],
+            3,
+        );
+        let types = local_types(&program, 0).unwrap();
+        assert_eq!(types.get(0, 0), Some(LocalType::Reference));
+        assert_eq!(types.get(0, 1), None);
+        assert_eq!(types.get(4, 1), Some(LocalType::Int));
+        assert_eq!(types.get(10, 1), Some(LocalType::Reference));
+        // Local 1 is an int or null depending on the path.
+        assert_eq!(types.get(11, 1), None);
+        assert_eq!(types.get(11, 2), Some(LocalType::Int));
+        assert_eq!(
+            argument_types("(IJ[ILFoo;D)V"),
+            Some(vec![
+                (LocalType::Int, 1),
+                (LocalType::Long, 2),
+                (LocalType::Reference, 1),
+                (LocalType::Reference, 1),
+                (LocalType::Double, 2),
+            ])
+        );
+    }
+}
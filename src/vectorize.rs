@@ -0,0 +1,424 @@
+//! Vectorization of simple array loops.
+//!
+//! Traces of counted loops whose body is a single elementwise store, such
+//! as `c[i] = a[i] + b[i] * k`, are rewritten into a `VectorLoop` which the
+//! JIT compiles to 128-bit SIMD code processing `LANES` elements at once.
+//! The loop has to look as follows :
+//!
+//! ```text
+//! iload i                 ; loop condition, i < n or i < a.length
+//! iload n | const | aload a, arraylength
+//! if_icmpge exit
+//! aload dst               ; body, a single store at index i
+//! iload i
+//! <expression>
+//! iastore | fastore
+//! iinc i 1
+//! goto header
+//! ```
+//!
+//! Expressions are built from `int` or `float` array loads at index `i`,
+//! constants, loop invariant `int` locals and `i` itself combined with
+//! `iadd`, `isub`, `fadd`, `fsub`, `fmul` and `fdiv`. Integer multiplication
+//! is left to the scalar compiler, SSE2 has no packed 32-bit multiply.
+use core::fmt;
+
+use crate::bytecode::OPCode;
+use crate::runtime::{ProgramCounter, Value};
+use crate::trace::{Record, Trace};
+
+/// Number of 32-bit elements in a 128-bit vector register.
+pub const LANES: usize = 4;
+
+/// Deepest expression the code generator keeps in vector registers.
+pub const MAX_DEPTH: usize = 14;
+
+/// Most distinct arrays a loop can use, their addresses are kept in
+/// registers.
+pub const MAX_ARRAYS: usize = 4;
+
+/// Type of the array elements a loop works on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Element {
+    Int,
+    Float,
+}
+
+/// Upper bound of the induction variable, the loop runs while `i` is
+/// smaller than it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopBound {
+    /// Value of an `int` local.
+    Local(usize),
+    /// Integer constant.
+    Const(i32),
+    /// Length of the array held in a local.
+    Length(usize),
+}
+
+/// Lanewise operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VectorOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// Expression computed for every element of the stored array.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    /// Element `i` of the array held in a local.
+    Load(usize),
+    /// The induction variable.
+    Index,
+    /// Loop invariant `int` local.
+    Local(usize),
+    /// Constant given by its bits.
+    Const(u32),
+    Binary(VectorOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Returns the number of vector registers needed to evaluate the
+    /// expression.
+    pub fn depth(&self) -> usize {
+        match self {
+            Self::Binary(_, lhs, rhs) => lhs.depth().max(rhs.depth() + 1),
+            _ => 1,
+        }
+    }
+
+    /// Returns true if the induction variable is part of the expression.
+    pub fn uses_index(&self) -> bool {
+        match self {
+            Self::Index => true,
+            Self::Binary(_, lhs, rhs) => lhs.uses_index() || rhs.uses_index(),
+            _ => false,
+        }
+    }
+
+    /// Push the locals holding arrays the expression loads from to
+    /// `arrays`.
+    fn arrays(&self, arrays: &mut Vec<usize>) {
+        match self {
+            Self::Load(local) if !arrays.contains(local) => {
+                arrays.push(*local);
+            }
+            Self::Binary(_, lhs, rhs) => {
+                lhs.arrays(arrays);
+                rhs.arrays(arrays);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Load(local) => write!(f, "local{local}[i]"),
+            Self::Index => write!(f, "i"),
+            Self::Local(local) => write!(f, "local{local}"),
+            Self::Const(bits) => write!(f, "{:#x}", bits),
+            Self::Binary(op, lhs, rhs) => {
+                let op = match op {
+                    VectorOp::Add => "+",
+                    VectorOp::Sub => "-",
+                    VectorOp::Mul => "*",
+                    VectorOp::Div => "/",
+                };
+                write!(f, "({lhs} {op} {rhs})")
+            }
+        }
+    }
+}
+
+/// `VectorLoop` is the widened form of a trace, every iteration of the
+/// native loop computes `LANES` consecutive elements.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VectorLoop {
+    /// Loop header, the start of the trace.
+    pub header: ProgramCounter,
+    /// Where the interpreter continues once the loop is done.
+    pub exit: ProgramCounter,
+    /// Local holding the induction variable.
+    pub index: usize,
+    pub bound: LoopBound,
+    pub element: Element,
+    /// Local holding the stored array.
+    pub store: usize,
+    pub expr: Expr,
+}
+
+impl VectorLoop {
+    /// Returns the distinct locals holding the arrays the loop reads or
+    /// writes, the stored array comes first.
+    pub fn arrays(&self) -> Vec<usize> {
+        let mut arrays = vec![self.store];
+        self.expr.arrays(&mut arrays);
+        if let LoopBound::Length(local) = self.bound {
+            if !arrays.contains(&local) {
+                arrays.push(local);
+            }
+        }
+        arrays
+    }
+}
+
+impl fmt::Display for VectorLoop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "local{}[i] = {} for i < {:?} ({:?} x {LANES})",
+            self.store, self.expr, self.bound, self.element
+        )
+    }
+}
+
+/// Returns the vectorized form of `trace` if it's a simple array loop.
+pub fn vectorize(trace: &Trace) -> Option<VectorLoop> {
+    let records = trace.records();
+    if !trace.is_loop() || records.len() < 8 {
+        return None;
+    }
+    // Loop condition.
+    let index = load_local(&records[0], OPCode::ILoad)?;
+    let (bound, branch) = match records[1].opcode() {
+        OPCode::ILoad => {
+            (LoopBound::Local(load_local(&records[1], OPCode::ILoad)?), 2)
+        }
+        OPCode::BiPush | OPCode::SiPush | OPCode::Ldc => {
+            let Some(Value::Int(value)) = records[1].instruction().nth(0)
+            else {
+                return None;
+            };
+            (LoopBound::Const(value), 2)
+        }
+        _ => {
+            let array = load_local(&records[1], OPCode::ALoad)?;
+            if records[2].opcode() != OPCode::ArrayLength {
+                return None;
+            }
+            (LoopBound::Length(array), 3)
+        }
+    };
+    if records[branch].opcode() != OPCode::IfICmpGe {
+        return None;
+    }
+    let Some(Value::Int(offset)) = records[branch].instruction().nth(0) else {
+        return None;
+    };
+    let mut exit = records[branch].pc();
+    exit.inc_instruction_index(offset);
+
+    // Increment and back edge.
+    let [.., increment, back_edge] = records else {
+        return None;
+    };
+    if increment.opcode() != OPCode::IInc
+        || increment.instruction().nth(0) != Some(Value::Int(index as i32))
+        || increment.instruction().nth(1) != Some(Value::Int(1))
+        || back_edge.opcode() != OPCode::Goto
+    {
+        return None;
+    }
+
+    // Body, evaluated symbolically.
+    let body = &records[branch + 1..records.len() - 2];
+    let (last, body) = body.split_last()?;
+    let element = match last.opcode() {
+        OPCode::IAStore => Element::Int,
+        OPCode::FAStore => Element::Float,
+        _ => return None,
+    };
+    let mut stack = vec![];
+    for record in body {
+        let operand = match record.opcode() {
+            OPCode::ALoad
+            | OPCode::ALoad0
+            | OPCode::ALoad1
+            | OPCode::ALoad2
+            | OPCode::ALoad3 => {
+                Operand::Array(load_local(record, OPCode::ALoad)?)
+            }
+            OPCode::ILoad => match load_local(record, OPCode::ILoad)? {
+                local if local == index => Operand::Index,
+                local if element == Element::Int => {
+                    Operand::Value(Expr::Local(local))
+                }
+                _ => return None,
+            },
+            OPCode::BiPush | OPCode::SiPush | OPCode::Ldc => {
+                match (record.instruction().nth(0)?, element) {
+                    (Value::Int(value), Element::Int) => {
+                        Operand::Value(Expr::Const(value as u32))
+                    }
+                    (Value::Float(value), Element::Float) => {
+                        Operand::Value(Expr::Const(value.to_bits()))
+                    }
+                    _ => return None,
+                }
+            }
+            OPCode::IALoad | OPCode::FALoad => {
+                let loaded = if record.opcode() == OPCode::IALoad {
+                    Element::Int
+                } else {
+                    Element::Float
+                };
+                let (Some(Operand::Index), Some(Operand::Array(array))) =
+                    (stack.pop(), stack.pop())
+                else {
+                    return None;
+                };
+                if loaded != element {
+                    return None;
+                }
+                Operand::Value(Expr::Load(array))
+            }
+            opcode => {
+                let op = match (opcode, element) {
+                    (OPCode::IAdd, Element::Int)
+                    | (OPCode::FAdd, Element::Float) => VectorOp::Add,
+                    (OPCode::ISub, Element::Int)
+                    | (OPCode::FSub, Element::Float) => VectorOp::Sub,
+                    (OPCode::FMul, Element::Float) => VectorOp::Mul,
+                    (OPCode::FDiv, Element::Float) => VectorOp::Div,
+                    _ => return None,
+                };
+                let rhs = stack.pop()?.value()?;
+                let lhs = stack.pop()?.value()?;
+                Operand::Value(Expr::Binary(op, Box::new(lhs), Box::new(rhs)))
+            }
+        };
+        stack.push(operand);
+    }
+    let [Operand::Array(store), Operand::Index, value] = stack.as_slice()
+    else {
+        return None;
+    };
+    let expr = value.clone().value()?;
+    // Floats would need the index converted in every lane.
+    if expr.depth() > MAX_DEPTH
+        || (element == Element::Float && expr.uses_index())
+    {
+        return None;
+    }
+    let vector_loop = VectorLoop {
+        header: trace.start(),
+        exit,
+        index,
+        bound,
+        element,
+        store: *store,
+        expr,
+    };
+    (vector_loop.arrays().len() <= MAX_ARRAYS).then_some(vector_loop)
+}
+
+/// Values on the symbolic operand stack.
+#[derive(Debug, Clone)]
+enum Operand {
+    Array(usize),
+    Index,
+    Value(Expr),
+}
+
+impl Operand {
+    /// Returns the operand as an expression, the induction variable is only
+    /// a value in integer loops.
+    fn value(self) -> Option<Expr> {
+        match self {
+            Self::Value(expr) => Some(expr),
+            Self::Index => Some(Expr::Index),
+            Self::Array(_) => None,
+        }
+    }
+}
+
+/// Returns the local loaded by `record` if it's an `iload` or an `aload`
+/// as given by `load`, the recorder already rewrote `iload_<n>`.
+fn load_local(record: &Record, load: OPCode) -> Option<usize> {
+    match (record.opcode(), load) {
+        (OPCode::ALoad0, OPCode::ALoad) => Some(0),
+        (OPCode::ALoad1, OPCode::ALoad) => Some(1),
+        (OPCode::ALoad2, OPCode::ALoad) => Some(2),
+        (OPCode::ALoad3, OPCode::ALoad) => Some(3),
+        (opcode, load) if opcode == load => {
+            match record.instruction().nth(0)? {
+                Value::Int(local) => usize::try_from(local).ok(),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::Instruction;
+    use crate::trace::Recorder;
+
+    /// Record `c[i] = a[i] <op> b[i]` with `i` in local 4, `n` in local 0
+    /// and the arrays in locals 1 to 3.
+    fn record(op: OPCode) -> Trace {
+        let start = ProgramCounter::new(1, 10);
+        let mut recorder = Recorder::new();
+        recorder.init(start, start);
+        let local = |value| Some(vec![Value::Int(value)]);
+        for (index, opcode, operands) in [
+            (10, OPCode::ILoad, local(4)),
+            (12, OPCode::ILoad0, None),
+            (13, OPCode::IfICmpGe, local(18)),
+            (16, OPCode::ALoad3, None),
+            (17, OPCode::ILoad, local(4)),
+            (19, OPCode::ALoad1, None),
+            (20, OPCode::ILoad, local(4)),
+            (22, OPCode::IALoad, None),
+            (23, OPCode::ALoad2, None),
+            (24, OPCode::ILoad, local(4)),
+            (26, OPCode::IALoad, None),
+            (27, op, None),
+            (28, OPCode::IAStore, None),
+            (29, OPCode::IInc, Some(vec![Value::Int(4), Value::Int(1)])),
+            (32, OPCode::Goto, local(-22)),
+        ] {
+            recorder.record(
+                ProgramCounter::new(1, index),
+                Instruction::new(opcode, operands),
+                None,
+            );
+        }
+        recorder.recording()
+    }
+
+    #[test]
+    fn vectorizes_elementwise_loops() {
+        let vector_loop = vectorize(&record(OPCode::IAdd)).unwrap();
+        assert_eq!(
+            vector_loop,
+            VectorLoop {
+                header: ProgramCounter::new(1, 10),
+                exit: ProgramCounter::new(1, 31),
+                index: 4,
+                bound: LoopBound::Local(0),
+                element: Element::Int,
+                store: 3,
+                expr: Expr::Binary(
+                    VectorOp::Add,
+                    Box::new(Expr::Load(1)),
+                    Box::new(Expr::Load(2))
+                ),
+            }
+        );
+        assert_eq!(vector_loop.arrays(), vec![3, 1, 2]);
+        assert_eq!(vector_loop.expr.depth(), 2);
+    }
+
+    #[test]
+    fn leaves_integer_multiplication_scalar() {
+        assert_eq!(vectorize(&record(OPCode::IMul)), None);
+        assert_eq!(vectorize(&record(OPCode::FAdd)), None);
+    }
+}
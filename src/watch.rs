@@ -0,0 +1,208 @@
+//! Watch mode, re-runs a class file every time it changes on disk.
+//!
+//! The file's modification time is polled, which avoids depending on
+//! platform specific notification APIs and is plenty for an edit, compile
+//! and run loop. Every run goes through the interpreter and the JIT and is
+//! compared with the previous successful run.
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::bench::{self, BenchReport, Measurement};
+
+/// `FileWatcher` detects changes to a file by polling its modification
+/// time.
+#[derive(Debug, Clone)]
+pub struct FileWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl FileWatcher {
+    /// Watch `path`, the first call to `changed` reports a change if the
+    /// file exists.
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            modified: None,
+        }
+    }
+
+    /// Returns true if the file was modified since the last call.
+    pub fn changed(&mut self) -> bool {
+        let Ok(modified) = fs::metadata(&self.path).and_then(|m| m.modified())
+        else {
+            return false;
+        };
+        if self.modified == Some(modified) {
+            return false;
+        }
+        self.modified = Some(modified);
+        true
+    }
+}
+
+/// Comparison of a run with the previous one.
+#[derive(Debug, Clone)]
+pub struct RunComparison<'a> {
+    pub generation: usize,
+    pub previous: Option<&'a BenchReport>,
+    pub current: &'a BenchReport,
+}
+
+impl fmt::Display for RunComparison<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "[run {}] {}",
+            self.generation,
+            self.current.path.display()
+        )?;
+        let modes = [
+            (
+                "interpreter",
+                &self.current.interpreter,
+                self.previous.map(|report| &report.interpreter),
+            ),
+            (
+                "jit",
+                &self.current.jit,
+                self.previous.map(|report| &report.jit),
+            ),
+        ];
+        for (mode, current, previous) in modes {
+            write!(
+                f,
+                "  {mode:<12} {:>10} {:>8} instructions",
+                format!("{:.2?}", current.phases.run),
+                current.stats.interpreted()
+            )?;
+            if let Some(previous) = previous {
+                write!(f, "  (was {})", summarize(previous, current))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Summarize a previous measurement relative to the current one.
+fn summarize(previous: &Measurement, current: &Measurement) -> String {
+    let (old, new) = (previous.phases.run, current.phases.run);
+    let change = if old.is_zero() {
+        0.0
+    } else {
+        (new.as_secs_f64() - old.as_secs_f64()) / old.as_secs_f64() * 100.0
+    };
+    format!(
+        "{old:.2?}, {change:+.1}%, {} instructions",
+        previous.stats.interpreted()
+    )
+}
+
+/// `Watch` re-runs a class file when it changes and keeps the previous
+/// run's statistics around.
+pub struct Watch {
+    watcher: FileWatcher,
+    generation: usize,
+    previous: Option<BenchReport>,
+}
+
+impl Watch {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            watcher: FileWatcher::new(path),
+            generation: 0,
+            previous: None,
+        }
+    }
+
+    /// Returns the last successful run.
+    pub fn previous(&self) -> Option<&BenchReport> {
+        self.previous.as_ref()
+    }
+
+    /// Run the class file if it changed since the last poll and return the
+    /// comparison with the previous run, returns `None` if nothing changed.
+    /// # Errors
+    /// Returns an error if the class file can't be parsed or fails to run,
+    /// the previous run is kept for the next comparison.
+    pub fn poll(&mut self) -> Option<Result<String, Box<dyn Error>>> {
+        if !self.watcher.changed() {
+            return None;
+        }
+        self.generation += 1;
+        let report = match bench::run(&self.watcher.path, 1) {
+            Ok(report) => report,
+            Err(err) => return Some(Err(err)),
+        };
+        let comparison = RunComparison {
+            generation: self.generation,
+            previous: self.previous.as_ref(),
+            current: &report,
+        }
+        .to_string();
+        self.previous = Some(report);
+        Some(Ok(comparison))
+    }
+}
+
+/// Watch `path` forever, polling every `interval` and writing the result of
+/// every run to `out`.
+/// # Errors
+/// Returns an error if writing to `out` fails.
+pub fn run<W: Write>(
+    path: &Path,
+    interval: Duration,
+    out: &mut W,
+) -> std::io::Result<()> {
+    let mut watch = Watch::new(path);
+    writeln!(out, "Watching {} (Ctrl-C to exit)", path.display())?;
+    loop {
+        match watch.poll() {
+            Some(Ok(comparison)) => write!(out, "{comparison}")?,
+            Some(Err(err)) => writeln!(out, "Error : {err}")?,
+            None => {}
+        }
+        out.flush()?;
+        thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::File;
+
+    #[test]
+    fn reruns_on_change() {
+        let env_var = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let source = Path::new(&env_var).join("support/jit/Loop100.class");
+        let path = env::temp_dir().join("coldbrew-watch/Loop100.class");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::copy(&source, &path).unwrap();
+
+        let mut watch = Watch::new(&path);
+        let first = watch.poll().unwrap().unwrap();
+        assert!(first.starts_with("[run 1]"));
+        assert!(!first.contains("was"));
+        assert!(watch.poll().is_none());
+
+        let later = SystemTime::now() + Duration::from_secs(1);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        let second = watch.poll().unwrap().unwrap();
+        assert!(second.starts_with("[run 2]"));
+        assert!(second.contains("was"));
+        assert!(watch.previous().is_some());
+    }
+}
@@ -0,0 +1,42 @@
+public class Narrowing {
+    static int sum(byte[] bytes) {
+        int total = 0;
+        for (int i = 0; i < 4; i++) {
+            total += bytes[i];
+        }
+        return total;
+    }
+
+    public static void main(String[] args) {
+        int big = 200;
+        System.out.println((byte) big);
+        System.out.println((short) 70000);
+        System.out.println((int) (char) -1);
+        byte b = -100;
+        System.out.println(b);
+
+        byte[] bytes = new byte[4];
+        bytes[0] = (byte) 255;
+        bytes[1] = 127;
+        bytes[2] = (byte) 128;
+        bytes[3] = 1;
+        System.out.println(sum(bytes));
+
+        char[] chars = new char[3];
+        chars[0] = 'h';
+        chars[1] = 'i';
+        chars[2] = (char) 65535;
+        System.out.println((int) chars[2]);
+        chars[2] = '!';
+        System.out.println(chars);
+
+        short[] shorts = new short[2];
+        shorts[0] = (short) 40000;
+        System.out.println(shorts[0] + shorts[1]);
+
+        boolean[] flags = new boolean[2];
+        flags[1] = true;
+        System.out.println(flags[0]);
+        System.out.println(flags[1]);
+    }
+}
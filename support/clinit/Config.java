@@ -0,0 +1,11 @@
+public class Config {
+    static int limit;
+
+    static {
+        limit = 10;
+    }
+
+    public static void main(String[] args) {
+        System.out.println(limit);
+    }
+}
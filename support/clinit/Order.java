@@ -0,0 +1,43 @@
+class Base {
+    static int created;
+
+    static {
+        System.out.println("Base");
+    }
+}
+
+class Middle extends Base {
+}
+
+class Leaf extends Middle {
+    static int value = 7;
+
+    static {
+        System.out.println("Leaf");
+    }
+}
+
+class Sibling extends Base {
+    static {
+        System.out.println("Sibling");
+    }
+
+    static int twice(int x) {
+        return 2 * x;
+    }
+}
+
+public class Order {
+    static {
+        System.out.println("Order");
+    }
+
+    public static void main(String[] args) {
+        System.out.println("main");
+        new Middle();
+        System.out.println(Leaf.value);
+        System.out.println(Sibling.twice(4));
+        Base.created++;
+        System.out.println(Base.created);
+    }
+}
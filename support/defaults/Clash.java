@@ -0,0 +1,4 @@
+// Loud was compiled without greet, once it gains a default method Clash
+// inherits two unrelated defaults.
+public class Clash implements Greeter, Loud {
+}
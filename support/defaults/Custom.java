@@ -0,0 +1,5 @@
+public class Custom implements Greeter {
+    public int greet() {
+        return 4;
+    }
+}
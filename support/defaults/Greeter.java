@@ -0,0 +1,5 @@
+public interface Greeter {
+    default int greet() {
+        return 1;
+    }
+}
@@ -0,0 +1,10 @@
+// Clash.greet only fails once it's called, the error can be caught.
+public class Guarded {
+    public static void main(String[] args) {
+        try {
+            System.out.println(new Clash().greet());
+        } catch (IncompatibleClassChangeError e) {
+            System.out.println(-1);
+        }
+    }
+}
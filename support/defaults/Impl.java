@@ -0,0 +1,3 @@
+// Polite.greet is more specific than Greeter.greet.
+public class Impl implements Polite, Greeter {
+}
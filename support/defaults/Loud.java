@@ -0,0 +1,5 @@
+public interface Loud {
+    default int greet() {
+        return 3;
+    }
+}
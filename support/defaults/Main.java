@@ -0,0 +1,16 @@
+public class Main {
+    static int polite(Impl impl) {
+        return impl.greet();
+    }
+
+    static int custom(Custom custom) {
+        return custom.greet();
+    }
+
+    static int clash(Clash clash) {
+        return clash.greet();
+    }
+
+    public static void main(String[] args) {
+    }
+}
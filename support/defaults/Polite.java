@@ -0,0 +1,5 @@
+public interface Polite extends Greeter {
+    default int greet() {
+        return 2;
+    }
+}
@@ -0,0 +1,18 @@
+public class Trace {
+    static int get(byte[] bytes, int index) {
+        return bytes[index];
+    }
+
+    static int sum(byte[] bytes) {
+        int total = 0;
+        for (int i = 0; i <= 2; i++) {
+            total += get(bytes, i);
+        }
+        return total;
+    }
+
+    public static void main(String[] args) {
+        byte[] bytes = new byte[2];
+        System.out.println(sum(bytes));
+    }
+}
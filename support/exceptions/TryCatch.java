@@ -0,0 +1,11 @@
+public class TryCatch {
+    public static void main(String[] args) {
+        int x = 10;
+        try {
+            x = x / (x - 10);
+        } catch (ArithmeticException e) {
+            x = -1;
+        }
+        System.out.println(x);
+    }
+}
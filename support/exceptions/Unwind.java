@@ -0,0 +1,19 @@
+public class Unwind {
+    static int divide(int a, int b) {
+        return a / b;
+    }
+
+    static int half(int a, int b) {
+        return divide(a, b) / 2;
+    }
+
+    public static void main(String[] args) {
+        int x;
+        try {
+            x = half(10, 0);
+        } catch (ArithmeticException e) {
+            x = -1;
+        }
+        System.out.println(x);
+    }
+}
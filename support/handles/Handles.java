@@ -0,0 +1,29 @@
+import java.lang.invoke.MethodHandle;
+import java.util.function.IntBinaryOperator;
+import java.util.function.Supplier;
+
+public class Handles {
+    static int add(int a, int b) {
+        return a + b;
+    }
+
+    static int exact(MethodHandle handle, int a, int b) throws Throwable {
+        return (int) handle.invokeExact(a, b);
+    }
+
+    static int loose(MethodHandle handle, short a, int b) throws Throwable {
+        return (int) handle.invoke(a, b);
+    }
+
+    static String name(MethodHandle handle, Class<?> c) throws Throwable {
+        return (String) handle.invokeExact(c);
+    }
+
+    public static void main(String[] args) throws Throwable {
+        // Method references put handles to `add` and `getName` in the
+        // constant pool.
+        IntBinaryOperator op = Handles::add;
+        Supplier<String> name = Handles.class::getName;
+        System.out.println(op.applyAsInt(1, 2));
+    }
+}
@@ -0,0 +1,12 @@
+import java.util.function.IntBinaryOperator;
+
+public class Lambdas {
+    static int add(int a, int b) {
+        return a + b;
+    }
+
+    public static void main(String[] args) {
+        IntBinaryOperator op = Lambdas::add;
+        System.out.println(op.applyAsInt(1, 2));
+    }
+}
@@ -0,0 +1,55 @@
+import java.lang.invoke.MethodHandle;
+import java.util.function.IntFunction;
+import java.util.function.IntSupplier;
+import java.util.function.ToIntFunction;
+
+public class Members {
+    static int count = 3;
+    int value;
+
+    Members(int value) {
+        this.value = value;
+    }
+
+    static int count() {
+        return count;
+    }
+
+    int value() {
+        return value;
+    }
+
+    static int get(MethodHandle handle) throws Throwable {
+        return (int) handle.invokeExact();
+    }
+
+    static int get(MethodHandle handle, Members members) throws Throwable {
+        return (int) handle.invokeExact(members);
+    }
+
+    static void set(MethodHandle handle, int value) throws Throwable {
+        handle.invokeExact(value);
+    }
+
+    static void set(MethodHandle handle, long value) throws Throwable {
+        handle.invoke(value);
+    }
+
+    static void set(MethodHandle handle, Members members, int value)
+            throws Throwable {
+        handle.invokeExact(members, value);
+    }
+
+    static Members make(MethodHandle handle, int value) throws Throwable {
+        return (Members) handle.invokeExact(value);
+    }
+
+    public static void main(String[] args) {
+        // Method references put handles to the constructor, `count` and
+        // `value` in the constant pool.
+        IntFunction<Members> make = Members::new;
+        IntSupplier count = Members::count;
+        ToIntFunction<Members> value = Members::value;
+        System.out.println(value.applyAsInt(make.apply(count.getAsInt())));
+    }
+}
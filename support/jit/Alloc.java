@@ -0,0 +1,20 @@
+public class Alloc {
+    public static void main(String[] args) {
+        int total = 0;
+        int i = 0;
+        int[] last = null;
+        while (i < 200) {
+            int[] buffer = new int[i % 16];
+            total += i * 3 + buffer.length;
+            last = buffer;
+            i++;
+        }
+        System.out.println(total);
+        System.out.println(last.length);
+        int n = 300;
+        while (true) {
+            total += new int[n - 100].length;
+            n--;
+        }
+    }
+}
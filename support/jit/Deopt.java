@@ -0,0 +1,19 @@
+public class Deopt {
+    public static void main(String[] args) {
+        long total = 5000000000L;
+        double scale = 0.5;
+        int sum = 0;
+        int i = 0;
+        while (i < 200) {
+            if (i % 50 == 49) {
+                sum -= i;
+            } else {
+                sum += i;
+            }
+            i++;
+        }
+        System.out.println(sum);
+        System.out.println(total);
+        System.out.println(scale);
+    }
+}
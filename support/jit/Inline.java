@@ -0,0 +1,25 @@
+public class Inline {
+    static int clamp(int x) {
+        if (x > 50) {
+            return 50;
+        }
+        return x;
+    }
+
+    static int total(int n) {
+        int sum = 0;
+        for (int i = 0; i < n; i++) {
+            sum += clamp(i);
+        }
+        return sum;
+    }
+
+    public static void main(String[] args) {
+        int sum = 0;
+        for (int i = 0; i < 10; i++) {
+            sum += total(20);
+        }
+        System.out.println(sum);
+        System.out.println(total(100));
+    }
+}
@@ -0,0 +1,15 @@
+public class Layout {
+    public static void main(String[] args) {
+        int sum = 0;
+        int i = 0;
+        while (i < 200) {
+            if (i % 50 == 49) {
+                sum -= i;
+            } else {
+                sum += i;
+            }
+            i++;
+        }
+        System.out.println(sum);
+    }
+}
@@ -0,0 +1,21 @@
+public class Partitions {
+    static int partitions(int n, int max) {
+        if (n == 0) {
+            return 1;
+        }
+        int count = 0;
+        for (int k = 1; k <= max && k <= n; k++) {
+            count += partitions(n - k, k);
+        }
+        return count;
+    }
+
+    public static void main(String[] args) {
+        int sum = 0;
+        for (int n = 1; n <= 15; n++) {
+            sum += partitions(n, n);
+        }
+        System.out.println(sum);
+        System.out.println(partitions(30, 30));
+    }
+}
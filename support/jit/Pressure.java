@@ -0,0 +1,12 @@
+public class Pressure {
+  public static void main(String[] args) {
+      int sum = 0;
+      int i = 1;
+      for (i = 1; i <= 100; i++) {
+          // Every load stays live until the innermost addition, more than
+          // the JIT has registers for.
+          sum = sum + (i + (i + (i + (i + (i + (i + (i + (i + (i + (i + (i + (i + (i + i)))))))))))));
+      }
+      System.out.println(sum);
+  }
+}
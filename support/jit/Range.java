@@ -0,0 +1,15 @@
+public class Range {
+  public static void main(String[] args) {
+      int sum = 0;
+      for (int i = 0; i < 100; i++) {
+          // Both bounds of `i` are checked back to back, the trace checks
+          // them at once.
+          if (i >= 0 && i < 60) {
+              sum = sum + i;
+          } else {
+              sum = sum - 1;
+          }
+      }
+      System.out.println(sum);
+  }
+}
@@ -0,0 +1,42 @@
+public class Vector {
+  public static void main(String[] args) {
+      int n = 103;
+      int k = 7;
+      int[] a = new int[n];
+      int[] b = new int[n];
+      int[] c = new int[n];
+      float[] x = new float[n];
+      float[] y = new float[n];
+      for (int i = 0; i < n; i++) {
+          a[i] = i;
+      }
+      for (int i = 0; i < n; i++) {
+          b[i] = a[i] + a[i] + k;
+      }
+      // `d` and `c` are the same array.
+      int[] d = c;
+      for (int i = 0; i < c.length; i++) {
+          d[i] = b[i] - a[i] - 3;
+      }
+      for (int i = 0; i < n; i++) {
+          c[i] = c[i] + 100;
+      }
+      for (int i = 0; i < n; i++) {
+          x[i] = x[i] + 1.5f;
+      }
+      for (int i = 0; i < n; i++) {
+          y[i] = x[i] * x[i] / 2.0f - 0.25f;
+      }
+      System.out.println(a[102]);
+      System.out.println(b[0]);
+      System.out.println(b[102]);
+      System.out.println(c[0]);
+      System.out.println(c[1]);
+      System.out.println(c[50]);
+      System.out.println(c[101]);
+      System.out.println(c[102]);
+      System.out.println(x[102]);
+      System.out.println(y[0]);
+      System.out.println(y[102]);
+  }
+}
@@ -0,0 +1,15 @@
+interface Step {
+    int next(int n);
+}
+
+public class Countdown implements Step {
+    public int next(int n) {
+        Step step = this;
+        return n == 0 ? 0 : step.next(n - 1);
+    }
+
+    public static void main(String[] args) {
+        Step step = new Countdown();
+        System.out.println(step.next(10));
+    }
+}
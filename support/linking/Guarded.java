@@ -0,0 +1,10 @@
+// Run against the stale Ops, the call to Ops.add throws a NoSuchMethodError.
+public class Guarded {
+    public static void main(String[] args) {
+        try {
+            System.out.println(Ops.add(1, 2));
+        } catch (NoSuchMethodError e) {
+            System.out.println(Ops.square(3));
+        }
+    }
+}
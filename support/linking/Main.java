@@ -0,0 +1,6 @@
+public class Main {
+    public static void main(String[] args) {
+        int x = Ops.square(7);
+        System.out.println(Ops.add(x, Ops.square(3)));
+    }
+}
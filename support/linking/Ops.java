@@ -0,0 +1,9 @@
+public class Ops {
+    public static int square(int x) {
+        return x * x;
+    }
+
+    public static int add(int a, int b) {
+        return a + b;
+    }
+}
@@ -0,0 +1,5 @@
+public class Ops {
+    public static int square(int x) {
+        return x * x;
+    }
+}
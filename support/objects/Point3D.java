@@ -0,0 +1,15 @@
+public class Point3D extends Point {
+    long z;
+    Point3D next;
+
+    Point3D(int x, int y, long z) {
+        super(x, y);
+        this.z = z;
+    }
+
+    public static void main(String[] args) {
+        Point3D point = new Point3D(1, 2, 3);
+        System.out.println(point.x + point.y + point.z);
+        System.out.println(point.next == null);
+    }
+}
@@ -0,0 +1,19 @@
+public class Overload {
+    public static void main(String[] args) {
+        int a = add(1, 2);
+        int b = add(a, 3, 4);
+        System.out.println(b - add(a));
+    }
+
+    public static int add(int x) {
+        return x + 100;
+    }
+
+    public static int add(int x, int y) {
+        return x + y;
+    }
+
+    public static int add(int x, int y, int z) {
+        return x + y + z;
+    }
+}
@@ -0,0 +1,25 @@
+public class Overloads {
+    public static void main(String[] args) {
+        System.out.print(1);
+        System.out.print(' ');
+        System.out.print(2L);
+        System.out.print(' ');
+        System.out.print(true);
+        System.out.println();
+        System.out.println(1.0);
+        System.out.println(0.1f);
+        System.out.println(-0.0);
+        System.out.println(1e7);
+        System.out.println(1.5e-4);
+        System.out.println(123456.789);
+        System.out.println(1.0 / 0.0);
+        System.out.println(-1.0f / 0.0f);
+        System.out.println(0.0 / 0.0);
+        System.out.println(Double.MAX_VALUE);
+        System.out.println(Float.MIN_VALUE);
+        System.out.println('x');
+        System.out.print("no newline");
+        System.out.println((Object) "object");
+        System.out.println((Object) Overloads.class);
+    }
+}
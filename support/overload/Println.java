@@ -0,0 +1,11 @@
+public class Println {
+    public static void main(String[] args) {
+        int i = 7;
+        char c = 'A';
+        System.out.println(i);
+        System.out.println((long) i * i);
+        System.out.println(c);
+        System.out.println(i > 3);
+        System.out.println();
+    }
+}
@@ -0,0 +1,9 @@
+package com.example;
+
+import com.example.util.Twice;
+
+public class Main {
+    public static void main(String[] args) {
+        System.out.println(Twice.of(21));
+    }
+}
@@ -0,0 +1,7 @@
+package com.example.util;
+
+public class Twice {
+    public static int of(int value) {
+        return value * 2;
+    }
+}
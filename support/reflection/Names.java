@@ -0,0 +1,18 @@
+public class Names {
+    static String describe(Class<?> c) {
+        return c.getSimpleName();
+    }
+
+    public static void main(String[] args) {
+        System.out.println(Names.class.getName());
+        System.out.println("abc".getClass().getName());
+        System.out.println(String[].class.getName());
+        System.out.println(String[].class.getSimpleName());
+        System.out.println(int[][].class.getSimpleName());
+        System.out.println(int[].class.isArray());
+        System.out.println(Names.class.isPrimitive());
+        Class<?> c = java.util.Map.Entry.class;
+        System.out.println(c.getName());
+        System.out.println(describe(c));
+    }
+}
@@ -0,0 +1,11 @@
+public class SumDigits {
+  public static void main(String[] args) throws java.io.IOException {
+    int sum = 0;
+    int c = System.in.read();
+    while (c != -1) {
+      if (c >= 48 && c <= 57) sum += c - 48;
+      c = System.in.read();
+    }
+    System.out.println(sum);
+  }
+}
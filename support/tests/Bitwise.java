@@ -0,0 +1,40 @@
+public class Bitwise {
+  static int hash(int h) {
+      // Spreads the high bits down like HashMap does.
+      return h ^ (h >>> 16);
+  }
+
+  public static void main(String[] args) {
+      // Testing shift, bitwise, negation and comparison ops.
+      int a = -16;
+      int b = 12;
+      System.out.println(a << 2);
+      System.out.println(a >> 2);
+      System.out.println(a >>> 28);
+      System.out.println(1 << (b + 21));
+      System.out.println(b & 10);
+      System.out.println(b | 10);
+      System.out.println(b ^ 10);
+      System.out.println(-a);
+      int min = Integer.MIN_VALUE;
+      System.out.println(-min);
+      System.out.println(hash(123456789));
+
+      long c = -1L;
+      long d = 0x0f0fL;
+      System.out.println(c << 63);
+      System.out.println(c >> 40);
+      System.out.println(c >>> 60);
+      System.out.println(d & 0xffL);
+      System.out.println(d | 0xf0000L);
+      System.out.println(d ^ c);
+      System.out.println(-d);
+
+      float nan = 0.0f / 0.0f;
+      double x = 2.5;
+      System.out.println(-x);
+      System.out.println(nan < 1.0f);
+      System.out.println(nan > 1.0f);
+      System.out.println(x >= 2.5);
+  }
+}
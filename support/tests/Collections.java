@@ -0,0 +1,50 @@
+import java.util.ArrayList;
+import java.util.HashMap;
+import java.util.List;
+import java.util.Map;
+
+public class Collections {
+    static void count(Map<String, Integer> counts, String word) {
+        counts.put(word, counts.getOrDefault(word, 0) + 1);
+    }
+
+    public static void main(String[] args) {
+        List<Integer> squares = new ArrayList<>();
+        for (int i = 0; i < 6; i++) {
+            squares.add(i * i);
+        }
+        int sum = 0;
+        for (int square : squares) {
+            sum += square;
+        }
+        System.out.println(squares.size());
+        System.out.println(squares.get(3));
+        System.out.println(sum);
+        squares.remove(0);
+        squares.set(0, -1);
+        System.out.println(squares);
+
+        Map<String, Integer> counts = new HashMap<>();
+        count(counts, "pear");
+        count(counts, "apple");
+        count(counts, "pear");
+        count(counts, "fig");
+        count(counts, "pear");
+        System.out.println(counts.get("pear"));
+        System.out.println(counts.containsKey("plum"));
+        System.out.println(counts.size());
+        System.out.println(counts);
+
+        Map<Integer, Long> cubes = new HashMap<>();
+        for (int i = 40; i > 0; i -= 3) {
+            cubes.put(i, (long) i * i * i);
+        }
+        long total = 0;
+        for (long cube : cubes.values()) {
+            total += cube;
+        }
+        System.out.println(cubes.keySet());
+        System.out.println(total);
+        System.out.println(cubes.containsKey(7));
+    }
+}
@@ -0,0 +1,15 @@
+public class DivideByZero {
+    static int total(int n, int d) {
+        int sum = 0;
+        for (int i = 0; i < n; i++) {
+            sum += 100000 / (d - i);
+        }
+        return sum;
+    }
+
+    public static void main(String[] args) {
+        // Hot enough to run natively, then divides by zero in the trace.
+        System.out.println(total(2000, 5000));
+        System.out.println(total(2000, 1000));
+    }
+}
@@ -0,0 +1,74 @@
+public class Exceptions {
+    static int divide(int a, int b) {
+        return a / b;
+    }
+
+    static int check(int value) {
+        if (value < 0) {
+            throw new IllegalArgumentException("negative");
+        }
+        return value;
+    }
+
+    static int nested(int[] values, int index) {
+        try {
+            return values[index];
+        } catch (ArithmeticException e) {
+            return -1;
+        }
+    }
+
+    static int cleanup(int value) {
+        int result = 0;
+        try {
+            result = check(value);
+        } finally {
+            result += 100;
+        }
+        return result;
+    }
+
+    public static void main(String[] args) {
+        try {
+            System.out.println(divide(10, 0));
+        } catch (ArithmeticException e) {
+            System.out.println(e.getMessage());
+        }
+        try {
+            System.out.println(nested(new int[3], 5));
+        } catch (ArrayIndexOutOfBoundsException e) {
+            System.out.println(e);
+        }
+        int caught = 0;
+        for (int i = -3; i < 3; i++) {
+            try {
+                caught += check(i);
+            } catch (IllegalArgumentException e) {
+                System.out.println(e.getMessage());
+                caught--;
+            }
+        }
+        System.out.println(caught);
+        try {
+            System.out.println(cleanup(7));
+            System.out.println(cleanup(-7));
+        } catch (RuntimeException e) {
+            System.out.println(e);
+        }
+        try {
+            try {
+                throw new IllegalStateException("inner");
+            } catch (IllegalStateException e) {
+                throw new UnsupportedOperationException(e.getMessage());
+            }
+        } catch (UnsupportedOperationException e) {
+            System.out.println(e);
+        }
+        try {
+            int[] values = null;
+            System.out.println(values.length);
+        } catch (NullPointerException e) {
+            System.out.println("null");
+        }
+    }
+}
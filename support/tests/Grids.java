@@ -0,0 +1,45 @@
+public class Grids {
+  static long sum(long[] values) {
+      long total = 0;
+      for (int i = 0; i < values.length; i++) {
+          total += values[i];
+      }
+      return total;
+  }
+
+  public static void main(String[] args) {
+      // Testing reference, long, double and multi-dimensional arrays.
+      int[][] grid = new int[3][4];
+      for (int i = 0; i < grid.length; i++) {
+          for (int j = 0; j < grid[i].length; j++) {
+              grid[i][j] = i * j;
+          }
+      }
+      System.out.println(grid[2][3]);
+
+      long[] longs = new long[5];
+      for (int i = 0; i < longs.length; i++) {
+          longs[i] = i * 1000000000L;
+      }
+      System.out.println(sum(longs));
+
+      double[] halves = new double[2];
+      halves[0] = 0.5;
+      halves[1] = halves[0] * 3;
+      System.out.println(halves[0] + halves[1]);
+
+      String[] names = new String[2];
+      names[0] = "coldbrew";
+      System.out.println(names[0]);
+      System.out.println(names[1] == null);
+
+      int[][] jagged = new int[2][];
+      System.out.println(jagged[0] == null);
+      jagged[1] = new int[3];
+      System.out.println(jagged[1].length);
+
+      long[][][] cube = new long[2][3][4];
+      cube[1][2][3] = 7;
+      System.out.println(cube[1][2][3] + cube.length + cube[1].length);
+  }
+}
@@ -0,0 +1,41 @@
+public class HotBitwise {
+  public static void main(String[] args) {
+      // Hot loops using each bitwise, shift and negation op.
+      int x = 0;
+      for (int i = 0; i < 300; i++) {
+          x += i * i;
+          x ^= i;
+      }
+      System.out.println(x);
+      int y = 0;
+      for (int i = 0; i < 300; i++) {
+          y ^= i;
+      }
+      System.out.println(y);
+      int w = 0;
+      for (int i = 0; i < 300; i++) {
+          w |= i;
+          w &= 0xff0f;
+      }
+      System.out.println(w);
+      int u = 0;
+      for (int i = 0; i < 300; i++) {
+          u = -u + i;
+      }
+      System.out.println(u);
+      int v = 0;
+      for (int i = 0; i < 30; i++) {
+          for (int j = 0; j < 40; j++) {
+              v += i ^ j;
+          }
+      }
+      System.out.println(v);
+      int s = 1;
+      for (int i = 0; i < 300; i++) {
+          s = (s << 3) + (s >> 2) + (s >>> 5) + i;
+          s = s << i;
+          s = s + (i >> 1) - (-i >>> 27);
+      }
+      System.out.println(s);
+  }
+}
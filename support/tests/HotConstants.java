@@ -0,0 +1,18 @@
+public class HotConstants {
+  public static void main(String[] args) {
+      // Hot loops pushing string and float constants.
+      String s = "";
+      int n = 0;
+      for (int i = 0; i < 300; i++) {
+          s = "abc";
+          n += i;
+      }
+      System.out.println(s);
+      System.out.println(n);
+      float f = 0;
+      for (int i = 0; i < 300; i++) {
+          f = 0.5f;
+      }
+      System.out.println(f);
+  }
+}
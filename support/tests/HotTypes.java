@@ -0,0 +1,46 @@
+public class HotTypes {
+  public static void main(String[] args) {
+      // Hot loops over long, float, double, byte and char locals.
+      long l = 0;
+      for (int i = 0; i < 300; i++) {
+          l += i;
+      }
+      System.out.println(l);
+      double d = 0;
+      for (int i = 0; i < 300; i++) {
+          d += 0.5;
+      }
+      System.out.println(d);
+      float f = 0;
+      for (int i = 0; i < 300; i++) {
+          f += i;
+      }
+      System.out.println(f);
+      byte b = 0;
+      for (int i = 0; i < 300; i++) {
+          b += 3;
+      }
+      System.out.println(b);
+      char c = 0;
+      for (int i = 0; i < 300; i++) {
+          c += i;
+      }
+      System.out.println((int) c);
+      int q = 0;
+      for (int i = 1; i < 300; i++) {
+          q += 1000000 / i;
+          q += -i % 3;
+      }
+      System.out.println(q);
+      long big = Long.MAX_VALUE;
+      for (int i = 0; i < 300; i++) {
+          big += i;
+      }
+      System.out.println(big);
+      double nan = 0;
+      for (int i = 0; i < 300; i++) {
+          nan = nan * 0.0 / 0.0;
+      }
+      System.out.println(nan);
+  }
+}
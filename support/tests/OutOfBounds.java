@@ -0,0 +1,9 @@
+public class OutOfBounds {
+    public static void main(String[] args) {
+        int[] values = new int[4];
+        for (int i = 0; i <= values.length; i++) {
+            values[i] = i * i;
+            System.out.println(values[i]);
+        }
+    }
+}
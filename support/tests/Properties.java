@@ -0,0 +1,18 @@
+public class Properties {
+    public static void main(String[] args) {
+        System.out.println(System.getProperty("app.greeting", "hello"));
+        System.out.print("first line");
+        System.out.print(System.lineSeparator());
+        System.out.println(System.getProperty("file.separator"));
+        System.out.println(System.getProperty("app.missing") == null);
+
+        System.out.println(System.setProperty("app.mode", "fast") == null);
+        System.out.println(System.getProperty("app.mode"));
+        System.out.println(System.clearProperty("app.mode"));
+        System.out.println(System.getProperty("app.mode", "cleared"));
+
+        if (System.getenv("COLDBREW_UNSET_VARIABLE") == null) {
+            System.out.println("no such variable");
+        }
+    }
+}
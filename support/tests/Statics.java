@@ -0,0 +1,49 @@
+public class Statics {
+    static final int[] SQUARES = new int[8];
+    static final long SCALE = 1000L;
+    static int counter;
+    static double total = 0.5;
+    static String label;
+    static Statics last;
+    int id;
+
+    static {
+        System.out.println("initializing");
+        for (int i = 0; i < SQUARES.length; i++) {
+            SQUARES[i] = i * i;
+        }
+        label = "squares";
+    }
+
+    Statics() {
+        counter++;
+        id = counter;
+        last = this;
+    }
+
+    static int next() {
+        return ++counter;
+    }
+
+    public static void main(String[] args) {
+        System.out.println("main");
+        System.out.println(label);
+        int sum = 0;
+        for (int i = 0; i < SQUARES.length; i++) {
+            sum += SQUARES[i];
+        }
+        System.out.println(sum);
+        for (int i = 0; i < 100; i++) {
+            next();
+            total += 0.25;
+        }
+        System.out.println(counter);
+        System.out.println(total);
+        new Statics();
+        new Statics();
+        System.out.println(last.id);
+        System.out.println(counter * SCALE);
+        label = null;
+        System.out.println(label == null);
+    }
+}
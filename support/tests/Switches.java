@@ -0,0 +1,84 @@
+public class Switches {
+    static int classify(int n) {
+        switch (n % 4) {
+            case 0:
+                return 10;
+            case 1:
+            case 2:
+                return 20;
+            default:
+                return 30;
+        }
+    }
+
+    public static void main(String[] args) {
+        // A state machine stepping through its states in a hot loop.
+        int state = 0;
+        int a = 0;
+        int b = 0;
+        int c = 0;
+        for (int i = 0; i < 10000; i++) {
+            switch (state) {
+                case 0:
+                    a += i;
+                    state = 1;
+                    break;
+                case 1:
+                    b += 2;
+                    state = 2;
+                    break;
+                case 2:
+                    c++;
+                    state = i % 3 == 0 ? 0 : 3;
+                    break;
+                default:
+                    state = 0;
+                    break;
+            }
+        }
+        System.out.println(a);
+        System.out.println(b);
+        System.out.println(c);
+
+        // Sparse keys compile to a lookupswitch.
+        int sum = 0;
+        for (int i = 0; i < 5000; i++) {
+            switch (i % 7 * 100) {
+                case 0:
+                    sum += 1;
+                    break;
+                case 300:
+                    sum += 3;
+                    break;
+                case 600:
+                    sum -= 6;
+                    break;
+                default:
+                    sum += 2;
+            }
+        }
+        System.out.println(sum);
+
+        // Mostly the same case, leaving the trace rarely.
+        int hits = 0;
+        for (int i = 0; i < 3000; i++) {
+            switch (i < 2990 ? 5 : i % 3) {
+                case 5:
+                    hits++;
+                    break;
+                case 0:
+                    hits += 50;
+                    break;
+                default:
+                    hits += 90;
+            }
+        }
+        System.out.println(hits);
+
+        int total = 0;
+        for (int i = 0; i < 100; i++) {
+            total += classify(i);
+        }
+        System.out.println(total);
+    }
+}
@@ -0,0 +1,43 @@
+public class ToStrings {
+    int x;
+    int y;
+    ToStrings next;
+
+    ToStrings(int x, int y) {
+        this.x = x;
+        this.y = y;
+    }
+
+    int sum() {
+        return x + y;
+    }
+
+    @Override
+    public String toString() {
+        StringBuilder builder = new StringBuilder();
+        builder.append('(').append(x).append(", ").append(y).append(')');
+        if (next != null) {
+            builder.append(" -> ").append(next);
+        }
+        return builder.toString();
+    }
+
+    public static void main(String[] args) {
+        ToStrings point = new ToStrings(1, 2);
+        System.out.println(point);
+        point.next = new ToStrings(3, 4);
+        point.next.x += 10;
+        System.out.println(point);
+        System.out.println(point.sum() + point.next.sum());
+        Object object = point.next;
+        System.out.println(object.toString());
+        System.out.println(String.valueOf(object));
+        System.out.println(String.valueOf('c'));
+        System.out.println(new StringBuilder("sum=").append(2.5).append(true));
+        Integer boxed = Integer.valueOf(42);
+        System.out.println(new StringBuilder().append(boxed).append(object));
+        System.out.println((Object) null);
+        System.out.print('z');
+        System.out.println();
+    }
+}
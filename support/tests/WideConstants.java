@@ -0,0 +1,18 @@
+public class WideConstants {
+  public static void main(String[] args) {
+    // ldc2_w constants split across the high and low words.
+    long negative = -4294967296L;
+    long minimum = -9223372036854775808L;
+    long mixed = -123456789012345L;
+    System.out.println(negative);
+    System.out.println(minimum);
+    System.out.println(mixed);
+    System.out.println(mixed + 1L);
+    // Subnormal doubles, the smallest one and one just below the normals.
+    double smallest = 4.9E-324;
+    double largest = 2.225073858507201E-308;
+    System.out.println(smallest);
+    System.out.println(largest);
+    System.out.println(-1.5E-320);
+  }
+}
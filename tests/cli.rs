@@ -0,0 +1,73 @@
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn coldbrew(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_coldbrew"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(args)
+        .env_remove("COLDBREW_OPTS")
+        .env_remove("COLDBREW_LOG")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn stdout_only_holds_program_output() {
+    let expected = std::fs::read_to_string(
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("support/tests/Bitwise.expected"),
+    )
+    .unwrap();
+    for mode in [&[][..], &["--jit"]] {
+        let mut args = vec!["run", "--time", "--stats", "--log=debug"];
+        args.extend_from_slice(mode);
+        args.push("support/tests/Bitwise.class");
+        let output = coldbrew(&args);
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+        // The status and the reports go to stderr.
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("finished running successfully"));
+        assert!(stderr.contains("interpreted instructions"));
+    }
+
+    // So do load failures.
+    let output = coldbrew(&["run", "support/tests/Missing.class"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .starts_with("Failed to load support/tests/Missing.class"));
+}
+
+#[test]
+fn commands_link_referenced_classes() {
+    // Main calls into Ops, found next to it.
+    for command in [&["coverage"][..], &["check"], &["bench", "1"]] {
+        let mut args = vec![command[0], "support/linking/Main.class"];
+        args.extend_from_slice(&command[1..]);
+        let output = coldbrew(&args);
+        assert!(output.status.success(), "{command:?}");
+        assert!(output.stderr.is_empty(), "{command:?}");
+    }
+}
+
+#[test]
+fn passes_flags_after_the_class_to_main() {
+    let output = coldbrew(&["run", "support/args/Echo.class", "a", "--jit"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2\na\n--jit\n");
+}
+
+#[test]
+fn usage_errors_go_to_stderr() {
+    for args in [&[][..], &["run"], &["bench"], &["check"], &["unknown"]] {
+        let output = coldbrew(args);
+        assert_eq!(output.status.code(), Some(64), "{args:?}");
+        assert!(output.stdout.is_empty(), "{args:?}");
+        assert!(!output.stderr.is_empty(), "{args:?}");
+    }
+    // Help is what was asked for, it goes to stdout.
+    let output = coldbrew(&["help"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("usage guide"));
+}